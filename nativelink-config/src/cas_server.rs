@@ -111,6 +111,23 @@ pub struct AcStoreConfig {
     /// it is only possible to read from the Action Cache.
     #[serde(default)]
     pub read_only: bool,
+
+    /// The store blobs are read from when a client asks for stdout, stderr
+    /// or output files to be inlined into the returned `ActionResult`.
+    /// Usually the CAS store of the same instance. Saves clients a round
+    /// trip per small output.
+    ///
+    /// Default: "" (nothing is inlined)
+    #[serde(default, deserialize_with = "convert_string_with_shellexpand")]
+    pub inline_cas_store: StoreRefName,
+
+    /// Largest blob inlined into an `ActionResult`. Larger blobs are left
+    /// for the client to fetch from the CAS. At most 1MiB is inlined into
+    /// a single `ActionResult` in total.
+    ///
+    /// Default: 16KiB
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_inline_blob_size: u64,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -137,6 +154,15 @@ pub struct CapabilitiesConfig {
     /// If not set the capabilities service will inform the client that remote
     /// execution is not supported.
     pub remote_execution: Option<CapabilitiesRemoteExecutionConfig>,
+
+    /// Digest functions advertised to clients of this instance name. The
+    /// first one is advertised as the digest function of remote execution,
+    /// which lets clients like buck2 be moved to `blake3` per instance name.
+    ///
+    /// Default: The `default_digest_hash_function` of the global config,
+    /// followed by the other supported digest functions.
+    #[serde(default)]
+    pub digest_functions: Vec<ConfigDigestHashFunction>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -151,6 +177,21 @@ pub struct ExecutionConfig {
     /// The scheduler name referenced in the `schedulers` map in the main config.
     #[serde(deserialize_with = "convert_string_with_shellexpand")]
     pub scheduler: SchedulerRefName,
+
+    /// Lowest priority a client may request through `ExecutionPolicy.priority`
+    /// (eg: bazel's `--remote_execution_priority`). Actions with a higher
+    /// priority are scheduled before actions with a lower one. Requested
+    /// priorities below this value are raised to it.
+    /// Default: None (no lower bound)
+    #[serde(default)]
+    pub min_priority: Option<i32>,
+
+    /// Highest priority a client may request through
+    /// `ExecutionPolicy.priority`. Requested priorities above this value are
+    /// lowered to it, so clients can not jump ahead of everyone else.
+    /// Default: None (no upper bound)
+    #[serde(default)]
+    pub max_priority: Option<i32>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -212,6 +253,34 @@ pub struct ByteStreamConfig {
         skip_serializing_if = "default"
     )]
     pub persist_stream_on_disconnect_timeout: usize,
+
+    /// Store the data of partial uploads is written to while it is received,
+    /// so an upload interrupted by a restart of the server can be resumed
+    /// at the last persisted offset instead of starting over. Data is
+    /// persisted in segments of `upload_segment_size`, uploads no larger
+    /// than one segment are only kept in memory. Segments of uploads that
+    /// are never resumed are left behind, so the store should evict old
+    /// items.
+    ///
+    /// Default: "" (partial uploads are only kept in memory)
+    #[serde(
+        default,
+        deserialize_with = "convert_string_with_shellexpand",
+        skip_serializing_if = "default"
+    )]
+    pub upload_store: StoreRefName,
+
+    /// Size of the segments partial uploads are persisted in to
+    /// `upload_store`. Up to this many bytes per upload are lost when the
+    /// server restarts.
+    ///
+    /// Default: 16MiB
+    #[serde(
+        default,
+        deserialize_with = "convert_data_size_with_shellexpand",
+        skip_serializing_if = "default"
+    )]
+    pub upload_segment_size: usize,
 }
 
 // Older bytestream config. All fields are as per the newer docs, but this requires
@@ -259,6 +328,52 @@ pub struct AdminConfig {
     /// Default: "/admin"
     #[serde(default)]
     pub path: String,
+
+    /// Requests carrying an `Authorization: Bearer <token>` header matching
+    /// this value are granted the admin role, which allows every route of
+    /// the admin API.
+    ///
+    /// Once any of `bearer_token`, `read_only_bearer_token`,
+    /// `client_cert_names` or `read_only_client_cert_names` is set, requests
+    /// without a role are rejected with 401, and requests with the
+    /// read-only role are rejected with 403 on routes other than `GET`.
+    ///
+    /// The routes draining schedulers and workers, cancelling operations,
+    /// shutting down and adding, repointing and removing stores are only
+    /// registered once any of them is set. Services resolve their stores
+    /// when they start and keep using them, so these routes only affect
+    /// `ref_store`s, which look their store up again after every change,
+    /// and stores created afterwards.
+    ///
+    /// Default: None
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub bearer_token: Option<String>,
+
+    /// Requests carrying an `Authorization: Bearer <token>` header matching
+    /// this value are granted the read-only role, which only allows the
+    /// `GET` routes of the admin API.
+    ///
+    /// Default: None
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub read_only_bearer_token: Option<String>,
+
+    /// Requests on a connection whose client certificate is valid for one
+    /// of these DNS names or IP addresses are granted the admin role.
+    /// Client certificates are only verified on listeners with
+    /// `TlsConfig::client_ca_file` set.
+    ///
+    /// Default: []
+    #[serde(default, deserialize_with = "convert_vec_string_with_shellexpand")]
+    pub client_cert_names: Vec<String>,
+
+    /// Requests on a connection whose client certificate is valid for one
+    /// of these DNS names or IP addresses are granted the read-only role.
+    /// Client certificates are only verified on listeners with
+    /// `TlsConfig::client_ca_file` set.
+    ///
+    /// Default: []
+    #[serde(default, deserialize_with = "convert_vec_string_with_shellexpand")]
+    pub read_only_client_cert_names: Vec<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Default)]
@@ -791,6 +906,14 @@ pub struct LocalWorkerConfig {
     /// worker.
     pub platform_properties: HashMap<String, WorkerProperty>,
 
+    /// Free-form labels to attach to this worker, for example the rack or
+    /// node pool it runs in. Labels are sent to the scheduler and published
+    /// with the worker's metrics, but unlike `platform_properties` they are
+    /// never used to decide what runs on this worker.
+    /// Default: {} (no labels)
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+
     /// An optional mapping of environment names to set for the execution
     /// as well as those specified in the action itself.  If set, will set each
     /// key as an environment variable before executing the job with the value
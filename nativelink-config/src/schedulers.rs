@@ -16,7 +16,10 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
-use crate::serde_utils::{convert_duration_with_shellexpand, convert_numeric_with_shellexpand};
+use crate::serde_utils::{
+    convert_duration_with_shellexpand, convert_numeric_with_shellexpand,
+    convert_string_with_shellexpand,
+};
 use crate::stores::{GrpcEndpoint, Retry, StoreRefName};
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -26,6 +29,7 @@ pub enum SchedulerSpec {
     Grpc(GrpcSpec),
     CacheLookup(CacheLookupSpec),
     PropertyModifier(PropertyModifierSpec),
+    Router(RouterSpec),
 }
 
 /// When the scheduler matches tasks to workers that are capable of running
@@ -44,6 +48,13 @@ pub enum PropertyType {
     /// set to the value with exact string match.
     Exact,
 
+    /// Workers advertise a plain value and actions request a constraint on
+    /// it. Supported constraints are numeric comparisons (`>=16000`, `>8`,
+    /// `<=4`, `<2`), inequality (`!=arm64`) and set membership
+    /// (`in {avx2, avx512}`). Any other value must match exactly. Unlike
+    /// `minimum`, the value is not reduced while the worker runs the task.
+    Expression,
+
     /// Does not restrict on this value and instead will be passed to the worker
     /// as an informational piece.
     /// TODO(palfrey) In the future this will be used by the scheduler and worker
@@ -65,6 +76,46 @@ pub enum WorkerAllocationStrategy {
     MostRecentlyUsed,
 }
 
+/// The policy used to pick which of the workers able to run a job should
+/// run it.
+#[derive(Copy, Clone, Deserialize, Serialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerSchedulingPolicy {
+    /// Pick workers based on `allocation_strategy`, preferring workers that
+    /// recently ran a job with the same input root when
+    /// `worker_input_root_affinity_size` is set.
+    #[default]
+    Default,
+    /// Pick the worker running the fewest jobs, preferring the least recently
+    /// used one on ties. Useful for workers that can run multiple jobs at
+    /// once.
+    LeastLoaded,
+    /// Pick the worker with the least `minimum` resources (for example
+    /// `cpu_count` or `memory_kb`) left once the job is started, so jobs are
+    /// packed onto as few workers as possible and large jobs still find
+    /// workers with enough free resources. Workers run jobs concurrently as
+    /// long as their remaining `minimum` resources satisfy the job.
+    BinPacking,
+}
+
+/// What the scheduler does with an operation once no client is waiting on
+/// it anymore, for example because the client was killed.
+#[derive(Copy, Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientDisconnectBehavior {
+    /// Fail the operation once no client has been listening to it for
+    /// `client_action_timeout_s`.
+    #[default]
+    Cancel,
+    /// Keep running the operation to completion so its result is cached.
+    /// Clients executing the same action again wait on the running
+    /// operation instead of starting a new one.
+    KeepRunning,
+    /// Like `cancel`, but waits this many more seconds for a client to
+    /// re-subscribe to the operation before failing it.
+    GracePeriod(u64),
+}
+
 #[derive(Deserialize, Serialize, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct SimpleSpec {
@@ -92,6 +143,12 @@ pub struct SimpleSpec {
     /// have the `"cpu_arch"` label. There is no special treatment of any platform
     /// property labels other and entirely driven by worker configs and this
     /// config.
+    ///
+    /// The only exception is the reserved `"exclusive"` property, which does
+    /// not need to be listed here. Actions setting it to `"true"` run alone
+    /// on a worker: the scheduler waits for the actions running on a capable
+    /// worker to finish, without scheduling new ones on it, and then sends
+    /// no other action to the worker until the exclusive one completes.
     pub supported_platform_properties: Option<HashMap<String, PropertyType>>,
 
     /// The amount of time to retain completed actions in memory for in case
@@ -106,6 +163,20 @@ pub struct SimpleSpec {
     #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
     pub client_action_timeout_s: u64,
 
+    /// What to do with an operation once its last client stopped waiting on
+    /// it.
+    /// Default: cancel
+    #[serde(default)]
+    pub client_disconnect_behavior: ClientDisconnectBehavior,
+
+    /// Fail operations that have been waiting in the queue for longer than
+    /// this many seconds, most likely because their clients gave up on them
+    /// or no worker can run them. Such operations complete with a
+    /// `DEADLINE_EXCEEDED` error and are removed from the queue.
+    /// Default: 0 (queued operations never expire)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub max_queued_action_age_s: u64,
+
     /// Remove workers from pool once the worker has not responded in this
     /// amount of time in seconds.
     /// Default: 5 (seconds)
@@ -122,15 +193,231 @@ pub struct SimpleSpec {
     #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
     pub max_job_retries: usize,
 
+    /// When a job fails because of its worker (an internal error on the
+    /// worker, the worker timing out or failing to upload the outputs) and is
+    /// retried, prefer workers the job has not failed on yet. The job still
+    /// goes back to one of those workers if no other worker can run it.
+    /// Default: false
+    #[serde(default)]
+    pub retry_on_different_worker: bool,
+
     /// The strategy used to assign workers jobs.
     #[serde(default)]
     pub allocation_strategy: WorkerAllocationStrategy,
 
+    /// Number of recently started input roots to remember for each worker.
+    /// When greater than zero, an action is preferably assigned to a worker
+    /// that recently ran an action with the same input root, since that
+    /// worker likely still has most of the inputs in its local cache. Falls
+    /// back to `allocation_strategy` when no such worker is available.
+    /// Default: 0 (disabled)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub worker_input_root_affinity_size: usize,
+
+    /// The policy used to pick a worker among those able to run a job.
+    /// Default: default
+    #[serde(default)]
+    pub scheduling_policy: WorkerSchedulingPolicy,
+
+    /// Number of shards the worker pool is split into. Keep alives and
+    /// action updates only lock the shard of their worker, and matching
+    /// searches the shards one after the other, so raising this reduces
+    /// lock contention with thousands of workers. Only the worker pool is
+    /// sharded, the operations of the memory backend stay behind one lock.
+    /// Default: 1
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub worker_shards: usize,
+
+    /// When a queued action cannot be matched to any worker, allow the
+    /// scheduler to preempt an executing action with a lower priority on a
+    /// worker that could run the queued action instead. The preempted action
+    /// is killed on its worker and put back in the queue without counting
+    /// against `max_job_retries`. This is the maximum number of preemptions
+    /// the scheduler may perform per minute.
+    /// Default: 0 (preemption disabled)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_preemptions_per_minute: usize,
+
+    /// Stop and fail operations that are still executing this many seconds
+    /// after their action timeout expired, for example because the worker
+    /// hung without reporting. Failed operations are retried like any other
+    /// failure, up to `max_job_retries`. Actions without a timeout are not
+    /// affected.
+    /// Default: 0 (disabled)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub execution_timeout_grace_s: u64,
+
+    /// Automatically quarantine workers whose actions keep failing. A
+    /// quarantined worker receives no new actions until its cool-down has
+    /// passed.
+    /// Default: None (workers are never quarantined)
+    pub worker_quarantine: Option<WorkerQuarantineSpec>,
+
+    /// Named pools of workers. Actions are assigned to the first pool whose
+    /// platform property they request, and that pool's limits apply to them.
+    /// Actions that do not belong to any pool are not restricted.
+    /// Default: empty
+    #[serde(default)]
+    pub worker_pools: Vec<WorkerPoolSpec>,
+
+    /// Periodically save the actions that have not finished yet to a local
+    /// file and restore them on startup, so restarting the scheduler does
+    /// not drop the queue. Clients resume waiting on restored operations with
+    /// `WaitExecution`. Only used by the memory backend.
+    /// Default: None (nothing is saved)
+    pub memory_snapshot: Option<MemorySnapshotSpec>,
+
+    /// Limits on the number of operations a single client may have in the
+    /// scheduler. Clients are identified by the `enduser.id` the request was
+    /// made with; requests without an identity are not limited.
+    /// Default: None (clients are not limited)
+    pub client_quotas: Option<ClientQuotaSpec>,
+
+    /// Reject new actions with `RESOURCE_EXHAUSTED` while too many actions
+    /// are queued, instead of accepting work that would likely time out in
+    /// the queue.
+    /// Default: None (the queue is not limited)
+    pub queue_backpressure: Option<QueueBackpressureSpec>,
+
+    /// Send idle workers able to run a newly queued action a hint to start
+    /// downloading its input root, so the inputs are likely in their local
+    /// cache once they are assigned similar actions. Only workers that
+    /// advertise the `prefetch_inputs` feature receive hints.
+    /// Default: None (no hints are sent)
+    pub input_prefetch: Option<InputPrefetchSpec>,
+
     /// The storage backend to use for the scheduler.
     /// Default: memory
     pub experimental_backend: Option<ExperimentalSimpleSchedulerBackend>,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MemorySnapshotSpec {
+    /// Path of the file the snapshot is written to and restored from.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub path: String,
+
+    /// Seconds between two snapshots.
+    /// Default: 10 (seconds)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub interval_s: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct ClientQuotaSpec {
+    /// Maximum number of queued operations a client may have. New actions
+    /// are rejected with `RESOURCE_EXHAUSTED` once the limit is reached.
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_queued_actions: usize,
+
+    /// Maximum number of executing operations a client may have. New
+    /// actions are rejected with `RESOURCE_EXHAUSTED` once the limit is
+    /// reached.
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_executing_actions: usize,
+
+    /// Seconds clients are told to wait before retrying a rejected action.
+    /// Default: 10 (seconds)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub retry_delay_s: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QueueBackpressureSpec {
+    /// Maximum number of queued operations in the scheduler. New actions are
+    /// rejected with `RESOURCE_EXHAUSTED` once the limit is reached.
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_queued_actions: usize,
+
+    /// Maximum number of queued operations in a single platform bucket. New
+    /// actions are rejected with `RESOURCE_EXHAUSTED` once the bucket they
+    /// fall into reaches the limit.
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_queued_actions_per_bucket: usize,
+
+    /// Platform properties that define the buckets. Actions with the same
+    /// values for these properties are in the same bucket, eg:
+    /// `["OSFamily", "cpu_arch"]`. Properties an action does not request are
+    /// ignored for that action.
+    /// Default: empty (actions with the same platform properties are in the
+    /// same bucket)
+    #[serde(default)]
+    pub bucket_properties: Vec<String>,
+
+    /// Seconds clients are told to wait before retrying a rejected action.
+    /// Default: 10 (seconds)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub retry_delay_s: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct InputPrefetchSpec {
+    /// Maximum number of idle workers hinted for a single action. Workers
+    /// that recently ran or were hinted the same input root are skipped
+    /// when `worker_input_root_affinity_size` is set.
+    /// Default: 1
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_workers_per_action: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct WorkerQuarantineSpec {
+    /// Number of consecutive actions that must fail with an error on a
+    /// worker before it is quarantined. Back pressure from the worker is not
+    /// counted as a failure.
+    #[serde(deserialize_with = "convert_numeric_with_shellexpand")]
+    pub failure_threshold: usize,
+
+    /// Seconds a worker stays quarantined before it may receive actions
+    /// again.
+    /// Default: 300 (seconds)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub cooldown_s: u64,
+}
+
+/// A named pool of workers, such as the workers reserved for release builds.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WorkerPoolSpec {
+    /// Name of the pool, used in logs and error messages.
+    pub name: String,
+
+    /// The platform property that selects this pool. Workers in the pool
+    /// should advertise it as an `exact` property.
+    pub property_name: String,
+
+    /// The value of `property_name` an action must request to belong to
+    /// this pool.
+    pub property_value: String,
+
+    /// Maximum number of actions that may be queued in this pool. New
+    /// actions are rejected with `RESOURCE_EXHAUSTED` once it is reached.
+    /// Default: 0 (unlimited)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub max_queued_actions: usize,
+
+    /// Added to the priority of every action in this pool, so pools can be
+    /// ranked against each other when they share workers.
+    /// Default: 0
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub priority: i32,
+
+    /// Instance names allowed to schedule actions in this pool. Actions from
+    /// other instances are rejected with `PERMISSION_DENIED`.
+    /// Default: empty (all instances are allowed)
+    #[serde(default)]
+    pub allowed_instance_names: Vec<String>,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum ExperimentalSimpleSchedulerBackend {
@@ -144,8 +431,23 @@ pub enum ExperimentalSimpleSchedulerBackend {
 #[serde(deny_unknown_fields)]
 pub struct ExperimentalRedisSchedulerBackend {
     /// A reference to the redis store to use for the scheduler.
-    /// Note: This MUST resolve to a `RedisSpec`.
+    /// Note: This MUST resolve to a `RedisSpec`. TLS and authentication
+    /// of the connections are configured on that store.
     pub redis_store: StoreRefName,
+
+    /// Seconds a completed operation is kept in redis after the last time
+    /// it was updated or a client asked for it. Completed operations older
+    /// than this, and the client operation ids left pointing to them, are
+    /// deleted by a background compaction task.
+    /// Default: 0 (completed operations are never deleted)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub retain_completed_for_s: u64,
+
+    /// Seconds between two compactions of completed operations. Only used
+    /// when `retain_completed_for_s` is set.
+    /// Default: 300 (seconds)
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub compaction_interval_s: u64,
 }
 
 /// A scheduler that simply forwards requests to an upstream scheduler.  This
@@ -182,6 +484,14 @@ pub struct CacheLookupSpec {
     /// To prevent unintended issues, this store should probably be a `CompletenessCheckingSpec`.
     pub ac_store: StoreRefName,
 
+    /// If set, a cached result is only returned if every output blob it
+    /// references still exists in this CAS store. Otherwise the action is
+    /// executed again. This protects clients from stale action cache entries
+    /// at the cost of a `FindMissingBlobs` round trip per cache hit.
+    /// Default: unset (cached results are returned without being checked)
+    #[serde(default)]
+    pub verify_cas_store: Option<StoreRefName>,
+
     /// The nested scheduler to use if cache lookup fails.
     pub scheduler: Box<SchedulerSpec>,
 }
@@ -210,6 +520,38 @@ pub struct PlatformPropertyReplacement {
     pub new_value: Option<String>,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlatformPropertyRewrite {
+    /// The name of the property to rewrite.
+    pub name: String,
+    /// The regular expression the value of the property is matched against.
+    /// If the value does not match, the property is left untouched.
+    pub pattern: String,
+    /// The value every match of `pattern` is replaced with. Capture groups
+    /// can be referenced with `$1` or `${name}`.
+    /// Example: `pattern: "^docker://foo:latest$"` with
+    /// `replacement: "docker://foo@sha256:abc"` pins the image to a digest.
+    pub replacement: String,
+    /// The property to write the rewritten value to. If unset the property
+    /// is rewritten in place, otherwise the original property is kept.
+    #[serde(default)]
+    pub new_name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlatformPropertyTemplate {
+    /// The name of the property to set.
+    pub name: String,
+    /// The value to assign to the property. `{other}` is substituted with the
+    /// value of the property named `other`, `{{` and `}}` produce literal
+    /// braces. If a referenced property is not set on the action, the
+    /// property is left untouched.
+    /// Example: `"{OSFamily}-{ISA}"`.
+    pub template: String,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum PropertyModification {
@@ -219,6 +561,11 @@ pub enum PropertyModification {
     Remove(String),
     /// If a property is found, then replace it with another one.
     Replace(PlatformPropertyReplacement),
+    /// If a property is found and its value matches a regular expression,
+    /// then rewrite its value.
+    Rewrite(PlatformPropertyRewrite),
+    /// Set a property to a value built from the values of other properties.
+    Template(PlatformPropertyTemplate),
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -234,3 +581,34 @@ pub struct PropertyModifierSpec {
     /// The nested scheduler to use after modifying the properties.
     pub scheduler: Box<SchedulerSpec>,
 }
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RouterRoute {
+    /// Platform properties the action must have, with exactly these values,
+    /// to be sent to `scheduler`. Other properties of the action are ignored.
+    pub platform_properties: HashMap<String, String>,
+
+    /// The nested scheduler matching actions are sent to.
+    pub scheduler: Box<SchedulerSpec>,
+}
+
+/// Dispatches each action to one of several nested schedulers based on its
+/// platform properties, e.g. sending actions with `OSFamily=macos` to a mac
+/// cluster and everything else to a linux one.
+///
+/// The action is passed to the nested scheduler unchanged, so the nested
+/// scheduler must accept the properties used for routing (wrap it in a
+/// `property_modifier` to remove them otherwise). Workers connect to a
+/// scheduler by name, so at most one of the nested schedulers may accept
+/// workers; the others are usually `grpc` schedulers of other clusters.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RouterSpec {
+    /// The routes to try in order. An action is sent to the scheduler of the
+    /// first route whose platform properties all match the action.
+    pub routes: Vec<RouterRoute>,
+
+    /// The nested scheduler to use for actions that match none of the routes.
+    pub default_scheduler: Box<SchedulerSpec>,
+}
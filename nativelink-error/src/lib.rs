@@ -13,11 +13,13 @@
 // limitations under the License.
 
 use core::convert::Into;
+use core::time::Duration;
 use std::sync::{MutexGuard, PoisonError};
 
 use nativelink_metric::{
     MetricFieldData, MetricKind, MetricPublishKnownKindData, MetricsComponent,
 };
+use prost::Message;
 use prost_types::TimestampError;
 use serde::{Deserialize, Serialize};
 // Reexport of tonic's error codes which we use as "nativelink_error::Code".
@@ -49,11 +51,26 @@ macro_rules! error_if {
     }};
 }
 
-#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Error {
     #[serde(with = "CodeDef")]
     pub code: Code,
     pub messages: Vec<String>,
+    /// How long the client should wait before retrying the request, sent
+    /// to gRPC clients as a `google.rpc.RetryInfo` detail.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_delay: Option<Duration>,
+}
+
+/// Type URL of `RetryInfo` when packed in a `google.protobuf.Any`.
+const RETRY_INFO_TYPE_URL: &str = "type.googleapis.com/google.rpc.RetryInfo";
+
+/// The `google.rpc.RetryInfo` error detail. Only this detail is used, so it
+/// is declared here rather than generating all of `error_details.proto`.
+#[derive(Clone, PartialEq, Message)]
+struct RetryInfo {
+    #[prost(message, optional, tag = "1")]
+    retry_delay: Option<prost_types::Duration>,
 }
 
 impl MetricsComponent for Error {
@@ -69,7 +86,11 @@ impl MetricsComponent for Error {
 impl Error {
     #[must_use]
     pub const fn new_with_messages(code: Code, messages: Vec<String>) -> Self {
-        Self { code, messages }
+        Self {
+            code,
+            messages,
+            retry_delay: None,
+        }
     }
 
     #[must_use]
@@ -81,6 +102,13 @@ impl Error {
         }
     }
 
+    /// Tells the client to wait `retry_delay` before retrying the request.
+    #[must_use]
+    pub const fn with_retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = Some(retry_delay);
+        self
+    }
+
     #[inline]
     #[must_use]
     pub fn append<S: Into<String>>(mut self, msg: S) -> Self {
@@ -94,6 +122,7 @@ impl Error {
         // This will help with knowing which messages are tied to different errors.
         self.messages.push("---".to_string());
         self.messages.append(&mut other.messages);
+        self.retry_delay = self.retry_delay.or(other.retry_delay);
         self
     }
 
@@ -120,6 +149,30 @@ impl Error {
     pub fn message_string(&self) -> String {
         self.messages.join(" : ")
     }
+
+    /// The error details sent to gRPC clients along with the status.
+    fn details(&self) -> Vec<prost_types::Any> {
+        self.retry_delay
+            .and_then(|retry_delay| prost_types::Duration::try_from(retry_delay).ok())
+            .map(|retry_delay| prost_types::Any {
+                type_url: RETRY_INFO_TYPE_URL.to_string(),
+                value: RetryInfo {
+                    retry_delay: Some(retry_delay),
+                }
+                .encode_to_vec(),
+            })
+            .into_iter()
+            .collect()
+    }
+
+    /// Reads the retry delay from the details of a gRPC status, if any.
+    fn retry_delay_from_details(details: &[prost_types::Any]) -> Option<Duration> {
+        details
+            .iter()
+            .filter(|detail| detail.type_url == RETRY_INFO_TYPE_URL)
+            .find_map(|detail| RetryInfo::decode(detail.value.as_slice()).ok()?.retry_delay)
+            .and_then(|retry_delay| Duration::try_from(retry_delay).ok())
+    }
 }
 
 impl core::error::Error for Error {}
@@ -129,7 +182,7 @@ impl From<Error> for nativelink_proto::google::rpc::Status {
         Self {
             code: val.code as i32,
             message: val.message_string(),
-            details: vec![],
+            details: val.details(),
         }
     }
 }
@@ -139,7 +192,21 @@ impl From<nativelink_proto::google::rpc::Status> for Error {
         Self {
             code: val.code.into(),
             messages: vec![val.message],
+            retry_delay: Self::retry_delay_from_details(&val.details),
+        }
+    }
+}
+
+impl core::fmt::Debug for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // A manual impl to leave out the retry delay when it is not set.
+        let mut builder = f.debug_struct("Error");
+        builder.field("code", &self.code);
+        builder.field("messages", &self.messages);
+        if let Some(retry_delay) = &self.retry_delay {
+            builder.field("retry_delay", retry_delay);
         }
+        builder.finish()
     }
 }
 
@@ -154,6 +221,10 @@ impl core::fmt::Display for Error {
             builder.field("messages", &self.messages);
         }
 
+        if let Some(retry_delay) = &self.retry_delay {
+            builder.field("retry_delay", retry_delay);
+        }
+
         builder.finish()
     }
 }
@@ -235,10 +306,7 @@ impl From<TimestampError> for Error {
 
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
-        Self {
-            code: err.kind().into_code(),
-            messages: vec![err.to_string()],
-        }
+        Self::new_with_messages(err.kind().into_code(), vec![err.to_string()])
     }
 }
 
@@ -267,13 +335,32 @@ impl From<fred::error::Error> for Error {
 
 impl From<tonic::Status> for Error {
     fn from(status: tonic::Status) -> Self {
-        make_err!(status.code(), "{}", status.to_string())
+        let mut error = make_err!(status.code(), "{}", status.to_string());
+        if !status.details().is_empty() {
+            error.retry_delay = nativelink_proto::google::rpc::Status::decode(status.details())
+                .ok()
+                .and_then(|details| Self::retry_delay_from_details(&details.details));
+        }
+        error
     }
 }
 
 impl From<Error> for tonic::Status {
     fn from(val: Error) -> Self {
-        Self::new(val.code, val.messages.join(" : "))
+        let details = val.details();
+        if details.is_empty() {
+            return Self::new(val.code, val.messages.join(" : "));
+        }
+        let status_details = nativelink_proto::google::rpc::Status {
+            code: val.code as i32,
+            message: val.message_string(),
+            details,
+        };
+        Self::with_details(
+            val.code,
+            val.messages.join(" : "),
+            status_details.encode_to_vec().into(),
+        )
     }
 }
 
@@ -355,10 +442,7 @@ impl<T> ResultExt<T> for Option<T> {
         F: (FnOnce(&Error) -> (Code, S)) + Sized,
     {
         self.ok_or_else(|| {
-            let mut error = Error {
-                code: Code::Internal,
-                messages: vec![],
-            };
+            let mut error = Error::new_with_messages(Code::Internal, vec![]);
             let (code, message) = tip_fn(&error);
             error.code = code;
             error.messages.push(message.to_string());
@@ -19,6 +19,33 @@ pub struct KeepAliveRequest {
     /// / ID of the worker making the request.
     #[prost(string, tag = "1")]
     pub worker_id: ::prost::alloc::string::String,
+    /// / Current resource utilization of the worker host. Not set if the
+    /// / worker could not collect it.
+    #[prost(message, optional, tag = "2")]
+    pub utilization: ::core::option::Option<WorkerUtilization>,
+}
+/// / Resource utilization of a worker host at the time it was collected.
+/// / Fields the worker could not collect are left at zero.
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct WorkerUtilization {
+    /// / Fraction of the host CPU capacity in use, from 0.0 to 1.0. May
+    /// / exceed 1.0 if the host is overloaded.
+    #[prost(double, tag = "1")]
+    pub cpu_usage: f64,
+    /// / Memory in use on the host, in bytes.
+    #[prost(uint64, tag = "2")]
+    pub memory_used_bytes: u64,
+    /// / Total memory of the host, in bytes.
+    #[prost(uint64, tag = "3")]
+    pub memory_total_bytes: u64,
+    /// / Disk space in use on the filesystem of the worker's work directory,
+    /// / in bytes.
+    #[prost(uint64, tag = "4")]
+    pub disk_used_bytes: u64,
+    /// / Total disk space of the filesystem of the worker's work directory,
+    /// / in bytes.
+    #[prost(uint64, tag = "5")]
+    pub disk_total_bytes: u64,
 }
 /// / Request object for going away requests.
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -50,6 +77,24 @@ pub struct ConnectWorkerRequest {
     /// / append this prefix to the assigned worker_id followed by a UUIDv6.
     #[prost(string, tag = "2")]
     pub worker_id_prefix: ::prost::alloc::string::String,
+    /// / Free-form labels assigned by the operator (eg: rack or node pool).
+    /// / Labels are informational only and never used to match actions to
+    /// / workers; use `properties` for that.
+    #[prost(map = "string, string", tag = "3")]
+    pub labels: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
+    /// / Version of the worker API protocol the worker speaks. Zero for
+    /// / workers that predate protocol versioning.
+    #[prost(uint32, tag = "4")]
+    pub protocol_version: u32,
+    /// / Optional features the worker supports, eg: `resource_utilization`.
+    /// / The scheduler only relies on a feature if the worker advertised it,
+    /// / so new worker capabilities can be rolled out across a fleet of
+    /// / workers running different versions.
+    #[prost(string, repeated, tag = "5")]
+    pub features: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 /// / The result of an ExecutionRequest.
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -92,6 +137,14 @@ pub struct ConnectionResult {
     /// / The internal ID given to the newly connected node.
     #[prost(string, tag = "1")]
     pub worker_id: ::prost::alloc::string::String,
+    /// / Version of the worker API protocol the scheduler speaks. Zero for
+    /// / schedulers that predate protocol versioning.
+    #[prost(uint32, tag = "2")]
+    pub protocol_version: u32,
+    /// / The features advertised by the worker that the scheduler supports
+    /// / too. Only these features may be used on this connection.
+    #[prost(string, repeated, tag = "3")]
+    pub features: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 /// / Request to kill a running operation sent from the scheduler to a worker.
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -100,10 +153,22 @@ pub struct KillOperationRequest {
     #[prost(string, tag = "1")]
     pub operation_id: ::prost::alloc::string::String,
 }
+/// / Hint sent from the scheduler to an idle worker that actions needing these
+/// / inputs are queued, so the worker can start downloading them before it is
+/// / assigned one of the actions.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PrefetchInputs {
+    /// / Digests of the `Directory` trees the worker should download into its
+    /// / local cache, usually the input roots of the queued actions.
+    #[prost(message, repeated, tag = "1")]
+    pub directory_digests: ::prost::alloc::vec::Vec<
+        super::super::super::super::super::build::bazel::remote::execution::v2::Digest,
+    >,
+}
 /// / Communication from the scheduler to the worker.
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct UpdateForWorker {
-    #[prost(oneof = "update_for_worker::Update", tags = "1, 2, 3, 4, 5")]
+    #[prost(oneof = "update_for_worker::Update", tags = "1, 2, 3, 4, 5, 6")]
     pub update: ::core::option::Option<update_for_worker::Update>,
 }
 /// Nested message and enum types in `UpdateForWorker`.
@@ -132,6 +197,10 @@ pub mod update_for_worker {
         /// / Instructs the worker to kill a specific running operation.
         #[prost(message, tag = "5")]
         KillOperationRequest(super::KillOperationRequest),
+        /// / Suggests inputs the worker should download ahead of time. Only sent
+        /// / to workers that advertised the `prefetch_inputs` feature.
+        #[prost(message, tag = "6")]
+        PrefetchInputs(super::PrefetchInputs),
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
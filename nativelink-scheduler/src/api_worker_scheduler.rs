@@ -12,36 +12,65 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use core::cmp::Reverse;
+use core::hash::BuildHasher;
 use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::hash::RandomState;
 use std::sync::Arc;
 
-use async_lock::Mutex;
+use async_lock::{Mutex, MutexGuard};
 use lru::LruCache;
-use nativelink_config::schedulers::WorkerAllocationStrategy;
+use nativelink_config::schedulers::WorkerQuarantineSpec;
 use nativelink_error::{Code, Error, ResultExt, error_if, make_err, make_input_err};
 use nativelink_metric::{
     MetricFieldData, MetricKind, MetricPublishKnownKindData, MetricsComponent,
     RootMetricsComponent, group,
 };
 use nativelink_util::action_messages::{OperationId, WorkerId};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::metrics_utils::CounterWithTime;
 use nativelink_util::operation_state_manager::{UpdateOperationType, WorkerStateManager};
 use nativelink_util::platform_properties::PlatformProperties;
 use nativelink_util::shutdown_guard::ShutdownGuard;
 use nativelink_util::spawn;
 use nativelink_util::task::JoinHandleDropGuard;
+use nativelink_util::worker_features::PREFETCH_INPUTS;
 use tokio::sync::Notify;
 use tokio::sync::mpsc::{self, UnboundedSender};
 use tonic::async_trait;
-#[cfg(feature = "worker_find_logging")]
-use tracing::info;
-use tracing::{error, warn};
+use tracing::{error, info, warn};
 
 use crate::platform_property_manager::PlatformPropertyManager;
-use crate::worker::{ActionInfoWithProps, Worker, WorkerTimestamp, WorkerUpdate};
-use crate::worker_scheduler::WorkerScheduler;
+use crate::scheduling_policy::{ActionPlacement, SchedulingPolicy};
+use crate::worker::{
+    ActionInfoWithProps, Worker, WorkerTimestamp, WorkerUpdate, WorkerUtilization,
+    restore_platform_properties,
+};
+use crate::worker_scheduler::{WorkerPlacement, WorkerPlacementStatus, WorkerScheduler};
+
+/// Default seconds a worker stays quarantined if not set in the config.
+const DEFAULT_WORKER_QUARANTINE_COOLDOWN_S: u64 = 300;
 
+/// The workers of a shard, from the most to the least recently used, and
+/// the counter used to order workers of all shards by use.
 #[derive(Debug)]
-struct Workers(LruCache<WorkerId, Worker>);
+struct Workers(LruCache<WorkerId, Worker>, Arc<AtomicU64>);
+
+impl Workers {
+    /// Like `LruCache::get_mut`, but also records the use across shards.
+    fn get_mut(&mut self, worker_id: &WorkerId) -> Option<&mut Worker> {
+        let worker = self.0.get_mut(worker_id)?;
+        worker.last_used_sequence = self.1.fetch_add(1, Ordering::Relaxed);
+        Some(worker)
+    }
+
+    /// Like `LruCache::put`, but also records the use across shards.
+    fn put(&mut self, worker_id: WorkerId, mut worker: Worker) -> Option<Worker> {
+        worker.last_used_sequence = self.1.fetch_add(1, Ordering::Relaxed);
+        self.0.put(worker_id, worker)
+    }
+}
 
 impl Deref for Workers {
     type Target = LruCache<WorkerId, Worker>;
@@ -74,18 +103,26 @@ impl MetricsComponent for Workers {
     }
 }
 
-/// A collection of workers that are available to run tasks.
+/// A shard of the workers that are available to run tasks.
 #[derive(MetricsComponent)]
 struct ApiWorkerSchedulerImpl {
-    /// A `LruCache` of workers available based on `allocation_strategy`.
+    /// A `LruCache` of workers, from the most to the least recently used.
     #[metric(group = "workers")]
     workers: Workers,
 
     /// The worker state manager.
-    #[metric(group = "worker_state_manager")]
     worker_state_manager: Arc<dyn WorkerStateManager>,
-    /// The allocation strategy for workers.
-    allocation_strategy: WorkerAllocationStrategy,
+    /// Number of recent input roots remembered per worker to prefer workers
+    /// that likely have the inputs cached. Zero disables this.
+    input_root_affinity_size: usize,
+    /// When set, workers whose actions keep failing are quarantined.
+    worker_quarantine: Option<WorkerQuarantineSpec>,
+    /// Seconds an operation may run past its action timeout before it is
+    /// stopped. Zero disables this.
+    execution_timeout_grace_s: u64,
+    /// The number of operations stopped for running past their timeout,
+    /// shared by all shards.
+    execution_timeouts: Arc<CounterWithTime>,
     /// A channel to notify the matching engine that the worker pool has changed.
     worker_change_notify: Arc<Notify>,
     /// A channel to notify that an operation is still alive.
@@ -96,7 +133,9 @@ impl core::fmt::Debug for ApiWorkerSchedulerImpl {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("ApiWorkerSchedulerImpl")
             .field("workers", &self.workers)
-            .field("allocation_strategy", &self.allocation_strategy)
+            .field("input_root_affinity_size", &self.input_root_affinity_size)
+            .field("worker_quarantine", &self.worker_quarantine)
+            .field("execution_timeout_grace_s", &self.execution_timeout_grace_s)
             .field("worker_change_notify", &self.worker_change_notify)
             .field("operation_keep_alive_tx", &self.operation_keep_alive_tx)
             .finish_non_exhaustive()
@@ -123,6 +162,10 @@ impl ApiWorkerSchedulerImpl {
             timestamp
         );
         worker.last_update_timestamp = timestamp;
+        if worker.maybe_lift_quarantine(timestamp) {
+            info!(?worker_id, "Worker quarantine expired");
+            self.worker_change_notify.notify_one();
+        }
         for operation_id in worker.running_action_infos.keys() {
             if self
                 .operation_keep_alive_tx
@@ -172,17 +215,20 @@ impl ApiWorkerSchedulerImpl {
         result
     }
 
-    /// Sets if the worker is draining or not.
+    /// Sets if the worker is draining or not. A draining worker with a
+    /// deadline is removed from the pool once the deadline passes.
     async fn set_drain_worker(
         &mut self,
         worker_id: &WorkerId,
         is_draining: bool,
+        drain_deadline: Option<WorkerTimestamp>,
     ) -> Result<(), Error> {
         let worker = self
             .workers
             .get_mut(worker_id)
             .err_tip(|| format!("Worker {worker_id} doesn't exist in the pool"))?;
         worker.is_draining = is_draining;
+        worker.drain_deadline_timestamp = drain_deadline.filter(|_| is_draining);
         self.worker_change_notify.notify_one();
         Ok(())
     }
@@ -191,6 +237,7 @@ impl ApiWorkerSchedulerImpl {
     fn inner_worker_checker(
         (worker_id, w): &(&WorkerId, &Worker),
         platform_properties: &PlatformProperties,
+        exclusive: bool,
     ) -> bool {
         #[cfg(feature = "worker_find_logging")]
         {
@@ -201,6 +248,12 @@ impl ApiWorkerSchedulerImpl {
                 );
                 return false;
             }
+            if !w.allows_exclusivity(exclusive) {
+                info!(
+                    "Worker {worker_id} cannot accept work because of an exclusive action, exclusive: {exclusive}"
+                );
+                return false;
+            }
             if !platform_properties.is_satisfied_by(&w.platform_properties) {
                 info!("Worker {worker_id} properties are insufficient");
                 return false;
@@ -209,47 +262,278 @@ impl ApiWorkerSchedulerImpl {
         }
         #[cfg(not(feature = "worker_find_logging"))]
         {
-            w.can_accept_work() && platform_properties.is_satisfied_by(&w.platform_properties)
+            w.can_accept_work()
+                && w.allows_exclusivity(exclusive)
+                && platform_properties.is_satisfied_by(&w.platform_properties)
         }
     }
 
-    fn inner_find_worker_for_action(
+    /// Finds the unreserved worker with the fewest running actions that
+    /// could run an exclusive action requiring `platform_properties` once
+    /// it is idle. Returns the number of actions running on it too.
+    fn inner_find_worker_to_reserve(
         &self,
         platform_properties: &PlatformProperties,
-    ) -> Option<WorkerId> {
-        let mut workers_iter = self.workers.iter();
-        let workers_iter =
-            match self.allocation_strategy {
-                // Use rfind to get the least recently used that satisfies the properties.
-                WorkerAllocationStrategy::LeastRecentlyUsed => workers_iter
-                    .rfind(|worker| Self::inner_worker_checker(worker, platform_properties)),
-                // Use find to get the most recently used that satisfies the properties.
-                WorkerAllocationStrategy::MostRecentlyUsed => workers_iter
-                    .find(|worker| Self::inner_worker_checker(worker, platform_properties)),
-            };
-        workers_iter.map(|(_, w)| w.id.clone())
+    ) -> Option<(usize, &WorkerId)> {
+        self.workers
+            .iter()
+            .filter(|(_, worker)| {
+                worker.can_accept_work()
+                    && !worker.is_reserved_for_exclusive_action
+                    && worker.could_run_when_idle(platform_properties)
+            })
+            .map(|(worker_id, worker)| (worker.running_action_infos.len(), worker_id))
+            .min_by_key(|(running_actions, _)| *running_actions)
     }
 
-    async fn update_action(
+    /// Asks up to `max_workers` idle workers able to run an action requiring
+    /// `platform_properties`, and that did not recently see its input root,
+    /// to prefetch `input_root_digest`. Returns the number of workers asked.
+    async fn inner_prefetch_inputs(
+        &mut self,
+        platform_properties: &PlatformProperties,
+        input_root_digest: DigestInfo,
+        max_workers: usize,
+    ) -> usize {
+        let mut hinted_workers = 0;
+        for (worker_id, worker) in self.workers.iter_mut() {
+            if hinted_workers >= max_workers {
+                break;
+            }
+            if worker.has_actions()
+                || !worker.can_accept_work()
+                || !worker.supports_feature(PREFETCH_INPUTS)
+                || worker.has_recent_input_root(&input_root_digest)
+                || !platform_properties.is_satisfied_by(&worker.platform_properties)
+            {
+                continue;
+            }
+            // A worker that disconnected is evicted by the next keep alive,
+            // the hint is only best effort.
+            if let Err(err) = worker
+                .notify_update(WorkerUpdate::PrefetchInputs(vec![input_root_digest]))
+                .await
+            {
+                warn!(?worker_id, ?err, "Failed to send prefetch hint to worker");
+                continue;
+            }
+            if self.input_root_affinity_size > 0 {
+                worker.record_input_root(input_root_digest, self.input_root_affinity_size);
+            }
+            hinted_workers += 1;
+        }
+        hinted_workers
+    }
+
+    /// Returns whether `worker` can run an action requiring
+    /// `platform_properties`, and if not, why.
+    fn inner_placement_status(
+        worker: &Worker,
+        platform_properties: &PlatformProperties,
+    ) -> WorkerPlacementStatus {
+        if worker.is_paused {
+            return WorkerPlacementStatus::Paused;
+        }
+        if worker.is_draining {
+            return WorkerPlacementStatus::Draining;
+        }
+        if worker.is_quarantined {
+            return WorkerPlacementStatus::Quarantined;
+        }
+        if worker.is_reserved_for_exclusive_action || worker.is_running_exclusive_action() {
+            return WorkerPlacementStatus::Exclusive;
+        }
+        let Some(property) =
+            platform_properties.first_unsatisfied_property(&worker.platform_properties)
+        else {
+            return WorkerPlacementStatus::Available;
+        };
+        let property = property.to_string();
+        if !worker
+            .platform_properties
+            .properties
+            .contains_key(&property)
+        {
+            return WorkerPlacementStatus::MissingProperty { property };
+        }
+        // Running actions reserve some of the worker's properties, check if
+        // the worker would match once they give them back.
+        let mut idle_platform_properties = worker.platform_properties.clone();
+        for pending_action_info in worker.running_action_infos.values() {
+            restore_platform_properties(
+                &mut idle_platform_properties,
+                &pending_action_info.action_info.platform_properties,
+            );
+        }
+        if platform_properties.is_satisfied_by(&idle_platform_properties) {
+            WorkerPlacementStatus::Busy { property }
+        } else {
+            WorkerPlacementStatus::PropertyMismatch { property }
+        }
+    }
+
+    /// Finds the lowest priority operation running below `priority` on a
+    /// worker that could run an action with `platform_properties` once that
+    /// operation is stopped. Returns the priority of the operation too.
+    fn inner_find_operation_to_preempt(
+        &self,
+        platform_properties: &PlatformProperties,
+        priority: i32,
+    ) -> Option<(i32, WorkerId, OperationId)> {
+        let mut best_candidate: Option<(i32, &WorkerId, &OperationId)> = None;
+        for (worker_id, worker) in self.workers.iter() {
+            if !worker.can_accept_work() || !worker.allows_exclusivity(false) {
+                continue;
+            }
+            for (operation_id, pending_action_info) in &worker.running_action_infos {
+                let running_priority = pending_action_info.action_info.inner.priority;
+                if running_priority >= priority
+                    || best_candidate
+                        .is_some_and(|(best_priority, _, _)| best_priority <= running_priority)
+                {
+                    continue;
+                }
+                let mut freed_platform_properties = worker.platform_properties.clone();
+                restore_platform_properties(
+                    &mut freed_platform_properties,
+                    &pending_action_info.action_info.platform_properties,
+                );
+                if platform_properties.is_satisfied_by(&freed_platform_properties) {
+                    best_candidate = Some((running_priority, worker_id, operation_id));
+                }
+            }
+        }
+        best_candidate.map(|(running_priority, worker_id, operation_id)| {
+            (running_priority, worker_id.clone(), operation_id.clone())
+        })
+    }
+
+    /// Kills the operation on the worker and puts it back in the queue.
+    async fn preempt_operation(
         &mut self,
         worker_id: &WorkerId,
         operation_id: &OperationId,
-        update: UpdateOperationType,
     ) -> Result<(), Error> {
         let worker = self.workers.get_mut(worker_id).err_tip(|| {
-            format!("Worker {worker_id} does not exist in SimpleScheduler::update_action")
+            format!("Worker {worker_id} does not exist in SimpleScheduler::preempt_operation")
         })?;
+        let preempt_result = worker
+            .preempt_action(operation_id)
+            .await
+            .err_tip(|| "In SimpleScheduler::preempt_operation");
+        // A disconnect puts the operation back in the queue without counting
+        // against its retries, which is what we want for a preemption.
+        let requeue_result = self
+            .worker_state_manager
+            .update_operation(
+                operation_id,
+                worker_id,
+                UpdateOperationType::UpdateWithDisconnect,
+            )
+            .await;
+        self.worker_change_notify.notify_one();
+        preempt_result.merge(requeue_result)
+    }
 
-        // Ensure the worker is supposed to be running the operation.
-        if !worker.running_action_infos.contains_key(operation_id) {
-            let err = make_err!(
-                Code::Internal,
-                "Operation {operation_id} should not be running on worker {worker_id} in SimpleScheduler::update_action"
+    /// Asks the worker running the operation, if any, to kill it. Returns
+    /// true if a worker was running the operation.
+    async fn kill_operation(&mut self, operation_id: &OperationId) -> Result<bool, Error> {
+        let Some(worker) = self
+            .workers
+            .iter_mut()
+            .map(|(_, worker)| worker)
+            .find(|worker| worker.running_action_infos.contains_key(operation_id))
+        else {
+            return Ok(false);
+        };
+        // Killing works the same way as for a preemption, so late updates
+        // from the worker are ignored.
+        let kill_result = worker
+            .preempt_action(operation_id)
+            .await
+            .err_tip(|| "In SimpleScheduler::kill_operation");
+        self.worker_change_notify.notify_one();
+        kill_result.map(|()| true)
+    }
+
+    /// Stops operations that are running past their action timeout plus the
+    /// grace period and fails them, so they are retried or reported to the
+    /// client. The deadline of an operation is set the first time it is seen
+    /// here, so it is never earlier than intended.
+    async fn timeout_overdue_operations(
+        &mut self,
+        now_timestamp: WorkerTimestamp,
+    ) -> Result<(), Error> {
+        let mut overdue_operations = Vec::new();
+        for (worker_id, worker) in self.workers.iter_mut() {
+            for (operation_id, pending_action_info) in &mut worker.running_action_infos {
+                let timeout_s = pending_action_info.action_info.inner.timeout.as_secs();
+                // Actions without a timeout are bounded by the worker instead.
+                if timeout_s == 0 {
+                    continue;
+                }
+                let execution_deadline = *pending_action_info.execution_deadline.get_or_insert(
+                    now_timestamp
+                        .saturating_add(timeout_s)
+                        .saturating_add(self.execution_timeout_grace_s),
+                );
+                if execution_deadline <= now_timestamp {
+                    overdue_operations.push((worker_id.clone(), operation_id.clone(), timeout_s));
+                }
+            }
+        }
+
+        let mut result = Ok(());
+        for (worker_id, operation_id, timeout_s) in overdue_operations {
+            warn!(
+                ?worker_id,
+                ?operation_id,
+                timeout_s,
+                "Operation ran past its timeout, stopping it"
             );
-            return Result::<(), _>::Err(err.clone())
-                .merge(self.immediate_evict_worker(worker_id, err, false).await);
+            self.execution_timeouts.inc();
+            // Peek so the worker keeps its place in the timeout order.
+            if let Some(worker) = self.workers.peek_mut(&worker_id) {
+                // Killing works the same way as for a preemption, so late
+                // updates from the worker are ignored.
+                result = result.merge(
+                    worker
+                        .preempt_action(&operation_id)
+                        .await
+                        .err_tip(|| "In SimpleScheduler::timeout_overdue_operations"),
+                );
+            }
+            result = result.merge(
+                self.worker_state_manager
+                    .update_operation(
+                        &operation_id,
+                        &worker_id,
+                        UpdateOperationType::UpdateWithError(make_err!(
+                            Code::DeadlineExceeded,
+                            "Operation {operation_id} did not finish on worker {worker_id} within its timeout of {timeout_s}s"
+                        )),
+                    )
+                    .await,
+            );
+            self.worker_change_notify.notify_one();
         }
+        result
+    }
 
+    async fn update_action(
+        &mut self,
+        worker_id: &WorkerId,
+        operation_id: &OperationId,
+        update: UpdateOperationType,
+    ) -> Result<(), Error> {
+        let worker = self.workers.get_mut(worker_id).err_tip(|| {
+            format!("Worker {worker_id} does not exist in SimpleScheduler::update_action")
+        })?;
+
+        let is_failure = matches!(
+            &update,
+            UpdateOperationType::UpdateWithError(err) if err.code != Code::ResourceExhausted
+        );
         let (is_finished, due_to_backpressure) = match &update {
             UpdateOperationType::UpdateWithActionStage(action_stage) => {
                 (action_stage.is_finished(), false)
@@ -261,6 +545,25 @@ impl ApiWorkerSchedulerImpl {
             UpdateOperationType::UpdateWithDisconnect => (true, false),
         };
 
+        // The operation was preempted on this worker and has already been put
+        // back in the queue, so anything the worker reports about it is stale.
+        if worker.is_preempted(operation_id) {
+            if is_finished {
+                worker.clear_preempted(operation_id);
+            }
+            return Ok(());
+        }
+
+        // Ensure the worker is supposed to be running the operation.
+        if !worker.running_action_infos.contains_key(operation_id) {
+            let err = make_err!(
+                Code::Internal,
+                "Operation {operation_id} should not be running on worker {worker_id} in SimpleScheduler::update_action"
+            );
+            return Result::<(), _>::Err(err.clone())
+                .merge(self.immediate_evict_worker(worker_id, err, false).await);
+        }
+
         // Update the operation in the worker state manager.
         {
             let update_operation_res = self
@@ -294,6 +597,24 @@ impl ApiWorkerSchedulerImpl {
             if (was_paused || due_to_backpressure) && worker.has_actions() {
                 worker.is_paused = true;
             }
+
+            if let Some(worker_quarantine) = &self.worker_quarantine {
+                let cooldown_s = if worker_quarantine.cooldown_s == 0 {
+                    DEFAULT_WORKER_QUARANTINE_COOLDOWN_S
+                } else {
+                    worker_quarantine.cooldown_s
+                };
+                if worker.record_action_outcome(
+                    is_failure,
+                    worker_quarantine.failure_threshold,
+                    cooldown_s,
+                ) {
+                    warn!(
+                        ?worker_id,
+                        cooldown_s, "Quarantining worker after repeated action failures"
+                    );
+                }
+            }
             complete_action_res
         };
 
@@ -311,6 +632,7 @@ impl ApiWorkerSchedulerImpl {
         action_info: ActionInfoWithProps,
     ) -> Result<(), Error> {
         if let Some(worker) = self.workers.get_mut(&worker_id) {
+            let input_root_digest = action_info.inner.input_root_digest;
             let notify_worker_result = worker
                 .notify_update(WorkerUpdate::RunAction((operation_id, action_info.clone())))
                 .await;
@@ -340,6 +662,9 @@ impl ApiWorkerSchedulerImpl {
                         .await,
                 );
             }
+            if self.input_root_affinity_size > 0 {
+                worker.record_input_root(input_root_digest, self.input_root_affinity_size);
+            }
             Ok(())
         } else {
             warn!(
@@ -388,12 +713,122 @@ impl ApiWorkerSchedulerImpl {
         self.worker_change_notify.notify_one();
         result
     }
+
+    /// Removes the workers of this shard that timed out or passed their
+    /// drain deadline and stops operations running past their timeout.
+    async fn remove_timedout_workers(
+        &mut self,
+        now_timestamp: WorkerTimestamp,
+        worker_timeout_s: u64,
+    ) -> Result<(), Error> {
+        let mut result = Ok(());
+        // Items should be sorted based on last_update_timestamp, so we don't need to iterate the entire
+        // map most of the time.
+        let worker_ids_to_remove: Vec<WorkerId> = self
+            .workers
+            .iter()
+            .rev()
+            .map_while(|(worker_id, worker)| {
+                if worker.last_update_timestamp <= now_timestamp - worker_timeout_s {
+                    Some(worker_id.clone())
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for worker_id in &worker_ids_to_remove {
+            warn!(?worker_id, "Worker timed out, removing from pool");
+            result = result.merge(
+                self.immediate_evict_worker(
+                    worker_id,
+                    make_err!(
+                        Code::Internal,
+                        "Worker {worker_id} timed out, removing from pool"
+                    ),
+                    false,
+                )
+                .await,
+            );
+        }
+
+        // Draining workers are not necessarily the least recently used ones,
+        // so all workers need to be checked for a passed drain deadline.
+        let drained_worker_ids: Vec<WorkerId> = self
+            .workers
+            .iter()
+            .filter(|(_, worker)| {
+                worker
+                    .drain_deadline_timestamp
+                    .is_some_and(|deadline| deadline <= now_timestamp)
+            })
+            .map(|(worker_id, _)| worker_id.clone())
+            .collect();
+        for worker_id in &drained_worker_ids {
+            warn!(
+                ?worker_id,
+                "Worker drain deadline passed, requeueing its operations and removing from pool"
+            );
+            result = result.merge(
+                self.immediate_evict_worker(
+                    worker_id,
+                    make_err!(
+                        Code::Internal,
+                        "Worker {worker_id} drain deadline passed, removing from pool"
+                    ),
+                    true,
+                )
+                .await,
+            );
+        }
+
+        if self.execution_timeout_grace_s > 0 {
+            result = result.merge(self.timeout_overdue_operations(now_timestamp).await);
+        }
+
+        result
+    }
+}
+
+/// The worker pool, split into shards by worker id so that operations on
+/// unrelated workers do not wait on each other.
+#[derive(Debug)]
+struct WorkerShards {
+    shards: Box<[Mutex<ApiWorkerSchedulerImpl>]>,
+    hasher: RandomState,
+}
+
+impl WorkerShards {
+    /// Returns the shard the worker belongs to.
+    fn shard_for(&self, worker_id: &WorkerId) -> &Mutex<ApiWorkerSchedulerImpl> {
+        let index = self.hasher.hash_one(worker_id) % self.shards.len() as u64;
+        &self.shards[index as usize]
+    }
+}
+
+impl MetricsComponent for WorkerShards {
+    fn publish(
+        &self,
+        kind: MetricKind,
+        field_metadata: MetricFieldData,
+    ) -> Result<MetricPublishKnownKindData, nativelink_metric::Error> {
+        for shard in &self.shards {
+            shard.publish(kind, field_metadata.clone())?;
+        }
+        Ok(MetricPublishKnownKindData::Component)
+    }
 }
 
 #[derive(Debug, MetricsComponent)]
 pub struct ApiWorkerScheduler {
     #[metric]
-    inner: Mutex<ApiWorkerSchedulerImpl>,
+    shards: WorkerShards,
+    /// The worker state manager.
+    #[metric(group = "worker_state_manager")]
+    worker_state_manager: Arc<dyn WorkerStateManager>,
+    /// Decides which of the eligible workers runs an action.
+    scheduling_policy: Box<dyn SchedulingPolicy>,
+    #[metric(help = "The number of operations stopped for running past their timeout.")]
+    execution_timeouts: Arc<CounterWithTime>,
     #[metric(group = "platform_property_manager")]
     platform_property_manager: Arc<PlatformPropertyManager>,
 
@@ -401,28 +836,55 @@ pub struct ApiWorkerScheduler {
         help = "Timeout of how long to evict workers if no response in this given amount of time in seconds."
     )]
     worker_timeout_s: u64,
+    /// Whether queued operations are no longer matched to workers.
+    draining: AtomicBool,
+    /// Wakes the matching engine up when draining stops.
+    worker_change_notify: Arc<Notify>,
     _operation_keep_alive_spawn: JoinHandleDropGuard<()>,
 }
 
 impl ApiWorkerScheduler {
+    #[expect(clippy::too_many_arguments)]
     pub fn new(
         worker_state_manager: Arc<dyn WorkerStateManager>,
         platform_property_manager: Arc<PlatformPropertyManager>,
-        allocation_strategy: WorkerAllocationStrategy,
+        scheduling_policy: Box<dyn SchedulingPolicy>,
+        input_root_affinity_size: usize,
+        worker_quarantine: Option<WorkerQuarantineSpec>,
+        execution_timeout_grace_s: u64,
         worker_change_notify: Arc<Notify>,
         worker_timeout_s: u64,
+        worker_shards: usize,
     ) -> Arc<Self> {
         let (operation_keep_alive_tx, mut operation_keep_alive_rx) = mpsc::unbounded_channel();
+        let execution_timeouts = Arc::new(CounterWithTime::default());
+        let use_sequence = Arc::new(AtomicU64::new(0));
+        let shards = (0..worker_shards.max(1))
+            .map(|_| {
+                Mutex::new(ApiWorkerSchedulerImpl {
+                    workers: Workers(LruCache::unbounded(), use_sequence.clone()),
+                    worker_state_manager: worker_state_manager.clone(),
+                    input_root_affinity_size,
+                    worker_quarantine,
+                    execution_timeout_grace_s,
+                    execution_timeouts: execution_timeouts.clone(),
+                    worker_change_notify: worker_change_notify.clone(),
+                    operation_keep_alive_tx: operation_keep_alive_tx.clone(),
+                })
+            })
+            .collect();
         Arc::new(Self {
-            inner: Mutex::new(ApiWorkerSchedulerImpl {
-                workers: Workers(LruCache::unbounded()),
-                worker_state_manager: worker_state_manager.clone(),
-                allocation_strategy,
-                worker_change_notify,
-                operation_keep_alive_tx,
-            }),
+            shards: WorkerShards {
+                shards,
+                hasher: RandomState::new(),
+            },
+            worker_state_manager: worker_state_manager.clone(),
+            scheduling_policy,
+            execution_timeouts,
             platform_property_manager,
             worker_timeout_s,
+            draining: AtomicBool::new(false),
+            worker_change_notify,
             _operation_keep_alive_spawn: spawn!(
                 "simple_scheduler_operation_keep_alive",
                 async move {
@@ -463,28 +925,284 @@ impl ApiWorkerScheduler {
         operation_id: OperationId,
         action_info: ActionInfoWithProps,
     ) -> Result<(), Error> {
-        let mut inner = self.inner.lock().await;
+        let mut inner = self.shards.shard_for(&worker_id).lock().await;
         inner
             .worker_notify_run_action(worker_id, operation_id, action_info)
             .await
     }
 
     /// Attempts to find a worker that is capable of running this action.
+    /// An `exclusive` action only goes to a worker without running actions;
+    /// if there is none, the capable worker with the fewest running actions
+    /// is reserved for it until the next `clear_exclusive_reservations`.
     // TODO(palfrey) This algorithm is not very efficient. Simple testing using a tree-like
     // structure showed worse performance on a 10_000 worker * 7 properties * 1000 queued tasks
     // simulation of worst cases in a single threaded environment.
     pub async fn find_worker_for_action(
         &self,
         platform_properties: &PlatformProperties,
+        input_root_digest: &DigestInfo,
+        excluded_worker_ids: &[WorkerId],
+        exclusive: bool,
     ) -> Option<WorkerId> {
-        let inner = self.inner.lock().await;
-        inner.inner_find_worker_for_action(platform_properties)
+        // Only avoid the excluded workers if another worker can take the
+        // action, so it is not stuck when they are the only capable ones.
+        let maybe_worker_id = match self
+            .select_worker_across_shards(
+                platform_properties,
+                input_root_digest,
+                excluded_worker_ids,
+                exclusive,
+            )
+            .await
+        {
+            None if !excluded_worker_ids.is_empty() => {
+                self.select_worker_across_shards(
+                    platform_properties,
+                    input_root_digest,
+                    &[],
+                    exclusive,
+                )
+                .await
+            }
+            maybe_worker_id => maybe_worker_id,
+        };
+        if exclusive && maybe_worker_id.is_none() {
+            // Keep a worker free of new actions while its running ones
+            // finish, otherwise the exclusive action could wait forever.
+            self.reserve_worker_for_exclusive_action(platform_properties)
+                .await;
+        }
+        maybe_worker_id
+    }
+
+    /// Runs the scheduling policy over the capable workers that are not in
+    /// `excluded_worker_ids`. Shards are searched one after the other, and
+    /// the worker chosen so far is offered again with the workers of the
+    /// next shard, so only its shard stays locked with the one searched.
+    async fn select_worker_across_shards(
+        &self,
+        platform_properties: &PlatformProperties,
+        input_root_digest: &DigestInfo,
+        excluded_worker_ids: &[WorkerId],
+        exclusive: bool,
+    ) -> Option<WorkerId> {
+        let placement = ActionPlacement {
+            platform_properties,
+            input_root_digest,
+        };
+        let mut best: Option<(MutexGuard<'_, ApiWorkerSchedulerImpl>, WorkerId)> = None;
+        for shard in &self.shards.shards {
+            let shard = shard.lock().await;
+            let maybe_worker_id = {
+                let mut candidates: Vec<&Worker> = shard
+                    .workers
+                    .iter()
+                    .filter(|worker| {
+                        ApiWorkerSchedulerImpl::inner_worker_checker(
+                            worker,
+                            platform_properties,
+                            exclusive,
+                        )
+                    })
+                    .map(|(_, worker)| worker)
+                    .filter(|worker| !excluded_worker_ids.contains(&worker.id))
+                    .collect();
+                if candidates.is_empty() {
+                    continue;
+                }
+                candidates.extend(
+                    best.as_ref()
+                        .and_then(|(best_shard, worker_id)| best_shard.workers.peek(worker_id)),
+                );
+                // Each shard only knows the order of its own workers, so
+                // restore the order of the whole pool, from the most to the
+                // least recently used.
+                candidates.sort_unstable_by_key(|worker| Reverse(worker.last_used_sequence));
+                self.scheduling_policy
+                    .select_worker(&candidates, &placement)
+                    .map(|worker| worker.id.clone())
+            };
+            let Some(worker_id) = maybe_worker_id else {
+                continue;
+            };
+            if best
+                .as_ref()
+                .is_none_or(|(_, best_worker_id)| *best_worker_id != worker_id)
+            {
+                best = Some((shard, worker_id));
+            }
+        }
+        best.map(|(_, worker_id)| worker_id)
+    }
+
+    /// Reserves the capable worker with the fewest running actions for an
+    /// exclusive action that no worker can run right now.
+    async fn reserve_worker_for_exclusive_action(&self, platform_properties: &PlatformProperties) {
+        let mut reservation: Option<(usize, &Mutex<ApiWorkerSchedulerImpl>, WorkerId)> = None;
+        for shard in &self.shards.shards {
+            let inner = shard.lock().await;
+            let Some((running_actions, worker_id)) =
+                inner.inner_find_worker_to_reserve(platform_properties)
+            else {
+                continue;
+            };
+            if reservation
+                .as_ref()
+                .is_none_or(|(best_running_actions, ..)| running_actions < *best_running_actions)
+            {
+                reservation = Some((running_actions, shard, worker_id.clone()));
+            }
+        }
+        let Some((_, shard, worker_id)) = reservation else {
+            return;
+        };
+        // The worker may have left the pool since the search.
+        if let Some(worker) = shard.lock().await.workers.peek_mut(&worker_id) {
+            worker.is_reserved_for_exclusive_action = true;
+        }
+    }
+
+    /// Asks up to `max_workers` idle workers able to run an action requiring
+    /// `platform_properties` to download `input_root_digest` ahead of time.
+    /// Returns the number of workers asked.
+    pub async fn prefetch_inputs(
+        &self,
+        platform_properties: &PlatformProperties,
+        input_root_digest: DigestInfo,
+        max_workers: usize,
+    ) -> usize {
+        let mut hinted_workers = 0;
+        for shard in &self.shards.shards {
+            if hinted_workers >= max_workers {
+                break;
+            }
+            hinted_workers += shard
+                .lock()
+                .await
+                .inner_prefetch_inputs(
+                    platform_properties,
+                    input_root_digest,
+                    max_workers - hinted_workers,
+                )
+                .await;
+        }
+        hinted_workers
+    }
+
+    /// Lifts the reservations made for exclusive actions. Called before every
+    /// matching pass, which reserves the workers again for the exclusive
+    /// actions that are still queued.
+    pub async fn clear_exclusive_reservations(&self) {
+        for shard in &self.shards.shards {
+            for (_, worker) in shard.lock().await.workers.iter_mut() {
+                worker.is_reserved_for_exclusive_action = false;
+            }
+        }
+    }
+
+    /// Reports for every worker, from the most to the least recently used,
+    /// whether it can run an action requiring `platform_properties`.
+    /// `excluded_worker_ids` are the workers the action already failed on.
+    pub async fn explain_placement(
+        &self,
+        platform_properties: &PlatformProperties,
+        excluded_worker_ids: &[WorkerId],
+    ) -> Vec<WorkerPlacement> {
+        let mut workers = Vec::new();
+        for shard in &self.shards.shards {
+            let inner = shard.lock().await;
+            workers.extend(inner.workers.iter().map(|(worker_id, worker)| {
+                let status = match ApiWorkerSchedulerImpl::inner_placement_status(
+                    worker,
+                    platform_properties,
+                ) {
+                    WorkerPlacementStatus::Available if excluded_worker_ids.contains(worker_id) => {
+                        WorkerPlacementStatus::PreviouslyFailed
+                    }
+                    status => status,
+                };
+                (
+                    worker.last_used_sequence,
+                    WorkerPlacement {
+                        worker_id: worker_id.clone(),
+                        status,
+                    },
+                )
+            }));
+        }
+        // Each shard only knows the order of its own workers, so restore the
+        // order of the whole pool, from the most to the least recently used.
+        workers.sort_unstable_by_key(|(last_used_sequence, _)| Reverse(*last_used_sequence));
+        workers
+            .into_iter()
+            .map(|(_, worker_placement)| worker_placement)
+            .collect()
+    }
+
+    /// Tries to make room for an action that could not be matched to any
+    /// worker by preempting an operation with a lower priority. `may_preempt`
+    /// is only called once a candidate was found and may veto the
+    /// preemption. Returns true if an operation was preempted.
+    pub async fn preempt_operation_for_action(
+        &self,
+        platform_properties: &PlatformProperties,
+        priority: i32,
+        may_preempt: impl FnOnce() -> bool + Send,
+    ) -> Result<bool, Error> {
+        let mut best_candidate: Option<(i32, &Mutex<ApiWorkerSchedulerImpl>)> = None;
+        for shard in &self.shards.shards {
+            let Some((running_priority, ..)) = shard
+                .lock()
+                .await
+                .inner_find_operation_to_preempt(platform_properties, priority)
+            else {
+                continue;
+            };
+            if best_candidate.is_none_or(|(best_priority, _)| running_priority < best_priority) {
+                best_candidate = Some((running_priority, shard));
+            }
+        }
+        let Some((_, shard)) = best_candidate else {
+            return Ok(false);
+        };
+        // The shard was unlocked after the search, so look for the operation
+        // to preempt again, it may have finished in the meantime.
+        let mut inner = shard.lock().await;
+        let Some((_, worker_id, operation_id)) =
+            inner.inner_find_operation_to_preempt(platform_properties, priority)
+        else {
+            return Ok(false);
+        };
+        if !may_preempt() {
+            return Ok(false);
+        }
+        warn!(
+            ?worker_id,
+            ?operation_id,
+            priority,
+            "Preempting operation to make room for a higher priority action"
+        );
+        inner.preempt_operation(&worker_id, &operation_id).await?;
+        Ok(true)
+    }
+
+    /// Asks the worker running the operation, if any, to kill it and frees
+    /// the resources it held for the operation. Returns true if a worker was
+    /// running the operation.
+    pub async fn kill_operation(&self, operation_id: &OperationId) -> Result<bool, Error> {
+        for shard in &self.shards.shards {
+            if shard.lock().await.kill_operation(operation_id).await? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
     }
 
     /// Checks to see if the worker exists in the worker pool. Should only be used in unit tests.
     #[must_use]
     pub async fn contains_worker_for_test(&self, worker_id: &WorkerId) -> bool {
-        let inner = self.inner.lock().await;
+        let inner = self.shards.shard_for(worker_id).lock().await;
         inner.workers.contains(worker_id)
     }
 
@@ -493,12 +1211,24 @@ impl ApiWorkerScheduler {
         &self,
         worker_id: &WorkerId,
     ) -> Result<(), Error> {
-        let mut inner = self.inner.lock().await;
+        let mut inner = self.shards.shard_for(worker_id).lock().await;
         let worker = inner.workers.get_mut(worker_id).ok_or_else(|| {
             make_input_err!("WorkerId '{}' does not exist in workers map", worker_id)
         })?;
         worker.keep_alive()
     }
+
+    /// A unit test function used to read the utilization a worker reported.
+    pub async fn worker_utilization_for_test(
+        &self,
+        worker_id: &WorkerId,
+    ) -> Option<WorkerUtilization> {
+        let inner = self.shards.shard_for(worker_id).lock().await;
+        inner
+            .workers
+            .peek(worker_id)
+            .and_then(|worker| worker.utilization)
+    }
 }
 
 #[async_trait]
@@ -508,8 +1238,8 @@ impl WorkerScheduler for ApiWorkerScheduler {
     }
 
     async fn add_worker(&self, worker: Worker) -> Result<(), Error> {
-        let mut inner = self.inner.lock().await;
         let worker_id = worker.id.clone();
+        let mut inner = self.shards.shard_for(&worker_id).lock().await;
         let result = inner
             .add_worker(worker)
             .err_tip(|| "Error while adding worker, removing from pool");
@@ -526,7 +1256,7 @@ impl WorkerScheduler for ApiWorkerScheduler {
         operation_id: &OperationId,
         update: UpdateOperationType,
     ) -> Result<(), Error> {
-        let mut inner = self.inner.lock().await;
+        let mut inner = self.shards.shard_for(worker_id).lock().await;
         inner.update_action(worker_id, operation_id, update).await
     }
 
@@ -535,14 +1265,32 @@ impl WorkerScheduler for ApiWorkerScheduler {
         worker_id: &WorkerId,
         timestamp: WorkerTimestamp,
     ) -> Result<(), Error> {
-        let mut inner = self.inner.lock().await;
+        let mut inner = self.shards.shard_for(worker_id).lock().await;
         inner
             .refresh_lifetime(worker_id, timestamp)
             .err_tip(|| "Error refreshing lifetime in worker_keep_alive_received()")
     }
 
+    async fn update_worker_utilization(
+        &self,
+        worker_id: &WorkerId,
+        utilization: WorkerUtilization,
+    ) -> Result<(), Error> {
+        let mut inner = self.shards.shard_for(worker_id).lock().await;
+        // Use peek_mut so reporting utilization does not change the order
+        // workers are allocated in.
+        let worker = inner.workers.peek_mut(worker_id).ok_or_else(|| {
+            make_input_err!(
+                "Worker not found in worker map in update_worker_utilization() {}",
+                worker_id
+            )
+        })?;
+        worker.utilization = Some(utilization);
+        Ok(())
+    }
+
     async fn remove_worker(&self, worker_id: &WorkerId) -> Result<(), Error> {
-        let mut inner = self.inner.lock().await;
+        let mut inner = self.shards.shard_for(worker_id).lock().await;
         inner
             .immediate_evict_worker(
                 worker_id,
@@ -553,66 +1301,69 @@ impl WorkerScheduler for ApiWorkerScheduler {
     }
 
     async fn shutdown(&self, shutdown_guard: ShutdownGuard) {
-        let mut inner = self.inner.lock().await;
-        while let Some(worker_id) = inner
-            .workers
-            .peek_lru()
-            .map(|(worker_id, _worker)| worker_id.clone())
-        {
-            if let Err(err) = inner
-                .immediate_evict_worker(
-                    &worker_id,
-                    make_err!(Code::Internal, "Scheduler shutdown"),
-                    true,
-                )
-                .await
+        for shard in &self.shards.shards {
+            let mut inner = shard.lock().await;
+            while let Some(worker_id) = inner
+                .workers
+                .peek_lru()
+                .map(|(worker_id, _worker)| worker_id.clone())
             {
-                error!(?err, "Error evicting worker on shutdown.");
+                if let Err(err) = inner
+                    .immediate_evict_worker(
+                        &worker_id,
+                        make_err!(Code::Internal, "Scheduler shutdown"),
+                        true,
+                    )
+                    .await
+                {
+                    error!(?err, "Error evicting worker on shutdown.");
+                }
             }
         }
         drop(shutdown_guard);
     }
 
     async fn remove_timedout_workers(&self, now_timestamp: WorkerTimestamp) -> Result<(), Error> {
-        let mut inner = self.inner.lock().await;
-
         let mut result = Ok(());
-        // Items should be sorted based on last_update_timestamp, so we don't need to iterate the entire
-        // map most of the time.
-        let worker_ids_to_remove: Vec<WorkerId> = inner
-            .workers
-            .iter()
-            .rev()
-            .map_while(|(worker_id, worker)| {
-                if worker.last_update_timestamp <= now_timestamp - self.worker_timeout_s {
-                    Some(worker_id.clone())
-                } else {
-                    None
-                }
-            })
-            .collect();
-        for worker_id in &worker_ids_to_remove {
-            warn!(?worker_id, "Worker timed out, removing from pool");
+        for shard in &self.shards.shards {
             result = result.merge(
-                inner
-                    .immediate_evict_worker(
-                        worker_id,
-                        make_err!(
-                            Code::Internal,
-                            "Worker {worker_id} timed out, removing from pool"
-                        ),
-                        false,
-                    )
+                shard
+                    .lock()
+                    .await
+                    .remove_timedout_workers(now_timestamp, self.worker_timeout_s)
                     .await,
             );
         }
-
         result
     }
 
     async fn set_drain_worker(&self, worker_id: &WorkerId, is_draining: bool) -> Result<(), Error> {
-        let mut inner = self.inner.lock().await;
-        inner.set_drain_worker(worker_id, is_draining).await
+        let mut inner = self.shards.shard_for(worker_id).lock().await;
+        inner.set_drain_worker(worker_id, is_draining, None).await
+    }
+
+    async fn drain_worker_with_deadline(
+        &self,
+        worker_id: &WorkerId,
+        deadline: WorkerTimestamp,
+    ) -> Result<(), Error> {
+        let mut inner = self.shards.shard_for(worker_id).lock().await;
+        inner
+            .set_drain_worker(worker_id, true, Some(deadline))
+            .await
+    }
+
+    fn set_draining(&self, is_draining: bool) -> Result<(), Error> {
+        let was_draining = self.draining.swap(is_draining, Ordering::AcqRel);
+        if was_draining && !is_draining {
+            // Operations queued while draining are waiting to be matched.
+            self.worker_change_notify.notify_one();
+        }
+        Ok(())
+    }
+
+    fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Acquire)
     }
 }
 
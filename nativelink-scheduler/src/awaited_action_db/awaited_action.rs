@@ -90,6 +90,15 @@ pub struct AwaitedAction {
     /// Number of attempts the job has been tried.
     #[metric(help = "The number of attempts the AwaitedAction has been tried")]
     pub attempts: usize,
+
+    /// Workers on which an attempt of this action failed, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    failed_worker_ids: Vec<WorkerId>,
+
+    /// The time the action entered its current stage. None for actions
+    /// stored before this was tracked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stage_entered_timestamp: Option<SystemTime>,
 }
 
 impl AwaitedAction {
@@ -130,11 +139,13 @@ impl AwaitedAction {
             operation_id,
             sort_key,
             attempts: 0,
+            failed_worker_ids: Vec::new(),
             last_worker_updated_timestamp: now,
             last_client_keepalive_timestamp: now,
             maybe_origin_metadata,
             worker_id: None,
             state: action_state,
+            stage_entered_timestamp: Some(now),
         }
     }
 
@@ -174,6 +185,17 @@ impl AwaitedAction {
         self.worker_id.as_ref()
     }
 
+    pub(crate) fn failed_worker_ids(&self) -> &[WorkerId] {
+        &self.failed_worker_ids
+    }
+
+    /// Remembers that an attempt of this action failed on `worker_id`.
+    pub(crate) fn record_failed_worker(&mut self, worker_id: WorkerId) {
+        if !self.failed_worker_ids.contains(&worker_id) {
+            self.failed_worker_ids.push(worker_id);
+        }
+    }
+
     pub(crate) const fn last_worker_updated_timestamp(&self) -> SystemTime {
         self.last_worker_updated_timestamp
     }
@@ -202,8 +224,15 @@ impl AwaitedAction {
         }
     }
 
+    pub(crate) const fn stage_entered_timestamp(&self) -> Option<SystemTime> {
+        self.stage_entered_timestamp
+    }
+
     /// Sets the current state of the action and updates the last worker updated timestamp.
     pub fn worker_set_state(&mut self, mut state: Arc<ActionState>, now: SystemTime) {
+        if core::mem::discriminant(&self.state.stage) != core::mem::discriminant(&state.stage) {
+            self.stage_entered_timestamp = Some(now);
+        }
         core::mem::swap(&mut self.state, &mut state);
         self.worker_keep_alive(now);
     }
@@ -18,7 +18,7 @@ use core::time::Duration;
 use std::sync::Arc;
 
 pub use awaited_action::{AwaitedAction, AwaitedActionSortKey};
-use futures::{Future, Stream};
+use futures::{Future, Stream, TryStreamExt};
 use nativelink_error::{Error, ResultExt, make_input_err};
 use nativelink_metric::MetricsComponent;
 use nativelink_util::action_messages::{ActionInfo, ActionStage, OperationId};
@@ -174,6 +174,23 @@ pub trait AwaitedActionDb: Send + Sync + MetricsComponent + Unpin + 'static {
         Output = Result<impl Stream<Item = Result<Self::Subscriber, Error>> + Send, Error>,
     > + Send;
 
+    /// Count the `AwaitedActions` in a specific state. The default
+    /// implementation walks every action in that state, so implementations
+    /// that can answer more cheaply should override it.
+    fn count_actions(
+        &self,
+        state: SortedAwaitedActionState,
+    ) -> impl Future<Output = Result<usize, Error>> + Send {
+        async move {
+            self.get_range_of_actions(state, Bound::Unbounded, Bound::Unbounded, false)
+                .await
+                .err_tip(|| "In AwaitedActionDb::count_actions")?
+                .try_fold(0, |count, _| async move { Ok(count + 1) })
+                .await
+                .err_tip(|| "In AwaitedActionDb::count_actions")
+        }
+    }
+
     /// Process a change changed `AwaitedAction` and notify any listeners.
     fn update_awaited_action(
         &self,
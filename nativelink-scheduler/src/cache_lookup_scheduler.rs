@@ -14,12 +14,13 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 
 use async_trait::async_trait;
 use nativelink_error::{Code, Error, ResultExt, make_err};
 use nativelink_metric::{MetricsComponent, RootMetricsComponent};
 use nativelink_proto::build::bazel::remote::execution::v2::{
-    ActionResult as ProtoActionResult, GetActionResultRequest,
+    ActionResult as ProtoActionResult, GetActionResultRequest, Tree as ProtoTree,
 };
 use nativelink_store::ac_utils::get_and_decode_digest;
 use nativelink_store::grpc_store::GrpcStore;
@@ -34,7 +35,7 @@ use nativelink_util::operation_state_manager::{
     ActionStateResult, ActionStateResultStream, ClientStateManager, OperationFilter,
 };
 use nativelink_util::origin_event::OriginMetadata;
-use nativelink_util::store_trait::Store;
+use nativelink_util::store_trait::{Store, StoreKey, StoreLike};
 use opentelemetry::baggage::BaggageExt;
 use opentelemetry::context::Context;
 use opentelemetry_semantic_conventions::attribute::ENDUSER_ID;
@@ -42,7 +43,9 @@ use parking_lot::{Mutex, MutexGuard};
 use scopeguard::guard;
 use tokio::sync::oneshot;
 use tonic::{Request, Response};
-use tracing::error;
+use tracing::{error, warn};
+
+use crate::stage_dwell_times::{DwellStage, StageDwellTimes};
 
 /// Actions that are having their cache checked or failed cache lookup and are
 /// being forwarded upstream.  Missing the `skip_cache_check` actions which are
@@ -61,18 +64,26 @@ pub struct CacheLookupScheduler {
     /// To prevent unintended issues, this store should probably be a `CompletenessCheckingStore`.
     #[metric(group = "ac_store")]
     ac_store: Store,
+    /// If set, the CAS that must hold every output of a cached result for
+    /// it to be returned.
+    #[metric(group = "verify_cas_store")]
+    verify_cas_store: Option<Store>,
     /// The "real" scheduler to use to perform actions if they were not found
     /// in the action cache.
     #[metric(group = "action_scheduler")]
     action_scheduler: Arc<dyn ClientStateManager>,
     /// Actions that are currently performing a `CacheCheck`.
     inflight_cache_checks: Arc<Mutex<CheckActions>>,
+    /// Time actions spent in the `CacheCheck` stage.
+    #[metric(group = "stage_dwell_times")]
+    stage_dwell_times: Arc<StageDwellTimes>,
 }
 
 impl core::fmt::Debug for CacheLookupScheduler {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("CacheLookupScheduler")
             .field("ac_store", &self.ac_store)
+            .field("verify_cas_store", &self.verify_cas_store)
             .finish_non_exhaustive()
     }
 }
@@ -102,6 +113,56 @@ async fn get_action_from_store(
     }
 }
 
+/// Returns true if every blob referenced by `action_result`, including the
+/// files of its output directories, exists in `cas_store`.
+async fn action_result_blobs_exist(
+    cas_store: &Store,
+    action_result: &ProtoActionResult,
+) -> Result<bool, Error> {
+    let mut digests = action_result
+        .output_files
+        .iter()
+        .filter_map(|file| file.digest.clone())
+        .chain(action_result.stdout_digest.clone())
+        .chain(action_result.stderr_digest.clone())
+        .map(|digest| DigestInfo::try_from(digest).map(StoreKey::from))
+        .collect::<Result<Vec<_>, _>>()
+        .err_tip(|| "Could not decode output digest in action_result_blobs_exist")?;
+    for output_directory in &action_result.output_directories {
+        let Some(tree_digest) = output_directory.tree_digest.clone() else {
+            continue;
+        };
+        let tree_digest = DigestInfo::try_from(tree_digest)
+            .err_tip(|| "Could not decode tree digest in action_result_blobs_exist")?;
+        let tree = match get_and_decode_digest::<ProtoTree>(cas_store, tree_digest.into()).await {
+            Ok(tree) => tree,
+            Err(err) if err.code == Code::NotFound => return Ok(false),
+            Err(err) => return Err(err.append("In action_result_blobs_exist")),
+        };
+        for file_digest in tree
+            .root
+            .into_iter()
+            .chain(tree.children)
+            .flat_map(|directory| directory.files)
+            .filter_map(|file| file.digest)
+        {
+            digests.push(
+                DigestInfo::try_from(file_digest)
+                    .err_tip(|| "Could not decode file digest in action_result_blobs_exist")?
+                    .into(),
+            );
+        }
+    }
+    if digests.is_empty() {
+        return Ok(true);
+    }
+    let results = cas_store
+        .has_many(&digests)
+        .await
+        .err_tip(|| "In action_result_blobs_exist")?;
+    Ok(results.iter().all(Option::is_some))
+}
+
 /// Future for when `ActionStateResults` are known.
 type ActionStateResultOneshot = oneshot::Receiver<Result<Box<dyn ActionStateResult>, Error>>;
 
@@ -161,12 +222,15 @@ impl ActionStateResult for CacheLookupActionStateResult {
 impl CacheLookupScheduler {
     pub fn new(
         ac_store: Store,
+        verify_cas_store: Option<Store>,
         action_scheduler: Arc<dyn ClientStateManager>,
     ) -> Result<Self, Error> {
         Ok(Self {
             ac_store,
+            verify_cas_store,
             action_scheduler,
             inflight_cache_checks: Arc::default(),
+            stage_dwell_times: Arc::default(),
         })
     }
 
@@ -225,8 +289,10 @@ impl CacheLookupScheduler {
         };
 
         let ac_store = self.ac_store.clone();
+        let verify_cas_store = self.verify_cas_store.clone();
         let action_scheduler = self.action_scheduler.clone();
         let inflight_cache_checks = self.inflight_cache_checks.clone();
+        let stage_dwell_times = self.stage_dwell_times.clone();
         // We need this spawn because we are returning a stream and this spawn will populate the stream's data.
         background_spawn!("cache_lookup_scheduler_add_action", async move {
             // If our spawn ever dies, we will remove the action from the inflight_cache_checks map.
@@ -244,6 +310,7 @@ impl CacheLookupScheduler {
             };
 
             // Perform cache check.
+            let cache_check_start = Instant::now();
             let instance_name = action_info.unique_qualifier.instance_name().clone();
             let maybe_action_result = get_action_from_store(
                 &ac_store,
@@ -252,6 +319,37 @@ impl CacheLookupScheduler {
                 action_info.unique_qualifier.digest_function(),
             )
             .await;
+            // A cached result whose outputs were evicted from the CAS is
+            // treated as a cache miss so the action runs again.
+            let maybe_action_result = match (maybe_action_result, &verify_cas_store) {
+                (Ok(action_result), Some(cas_store)) => {
+                    match action_result_blobs_exist(cas_store, &action_result).await {
+                        Ok(true) => Ok(action_result),
+                        Ok(false) => Err(make_err!(
+                            Code::NotFound,
+                            "Cached ActionResult references blobs missing from the CAS"
+                        )),
+                        Err(err) => {
+                            warn!(
+                                ?err,
+                                ?unique_key,
+                                "Could not verify cached ActionResult outputs, executing the action"
+                            );
+                            Err(make_err!(
+                                Code::NotFound,
+                                "Could not verify cached ActionResult outputs"
+                            ))
+                        }
+                    }
+                }
+                (maybe_action_result, _) => maybe_action_result,
+            };
+            stage_dwell_times.record(
+                DwellStage::CacheCheck,
+                action_info.unique_qualifier.instance_name(),
+                action_info.priority,
+                cache_check_start.elapsed(),
+            );
             match maybe_action_result {
                 Ok(action_result) => {
                     let maybe_pending_txs = {
@@ -376,6 +474,20 @@ impl ClientStateManager for CacheLookupScheduler {
         self.inner_filter_operations(filter).await
     }
 
+    async fn count_operations(&self, filter: OperationFilter) -> Result<usize, Error> {
+        self.action_scheduler
+            .count_operations(filter)
+            .await
+            .err_tip(|| "In CacheLookupScheduler::count_operations")
+    }
+
+    async fn cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        self.action_scheduler
+            .cancel_operation(client_operation_id)
+            .await
+            .err_tip(|| "In CacheLookupScheduler::cancel_operation")
+    }
+
     fn as_known_platform_property_provider(&self) -> Option<&dyn KnownPlatformPropertyProvider> {
         self.action_scheduler.as_known_platform_property_provider()
     }
@@ -12,11 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use core::time::Duration;
 use std::sync::Arc;
 use std::time::SystemTime;
 
 use nativelink_config::schedulers::{
-    ExperimentalSimpleSchedulerBackend, SchedulerSpec, SimpleSpec,
+    ClientDisconnectBehavior, ExperimentalSimpleSchedulerBackend, SchedulerSpec, SimpleSpec,
 };
 use nativelink_config::stores::EvictionPolicy;
 use nativelink_error::{Error, ResultExt, make_input_err};
@@ -31,6 +32,7 @@ use crate::cache_lookup_scheduler::CacheLookupScheduler;
 use crate::grpc_scheduler::GrpcScheduler;
 use crate::memory_awaited_action_db::MemoryAwaitedActionDb;
 use crate::property_modifier_scheduler::PropertyModifierScheduler;
+use crate::router_scheduler::RouterScheduler;
 use crate::simple_scheduler::SimpleScheduler;
 use crate::store_awaited_action_db::StoreAwaitedActionDb;
 use crate::worker_scheduler::WorkerScheduler;
@@ -39,6 +41,14 @@ use crate::worker_scheduler::WorkerScheduler;
 /// If this changes, remember to change the documentation in the config.
 const DEFAULT_RETAIN_COMPLETED_FOR_S: u32 = 60;
 
+/// Default seconds between snapshots of the memory scheduler.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MEMORY_SNAPSHOT_INTERVAL_S: u64 = 10;
+
+/// Default seconds between compactions of the redis scheduler.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_REDIS_COMPACTION_INTERVAL_S: u64 = 300;
+
 pub type SchedulerFactoryResults = (
     Option<Arc<dyn ClientStateManager>>,
     Option<Arc<dyn WorkerScheduler>>,
@@ -66,11 +76,21 @@ fn inner_scheduler_factory(
             let ac_store = store_manager
                 .get_store(&spec.ac_store)
                 .err_tip(|| format!("'ac_store': '{}' does not exist", spec.ac_store))?;
+            let verify_cas_store = spec
+                .verify_cas_store
+                .as_ref()
+                .map(|store_name| {
+                    store_manager
+                        .get_store(store_name)
+                        .err_tip(|| format!("'verify_cas_store': '{store_name}' does not exist"))
+                })
+                .transpose()?;
             let (action_scheduler, worker_scheduler) =
                 inner_scheduler_factory(&spec.scheduler, store_manager, maybe_origin_event_tx)
                     .err_tip(|| "In nested CacheLookupScheduler construction")?;
             let cache_lookup_scheduler = Arc::new(CacheLookupScheduler::new(
                 ac_store,
+                verify_cas_store,
                 action_scheduler.err_tip(|| "Nested scheduler is not an action scheduler")?,
             )?);
             (Some(cache_lookup_scheduler), worker_scheduler)
@@ -82,9 +102,38 @@ fn inner_scheduler_factory(
             let property_modifier_scheduler = Arc::new(PropertyModifierScheduler::new(
                 spec,
                 action_scheduler.err_tip(|| "Nested scheduler is not an action scheduler")?,
-            ));
+            )?);
             (Some(property_modifier_scheduler), worker_scheduler)
         }
+        SchedulerSpec::Router(spec) => {
+            let mut maybe_worker_scheduler = None;
+            let mut nested_scheduler_factory = |nested_spec: &SchedulerSpec| {
+                let (action_scheduler, worker_scheduler) =
+                    inner_scheduler_factory(nested_spec, store_manager, maybe_origin_event_tx)
+                        .err_tip(|| "In nested RouterScheduler construction")?;
+                if worker_scheduler.is_some() {
+                    if maybe_worker_scheduler.is_some() {
+                        return Err(make_input_err!(
+                            "At most one nested scheduler of a RouterScheduler may accept workers"
+                        ));
+                    }
+                    maybe_worker_scheduler = worker_scheduler;
+                }
+                action_scheduler.err_tip(|| "Nested scheduler is not an action scheduler")
+            };
+            let route_schedulers = spec
+                .routes
+                .iter()
+                .map(|route| nested_scheduler_factory(&route.scheduler))
+                .collect::<Result<Vec<_>, Error>>()?;
+            let default_scheduler = nested_scheduler_factory(&spec.default_scheduler)?;
+            let router_scheduler = Arc::new(RouterScheduler::new(
+                spec,
+                route_schedulers,
+                default_scheduler,
+            )?);
+            (Some(router_scheduler), maybe_worker_scheduler)
+        }
     };
 
     Ok(scheduler)
@@ -103,11 +152,24 @@ fn simple_scheduler_factory(
     {
         ExperimentalSimpleSchedulerBackend::Memory => {
             let task_change_notify = Arc::new(Notify::new());
-            let awaited_action_db = memory_awaited_action_db_factory(
+            let mut awaited_action_db = memory_awaited_action_db_factory(
                 spec.retain_completed_for_s,
                 &task_change_notify,
                 SystemTime::now,
             );
+            if spec.client_disconnect_behavior != ClientDisconnectBehavior::Cancel {
+                awaited_action_db = awaited_action_db.keep_unfinished_operations_without_clients();
+            }
+            if let Some(memory_snapshot) = &spec.memory_snapshot {
+                let mut interval_s = memory_snapshot.interval_s;
+                if interval_s == 0 {
+                    interval_s = DEFAULT_MEMORY_SNAPSHOT_INTERVAL_S;
+                }
+                awaited_action_db = awaited_action_db.with_snapshots(
+                    memory_snapshot.path.clone(),
+                    Duration::from_secs(interval_s),
+                );
+            }
             let (action_scheduler, worker_scheduler) = SimpleScheduler::new(
                 spec,
                 awaited_action_db,
@@ -135,13 +197,23 @@ fn simple_scheduler_factory(
                         "Could not downcast to redis store in RedisAwaitedActionDb::new"
                     )
                 })?;
-            let awaited_action_db = StoreAwaitedActionDb::new(
+            let mut awaited_action_db = StoreAwaitedActionDb::new(
                 store,
                 task_change_notify.clone(),
                 now_fn,
                 Default::default,
             )
             .err_tip(|| "In state_manager_factory::redis_state_manager")?;
+            if redis_config.retain_completed_for_s > 0 {
+                let mut compaction_interval_s = redis_config.compaction_interval_s;
+                if compaction_interval_s == 0 {
+                    compaction_interval_s = DEFAULT_REDIS_COMPACTION_INTERVAL_S;
+                }
+                awaited_action_db = awaited_action_db.with_compaction(
+                    Duration::from_secs(redis_config.retain_completed_for_s),
+                    Duration::from_secs(compaction_interval_s),
+                );
+            }
             let (action_scheduler, worker_scheduler) = SimpleScheduler::new(
                 spec,
                 awaited_action_db,
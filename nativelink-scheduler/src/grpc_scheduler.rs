@@ -32,7 +32,7 @@ use nativelink_proto::google::longrunning::Operation;
 use nativelink_util::action_messages::{
     ActionInfo, ActionState, ActionUniqueQualifier, DEFAULT_EXECUTION_PRIORITY, OperationId,
 };
-use nativelink_util::connection_manager::ConnectionManager;
+use nativelink_util::connection_manager::{ConnectionManager, LoadBalancing};
 use nativelink_util::known_platform_property_provider::KnownPlatformPropertyProvider;
 use nativelink_util::operation_state_manager::{
     ActionStateResult, ActionStateResultStream, ClientStateManager, OperationFilter,
@@ -117,6 +117,7 @@ impl GrpcScheduler {
                 spec.max_concurrent_requests,
                 spec.retry.clone(),
                 jitter_fn,
+                LoadBalancing::default(),
             ),
         })
     }
@@ -351,6 +352,13 @@ impl ClientStateManager for GrpcScheduler {
         self.inner_filter_operations(filter).await
     }
 
+    async fn cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        Err(make_err!(
+            Code::Unimplemented,
+            "GrpcScheduler does not support cancelling operation {client_operation_id}"
+        ))
+    }
+
     fn as_known_platform_property_provider(&self) -> Option<&dyn KnownPlatformPropertyProvider> {
         Some(self)
     }
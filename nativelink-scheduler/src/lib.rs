@@ -21,8 +21,11 @@ pub mod memory_awaited_action_db;
 pub mod mock_scheduler;
 pub mod platform_property_manager;
 pub mod property_modifier_scheduler;
+pub mod router_scheduler;
+pub mod scheduling_policy;
 pub mod simple_scheduler;
 mod simple_scheduler_state_manager;
+pub mod stage_dwell_times;
 pub mod store_awaited_action_db;
 pub mod worker;
 pub mod worker_scheduler;
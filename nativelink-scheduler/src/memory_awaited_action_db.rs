@@ -16,7 +16,7 @@ use core::ops::{Bound, RangeBounds};
 use core::time::Duration;
 use std::collections::hash_map::Entry;
 use std::collections::{BTreeMap, BTreeSet, HashMap};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
 use async_lock::Mutex;
 use futures::{FutureExt, Stream};
@@ -29,10 +29,11 @@ use nativelink_util::action_messages::{
 use nativelink_util::chunked_stream::ChunkedStream;
 use nativelink_util::evicting_map::{EvictingMap, LenEntry};
 use nativelink_util::instant_wrapper::InstantWrapper;
-use nativelink_util::spawn;
 use nativelink_util::task::JoinHandleDropGuard;
+use nativelink_util::{fs, spawn};
+use serde::{Deserialize, Serialize};
 use tokio::sync::{Notify, mpsc, watch};
-use tracing::{debug, error};
+use tracing::{debug, error, info};
 
 use crate::awaited_action_db::{
     AwaitedAction, AwaitedActionDb, AwaitedActionSubscriber, CLIENT_KEEPALIVE_DURATION,
@@ -103,6 +104,13 @@ pub(crate) enum ActionEvent {
     ClientDroppedOperation(OperationId),
 }
 
+/// An action that had not finished when a snapshot was taken.
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotAction {
+    client_operation_id: OperationId,
+    action_info: Arc<ActionInfo>,
+}
+
 /// Information required to track an individual client
 /// keep alive config and state.
 #[derive(Debug)]
@@ -328,6 +336,10 @@ pub struct AwaitedActionDbImpl<I: InstantWrapper, NowFn: Fn() -> I> {
     /// Where to send notifications about important events related to actions.
     action_event_tx: mpsc::UnboundedSender<ActionEvent>,
 
+    /// Whether operations that have not finished are kept after their last
+    /// client dropped.
+    keep_unfinished_operations_without_clients: bool,
+
     /// The function to get the current time.
     now_fn: NowFn,
 }
@@ -411,48 +423,21 @@ impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync> AwaitedActionDbI
                             .insert(operation_id, connected_clients);
                         continue;
                     }
-                    debug!(?operation_id, "Clearing operation from state manager");
                     let awaited_action = tx.borrow().clone();
-                    // Cleanup action_info_hash_key_to_awaited_action if it was marked cached.
-                    match &awaited_action.action_info().unique_qualifier {
-                        ActionUniqueQualifier::Cacheable(action_key) => {
-                            let maybe_awaited_action = self
-                                .action_info_hash_key_to_awaited_action
-                                .remove(action_key);
-                            if !awaited_action.state().stage.is_finished()
-                                && maybe_awaited_action.is_none()
-                            {
-                                error!(
-                                    ?operation_id,
-                                    ?awaited_action,
-                                    ?action_key,
-                                    "action_info_hash_key_to_awaited_action and operation_id_to_awaited_action are out of sync",
-                                );
-                            }
-                        }
-                        ActionUniqueQualifier::Uncacheable(_action_key) => {
-                            // This Operation should not be in the hash_key map.
-                        }
-                    }
-
-                    // Cleanup sorted_awaited_action.
-                    let sort_key = awaited_action.sort_key();
-                    let sort_btree_for_state = self
-                        .sorted_action_info_hash_keys
-                        .btree_for_state(&awaited_action.state().stage);
-
-                    let maybe_sorted_awaited_action =
-                        sort_btree_for_state.take(&SortedAwaitedAction {
-                            sort_key,
-                            operation_id: operation_id.clone(),
-                        });
-                    if maybe_sorted_awaited_action.is_none() {
-                        error!(
-                            ?operation_id,
-                            ?sort_key,
-                            "Expected maybe_sorted_awaited_action to have {sort_key:?}",
-                        );
+                    if self.keep_unfinished_operations_without_clients
+                        && !awaited_action.state().stage.is_finished()
+                    {
+                        // Keep the operation until it finishes, clients may
+                        // still subscribe to it again.
+                        debug!(?operation_id, "Keeping operation without clients");
+                        self.operation_id_to_awaited_action
+                            .insert(operation_id.clone(), tx);
+                        self.connected_clients_for_operation_id
+                            .insert(operation_id, 0);
+                        continue;
                     }
+                    debug!(?operation_id, "Clearing operation from state manager");
+                    self.remove_operation(&operation_id, &awaited_action);
                 }
                 ActionEvent::ClientKeepAlive(client_id) => {
                     if let Some(client_awaited_action) = self
@@ -481,6 +466,48 @@ impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync> AwaitedActionDbI
         NoEarlyReturn
     }
 
+    /// Removes `awaited_action` from the maps that are not cleaned up when
+    /// its last client drops.
+    fn remove_operation(&mut self, operation_id: &OperationId, awaited_action: &AwaitedAction) {
+        // Cleanup action_info_hash_key_to_awaited_action if it was marked cached.
+        match &awaited_action.action_info().unique_qualifier {
+            ActionUniqueQualifier::Cacheable(action_key) => {
+                let maybe_awaited_action = self
+                    .action_info_hash_key_to_awaited_action
+                    .remove(action_key);
+                if !awaited_action.state().stage.is_finished() && maybe_awaited_action.is_none() {
+                    error!(
+                        ?operation_id,
+                        ?awaited_action,
+                        ?action_key,
+                        "action_info_hash_key_to_awaited_action and operation_id_to_awaited_action are out of sync",
+                    );
+                }
+            }
+            ActionUniqueQualifier::Uncacheable(_action_key) => {
+                // This Operation should not be in the hash_key map.
+            }
+        }
+
+        // Cleanup sorted_awaited_action.
+        let sort_key = awaited_action.sort_key();
+        let sort_btree_for_state = self
+            .sorted_action_info_hash_keys
+            .btree_for_state(&awaited_action.state().stage);
+
+        let maybe_sorted_awaited_action = sort_btree_for_state.take(&SortedAwaitedAction {
+            sort_key,
+            operation_id: operation_id.clone(),
+        });
+        if maybe_sorted_awaited_action.is_none() {
+            error!(
+                ?operation_id,
+                ?sort_key,
+                "Expected maybe_sorted_awaited_action to have {sort_key:?}",
+            );
+        }
+    }
+
     fn get_awaited_actions_range(
         &self,
         start: Bound<&OperationId>,
@@ -640,14 +667,51 @@ impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync> AwaitedActionDbI
             }
         }
 
+        let operation_id = new_awaited_action.operation_id().clone();
+        let is_finished = new_awaited_action.state().stage.is_finished();
         // Notify all listeners of the new state and ignore if no one is listening.
         // Note: Do not use `.send()` as it will not update the state if all listeners
         // are dropped.
         drop(tx.send_replace(new_awaited_action));
 
+        // Operations kept after their last client dropped are removed once
+        // they finish, as nobody is left to drop them.
+        if is_finished && self.connected_clients_for_operation_id.get(&operation_id) == Some(&0) {
+            self.connected_clients_for_operation_id
+                .remove(&operation_id);
+            if let Some(tx) = self.operation_id_to_awaited_action.remove(&operation_id) {
+                let awaited_action = tx.borrow().clone();
+                self.remove_operation(&operation_id, &awaited_action);
+            }
+        }
+
         Ok(())
     }
 
+    /// Returns every client operation whose action has not finished yet.
+    async fn snapshot_actions(&self) -> Vec<SnapshotAction> {
+        let mut snapshot_actions = Vec::new();
+        self.client_operation_to_awaited_action
+            .range(.., |client_operation_id, client_awaited_action| {
+                let Some(tx) = self
+                    .operation_id_to_awaited_action
+                    .get(client_awaited_action.operation_id())
+                else {
+                    return true;
+                };
+                let awaited_action = tx.borrow();
+                if !awaited_action.state().stage.is_finished() {
+                    snapshot_actions.push(SnapshotAction {
+                        client_operation_id: client_operation_id.clone(),
+                        action_info: awaited_action.action_info().clone(),
+                    });
+                }
+                true
+            })
+            .await;
+        snapshot_actions
+    }
+
     /// Creates a new [`ClientAwaitedAction`] and a [`watch::Receiver`] to
     /// listen for changes. We don't do this in-line because it is important
     /// to ALWAYS construct a [`ClientAwaitedAction`] before inserting it into
@@ -828,6 +892,7 @@ pub struct MemoryAwaitedActionDb<I: InstantWrapper, NowFn: Fn() -> I> {
     inner: Arc<Mutex<AwaitedActionDbImpl<I, NowFn>>>,
     tasks_change_notify: Arc<Notify>,
     _handle_awaited_action_events: JoinHandleDropGuard<()>,
+    _snapshot_task: Option<JoinHandleDropGuard<()>>,
 }
 
 impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync + 'static>
@@ -846,6 +911,7 @@ impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync + 'static>
             sorted_action_info_hash_keys: SortedAwaitedActions::default(),
             connected_clients_for_operation_id: HashMap::new(),
             action_event_tx,
+            keep_unfinished_operations_without_clients: false,
             now_fn,
         }));
         let weak_inner = Arc::downgrade(&inner);
@@ -868,10 +934,122 @@ impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync + 'static>
                         .await;
                 }
             }),
+            _snapshot_task: None,
+        }
+    }
+
+    /// Keeps operations that have not finished after their last client
+    /// dropped, so they run to completion. They are removed once they finish.
+    #[must_use]
+    pub fn keep_unfinished_operations_without_clients(self) -> Self {
+        self.inner
+            .lock_blocking()
+            .keep_unfinished_operations_without_clients = true;
+        self
+    }
+
+    /// Restores the actions saved in the snapshot file at `path`, then keeps
+    /// saving the actions that have not finished yet to it every `interval`.
+    #[must_use]
+    pub fn with_snapshots(self, path: String, interval: Duration) -> Self {
+        let weak_inner = Arc::downgrade(&self.inner);
+        let tasks_change_notify = self.tasks_change_notify.clone();
+        let snapshot_task = spawn!("awaited_action_db_snapshots", async move {
+            match restore_snapshot(&weak_inner, &path).await {
+                Ok(restored_actions) => {
+                    info!(restored_actions, path, "Restored actions from snapshot");
+                    tasks_change_notify.notify_one();
+                }
+                // Nothing was saved yet.
+                Err(err) if err.code == Code::NotFound => {}
+                Err(err) => error!(?err, path, "Failed to restore actions from snapshot"),
+            }
+            loop {
+                tokio::time::sleep(interval).await;
+                if weak_inner.strong_count() == 0 {
+                    return; // Our struct was dropped.
+                }
+                if let Err(err) = write_snapshot(&weak_inner, &path).await {
+                    error!(?err, path, "Failed to write snapshot of actions");
+                }
+            }
+        });
+        Self {
+            _snapshot_task: Some(snapshot_task),
+            ..self
         }
     }
 }
 
+/// Re-adds the actions saved in the snapshot file at `path`. Clients are not
+/// subscribed yet, they resume waiting on their client operation ids.
+async fn restore_snapshot<I, NowFn>(
+    weak_inner: &Weak<Mutex<AwaitedActionDbImpl<I, NowFn>>>,
+    path: &str,
+) -> Result<usize, Error>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Clone + Send + Sync + 'static,
+{
+    let data = fs::read(path)
+        .await
+        .err_tip(|| format!("Failed to read snapshot {path}"))?;
+    let snapshot_actions: Vec<SnapshotAction> = serde_json::from_slice(&data).map_err(|e| {
+        make_err!(
+            Code::InvalidArgument,
+            "Failed to parse snapshot {path}: {e}"
+        )
+    })?;
+    let inner = weak_inner
+        .upgrade()
+        .err_tip(|| "AwaitedActionDb was dropped in restore_snapshot")?;
+    let mut inner = inner.lock().await;
+    let restored_actions = snapshot_actions.len();
+    for snapshot_action in snapshot_actions {
+        drop(
+            inner
+                .add_action(
+                    snapshot_action.client_operation_id,
+                    snapshot_action.action_info,
+                )
+                .await
+                .err_tip(|| "In restore_snapshot")?,
+        );
+    }
+    Ok(restored_actions)
+}
+
+/// Saves the actions that have not finished yet to the snapshot file at
+/// `path`. The file is replaced atomically.
+async fn write_snapshot<I, NowFn>(
+    weak_inner: &Weak<Mutex<AwaitedActionDbImpl<I, NowFn>>>,
+    path: &str,
+) -> Result<(), Error>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Clone + Send + Sync + 'static,
+{
+    let snapshot_actions = {
+        let inner = weak_inner
+            .upgrade()
+            .err_tip(|| "AwaitedActionDb was dropped in write_snapshot")?;
+        let inner = inner.lock().await;
+        inner.snapshot_actions().await
+    };
+    let data = serde_json::to_vec(&snapshot_actions)
+        .map_err(|e| make_err!(Code::Internal, "Failed to serialize snapshot: {e}"))?;
+    let temp_path = format!("{path}.tmp");
+    let write_path = temp_path.clone();
+    fs::call_with_permit(move |_| {
+        std::fs::write(&write_path, data)
+            .err_tip(|| format!("Failed to write snapshot {write_path}"))
+    })
+    .await?;
+    fs::rename(&temp_path, path)
+        .await
+        .err_tip(|| format!("Failed to move snapshot to {path}"))
+}
+
 impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync + 'static> AwaitedActionDb
     for MemoryAwaitedActionDb<I, NowFn>
 {
@@ -965,12 +1143,30 @@ impl<I: InstantWrapper, NowFn: Fn() -> I + Clone + Send + Sync + 'static> Awaite
         ))
     }
 
+    async fn count_actions(&self, state: SortedAwaitedActionState) -> Result<usize, Error> {
+        let inner = self.inner.lock().await;
+        let sorted_awaited_actions = &inner.sorted_action_info_hash_keys;
+        Ok(match state {
+            SortedAwaitedActionState::CacheCheck => sorted_awaited_actions.cache_check.len(),
+            SortedAwaitedActionState::Queued => sorted_awaited_actions.queued.len(),
+            SortedAwaitedActionState::Executing => sorted_awaited_actions.executing.len(),
+            SortedAwaitedActionState::Completed => sorted_awaited_actions.completed.len(),
+        })
+    }
+
     async fn update_awaited_action(&self, new_awaited_action: AwaitedAction) -> Result<(), Error> {
+        let is_queued = matches!(new_awaited_action.state().stage, ActionStage::Queued);
         self.inner
             .lock()
             .await
             .update_awaited_action(new_awaited_action)?;
-        self.tasks_change_notify.notify_one();
+        // Only queued operations can be matched to a worker. Other updates,
+        // like keep alives or progress reports, never make a match possible,
+        // and finished operations wake the matching engine through the
+        // worker that freed up.
+        if is_queued {
+            self.tasks_change_notify.notify_one();
+        }
         Ok(())
     }
 
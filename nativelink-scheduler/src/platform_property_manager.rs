@@ -21,6 +21,8 @@ use nativelink_metric::{
 };
 use nativelink_util::platform_properties::{PlatformProperties, PlatformPropertyValue};
 
+use crate::worker::EXCLUSIVE_PROPERTY_NAME;
+
 /// Helps manage known properties and conversion into `PlatformPropertyValue`.
 #[derive(Debug)]
 pub struct PlatformPropertyManager {
@@ -60,12 +62,17 @@ impl PlatformPropertyManager {
 
     /// Given a map of key-value pairs, returns a map of `PlatformPropertyValue` based on the
     /// configuration passed into the `PlatformPropertyManager` constructor.
+    /// The reserved `exclusive` property is handled by the scheduler itself
+    /// and never required from workers.
     pub fn make_platform_properties(
         &self,
         properties: HashMap<String, String>,
     ) -> Result<PlatformProperties, Error> {
         let mut platform_properties = HashMap::with_capacity(properties.len());
         for (key, value) in properties {
+            if key == EXCLUSIVE_PROPERTY_NAME {
+                continue;
+            }
             let prop_value = self.make_prop_value(&key, &value)?;
             platform_properties.insert(key, prop_value);
         }
@@ -87,6 +94,9 @@ impl PlatformPropertyManager {
                     })?,
                 )),
                 PropertyType::Exact => Ok(PlatformPropertyValue::Exact(value.to_string())),
+                PropertyType::Expression => {
+                    Ok(PlatformPropertyValue::Expression(value.to_string()))
+                }
                 PropertyType::Priority => Ok(PlatformPropertyValue::Priority(value.to_string())),
             };
         }
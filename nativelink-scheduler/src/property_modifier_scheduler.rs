@@ -17,9 +17,10 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use nativelink_config::schedulers::{
-    PlatformPropertyReplacement, PropertyModification, PropertyModifierSpec,
+    PlatformPropertyAddition, PlatformPropertyReplacement, PlatformPropertyRewrite,
+    PropertyModification, PropertyModifierSpec,
 };
-use nativelink_error::{Error, ResultExt};
+use nativelink_error::{Error, ResultExt, make_input_err};
 use nativelink_metric::{MetricsComponent, RootMetricsComponent};
 use nativelink_util::action_messages::{ActionInfo, OperationId};
 use nativelink_util::known_platform_property_provider::KnownPlatformPropertyProvider;
@@ -27,10 +28,124 @@ use nativelink_util::operation_state_manager::{
     ActionStateResult, ActionStateResultStream, ClientStateManager, OperationFilter,
 };
 use parking_lot::Mutex;
+use regex::Regex;
+
+/// A piece of a parsed `PlatformPropertyTemplate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    Property(String),
+}
+
+/// Parses a template where `{name}` references the property `name` and
+/// `{{` / `}}` are literal braces.
+fn parse_template(template: &str) -> Result<Vec<TemplatePart>, Error> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some('{') | None => {
+                            return Err(make_input_err!(
+                                "Unterminated property reference in template {template:?}"
+                            ));
+                        }
+                        Some(c) => name.push(c),
+                    }
+                }
+                if name.is_empty() {
+                    return Err(make_input_err!(
+                        "Empty property reference in template {template:?}"
+                    ));
+                }
+                if !literal.is_empty() {
+                    parts.push(TemplatePart::Literal(core::mem::take(&mut literal)));
+                }
+                parts.push(TemplatePart::Property(name));
+            }
+            '}' => {
+                return Err(make_input_err!("Unmatched '}}' in template {template:?}"));
+            }
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(TemplatePart::Literal(literal));
+    }
+    Ok(parts)
+}
+
+/// Renders a parsed template, or returns None if it references a property
+/// that is not set.
+fn render_template(parts: &[TemplatePart], properties: &HashMap<String, String>) -> Option<String> {
+    parts
+        .iter()
+        .map(|part| match part {
+            TemplatePart::Literal(literal) => Some(literal.as_str()),
+            TemplatePart::Property(name) => properties.get(name).map(String::as_str),
+        })
+        .collect()
+}
+
+/// A `PropertyModification` with its patterns and templates compiled.
+#[derive(Debug)]
+enum Modification {
+    Add(PlatformPropertyAddition),
+    Remove(String),
+    Replace(PlatformPropertyReplacement),
+    Rewrite {
+        rewrite: PlatformPropertyRewrite,
+        pattern: Regex,
+    },
+    Template {
+        name: String,
+        parts: Vec<TemplatePart>,
+    },
+}
+
+impl TryFrom<&PropertyModification> for Modification {
+    type Error = Error;
+
+    fn try_from(modification: &PropertyModification) -> Result<Self, Self::Error> {
+        Ok(match modification {
+            PropertyModification::Add(addition) => Self::Add(addition.clone()),
+            PropertyModification::Remove(name) => Self::Remove(name.clone()),
+            PropertyModification::Replace(replacement) => Self::Replace(replacement.clone()),
+            PropertyModification::Rewrite(rewrite) => Self::Rewrite {
+                pattern: Regex::new(&rewrite.pattern).map_err(|e| {
+                    make_input_err!(
+                        "Invalid pattern {:?} to rewrite property {:?} : {e}",
+                        rewrite.pattern,
+                        rewrite.name
+                    )
+                })?,
+                rewrite: rewrite.clone(),
+            },
+            PropertyModification::Template(template) => Self::Template {
+                name: template.name.clone(),
+                parts: parse_template(&template.template)
+                    .err_tip(|| format!("For property {:?}", template.name))?,
+            },
+        })
+    }
+}
 
 #[derive(MetricsComponent)]
 pub struct PropertyModifierScheduler {
-    modifications: Vec<PropertyModification>,
+    modifications: Vec<Modification>,
     #[metric(group = "scheduler")]
     scheduler: Arc<dyn ClientStateManager>,
     #[metric(group = "property_manager")]
@@ -47,12 +162,21 @@ impl core::fmt::Debug for PropertyModifierScheduler {
 }
 
 impl PropertyModifierScheduler {
-    pub fn new(spec: &PropertyModifierSpec, scheduler: Arc<dyn ClientStateManager>) -> Self {
-        Self {
-            modifications: spec.modifications.clone(),
+    pub fn new(
+        spec: &PropertyModifierSpec,
+        scheduler: Arc<dyn ClientStateManager>,
+    ) -> Result<Self, Error> {
+        let modifications = spec
+            .modifications
+            .iter()
+            .map(Modification::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .err_tip(|| "In PropertyModifierScheduler::new")?;
+        Ok(Self {
+            modifications,
             scheduler,
             known_properties: Mutex::new(HashMap::new()),
-        }
+        })
     }
 
     async fn inner_get_known_properties(&self, instance_name: &str) -> Result<Vec<String>, Error> {
@@ -73,11 +197,13 @@ impl PropertyModifierScheduler {
         );
         for modification in &self.modifications {
             match modification {
-                PropertyModification::Remove(name)
-                | PropertyModification::Replace(PlatformPropertyReplacement { name, .. }) => {
+                Modification::Remove(name)
+                | Modification::Replace(PlatformPropertyReplacement { name, .. }) => {
                     known_properties.insert(name.clone());
                 }
-                PropertyModification::Add(_) => (),
+                Modification::Add(_)
+                | Modification::Rewrite { .. }
+                | Modification::Template { .. } => (),
             }
         }
         let final_known_properties: Vec<String> = known_properties.into_iter().collect();
@@ -96,15 +222,15 @@ impl PropertyModifierScheduler {
         let action_info_mut = Arc::make_mut(&mut action_info);
         for modification in &self.modifications {
             match modification {
-                PropertyModification::Add(addition) => {
+                Modification::Add(addition) => {
                     action_info_mut
                         .platform_properties
                         .insert(addition.name.clone(), addition.value.clone());
                 }
-                PropertyModification::Remove(name) => {
+                Modification::Remove(name) => {
                     action_info_mut.platform_properties.remove(name);
                 }
-                PropertyModification::Replace(replacement) => {
+                Modification::Replace(replacement) => {
                     if let Some((existing_name, existing_value)) = action_info_mut
                         .platform_properties
                         .remove_entry(&replacement.name)
@@ -125,6 +251,35 @@ impl PropertyModifierScheduler {
                         }
                     }
                 }
+                Modification::Rewrite { rewrite, pattern } => {
+                    let new_value = action_info_mut
+                        .platform_properties
+                        .get(&rewrite.name)
+                        .filter(|value| pattern.is_match(value))
+                        .map(|value| {
+                            pattern
+                                .replace_all(value, rewrite.replacement.as_str())
+                                .into_owned()
+                        });
+                    if let Some(new_value) = new_value {
+                        action_info_mut.platform_properties.insert(
+                            rewrite
+                                .new_name
+                                .clone()
+                                .unwrap_or_else(|| rewrite.name.clone()),
+                            new_value,
+                        );
+                    }
+                }
+                Modification::Template { name, parts } => {
+                    if let Some(value) =
+                        render_template(parts, &action_info_mut.platform_properties)
+                    {
+                        action_info_mut
+                            .platform_properties
+                            .insert(name.clone(), value);
+                    }
+                }
             }
         }
         self.scheduler
@@ -165,6 +320,20 @@ impl ClientStateManager for PropertyModifierScheduler {
         self.inner_filter_operations(filter).await
     }
 
+    async fn count_operations(&self, filter: OperationFilter) -> Result<usize, Error> {
+        self.scheduler
+            .count_operations(filter)
+            .await
+            .err_tip(|| "In PropertyModifierScheduler::count_operations")
+    }
+
+    async fn cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        self.scheduler
+            .cancel_operation(client_operation_id)
+            .await
+            .err_tip(|| "In PropertyModifierScheduler::cancel_operation")
+    }
+
     fn as_known_platform_property_provider(&self) -> Option<&dyn KnownPlatformPropertyProvider> {
         Some(self)
     }
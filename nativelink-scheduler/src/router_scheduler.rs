@@ -0,0 +1,227 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::{StreamExt, stream};
+use nativelink_config::schedulers::RouterSpec;
+use nativelink_error::{Code, Error, ResultExt, make_err, make_input_err};
+use nativelink_metric::{MetricsComponent, RootMetricsComponent};
+use nativelink_util::action_messages::{ActionInfo, OperationId};
+use nativelink_util::known_platform_property_provider::KnownPlatformPropertyProvider;
+use nativelink_util::operation_state_manager::{
+    ActionStateResult, ActionStateResultStream, ClientStateManager, OperationFilter,
+};
+
+#[derive(MetricsComponent)]
+struct Route {
+    #[metric(group = "platform_properties")]
+    platform_properties: HashMap<String, String>,
+    #[metric(group = "scheduler")]
+    scheduler: Arc<dyn ClientStateManager>,
+}
+
+impl Route {
+    fn matches(&self, action_info: &ActionInfo) -> bool {
+        self.platform_properties.iter().all(|(name, value)| {
+            action_info
+                .platform_properties
+                .get(name)
+                .is_some_and(|action_value| action_value == value)
+        })
+    }
+}
+
+/// Sends each action to the scheduler of the first route matching its
+/// platform properties, or to the default scheduler if none matches.
+#[derive(MetricsComponent)]
+pub struct RouterScheduler {
+    #[metric(group = "routes")]
+    routes: Vec<Route>,
+    #[metric(group = "default_scheduler")]
+    default_scheduler: Arc<dyn ClientStateManager>,
+}
+
+impl core::fmt::Debug for RouterScheduler {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RouterScheduler")
+            .field(
+                "routes",
+                &self
+                    .routes
+                    .iter()
+                    .map(|route| &route.platform_properties)
+                    .collect::<Vec<_>>(),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl RouterScheduler {
+    /// Creates a router sending actions matching `spec.routes[i]` to
+    /// `route_schedulers[i]`.
+    pub fn new(
+        spec: &RouterSpec,
+        route_schedulers: Vec<Arc<dyn ClientStateManager>>,
+        default_scheduler: Arc<dyn ClientStateManager>,
+    ) -> Result<Self, Error> {
+        if spec.routes.len() != route_schedulers.len() {
+            return Err(make_input_err!(
+                "Got {} schedulers for {} routes in RouterScheduler::new",
+                route_schedulers.len(),
+                spec.routes.len()
+            ));
+        }
+        let routes = spec
+            .routes
+            .iter()
+            .zip(route_schedulers)
+            .map(|(route, scheduler)| Route {
+                platform_properties: route.platform_properties.clone(),
+                scheduler,
+            })
+            .collect();
+        Ok(Self {
+            routes,
+            default_scheduler,
+        })
+    }
+
+    fn schedulers(&self) -> impl Iterator<Item = &Arc<dyn ClientStateManager>> {
+        self.routes
+            .iter()
+            .map(|route| &route.scheduler)
+            .chain(core::iter::once(&self.default_scheduler))
+    }
+
+    fn scheduler_for_action(&self, action_info: &ActionInfo) -> &Arc<dyn ClientStateManager> {
+        self.routes
+            .iter()
+            .find(|route| route.matches(action_info))
+            .map_or(&self.default_scheduler, |route| &route.scheduler)
+    }
+
+    async fn inner_get_known_properties(&self, instance_name: &str) -> Result<Vec<String>, Error> {
+        let mut known_properties = HashSet::new();
+        for scheduler in self.schedulers() {
+            if let Some(known_platform_property_provider) =
+                scheduler.as_known_platform_property_provider()
+            {
+                known_properties.extend(
+                    known_platform_property_provider
+                        .get_known_properties(instance_name)
+                        .await
+                        .err_tip(|| "In RouterScheduler::get_known_properties")?,
+                );
+            }
+        }
+        // The properties used for routing must reach us even if no nested
+        // scheduler knows them.
+        for route in &self.routes {
+            known_properties.extend(route.platform_properties.keys().cloned());
+        }
+        Ok(known_properties.into_iter().collect())
+    }
+
+    async fn inner_filter_operations(
+        &self,
+        filter: OperationFilter,
+    ) -> Result<ActionStateResultStream<'_>, Error> {
+        // Operations are only ordered within each nested scheduler.
+        let mut streams = Vec::new();
+        for scheduler in self.schedulers() {
+            streams.push(
+                scheduler
+                    .filter_operations(filter.clone())
+                    .await
+                    .err_tip(|| "In RouterScheduler::filter_operations")?,
+            );
+        }
+        Ok(Box::pin(stream::iter(streams).flatten()))
+    }
+
+    async fn inner_cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        let filter = OperationFilter {
+            client_operation_id: Some(client_operation_id.clone()),
+            ..Default::default()
+        };
+        for scheduler in self.schedulers() {
+            let operation_count = scheduler
+                .count_operations(filter.clone())
+                .await
+                .err_tip(|| "In RouterScheduler::cancel_operation")?;
+            if operation_count > 0 {
+                return scheduler
+                    .cancel_operation(client_operation_id)
+                    .await
+                    .err_tip(|| "In RouterScheduler::cancel_operation");
+            }
+        }
+        Err(make_err!(
+            Code::NotFound,
+            "Operation {client_operation_id} not found in RouterScheduler::cancel_operation"
+        ))
+    }
+}
+
+#[async_trait]
+impl KnownPlatformPropertyProvider for RouterScheduler {
+    async fn get_known_properties(&self, instance_name: &str) -> Result<Vec<String>, Error> {
+        self.inner_get_known_properties(instance_name).await
+    }
+}
+
+#[async_trait]
+impl ClientStateManager for RouterScheduler {
+    async fn add_action(
+        &self,
+        client_operation_id: OperationId,
+        action_info: Arc<ActionInfo>,
+    ) -> Result<Box<dyn ActionStateResult>, Error> {
+        self.scheduler_for_action(&action_info)
+            .add_action(client_operation_id, action_info)
+            .await
+            .err_tip(|| "In RouterScheduler::add_action")
+    }
+
+    async fn filter_operations<'a>(
+        &'a self,
+        filter: OperationFilter,
+    ) -> Result<ActionStateResultStream<'a>, Error> {
+        self.inner_filter_operations(filter).await
+    }
+
+    async fn count_operations(&self, filter: OperationFilter) -> Result<usize, Error> {
+        let mut operation_count = 0;
+        for scheduler in self.schedulers() {
+            operation_count += scheduler
+                .count_operations(filter.clone())
+                .await
+                .err_tip(|| "In RouterScheduler::count_operations")?;
+        }
+        Ok(operation_count)
+    }
+
+    async fn cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        self.inner_cancel_operation(client_operation_id).await
+    }
+
+    fn as_known_platform_property_provider(&self) -> Option<&dyn KnownPlatformPropertyProvider> {
+        Some(self)
+    }
+}
+
+impl RootMetricsComponent for RouterScheduler {}
@@ -0,0 +1,162 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nativelink_config::schedulers::{WorkerAllocationStrategy, WorkerSchedulingPolicy};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::platform_properties::PlatformProperties;
+
+use crate::worker::Worker;
+
+/// What a `SchedulingPolicy` knows about the action it has to place.
+#[derive(Debug, Clone, Copy)]
+pub struct ActionPlacement<'a> {
+    /// The platform properties the action requires.
+    pub platform_properties: &'a PlatformProperties,
+    /// The input root of the action.
+    pub input_root_digest: &'a DigestInfo,
+}
+
+/// Decides which worker runs an action.
+pub trait SchedulingPolicy: core::fmt::Debug + Send + Sync + 'static {
+    /// Returns the worker among `candidates` that should run the action, or
+    /// None to leave the action queued. `candidates` only holds workers that
+    /// are able to run the action, from the most to the least recently used.
+    /// A sharded worker pool is offered one shard at a time, together with
+    /// the worker chosen from the shards before, so choosing batch by batch
+    /// must end with the worker that would be chosen among all of them.
+    fn select_worker<'a>(
+        &self,
+        candidates: &[&'a Worker],
+        action: &ActionPlacement<'_>,
+    ) -> Option<&'a Worker>;
+}
+
+/// Creates the scheduling policy selected in the config.
+#[must_use]
+pub fn make_scheduling_policy(
+    scheduling_policy: WorkerSchedulingPolicy,
+    allocation_strategy: WorkerAllocationStrategy,
+    input_root_affinity_size: usize,
+) -> Box<dyn SchedulingPolicy> {
+    match scheduling_policy {
+        WorkerSchedulingPolicy::Default => Box::new(DefaultSchedulingPolicy::new(
+            allocation_strategy,
+            input_root_affinity_size,
+        )),
+        WorkerSchedulingPolicy::LeastLoaded => Box::new(LeastLoadedSchedulingPolicy),
+        WorkerSchedulingPolicy::BinPacking => Box::new(BinPackingSchedulingPolicy),
+    }
+}
+
+/// Picks workers based on the allocation strategy, preferring workers that
+/// recently ran an action with the same input root.
+#[derive(Debug)]
+pub struct DefaultSchedulingPolicy {
+    allocation_strategy: WorkerAllocationStrategy,
+    input_root_affinity_size: usize,
+}
+
+impl DefaultSchedulingPolicy {
+    #[must_use]
+    pub const fn new(
+        allocation_strategy: WorkerAllocationStrategy,
+        input_root_affinity_size: usize,
+    ) -> Self {
+        Self {
+            allocation_strategy,
+            input_root_affinity_size,
+        }
+    }
+
+    fn find_in_allocation_order<'a>(
+        &self,
+        candidates: &[&'a Worker],
+        predicate: impl Fn(&Worker) -> bool,
+    ) -> Option<&'a Worker> {
+        let mut candidates_iter = candidates.iter().copied();
+        match self.allocation_strategy {
+            // Use rfind to get the least recently used that satisfies the predicate.
+            WorkerAllocationStrategy::LeastRecentlyUsed => {
+                candidates_iter.rfind(|worker| predicate(worker))
+            }
+            // Use find to get the most recently used that satisfies the predicate.
+            WorkerAllocationStrategy::MostRecentlyUsed => {
+                candidates_iter.find(|worker| predicate(worker))
+            }
+        }
+    }
+}
+
+impl SchedulingPolicy for DefaultSchedulingPolicy {
+    fn select_worker<'a>(
+        &self,
+        candidates: &[&'a Worker],
+        action: &ActionPlacement<'_>,
+    ) -> Option<&'a Worker> {
+        // Prefer a worker that recently ran an action with the same input
+        // root, as it likely still has most of the inputs cached.
+        if self.input_root_affinity_size > 0 {
+            let maybe_worker = self.find_in_allocation_order(candidates, |worker| {
+                worker.has_recent_input_root(action.input_root_digest)
+            });
+            if maybe_worker.is_some() {
+                return maybe_worker;
+            }
+        }
+        self.find_in_allocation_order(candidates, |_| true)
+    }
+}
+
+/// Picks the worker running the fewest actions, and the least recently used
+/// one among those.
+#[derive(Debug)]
+pub struct LeastLoadedSchedulingPolicy;
+
+impl SchedulingPolicy for LeastLoadedSchedulingPolicy {
+    fn select_worker<'a>(
+        &self,
+        candidates: &[&'a Worker],
+        _action: &ActionPlacement<'_>,
+    ) -> Option<&'a Worker> {
+        candidates
+            .iter()
+            .rev()
+            .min_by_key(|worker| worker.running_action_infos.len())
+            .copied()
+    }
+}
+
+/// Packs actions onto as few workers as possible by picking the worker that
+/// has the least consumable resources left once the action is started. Ties
+/// go to the busiest worker, so idle workers stay free for large actions.
+#[derive(Debug)]
+pub struct BinPackingSchedulingPolicy;
+
+impl SchedulingPolicy for BinPackingSchedulingPolicy {
+    fn select_worker<'a>(
+        &self,
+        candidates: &[&'a Worker],
+        action: &ActionPlacement<'_>,
+    ) -> Option<&'a Worker> {
+        candidates.iter().copied().min_by(|a, b| {
+            a.free_capacity_after(action.platform_properties)
+                .total_cmp(&b.free_capacity_after(action.platform_properties))
+                .then_with(|| {
+                    b.running_action_infos
+                        .len()
+                        .cmp(&a.running_action_infos.len())
+                })
+        })
+    }
+}
@@ -12,18 +12,23 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::SystemTime;
 
 use async_trait::async_trait;
 use futures::Future;
-use nativelink_config::schedulers::SimpleSpec;
-use nativelink_error::{Code, Error, ResultExt};
+use nativelink_config::schedulers::{
+    ClientDisconnectBehavior, ClientQuotaSpec, InputPrefetchSpec, QueueBackpressureSpec,
+    SimpleSpec, WorkerPoolSpec,
+};
+use nativelink_error::{Code, Error, ResultExt, make_err};
 use nativelink_metric::{MetricsComponent, RootMetricsComponent};
 use nativelink_proto::com::github::trace_machina::nativelink::events::OriginEvent;
 use nativelink_util::action_messages::{ActionInfo, ActionState, OperationId, WorkerId};
 use nativelink_util::instant_wrapper::InstantWrapper;
 use nativelink_util::known_platform_property_provider::KnownPlatformPropertyProvider;
+use nativelink_util::metrics_utils::CounterWithTime;
 use nativelink_util::operation_state_manager::{
     ActionStateResult, ActionStateResultStream, ClientStateManager, MatchingEngineStateManager,
     OperationFilter, OperationStageFlags, OrderDirection, UpdateOperationType,
@@ -36,17 +41,20 @@ use opentelemetry::KeyValue;
 use opentelemetry::baggage::BaggageExt;
 use opentelemetry::context::{Context, FutureExt as OtelFutureExt};
 use opentelemetry_semantic_conventions::attribute::ENDUSER_ID;
+use parking_lot::Mutex;
 use tokio::sync::{Notify, mpsc};
 use tokio::time::Duration;
 use tokio_stream::StreamExt;
-use tracing::{error, info_span};
+use tracing::{error, info_span, warn};
 
 use crate::api_worker_scheduler::ApiWorkerScheduler;
 use crate::awaited_action_db::{AwaitedActionDb, CLIENT_KEEPALIVE_DURATION};
 use crate::platform_property_manager::PlatformPropertyManager;
+use crate::scheduling_policy::make_scheduling_policy;
 use crate::simple_scheduler_state_manager::SimpleSchedulerStateManager;
-use crate::worker::{ActionInfoWithProps, Worker, WorkerTimestamp};
-use crate::worker_scheduler::WorkerScheduler;
+use crate::stage_dwell_times::StageDwellTimes;
+use crate::worker::{ActionInfoWithProps, Worker, WorkerTimestamp, WorkerUtilization};
+use crate::worker_scheduler::{PlacementExplanation, WorkerScheduler};
 
 /// Default timeout for workers in seconds.
 /// If this changes, remember to change the documentation in the config.
@@ -61,6 +69,18 @@ const DEFAULT_CLIENT_ACTION_TIMEOUT_S: u64 = 60;
 /// If this changes, remember to change the documentation in the config.
 const DEFAULT_MAX_JOB_RETRIES: usize = 3;
 
+/// Default seconds a client is told to wait after exceeding its quota.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_CLIENT_QUOTA_RETRY_DELAY_S: u64 = 10;
+
+/// Default seconds a client is told to wait when the queue is full.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_QUEUE_BACKPRESSURE_RETRY_DELAY_S: u64 = 10;
+
+/// Default number of idle workers asked to prefetch the inputs of an action.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_INPUT_PREFETCH_MAX_WORKERS_PER_ACTION: usize = 1;
+
 struct SimpleSchedulerActionStateResult {
     client_operation_id: OperationId,
     action_state_result: Box<dyn ActionStateResult>,
@@ -110,6 +130,42 @@ impl ActionStateResult for SimpleSchedulerActionStateResult {
             .await
             .err_tip(|| "In SimpleSchedulerActionStateResult")
     }
+
+    async fn queue_position(&self) -> Result<Option<usize>, Error> {
+        self.action_state_result
+            .queue_position()
+            .await
+            .err_tip(|| "In SimpleSchedulerActionStateResult")
+    }
+}
+
+/// Window over which `max_preemptions_per_minute` is enforced.
+const PREEMPTION_WINDOW: Duration = Duration::from_secs(60);
+
+/// Limits how many operations may be preempted within `PREEMPTION_WINDOW`.
+struct PreemptionLimiter {
+    max_preemptions: usize,
+    recent_preemptions: Mutex<VecDeque<SystemTime>>,
+    now_fn: Box<dyn Fn() -> SystemTime + Send + Sync>,
+}
+
+impl PreemptionLimiter {
+    /// Records a preemption and returns true if it is within the limit.
+    fn try_acquire(&self) -> bool {
+        let now = (self.now_fn)();
+        let mut recent_preemptions = self.recent_preemptions.lock();
+        while recent_preemptions.front().is_some_and(|preempted_at| {
+            now.duration_since(*preempted_at)
+                .is_ok_and(|elapsed| elapsed >= PREEMPTION_WINDOW)
+        }) {
+            recent_preemptions.pop_front();
+        }
+        if recent_preemptions.len() >= self.max_preemptions {
+            return false;
+        }
+        recent_preemptions.push_back(now);
+        true
+    }
 }
 
 /// Engine used to manage the queued/running tasks and relationship with
@@ -137,6 +193,34 @@ pub struct SimpleScheduler {
     /// The sender to send origin events to the origin events.
     maybe_origin_event_tx: Option<mpsc::Sender<OriginEvent>>,
 
+    /// Limits preemption of lower priority operations. None if preemption
+    /// is disabled.
+    maybe_preemption_limiter: Option<PreemptionLimiter>,
+
+    /// Named worker pools whose limits apply to the actions requesting them.
+    worker_pools: Vec<WorkerPoolSpec>,
+
+    /// Limits on the operations a single client may have. None if clients
+    /// are not limited.
+    maybe_client_quotas: Option<ClientQuotaSpec>,
+
+    /// Limits on the number of queued operations. None if the queue is not
+    /// limited.
+    maybe_queue_backpressure: Option<QueueBackpressureSpec>,
+
+    #[metric(help = "The number of actions rejected because too many actions were queued.")]
+    queue_backpressure_rejections: CounterWithTime,
+
+    /// How idle workers are asked to prefetch the inputs of new actions.
+    /// None if no hints are sent.
+    maybe_input_prefetch: Option<InputPrefetchSpec>,
+
+    /// Whether retried actions avoid the workers they already failed on.
+    retry_on_different_worker: bool,
+
+    /// Time operations spent in each stage. Published by the state manager.
+    stage_dwell_times: Arc<StageDwellTimes>,
+
     /// Background task that tries to match actions to workers. If this struct
     /// is dropped the spawn will be cancelled as well.
     task_worker_matching_spawn: JoinHandleDropGuard<()>,
@@ -167,8 +251,28 @@ impl SimpleScheduler {
     async fn inner_add_action(
         &self,
         client_operation_id: OperationId,
-        action_info: Arc<ActionInfo>,
+        mut action_info: Arc<ActionInfo>,
     ) -> Result<Box<dyn ActionStateResult>, Error> {
+        if let Some(client_quotas) = &self.maybe_client_quotas {
+            self.enforce_client_quotas(client_quotas)
+                .await
+                .err_tip(|| "In SimpleScheduler::add_action")?;
+        }
+        if let Some(queue_backpressure) = &self.maybe_queue_backpressure {
+            self.enforce_queue_backpressure(queue_backpressure, &action_info)
+                .await
+                .err_tip(|| "In SimpleScheduler::add_action")?;
+        }
+        if let Some(worker_pool) = self.worker_pool_for_action(&action_info) {
+            self.apply_worker_pool(worker_pool, &mut action_info)
+                .await
+                .err_tip(|| "In SimpleScheduler::add_action")?;
+        }
+        // Hint the idle workers before the action is queued, so the hint
+        // reaches them before any of them is assigned the action.
+        if let Some(input_prefetch) = &self.maybe_input_prefetch {
+            self.send_prefetch_hints(input_prefetch, &action_info).await;
+        }
         let action_state_result = self
             .client_state_manager
             .add_action(client_operation_id.clone(), action_info)
@@ -180,6 +284,218 @@ impl SimpleScheduler {
         )))
     }
 
+    /// Rejects a new action if the client adding it already has too many
+    /// queued or executing operations.
+    async fn enforce_client_quotas(&self, client_quotas: &ClientQuotaSpec) -> Result<(), Error> {
+        let identity = Context::current()
+            .baggage()
+            .get(ENDUSER_ID)
+            .map(|value| value.as_str().to_string())
+            .unwrap_or_default();
+        if identity.is_empty() {
+            return Ok(());
+        }
+        let retry_delay_s = if client_quotas.retry_delay_s == 0 {
+            DEFAULT_CLIENT_QUOTA_RETRY_DELAY_S
+        } else {
+            client_quotas.retry_delay_s
+        };
+        for (stages, max_actions, stage_name) in [
+            (
+                OperationStageFlags::Queued,
+                client_quotas.max_queued_actions,
+                "queued",
+            ),
+            (
+                OperationStageFlags::Executing,
+                client_quotas.max_executing_actions,
+                "executing",
+            ),
+        ] {
+            if max_actions == 0 {
+                continue;
+            }
+            let actions = self
+                .client_state_manager
+                .count_operations(OperationFilter {
+                    stages,
+                    identity: Some(identity.clone()),
+                    ..Default::default()
+                })
+                .await
+                .err_tip(
+                    || "Failed to count client actions in SimpleScheduler::enforce_client_quotas",
+                )?;
+            if actions >= max_actions {
+                return Err(make_err!(
+                    Code::ResourceExhausted,
+                    "Client {identity} already has {actions} {stage_name} actions, retry in {retry_delay_s}s"
+                )
+                .with_retry_delay(Duration::from_secs(retry_delay_s)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects a new action if too many actions are queued, in total or in
+    /// the platform bucket of the action.
+    async fn enforce_queue_backpressure(
+        &self,
+        queue_backpressure: &QueueBackpressureSpec,
+        action_info: &ActionInfo,
+    ) -> Result<(), Error> {
+        let retry_delay_s = if queue_backpressure.retry_delay_s == 0 {
+            DEFAULT_QUEUE_BACKPRESSURE_RETRY_DELAY_S
+        } else {
+            queue_backpressure.retry_delay_s
+        };
+        let bucket_platform_properties: BTreeMap<String, String> =
+            if queue_backpressure.bucket_properties.is_empty() {
+                action_info
+                    .platform_properties
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect()
+            } else {
+                queue_backpressure
+                    .bucket_properties
+                    .iter()
+                    .filter_map(|name| {
+                        let value = action_info.platform_properties.get(name)?;
+                        Some((name.clone(), value.clone()))
+                    })
+                    .collect()
+            };
+        for (max_queued_actions, platform_properties, scope) in [
+            (
+                queue_backpressure.max_queued_actions,
+                BTreeMap::new(),
+                "the scheduler",
+            ),
+            (
+                queue_backpressure.max_queued_actions_per_bucket,
+                bucket_platform_properties,
+                "the platform bucket of the action",
+            ),
+        ] {
+            if max_queued_actions == 0 {
+                continue;
+            }
+            let queued_actions = self
+                .client_state_manager
+                .count_operations(OperationFilter {
+                    stages: OperationStageFlags::Queued,
+                    platform_properties,
+                    ..Default::default()
+                })
+                .await
+                .err_tip(|| {
+                    "Failed to count queued actions in SimpleScheduler::enforce_queue_backpressure"
+                })?;
+            if queued_actions >= max_queued_actions {
+                self.queue_backpressure_rejections.inc();
+                return Err(make_err!(
+                    Code::ResourceExhausted,
+                    "{queued_actions} actions are already queued in {scope}, retry in {retry_delay_s}s"
+                )
+                .with_retry_delay(Duration::from_secs(retry_delay_s)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Asks idle workers able to run the action to start downloading its
+    /// input root. Failures are only logged, since the hint is an
+    /// optimization.
+    async fn send_prefetch_hints(
+        &self,
+        input_prefetch: &InputPrefetchSpec,
+        action_info: &ActionInfo,
+    ) {
+        let max_workers = if input_prefetch.max_workers_per_action == 0 {
+            DEFAULT_INPUT_PREFETCH_MAX_WORKERS_PER_ACTION
+        } else {
+            input_prefetch.max_workers_per_action
+        };
+        let platform_properties = match self
+            .platform_property_manager
+            .make_platform_properties(action_info.platform_properties.clone())
+        {
+            Ok(platform_properties) => platform_properties,
+            Err(err) => {
+                warn!(
+                    ?err,
+                    "Failed to make platform properties for prefetch hints"
+                );
+                return;
+            }
+        };
+        self.worker_scheduler
+            .prefetch_inputs(
+                &platform_properties,
+                action_info.input_root_digest,
+                max_workers,
+            )
+            .await;
+    }
+
+    /// Returns the worker pool the action requests, if any.
+    fn worker_pool_for_action(&self, action_info: &ActionInfo) -> Option<&WorkerPoolSpec> {
+        self.worker_pools.iter().find(|worker_pool| {
+            action_info
+                .platform_properties
+                .get(&worker_pool.property_name)
+                == Some(&worker_pool.property_value)
+        })
+    }
+
+    /// Enforces the limits of the worker pool on a new action and applies
+    /// the pool's priority to it.
+    async fn apply_worker_pool(
+        &self,
+        worker_pool: &WorkerPoolSpec,
+        action_info: &mut Arc<ActionInfo>,
+    ) -> Result<(), Error> {
+        let instance_name = action_info.instance_name();
+        if !worker_pool.allowed_instance_names.is_empty()
+            && !worker_pool.allowed_instance_names.contains(instance_name)
+        {
+            return Err(make_err!(
+                Code::PermissionDenied,
+                "Instance {instance_name} is not allowed to use worker pool {}",
+                worker_pool.name
+            ));
+        }
+        if worker_pool.max_queued_actions > 0 {
+            let queued_actions = self
+                .client_state_manager
+                .count_operations(OperationFilter {
+                    stages: OperationStageFlags::Queued,
+                    platform_properties: BTreeMap::from([(
+                        worker_pool.property_name.clone(),
+                        worker_pool.property_value.clone(),
+                    )]),
+                    ..Default::default()
+                })
+                .await
+                .err_tip(
+                    || "Failed to count queued actions in SimpleScheduler::apply_worker_pool",
+                )?;
+            if queued_actions >= worker_pool.max_queued_actions {
+                return Err(make_err!(
+                    Code::ResourceExhausted,
+                    "Worker pool {} already has {queued_actions} queued actions",
+                    worker_pool.name
+                ));
+            }
+        }
+        if worker_pool.priority != 0 {
+            let action_info = Arc::make_mut(action_info);
+            action_info.priority = action_info.priority.saturating_add(worker_pool.priority);
+        }
+        Ok(())
+    }
+
     async fn inner_filter_operations(
         &self,
         filter: OperationFilter,
@@ -202,19 +518,28 @@ impl SimpleScheduler {
             .err_tip(|| "In SimpleScheduler::get_queued_operations getting filter result")
     }
 
+    /// Histograms of the time operations spent in each stage.
+    pub fn stage_dwell_times(&self) -> &StageDwellTimes {
+        &self.stage_dwell_times
+    }
+
     pub async fn do_try_match_for_test(&self) -> Result<(), Error> {
         self.do_try_match().await
     }
 
-    // TODO(palfrey) This is an O(n*m) (aka n^2) algorithm. In theory we
-    // can create a map of capabilities of each worker and then try and match
-    // the actions to the worker using the map lookup (ie. map reduce).
+    // Operations are grouped by their platform properties. Once no worker
+    // can take an operation of a group, the rest of the group is skipped
+    // for this pass, so a pass only scans the workers once per group that
+    // is still schedulable instead of once per queued operation.
     async fn do_try_match(&self) -> Result<(), Error> {
         async fn match_action_to_worker(
             action_state_result: &dyn ActionStateResult,
+            full_platform_buckets: &mut HashSet<BTreeMap<String, String>>,
             workers: &ApiWorkerScheduler,
             matching_engine_state_manager: &dyn MatchingEngineStateManager,
             platform_property_manager: &PlatformPropertyManager,
+            maybe_preemption_limiter: Option<&PreemptionLimiter>,
+            retry_on_different_worker: bool,
         ) -> Result<(), Error> {
             let (action_info, maybe_origin_metadata) =
                 action_state_result
@@ -222,6 +547,27 @@ impl SimpleScheduler {
                     .await
                     .err_tip(|| "Failed to get action_info from as_action_info_result stream")?;
 
+            // Operations are visited in priority order, so if an earlier
+            // operation with the same properties found no worker, neither
+            // will this one.
+            let platform_bucket: BTreeMap<String, String> = action_info
+                .platform_properties
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            if full_platform_buckets.contains(&platform_bucket) {
+                return Ok(());
+            }
+
+            let excluded_worker_ids = if retry_on_different_worker {
+                action_state_result
+                    .failed_worker_ids()
+                    .await
+                    .err_tip(|| "Failed to get failed_worker_ids in do_try_match")?
+            } else {
+                Vec::new()
+            };
+
             // TODO(palfrey) We should not compute this every time and instead store
             // it with the ActionInfo when we receive it.
             let platform_properties = platform_property_manager
@@ -238,13 +584,57 @@ impl SimpleScheduler {
             // Try to find a worker for the action.
             let worker_id = {
                 match workers
-                    .find_worker_for_action(&action_info.platform_properties)
+                    .find_worker_for_action(
+                        &action_info.platform_properties,
+                        &action_info.inner.input_root_digest,
+                        &excluded_worker_ids,
+                        action_info.is_exclusive(),
+                    )
                     .await
                 {
                     Some(worker_id) => worker_id,
-                    // If we could not find a worker for the action,
-                    // we have nothing to do.
-                    None => return Ok(()),
+                    // If we could not find a worker for the action and are not
+                    // allowed to preempt, we have nothing to do. Exclusive
+                    // actions wait for the worker reserved for them instead.
+                    None => {
+                        let Some(preemption_limiter) =
+                            maybe_preemption_limiter.filter(|_| !action_info.is_exclusive())
+                        else {
+                            full_platform_buckets.insert(platform_bucket);
+                            return Ok(());
+                        };
+                        let preempted = workers
+                            .preempt_operation_for_action(
+                                &action_info.platform_properties,
+                                action_info.inner.priority,
+                                || preemption_limiter.try_acquire(),
+                            )
+                            .await
+                            .err_tip(
+                                || "Failed to preempt operation in SimpleScheduler::do_try_match",
+                            )?;
+                        if !preempted {
+                            full_platform_buckets.insert(platform_bucket);
+                            return Ok(());
+                        }
+                        // Claim the freed worker now, otherwise a lower priority
+                        // action further down the queue could take it.
+                        match workers
+                            .find_worker_for_action(
+                                &action_info.platform_properties,
+                                &action_info.inner.input_root_digest,
+                                &excluded_worker_ids,
+                                false,
+                            )
+                            .await
+                        {
+                            Some(worker_id) => worker_id,
+                            None => {
+                                full_platform_buckets.insert(platform_bucket);
+                                return Ok(());
+                            }
+                        }
+                    }
                 }
             };
 
@@ -295,7 +685,14 @@ impl SimpleScheduler {
                 .await
         }
 
+        // A draining scheduler lets the executing operations finish, but
+        // does not start new ones.
+        if self.worker_scheduler.is_draining() {
+            return Ok(());
+        }
         let mut result = Ok(());
+        let mut full_platform_buckets = HashSet::new();
+        self.worker_scheduler.clear_exclusive_reservations().await;
 
         let mut stream = self
             .get_queued_operations()
@@ -306,9 +703,12 @@ impl SimpleScheduler {
             result = result.merge(
                 match_action_to_worker(
                     action_state_result.as_ref(),
+                    &mut full_platform_buckets,
                     self.worker_scheduler.as_ref(),
                     self.matching_engine_state_manager.as_ref(),
                     self.platform_property_manager.as_ref(),
+                    self.maybe_preemption_limiter.as_ref(),
+                    self.retry_on_different_worker,
                 )
                 .await,
             );
@@ -383,27 +783,61 @@ impl SimpleScheduler {
                 CLIENT_KEEPALIVE_DURATION.as_secs()
             );
         }
+        let client_action_timeout = match spec.client_disconnect_behavior {
+            ClientDisconnectBehavior::Cancel => Some(Duration::from_secs(client_action_timeout_s)),
+            ClientDisconnectBehavior::KeepRunning => None,
+            ClientDisconnectBehavior::GracePeriod(grace_period_s) => Some(Duration::from_secs(
+                client_action_timeout_s + grace_period_s,
+            )),
+        };
 
         let mut max_job_retries = spec.max_job_retries;
         if max_job_retries == 0 {
             max_job_retries = DEFAULT_MAX_JOB_RETRIES;
         }
 
+        let maybe_preemption_limiter = (spec.max_preemptions_per_minute > 0).then(|| {
+            let now_fn = now_fn.clone();
+            PreemptionLimiter {
+                max_preemptions: spec.max_preemptions_per_minute,
+                recent_preemptions: Mutex::new(VecDeque::new()),
+                now_fn: Box::new(move || now_fn().now()),
+            }
+        });
+
+        let worker_pools = spec.worker_pools.clone();
+        let maybe_client_quotas = spec.client_quotas;
+        let maybe_queue_backpressure = spec.queue_backpressure.clone();
+        let maybe_input_prefetch = spec.input_prefetch;
+        let retry_on_different_worker = spec.retry_on_different_worker;
+
         let worker_change_notify = Arc::new(Notify::new());
+        let stage_dwell_times = Arc::new(StageDwellTimes::default());
         let state_manager = SimpleSchedulerStateManager::new(
             max_job_retries,
             Duration::from_secs(worker_timeout_s),
-            Duration::from_secs(client_action_timeout_s),
+            client_action_timeout,
+            (spec.max_queued_action_age_s > 0)
+                .then(|| Duration::from_secs(spec.max_queued_action_age_s)),
             awaited_action_db,
+            stage_dwell_times.clone(),
             now_fn,
         );
 
         let worker_scheduler = ApiWorkerScheduler::new(
             state_manager.clone(),
             platform_property_manager.clone(),
-            spec.allocation_strategy,
+            make_scheduling_policy(
+                spec.scheduling_policy,
+                spec.allocation_strategy,
+                spec.worker_input_root_affinity_size,
+            ),
+            spec.worker_input_root_affinity_size,
+            spec.worker_quarantine,
+            spec.execution_timeout_grace_s,
             worker_change_notify.clone(),
             worker_timeout_s,
+            spec.worker_shards,
         );
 
         let worker_scheduler_clone = worker_scheduler.clone();
@@ -454,6 +888,14 @@ impl SimpleScheduler {
                 worker_scheduler,
                 platform_property_manager,
                 maybe_origin_event_tx,
+                maybe_preemption_limiter,
+                worker_pools,
+                maybe_client_quotas,
+                maybe_queue_backpressure,
+                queue_backpressure_rejections: CounterWithTime::default(),
+                maybe_input_prefetch,
+                retry_on_different_worker,
+                stage_dwell_times,
                 task_worker_matching_spawn,
             }
         });
@@ -479,6 +921,29 @@ impl ClientStateManager for SimpleScheduler {
         self.inner_filter_operations(filter).await
     }
 
+    async fn count_operations(&self, filter: OperationFilter) -> Result<usize, Error> {
+        self.client_state_manager
+            .count_operations(filter)
+            .await
+            .err_tip(|| "In SimpleScheduler::count_operations")
+    }
+
+    async fn cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        // Workers know operations by their internal id, not the client one.
+        let maybe_operation_id = self
+            .matching_engine_state_manager
+            .cancel_operation(client_operation_id)
+            .await
+            .err_tip(|| "In SimpleScheduler::cancel_operation")?;
+        if let Some(operation_id) = maybe_operation_id {
+            self.worker_scheduler
+                .kill_operation(&operation_id)
+                .await
+                .err_tip(|| "In SimpleScheduler::cancel_operation")?;
+        }
+        Ok(())
+    }
+
     fn as_known_platform_property_provider(&self) -> Option<&dyn KnownPlatformPropertyProvider> {
         Some(self)
     }
@@ -528,6 +993,16 @@ impl WorkerScheduler for SimpleScheduler {
             .await
     }
 
+    async fn update_worker_utilization(
+        &self,
+        worker_id: &WorkerId,
+        utilization: WorkerUtilization,
+    ) -> Result<(), Error> {
+        self.worker_scheduler
+            .update_worker_utilization(worker_id, utilization)
+            .await
+    }
+
     async fn remove_worker(&self, worker_id: &WorkerId) -> Result<(), Error> {
         self.worker_scheduler.remove_worker(worker_id).await
     }
@@ -547,6 +1022,74 @@ impl WorkerScheduler for SimpleScheduler {
             .set_drain_worker(worker_id, is_draining)
             .await
     }
+
+    async fn drain_worker_with_deadline(
+        &self,
+        worker_id: &WorkerId,
+        deadline: WorkerTimestamp,
+    ) -> Result<(), Error> {
+        self.worker_scheduler
+            .drain_worker_with_deadline(worker_id, deadline)
+            .await
+    }
+
+    fn set_draining(&self, is_draining: bool) -> Result<(), Error> {
+        self.worker_scheduler.set_draining(is_draining)
+    }
+
+    fn is_draining(&self) -> bool {
+        self.worker_scheduler.is_draining()
+    }
+
+    async fn explain_placement(
+        &self,
+        operation_id: &OperationId,
+    ) -> Result<PlacementExplanation, Error> {
+        let action_state_result = self
+            .client_state_manager
+            .filter_operations(OperationFilter {
+                client_operation_id: Some(operation_id.clone()),
+                ..Default::default()
+            })
+            .await
+            .err_tip(|| "In SimpleScheduler::explain_placement")?
+            .next()
+            .await
+            .ok_or_else(|| make_err!(Code::NotFound, "Operation {operation_id} not found"))?;
+        let (action_state, _origin_metadata) = action_state_result
+            .as_state()
+            .await
+            .err_tip(|| "In SimpleScheduler::explain_placement")?;
+        let (action_info, _origin_metadata) = action_state_result
+            .as_action_info()
+            .await
+            .err_tip(|| "In SimpleScheduler::explain_placement")?;
+        let excluded_worker_ids = if self.retry_on_different_worker {
+            action_state_result
+                .failed_worker_ids()
+                .await
+                .err_tip(|| "In SimpleScheduler::explain_placement")?
+        } else {
+            Vec::new()
+        };
+        let platform_properties = self
+            .platform_property_manager
+            .make_platform_properties(action_info.platform_properties.clone())
+            .err_tip(|| "In SimpleScheduler::explain_placement")?;
+        Ok(PlacementExplanation {
+            operation_id: operation_id.clone(),
+            stage: action_state.stage.name().to_string(),
+            platform_properties: action_info
+                .platform_properties
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            workers: self
+                .worker_scheduler
+                .explain_placement(&platform_properties, &excluded_worker_ids)
+                .await,
+        })
+    }
 }
 
 impl RootMetricsComponent for SimpleScheduler {}
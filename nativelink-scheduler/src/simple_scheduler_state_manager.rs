@@ -16,6 +16,7 @@ use core::ops::Bound;
 use core::time::Duration;
 use std::string::ToString;
 use std::sync::{Arc, Weak};
+use std::time::SystemTime;
 
 use async_lock::Mutex;
 use async_trait::async_trait;
@@ -28,6 +29,7 @@ use nativelink_util::action_messages::{
 };
 use nativelink_util::instant_wrapper::InstantWrapper;
 use nativelink_util::known_platform_property_provider::KnownPlatformPropertyProvider;
+use nativelink_util::metrics_utils::CounterWithTime;
 use nativelink_util::operation_state_manager::{
     ActionStateResult, ActionStateResultStream, ClientStateManager, MatchingEngineStateManager,
     OperationFilter, OperationStageFlags, OrderDirection, UpdateOperationType, WorkerStateManager,
@@ -36,13 +38,81 @@ use nativelink_util::origin_event::OriginMetadata;
 use tracing::{info, warn};
 
 use super::awaited_action_db::{
-    AwaitedAction, AwaitedActionDb, AwaitedActionSubscriber, SortedAwaitedActionState,
+    AwaitedAction, AwaitedActionDb, AwaitedActionSubscriber, SortedAwaitedAction,
+    SortedAwaitedActionState,
 };
+use crate::stage_dwell_times::{DwellStage, StageDwellTimes};
 
 /// Maximum number of times an update to the database
 /// can fail before giving up.
 const MAX_UPDATE_RETRIES: usize = 5;
 
+/// Returns the sorted state that holds exactly the operations in `stage`,
+/// or `None` if `stage` covers zero or several states.
+const fn sorted_awaited_action_state_for_flags(
+    stage: OperationStageFlags,
+) -> Option<SortedAwaitedActionState> {
+    match stage {
+        OperationStageFlags::CacheCheck => Some(SortedAwaitedActionState::CacheCheck),
+        OperationStageFlags::Queued => Some(SortedAwaitedActionState::Queued),
+        OperationStageFlags::Executing => Some(SortedAwaitedActionState::Executing),
+        OperationStageFlags::Completed => Some(SortedAwaitedActionState::Completed),
+        _ => None,
+    }
+}
+
+/// Returns how long `awaited_action` spent in each stage it leaves by moving
+/// to `new_stage`. The upload of the outputs of a completed action is split
+/// out of its execution time using the timestamps reported by the worker.
+fn finished_stage_dwell_times(
+    awaited_action: &AwaitedAction,
+    new_stage: &ActionStage,
+    now: SystemTime,
+) -> Vec<(DwellStage, Duration)> {
+    let current_stage = &awaited_action.state().stage;
+    if core::mem::discriminant(current_stage) == core::mem::discriminant(new_stage) {
+        return Vec::new();
+    }
+    let Some(dwell_time) = awaited_action
+        .stage_entered_timestamp()
+        .and_then(|entered| now.duration_since(entered).ok())
+    else {
+        return Vec::new();
+    };
+    match current_stage {
+        ActionStage::CacheCheck => vec![(DwellStage::CacheCheck, dwell_time)],
+        ActionStage::Queued => vec![(DwellStage::Queued, dwell_time)],
+        ActionStage::Executing => {
+            let upload_time = match new_stage {
+                ActionStage::Completed(action_result) => {
+                    let metadata = &action_result.execution_metadata;
+                    metadata
+                        .output_upload_completed_timestamp
+                        .duration_since(metadata.output_upload_start_timestamp)
+                        .ok()
+                        .filter(|_| {
+                            metadata.output_upload_start_timestamp != SystemTime::UNIX_EPOCH
+                        })
+                }
+                _ => None,
+            };
+            match upload_time {
+                Some(upload_time) => vec![
+                    (
+                        DwellStage::Executing,
+                        dwell_time.saturating_sub(upload_time),
+                    ),
+                    (DwellStage::ResultUpload, upload_time),
+                ],
+                None => vec![(DwellStage::Executing, dwell_time)],
+            }
+        }
+        ActionStage::Unknown | ActionStage::Completed(_) | ActionStage::CompletedFromCache(_) => {
+            Vec::new()
+        }
+    }
+}
+
 /// Simple struct that implements the `ActionStateResult` trait and always returns an error.
 struct ErrorActionStateResult(Error);
 
@@ -114,6 +184,14 @@ where
     async fn as_action_info(&self) -> Result<(Arc<ActionInfo>, Option<OriginMetadata>), Error> {
         self.inner.as_action_info().await
     }
+
+    async fn queue_position(&self) -> Result<Option<usize>, Error> {
+        self.inner.queue_position().await
+    }
+
+    async fn failed_worker_ids(&self) -> Result<Vec<WorkerId>, Error> {
+        self.inner.failed_worker_ids().await
+    }
 }
 
 struct MatchingEngineActionStateResult<U, T, I, NowFn>
@@ -238,6 +316,48 @@ where
             awaited_action.maybe_origin_metadata().cloned(),
         ))
     }
+
+    async fn queue_position(&self) -> Result<Option<usize>, Error> {
+        let awaited_action = self
+            .awaited_action_sub
+            .borrow()
+            .await
+            .err_tip(|| "In MatchingEngineActionStateResult::queue_position")?;
+        if !matches!(awaited_action.state().stage, ActionStage::Queued) {
+            return Ok(None);
+        }
+        let simple_scheduler_state_manager = self
+            .simple_scheduler_state_manager
+            .upgrade()
+            .err_tip(|| "Failed to upgrade weak reference to SimpleSchedulerStateManager in MatchingEngineActionStateResult::queue_position")?;
+        // The matching engine picks up queued actions in descending sort key
+        // order, so every action with a greater key is ahead of this one.
+        let range_result = simple_scheduler_state_manager
+            .action_db
+            .get_range_of_actions(
+                SortedAwaitedActionState::Queued,
+                Bound::Excluded(SortedAwaitedAction::from(&awaited_action)),
+                Bound::Unbounded,
+                false,
+            )
+            .await;
+        match range_result {
+            Ok(stream) => Ok(Some(stream.count().await)),
+            // Not every database supports ranges and the position is only
+            // informational, so just leave it out.
+            Err(err) if err.code == Code::Unimplemented => Ok(None),
+            Err(err) => Err(err).err_tip(|| "In MatchingEngineActionStateResult::queue_position"),
+        }
+    }
+
+    async fn failed_worker_ids(&self) -> Result<Vec<WorkerId>, Error> {
+        let awaited_action = self
+            .awaited_action_sub
+            .borrow()
+            .await
+            .err_tip(|| "In MatchingEngineActionStateResult::failed_worker_ids")?;
+        Ok(awaited_action.failed_worker_ids().to_vec())
+    }
 }
 
 /// `SimpleSchedulerStateManager` is responsible for maintaining the state of the scheduler.
@@ -270,8 +390,19 @@ where
 
     /// Mark operation as timed out if the worker has not updated in this duration.
     /// This is used to prevent operations from being stuck in the queue forever
-    /// if it is not being processed by any worker.
-    client_action_timeout: Duration,
+    /// if it is not being processed by any worker. None if operations are
+    /// kept running without clients.
+    client_action_timeout: Option<Duration>,
+
+    /// Fail operations that have been queued for longer than this.
+    max_queued_action_age: Option<Duration>,
+
+    #[metric(help = "The number of queued operations failed for being queued too long")]
+    stale_queued_actions_evicted: CounterWithTime,
+
+    /// Time operations spent in each stage.
+    #[metric(group = "stage_dwell_times")]
+    stage_dwell_times: Arc<StageDwellTimes>,
 
     // A lock to ensure only one timeout operation is running at a time
     // on this service.
@@ -296,8 +427,10 @@ where
     pub(crate) fn new(
         max_job_retries: usize,
         no_event_action_timeout: Duration,
-        client_action_timeout: Duration,
+        client_action_timeout: Option<Duration>,
+        max_queued_action_age: Option<Duration>,
         action_db: T,
+        stage_dwell_times: Arc<StageDwellTimes>,
         now_fn: NowFn,
     ) -> Arc<Self> {
         Arc::new_cyclic(|weak_self| Self {
@@ -305,12 +438,73 @@ where
             max_job_retries,
             no_event_action_timeout,
             client_action_timeout,
+            max_queued_action_age,
+            stale_queued_actions_evicted: CounterWithTime::default(),
+            stage_dwell_times,
             timeout_operation_mux: Mutex::new(()),
             weak_self: weak_self.clone(),
             now_fn,
         })
     }
 
+    /// Completes an unfinished action with `err`. If the action changed in
+    /// the meantime it is reloaded and only completed while `should_complete`
+    /// still holds for it. Returns the reloaded action if it no longer needs
+    /// to be completed, otherwise None.
+    async fn complete_with_error(
+        &self,
+        awaited_action: &AwaitedAction,
+        subscriber: &T::Subscriber,
+        err: Error,
+        should_complete: impl Fn(&AwaitedAction) -> bool + Send + Sync,
+    ) -> Option<AwaitedAction> {
+        if awaited_action.state().stage.is_finished() {
+            return None;
+        }
+        let mut state = awaited_action.state().as_ref().clone();
+        state.stage = ActionStage::Completed(ActionResult {
+            error: Some(err),
+            ..ActionResult::default()
+        });
+        let state = Arc::new(state);
+        let mut new_awaited_action = awaited_action.clone();
+        // We may be competing with an client timestamp update, so try
+        // this a few times.
+        for attempt in 1..=MAX_UPDATE_RETRIES {
+            new_awaited_action.worker_set_state(state.clone(), (self.now_fn)().now());
+            let err = match self
+                .action_db
+                .update_awaited_action(new_awaited_action)
+                .await
+            {
+                Ok(()) => break,
+                Err(err) => err,
+            };
+            // Reload from the database if the action was outdated.
+            let maybe_awaited_action = if attempt == MAX_UPDATE_RETRIES || err.code != Code::Aborted
+            {
+                None
+            } else {
+                subscriber.borrow().await.ok()
+            };
+            let Some(reloaded_awaited_action) = maybe_awaited_action else {
+                warn!(
+                    "Failed to update action to completed state. This is ok if multiple schedulers tried to set the state at the same time: {err}",
+                );
+                break;
+            };
+            // Re-check the predicate after reload.
+            if !should_complete(&reloaded_awaited_action) {
+                return Some(reloaded_awaited_action);
+            }
+            if reloaded_awaited_action.state().stage.is_finished() {
+                break;
+            }
+            new_awaited_action = reloaded_awaited_action;
+        }
+        None
+    }
+
     async fn apply_filter_predicate(
         &self,
         awaited_action: &AwaitedAction,
@@ -320,74 +514,57 @@ where
         // Note: The caller must filter `client_operation_id`.
 
         let mut maybe_reloaded_awaited_action: Option<AwaitedAction> = None;
-        if awaited_action.last_client_keepalive_timestamp() + self.client_action_timeout
-            < (self.now_fn)().now()
-        {
-            // This may change if the version is out of date.
-            let mut timed_out = true;
-            if !awaited_action.state().stage.is_finished() {
-                let mut state = awaited_action.state().as_ref().clone();
-                state.stage = ActionStage::Completed(ActionResult {
-                    error: Some(make_err!(
-                        Code::DeadlineExceeded,
-                        "Operation timed out {} seconds of having no more clients listening",
-                        self.client_action_timeout.as_secs_f32(),
-                    )),
-                    ..ActionResult::default()
-                });
-                let state = Arc::new(state);
-                // We may be competing with an client timestamp update, so try
-                // this a few times.
-                for attempt in 1..=MAX_UPDATE_RETRIES {
-                    let mut new_awaited_action = match &maybe_reloaded_awaited_action {
-                        None => awaited_action.clone(),
-                        Some(reloaded_awaited_action) => reloaded_awaited_action.clone(),
-                    };
-                    new_awaited_action.worker_set_state(state.clone(), (self.now_fn)().now());
-                    let err = match self
-                        .action_db
-                        .update_awaited_action(new_awaited_action)
-                        .await
-                    {
-                        Ok(()) => break,
-                        Err(err) => err,
-                    };
-                    // Reload from the database if the action was outdated.
-                    let maybe_awaited_action =
-                        if attempt == MAX_UPDATE_RETRIES || err.code != Code::Aborted {
-                            None
-                        } else {
-                            subscriber.borrow().await.ok()
-                        };
-                    if let Some(reloaded_awaited_action) = maybe_awaited_action {
-                        maybe_reloaded_awaited_action = Some(reloaded_awaited_action);
-                    } else {
-                        warn!(
-                            "Failed to update action to timed out state after client keepalive timeout. This is ok if multiple schedulers tried to set the state at the same time: {err}",
-                        );
-                        break;
-                    }
-                    // Re-check the predicate after reload.
-                    if maybe_reloaded_awaited_action
-                        .as_ref()
-                        .is_some_and(|awaited_action| {
-                            awaited_action.last_client_keepalive_timestamp()
-                                + self.client_action_timeout
-                                >= (self.now_fn)().now()
-                        })
-                    {
-                        timed_out = false;
-                        break;
-                    } else if maybe_reloaded_awaited_action
-                        .as_ref()
-                        .is_some_and(|awaited_action| awaited_action.state().stage.is_finished())
-                    {
-                        break;
-                    }
-                }
+        if let Some(client_action_timeout) = self.client_action_timeout {
+            let is_abandoned = |awaited_action: &AwaitedAction| {
+                awaited_action.last_client_keepalive_timestamp() + client_action_timeout
+                    < (self.now_fn)().now()
+            };
+            if is_abandoned(awaited_action) {
+                let maybe_awaited_action = self
+                    .complete_with_error(
+                        awaited_action,
+                        subscriber,
+                        make_err!(
+                            Code::DeadlineExceeded,
+                            "Operation timed out {} seconds of having no more clients listening",
+                            client_action_timeout.as_secs_f32(),
+                        ),
+                        is_abandoned,
+                    )
+                    .await;
+                let Some(reloaded_awaited_action) = maybe_awaited_action else {
+                    return false;
+                };
+                maybe_reloaded_awaited_action = Some(reloaded_awaited_action);
             }
-            if timed_out {
-                return false;
+        }
+        if let Some(max_queued_action_age) = self.max_queued_action_age {
+            let is_stale = |awaited_action: &AwaitedAction| {
+                matches!(awaited_action.state().stage, ActionStage::Queued)
+                    && awaited_action.last_worker_updated_timestamp() + max_queued_action_age
+                        < (self.now_fn)().now()
+            };
+            let current_awaited_action = maybe_reloaded_awaited_action
+                .as_ref()
+                .unwrap_or(awaited_action);
+            if is_stale(current_awaited_action) {
+                let maybe_awaited_action = self
+                    .complete_with_error(
+                        current_awaited_action,
+                        subscriber,
+                        make_err!(
+                            Code::DeadlineExceeded,
+                            "Operation was queued for more than {} seconds without being picked up by a worker",
+                            max_queued_action_age.as_secs_f32(),
+                        ),
+                        is_stale,
+                    )
+                    .await;
+                let Some(reloaded_awaited_action) = maybe_awaited_action else {
+                    self.stale_queued_actions_evicted.inc();
+                    return false;
+                };
+                maybe_reloaded_awaited_action = Some(reloaded_awaited_action);
             }
         }
         // If the action was reloaded, then use that for the rest of the checks
@@ -424,6 +601,21 @@ where
                     return false;
                 }
             }
+            let platform_properties = &awaited_action.action_info().platform_properties;
+            for (name, value) in &filter.platform_properties {
+                if platform_properties.get(name) != Some(value) {
+                    return false;
+                }
+            }
+            if let Some(identity) = &filter.identity {
+                if awaited_action
+                    .maybe_origin_metadata()
+                    .map(|origin_metadata| &origin_metadata.identity)
+                    != Some(identity)
+                {
+                    return false;
+                }
+            }
         }
 
         {
@@ -607,6 +799,9 @@ where
                     let due_to_backpressure = err.code == Code::ResourceExhausted;
                     if !due_to_backpressure {
                         awaited_action.attempts += 1;
+                        if let Some(worker_id) = maybe_worker_id {
+                            awaited_action.record_failed_worker(worker_id.clone());
+                        }
                     }
 
                     if awaited_action.attempts > self.max_job_retries {
@@ -631,6 +826,8 @@ where
                 UpdateOperationType::UpdateWithDisconnect => ActionStage::Queued,
             };
             let now = (self.now_fn)().now();
+            let dwell_times = finished_stage_dwell_times(&awaited_action, &stage, now);
+            let action_info = awaited_action.action_info().clone();
             if matches!(stage, ActionStage::Queued) {
                 // If the action is queued, we need to unset the worker id regardless of
                 // which worker sent the update.
@@ -665,6 +862,14 @@ where
                 }
                 return Err(err);
             }
+            for (dwell_stage, dwell_time) in dwell_times {
+                self.stage_dwell_times.record(
+                    dwell_stage,
+                    action_info.unique_qualifier.instance_name(),
+                    action_info.priority,
+                    dwell_time,
+                );
+            }
             return Ok(());
         }
         Err(last_err.unwrap_or_else(|| {
@@ -676,6 +881,70 @@ where
         }))
     }
 
+    /// Returns the id of the cancelled operation, or None if the operation
+    /// had already finished.
+    async fn inner_cancel_operation(
+        &self,
+        client_operation_id: &OperationId,
+    ) -> Result<Option<OperationId>, Error> {
+        let mut last_err = None;
+        for _ in 0..MAX_UPDATE_RETRIES {
+            let awaited_action_subscriber = self
+                .action_db
+                .get_awaited_action_by_id(client_operation_id)
+                .await
+                .err_tip(|| "In SimpleSchedulerStateManager::cancel_operation")?
+                .ok_or_else(|| {
+                    make_err!(
+                        Code::NotFound,
+                        "Operation {client_operation_id} not found in SimpleSchedulerStateManager::cancel_operation"
+                    )
+                })?;
+            let mut awaited_action = awaited_action_subscriber
+                .borrow()
+                .await
+                .err_tip(|| "In SimpleSchedulerStateManager::cancel_operation")?;
+            if awaited_action.state().stage.is_finished() {
+                return Ok(None);
+            }
+            let operation_id = awaited_action.operation_id().clone();
+            let mut state = awaited_action.state().as_ref().clone();
+            state.stage = ActionStage::Completed(ActionResult {
+                execution_metadata: ExecutionMetadata {
+                    worker: awaited_action
+                        .worker_id()
+                        .map_or_else(String::default, ToString::to_string),
+                    ..ExecutionMetadata::default()
+                },
+                error: Some(make_err!(
+                    Code::Cancelled,
+                    "Operation {client_operation_id} was cancelled"
+                )),
+                ..ActionResult::default()
+            });
+            awaited_action.worker_set_state(Arc::new(state), (self.now_fn)().now());
+            match self
+                .action_db
+                .update_awaited_action(awaited_action)
+                .await
+                .err_tip(|| "In SimpleSchedulerStateManager::cancel_operation")
+            {
+                // Try again if there was a version mismatch.
+                Err(err) if err.code == Code::Aborted => {
+                    last_err = Some(err);
+                }
+                result => return result.map(|()| Some(operation_id)),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            make_err!(
+                Code::Internal,
+                "Failed to cancel action after {} retries with no error set",
+                MAX_UPDATE_RETRIES,
+            )
+        }))
+    }
+
     async fn inner_add_operation(
         &self,
         new_client_operation_id: OperationId,
@@ -699,18 +968,6 @@ where
     where
         F: Fn(T::Subscriber) -> Box<dyn ActionStateResult> + Send + Sync + 'a,
     {
-        const fn sorted_awaited_action_state_for_flags(
-            stage: OperationStageFlags,
-        ) -> Option<SortedAwaitedActionState> {
-            match stage {
-                OperationStageFlags::CacheCheck => Some(SortedAwaitedActionState::CacheCheck),
-                OperationStageFlags::Queued => Some(SortedAwaitedActionState::Queued),
-                OperationStageFlags::Executing => Some(SortedAwaitedActionState::Executing),
-                OperationStageFlags::Completed => Some(SortedAwaitedActionState::Completed),
-                _ => None,
-            }
-        }
-
         if let Some(operation_id) = &filter.operation_id {
             let maybe_subscriber = self
                 .action_db
@@ -835,6 +1092,29 @@ where
             });
         Ok(Box::pin(stream))
     }
+
+    async fn inner_count_operations(&self, filter: OperationFilter) -> Result<usize, Error> {
+        // A filter on a single stage alone can be answered by the database
+        // without loading every operation in that stage.
+        let is_stage_only_filter = filter
+            == OperationFilter {
+                stages: filter.stages,
+                ..Default::default()
+            };
+        if is_stage_only_filter {
+            if let Some(state) = sorted_awaited_action_state_for_flags(filter.stages) {
+                return self
+                    .action_db
+                    .count_actions(state)
+                    .await
+                    .err_tip(|| "In SimpleSchedulerStateManager::count_operations");
+            }
+        }
+        let stream = ClientStateManager::filter_operations(self, filter)
+            .await
+            .err_tip(|| "In SimpleSchedulerStateManager::count_operations")?;
+        Ok(stream.count().await)
+    }
 }
 
 #[async_trait]
@@ -876,6 +1156,16 @@ where
         .await
     }
 
+    async fn count_operations(&self, filter: OperationFilter) -> Result<usize, Error> {
+        self.inner_count_operations(filter).await
+    }
+
+    async fn cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error> {
+        self.inner_cancel_operation(client_operation_id)
+            .await
+            .map(|_| ())
+    }
+
     fn as_known_platform_property_provider(&self) -> Option<&dyn KnownPlatformPropertyProvider> {
         None
     }
@@ -936,4 +1226,11 @@ where
         self.inner_update_operation(operation_id, maybe_worker_id, update)
             .await
     }
+
+    async fn cancel_operation(
+        &self,
+        client_operation_id: &OperationId,
+    ) -> Result<Option<OperationId>, Error> {
+        self.inner_cancel_operation(client_operation_id).await
+    }
 }
@@ -0,0 +1,114 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::time::Duration;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use nativelink_metric::{
+    MetricFieldData, MetricKind, MetricPublishKnownKindData, MetricsComponent,
+};
+use nativelink_util::action_messages::DEFAULT_EXECUTION_PRIORITY;
+use nativelink_util::metrics_utils::DurationHistogram;
+use parking_lot::Mutex;
+
+/// A stage of an operation whose dwell time is recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DwellStage {
+    /// Looking up the action in the action cache.
+    CacheCheck,
+    /// Waiting for a worker to pick up the action.
+    Queued,
+    /// Running on a worker, excluding the upload of its outputs.
+    Executing,
+    /// Uploading the outputs of the action from the worker.
+    ResultUpload,
+}
+
+impl DwellStage {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::CacheCheck => "cache_check",
+            Self::Queued => "queued",
+            Self::Executing => "executing",
+            Self::ResultUpload => "result_upload",
+        }
+    }
+}
+
+/// Name of the priority class `priority` falls into.
+const fn priority_class(priority: i32) -> &'static str {
+    if priority < DEFAULT_EXECUTION_PRIORITY {
+        "low"
+    } else if priority > DEFAULT_EXECUTION_PRIORITY {
+        "high"
+    } else {
+        "default"
+    }
+}
+
+/// Stage -> instance name -> priority class -> histogram.
+type HistogramsByLabel =
+    HashMap<&'static str, HashMap<String, HashMap<&'static str, Arc<DurationHistogram>>>>;
+
+/// Histograms of the time operations spend in each stage, labeled by the
+/// instance name and priority class of the action. Histograms are created
+/// the first time a duration is recorded for their labels.
+#[derive(Debug, Default)]
+pub struct StageDwellTimes {
+    histograms: Mutex<HistogramsByLabel>,
+}
+
+impl StageDwellTimes {
+    /// Records that an action of `instance_name` with `priority` spent
+    /// `duration` in `stage`.
+    pub fn record(
+        &self,
+        stage: DwellStage,
+        instance_name: &str,
+        priority: i32,
+        duration: Duration,
+    ) {
+        self.histogram(stage, instance_name, priority)
+            .observe(duration);
+    }
+
+    /// Returns the histogram for the given labels, creating it if needed.
+    pub fn histogram(
+        &self,
+        stage: DwellStage,
+        instance_name: &str,
+        priority: i32,
+    ) -> Arc<DurationHistogram> {
+        self.histograms
+            .lock()
+            .entry(stage.as_str())
+            .or_default()
+            .entry(instance_name.to_string())
+            .or_default()
+            .entry(priority_class(priority))
+            .or_default()
+            .clone()
+    }
+}
+
+impl MetricsComponent for StageDwellTimes {
+    fn publish(
+        &self,
+        kind: MetricKind,
+        field_metadata: MetricFieldData,
+    ) -> Result<MetricPublishKnownKindData, nativelink_metric::Error> {
+        self.histograms.lock().publish(kind, field_metadata)
+    }
+}
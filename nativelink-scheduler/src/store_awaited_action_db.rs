@@ -18,6 +18,7 @@ use core::sync::atomic::{AtomicU64, Ordering};
 use core::time::Duration;
 use std::borrow::Cow;
 use std::sync::{Arc, Weak};
+use std::time::SystemTime;
 
 use bytes::Bytes;
 use futures::{Stream, TryStreamExt};
@@ -27,6 +28,7 @@ use nativelink_util::action_messages::{
     ActionInfo, ActionStage, ActionUniqueQualifier, OperationId,
 };
 use nativelink_util::instant_wrapper::InstantWrapper;
+use nativelink_util::metrics_utils::CounterWithTime;
 use nativelink_util::spawn;
 use nativelink_util::store_trait::{
     FalseValue, SchedulerCurrentVersionProvider, SchedulerIndexProvider, SchedulerStore,
@@ -34,6 +36,7 @@ use nativelink_util::store_trait::{
     SchedulerSubscription, SchedulerSubscriptionManager, StoreKey, TrueValue,
 };
 use nativelink_util::task::JoinHandleDropGuard;
+use serde::{Deserialize, Serialize};
 use tokio::sync::Notify;
 use tracing::error;
 
@@ -47,6 +50,10 @@ type ClientOperationId = OperationId;
 /// Maximum number of retries to update client keep alive.
 const MAX_RETRIES_FOR_CLIENT_KEEPALIVE: u32 = 8;
 
+/// How long to wait before looking again for the operation of a unique
+/// qualifier claimed by another scheduler which hasn't written it yet.
+const CLAIM_RETRY_DELAY: Duration = Duration::from_millis(10);
+
 enum OperationSubscriberState<Sub> {
     Unsubscribed,
     Subscribed(Sub),
@@ -292,6 +299,7 @@ fn awaited_action_decode(version: i64, data: &Bytes) -> Result<AwaitedAction, Er
 
 const OPERATION_ID_TO_AWAITED_ACTION_KEY_PREFIX: &str = "aa_";
 const CLIENT_ID_TO_OPERATION_ID_KEY_PREFIX: &str = "cid_";
+const UNIQUE_QUALIFIER_TO_CLAIM_KEY_PREFIX: &str = "uq_";
 
 #[derive(Debug)]
 struct OperationIdToAwaitedAction<'a>(Cow<'a, OperationId>);
@@ -338,6 +346,42 @@ impl SchedulerStoreDecodeTo for ClientIdToOperationId<'_> {
     }
 }
 
+/// The operation that executes a cacheable action. Schedulers write the
+/// claim of an action with a versioned update before creating its
+/// operation, so only one of them creates an operation when several add
+/// the same action at the same time.
+#[derive(Debug, Serialize, Deserialize)]
+struct UniqueQualifierClaim {
+    operation_id: OperationId,
+    claimed_at: SystemTime,
+    #[serde(skip)]
+    version: i64,
+}
+
+struct UniqueQualifierToClaim<'a>(&'a ActionUniqueQualifier);
+impl SchedulerStoreKeyProvider for UniqueQualifierToClaim<'_> {
+    type Versioned = TrueValue;
+    fn get_key(&self) -> StoreKey<'static> {
+        StoreKey::Str(Cow::Owned(format!(
+            "{UNIQUE_QUALIFIER_TO_CLAIM_KEY_PREFIX}{}",
+            self.0
+        )))
+    }
+}
+impl SchedulerStoreDecodeTo for UniqueQualifierToClaim<'_> {
+    type DecodeOutput = UniqueQualifierClaim;
+    fn decode(version: i64, data: Bytes) -> Result<Self::DecodeOutput, Error> {
+        let mut claim: UniqueQualifierClaim = serde_json::from_slice(&data).map_err(|e| {
+            make_input_err!(
+                "In UniqueQualifierToClaim::decode - {e:?} (data: {:02x?})",
+                data
+            )
+        })?;
+        claim.version = version;
+        Ok(claim)
+    }
+}
+
 // TODO(palfrey) We only need operation_id here, it would be nice if we had a way
 // to tell the decoder we only care about specific fields.
 struct SearchUniqueQualifierToAwaitedAction<'a>(&'a ActionUniqueQualifier);
@@ -428,6 +472,36 @@ impl SchedulerStoreDataProvider for UpdateOperationIdToAwaitedAction {
     }
 }
 
+struct UpdateUniqueQualifierClaim<'a> {
+    unique_qualifier: &'a ActionUniqueQualifier,
+    claim: UniqueQualifierClaim,
+    // The claim expires on its own, so a scheduler which dies while holding
+    // it doesn't keep others waiting for it.
+    expiry: Duration,
+}
+impl SchedulerCurrentVersionProvider for UpdateUniqueQualifierClaim<'_> {
+    fn current_version(&self) -> i64 {
+        self.claim.version
+    }
+}
+impl SchedulerStoreKeyProvider for UpdateUniqueQualifierClaim<'_> {
+    type Versioned = TrueValue;
+    fn get_key(&self) -> StoreKey<'static> {
+        UniqueQualifierToClaim(self.unique_qualifier).get_key()
+    }
+}
+impl SchedulerStoreDataProvider for UpdateUniqueQualifierClaim<'_> {
+    fn try_into_bytes(self) -> Result<Bytes, Error> {
+        serde_json::to_string(&self.claim)
+            .map(Bytes::from)
+            .map_err(|e| make_input_err!("Could not convert UniqueQualifierClaim to json - {e:?}"))
+    }
+
+    fn expiry(&self) -> Option<Duration> {
+        Some(self.expiry)
+    }
+}
+
 struct UpdateClientIdToOperationId {
     client_operation_id: ClientOperationId,
     operation_id: OperationId,
@@ -472,6 +546,119 @@ async fn inner_update_awaited_action(
     Ok(())
 }
 
+#[derive(Debug, Default, MetricsComponent)]
+struct CompactionMetrics {
+    #[metric(help = "The number of completed operations deleted by compaction.")]
+    reclaimed_awaited_actions: CounterWithTime,
+    #[metric(
+        help = "The number of client operation ids of deleted operations removed by compaction."
+    )]
+    reclaimed_client_operation_ids: CounterWithTime,
+    #[metric(
+        help = "The number of unique qualifier claims of deleted operations removed by compaction."
+    )]
+    reclaimed_unique_qualifier_claims: CounterWithTime,
+    #[metric(help = "The number of compaction passes that failed.")]
+    failed_compactions: CounterWithTime,
+}
+
+/// Deletes the completed operations that nobody used for longer than
+/// `retain_completed_for` with the claims of their unique qualifiers, then
+/// the client operation ids pointing to operations that no longer exist.
+async fn compact<S, I, NowFn>(
+    store: &S,
+    retain_completed_for: Duration,
+    now_fn: &NowFn,
+    metrics: &CompactionMetrics,
+) -> Result<(), Error>
+where
+    S: SchedulerStore,
+    I: InstantWrapper,
+    NowFn: Fn() -> I,
+{
+    let now = now_fn().now();
+    let completed_actions = store
+        .search_by_index_prefix(SearchStateToAwaitedAction(get_state_prefix(
+            SortedAwaitedActionState::Completed,
+        )))
+        .await
+        .err_tip(|| "In StoreAwaitedActionDb::compact")?;
+    tokio::pin!(completed_actions);
+    while let Some(awaited_action) = completed_actions
+        .try_next()
+        .await
+        .err_tip(|| "In StoreAwaitedActionDb::compact")?
+    {
+        let last_used_timestamp = awaited_action
+            .stage_entered_timestamp()
+            .unwrap_or_else(|| awaited_action.last_worker_updated_timestamp())
+            .max(awaited_action.last_client_keepalive_timestamp());
+        let expires_at = last_used_timestamp
+            .checked_add(retain_completed_for)
+            .unwrap_or(last_used_timestamp);
+        if expires_at > now {
+            continue;
+        }
+        if store
+            .delete_data(OperationIdToAwaitedAction(Cow::Borrowed(
+                awaited_action.operation_id(),
+            )))
+            .await
+            .err_tip(|| "In StoreAwaitedActionDb::compact")?
+        {
+            metrics.reclaimed_awaited_actions.inc();
+        }
+        let unique_qualifier = &awaited_action.action_info().unique_qualifier;
+        let claimed_by_operation = store
+            .get_and_decode(UniqueQualifierToClaim(unique_qualifier))
+            .await
+            .err_tip(|| "In StoreAwaitedActionDb::compact")?
+            .is_some_and(|claim| claim.operation_id == *awaited_action.operation_id());
+        if claimed_by_operation
+            && store
+                .delete_data(UniqueQualifierToClaim(unique_qualifier))
+                .await
+                .err_tip(|| "In StoreAwaitedActionDb::compact")?
+        {
+            metrics.reclaimed_unique_qualifier_claims.inc();
+        }
+    }
+
+    let client_id_keys = store
+        .list_keys_with_prefix(CLIENT_ID_TO_OPERATION_ID_KEY_PREFIX)
+        .await
+        .err_tip(|| "In StoreAwaitedActionDb::compact")?;
+    for client_id_key in client_id_keys {
+        let Some(client_operation_id) =
+            client_id_key.strip_prefix(CLIENT_ID_TO_OPERATION_ID_KEY_PREFIX)
+        else {
+            continue;
+        };
+        let client_operation_id = OperationId::String(client_operation_id.to_string());
+        let Some(operation_id) = store
+            .get_and_decode(ClientIdToOperationId(&client_operation_id))
+            .await
+            .err_tip(|| "In StoreAwaitedActionDb::compact")?
+        else {
+            continue;
+        };
+        let operation_exists = store
+            .get_and_decode(OperationIdToAwaitedAction(Cow::Owned(operation_id)))
+            .await
+            .err_tip(|| "In StoreAwaitedActionDb::compact")?
+            .is_some();
+        if !operation_exists
+            && store
+                .delete_data(ClientIdToOperationId(&client_operation_id))
+                .await
+                .err_tip(|| "In StoreAwaitedActionDb::compact")?
+        {
+            metrics.reclaimed_client_operation_ids.inc();
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, MetricsComponent)]
 pub struct StoreAwaitedActionDb<S, F, I, NowFn>
 where
@@ -483,7 +670,10 @@ where
     store: Arc<S>,
     now_fn: NowFn,
     operation_id_creator: F,
+    #[metric(group = "compaction")]
+    compaction_metrics: Arc<CompactionMetrics>,
     _pull_task_change_subscriber_spawn: JoinHandleDropGuard<()>,
+    _compaction_task: Option<JoinHandleDropGuard<()>>,
 }
 
 impl<S, F, I, NowFn> StoreAwaitedActionDb<S, F, I, NowFn>
@@ -531,10 +721,45 @@ where
             store,
             now_fn,
             operation_id_creator,
+            compaction_metrics: Arc::new(CompactionMetrics::default()),
             _pull_task_change_subscriber_spawn: pull_task_change_subscriber,
+            _compaction_task: None,
         })
     }
 
+    /// Deletes completed operations nobody used for longer than
+    /// `retain_completed_for`, and the client operation ids left pointing to
+    /// deleted operations, every `interval`.
+    #[must_use]
+    pub fn with_compaction(self, retain_completed_for: Duration, interval: Duration) -> Self {
+        let weak_store = Arc::downgrade(&self.store);
+        let now_fn = self.now_fn.clone();
+        let compaction_metrics = self.compaction_metrics.clone();
+        let compaction_task = spawn!("store_awaited_action_db_compaction", async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(store) = weak_store.upgrade() else {
+                    return; // Our struct was dropped.
+                };
+                if let Err(err) = compact(
+                    store.as_ref(),
+                    retain_completed_for,
+                    &now_fn,
+                    &compaction_metrics,
+                )
+                .await
+                {
+                    compaction_metrics.failed_compactions.inc();
+                    error!(?err, "Failed to compact awaited actions");
+                }
+            }
+        });
+        Self {
+            _compaction_task: Some(compaction_task),
+            ..self
+        }
+    }
+
     #[expect(clippy::future_not_send)] // TODO(jhpratt) remove this
     async fn try_subscribe(
         &self,
@@ -567,16 +792,8 @@ where
                 // If the existing job failed then we need to set back to queued or we get
                 // a version mismatch.  Equally we need to check the timeout as the job
                 // may be abandoned in the store.
-                let worker_should_update_before = (awaited_action.state().stage
-                    == ActionStage::Executing)
-                    .then_some(())
-                    .map(|()| awaited_action.last_worker_updated_timestamp())
-                    .and_then(|last_worker_updated| {
-                        last_worker_updated.checked_add(no_event_action_timeout)
-                    });
-                let awaited_action = if awaited_action.state().stage.is_finished()
-                    || worker_should_update_before
-                        .is_some_and(|timestamp| timestamp < (self.now_fn)().now())
+                let awaited_action = if self
+                    .is_finished_or_abandoned(&awaited_action, no_event_action_timeout)
                 {
                     tracing::debug!(
                         "Recreating action {:?} for operation {client_operation_id}",
@@ -601,6 +818,107 @@ where
         }
     }
 
+    /// Whether new clients can't subscribe to `awaited_action`, because it
+    /// finished or its worker stopped updating it.
+    fn is_finished_or_abandoned(
+        &self,
+        awaited_action: &AwaitedAction,
+        no_event_action_timeout: Duration,
+    ) -> bool {
+        let worker_should_update_before = (awaited_action.state().stage == ActionStage::Executing)
+            .then_some(())
+            .map(|()| awaited_action.last_worker_updated_timestamp())
+            .and_then(|last_worker_updated| {
+                last_worker_updated.checked_add(no_event_action_timeout)
+            });
+        awaited_action.state().stage.is_finished()
+            || worker_should_update_before
+                .is_some_and(|timestamp| timestamp < (self.now_fn)().now())
+    }
+
+    /// Claims the unique qualifier of the new operation `awaited_action`, so
+    /// schedulers adding the same action at the same time don't each create
+    /// an operation for it. Returns the action to subscribe to, which is the
+    /// operation of another scheduler if that one claimed it first, or
+    /// `None` if the claim has to be tried again.
+    #[expect(clippy::future_not_send)] // TODO(jhpratt) remove this
+    async fn claim_unique_qualifier(
+        &self,
+        awaited_action: AwaitedAction,
+        no_event_action_timeout: Duration,
+    ) -> Result<Option<AwaitedAction>, Error> {
+        let action_info = awaited_action.action_info().clone();
+        let unique_qualifier = &action_info.unique_qualifier;
+        if let ActionUniqueQualifier::Uncacheable(_) = unique_qualifier {
+            return Ok(Some(awaited_action));
+        }
+        let now = (self.now_fn)().now();
+        let maybe_claim = self
+            .store
+            .get_and_decode(UniqueQualifierToClaim(unique_qualifier))
+            .await
+            .err_tip(|| "In RedisAwaitedActionDb::claim_unique_qualifier")?;
+        let version = match maybe_claim {
+            Some(claim) => {
+                let maybe_claimed_action = self
+                    .store
+                    .get_and_decode(OperationIdToAwaitedAction(Cow::Borrowed(
+                        &claim.operation_id,
+                    )))
+                    .await
+                    .err_tip(|| "In RedisAwaitedActionDb::claim_unique_qualifier")?;
+                match maybe_claimed_action {
+                    Some(claimed_action)
+                        if !self
+                            .is_finished_or_abandoned(&claimed_action, no_event_action_timeout) =>
+                    {
+                        tracing::debug!(
+                            "Subscribing to claimed action {:?} for operation {}",
+                            action_info.digest(),
+                            claimed_action.operation_id()
+                        );
+                        return Ok(Some(claimed_action));
+                    }
+                    Some(_) => {}
+                    None => {
+                        // The scheduler which claimed the action may not have
+                        // written its operation yet. Only take the claim over
+                        // once that scheduler should have given up.
+                        let claim_expires_at =
+                            claim.claimed_at.checked_add(no_event_action_timeout);
+                        if claim_expires_at.is_none_or(|expires_at| now < expires_at) {
+                            tokio::time::sleep(CLAIM_RETRY_DELAY).await;
+                            return Ok(None);
+                        }
+                    }
+                }
+                claim.version
+            }
+            None => 0,
+        };
+        let claimed = self
+            .store
+            .update_data(UpdateUniqueQualifierClaim {
+                unique_qualifier,
+                claim: UniqueQualifierClaim {
+                    operation_id: awaited_action.operation_id().clone(),
+                    claimed_at: now,
+                    version,
+                },
+                expiry: no_event_action_timeout,
+            })
+            .await
+            .err_tip(|| "In RedisAwaitedActionDb::claim_unique_qualifier")?
+            .is_some();
+        if !claimed {
+            tracing::info!(
+                "Unique qualifier of {:?} claimed by another operation, retrying.",
+                action_info.digest()
+            );
+        }
+        Ok(claimed.then_some(awaited_action))
+    }
+
     #[expect(clippy::future_not_send)] // TODO(jhpratt) remove this
     async fn inner_get_awaited_action_by_id(
         &self,
@@ -664,7 +982,7 @@ where
     ) -> Result<Self::Subscriber, Error> {
         loop {
             // Check to see if the action is already known and subscribe if it is.
-            let mut awaited_action = self
+            let awaited_action = self
                 .try_subscribe(
                     &client_operation_id,
                     &action_info.unique_qualifier,
@@ -684,6 +1002,20 @@ where
                         (self.now_fn)().now(),
                     )
                 });
+            // New operations are only written once they hold the claim of
+            // their unique qualifier.
+            let mut awaited_action = if awaited_action.version() == 0 {
+                let Some(awaited_action) = self
+                    .claim_unique_qualifier(awaited_action, no_event_action_timeout)
+                    .await
+                    .err_tip(|| "In RedisAwaitedActionDb::add_action")?
+                else {
+                    continue;
+                };
+                awaited_action
+            } else {
+                awaited_action
+            };
 
             debug_assert!(
                 ActionStage::Queued == awaited_action.state().stage,
@@ -774,6 +1106,13 @@ where
             }))
     }
 
+    async fn count_actions(&self, state: SortedAwaitedActionState) -> Result<usize, Error> {
+        self.store
+            .count_by_index_prefix(SearchStateToAwaitedAction(get_state_prefix(state)))
+            .await
+            .err_tip(|| "In RedisAwaitedActionDb::count_actions")
+    }
+
     async fn get_all_awaited_actions(
         &self,
     ) -> Result<impl Stream<Item = Result<Self::Subscriber, Error>>, Error> {
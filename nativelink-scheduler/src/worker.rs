@@ -13,22 +13,29 @@
 // limitations under the License.
 
 use core::hash::{Hash, Hasher};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use nativelink_error::{Code, Error, ResultExt, make_err};
 use nativelink_metric::MetricsComponent;
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::{
-    ConnectionResult, StartExecute, UpdateForWorker, update_for_worker,
+    ConnectionResult, KillOperationRequest, PrefetchInputs, StartExecute, UpdateForWorker,
+    WorkerUtilization as ProtoWorkerUtilization, update_for_worker,
 };
 use nativelink_util::action_messages::{ActionInfo, OperationId, WorkerId};
+use nativelink_util::common::DigestInfo;
 use nativelink_util::metrics_utils::{AsyncCounterWrapper, CounterWithTime, FuncCounterWrapper};
 use nativelink_util::platform_properties::{PlatformProperties, PlatformPropertyValue};
+use nativelink_util::worker_features::{SUPPORTED_WORKER_FEATURES, WORKER_API_PROTOCOL_VERSION};
 use tokio::sync::mpsc::UnboundedSender;
 
 pub type WorkerTimestamp = u64;
 
+/// Reserved platform property an action sets to `true` to run alone on a
+/// worker. It is never matched against the properties of the workers.
+pub const EXCLUSIVE_PROPERTY_NAME: &str = "exclusive";
+
 /// Represents the action info and the platform properties of the action.
 /// These platform properties have the type of the properties as well as
 /// the value of the properties, unlike `ActionInfo`, which only has the
@@ -43,6 +50,50 @@ pub struct ActionInfoWithProps {
     pub platform_properties: PlatformProperties,
 }
 
+impl ActionInfoWithProps {
+    /// Returns true if the action must run alone on a worker.
+    pub fn is_exclusive(&self) -> bool {
+        self.inner
+            .platform_properties
+            .get(EXCLUSIVE_PROPERTY_NAME)
+            .is_some_and(|value| value == "true")
+    }
+}
+
+/// Resource utilization of a worker host, as last reported in a keep alive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, MetricsComponent)]
+pub struct WorkerUtilization {
+    #[metric(help = "Fraction of the host CPU capacity in use.")]
+    pub cpu_usage: f64,
+    #[metric(help = "Memory in use on the host in bytes.")]
+    pub memory_used_bytes: u64,
+    #[metric(help = "Total memory of the host in bytes.")]
+    pub memory_total_bytes: u64,
+    #[metric(help = "Disk space in use in the work directory filesystem in bytes.")]
+    pub disk_used_bytes: u64,
+    #[metric(help = "Total disk space of the work directory filesystem in bytes.")]
+    pub disk_total_bytes: u64,
+    #[metric(help = "Last time the worker reported its utilization.")]
+    pub reported_timestamp: WorkerTimestamp,
+}
+
+impl WorkerUtilization {
+    #[must_use]
+    pub const fn from_proto(
+        utilization: ProtoWorkerUtilization,
+        timestamp: WorkerTimestamp,
+    ) -> Self {
+        Self {
+            cpu_usage: utilization.cpu_usage,
+            memory_used_bytes: utilization.memory_used_bytes,
+            memory_total_bytes: utilization.memory_total_bytes,
+            disk_used_bytes: utilization.disk_used_bytes,
+            disk_total_bytes: utilization.disk_total_bytes,
+            reported_timestamp: timestamp,
+        }
+    }
+}
+
 /// Notifications to send worker about a requested state change.
 #[derive(Debug)]
 pub enum WorkerUpdate {
@@ -51,12 +102,21 @@ pub enum WorkerUpdate {
 
     /// Request that the worker is no longer in the pool and may discard any jobs.
     Disconnect,
+
+    /// Requests that the worker stop executing this operation.
+    KillOperation(OperationId),
+
+    /// Suggests that the worker download these directories ahead of time.
+    PrefetchInputs(Vec<DigestInfo>),
 }
 
 #[derive(Debug, MetricsComponent)]
 pub struct PendingActionInfoData {
     #[metric]
     pub action_info: ActionInfoWithProps,
+    /// Time by which the worker must have finished the action. Set the first
+    /// time the scheduler checks for overdue actions.
+    pub execution_deadline: Option<WorkerTimestamp>,
 }
 
 /// Represents a connection to a worker and used as the medium to
@@ -71,6 +131,26 @@ pub struct Worker {
     #[metric(group = "platform_properties")]
     pub platform_properties: PlatformProperties,
 
+    /// Platform properties the worker advertised when it connected. Unlike
+    /// `platform_properties`, minimum values here are not reduced by the
+    /// running actions, so they describe the total capacity of the worker.
+    capacity: PlatformProperties,
+
+    /// Operator-assigned labels of this worker. These are informational
+    /// only and are never used to match actions to workers.
+    #[metric(group = "labels")]
+    pub labels: HashMap<String, String>,
+
+    /// Version of the worker API protocol the worker speaks. Zero for
+    /// workers that predate protocol versioning.
+    #[metric(help = "The worker API protocol version of the worker.")]
+    pub protocol_version: u32,
+
+    /// Optional worker API features the worker advertised when it
+    /// connected.
+    #[metric(group = "features")]
+    pub features: BTreeSet<String>,
+
     /// Channel to send commands from scheduler to worker.
     pub tx: UnboundedSender<UpdateForWorker>,
 
@@ -84,6 +164,10 @@ pub struct Worker {
     #[metric(help = "Last time this worker was communicated with.")]
     pub last_update_timestamp: WorkerTimestamp,
 
+    /// Resource utilization last reported by the worker, if it reports any.
+    #[metric(group = "utilization")]
+    pub utilization: Option<WorkerUtilization>,
+
     /// Whether the worker rejected the last action due to back pressure.
     #[metric(help = "If the worker is paused.")]
     pub is_paused: bool,
@@ -92,6 +176,40 @@ pub struct Worker {
     #[metric(help = "If the worker is draining.")]
     pub is_draining: bool,
 
+    /// Timestamp after which a draining worker is removed from the pool and
+    /// the operations still running on it are requeued, if set.
+    #[metric(help = "Time after which the draining worker is removed from the pool.")]
+    pub drain_deadline_timestamp: Option<WorkerTimestamp>,
+
+    /// Whether the worker was quarantined after too many failed actions.
+    #[metric(help = "If the worker is quarantined.")]
+    pub is_quarantined: bool,
+
+    /// Whether the worker takes no new actions so that an exclusive action
+    /// can run on it once its running actions finished. Reservations only
+    /// last for one matching pass.
+    #[metric(help = "If the worker is reserved for an exclusive action.")]
+    pub is_reserved_for_exclusive_action: bool,
+
+    /// Number of actions that failed in a row on this worker.
+    consecutive_failures: usize,
+
+    /// Timestamp after which a quarantined worker may receive actions again.
+    quarantine_expires_timestamp: WorkerTimestamp,
+
+    /// Operations that were preempted on this worker. The worker may still
+    /// send updates for these until it notices the kill request, and those
+    /// updates must be ignored.
+    preempted_operation_ids: HashSet<OperationId>,
+
+    /// Input roots of the actions most recently started on this worker, most
+    /// recent first.
+    recent_input_root_digests: VecDeque<DigestInfo>,
+
+    /// Position of the worker in the allocation order of the whole worker
+    /// pool; the most recently used worker has the highest value.
+    pub(crate) last_used_sequence: u64,
+
     /// Stats about the worker.
     #[metric]
     metrics: Arc<Metrics>,
@@ -125,6 +243,23 @@ fn reduce_platform_properties(
     }
 }
 
+/// Gives back the platform properties reserved by `reduce_platform_properties`.
+pub(crate) fn restore_platform_properties(
+    parent_props: &mut PlatformProperties,
+    restore_props: &PlatformProperties,
+) {
+    for (property, prop_value) in &restore_props.properties {
+        if let PlatformPropertyValue::Minimum(value) = prop_value {
+            let worker_props = &mut parent_props.properties;
+            if let PlatformPropertyValue::Minimum(worker_value) =
+                worker_props.get_mut(property).unwrap()
+            {
+                *worker_value += value;
+            }
+        }
+    }
+}
+
 impl Worker {
     pub fn new(
         id: WorkerId,
@@ -134,12 +269,25 @@ impl Worker {
     ) -> Self {
         Self {
             id,
+            capacity: platform_properties.clone(),
             platform_properties,
+            labels: HashMap::new(),
+            protocol_version: 0,
+            features: BTreeSet::new(),
             tx,
             running_action_infos: HashMap::new(),
             last_update_timestamp: timestamp,
+            utilization: None,
             is_paused: false,
             is_draining: false,
+            drain_deadline_timestamp: None,
+            is_quarantined: false,
+            is_reserved_for_exclusive_action: false,
+            consecutive_failures: 0,
+            quarantine_expires_timestamp: 0,
+            preempted_operation_ids: HashSet::new(),
+            recent_input_root_digests: VecDeque::new(),
+            last_used_sequence: 0,
             metrics: Arc::new(Metrics {
                 connected_timestamp: SystemTime::now()
                     .duration_since(UNIX_EPOCH)
@@ -149,6 +297,7 @@ impl Worker {
                 run_action: AsyncCounterWrapper::default(),
                 keep_alive: FuncCounterWrapper::default(),
                 notify_disconnect: CounterWithTime::default(),
+                prefetch_inputs: CounterWithTime::default(),
             }),
         }
     }
@@ -160,11 +309,24 @@ impl Worker {
             &self.tx,
             update_for_worker::Update::ConnectionResult(ConnectionResult {
                 worker_id: self.id.clone().into(),
+                protocol_version: WORKER_API_PROTOCOL_VERSION,
+                features: self
+                    .features
+                    .iter()
+                    .filter(|feature| self.supports_feature(feature))
+                    .cloned()
+                    .collect(),
             }),
         )
         .err_tip(|| format!("Failed to send ConnectionResult to worker : {}", self.id))
     }
 
+    /// Returns true if the worker advertised the feature and this build of
+    /// the scheduler supports it too.
+    pub fn supports_feature(&self, feature: &str) -> bool {
+        SUPPORTED_WORKER_FEATURES.contains(&feature) && self.features.contains(feature)
+    }
+
     /// Notifies the worker of a requested state change.
     pub async fn notify_update(&mut self, worker_update: WorkerUpdate) -> Result<(), Error> {
         match worker_update {
@@ -175,6 +337,21 @@ impl Worker {
                 self.metrics.notify_disconnect.inc();
                 send_msg_to_worker(&self.tx, update_for_worker::Update::Disconnect(()))
             }
+            WorkerUpdate::KillOperation(operation_id) => send_msg_to_worker(
+                &self.tx,
+                update_for_worker::Update::KillOperationRequest(KillOperationRequest {
+                    operation_id: operation_id.to_string(),
+                }),
+            ),
+            WorkerUpdate::PrefetchInputs(directory_digests) => {
+                self.metrics.prefetch_inputs.inc();
+                send_msg_to_worker(
+                    &self.tx,
+                    update_for_worker::Update::PrefetchInputs(PrefetchInputs {
+                        directory_digests: directory_digests.into_iter().map(Into::into).collect(),
+                    }),
+                )
+            }
         }
     }
 
@@ -195,6 +372,7 @@ impl Worker {
         let tx = &mut self.tx;
         let worker_platform_properties = &mut self.platform_properties;
         let running_action_infos = &mut self.running_action_infos;
+        let preempted_operation_ids = &mut self.preempted_operation_ids;
         let worker_id = self.id.clone().into();
         self.metrics
             .run_action
@@ -212,7 +390,14 @@ impl Worker {
                     worker_platform_properties,
                     &action_info.platform_properties,
                 );
-                running_action_infos.insert(operation_id, PendingActionInfoData { action_info });
+                preempted_operation_ids.remove(&operation_id);
+                running_action_infos.insert(
+                    operation_id,
+                    PendingActionInfoData {
+                        action_info,
+                        execution_deadline: None,
+                    },
+                );
 
                 send_msg_to_worker(tx, update_for_worker::Update::StartAction(start_execute))
             })
@@ -235,25 +420,148 @@ impl Worker {
         Ok(())
     }
 
+    /// Stops tracking the operation as running on this worker and asks the
+    /// worker to kill it. Any further updates the worker sends for this
+    /// operation are ignored, see `is_preempted`.
+    pub(crate) async fn preempt_action(&mut self, operation_id: &OperationId) -> Result<(), Error> {
+        let pending_action_info = self.running_action_infos.remove(operation_id).err_tip(|| {
+            format!(
+                "Worker {} tried to preempt operation {} that was not running",
+                self.id, operation_id
+            )
+        })?;
+        self.restore_platform_properties(&pending_action_info.action_info.platform_properties);
+        self.preempted_operation_ids.insert(operation_id.clone());
+        self.notify_update(WorkerUpdate::KillOperation(operation_id.clone()))
+            .await
+    }
+
+    /// Returns true if the operation was preempted on this worker.
+    pub(crate) fn is_preempted(&self, operation_id: &OperationId) -> bool {
+        self.preempted_operation_ids.contains(operation_id)
+    }
+
+    /// Forgets about a preempted operation once the worker has reported
+    /// that it finished.
+    pub(crate) fn clear_preempted(&mut self, operation_id: &OperationId) {
+        self.preempted_operation_ids.remove(operation_id);
+    }
+
+    /// Remembers that an action with this input root was started on the
+    /// worker, or that the worker was asked to prefetch it, keeping at most `max_size` of the most recent input roots.
+    pub(crate) fn record_input_root(&mut self, input_root_digest: DigestInfo, max_size: usize) {
+        self.recent_input_root_digests
+            .retain(|digest| *digest != input_root_digest);
+        self.recent_input_root_digests.push_front(input_root_digest);
+        self.recent_input_root_digests.truncate(max_size);
+    }
+
+    /// Returns true if an action with this input root was recently started
+    /// on the worker, or the worker was recently asked to prefetch it.
+    pub fn has_recent_input_root(&self, input_root_digest: &DigestInfo) -> bool {
+        self.recent_input_root_digests.contains(input_root_digest)
+    }
+
+    /// Returns true if the worker runs an exclusive action, so it can not
+    /// take any other action.
+    pub fn is_running_exclusive_action(&self) -> bool {
+        self.running_action_infos
+            .values()
+            .any(|pending_action_info| pending_action_info.action_info.is_exclusive())
+    }
+
+    /// Returns true if exclusivity allows the worker to take an action:
+    /// an exclusive action needs a worker without running actions, and no
+    /// action is added to a worker running or reserved for an exclusive one.
+    pub fn allows_exclusivity(&self, exclusive: bool) -> bool {
+        if exclusive {
+            !self.has_actions()
+        } else {
+            !self.is_reserved_for_exclusive_action && !self.is_running_exclusive_action()
+        }
+    }
+
+    /// Returns true if the worker could run an action requiring
+    /// `platform_properties` once it has no running actions.
+    pub fn could_run_when_idle(&self, platform_properties: &PlatformProperties) -> bool {
+        platform_properties.is_satisfied_by(&self.capacity)
+    }
+
+    /// Returns the fraction of the worker's consumable resources that would
+    /// still be free after it starts an action with `platform_properties`,
+    /// averaged over the minimum properties of the worker. Workers without
+    /// consumable resources are always considered fully free.
+    pub fn free_capacity_after(&self, platform_properties: &PlatformProperties) -> f64 {
+        let mut total_fraction = 0.0;
+        let mut resource_count = 0;
+        for (property, capacity_value) in &self.capacity.properties {
+            let PlatformPropertyValue::Minimum(capacity) = capacity_value else {
+                continue;
+            };
+            if *capacity == 0 {
+                continue;
+            }
+            let Some(PlatformPropertyValue::Minimum(available)) =
+                self.platform_properties.properties.get(property)
+            else {
+                continue;
+            };
+            let requested = match platform_properties.properties.get(property) {
+                Some(PlatformPropertyValue::Minimum(requested)) => *requested,
+                _ => 0,
+            };
+            total_fraction += available.saturating_sub(requested) as f64 / *capacity as f64;
+            resource_count += 1;
+        }
+        if resource_count == 0 {
+            return 1.0;
+        }
+        total_fraction / f64::from(resource_count)
+    }
+
     pub fn has_actions(&self) -> bool {
         !self.running_action_infos.is_empty()
     }
 
     fn restore_platform_properties(&mut self, props: &PlatformProperties) {
-        for (property, prop_value) in &props.properties {
-            if let PlatformPropertyValue::Minimum(value) = prop_value {
-                let worker_props = &mut self.platform_properties.properties;
-                if let PlatformPropertyValue::Minimum(worker_value) =
-                    worker_props.get_mut(property).unwrap()
-                {
-                    *worker_value += value;
-                }
-            }
+        restore_platform_properties(&mut self.platform_properties, props);
+    }
+
+    /// Tracks actions failing in a row and quarantines the worker for
+    /// `cooldown_s` once `failure_threshold` is reached. Returns true if the
+    /// worker was quarantined by this call.
+    pub(crate) fn record_action_outcome(
+        &mut self,
+        failed: bool,
+        failure_threshold: usize,
+        cooldown_s: u64,
+    ) -> bool {
+        if !failed {
+            self.consecutive_failures = 0;
+            return false;
+        }
+        self.consecutive_failures += 1;
+        if self.is_quarantined || self.consecutive_failures < failure_threshold {
+            return false;
+        }
+        self.is_quarantined = true;
+        self.quarantine_expires_timestamp = self.last_update_timestamp + cooldown_s;
+        true
+    }
+
+    /// Lifts the quarantine if it expired at `timestamp`. Returns true if the
+    /// worker left quarantine.
+    pub(crate) const fn maybe_lift_quarantine(&mut self, timestamp: WorkerTimestamp) -> bool {
+        if !self.is_quarantined || timestamp < self.quarantine_expires_timestamp {
+            return false;
         }
+        self.is_quarantined = false;
+        self.consecutive_failures = 0;
+        true
     }
 
     pub const fn can_accept_work(&self) -> bool {
-        !self.is_paused && !self.is_draining
+        !self.is_paused && !self.is_draining && !self.is_quarantined
     }
 }
 
@@ -283,4 +591,6 @@ struct Metrics {
     keep_alive: FuncCounterWrapper,
     #[metric(help = "The number of notify_disconnect sent to this worker.")]
     notify_disconnect: CounterWithTime,
+    #[metric(help = "The number of prefetch_inputs hints sent to this worker.")]
+    prefetch_inputs: CounterWithTime,
 }
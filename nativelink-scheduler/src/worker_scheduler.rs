@@ -12,15 +12,65 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeMap;
+
 use async_trait::async_trait;
-use nativelink_error::Error;
+use nativelink_error::{Code, Error, make_err};
 use nativelink_metric::RootMetricsComponent;
 use nativelink_util::action_messages::{OperationId, WorkerId};
 use nativelink_util::operation_state_manager::UpdateOperationType;
 use nativelink_util::shutdown_guard::ShutdownGuard;
+use serde::Serialize;
 
 use crate::platform_property_manager::PlatformPropertyManager;
-use crate::worker::{Worker, WorkerTimestamp};
+use crate::worker::{Worker, WorkerTimestamp, WorkerUtilization};
+
+/// Whether a worker can run an action, and if not, why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum WorkerPlacementStatus {
+    /// The worker can run the action now.
+    Available,
+    /// The worker is paused.
+    Paused,
+    /// The worker is draining.
+    Draining,
+    /// The worker is quarantined after failing too many actions.
+    Quarantined,
+    /// The worker runs or is reserved for an exclusive action.
+    Exclusive,
+    /// The action already failed on the worker, so other workers are
+    /// preferred.
+    PreviouslyFailed,
+    /// The worker does not advertise `property`.
+    MissingProperty { property: String },
+    /// The value the worker advertises for `property` does not satisfy the
+    /// action.
+    PropertyMismatch { property: String },
+    /// The worker could run the action once the actions running on it give
+    /// back enough of `property`.
+    Busy { property: String },
+}
+
+/// The `WorkerPlacementStatus` of a single worker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WorkerPlacement {
+    pub worker_id: WorkerId,
+    #[serde(flatten)]
+    pub status: WorkerPlacementStatus,
+}
+
+/// Explains why an operation is or is not being dispatched to a worker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlacementExplanation {
+    pub operation_id: OperationId,
+    /// The stage the operation is in, only queued operations are dispatched.
+    pub stage: String,
+    /// The platform properties the action requests.
+    pub platform_properties: BTreeMap<String, String>,
+    /// Every worker known to the scheduler.
+    pub workers: Vec<WorkerPlacement>,
+}
 
 /// WorkerScheduler interface is responsible for interactions between the scheduler
 /// and worker related operations.
@@ -47,6 +97,13 @@ pub trait WorkerScheduler: Sync + Send + Unpin + RootMetricsComponent + 'static
         timestamp: WorkerTimestamp,
     ) -> Result<(), Error>;
 
+    /// Stores the resource utilization the worker reported.
+    async fn update_worker_utilization(
+        &self,
+        worker_id: &WorkerId,
+        utilization: WorkerUtilization,
+    ) -> Result<(), Error>;
+
     /// Removes worker from pool and reschedule any tasks that might be running on it.
     async fn remove_worker(&self, worker_id: &WorkerId) -> Result<(), Error>;
 
@@ -59,4 +116,39 @@ pub trait WorkerScheduler: Sync + Send + Unpin + RootMetricsComponent + 'static
 
     /// Sets if the worker is draining or not.
     async fn set_drain_worker(&self, worker_id: &WorkerId, is_draining: bool) -> Result<(), Error>;
+
+    /// Sets the worker draining. Once `deadline` passes, the worker is removed
+    /// from the pool and the operations still running on it are requeued.
+    async fn drain_worker_with_deadline(
+        &self,
+        worker_id: &WorkerId,
+        deadline: WorkerTimestamp,
+    ) -> Result<(), Error>;
+
+    /// Explains which workers could run the operation and why the others
+    /// can not.
+    async fn explain_placement(
+        &self,
+        operation_id: &OperationId,
+    ) -> Result<PlacementExplanation, Error> {
+        Err(make_err!(
+            Code::Unimplemented,
+            "Placement explanations are not supported by this scheduler, requested for {operation_id}"
+        ))
+    }
+
+    /// Sets if the scheduler is draining or not. A draining scheduler keeps
+    /// queueing new operations and running the executing ones, but does
+    /// not match queued operations to workers anymore.
+    fn set_draining(&self, _is_draining: bool) -> Result<(), Error> {
+        Err(make_err!(
+            Code::Unimplemented,
+            "Draining is not supported by this scheduler"
+        ))
+    }
+
+    /// Returns true if the scheduler is draining.
+    fn is_draining(&self) -> bool {
+        false
+    }
 }
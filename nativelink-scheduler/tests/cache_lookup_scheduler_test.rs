@@ -43,23 +43,30 @@ use utils::scheduler_utils::{TokioWatchActionStateResult, make_base_action_info}
 struct TestContext {
     mock_scheduler: Arc<MockActionScheduler>,
     ac_store: Store,
+    cas_store: Store,
     cache_scheduler: CacheLookupScheduler,
 }
 
-fn make_cache_scheduler() -> Result<TestContext, Error> {
+fn make_cache_scheduler(verify_outputs: bool) -> Result<TestContext, Error> {
     let mock_scheduler = Arc::new(MockActionScheduler::new());
     let ac_store = Store::new(MemoryStore::new(&MemorySpec::default()));
-    let cache_scheduler = CacheLookupScheduler::new(ac_store.clone(), mock_scheduler.clone())?;
+    let cas_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let cache_scheduler = CacheLookupScheduler::new(
+        ac_store.clone(),
+        verify_outputs.then(|| cas_store.clone()),
+        mock_scheduler.clone(),
+    )?;
     Ok(TestContext {
         mock_scheduler,
         ac_store,
+        cas_store,
         cache_scheduler,
     })
 }
 
 #[nativelink_test]
 async fn add_action_handles_skip_cache() -> Result<(), Error> {
-    let context = make_cache_scheduler()?;
+    let context = make_cache_scheduler(false)?;
     let action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest());
     let action_result = ProtoActionResult::try_from(ActionResult::default())?;
     context
@@ -94,9 +101,77 @@ async fn add_action_handles_skip_cache() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn add_action_returns_verified_cache_hit() -> Result<(), Error> {
+    const STDOUT: &str = "stdout";
+    let context = make_cache_scheduler(true)?;
+    let action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest());
+    let stdout_digest = DigestInfo::new([1u8; 32], STDOUT.len() as u64);
+    context
+        .cas_store
+        .update_oneshot(stdout_digest, STDOUT.into())
+        .await?;
+    let action_result = ProtoActionResult {
+        stdout_digest: Some(stdout_digest.into()),
+        ..Default::default()
+    };
+    context
+        .ac_store
+        .update_oneshot(action_info.digest(), action_result.encode_to_vec().into())
+        .await?;
+    let (action_state, _) = context
+        .cache_scheduler
+        .add_action(OperationId::default(), action_info)
+        .await?
+        .as_state()
+        .await?;
+    assert_eq!(
+        ActionStage::CompletedFromCache(action_result),
+        action_state.stage
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn add_action_executes_when_cached_outputs_are_missing() -> Result<(), Error> {
+    let context = make_cache_scheduler(true)?;
+    let action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest());
+    let action_result = ProtoActionResult {
+        stdout_digest: Some(DigestInfo::new([1u8; 32], 6).into()),
+        ..Default::default()
+    };
+    context
+        .ac_store
+        .update_oneshot(action_info.digest(), action_result.encode_to_vec().into())
+        .await?;
+    let (_forward_watch_channel_tx, forward_watch_channel_rx) =
+        watch::channel(Arc::new(ActionState {
+            client_operation_id: OperationId::default(),
+            stage: ActionStage::Queued,
+            action_digest: action_info.unique_qualifier.digest(),
+        }));
+    let client_operation_id = OperationId::default();
+    let (result, (passed_client_operation_id, _)) = join!(
+        context
+            .cache_scheduler
+            .add_action(client_operation_id.clone(), action_info.clone()),
+        context
+            .mock_scheduler
+            .expect_add_action(Ok(Box::new(TokioWatchActionStateResult::new(
+                client_operation_id.clone(),
+                action_info,
+                forward_watch_channel_rx
+            ))))
+    );
+    let (action_state, _) = result?.as_state().await?;
+    assert_eq!(ActionStage::Queued, action_state.stage);
+    assert_eq!(client_operation_id, passed_client_operation_id);
+    Ok(())
+}
+
 #[nativelink_test]
 async fn find_by_client_operation_id_call_passed() -> Result<(), Error> {
-    let context = make_cache_scheduler()?;
+    let context = make_cache_scheduler(false)?;
     let client_operation_id = OperationId::default();
     let (actual_result, actual_filter) = join!(
         context.cache_scheduler.filter_operations(OperationFilter {
@@ -22,8 +22,9 @@ mod utils {
 
 use futures::{StreamExt, join};
 use nativelink_config::schedulers::{
-    PlatformPropertyAddition, PlatformPropertyReplacement, PropertyModification,
-    PropertyModifierSpec, SchedulerSpec, SimpleSpec,
+    PlatformPropertyAddition, PlatformPropertyReplacement, PlatformPropertyRewrite,
+    PlatformPropertyTemplate, PropertyModification, PropertyModifierSpec, SchedulerSpec,
+    SimpleSpec,
 };
 use nativelink_error::Error;
 use nativelink_macro::nativelink_test;
@@ -48,7 +49,8 @@ fn make_modifier_scheduler(modifications: Vec<PropertyModification>) -> TestCont
         modifications,
         scheduler: Box::new(SchedulerSpec::Simple(SimpleSpec::default())),
     };
-    let modifier_scheduler = PropertyModifierScheduler::new(&config, mock_scheduler.clone());
+    let modifier_scheduler =
+        PropertyModifierScheduler::new(&config, mock_scheduler.clone()).unwrap();
     TestContext {
         mock_scheduler,
         modifier_scheduler,
@@ -379,6 +381,178 @@ async fn add_action_property_remove() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn add_action_property_rewrite() -> Result<(), Error> {
+    let name = "container-image".to_string();
+    let context = make_modifier_scheduler(vec![PropertyModification::Rewrite(
+        PlatformPropertyRewrite {
+            name: name.clone(),
+            pattern: "^docker://(?<image>[a-z]+):latest$".to_string(),
+            replacement: "docker://${image}@sha256:abc".to_string(),
+            new_name: None,
+        },
+    )]);
+    let mut action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest());
+    Arc::make_mut(&mut action_info)
+        .platform_properties
+        .insert(name.clone(), "docker://foo:latest".to_string());
+    let (_forward_watch_channel_tx, forward_watch_channel_rx) =
+        watch::channel(Arc::new(ActionState {
+            client_operation_id: OperationId::default(),
+            stage: ActionStage::Queued,
+            action_digest: action_info.unique_qualifier.digest(),
+        }));
+    let client_operation_id = OperationId::default();
+    let (_, (passed_client_operation_id, action_info)) = join!(
+        context
+            .modifier_scheduler
+            .add_action(client_operation_id.clone(), action_info.clone()),
+        context
+            .mock_scheduler
+            .expect_add_action(Ok(Box::new(TokioWatchActionStateResult::new(
+                client_operation_id.clone(),
+                action_info,
+                forward_watch_channel_rx
+            )))),
+    );
+    assert_eq!(client_operation_id, passed_client_operation_id);
+    assert_eq!(
+        HashMap::from([(name, "docker://foo@sha256:abc".to_string())]),
+        action_info.platform_properties
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn add_action_property_rewrite_no_match() -> Result<(), Error> {
+    let name = "container-image".to_string();
+    let value = "docker://foo:1.0".to_string();
+    let context = make_modifier_scheduler(vec![PropertyModification::Rewrite(
+        PlatformPropertyRewrite {
+            name: name.clone(),
+            pattern: ":latest$".to_string(),
+            replacement: ":1.0".to_string(),
+            new_name: Some("new_name".to_string()),
+        },
+    )]);
+    let mut action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest());
+    Arc::make_mut(&mut action_info)
+        .platform_properties
+        .insert(name.clone(), value.clone());
+    let (_forward_watch_channel_tx, forward_watch_channel_rx) =
+        watch::channel(Arc::new(ActionState {
+            client_operation_id: OperationId::default(),
+            stage: ActionStage::Queued,
+            action_digest: action_info.unique_qualifier.digest(),
+        }));
+    let client_operation_id = OperationId::default();
+    let (_, (passed_client_operation_id, action_info)) = join!(
+        context
+            .modifier_scheduler
+            .add_action(client_operation_id.clone(), action_info.clone()),
+        context
+            .mock_scheduler
+            .expect_add_action(Ok(Box::new(TokioWatchActionStateResult::new(
+                client_operation_id.clone(),
+                action_info,
+                forward_watch_channel_rx
+            )))),
+    );
+    assert_eq!(client_operation_id, passed_client_operation_id);
+    assert_eq!(
+        HashMap::from([(name, value)]),
+        action_info.platform_properties
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn add_action_property_template() -> Result<(), Error> {
+    let context = make_modifier_scheduler(vec![
+        PropertyModification::Rewrite(PlatformPropertyRewrite {
+            name: "OSFamily".to_string(),
+            pattern: "^.*$".to_string(),
+            replacement: "$0".to_string(),
+            new_name: Some("os".to_string()),
+        }),
+        PropertyModification::Template(PlatformPropertyTemplate {
+            name: "pool".to_string(),
+            template: "{{{os}-{ISA}}}".to_string(),
+        }),
+        PropertyModification::Template(PlatformPropertyTemplate {
+            name: "unset".to_string(),
+            template: "{missing}".to_string(),
+        }),
+    ]);
+    let mut action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest());
+    Arc::make_mut(&mut action_info).platform_properties.extend([
+        ("OSFamily".to_string(), "linux".to_string()),
+        ("ISA".to_string(), "x86-64".to_string()),
+    ]);
+    let (_forward_watch_channel_tx, forward_watch_channel_rx) =
+        watch::channel(Arc::new(ActionState {
+            client_operation_id: OperationId::default(),
+            stage: ActionStage::Queued,
+            action_digest: action_info.unique_qualifier.digest(),
+        }));
+    let client_operation_id = OperationId::default();
+    let (_, (passed_client_operation_id, action_info)) = join!(
+        context
+            .modifier_scheduler
+            .add_action(client_operation_id.clone(), action_info.clone()),
+        context
+            .mock_scheduler
+            .expect_add_action(Ok(Box::new(TokioWatchActionStateResult::new(
+                client_operation_id.clone(),
+                action_info,
+                forward_watch_channel_rx
+            )))),
+    );
+    assert_eq!(client_operation_id, passed_client_operation_id);
+    assert_eq!(
+        HashMap::from([
+            ("OSFamily".to_string(), "linux".to_string()),
+            ("ISA".to_string(), "x86-64".to_string()),
+            ("os".to_string(), "linux".to_string()),
+            ("pool".to_string(), "{linux-x86-64}".to_string()),
+        ]),
+        action_info.platform_properties
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn invalid_modifications_are_rejected() -> Result<(), Error> {
+    let make_spec = |modification| PropertyModifierSpec {
+        modifications: vec![modification],
+        scheduler: Box::new(SchedulerSpec::Simple(SimpleSpec::default())),
+    };
+    let mock_scheduler = Arc::new(MockActionScheduler::new());
+    assert!(
+        PropertyModifierScheduler::new(
+            &make_spec(PropertyModification::Rewrite(PlatformPropertyRewrite {
+                name: "name".to_string(),
+                pattern: "(".to_string(),
+                replacement: String::new(),
+                new_name: None,
+            })),
+            mock_scheduler.clone(),
+        )
+        .is_err()
+    );
+    assert!(
+        PropertyModifierScheduler::new(
+            &make_spec(PropertyModification::Template(PlatformPropertyTemplate {
+                name: "name".to_string(),
+                template: "{unterminated".to_string(),
+            })),
+            mock_scheduler,
+        )
+        .is_err()
+    );
+    Ok(())
+}
+
 #[nativelink_test]
 async fn find_by_client_operation_id_call_passed() -> Result<(), Error> {
     let context = make_modifier_scheduler(vec![]);
@@ -40,7 +40,7 @@ use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::
     ConnectionResult, StartExecute, UpdateForWorker, update_for_worker,
 };
 use nativelink_scheduler::awaited_action_db::{
-    AwaitedAction, AwaitedActionDb, AwaitedActionSubscriber,
+    AwaitedAction, AwaitedActionDb, AwaitedActionSubscriber, SortedAwaitedActionState,
 };
 use nativelink_scheduler::simple_scheduler::SimpleScheduler;
 use nativelink_scheduler::store_awaited_action_db::StoreAwaitedActionDb;
@@ -56,6 +56,7 @@ use nativelink_util::instant_wrapper::MockInstantWrapped;
 use nativelink_util::operation_state_manager::{ClientStateManager, OperationFilter};
 use nativelink_util::platform_properties::PlatformProperties;
 use nativelink_util::store_trait::{SchedulerStore, SchedulerSubscriptionManager};
+use nativelink_util::worker_features::WORKER_API_PROTOCOL_VERSION;
 use parking_lot::Mutex;
 use pretty_assertions::assert_eq;
 use tokio::sync::{Notify, mpsc};
@@ -69,9 +70,12 @@ mod utils {
 const INSTANCE_NAME: &str = "instance_name";
 const TEMP_UUID: &str = "550e8400-e29b-41d4-a716-446655440000";
 const SCRIPT_VERSION: &str = "3e762c15";
-const VERSION_SCRIPT_HASH: &str = "b22b9926cbce9dd9ba97fa7ba3626f89feea1ed5";
+const VERSION_SCRIPT_HASH: &str = "43055fd93797bd858d171317f533cdb46dba1cae";
+const SET_SCRIPT_HASH: &str = "84448c360c53b2d7f1031483c97c9523941db183";
 const MAX_CHUNK_UPLOADS_PER_UPDATE: usize = 10;
 const SCAN_COUNT: u32 = 10_000;
+const UNIQUE_QUALIFIER_CLAIM_KEY: &str =
+    "uq_instance_name_SHA256_0000000000000000000000000000000000000000000000000000000000000000_0_c";
 
 fn mock_uuid_generator() -> String {
     uuid::Uuid::parse_str(TEMP_UUID).unwrap().to_string()
@@ -174,6 +178,10 @@ impl Drop for MockRedisBackend {
 struct FakeRedisBackend {
     /// Contains a list of all of the Redis keys -> fields.
     table: Mutex<HashMap<String, HashMap<String, RedisValue>>>,
+    /// The expiry in milliseconds of the keys which were set with one.
+    expiries: Mutex<HashMap<String, RedisValue>>,
+    /// The commands received, each of which is a round trip to Redis.
+    commands: Mutex<Vec<Str>>,
     /// The subscription manager (maybe).
     subscription_manager: Mutex<Option<Arc<RedisSubscriptionManager>>>,
 }
@@ -188,47 +196,78 @@ impl FakeRedisBackend {
     fn new() -> Self {
         Self {
             table: Mutex::new(HashMap::new()),
+            expiries: Mutex::new(HashMap::new()),
+            commands: Mutex::new(Vec::new()),
             subscription_manager: Mutex::new(None),
         }
     }
 
+    /// Records the expiry of `key` if it was set with one.
+    fn set_expiry(&self, key: &RedisValue, expiry_ms: &RedisValue) {
+        let key: String = str::from_utf8(key.as_bytes().expect("Key not bytes"))
+            .expect("Key cannot be parsed as string")
+            .into();
+        let mut expiries = self.expiries.lock();
+        if *expiry_ms == RedisValue::Bytes(Bytes::from_static(b"0")) {
+            expiries.remove(&key);
+        } else {
+            expiries.insert(key, expiry_ms.clone());
+        }
+    }
+
     fn set_subscription_manager(&self, subscription_manager: Arc<RedisSubscriptionManager>) {
         *self.subscription_manager.lock() = Some(subscription_manager);
     }
+
+    /// Notifies the subscriptions of `key` if it's published on a channel.
+    fn publish(&self, channel: &RedisValue, key: &RedisValue) {
+        if channel.as_bytes().is_some_and(<[u8]>::is_empty) {
+            return;
+        }
+        if let Some(subscription_manager) = self.subscription_manager.lock().as_ref() {
+            subscription_manager.notify_for_test(
+                str::from_utf8(key.as_bytes().expect("Notification not bytes"))
+                    .expect("Notification not UTF-8")
+                    .into(),
+            );
+        }
+    }
 }
 
 impl Mocks for FakeRedisBackend {
     fn process_command(&self, actual: MockCommand) -> Result<RedisValue, RedisError> {
+        self.commands.lock().push(actual.cmd.clone());
+
         if actual.cmd == Str::from_static("SUBSCRIBE") {
             // This does nothing at the moment, maybe we need to implement it later.
             return Ok(RedisValue::Integer(0));
         }
 
         if actual.cmd == Str::from_static("PUBLISH") {
-            if let Some(subscription_manager) = self.subscription_manager.lock().as_ref() {
-                subscription_manager.notify_for_test(
-                    str::from_utf8(actual.args[1].as_bytes().expect("Notification not bytes"))
-                        .expect("Notification not UTF-8")
-                        .into(),
-                );
-            }
+            self.publish(&actual.args[0], &actual.args[1]);
             return Ok(RedisValue::Integer(0));
         }
 
+        if actual.cmd == Str::from_static("FT.SEARCH") {
+            let (field, value) = parse_query(&actual.args[1]);
+            // Lazy implementation making assumptions.
+            assert_eq!(
+                actual.args[2..],
+                vec!["NOCONTENT".into(), "LIMIT".into(), 0.into(), 0.into()]
+            );
+            let total = self
+                .table
+                .lock()
+                .values()
+                .filter(|fields| {
+                    fields.get(&field) == Some(&RedisValue::Bytes(Bytes::from(value.clone())))
+                })
+                .count();
+            return Ok(RedisValue::Array(vec![(total as u32).into()]));
+        }
+
         if actual.cmd == Str::from_static("FT.AGGREGATE") {
-            // The query is @field:value where value might be wrapped in braces.
-            let query = actual.args[1]
-                .clone()
-                .into_string()
-                .expect("Aggregate query should be a string");
-            assert_eq!(&query[..1], "@");
-            let mut parts = query[1..].split(':');
-            let field = parts.next().expect("No field name");
-            let value = parts.next().expect("No value");
-            let value = value
-                .strip_prefix("{ ")
-                .and_then(|s| s.strip_suffix(" }"))
-                .unwrap_or(value);
+            let (field, value) = parse_query(&actual.args[1]);
             // Lazy implementation making assumptions.
             assert_eq!(
                 actual.args[2..6],
@@ -236,8 +275,8 @@ impl Mocks for FakeRedisBackend {
             );
             let mut results = vec![RedisValue::Integer(0)];
             for fields in self.table.lock().values() {
-                if let Some(key_value) = fields.get(field) {
-                    if *key_value == RedisValue::Bytes(Bytes::from(value.to_owned())) {
+                if let Some(key_value) = fields.get(&field) {
+                    if *key_value == RedisValue::Bytes(Bytes::from(value.clone())) {
                         results.push(RedisValue::Array(vec![
                             RedisValue::Bytes(Bytes::from("data")),
                             fields.get("data").expect("No data field").clone(),
@@ -254,11 +293,34 @@ impl Mocks for FakeRedisBackend {
             ]));
         }
 
+        if actual.cmd == Str::from_static("EVALSHA") && actual.args[0] == SET_SCRIPT_HASH.into() {
+            let mut table = self.table.lock();
+            let fields = table
+                .entry(
+                    str::from_utf8(actual.args[2].as_bytes().expect("Key not bytes"))
+                        .expect("Key cannot be parsed as string")
+                        .into(),
+                )
+                .or_default();
+            for pair in actual.args[5..].chunks(2) {
+                fields.insert(
+                    str::from_utf8(pair[0].as_bytes().expect("Field name not bytes"))
+                        .expect("Unable to parse field name as string")
+                        .into(),
+                    pair[1].clone(),
+                );
+            }
+            drop(table);
+            self.set_expiry(&actual.args[2], &actual.args[4]);
+            self.publish(&actual.args[3], &actual.args[2]);
+            return Ok(RedisValue::Integer(1));
+        }
+
         if actual.cmd == Str::from_static("EVALSHA") {
             assert_eq!(actual.args[0], VERSION_SCRIPT_HASH.into());
             let mut value = HashMap::new();
             value.insert("data".into(), actual.args[4].clone());
-            for pair in actual.args[5..].chunks(2) {
+            for pair in actual.args[7..].chunks(2) {
                 value.insert(
                     str::from_utf8(pair[0].as_bytes().expect("Field name not bytes"))
                         .expect("Unable to parse field name as string")
@@ -310,32 +372,14 @@ impl Mocks for FakeRedisBackend {
                     1
                 }
             };
+            self.set_expiry(&actual.args[2], &actual.args[6]);
+            self.publish(&actual.args[5], &actual.args[2]);
             return Ok(RedisValue::Array(vec![
                 RedisValue::Integer(1),
                 RedisValue::Integer(version),
             ]));
         }
 
-        if actual.cmd == Str::from_static("HSET") {
-            assert_eq!(
-                RedisValue::Bytes(Bytes::from_static(b"data")),
-                actual.args[1]
-            );
-            let mut values = HashMap::new();
-            values.insert("data".into(), actual.args[2].clone());
-            self.table.lock().insert(
-                str::from_utf8(
-                    actual.args[0]
-                        .as_bytes()
-                        .expect("Key argument is not bytes"),
-                )
-                .expect("Unable to parse key as string")
-                .into(),
-                values,
-            );
-            return Ok(RedisValue::new_ok());
-        }
-
         if actual.cmd == Str::from_static("HMGET") {
             if let Some(fields) = self.table.lock().get(
                 str::from_utf8(
@@ -358,7 +402,11 @@ impl Mocks for FakeRedisBackend {
                 }
                 return Ok(RedisValue::Array(result));
             }
-            return Err(RedisError::new(RedisErrorKind::NotFound, String::new()));
+            // Redis returns nil for every field of a missing key.
+            return Ok(RedisValue::Array(vec![
+                RedisValue::Null;
+                actual.args.len() - 1
+            ]));
         }
 
         panic!("Mock command not implemented! {actual:?}");
@@ -386,6 +434,24 @@ impl Mocks for FakeRedisBackend {
     }
 }
 
+/// Splits a search query of the form `@field:value` into the field and the
+/// value, which might be wrapped in braces.
+fn parse_query(query: &RedisValue) -> (String, String) {
+    let query = query
+        .clone()
+        .into_string()
+        .expect("Search query should be a string");
+    assert_eq!(&query[..1], "@");
+    let mut parts = query[1..].split(':');
+    let field = parts.next().expect("No field name");
+    let value = parts.next().expect("No value");
+    let value = value
+        .strip_prefix("{ ")
+        .and_then(|s| s.strip_suffix(" }"))
+        .unwrap_or(value);
+    (field.to_owned(), value.to_owned())
+}
+
 fn make_redis_store(sub_channel: &str, mocks: Arc<impl Mocks>) -> Arc<RedisStore> {
     let mut builder = Builder::default_centralized();
     builder.set_config(RedisConfig {
@@ -431,6 +497,8 @@ async fn verify_initial_connection_message(
         update: Some(update_for_worker::Update::ConnectionResult(
             ConnectionResult {
                 worker_id: worker_id.into(),
+                protocol_version: WORKER_API_PROTOCOL_VERSION,
+                features: Vec::new(),
             },
         )),
     };
@@ -456,6 +524,34 @@ async fn setup_new_worker(
     Ok(rx)
 }
 
+fn unique_qualifier_claim_json(operation_id: &OperationId, claimed_at: SystemTime) -> String {
+    format!(
+        "{{\"operation_id\":{},\"claimed_at\":{}}}",
+        serde_json::to_string(operation_id).unwrap(),
+        serde_json::to_string(&claimed_at).unwrap()
+    )
+}
+
+fn insert_unique_qualifier_claim(
+    mocks: &FakeRedisBackend,
+    operation_id: &OperationId,
+    claimed_at: SystemTime,
+) {
+    mocks.table.lock().insert(
+        UNIQUE_QUALIFIER_CLAIM_KEY.into(),
+        HashMap::from([
+            (
+                "data".into(),
+                RedisValue::Bytes(Bytes::from(unique_qualifier_claim_json(
+                    operation_id,
+                    claimed_at,
+                ))),
+            ),
+            ("version".into(), RedisValue::Bytes("1".into())),
+        ]),
+    );
+}
+
 fn make_awaited_action(operation_id: &str) -> AwaitedAction {
     AwaitedAction::new(
         operation_id.into(),
@@ -472,6 +568,7 @@ fn make_awaited_action(operation_id: &str) -> AwaitedAction {
                 digest_function: DigestHasherFunc::Sha256,
                 digest: DigestInfo::zero_digest(),
             }),
+            do_not_cache: false,
         }),
         MockSystemTime::now().into(),
     )
@@ -576,6 +673,19 @@ async fn add_action_smoke_test() -> Result<(), Error> {
             ])),
             None,
         )
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("HMGET"),
+                subcommand: None,
+                args: vec![
+                    UNIQUE_QUALIFIER_CLAIM_KEY.as_bytes().into(),
+                    "version".as_bytes().into(),
+                    "data".as_bytes().into(),
+                ],
+            },
+            Ok(RedisValue::Array(vec![RedisValue::Null, RedisValue::Null])),
+            None,
+        )
         .expect(
             MockCommand {
                 cmd: Str::from_static("EVALSHA"),
@@ -583,15 +693,14 @@ async fn add_action_smoke_test() -> Result<(), Error> {
                 args: vec![
                     VERSION_SCRIPT_HASH.into(),
                     1.into(),
-                    format!("aa_{WORKER_OPERATION_ID}").as_bytes().into(),
+                    UNIQUE_QUALIFIER_CLAIM_KEY.as_bytes().into(),
                     "0".as_bytes().into(),
-                    RedisValue::Bytes(Bytes::from(serde_json::to_string(&worker_awaited_action).unwrap())),
-                    "unique_qualifier".as_bytes().into(),
-                    format!("{INSTANCE_NAME}_SHA256_0000000000000000000000000000000000000000000000000000000000000000_0_c").as_bytes().into(),
-                    "state".as_bytes().into(),
-                    "queued".as_bytes().into(),
-                    "sort_key".as_bytes().into(),
-                    "80000000ffffffff".as_bytes().into(),
+                    RedisValue::Bytes(Bytes::from(unique_qualifier_claim_json(
+                        &worker_operation_id,
+                        MockSystemTime::now().into(),
+                    ))),
+                    SUB_CHANNEL.as_bytes().into(),
+                    "60000".as_bytes().into(),
                 ],
             },
             Ok(RedisValue::Array(vec![RedisValue::Integer(1), RedisValue::Integer(1)])),
@@ -599,39 +708,42 @@ async fn add_action_smoke_test() -> Result<(), Error> {
         )
         .expect(
             MockCommand {
-                cmd: Str::from_static("PUBLISH"),
+                cmd: Str::from_static("EVALSHA"),
                 subcommand: None,
                 args: vec![
-                    SUB_CHANNEL.into(),
-                    format!("aa_{WORKER_OPERATION_ID}").into(),
+                    VERSION_SCRIPT_HASH.into(),
+                    1.into(),
+                    format!("aa_{WORKER_OPERATION_ID}").as_bytes().into(),
+                    "0".as_bytes().into(),
+                    RedisValue::Bytes(Bytes::from(serde_json::to_string(&worker_awaited_action).unwrap())),
+                    SUB_CHANNEL.as_bytes().into(),
+                    "0".as_bytes().into(),
+                    "unique_qualifier".as_bytes().into(),
+                    format!("{INSTANCE_NAME}_SHA256_0000000000000000000000000000000000000000000000000000000000000000_0_c").as_bytes().into(),
+                    "state".as_bytes().into(),
+                    "queued".as_bytes().into(),
+                    "sort_key".as_bytes().into(),
+                    "80000000ffffffff".as_bytes().into(),
                 ],
             },
-            Ok(0.into() /* unused */),
+            Ok(RedisValue::Array(vec![RedisValue::Integer(1), RedisValue::Integer(1)])),
             Some(Box::new(|| SUBSCRIPTION_MANAGER.lock().as_ref().unwrap().notify_for_test(format!("aa_{WORKER_OPERATION_ID}")))),
         )
         .expect(
             MockCommand {
-                cmd: Str::from_static("HSET"),
+                cmd: Str::from_static("EVALSHA"),
                 subcommand: None,
                 args: vec![
+                    SET_SCRIPT_HASH.into(),
+                    1.into(),
                     format!("cid_{CLIENT_OPERATION_ID}").as_bytes().into(),
+                    SUB_CHANNEL.as_bytes().into(),
+                    "0".as_bytes().into(),
                     "data".as_bytes().into(),
                     format!("{{\"String\":\"{WORKER_OPERATION_ID}\"}}").as_bytes().into(),
                 ],
             },
-            Ok(RedisValue::new_ok()),
-            None,
-        )
-        .expect(
-            MockCommand {
-                cmd: Str::from_static("PUBLISH"),
-                subcommand: None,
-                args: vec![
-                    SUB_CHANNEL.into(),
-                    format!("cid_{CLIENT_OPERATION_ID}").into(),
-                ],
-            },
-            Ok(0.into() /* unused */),
+            Ok(RedisValue::Integer(1)),
             Some(Box::new(|| SUBSCRIPTION_MANAGER.lock().as_ref().unwrap().notify_for_test(format!("aa_{CLIENT_OPERATION_ID}")))),
         )
         .expect(
@@ -717,6 +829,8 @@ async fn add_action_smoke_test() -> Result<(), Error> {
                     format!("aa_{WORKER_OPERATION_ID}").as_bytes().into(),
                     "0".as_bytes().into(),
                     RedisValue::Bytes(Bytes::from(serde_json::to_string(&new_awaited_action).unwrap())),
+                    SUB_CHANNEL.as_bytes().into(),
+                    "0".as_bytes().into(),
                     "unique_qualifier".as_bytes().into(),
                     format!("{INSTANCE_NAME}_SHA256_0000000000000000000000000000000000000000000000000000000000000000_0_c").as_bytes().into(),
                     "state".as_bytes().into(),
@@ -726,18 +840,6 @@ async fn add_action_smoke_test() -> Result<(), Error> {
                 ],
             },
             Ok(RedisValue::Array(vec![RedisValue::Integer(1), RedisValue::Integer(2)])),
-            None,
-        )
-        .expect(
-            MockCommand {
-                cmd: Str::from_static("PUBLISH"),
-                subcommand: None,
-                args: vec![
-                    SUB_CHANNEL.into(),
-                    format!("aa_{WORKER_OPERATION_ID}").into(),
-                ],
-            },
-            Ok(0.into() /* unused */),
             Some(Box::new(|| SUBSCRIPTION_MANAGER.lock().as_ref().unwrap().notify_for_test(format!("aa_{WORKER_OPERATION_ID}")))),
         )
         .expect(
@@ -844,6 +946,7 @@ async fn test_multiple_clients_subscribe_to_same_action() -> Result<(), Error> {
             digest_function: DigestHasherFunc::Sha256,
             digest: DigestInfo::zero_digest(),
         }),
+        do_not_cache: false,
     });
 
     let mocks = Arc::new(FakeRedisBackend::new());
@@ -1029,3 +1132,222 @@ async fn test_outdated_version() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn add_action_from_two_schedulers_creates_one_operation_test() -> Result<(), Error> {
+    const CLIENT_OPERATION_ID_A: &str = "client_operation_id_a";
+    const CLIENT_OPERATION_ID_B: &str = "client_operation_id_b";
+
+    let mocks = Arc::new(FakeRedisBackend::new());
+    let store = make_redis_store("sub_channel", mocks.clone());
+    mocks.set_subscription_manager(store.subscription_manager().unwrap());
+
+    // Two schedulers sharing the same Redis.
+    let awaited_action_db_a = StoreAwaitedActionDb::new(
+        store.clone(),
+        Arc::new(Notify::new()),
+        MockInstantWrapped::default,
+        || "operation_a".into(),
+    )
+    .unwrap();
+    let awaited_action_db_b = StoreAwaitedActionDb::new(
+        store,
+        Arc::new(Notify::new()),
+        MockInstantWrapped::default,
+        || "operation_b".into(),
+    )
+    .unwrap();
+
+    let action_info = make_awaited_action("unused").action_info().clone();
+    let (subscription_a, subscription_b) = tokio::join!(
+        awaited_action_db_a.add_action(
+            CLIENT_OPERATION_ID_A.into(),
+            action_info.clone(),
+            Duration::from_secs(60),
+        ),
+        awaited_action_db_b.add_action(
+            CLIENT_OPERATION_ID_B.into(),
+            action_info,
+            Duration::from_secs(60),
+        ),
+    );
+
+    let awaited_action_a = subscription_a?.borrow().await?;
+    let awaited_action_b = subscription_b?.borrow().await?;
+    assert_eq!(
+        awaited_action_a.operation_id(),
+        awaited_action_b.operation_id()
+    );
+    // The claim expires once the action would be considered abandoned.
+    assert_eq!(
+        mocks.expiries.lock()[UNIQUE_QUALIFIER_CLAIM_KEY],
+        RedisValue::Bytes("60000".into())
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn add_action_waits_for_operation_of_claimed_action_test() -> Result<(), Error> {
+    const CLIENT_OPERATION_ID: &str = "client_operation_id";
+
+    let mocks = Arc::new(FakeRedisBackend::new());
+    let store = make_redis_store("sub_channel", mocks.clone());
+    let awaited_action_db = StoreAwaitedActionDb::new(
+        store,
+        Arc::new(Notify::new()),
+        MockInstantWrapped::default,
+        || "operation_b".into(),
+    )
+    .unwrap();
+
+    // Another scheduler claimed the action, but hasn't written its operation yet.
+    let claimed_awaited_action = make_awaited_action("operation_a");
+    insert_unique_qualifier_claim(
+        &mocks,
+        claimed_awaited_action.operation_id(),
+        MockSystemTime::now().into(),
+    );
+
+    let (subscription, update_res) = tokio::join!(
+        awaited_action_db.add_action(
+            CLIENT_OPERATION_ID.into(),
+            claimed_awaited_action.action_info().clone(),
+            Duration::from_secs(60),
+        ),
+        async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            awaited_action_db
+                .update_awaited_action(claimed_awaited_action.clone())
+                .await
+        },
+    );
+    assert_eq!(update_res, Ok(()));
+
+    assert_eq!(
+        subscription?.borrow().await?.operation_id(),
+        claimed_awaited_action.operation_id()
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn add_action_takes_over_expired_claim_test() -> Result<(), Error> {
+    const CLIENT_OPERATION_ID: &str = "client_operation_id";
+
+    let mocks = Arc::new(FakeRedisBackend::new());
+    let store = make_redis_store("sub_channel", mocks.clone());
+    let awaited_action_db = StoreAwaitedActionDb::new(
+        store,
+        Arc::new(Notify::new()),
+        MockInstantWrapped::default,
+        || "operation_b".into(),
+    )
+    .unwrap();
+
+    // A scheduler claimed the action and went away before writing its operation.
+    insert_unique_qualifier_claim(
+        &mocks,
+        &OperationId::from("operation_a"),
+        SystemTime::UNIX_EPOCH,
+    );
+
+    let subscription = awaited_action_db
+        .add_action(
+            CLIENT_OPERATION_ID.into(),
+            make_awaited_action("unused").action_info().clone(),
+            Duration::ZERO,
+        )
+        .await?;
+
+    let operation_id = OperationId::from("operation_b");
+    assert_eq!(subscription.borrow().await?.operation_id(), &operation_id);
+    let claim = mocks.table.lock()[UNIQUE_QUALIFIER_CLAIM_KEY].clone();
+    assert_eq!(
+        claim["data"],
+        RedisValue::Bytes(Bytes::from(unique_qualifier_claim_json(
+            &operation_id,
+            MockSystemTime::now().into(),
+        )))
+    );
+    assert_eq!(claim["version"], RedisValue::Bytes("2".into()));
+    // Redis rejects an expiry of 0, so it's rounded up.
+    assert_eq!(
+        mocks.expiries.lock()[UNIQUE_QUALIFIER_CLAIM_KEY],
+        RedisValue::Bytes("1".into())
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn count_actions_counts_on_redis_test() -> Result<(), Error> {
+    let mocks = Arc::new(FakeRedisBackend::new());
+    let store = make_redis_store("sub_channel", mocks);
+    let awaited_action_db = StoreAwaitedActionDb::new(
+        store,
+        Arc::new(Notify::new()),
+        MockInstantWrapped::default,
+        || "unused".into(),
+    )
+    .unwrap();
+
+    for operation_id in ["operation_a", "operation_b"] {
+        awaited_action_db
+            .update_awaited_action(make_awaited_action(operation_id))
+            .await?;
+    }
+
+    assert_eq!(
+        awaited_action_db
+            .count_actions(SortedAwaitedActionState::Queued)
+            .await?,
+        2
+    );
+    assert_eq!(
+        awaited_action_db
+            .count_actions(SortedAwaitedActionState::Executing)
+            .await?,
+        0
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn update_awaited_action_is_one_round_trip_test() -> Result<(), Error> {
+    let mocks = Arc::new(FakeRedisBackend::new());
+    let store = make_redis_store("sub_channel", mocks.clone());
+    mocks.set_subscription_manager(store.subscription_manager().unwrap());
+    let awaited_action_db = StoreAwaitedActionDb::new(
+        store,
+        Arc::new(Notify::new()),
+        MockInstantWrapped::default,
+        || "operation_id".into(),
+    )
+    .unwrap();
+
+    let subscription = awaited_action_db
+        .add_action(
+            "client_operation_id".into(),
+            make_awaited_action("unused").action_info().clone(),
+            Duration::from_secs(60),
+        )
+        .await?;
+    let mut awaited_action = subscription.borrow().await?;
+    drop(subscription);
+    let mut new_state = awaited_action.state().as_ref().clone();
+    new_state.stage = ActionStage::Executing;
+    awaited_action.worker_set_state(Arc::new(new_state), MockSystemTime::now().into());
+
+    mocks.commands.lock().clear();
+    awaited_action_db
+        .update_awaited_action(awaited_action)
+        .await?;
+    // The action, its indexes and the notification of its subscribers are
+    // all written by a single script call.
+    assert_eq!(*mocks.commands.lock(), vec![Str::from_static("EVALSHA")]);
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+mod utils {
+    pub(crate) mod scheduler_utils;
+}
+
+use futures::join;
+use nativelink_config::schedulers::{RouterRoute, RouterSpec, SchedulerSpec, SimpleSpec};
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_scheduler::mock_scheduler::MockActionScheduler;
+use nativelink_scheduler::router_scheduler::RouterScheduler;
+use nativelink_util::action_messages::{ActionInfo, ActionStage, ActionState, OperationId};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::known_platform_property_provider::KnownPlatformPropertyProvider;
+use nativelink_util::operation_state_manager::ClientStateManager;
+use pretty_assertions::assert_eq;
+use tokio::sync::watch;
+use utils::scheduler_utils::{INSTANCE_NAME, TokioWatchActionStateResult, make_base_action_info};
+
+const ROUTE_PROPERTY_NAME: &str = "OSFamily";
+const ROUTE_PROPERTY_VALUE: &str = "macos";
+
+struct TestContext {
+    mac_scheduler: Arc<MockActionScheduler>,
+    default_scheduler: Arc<MockActionScheduler>,
+    router_scheduler: RouterScheduler,
+}
+
+fn make_router_scheduler() -> TestContext {
+    let mac_scheduler = Arc::new(MockActionScheduler::new());
+    let default_scheduler = Arc::new(MockActionScheduler::new());
+    let config = RouterSpec {
+        routes: vec![RouterRoute {
+            platform_properties: HashMap::from([(
+                ROUTE_PROPERTY_NAME.to_string(),
+                ROUTE_PROPERTY_VALUE.to_string(),
+            )]),
+            scheduler: Box::new(SchedulerSpec::Simple(SimpleSpec::default())),
+        }],
+        default_scheduler: Box::new(SchedulerSpec::Simple(SimpleSpec::default())),
+    };
+    let router_scheduler = RouterScheduler::new(
+        &config,
+        vec![mac_scheduler.clone()],
+        default_scheduler.clone(),
+    )
+    .unwrap();
+    TestContext {
+        mac_scheduler,
+        default_scheduler,
+        router_scheduler,
+    }
+}
+
+fn make_action_info(platform_properties: &[(&str, &str)]) -> Arc<ActionInfo> {
+    let mut action_info = make_base_action_info(UNIX_EPOCH, DigestInfo::zero_digest())
+        .as_ref()
+        .clone();
+    for (name, value) in platform_properties {
+        action_info
+            .platform_properties
+            .insert((*name).to_string(), (*value).to_string());
+    }
+    Arc::new(action_info)
+}
+
+#[nativelink_test]
+async fn add_action_matching_route_goes_to_route_scheduler() -> Result<(), Error> {
+    let context = make_router_scheduler();
+    let action_info = make_action_info(&[
+        (ROUTE_PROPERTY_NAME, ROUTE_PROPERTY_VALUE),
+        ("cpu_count", "4"),
+    ]);
+    let (_forward_watch_channel_tx, forward_watch_channel_rx) =
+        watch::channel(Arc::new(ActionState {
+            client_operation_id: OperationId::default(),
+            stage: ActionStage::Queued,
+            action_digest: action_info.unique_qualifier.digest(),
+        }));
+    let client_operation_id = OperationId::default();
+    let (_, (passed_client_operation_id, passed_action_info)) = join!(
+        context
+            .router_scheduler
+            .add_action(client_operation_id.clone(), action_info.clone()),
+        context
+            .mac_scheduler
+            .expect_add_action(Ok(Box::new(TokioWatchActionStateResult::new(
+                client_operation_id.clone(),
+                action_info.clone(),
+                forward_watch_channel_rx
+            )))),
+    );
+    assert_eq!(client_operation_id, passed_client_operation_id);
+    assert_eq!(
+        action_info.platform_properties,
+        passed_action_info.platform_properties
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn add_action_not_matching_route_goes_to_default_scheduler() -> Result<(), Error> {
+    let context = make_router_scheduler();
+    let action_info = make_action_info(&[(ROUTE_PROPERTY_NAME, "linux")]);
+    let (_forward_watch_channel_tx, forward_watch_channel_rx) =
+        watch::channel(Arc::new(ActionState {
+            client_operation_id: OperationId::default(),
+            stage: ActionStage::Queued,
+            action_digest: action_info.unique_qualifier.digest(),
+        }));
+    let client_operation_id = OperationId::default();
+    let (_, (passed_client_operation_id, _)) = join!(
+        context
+            .router_scheduler
+            .add_action(client_operation_id.clone(), action_info.clone()),
+        context.default_scheduler.expect_add_action(Ok(Box::new(
+            TokioWatchActionStateResult::new(
+                client_operation_id.clone(),
+                action_info.clone(),
+                forward_watch_channel_rx
+            )
+        ))),
+    );
+    assert_eq!(client_operation_id, passed_client_operation_id);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn get_known_properties_merges_nested_schedulers() -> Result<(), Error> {
+    let context = make_router_scheduler();
+    let (known_properties, mac_instance_name, default_instance_name) = join!(
+        context.router_scheduler.get_known_properties(INSTANCE_NAME),
+        context
+            .mac_scheduler
+            .expect_get_known_properties(Ok(vec!["cpu_count".to_string()])),
+        context
+            .default_scheduler
+            .expect_get_known_properties(Ok(vec![
+                "cpu_count".to_string(),
+                "memory_kb".to_string()
+            ])),
+    );
+    assert_eq!(INSTANCE_NAME, mac_instance_name);
+    assert_eq!(INSTANCE_NAME, default_instance_name);
+    let mut known_properties = known_properties?;
+    known_properties.sort_unstable();
+    assert_eq!(
+        vec![
+            ROUTE_PROPERTY_NAME.to_string(),
+            "cpu_count".to_string(),
+            "memory_kb".to_string()
+        ],
+        known_properties
+    );
+    Ok(())
+}
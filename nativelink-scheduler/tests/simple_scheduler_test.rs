@@ -17,7 +17,8 @@ use core::ops::Bound;
 use core::pin::Pin;
 use core::sync::atomic::{AtomicBool, Ordering};
 use core::time::Duration;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -25,7 +26,11 @@ use async_lock::Mutex;
 use futures::task::Poll;
 use futures::{Stream, StreamExt, poll};
 use mock_instant::thread_local::{MockClock, SystemTime as MockSystemTime};
-use nativelink_config::schedulers::{PropertyType, SimpleSpec};
+use nativelink_config::schedulers::{
+    ClientDisconnectBehavior, ClientQuotaSpec, InputPrefetchSpec, PropertyType,
+    QueueBackpressureSpec, SimpleSpec, WorkerAllocationStrategy, WorkerPoolSpec,
+    WorkerQuarantineSpec, WorkerSchedulingPolicy,
+};
 use nativelink_error::{Code, Error, ResultExt, make_err};
 use nativelink_macro::nativelink_test;
 use nativelink_metric::MetricsComponent;
@@ -33,7 +38,8 @@ use nativelink_proto::build::bazel::remote::execution::v2::{
     ExecuteRequest, Platform, digest_function,
 };
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::{
-    ConnectionResult, StartExecute, UpdateForWorker, update_for_worker,
+    ConnectionResult, KillOperationRequest, PrefetchInputs, StartExecute, UpdateForWorker,
+    update_for_worker,
 };
 use nativelink_scheduler::awaited_action_db::{
     AwaitedAction, AwaitedActionDb, AwaitedActionSubscriber, SortedAwaitedAction,
@@ -41,8 +47,9 @@ use nativelink_scheduler::awaited_action_db::{
 };
 use nativelink_scheduler::default_scheduler_factory::memory_awaited_action_db_factory;
 use nativelink_scheduler::simple_scheduler::SimpleScheduler;
-use nativelink_scheduler::worker::Worker;
-use nativelink_scheduler::worker_scheduler::WorkerScheduler;
+use nativelink_scheduler::stage_dwell_times::DwellStage;
+use nativelink_scheduler::worker::{EXCLUSIVE_PROPERTY_NAME, Worker};
+use nativelink_scheduler::worker_scheduler::{WorkerPlacementStatus, WorkerScheduler};
 use nativelink_util::action_messages::{
     ActionInfo, ActionResult, ActionStage, ActionState, DirectoryInfo, ExecutionMetadata, FileInfo,
     INTERNAL_ERROR_EXIT_CODE, NameOrPath, OperationId, SymlinkInfo, WorkerId,
@@ -54,9 +61,17 @@ use nativelink_util::operation_state_manager::{
     UpdateOperationType,
 };
 use nativelink_util::platform_properties::{PlatformProperties, PlatformPropertyValue};
+use nativelink_util::worker_features::{
+    PREFETCH_INPUTS, RESOURCE_UTILIZATION, WORKER_API_PROTOCOL_VERSION,
+};
+use opentelemetry::KeyValue;
+use opentelemetry::baggage::BaggageExt;
+use opentelemetry::context::{Context, FutureExt as OtelFutureExt};
+use opentelemetry_semantic_conventions::attribute::ENDUSER_ID;
 use pretty_assertions::assert_eq;
 use tokio::sync::{Notify, mpsc};
 use utils::scheduler_utils::{INSTANCE_NAME, make_base_action_info, update_eq};
+use uuid::Uuid;
 
 mod utils {
     pub(crate) mod scheduler_utils;
@@ -71,6 +86,8 @@ async fn verify_initial_connection_message(
         update: Some(update_for_worker::Update::ConnectionResult(
             ConnectionResult {
                 worker_id: worker_id.into(),
+                protocol_version: WORKER_API_PROTOCOL_VERSION,
+                features: Vec::new(),
             },
         )),
     };
@@ -612,6 +629,138 @@ async fn set_drain_worker_pauses_and_resumes_worker_test() -> Result<(), Error>
     Ok(())
 }
 
+#[nativelink_test]
+async fn drain_worker_with_deadline_requeues_operations_test() -> Result<(), Error> {
+    const DRAIN_DEADLINE_S: u64 = 10;
+    let worker_id1 = WorkerId("worker_id1".to_string());
+    let worker_id2 = WorkerId("worker_id2".to_string());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            worker_timeout_s: WORKER_TIMEOUT_S,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let mut rx_from_worker1 = setup_new_worker(
+        &scheduler,
+        worker_id1.clone(),
+        PlatformProperties::default(),
+    )
+    .await?;
+    let mut action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([99u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    match rx_from_worker1.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert_eq!(
+        action_listener.changed().await?.0.stage,
+        ActionStage::Executing
+    );
+
+    scheduler
+        .drain_worker_with_deadline(&worker_id1, NOW_TIME + DRAIN_DEADLINE_S)
+        .await?;
+    let mut rx_from_worker2 =
+        setup_new_worker(&scheduler, worker_id2, PlatformProperties::default()).await?;
+
+    // Before the deadline the operation keeps running on the draining worker.
+    scheduler
+        .remove_timedout_workers(NOW_TIME + DRAIN_DEADLINE_S - 1)
+        .await?;
+    assert!(rx_from_worker1.try_recv().is_err());
+    assert!(rx_from_worker2.try_recv().is_err());
+
+    // Once the deadline passes the worker is removed and the operation is
+    // requeued to the other worker.
+    scheduler
+        .remove_timedout_workers(NOW_TIME + DRAIN_DEADLINE_S)
+        .await?;
+    tokio::task::yield_now().await; // Allow task<->worker matcher to run.
+    assert_eq!(
+        rx_from_worker1.recv().await.unwrap().update,
+        Some(update_for_worker::Update::Disconnect(()))
+    );
+    match rx_from_worker2.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn set_draining_stops_and_resumes_matching_test() -> Result<(), Error> {
+    let worker_id = WorkerId("worker_id".to_string());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id.clone(), PlatformProperties::default()).await?;
+
+    scheduler.set_draining(true)?;
+    assert!(scheduler.is_draining());
+
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+    let insert_timestamp = make_system_time(1);
+    let mut action_listener =
+        setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp).await?;
+
+    {
+        // Client should get notification saying it's been queued.
+        let (action_state, _maybe_origin_metadata) = action_listener.changed().await.unwrap();
+        assert_eq!(action_state.stage, ActionStage::Queued);
+    }
+    // The idle worker should not have been sent anything.
+    scheduler.do_try_match_for_test().await?;
+    assert!(rx_from_worker.try_recv().is_err());
+
+    scheduler.set_draining(false)?;
+    assert!(!scheduler.is_draining());
+    tokio::task::yield_now().await;
+
+    {
+        // Worker should have been sent an execute command.
+        match rx_from_worker.recv().await.unwrap().update {
+            Some(update_for_worker::Update::StartAction(_)) => {}
+            v => panic!("Expected StartAction, got : {v:?}"),
+        }
+        // Client should get notification saying it's being executed.
+        let (action_state, _maybe_origin_metadata) = action_listener.changed().await.unwrap();
+        assert_eq!(action_state.stage, ActionStage::Executing);
+    }
+
+    Ok(())
+}
+
 #[nativelink_test]
 async fn worker_should_not_queue_if_properties_dont_match_test() -> Result<(), Error> {
     let worker_id1 = WorkerId("worker1".to_string());
@@ -2348,3 +2497,1741 @@ async fn client_timesout_job_then_same_action_requested() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn cancel_queued_operation_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    let mut action_listener = setup_action(
+        &scheduler,
+        action_digest,
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    let (action_state, _maybe_origin_metadata) = action_listener.as_state().await?;
+    assert_eq!(action_state.stage, ActionStage::Queued);
+    let client_operation_id = action_state.client_operation_id.clone();
+
+    scheduler.cancel_operation(&client_operation_id).await?;
+
+    let (action_state, _maybe_origin_metadata) = action_listener.changed().await?;
+    let ActionStage::Completed(action_result) = &action_state.stage else {
+        panic!("Expected Completed stage, got {:?}", action_state.stage);
+    };
+    assert_eq!(
+        action_result.error.as_ref().map(|err| err.code),
+        Some(Code::Cancelled)
+    );
+
+    // Cancelling an operation that already finished is a no-op.
+    scheduler.cancel_operation(&client_operation_id).await?;
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn cancel_executing_operation_kills_it_on_worker_test() -> Result<(), Error> {
+    let worker_id = WorkerId("worker_id".to_string());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id.clone(), PlatformProperties::default()).await?;
+
+    let mut action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([99u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    let operation_id = match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => start_execute.operation_id,
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+    let (action_state, _maybe_origin_metadata) = action_listener.changed().await?;
+    assert_eq!(action_state.stage, ActionStage::Executing);
+
+    scheduler
+        .cancel_operation(&action_state.client_operation_id)
+        .await?;
+
+    assert_eq!(
+        rx_from_worker.recv().await.unwrap().update,
+        Some(update_for_worker::Update::KillOperationRequest(
+            KillOperationRequest {
+                operation_id: operation_id.clone()
+            }
+        ))
+    );
+    let (action_state, _maybe_origin_metadata) = action_listener.changed().await?;
+    let ActionStage::Completed(action_result) = &action_state.stage else {
+        panic!("Expected Completed stage, got {:?}", action_state.stage);
+    };
+    assert_eq!(
+        action_result.error.as_ref().map(|err| err.code),
+        Some(Code::Cancelled)
+    );
+
+    // The worker reporting that the killed operation failed is ignored.
+    scheduler
+        .update_action(
+            &worker_id,
+            &OperationId::from(operation_id.as_str()),
+            UpdateOperationType::UpdateWithError(make_err!(Code::Aborted, "Killed")),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn exclusive_action_runs_alone_on_worker_test() -> Result<(), Error> {
+    let worker_id = WorkerId("worker_id".to_string());
+
+    let mut supported_props = HashMap::new();
+    supported_props.insert("prop1".to_string(), PropertyType::Minimum);
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            supported_platform_properties: Some(supported_props),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    // The worker has room for two actions at once.
+    let mut rx_from_worker = setup_new_worker(
+        &scheduler,
+        worker_id.clone(),
+        PlatformProperties::new(HashMap::from([(
+            "prop1".to_string(),
+            PlatformPropertyValue::Minimum(2),
+        )])),
+    )
+    .await?;
+    let action_props = HashMap::from([("prop1".to_string(), "1".to_string())]);
+    let mut exclusive_action_props = action_props.clone();
+    exclusive_action_props.insert(EXCLUSIVE_PROPERTY_NAME.to_string(), "true".to_string());
+
+    let _first_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([1u8; 32], 512),
+        action_props.clone(),
+        make_system_time(1),
+    )
+    .await?;
+    let first_operation_id = match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            OperationId::from(start_execute.operation_id)
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+
+    // The exclusive action waits for the first action to finish and the
+    // worker takes no other action meanwhile, even though it has room.
+    let mut exclusive_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        exclusive_action_props,
+        make_system_time(2),
+    )
+    .await?;
+    let mut last_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([3u8; 32], 512),
+        action_props,
+        make_system_time(3),
+    )
+    .await?;
+    scheduler.do_try_match_for_test().await?;
+    let (action_state, _maybe_origin_metadata) = exclusive_action_listener.changed().await?;
+    assert_eq!(action_state.stage, ActionStage::Queued);
+    let (action_state, _maybe_origin_metadata) = last_action_listener.changed().await?;
+    assert_eq!(action_state.stage, ActionStage::Queued);
+
+    scheduler
+        .update_action(
+            &worker_id,
+            &first_operation_id,
+            UpdateOperationType::UpdateWithActionStage(ActionStage::Completed(
+                ActionResult::default(),
+            )),
+        )
+        .await?;
+    scheduler.do_try_match_for_test().await?;
+    let exclusive_operation_id = match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            OperationId::from(start_execute.operation_id)
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+    let (action_state, _maybe_origin_metadata) = exclusive_action_listener.changed().await?;
+    assert_eq!(action_state.stage, ActionStage::Executing);
+
+    // Nothing runs next to the exclusive action.
+    scheduler.do_try_match_for_test().await?;
+    assert!(rx_from_worker.try_recv().is_err());
+
+    scheduler
+        .update_action(
+            &worker_id,
+            &exclusive_operation_id,
+            UpdateOperationType::UpdateWithActionStage(ActionStage::Completed(
+                ActionResult::default(),
+            )),
+        )
+        .await?;
+    scheduler.do_try_match_for_test().await?;
+    assert!(matches!(
+        rx_from_worker.recv().await.unwrap().update,
+        Some(update_for_worker::Update::StartAction(_))
+    ));
+    let (action_state, _maybe_origin_metadata) = last_action_listener.changed().await?;
+    assert_eq!(action_state.stage, ActionStage::Executing);
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn worker_features_are_negotiated_on_connect_test() -> Result<(), Error> {
+    let worker_id = WorkerId("worker_id".to_string());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut worker = Worker::new(
+        worker_id.clone(),
+        PlatformProperties::default(),
+        tx,
+        NOW_TIME,
+    );
+    worker.protocol_version = WORKER_API_PROTOCOL_VERSION + 1;
+    worker.features = [
+        RESOURCE_UTILIZATION.to_string(),
+        "from_a_newer_worker".to_string(),
+    ]
+    .into();
+    assert!(worker.supports_feature(RESOURCE_UTILIZATION));
+    assert!(!worker.supports_feature("from_a_newer_worker"));
+    scheduler.add_worker(worker).await?;
+
+    // Only the features both sides support are acknowledged.
+    assert_eq!(
+        rx.recv().await.unwrap(),
+        UpdateForWorker {
+            update: Some(update_for_worker::Update::ConnectionResult(
+                ConnectionResult {
+                    worker_id: worker_id.into(),
+                    protocol_version: WORKER_API_PROTOCOL_VERSION,
+                    features: vec![RESOURCE_UTILIZATION.to_string()],
+                },
+            )),
+        }
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn idle_workers_are_asked_to_prefetch_inputs_test() -> Result<(), Error> {
+    let worker_id = WorkerId("worker_id".to_string());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            input_prefetch: Some(InputPrefetchSpec {
+                max_workers_per_action: 1,
+            }),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let (tx, mut rx_from_worker) = mpsc::unbounded_channel();
+    let mut worker = Worker::new(
+        worker_id.clone(),
+        PlatformProperties::default(),
+        tx,
+        NOW_TIME,
+    );
+    worker.features = [PREFETCH_INPUTS.to_string()].into();
+    scheduler.add_worker(worker).await?;
+    assert!(matches!(
+        rx_from_worker.recv().await.unwrap().update,
+        Some(update_for_worker::Update::ConnectionResult(_))
+    ));
+
+    // The idle worker is hinted before it is assigned the action.
+    let _first_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([1u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    assert_eq!(
+        rx_from_worker.recv().await.unwrap().update,
+        Some(update_for_worker::Update::PrefetchInputs(PrefetchInputs {
+            directory_digests: vec![DigestInfo::new([0u8; 32], 0).into()],
+        }))
+    );
+    assert!(matches!(
+        rx_from_worker.recv().await.unwrap().update,
+        Some(update_for_worker::Update::StartAction(_))
+    ));
+
+    // A busy worker is not hinted.
+    let _second_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        HashMap::new(),
+        make_system_time(2),
+    )
+    .await?;
+    assert!(matches!(
+        rx_from_worker.recv().await.unwrap().update,
+        Some(update_for_worker::Update::StartAction(_))
+    ));
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn filter_operations_by_platform_properties_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let linux_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([1u8; 32], 512),
+        HashMap::from([
+            ("OSFamily".to_string(), "linux".to_string()),
+            ("gpu".to_string(), "true".to_string()),
+        ]),
+        make_system_time(1),
+    )
+    .await?;
+    let _windows_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        HashMap::from([("OSFamily".to_string(), "windows".to_string())]),
+        make_system_time(1),
+    )
+    .await?;
+
+    let operation_ids: Vec<OperationId> = scheduler
+        .filter_operations(OperationFilter {
+            stages: OperationStageFlags::Queued,
+            platform_properties: BTreeMap::from([("OSFamily".to_string(), "linux".to_string())]),
+            ..Default::default()
+        })
+        .await?
+        .then(|action_state_result| async move {
+            action_state_result
+                .as_state()
+                .await
+                .unwrap()
+                .0
+                .client_operation_id
+                .clone()
+        })
+        .collect()
+        .await;
+    let (linux_action_state, _maybe_origin_metadata) = linux_action_listener.as_state().await?;
+    assert_eq!(
+        operation_ids,
+        vec![linux_action_state.client_operation_id.clone()]
+    );
+
+    let mut stream = scheduler
+        .filter_operations(OperationFilter {
+            platform_properties: BTreeMap::from([
+                ("OSFamily".to_string(), "linux".to_string()),
+                ("gpu".to_string(), "false".to_string()),
+            ]),
+            ..Default::default()
+        })
+        .await?;
+    assert!(stream.next().await.is_none());
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn count_operations_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let _action_listener1 = setup_action(
+        &scheduler,
+        DigestInfo::new([1u8; 32], 512),
+        HashMap::from([("OSFamily".to_string(), "linux".to_string())]),
+        make_system_time(1),
+    )
+    .await?;
+    let _action_listener2 = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+
+    let queued_filter = OperationFilter {
+        stages: OperationStageFlags::Queued,
+        ..Default::default()
+    };
+    assert_eq!(scheduler.count_operations(queued_filter).await?, 2);
+
+    let executing_filter = OperationFilter {
+        stages: OperationStageFlags::Executing,
+        ..Default::default()
+    };
+    assert_eq!(scheduler.count_operations(executing_filter).await?, 0);
+
+    let linux_filter = OperationFilter {
+        stages: OperationStageFlags::Queued,
+        platform_properties: BTreeMap::from([("OSFamily".to_string(), "linux".to_string())]),
+        ..Default::default()
+    };
+    assert_eq!(scheduler.count_operations(linux_filter).await?, 1);
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn queue_position_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    // No workers are connected, so all actions stay queued.
+    let first_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([1u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    let second_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        HashMap::new(),
+        make_system_time(2),
+    )
+    .await?;
+    assert_eq!(first_listener.queue_position().await?, Some(0));
+    assert_eq!(second_listener.queue_position().await?, Some(1));
+
+    // A higher priority action goes to the front of the queue.
+    let mut action_info =
+        make_base_action_info(make_system_time(3), DigestInfo::new([3u8; 32], 512));
+    Arc::make_mut(&mut action_info).priority = 10;
+    let high_priority_listener = scheduler
+        .add_action(OperationId::default(), action_info)
+        .await?;
+    assert_eq!(high_priority_listener.queue_position().await?, Some(0));
+    assert_eq!(first_listener.queue_position().await?, Some(1));
+    assert_eq!(second_listener.queue_position().await?, Some(2));
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn stale_queued_action_is_failed_test() -> Result<(), Error> {
+    const MAX_QUEUED_ACTION_AGE_S: u64 = 10;
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            max_queued_action_age_s: MAX_QUEUED_ACTION_AGE_S,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    // No workers are connected, so the action stays queued.
+    let mut action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([99u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+
+    MockClock::advance(Duration::from_secs(MAX_QUEUED_ACTION_AGE_S - 1));
+    scheduler.do_try_match_for_test().await?;
+    assert_eq!(
+        action_listener.as_state().await?.0.stage,
+        ActionStage::Queued
+    );
+
+    MockClock::advance(Duration::from_secs(2));
+    scheduler.do_try_match_for_test().await?;
+    match action_listener.changed().await?.0.stage {
+        ActionStage::Completed(action_result) => assert_eq!(
+            action_result.error.map(|err| err.code),
+            Some(Code::DeadlineExceeded)
+        ),
+        stage => panic!("Expected Completed, got : {stage:?}"),
+    }
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn preempts_lower_priority_action_test() -> Result<(), Error> {
+    let worker_id = WorkerId("worker_id".to_string());
+
+    let mut supported_props = HashMap::new();
+    supported_props.insert("prop1".to_string(), PropertyType::Minimum);
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            supported_platform_properties: Some(supported_props),
+            max_preemptions_per_minute: 1,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    // The worker only has room for a single action.
+    let platform_properties = PlatformProperties {
+        properties: HashMap::from([("prop1".to_string(), PlatformPropertyValue::Minimum(1))]),
+    };
+    let action_props = HashMap::from([("prop1".to_string(), "1".to_string())]);
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id.clone(), platform_properties).await?;
+
+    let mut low_priority_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([11u8; 32], 512),
+        action_props.clone(),
+        make_system_time(1),
+    )
+    .await?;
+    let low_priority_operation_id = match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            OperationId::from(start_execute.operation_id)
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+    assert_eq!(
+        low_priority_listener.changed().await?.0.stage,
+        ActionStage::Executing
+    );
+
+    let mut action_info =
+        make_base_action_info(make_system_time(2), DigestInfo::new([99u8; 32], 512));
+    {
+        let action_info = Arc::make_mut(&mut action_info);
+        action_info.platform_properties = action_props;
+        action_info.priority = 10;
+    }
+    let mut high_priority_listener = scheduler
+        .add_action(OperationId::default(), action_info)
+        .await?;
+    tokio::task::yield_now().await; // Allow task<->worker matcher to run.
+
+    // The low priority action is killed to make room for the new one.
+    assert_eq!(
+        rx_from_worker.recv().await.unwrap().update,
+        Some(update_for_worker::Update::KillOperationRequest(
+            KillOperationRequest {
+                operation_id: low_priority_operation_id.to_string(),
+            }
+        ))
+    );
+    match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert_eq!(
+        high_priority_listener.changed().await?.0.stage,
+        ActionStage::Executing
+    );
+    assert_eq!(
+        low_priority_listener.changed().await?.0.stage,
+        ActionStage::Queued
+    );
+
+    // Whatever the worker reports about the killed action is ignored.
+    scheduler
+        .update_action(
+            &worker_id,
+            &low_priority_operation_id,
+            UpdateOperationType::UpdateWithError(make_err!(Code::Aborted, "Killed")),
+        )
+        .await?;
+    assert_eq!(
+        low_priority_listener.as_state().await?.0.stage,
+        ActionStage::Queued
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn operation_past_timeout_is_stopped_test() -> Result<(), Error> {
+    const ACTION_TIMEOUT_S: u64 = 60;
+    const GRACE_S: u64 = 10;
+    let worker_id = WorkerId("worker_id".to_string());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            worker_timeout_s: WORKER_TIMEOUT_S,
+            execution_timeout_grace_s: GRACE_S,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id.clone(), PlatformProperties::default()).await?;
+
+    let mut action_info =
+        make_base_action_info(make_system_time(1), DigestInfo::new([99u8; 32], 512));
+    Arc::make_mut(&mut action_info).timeout = Duration::from_secs(ACTION_TIMEOUT_S);
+    let mut action_listener = scheduler
+        .add_action(OperationId::default(), action_info)
+        .await?;
+    tokio::task::yield_now().await; // Allow task<->worker matcher to run.
+    let operation_id = match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => start_execute.operation_id,
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+    assert_eq!(
+        action_listener.changed().await?.0.stage,
+        ActionStage::Executing
+    );
+
+    // The first check starts the clock, the second one is still in time.
+    scheduler.remove_timedout_workers(NOW_TIME).await?;
+    scheduler
+        .remove_timedout_workers(NOW_TIME + ACTION_TIMEOUT_S + GRACE_S - 1)
+        .await?;
+    assert!(rx_from_worker.try_recv().is_err());
+
+    scheduler
+        .remove_timedout_workers(NOW_TIME + ACTION_TIMEOUT_S + GRACE_S)
+        .await?;
+    tokio::task::yield_now().await; // Allow task<->worker matcher to run.
+    assert_eq!(
+        rx_from_worker.recv().await.unwrap().update,
+        Some(update_for_worker::Update::KillOperationRequest(
+            KillOperationRequest { operation_id }
+        ))
+    );
+    // The failure counts as an attempt, so the operation is retried.
+    match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn prefers_worker_with_same_input_root_test() -> Result<(), Error> {
+    let worker_id1 = WorkerId("worker_id1".to_string());
+    let worker_id2 = WorkerId("worker_id2".to_string());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            worker_input_root_affinity_size: 8,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let mut rx_from_worker1 =
+        setup_new_worker(&scheduler, worker_id1, PlatformProperties::default()).await?;
+    let mut rx_from_worker2 =
+        setup_new_worker(&scheduler, worker_id2, PlatformProperties::default()).await?;
+
+    // Both actions share the same input root. The least recently used worker
+    // gets the first one.
+    let _action_listener1 = setup_action(
+        &scheduler,
+        DigestInfo::new([11u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    match rx_from_worker1.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+
+    // The second action would go to the least recently used worker, but the
+    // first worker already has its inputs.
+    let _action_listener2 = setup_action(
+        &scheduler,
+        DigestInfo::new([22u8; 32], 512),
+        HashMap::new(),
+        make_system_time(2),
+    )
+    .await?;
+    match rx_from_worker1.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert!(rx_from_worker2.try_recv().is_err());
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn least_loaded_scheduling_policy_test() -> Result<(), Error> {
+    let worker_id1 = WorkerId("worker_id1".to_string());
+    let worker_id2 = WorkerId("worker_id2".to_string());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            allocation_strategy: WorkerAllocationStrategy::MostRecentlyUsed,
+            scheduling_policy: WorkerSchedulingPolicy::LeastLoaded,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let mut rx_from_worker1 =
+        setup_new_worker(&scheduler, worker_id1, PlatformProperties::default()).await?;
+    let mut rx_from_worker2 =
+        setup_new_worker(&scheduler, worker_id2, PlatformProperties::default()).await?;
+
+    // Both workers are idle, so the least recently used worker gets the
+    // first action.
+    let _action_listener1 = setup_action(
+        &scheduler,
+        DigestInfo::new([11u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    match rx_from_worker1.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+
+    // The allocation strategy would pick the most recently used worker, but
+    // it is busier than the second worker.
+    let _action_listener2 = setup_action(
+        &scheduler,
+        DigestInfo::new([22u8; 32], 512),
+        HashMap::new(),
+        make_system_time(2),
+    )
+    .await?;
+    match rx_from_worker2.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert!(rx_from_worker1.try_recv().is_err());
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn bin_packing_scheduling_policy_test() -> Result<(), Error> {
+    let worker_id1 = WorkerId("worker_id1".to_string());
+    let worker_id2 = WorkerId("worker_id2".to_string());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            supported_platform_properties: Some(HashMap::from([(
+                "cpu_count".to_string(),
+                PropertyType::Minimum,
+            )])),
+            scheduling_policy: WorkerSchedulingPolicy::BinPacking,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let mut rx_from_worker1 = setup_new_worker(
+        &scheduler,
+        worker_id1,
+        PlatformProperties {
+            properties: HashMap::from([(
+                "cpu_count".to_string(),
+                PlatformPropertyValue::Minimum(8),
+            )]),
+        },
+    )
+    .await?;
+    let mut rx_from_worker2 = setup_new_worker(
+        &scheduler,
+        worker_id2,
+        PlatformProperties {
+            properties: HashMap::from([(
+                "cpu_count".to_string(),
+                PlatformPropertyValue::Minimum(4),
+            )]),
+        },
+    )
+    .await?;
+    let action_props = HashMap::from([("cpu_count".to_string(), "2".to_string())]);
+
+    // Both actions fit on the smaller worker, so they are packed onto it and
+    // the larger worker stays free.
+    let _action_listener1 = setup_action(
+        &scheduler,
+        DigestInfo::new([11u8; 32], 512),
+        action_props.clone(),
+        make_system_time(1),
+    )
+    .await?;
+    let _action_listener2 = setup_action(
+        &scheduler,
+        DigestInfo::new([22u8; 32], 512),
+        action_props,
+        make_system_time(2),
+    )
+    .await?;
+    for _ in 0..2 {
+        match rx_from_worker2.recv().await.unwrap().update {
+            Some(update_for_worker::Update::StartAction(_)) => {}
+            v => panic!("Expected StartAction, got : {v:?}"),
+        }
+    }
+    assert!(rx_from_worker1.try_recv().is_err());
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn retry_on_different_worker_test() -> Result<(), Error> {
+    let worker_id1 = WorkerId("worker_id1".to_string());
+    let worker_id2 = WorkerId("worker_id2".to_string());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            allocation_strategy: WorkerAllocationStrategy::MostRecentlyUsed,
+            retry_on_different_worker: true,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let mut rx_from_worker2 =
+        setup_new_worker(&scheduler, worker_id2, PlatformProperties::default()).await?;
+    let mut rx_from_worker1 = setup_new_worker(
+        &scheduler,
+        worker_id1.clone(),
+        PlatformProperties::default(),
+    )
+    .await?;
+
+    let _action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([99u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    let operation_id = match rx_from_worker1.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            OperationId::from(start_execute.operation_id)
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+
+    // The first worker is still the most recently used one, but the retry
+    // goes to the worker the action has not failed on yet.
+    scheduler
+        .update_action(
+            &worker_id1,
+            &operation_id,
+            UpdateOperationType::UpdateWithError(make_err!(Code::Internal, "Some error")),
+        )
+        .await?;
+    tokio::task::yield_now().await; // Allow task<->worker matcher to run.
+    match rx_from_worker2.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert!(rx_from_worker1.try_recv().is_err());
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn worker_pool_limits_queued_actions_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            supported_platform_properties: Some(HashMap::from([(
+                "pool".to_string(),
+                PropertyType::Exact,
+            )])),
+            worker_pools: vec![
+                WorkerPoolSpec {
+                    name: "release".to_string(),
+                    property_name: "pool".to_string(),
+                    property_value: "release".to_string(),
+                    max_queued_actions: 1,
+                    priority: 0,
+                    allowed_instance_names: vec![INSTANCE_NAME.to_string()],
+                },
+                WorkerPoolSpec {
+                    name: "restricted".to_string(),
+                    property_name: "pool".to_string(),
+                    property_value: "restricted".to_string(),
+                    max_queued_actions: 0,
+                    priority: 0,
+                    allowed_instance_names: vec!["other_instance".to_string()],
+                },
+            ],
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let release_props = HashMap::from([("pool".to_string(), "release".to_string())]);
+
+    // No workers are connected, so the first action stays queued.
+    let _action_listener1 = setup_action(
+        &scheduler,
+        DigestInfo::new([11u8; 32], 512),
+        release_props.clone(),
+        make_system_time(1),
+    )
+    .await?;
+    let err = setup_action(
+        &scheduler,
+        DigestInfo::new([22u8; 32], 512),
+        release_props,
+        make_system_time(2),
+    )
+    .await
+    .err()
+    .expect("Expected the worker pool to be full");
+    assert_eq!(err.code, Code::ResourceExhausted);
+
+    // Actions outside of the pool are not limited.
+    let _action_listener2 = setup_action(
+        &scheduler,
+        DigestInfo::new([33u8; 32], 512),
+        HashMap::new(),
+        make_system_time(3),
+    )
+    .await?;
+
+    let err = setup_action(
+        &scheduler,
+        DigestInfo::new([44u8; 32], 512),
+        HashMap::from([("pool".to_string(), "restricted".to_string())]),
+        make_system_time(4),
+    )
+    .await
+    .err()
+    .expect("Expected the instance to not be allowed in the worker pool");
+    assert_eq!(err.code, Code::PermissionDenied);
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn client_quota_limits_queued_actions_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            client_quotas: Some(ClientQuotaSpec {
+                max_queued_actions: 1,
+                max_executing_actions: 0,
+                retry_delay_s: 0,
+            }),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let client_ctx = |identity: &str| {
+        Context::current_with_baggage(vec![KeyValue::new(ENDUSER_ID, identity.to_string())])
+    };
+
+    // No workers are connected, so the first action stays queued.
+    let _action_listener1 = setup_action(
+        &scheduler,
+        DigestInfo::new([11u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .with_context(client_ctx("client1"))
+    .await?;
+    let err = setup_action(
+        &scheduler,
+        DigestInfo::new([22u8; 32], 512),
+        HashMap::new(),
+        make_system_time(2),
+    )
+    .with_context(client_ctx("client1"))
+    .await
+    .err()
+    .expect("Expected the client quota to be exceeded");
+    assert_eq!(err.code, Code::ResourceExhausted);
+
+    // Other clients have their own quota.
+    let _action_listener2 = setup_action(
+        &scheduler,
+        DigestInfo::new([33u8; 32], 512),
+        HashMap::new(),
+        make_system_time(3),
+    )
+    .with_context(client_ctx("client2"))
+    .await?;
+
+    // Requests without an identity are not limited.
+    let _action_listener3 = setup_action(
+        &scheduler,
+        DigestInfo::new([44u8; 32], 512),
+        HashMap::new(),
+        make_system_time(4),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn queue_backpressure_rejects_actions_per_bucket_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            queue_backpressure: Some(QueueBackpressureSpec {
+                max_queued_actions: 3,
+                max_queued_actions_per_bucket: 1,
+                bucket_properties: vec!["OSFamily".to_string()],
+                retry_delay_s: 30,
+            }),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    let linux_properties = || HashMap::from([("OSFamily".to_string(), "linux".to_string())]);
+
+    // No workers are connected, so actions stay queued.
+    let _linux_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([11u8; 32], 512),
+        linux_properties(),
+        make_system_time(1),
+    )
+    .await?;
+    let err = setup_action(
+        &scheduler,
+        DigestInfo::new([22u8; 32], 512),
+        linux_properties(),
+        make_system_time(2),
+    )
+    .await
+    .err()
+    .expect("Expected the linux bucket to be full");
+    assert_eq!(err.code, Code::ResourceExhausted);
+    assert_eq!(err.retry_delay, Some(Duration::from_secs(30)));
+
+    // Other buckets are not affected until the whole queue is full.
+    let _windows_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([33u8; 32], 512),
+        HashMap::from([("OSFamily".to_string(), "windows".to_string())]),
+        make_system_time(3),
+    )
+    .await?;
+    let _macos_action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([44u8; 32], 512),
+        HashMap::from([("OSFamily".to_string(), "macos".to_string())]),
+        make_system_time(4),
+    )
+    .await?;
+    let err = setup_action(
+        &scheduler,
+        DigestInfo::new([55u8; 32], 512),
+        HashMap::from([("OSFamily".to_string(), "freebsd".to_string())]),
+        make_system_time(5),
+    )
+    .await
+    .err()
+    .expect("Expected the queue to be full");
+    assert_eq!(err.code, Code::ResourceExhausted);
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn worker_quarantined_after_failures_test() -> Result<(), Error> {
+    let worker_id = WorkerId("worker_id".to_string());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            worker_quarantine: Some(WorkerQuarantineSpec {
+                failure_threshold: 1,
+                cooldown_s: 10,
+            }),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id.clone(), PlatformProperties::default()).await?;
+    let _action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([99u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    let operation_id = match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            OperationId::from(start_execute.operation_id)
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+
+    // The action fails and is retried, but the worker is now quarantined.
+    scheduler
+        .update_action(
+            &worker_id,
+            &operation_id,
+            UpdateOperationType::UpdateWithError(make_err!(Code::Internal, "Some error")),
+        )
+        .await?;
+    scheduler.do_try_match_for_test().await?;
+    assert!(rx_from_worker.try_recv().is_err());
+
+    // Once the cool-down passed the worker receives the retry.
+    scheduler
+        .worker_keep_alive_received(&worker_id, NOW_TIME + 10)
+        .await?;
+    scheduler.do_try_match_for_test().await?;
+    match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn memory_snapshot_restores_queued_actions_test() -> Result<(), Error> {
+    const SNAPSHOT_INTERVAL: Duration = Duration::from_millis(10);
+    let snapshot_path = format!(
+        "{}/{}.json",
+        env::var("TEST_TMPDIR").unwrap_or_else(|_| env::temp_dir().to_str().unwrap().to_string()),
+        Uuid::new_v4(),
+    );
+
+    let client_operation_id = {
+        let task_change_notify = Arc::new(Notify::new());
+        let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+            &SimpleSpec::default(),
+            memory_awaited_action_db_factory(
+                0,
+                &task_change_notify.clone(),
+                MockInstantWrapped::default,
+            )
+            .with_snapshots(snapshot_path.clone(), SNAPSHOT_INTERVAL),
+            || async move {},
+            task_change_notify,
+            MockInstantWrapped::default,
+            None,
+        );
+        let action_listener = setup_action(
+            &scheduler,
+            DigestInfo::new([99u8; 32], 512),
+            HashMap::new(),
+            make_system_time(1),
+        )
+        .await?;
+        let (action_state, _maybe_origin_metadata) = action_listener.as_state().await?;
+        // Give the scheduler time to write a snapshot.
+        tokio::time::sleep(SNAPSHOT_INTERVAL * 5).await;
+        action_state.client_operation_id.clone()
+    };
+
+    // A new scheduler picks up the queued action from the snapshot.
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        )
+        .with_snapshots(snapshot_path, SNAPSHOT_INTERVAL),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+    tokio::time::sleep(SNAPSHOT_INTERVAL).await;
+    let mut stream = scheduler
+        .filter_operations(OperationFilter {
+            client_operation_id: Some(client_operation_id),
+            ..Default::default()
+        })
+        .await?;
+    let action_state_result = stream.next().await.expect("Expected restored operation");
+    let (action_state, _maybe_origin_metadata) = action_state_result.as_state().await?;
+    assert_eq!(action_state.stage, ActionStage::Queued);
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn unschedulable_platform_does_not_block_other_platforms_test() -> Result<(), Error> {
+    let worker_id = WorkerId("worker_id".to_string());
+    let linux_action_digest = DigestInfo::new([33u8; 32], 512);
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            supported_platform_properties: Some(HashMap::from([(
+                "OSFamily".to_string(),
+                PropertyType::Exact,
+            )])),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let mut rx_from_worker = setup_new_worker(
+        &scheduler,
+        worker_id,
+        PlatformProperties {
+            properties: HashMap::from([(
+                "OSFamily".to_string(),
+                PlatformPropertyValue::Exact("linux".to_string()),
+            )]),
+        },
+    )
+    .await?;
+
+    // No worker can run these, and they are ahead of the linux action.
+    let windows_properties = HashMap::from([("OSFamily".to_string(), "windows".to_string())]);
+    let windows_action_listener1 = setup_action(
+        &scheduler,
+        DigestInfo::new([11u8; 32], 512),
+        windows_properties.clone(),
+        make_system_time(1),
+    )
+    .await?;
+    let windows_action_listener2 = setup_action(
+        &scheduler,
+        DigestInfo::new([22u8; 32], 512),
+        windows_properties,
+        make_system_time(2),
+    )
+    .await?;
+    let _linux_action_listener = setup_action(
+        &scheduler,
+        linux_action_digest,
+        HashMap::from([("OSFamily".to_string(), "linux".to_string())]),
+        make_system_time(3),
+    )
+    .await?;
+
+    match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            assert_eq!(
+                start_execute.execute_request.unwrap().action_digest,
+                Some(linux_action_digest.into())
+            );
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+
+    scheduler.do_try_match_for_test().await?;
+    assert!(rx_from_worker.try_recv().is_err());
+    for action_listener in [windows_action_listener1, windows_action_listener2] {
+        let (action_state, _maybe_origin_metadata) = action_listener.as_state().await?;
+        assert_eq!(action_state.stage, ActionStage::Queued);
+    }
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn sharded_workers_keep_allocation_order_test() -> Result<(), Error> {
+    let worker_id1 = WorkerId("worker_id1".to_string());
+    let worker_id2 = WorkerId("worker_id2".to_string());
+    let worker_id3 = WorkerId("worker_id3".to_string());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            allocation_strategy: WorkerAllocationStrategy::LeastRecentlyUsed,
+            worker_shards: 4,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let mut rx_from_worker1 =
+        setup_new_worker(&scheduler, worker_id1, PlatformProperties::default()).await?;
+    let mut rx_from_worker2 =
+        setup_new_worker(&scheduler, worker_id2, PlatformProperties::default()).await?;
+    let mut rx_from_worker3 =
+        setup_new_worker(&scheduler, worker_id3, PlatformProperties::default()).await?;
+
+    // Workers likely live in different shards, but the least recently used
+    // worker of the whole pool must still get each action.
+    for (i, rx_from_worker) in [
+        &mut rx_from_worker1,
+        &mut rx_from_worker2,
+        &mut rx_from_worker3,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let _action_listener = setup_action(
+            &scheduler,
+            DigestInfo::new([i as u8 + 1; 32], 512),
+            HashMap::new(),
+            make_system_time(i as u64 + 1),
+        )
+        .await?;
+        match rx_from_worker.recv().await.unwrap().update {
+            Some(update_for_worker::Update::StartAction(_)) => {}
+            v => panic!("Expected StartAction, got : {v:?}"),
+        }
+    }
+    assert!(rx_from_worker1.try_recv().is_err());
+    assert!(rx_from_worker2.try_recv().is_err());
+    assert!(rx_from_worker3.try_recv().is_err());
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn records_stage_dwell_times_test() -> Result<(), Error> {
+    let worker_id = WorkerId("worker_id".to_string());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let mut action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([99u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+
+    // The action waits in the queue until a worker shows up.
+    MockClock::advance(Duration::from_secs(3));
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id.clone(), PlatformProperties::default()).await?;
+    let operation_id = match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            assert_eq!(
+                action_listener.changed().await?.0.stage,
+                ActionStage::Executing
+            );
+            start_execute.operation_id
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+
+    // The worker reports spending 1 of its 4 seconds uploading outputs.
+    MockClock::advance(Duration::from_secs(4));
+    scheduler
+        .update_action(
+            &worker_id,
+            &OperationId::from(operation_id),
+            UpdateOperationType::UpdateWithActionStage(ActionStage::Completed(ActionResult {
+                execution_metadata: ExecutionMetadata {
+                    output_upload_start_timestamp: make_system_time(12),
+                    output_upload_completed_timestamp: make_system_time(13),
+                    ..ExecutionMetadata::default()
+                },
+                ..ActionResult::default()
+            })),
+        )
+        .await?;
+
+    let stage_dwell_times = scheduler.stage_dwell_times();
+    let queued = stage_dwell_times.histogram(DwellStage::Queued, INSTANCE_NAME, 0);
+    assert_eq!(queued.count(), 1);
+    assert_eq!(queued.count_le(Duration::from_secs(1)), 0);
+    assert_eq!(queued.count_le(Duration::from_secs(5)), 1);
+
+    let executing = stage_dwell_times.histogram(DwellStage::Executing, INSTANCE_NAME, 0);
+    assert_eq!(executing.count(), 1);
+    assert_eq!(executing.count_le(Duration::from_secs(1)), 0);
+    assert_eq!(executing.count_le(Duration::from_secs(5)), 1);
+
+    let result_upload = stage_dwell_times.histogram(DwellStage::ResultUpload, INSTANCE_NAME, 0);
+    assert_eq!(result_upload.count(), 1);
+    assert_eq!(result_upload.count_le(Duration::from_millis(500)), 0);
+    assert_eq!(result_upload.count_le(Duration::from_secs(1)), 1);
+
+    // Other priority classes have nothing recorded.
+    assert_eq!(
+        stage_dwell_times
+            .histogram(DwellStage::Queued, INSTANCE_NAME, 1)
+            .count(),
+        0
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn explain_placement_reports_why_workers_do_not_match_test() -> Result<(), Error> {
+    let busy_worker_id = WorkerId("busy_worker_id".to_string());
+    let bare_worker_id = WorkerId("bare_worker_id".to_string());
+    let draining_worker_id = WorkerId("draining_worker_id".to_string());
+
+    let mut supported_props = HashMap::new();
+    supported_props.insert("prop1".to_string(), PropertyType::Minimum);
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            supported_platform_properties: Some(supported_props),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let platform_properties = PlatformProperties {
+        properties: HashMap::from([("prop1".to_string(), PlatformPropertyValue::Minimum(1))]),
+    };
+    let action_props = HashMap::from([("prop1".to_string(), "1".to_string())]);
+    let mut rx_from_busy_worker = setup_new_worker(
+        &scheduler,
+        busy_worker_id.clone(),
+        platform_properties.clone(),
+    )
+    .await?;
+    let _running_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([11u8; 32], 512),
+        action_props.clone(),
+        make_system_time(1),
+    )
+    .await?;
+    match rx_from_busy_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+
+    let _rx_from_bare_worker = setup_new_worker(
+        &scheduler,
+        bare_worker_id.clone(),
+        PlatformProperties::default(),
+    )
+    .await?;
+    let _rx_from_draining_worker =
+        setup_new_worker(&scheduler, draining_worker_id.clone(), platform_properties).await?;
+    scheduler
+        .set_drain_worker(&draining_worker_id, true)
+        .await?;
+
+    let mut action_info =
+        make_base_action_info(make_system_time(2), DigestInfo::new([22u8; 32], 512));
+    Arc::make_mut(&mut action_info).platform_properties = action_props.clone();
+    let client_operation_id = OperationId::default();
+    let _queued_listener = scheduler
+        .add_action(client_operation_id.clone(), action_info)
+        .await?;
+    tokio::task::yield_now().await; // Allow task<->worker matcher to run.
+
+    let explanation = scheduler.explain_placement(&client_operation_id).await?;
+    assert_eq!(explanation.operation_id, client_operation_id);
+    assert_eq!(explanation.stage, "Queued");
+    assert_eq!(
+        explanation.platform_properties,
+        action_props.into_iter().collect::<BTreeMap<_, _>>()
+    );
+    let statuses: HashMap<WorkerId, WorkerPlacementStatus> = explanation
+        .workers
+        .into_iter()
+        .map(|placement| (placement.worker_id, placement.status))
+        .collect();
+    assert_eq!(
+        statuses,
+        HashMap::from([
+            (
+                busy_worker_id,
+                WorkerPlacementStatus::Busy {
+                    property: "prop1".to_string()
+                }
+            ),
+            (
+                bare_worker_id,
+                WorkerPlacementStatus::MissingProperty {
+                    property: "prop1".to_string()
+                }
+            ),
+            (draining_worker_id, WorkerPlacementStatus::Draining),
+        ])
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn client_disconnect_grace_period_test() -> Result<(), Error> {
+    const CLIENT_ACTION_TIMEOUT_S: u64 = 60;
+    const GRACE_PERIOD_S: u64 = 30;
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &SimpleSpec {
+            client_action_timeout_s: CLIENT_ACTION_TIMEOUT_S,
+            client_disconnect_behavior: ClientDisconnectBehavior::GracePeriod(GRACE_PERIOD_S),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            &task_change_notify.clone(),
+            MockInstantWrapped::default,
+        )
+        .keep_unfinished_operations_without_clients(),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+        None,
+    );
+
+    let mut action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([99u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+
+    // The client stopped sending keep alives, but may still come back.
+    MockClock::advance(Duration::from_secs(CLIENT_ACTION_TIMEOUT_S + 1));
+    scheduler.do_try_match_for_test().await?;
+    assert_eq!(
+        action_listener.as_state().await?.0.stage,
+        ActionStage::Queued
+    );
+
+    MockClock::advance(Duration::from_secs(GRACE_PERIOD_S));
+    scheduler.do_try_match_for_test().await?;
+    match action_listener.changed().await?.0.stage {
+        ActionStage::Completed(action_result) => assert_eq!(
+            action_result.error.map(|err| err.code),
+            Some(Code::DeadlineExceeded)
+        ),
+        stage => panic!("Expected Completed, got : {stage:?}"),
+    }
+
+    Ok(())
+}
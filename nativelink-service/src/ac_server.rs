@@ -16,14 +16,14 @@ use core::convert::Into;
 use core::fmt::Debug;
 use std::collections::HashMap;
 
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use nativelink_config::cas_server::{AcStoreConfig, WithInstanceName};
 use nativelink_error::{Code, Error, ResultExt, make_err, make_input_err};
 use nativelink_proto::build::bazel::remote::execution::v2::action_cache_server::{
     ActionCache, ActionCacheServer as Server,
 };
 use nativelink_proto::build::bazel::remote::execution::v2::{
-    ActionResult, GetActionResultRequest, UpdateActionResultRequest,
+    ActionResult, Digest, GetActionResultRequest, UpdateActionResultRequest,
 };
 use nativelink_store::ac_utils::{ESTIMATED_DIGEST_SIZE, get_and_decode_digest};
 use nativelink_store::grpc_store::GrpcStore;
@@ -34,12 +34,52 @@ use nativelink_util::store_trait::{Store, StoreLike};
 use opentelemetry::context::FutureExt;
 use prost::Message;
 use tonic::{Request, Response, Status};
-use tracing::{Instrument, Level, error, error_span, instrument};
+use tracing::{Instrument, Level, error, error_span, instrument, warn};
+
+// Default largest blob inlined into an `ActionResult`.
+// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_INLINE_BLOB_SIZE: u64 = 16 * 1024;
+
+/// Most bytes inlined into a single `ActionResult`, which keeps responses
+/// well below the default gRPC message size limit of 4MiB.
+const MAX_INLINE_BYTES_PER_RESULT: u64 = 1024 * 1024;
 
 #[derive(Debug, Clone)]
 pub struct AcStoreInfo {
     store: Store,
     read_only: bool,
+    inline_cas_store: Option<Store>,
+    max_inline_blob_size: u64,
+}
+
+/// Reads the blob of `digest` from `cas_store` if it is small enough to be
+/// inlined and fits into the remaining `budget`. Blobs that can't be read
+/// are left for the client to fetch.
+async fn read_for_inlining(
+    cas_store: &Store,
+    digest: Option<&Digest>,
+    max_inline_blob_size: u64,
+    budget: &mut u64,
+) -> Option<Bytes> {
+    let digest = DigestInfo::try_from(digest?).ok()?;
+    let size = digest.size_bytes();
+    if size == 0 || size > max_inline_blob_size || size > *budget {
+        return None;
+    }
+    match cas_store.get_part_unchunked(digest, 0, None).await {
+        Ok(data) => {
+            *budget -= size;
+            Some(data)
+        }
+        Err(err) => {
+            warn!(
+                ?digest,
+                ?err,
+                "Failed to read blob to inline into ActionResult"
+            );
+            None
+        }
+    }
 }
 
 pub struct AcServer {
@@ -62,11 +102,31 @@ impl AcServer {
             let store = store_manager.get_store(&config.ac_store).ok_or_else(|| {
                 make_input_err!("'ac_store': '{}' does not exist", config.ac_store)
             })?;
+            let inline_cas_store = if config.inline_cas_store.is_empty() {
+                None
+            } else {
+                Some(
+                    store_manager
+                        .get_store(&config.inline_cas_store)
+                        .ok_or_else(|| {
+                            make_input_err!(
+                                "'inline_cas_store': '{}' does not exist",
+                                config.inline_cas_store
+                            )
+                        })?,
+                )
+            };
             stores.insert(
                 config.instance_name.to_string(),
                 AcStoreInfo {
                     store,
                     read_only: config.read_only,
+                    inline_cas_store,
+                    max_inline_blob_size: if config.max_inline_blob_size == 0 {
+                        DEFAULT_MAX_INLINE_BLOB_SIZE
+                    } else {
+                        config.max_inline_blob_size
+                    },
                 },
             );
         }
@@ -106,7 +166,10 @@ impl AcServer {
 
         let res = get_and_decode_digest::<ActionResult>(&store_info.store, digest.into()).await;
         match res {
-            Ok(action_result) => Ok(Response::new(action_result)),
+            Ok(mut action_result) => {
+                Self::inline_blobs(store_info, &request, &mut action_result).await;
+                Ok(Response::new(action_result))
+            }
             Err(mut e) => {
                 if e.code == Code::NotFound {
                     // `get_action_result` is frequent to get NotFound errors, so remove all
@@ -118,6 +181,59 @@ impl AcServer {
         }
     }
 
+    /// Inlines the stdout, stderr and output files the client asked for
+    /// into `action_result`, as far as the configured limits allow.
+    async fn inline_blobs(
+        store_info: &AcStoreInfo,
+        request: &GetActionResultRequest,
+        action_result: &mut ActionResult,
+    ) {
+        let Some(cas_store) = &store_info.inline_cas_store else {
+            return;
+        };
+        let max_size = store_info.max_inline_blob_size;
+        let mut budget = MAX_INLINE_BYTES_PER_RESULT;
+        if request.inline_stdout {
+            if let Some(data) = read_for_inlining(
+                cas_store,
+                action_result.stdout_digest.as_ref(),
+                max_size,
+                &mut budget,
+            )
+            .await
+            {
+                action_result.stdout_raw = data;
+            }
+        }
+        if request.inline_stderr {
+            if let Some(data) = read_for_inlining(
+                cas_store,
+                action_result.stderr_digest.as_ref(),
+                max_size,
+                &mut budget,
+            )
+            .await
+            {
+                action_result.stderr_raw = data;
+            }
+        }
+        for output_file in &mut action_result.output_files {
+            if !request.inline_output_files.contains(&output_file.path) {
+                continue;
+            }
+            if let Some(data) = read_for_inlining(
+                cas_store,
+                output_file.digest.as_ref(),
+                max_size,
+                &mut budget,
+            )
+            .await
+            {
+                output_file.contents = data;
+            }
+        }
+    }
+
     async fn inner_update_action_result(
         &self,
         request: UpdateActionResultRequest,
@@ -0,0 +1,570 @@
+// Copyright 2025 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::Router;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{Method, StatusCode, header};
+use axum::middleware::{Next, from_fn_with_state};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use futures::StreamExt;
+use nativelink_config::cas_server::AdminConfig;
+use nativelink_config::stores::StoreSpec;
+use nativelink_error::{Code, Error, ResultExt, make_err, make_input_err};
+use nativelink_scheduler::worker_scheduler::WorkerScheduler;
+use nativelink_store::default_store_factory::store_factory;
+use nativelink_store::store_manager::StoreManager;
+use nativelink_util::background_spawn;
+use nativelink_util::operation_state_manager::{
+    ClientStateManager, OperationFilter, OperationStageFlags,
+};
+use nativelink_util::shutdown_guard::{Priority, ShutdownGuard};
+use rustls_pki_types::{CertificateDer, ServerName};
+use tokio::sync::broadcast;
+use tokio::time::sleep;
+use tracing::warn;
+use webpki::EndEntityCert;
+
+/// Content type header value for JSON.
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// How often a shutdown checks whether the executing operations finished.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Client certificate the connection of a request was authenticated with.
+/// Listeners verifying client certificates insert it as an extension into
+/// every request of the connection.
+#[derive(Clone, Debug)]
+pub struct PeerCertificate(pub CertificateDer<'static>);
+
+/// Access granted to a request of the admin API. Read-only requests may
+/// only use the `GET` routes, admin requests may use every route.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Role {
+    ReadOnly,
+    Admin,
+}
+
+struct AdminAuth {
+    bearer_token: Option<String>,
+    read_only_bearer_token: Option<String>,
+    client_cert_names: Vec<ServerName<'static>>,
+    read_only_client_cert_names: Vec<ServerName<'static>>,
+}
+
+impl AdminAuth {
+    fn new(config: &AdminConfig) -> Result<Self, Error> {
+        let parse_names = |names: &[String]| {
+            names
+                .iter()
+                .map(|name| {
+                    ServerName::try_from(name.clone()).map_err(|e| {
+                        make_input_err!("Invalid client certificate name '{name}' - {e:?}")
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()
+        };
+        Ok(Self {
+            bearer_token: config.bearer_token.clone(),
+            read_only_bearer_token: config.read_only_bearer_token.clone(),
+            client_cert_names: parse_names(&config.client_cert_names)?,
+            read_only_client_cert_names: parse_names(&config.read_only_client_cert_names)?,
+        })
+    }
+
+    const fn is_enabled(&self) -> bool {
+        self.bearer_token.is_some()
+            || self.read_only_bearer_token.is_some()
+            || !self.client_cert_names.is_empty()
+            || !self.read_only_client_cert_names.is_empty()
+    }
+
+    /// Returns the highest role granted to `req` by its bearer token or
+    /// client certificate.
+    fn role(&self, req: &Request) -> Option<Role> {
+        let bearer_token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.as_bytes().strip_prefix(b"Bearer "));
+        let has_token = |expected: Option<&str>| {
+            bearer_token
+                .zip(expected)
+                .is_some_and(|(token, expected)| constant_time_eq(token, expected.as_bytes()))
+        };
+        let end_entity = req
+            .extensions()
+            .get::<PeerCertificate>()
+            .and_then(|cert| EndEntityCert::try_from(&cert.0).ok());
+        let has_cert_name = |names: &[ServerName<'static>]| {
+            end_entity.as_ref().is_some_and(|end_entity| {
+                names
+                    .iter()
+                    .any(|name| end_entity.verify_is_valid_for_subject_name(name).is_ok())
+            })
+        };
+        if has_token(self.bearer_token.as_deref()) || has_cert_name(&self.client_cert_names) {
+            Some(Role::Admin)
+        } else if has_token(self.read_only_bearer_token.as_deref())
+            || has_cert_name(&self.read_only_client_cert_names)
+        {
+            Some(Role::ReadOnly)
+        } else {
+            None
+        }
+    }
+}
+
+/// Compares in constant time to avoid leaking tokens through response timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+async fn authorize(State(auth): State<Arc<AdminAuth>>, req: Request, next: Next) -> Response {
+    let required_role = if req.method() == Method::GET || req.method() == Method::HEAD {
+        Role::ReadOnly
+    } else {
+        Role::Admin
+    };
+    match auth.role(&req) {
+        Some(role) if role >= required_role => next.run(req).await,
+        Some(_) => (StatusCode::FORBIDDEN, "Forbidden").into_response(),
+        None => (StatusCode::UNAUTHORIZED, "Unauthorized").into_response(),
+    }
+}
+
+fn error_response(e: &Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {e:?}"))
+}
+
+/// HTTP API to operate a running instance: drain workers, cancel
+/// operations, inspect the queues of the schedulers and manage stores.
+pub struct AdminServer {
+    auth: Arc<AdminAuth>,
+    action_schedulers: HashMap<String, Arc<dyn ClientStateManager>>,
+    worker_schedulers: HashMap<String, Arc<dyn WorkerScheduler>>,
+    store_manager: Arc<StoreManager>,
+    shutdown_tx: broadcast::Sender<ShutdownGuard>,
+    /// Whether a shutdown was already requested.
+    is_shutting_down: AtomicBool,
+}
+
+impl core::fmt::Debug for AdminServer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("AdminServer").finish_non_exhaustive()
+    }
+}
+
+impl AdminServer {
+    pub fn new(
+        config: &AdminConfig,
+        action_schedulers: &HashMap<String, Arc<dyn ClientStateManager>>,
+        worker_schedulers: &HashMap<String, Arc<dyn WorkerScheduler>>,
+        store_manager: &Arc<StoreManager>,
+        shutdown_tx: broadcast::Sender<ShutdownGuard>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            auth: Arc::new(AdminAuth::new(config).err_tip(|| "In AdminServer::new")?),
+            action_schedulers: action_schedulers.clone(),
+            worker_schedulers: worker_schedulers.clone(),
+            store_manager: store_manager.clone(),
+            shutdown_tx,
+            is_shutting_down: AtomicBool::new(false),
+        })
+    }
+
+    pub fn into_router(self) -> Router {
+        let auth = self.auth.clone();
+        let router = Router::new()
+            .route("/scheduler/{instance_name}/drain", get(drain_status))
+            .route("/scheduler/{instance_name}/queue_depth", get(queue_depth))
+            .route(
+                "/scheduler/{instance_name}/explain_placement/{operation_id}",
+                get(explain_placement),
+            )
+            .route("/stores", get(list_stores));
+        if !auth.is_enabled() {
+            return router.with_state(Arc::new(self));
+        }
+        // Schedulers, workers, operations and stores may only be changed by
+        // authenticated admins, stores can read and write arbitrary
+        // locations too.
+        router
+            .route(
+                "/scheduler/{instance_name}/drain",
+                post(start_drain).delete(stop_drain),
+            )
+            .route(
+                "/scheduler/{instance_name}/set_drain_worker/{worker_id}/{is_draining}",
+                post(set_drain_worker),
+            )
+            .route(
+                "/scheduler/{instance_name}/drain_worker/{worker_id}/{deadline_s}",
+                post(drain_worker),
+            )
+            .route(
+                "/scheduler/{instance_name}/cancel_operation/{operation_id}",
+                post(cancel_operation),
+            )
+            .route("/stores/{name}", put(add_store).delete(remove_store))
+            .route("/stores/{name}/repoint/{target}", post(repoint_store))
+            .route("/shutdown", post(shutdown))
+            .with_state(Arc::new(self))
+            .layer(from_fn_with_state(auth, authorize))
+    }
+
+    fn action_scheduler(&self, instance_name: &str) -> Result<&dyn ClientStateManager, Error> {
+        self.action_schedulers
+            .get(instance_name)
+            .map(Arc::as_ref)
+            .err_tip(|| format!("Can not get an instance with the name of '{instance_name}'"))
+    }
+
+    fn worker_scheduler(&self, instance_name: &str) -> Result<&dyn WorkerScheduler, Error> {
+        self.worker_schedulers
+            .get(instance_name)
+            .map(Arc::as_ref)
+            .err_tip(|| format!("Can not get an instance with the name of '{instance_name}'"))
+    }
+}
+
+async fn set_drain_worker(
+    State(server): State<Arc<AdminServer>>,
+    Path((instance_name, worker_id, is_draining)): Path<(String, String, String)>,
+) -> Result<String, (StatusCode, String)> {
+    async {
+        let is_draining = match is_draining.as_str() {
+            "0" => false,
+            "1" => true,
+            _ => {
+                return Err(make_input_err!("{is_draining} is neither 0 nor 1"));
+            }
+        };
+        server
+            .worker_scheduler(&instance_name)?
+            .set_drain_worker(&worker_id.clone().into(), is_draining)
+            .await?;
+        Ok::<_, Error>(format!("Draining worker {worker_id}"))
+    }
+    .await
+    .map_err(|e| error_response(&e))
+}
+
+async fn drain_worker(
+    State(server): State<Arc<AdminServer>>,
+    Path((instance_name, worker_id, deadline_s)): Path<(String, String, u64)>,
+) -> Result<String, (StatusCode, String)> {
+    async {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| make_err!(Code::Internal, "System time is now behind unix epoch"))?;
+        server
+            .worker_scheduler(&instance_name)?
+            .drain_worker_with_deadline(&worker_id.clone().into(), now.as_secs() + deadline_s)
+            .await?;
+        Ok::<_, Error>(format!(
+            "Draining worker {worker_id}, removing it in {deadline_s}s"
+        ))
+    }
+    .await
+    .map_err(|e| error_response(&e))
+}
+
+async fn cancel_operation(
+    State(server): State<Arc<AdminServer>>,
+    Path((instance_name, operation_id)): Path<(String, String)>,
+) -> Result<String, (StatusCode, String)> {
+    async {
+        server
+            .action_scheduler(&instance_name)?
+            .cancel_operation(&operation_id.clone().into())
+            .await?;
+        Ok::<_, Error>(format!("Cancelled operation {operation_id}"))
+    }
+    .await
+    .map_err(|e| error_response(&e))
+}
+
+/// Reports whether the scheduler is draining and how many operations are
+/// still executing, as JSON.
+async fn drain_status_json(server: &AdminServer, instance_name: &str) -> Result<String, Error> {
+    let is_draining = server.worker_scheduler(instance_name)?.is_draining();
+    let executing = server
+        .action_scheduler(instance_name)?
+        .count_operations(OperationFilter {
+            stages: OperationStageFlags::Executing,
+            ..Default::default()
+        })
+        .await
+        .err_tip(|| "Failed to count executing operations in drain_status_json")?;
+    Ok(format!(
+        r#"{{"draining":{is_draining},"executing":{executing}}}"#
+    ))
+}
+
+/// Sets the scheduler draining or not and reports its drain status.
+async fn set_draining(
+    server: &AdminServer,
+    instance_name: &str,
+    is_draining: bool,
+) -> Result<String, Error> {
+    server
+        .worker_scheduler(instance_name)?
+        .set_draining(is_draining)?;
+    warn!(
+        instance_name,
+        is_draining, "Scheduler draining set via admin API"
+    );
+    drain_status_json(server, instance_name).await
+}
+
+async fn drain_status(
+    State(server): State<Arc<AdminServer>>,
+    Path(instance_name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    drain_status_json(&server, &instance_name)
+        .await
+        .map(|body| ([(header::CONTENT_TYPE, JSON_CONTENT_TYPE)], body))
+        .map_err(|e| error_response(&e))
+}
+
+/// Stops matching queued operations to workers, the executing operations
+/// keep running.
+async fn start_drain(
+    State(server): State<Arc<AdminServer>>,
+    Path(instance_name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    set_draining(&server, &instance_name, true)
+        .await
+        .map(|body| ([(header::CONTENT_TYPE, JSON_CONTENT_TYPE)], body))
+        .map_err(|e| error_response(&e))
+}
+
+async fn stop_drain(
+    State(server): State<Arc<AdminServer>>,
+    Path(instance_name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    set_draining(&server, &instance_name, false)
+        .await
+        .map(|body| ([(header::CONTENT_TYPE, JSON_CONTENT_TYPE)], body))
+        .map_err(|e| error_response(&e))
+}
+
+async fn queue_depth(
+    State(server): State<Arc<AdminServer>>,
+    Path(instance_name): Path<String>,
+    // Query parameters select the platform properties of the operations
+    // to count, eg: `?OSFamily=linux`.
+    Query(platform_properties): Query<BTreeMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    async {
+        queue_depth_json(
+            server.action_scheduler(&instance_name)?,
+            platform_properties,
+        )
+        .await
+    }
+    .await
+    .map(|body| ([(header::CONTENT_TYPE, JSON_CONTENT_TYPE)], body))
+    .map_err(|e| error_response(&e))
+}
+
+/// Reports the number of queued and executing operations and how long the
+/// oldest queued operation has been waiting, as JSON that autoscalers such as
+/// the KEDA `metrics-api` scaler can poll. Only operations that request all of
+/// `platform_properties` are counted.
+async fn queue_depth_json(
+    action_scheduler: &dyn ClientStateManager,
+    platform_properties: BTreeMap<String, String>,
+) -> Result<String, Error> {
+    let now = SystemTime::now();
+    let mut queued = 0;
+    let mut oldest_queued_s = 0;
+    let mut queued_operations = action_scheduler
+        .filter_operations(OperationFilter {
+            stages: OperationStageFlags::Queued,
+            platform_properties: platform_properties.clone(),
+            ..Default::default()
+        })
+        .await
+        .err_tip(|| "Failed to get queued operations in queue_depth_json")?;
+    while let Some(action_state_result) = queued_operations.next().await {
+        queued += 1;
+        // Not every scheduler can provide the action info, in which case
+        // only the count is reported.
+        if let Ok((action_info, _origin_metadata)) = action_state_result.as_action_info().await {
+            let waiting = now
+                .duration_since(action_info.insert_timestamp)
+                .unwrap_or_default();
+            oldest_queued_s = oldest_queued_s.max(waiting.as_secs());
+        }
+    }
+    drop(queued_operations);
+    let executing = action_scheduler
+        .count_operations(OperationFilter {
+            stages: OperationStageFlags::Executing,
+            platform_properties,
+            ..Default::default()
+        })
+        .await
+        .err_tip(|| "Failed to count executing operations in queue_depth_json")?;
+    Ok(format!(
+        r#"{{"queued":{queued},"executing":{executing},"oldest_queued_s":{oldest_queued_s}}}"#
+    ))
+}
+
+async fn explain_placement(
+    State(server): State<Arc<AdminServer>>,
+    Path((instance_name, operation_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    async {
+        let explanation = server
+            .worker_scheduler(&instance_name)?
+            .explain_placement(&operation_id.into())
+            .await?;
+        serde_json::to_string(&explanation).map_err(|e| {
+            make_err!(
+                Code::Internal,
+                "Failed to serialize placement explanation: {e}"
+            )
+        })
+    }
+    .await
+    .map(|body| ([(header::CONTENT_TYPE, JSON_CONTENT_TYPE)], body))
+    .map_err(|e| error_response(&e))
+}
+
+async fn list_stores(
+    State(server): State<Arc<AdminServer>>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    serde_json::to_string(&server.store_manager.store_names())
+        .map(|body| ([(header::CONTENT_TYPE, JSON_CONTENT_TYPE)], body))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error: Failed to serialize store names: {e}"),
+            )
+        })
+}
+
+/// Registers a store built from the `StoreSpec` in the body, replacing the
+/// store of the same name if any. Like repointing and removing stores, this
+/// only affects `ref_store`s and stores looked up afterwards, services keep
+/// the stores they resolved at startup.
+async fn add_store(
+    State(server): State<Arc<AdminServer>>,
+    Path(name): Path<String>,
+    body: String,
+) -> Result<String, (StatusCode, String)> {
+    async {
+        let spec: StoreSpec = serde_json::from_str(&body)
+            .map_err(|e| make_input_err!("Invalid store spec for '{name}': {e}"))?;
+        let store = store_factory(&spec, &server.store_manager, None)
+            .await
+            .err_tip(|| format!("Failed to create store '{name}'"))?;
+        server.store_manager.add_store(&name, store);
+        warn!(name, "Store added via admin API");
+        Ok::<_, Error>(format!("Added store {name}"))
+    }
+    .await
+    .map_err(|e| error_response(&e))
+}
+
+async fn remove_store(
+    State(server): State<Arc<AdminServer>>,
+    Path(name): Path<String>,
+) -> Result<String, (StatusCode, String)> {
+    if server.store_manager.remove_store(&name).is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Error: Store '{name}' does not exist"),
+        ));
+    }
+    warn!(name, "Store removed via admin API");
+    Ok(format!("Removed store {name}"))
+}
+
+async fn repoint_store(
+    State(server): State<Arc<AdminServer>>,
+    Path((name, target)): Path<(String, String)>,
+) -> Result<String, (StatusCode, String)> {
+    server
+        .store_manager
+        .repoint_store(&name, &target)
+        .map(|()| {
+            warn!(name, target, "Store repointed via admin API");
+            format!("Store {name} now points to {target}")
+        })
+        .map_err(|e| error_response(&e))
+}
+
+/// Counts the executing operations of every scheduler.
+async fn count_executing_operations(
+    action_schedulers: &HashMap<String, Arc<dyn ClientStateManager>>,
+) -> usize {
+    let mut executing_operations = 0;
+    for (instance_name, action_scheduler) in action_schedulers {
+        match action_scheduler
+            .count_operations(OperationFilter {
+                stages: OperationStageFlags::Executing,
+                ..Default::default()
+            })
+            .await
+        {
+            Ok(count) => executing_operations += count,
+            Err(err) => {
+                warn!(?err, ?instance_name, "Failed to count executing operations");
+            }
+        }
+    }
+    executing_operations
+}
+
+/// Drains every scheduler and shuts down once the executing operations
+/// finished.
+async fn shutdown(State(server): State<Arc<AdminServer>>) -> String {
+    // Only the first request waits for the executing operations to finish.
+    if server.is_shutting_down.swap(true, Ordering::AcqRel) {
+        return "Already shutting down".to_string();
+    }
+    for (instance_name, worker_scheduler) in &server.worker_schedulers {
+        if let Err(err) = worker_scheduler.set_draining(true) {
+            warn!(
+                ?err,
+                ?instance_name,
+                "Failed to drain scheduler for shutdown"
+            );
+        }
+    }
+    // Report how much work is still running so operators know how long
+    // the shutdown is expected to take.
+    let executing_operations = count_executing_operations(&server.action_schedulers).await;
+    warn!(executing_operations, "Shutdown requested via admin API");
+    background_spawn!("admin_shutdown", async move {
+        while count_executing_operations(&server.action_schedulers).await > 0 {
+            sleep(SHUTDOWN_POLL_INTERVAL).await;
+        }
+        let mut shutdown_guard = ShutdownGuard::default();
+        drop(server.shutdown_tx.send(shutdown_guard.clone()));
+        let () = shutdown_guard.wait_for(Priority::P0).await;
+        warn!("Successfully shut down nativelink.");
+        std::process::exit(0);
+    });
+    format!("Draining, shutting down once {executing_operations} executing operations finished")
+}
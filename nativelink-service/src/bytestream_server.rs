@@ -22,11 +22,12 @@ use std::collections::hash_map::Entry;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use futures::future::{BoxFuture, pending};
 use futures::stream::unfold;
 use futures::{Future, Stream, TryFutureExt, try_join};
 use nativelink_config::cas_server::{ByteStreamConfig, InstanceName, WithInstanceName};
-use nativelink_error::{Code, Error, ResultExt, make_err, make_input_err};
+use nativelink_error::{Code, Error, ResultExt, error_if, make_err, make_input_err};
 use nativelink_proto::google::bytestream::byte_stream_server::{
     ByteStream, ByteStreamServer as Server,
 };
@@ -46,7 +47,7 @@ use nativelink_util::digest_hasher::{
 use nativelink_util::proto_stream_utils::WriteRequestStreamWrapper;
 use nativelink_util::resource_info::ResourceInfo;
 use nativelink_util::spawn;
-use nativelink_util::store_trait::{Store, StoreLike, UploadSizeInfo};
+use nativelink_util::store_trait::{Store, StoreKey, StoreLike, UploadSizeInfo};
 use nativelink_util::task::JoinHandleDropGuard;
 use opentelemetry::context::FutureExt;
 use parking_lot::Mutex;
@@ -60,6 +61,9 @@ const DEFAULT_PERSIST_STREAM_ON_DISCONNECT_TIMEOUT: Duration = Duration::from_se
 /// If this value changes update the documentation in the config definition.
 const DEFAULT_MAX_BYTES_PER_STREAM: usize = 64 * 1024;
 
+/// If this value changes update the documentation in the config definition.
+const DEFAULT_UPLOAD_SEGMENT_SIZE: usize = 16 * 1024 * 1024;
+
 type BytesWrittenAndIdleStream = (Arc<AtomicU64>, Option<IdleStream>);
 type SleepFn = Arc<dyn Fn() -> BoxFuture<'static, ()> + Send + Sync>;
 
@@ -69,6 +73,9 @@ pub struct InstanceInfo {
     max_bytes_per_stream: usize,
     active_uploads: Arc<Mutex<HashMap<String, BytesWrittenAndIdleStream>>>,
     sleep_fn: SleepFn,
+    // Store partial uploads are persisted to, if configured.
+    upload_store: Option<Store>,
+    upload_segment_size: usize,
 }
 
 impl Debug for InstanceInfo {
@@ -77,10 +84,181 @@ impl Debug for InstanceInfo {
             .field("store", &self.store)
             .field("max_bytes_per_stream", &self.max_bytes_per_stream)
             .field("active_uploads", &self.active_uploads)
+            .field("upload_store", &self.upload_store)
+            .field("upload_segment_size", &self.upload_segment_size)
             .finish()
     }
 }
 
+/// Data of an upload that was persisted to the upload store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PersistedUpload {
+    committed_size: u64,
+    segment_size: u64,
+}
+
+/// Persists the data of an upload in fixed size segments to the upload
+/// store, so a client can resume it after the server restarted. The
+/// committed size is only written after the segment it covers.
+#[derive(Debug)]
+struct PartialUpload {
+    store: Store,
+    key_prefix: String,
+    // Data received after the last persisted segment.
+    buffer: BytesMut,
+    persisted: PersistedUpload,
+    // Set once the persisted state was looked up, which happens before the
+    // first data is received.
+    resumed: bool,
+    // Set if persisting failed, the upload then continues in memory only.
+    failed: bool,
+}
+
+impl PartialUpload {
+    fn new(store: Store, uuid: &str, digest: DigestInfo, segment_size: usize) -> Self {
+        Self {
+            store,
+            key_prefix: format!("bytestream-upload-{uuid}-{digest}"),
+            buffer: BytesMut::new(),
+            persisted: PersistedUpload {
+                committed_size: 0,
+                segment_size: segment_size as u64,
+            },
+            resumed: false,
+            failed: false,
+        }
+    }
+
+    fn segment_key(&self, index: u64) -> StoreKey<'static> {
+        StoreKey::new_str(&format!("{}-{index}", self.key_prefix)).into_owned()
+    }
+
+    fn segment_keys(&self, persisted: PersistedUpload) -> Vec<StoreKey<'static>> {
+        (0..persisted.committed_size / persisted.segment_size)
+            .map(|index| self.segment_key(index))
+            .collect()
+    }
+
+    /// Returns the persisted state of the upload if all of its segments are
+    /// still in the store.
+    async fn load(&self) -> Result<Option<PersistedUpload>, Error> {
+        let key = StoreKey::new_str(&self.key_prefix);
+        let mut data = match self.store.get_part_unchunked(key, 0, None).await {
+            Ok(data) => data,
+            Err(err) if err.code == Code::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        error_if!(
+            data.len() != 16,
+            "Persisted upload {} has an invalid size of {} bytes",
+            self.key_prefix,
+            data.len()
+        );
+        let persisted = PersistedUpload {
+            committed_size: data.get_u64_le(),
+            segment_size: data.get_u64_le(),
+        };
+        error_if!(
+            persisted.segment_size == 0,
+            "Persisted upload {} has a segment size of zero",
+            self.key_prefix
+        );
+        let segment_keys = self.segment_keys(persisted);
+        let sizes = self
+            .store
+            .has_many(&segment_keys)
+            .await
+            .err_tip(|| "Checking segments of persisted upload")?;
+        if sizes.iter().any(Option::is_none) {
+            info!(key = ?self.key_prefix, "Segments of persisted upload were evicted");
+            return Ok(None);
+        }
+        Ok(Some(persisted))
+    }
+
+    /// Sends the persisted segments of the upload into `tx`. Only done for
+    /// new streams, a stream that was idle still has its data.
+    async fn resume(&mut self, tx: &mut DropCloserWriteHalf) -> Result<(), Error> {
+        if self.resumed {
+            return Ok(());
+        }
+        self.resumed = true;
+        let persisted = match self.load().await {
+            Ok(Some(persisted)) => persisted,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                warn!(?err, key = ?self.key_prefix, "Failed to load persisted upload");
+                return Ok(());
+            }
+        };
+        info!(
+            key = ?self.key_prefix,
+            committed_size = persisted.committed_size,
+            "Resuming persisted upload"
+        );
+        for key in self.segment_keys(persisted) {
+            let data = self
+                .store
+                .get_part_unchunked(key, 0, None)
+                .await
+                .err_tip(|| "Reading segment of persisted upload")?;
+            tx.send(data)
+                .await
+                .err_tip(|| "Sending segment of persisted upload")?;
+        }
+        self.persisted = persisted;
+        Ok(())
+    }
+
+    /// Adds `data` to the upload and persists every full segment.
+    async fn push(&mut self, data: &Bytes) {
+        if self.failed {
+            return;
+        }
+        self.buffer.extend_from_slice(data);
+        let segment_size = self.persisted.segment_size as usize;
+        while self.buffer.len() >= segment_size {
+            let segment = self.buffer.split_to(segment_size).freeze();
+            if let Err(err) = self.persist_segment(segment).await {
+                warn!(?err, key = ?self.key_prefix, "Failed to persist upload segment");
+                self.failed = true;
+                self.buffer = BytesMut::new();
+                return;
+            }
+        }
+    }
+
+    async fn persist_segment(&mut self, segment: Bytes) -> Result<(), Error> {
+        let index = self.persisted.committed_size / self.persisted.segment_size;
+        let segment_len = segment.len() as u64;
+        self.store
+            .update_oneshot(self.segment_key(index), segment)
+            .await
+            .err_tip(|| "Writing upload segment")?;
+        self.persisted.committed_size += segment_len;
+        let mut manifest = BytesMut::with_capacity(16);
+        manifest.put_u64_le(self.persisted.committed_size);
+        manifest.put_u64_le(self.persisted.segment_size);
+        self.store
+            .update_oneshot(StoreKey::new_str(&self.key_prefix), manifest.freeze())
+            .await
+            .err_tip(|| "Writing committed size of upload")
+    }
+
+    /// Removes the persisted data once the upload is complete.
+    async fn finish(&self) {
+        if self.persisted.committed_size == 0 {
+            return;
+        }
+        let manifest_key = StoreKey::new_str(&self.key_prefix).into_owned();
+        for key in core::iter::once(manifest_key).chain(self.segment_keys(self.persisted)) {
+            if let Err(err) = self.store.remove(key.borrow()).await {
+                warn!(?err, ?key, "Failed to remove persisted upload data");
+            }
+        }
+    }
+}
+
 type ReadStream = Pin<Box<dyn Stream<Item = Result<ReadResponse, Status>> + Send + 'static>>;
 type StoreUpdateFuture = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>;
 
@@ -88,6 +266,7 @@ struct StreamState {
     uuid: String,
     tx: DropCloserWriteHalf,
     store_update_fut: StoreUpdateFuture,
+    partial_upload: Option<PartialUpload>,
 }
 
 impl Debug for StreamState {
@@ -223,11 +402,29 @@ impl ByteStreamServer {
         } else {
             config.max_bytes_per_stream
         };
+        let upload_store = if config.upload_store.is_empty() {
+            None
+        } else {
+            Some(
+                store_manager
+                    .get_store(&config.upload_store)
+                    .ok_or_else(|| {
+                        make_input_err!("'upload_store': '{}' does not exist", config.upload_store)
+                    })?,
+            )
+        };
+        let upload_segment_size = if config.upload_segment_size == 0 {
+            DEFAULT_UPLOAD_SEGMENT_SIZE
+        } else {
+            config.upload_segment_size
+        };
         Ok(InstanceInfo {
             store,
             max_bytes_per_stream,
             active_uploads: Arc::new(Mutex::new(HashMap::new())),
             sleep_fn,
+            upload_store,
+            upload_segment_size,
         })
     }
 
@@ -302,11 +499,25 @@ impl ByteStreamServer {
                 .update(digest, rx, UploadSizeInfo::ExactSize(digest.size_bytes()))
                 .await
         });
+        // Uploads that fit into one segment are never persisted.
+        let partial_upload = instance
+            .upload_store
+            .as_ref()
+            .filter(|_| digest.size_bytes() > instance.upload_segment_size as u64)
+            .map(|upload_store| {
+                PartialUpload::new(
+                    upload_store.clone(),
+                    &uuid,
+                    digest,
+                    instance.upload_segment_size,
+                )
+            });
         ActiveStreamGuard {
             stream_state: Some(StreamState {
                 uuid,
                 tx,
                 store_update_fut,
+                partial_upload,
             }),
             bytes_received,
             active_uploads: instance.active_uploads.clone(),
@@ -451,9 +662,14 @@ impl ByteStreamServer {
                 impl Stream<Item = Result<WriteRequest, Status>> + Unpin,
             >,
             tx: &mut DropCloserWriteHalf,
+            mut partial_upload: Option<&mut PartialUpload>,
             outer_bytes_received: &Arc<AtomicU64>,
             expected_size: u64,
         ) -> Result<(), Error> {
+            if let Some(partial_upload) = partial_upload.as_deref_mut() {
+                partial_upload.resume(tx).await?;
+                outer_bytes_received.store(tx.get_bytes_written(), Ordering::Release);
+            }
             loop {
                 let write_request = match stream.next().await {
                     // Code path for when client tries to gracefully close the stream.
@@ -510,6 +726,9 @@ impl ByteStreamServer {
 
                 // Do not process EOF or weird stuff will happen.
                 if !data.is_empty() {
+                    if let Some(partial_upload) = partial_upload.as_deref_mut() {
+                        partial_upload.push(&data).await;
+                    }
                     // We also need to process the possible EOF branch, so we can't early return.
                     if let Err(mut err) = tx.send(data).await {
                         err.code = Code::Internal;
@@ -546,12 +765,16 @@ impl ByteStreamServer {
             process_client_stream(
                 stream,
                 &mut active_stream.tx,
+                active_stream.partial_upload.as_mut(),
                 &active_stream_guard.bytes_received,
                 expected_size
             ),
             (&mut active_stream.store_update_fut)
                 .map_err(|err| { err.append("Error updating inner store") })
         )?;
+        if let Some(partial_upload) = &active_stream.partial_upload {
+            partial_upload.finish().await;
+        }
 
         // Close our guard and consider the stream no longer active.
         active_stream_guard.graceful_finish();
@@ -606,6 +829,21 @@ impl ByteStreamServer {
 
         let has_fut = store_clone.has(digest);
         let Some(item_size) = has_fut.await.err_tip(|| "Failed to call .has() on store")? else {
+            // The upload may have been persisted before the server restarted.
+            if let Some(upload_store) = &instance.upload_store {
+                let partial_upload = PartialUpload::new(
+                    upload_store.clone(),
+                    uuid.as_ref(),
+                    digest,
+                    instance.upload_segment_size,
+                );
+                if let Some(persisted) = partial_upload.load().await? {
+                    return Ok(Response::new(QueryWriteStatusResponse {
+                        committed_size: persisted.committed_size as i64,
+                        complete: false,
+                    }));
+                }
+            }
             // We lie here and say that the stream needs to start over, even though
             // it was never started. This can happen when the client disconnects
             // before sending the first payload, but the client thinks it did send
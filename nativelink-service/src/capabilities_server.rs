@@ -16,11 +16,10 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use nativelink_config::cas_server::{CapabilitiesConfig, InstanceName, WithInstanceName};
-use nativelink_error::{Error, ResultExt};
+use nativelink_error::{Error, ResultExt, error_if};
 use nativelink_proto::build::bazel::remote::execution::v2::capabilities_server::{
     Capabilities, CapabilitiesServer as Server,
 };
-use nativelink_proto::build::bazel::remote::execution::v2::digest_function::Value as DigestFunction;
 use nativelink_proto::build::bazel::remote::execution::v2::priority_capabilities::PriorityRange;
 use nativelink_proto::build::bazel::remote::execution::v2::symlink_absolute_path_strategy::Value as SymlinkAbsolutePathStrategy;
 use nativelink_proto::build::bazel::remote::execution::v2::{
@@ -28,7 +27,7 @@ use nativelink_proto::build::bazel::remote::execution::v2::{
     GetCapabilitiesRequest, PriorityCapabilities, ServerCapabilities,
 };
 use nativelink_proto::build::bazel::semver::SemVer;
-use nativelink_util::digest_hasher::default_digest_hasher_func;
+use nativelink_util::digest_hasher::{DigestHasherFunc, default_digest_hasher_func};
 use nativelink_util::operation_state_manager::ClientStateManager;
 use tonic::{Request, Response, Status};
 use tracing::{Level, instrument, warn};
@@ -38,6 +37,20 @@ const MAX_BATCH_TOTAL_SIZE: i64 = 64 * 1024;
 #[derive(Debug, Default)]
 pub struct CapabilitiesServer {
     supported_node_properties_for_instance: HashMap<InstanceName, Vec<String>>,
+    digest_functions_for_instance: HashMap<InstanceName, Vec<DigestHasherFunc>>,
+}
+
+/// Digest functions advertised for instance names which don't configure
+/// them, starting with the default digest function.
+fn default_digest_functions() -> Vec<DigestHasherFunc> {
+    let default_func = default_digest_hasher_func();
+    core::iter::once(default_func)
+        .chain(
+            [DigestHasherFunc::Sha256, DigestHasherFunc::Blake3]
+                .into_iter()
+                .filter(|func| *func != default_func),
+        )
+        .collect()
 }
 
 impl CapabilitiesServer {
@@ -46,7 +59,22 @@ impl CapabilitiesServer {
         scheduler_map: &HashMap<String, Arc<dyn ClientStateManager>>,
     ) -> Result<Self, Error> {
         let mut supported_node_properties_for_instance = HashMap::new();
+        let mut digest_functions_for_instance = HashMap::new();
         for config in configs {
+            if !config.digest_functions.is_empty() {
+                let mut digest_functions: Vec<DigestHasherFunc> = Vec::new();
+                for &digest_function in &config.digest_functions {
+                    let digest_function = DigestHasherFunc::from(digest_function);
+                    error_if!(
+                        digest_functions.contains(&digest_function),
+                        "Digest function {digest_function} is listed twice in capabilities of '{}'",
+                        config.instance_name
+                    );
+                    digest_functions.push(digest_function);
+                }
+                digest_functions_for_instance
+                    .insert(config.instance_name.clone(), digest_functions);
+            }
             let mut properties = Vec::new();
             if let Some(remote_execution_cfg) = &config.remote_execution {
                 let scheduler =
@@ -82,6 +110,7 @@ impl CapabilitiesServer {
         }
         Ok(Self {
             supported_node_properties_for_instance,
+            digest_functions_for_instance,
         })
     }
 
@@ -109,9 +138,18 @@ impl Capabilities for CapabilitiesServer {
         let maybe_supported_node_properties = self
             .supported_node_properties_for_instance
             .get(&instance_name);
+        let digest_functions = self
+            .digest_functions_for_instance
+            .get(&instance_name)
+            .cloned()
+            .unwrap_or_else(default_digest_functions);
+        let proto_digest_functions: Vec<i32> = digest_functions
+            .iter()
+            .map(|func| func.proto_digest_func().into())
+            .collect();
         let execution_capabilities =
             maybe_supported_node_properties.map(|props_for_instance| ExecutionCapabilities {
-                digest_function: default_digest_hasher_func().proto_digest_func().into(),
+                digest_function: digest_functions[0].proto_digest_func().into(),
                 exec_enabled: true, // TODO(palfrey) Make this configurable.
                 execution_priority_capabilities: Some(PriorityCapabilities {
                     priorities: vec![PriorityRange {
@@ -120,18 +158,12 @@ impl Capabilities for CapabilitiesServer {
                     }],
                 }),
                 supported_node_properties: props_for_instance.clone(),
-                digest_functions: vec![
-                    DigestFunction::Sha256.into(),
-                    DigestFunction::Blake3.into(),
-                ],
+                digest_functions: proto_digest_functions.clone(),
             });
 
         let resp = ServerCapabilities {
             cache_capabilities: Some(CacheCapabilities {
-                digest_functions: vec![
-                    DigestFunction::Sha256.into(),
-                    DigestFunction::Blake3.into(),
-                ],
+                digest_functions: proto_digest_functions,
                 action_cache_update_capabilities: Some(ActionCacheUpdateCapabilities {
                     update_enabled: true,
                 }),
@@ -34,7 +34,8 @@ use nativelink_proto::google::longrunning::Operation;
 use nativelink_store::ac_utils::get_and_decode_digest;
 use nativelink_store::store_manager::StoreManager;
 use nativelink_util::action_messages::{
-    ActionInfo, ActionUniqueKey, ActionUniqueQualifier, DEFAULT_EXECUTION_PRIORITY, OperationId,
+    ActionInfo, ActionStage, ActionUniqueKey, ActionUniqueQualifier, DEFAULT_EXECUTION_PRIORITY,
+    OperationId,
 };
 use nativelink_util::common::DigestInfo;
 use nativelink_util::digest_hasher::{DigestHasherFunc, make_ctx_for_hash_func};
@@ -48,6 +49,10 @@ use tracing::{Instrument, Level, debug, error, error_span, instrument};
 
 type InstanceInfoName = String;
 
+/// How often clients waiting on a queued operation are sent its position in
+/// the queue again.
+const QUEUE_POSITION_UPDATE_INTERVAL: Duration = Duration::from_secs(10);
+
 struct NativelinkOperationId {
     instance_name: InstanceInfoName,
     client_operation_id: OperationId,
@@ -81,17 +86,26 @@ impl fmt::Display for NativelinkOperationId {
 struct InstanceInfo {
     scheduler: Arc<dyn ClientStateManager>,
     cas_store: Store,
+    min_priority: i32,
+    max_priority: i32,
 }
 
 impl fmt::Debug for InstanceInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("InstanceInfo")
             .field("cas_store", &self.cas_store)
+            .field("min_priority", &self.min_priority)
+            .field("max_priority", &self.max_priority)
             .finish_non_exhaustive()
     }
 }
 
 impl InstanceInfo {
+    /// Limits the priority requested by the client to the configured range.
+    fn clamp_priority(&self, requested_priority: i32) -> i32 {
+        requested_priority.clamp(self.min_priority, self.max_priority)
+    }
+
     async fn build_action_info(
         &self,
         instance_name: String,
@@ -142,7 +156,9 @@ impl InstanceInfo {
             digest_function,
             digest: action_digest,
         };
-        let unique_qualifier = if skip_cache_lookup {
+        // An action that must not be cached must not be served from the cache
+        // or merged with other operations either.
+        let unique_qualifier = if skip_cache_lookup || action.do_not_cache {
             ActionUniqueQualifier::Uncacheable(action_key)
         } else {
             ActionUniqueQualifier::Cacheable(action_key)
@@ -157,6 +173,7 @@ impl InstanceInfo {
             load_timestamp: UNIX_EPOCH,
             insert_timestamp: SystemTime::now(),
             unique_qualifier,
+            do_not_cache: action.do_not_cache,
         })
     }
 }
@@ -189,11 +206,22 @@ impl ExecutionServer {
                 })?
                 .clone();
 
+            let min_priority = config.min_priority.unwrap_or(i32::MIN);
+            let max_priority = config.max_priority.unwrap_or(i32::MAX);
+            if min_priority > max_priority {
+                return Err(make_input_err!(
+                    "'min_priority' ({min_priority}) must not be greater than 'max_priority' ({max_priority}) for instance '{}'",
+                    config.instance_name
+                ));
+            }
+
             instance_infos.insert(
                 config.instance_name.to_string(),
                 InstanceInfo {
                     scheduler,
                     cas_store,
+                    min_priority,
+                    max_priority,
                 },
             );
         }
@@ -209,25 +237,54 @@ impl ExecutionServer {
         action_listener: Box<dyn ActionStateResult>,
     ) -> impl Stream<Item = Result<Operation, Status>> + Send + use<> {
         let client_operation_id = OperationId::from(nl_client_operation_id.to_string());
-        unfold(Some(action_listener), move |maybe_action_listener| {
-            let client_operation_id = client_operation_id.clone();
-            async move {
-                let mut action_listener = maybe_action_listener?;
-                match action_listener.changed().await {
-                    Ok((action_update, _maybe_origin_metadata)) => {
-                        debug!(?action_update, "Execute Resp Stream");
-                        Some((
-                            Ok(action_update.as_operation(client_operation_id)),
-                            (!action_update.stage.is_finished()).then_some(action_listener),
-                        ))
-                    }
-                    Err(err) => {
-                        error!(?err, "Error in action_listener stream");
-                        Some((Err(err.into()), None))
+        unfold(
+            Some((action_listener, false)),
+            move |maybe_action_listener| {
+                let client_operation_id = client_operation_id.clone();
+                async move {
+                    let (mut action_listener, is_queued) = maybe_action_listener?;
+                    // While the operation is queued, periodically resend its
+                    // state so the client sees its position move.
+                    let maybe_changed_result = tokio::select! {
+                        changed_result = action_listener.changed() => Some(changed_result),
+                        () = tokio::time::sleep(QUEUE_POSITION_UPDATE_INTERVAL), if is_queued => None,
+                    };
+                    let changed_result = match maybe_changed_result {
+                        Some(changed_result) => changed_result,
+                        None => action_listener.as_state().await,
+                    };
+                    match changed_result {
+                        Ok((action_update, _maybe_origin_metadata)) => {
+                            debug!(?action_update, "Execute Resp Stream");
+                            let is_queued = matches!(action_update.stage, ActionStage::Queued);
+                            let maybe_queue_position = if is_queued {
+                                action_listener
+                                    .queue_position()
+                                    .await
+                                    .unwrap_or_else(|err| {
+                                        debug!(?err, "Failed to get queue position");
+                                        None
+                                    })
+                            } else {
+                                None
+                            };
+                            Some((
+                                Ok(action_update.as_operation_with_queue_position(
+                                    client_operation_id,
+                                    maybe_queue_position,
+                                )),
+                                (!action_update.stage.is_finished())
+                                    .then_some((action_listener, is_queued)),
+                            ))
+                        }
+                        Err(err) => {
+                            error!(?err, "Error in action_listener stream");
+                            Some((Err(err.into()), None))
+                        }
                     }
                 }
-            }
-        })
+            },
+        )
     }
 
     async fn inner_execute(
@@ -248,9 +305,11 @@ impl ExecutionServer {
         )
         .err_tip(|| "Failed to unwrap action cache")?;
 
-        let priority = request
-            .execution_policy
-            .map_or(DEFAULT_EXECUTION_PRIORITY, |p| p.priority);
+        let priority = instance_info.clamp_priority(
+            request
+                .execution_policy
+                .map_or(DEFAULT_EXECUTION_PRIORITY, |p| p.priority),
+        );
 
         let action =
             get_and_decode_digest::<Action>(&instance_info.cas_store, digest.into()).await?;
@@ -29,7 +29,7 @@ use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::{
     execute_result, ConnectWorkerRequest, ExecuteResult, GoingAwayRequest, KeepAliveRequest, UpdateForWorker
 };
-use nativelink_scheduler::worker::Worker;
+use nativelink_scheduler::worker::{Worker, WorkerUtilization};
 use nativelink_scheduler::worker_scheduler::WorkerScheduler;
 use nativelink_util::background_spawn;
 use nativelink_util::action_messages::{OperationId, WorkerId};
@@ -167,12 +167,15 @@ impl WorkerApiServer {
                 connect_worker_request.worker_id_prefix,
                 Uuid::now_v6(&self.node_id).hyphenated()
             ));
-            let worker = Worker::new(
+            let mut worker = Worker::new(
                 worker_id.clone(),
                 platform_properties,
                 tx,
                 (self.now_fn)()?.as_secs(),
             );
+            worker.labels = connect_worker_request.labels;
+            worker.protocol_version = connect_worker_request.protocol_version;
+            worker.features = connect_worker_request.features.into_iter().collect();
             self.scheduler
                 .add_worker(worker)
                 .await
@@ -202,10 +205,20 @@ impl WorkerApiServer {
         keep_alive_request: KeepAliveRequest,
     ) -> Result<Response<()>, Error> {
         let worker_id: WorkerId = keep_alive_request.worker_id.into();
+        let now = (self.now_fn)()?.as_secs();
         self.scheduler
-            .worker_keep_alive_received(&worker_id, (self.now_fn)()?.as_secs())
+            .worker_keep_alive_received(&worker_id, now)
             .await
             .err_tip(|| "Could not process keep_alive from worker in inner_keep_alive()")?;
+        if let Some(utilization) = keep_alive_request.utilization {
+            self.scheduler
+                .update_worker_utilization(
+                    &worker_id,
+                    WorkerUtilization::from_proto(utilization, now),
+                )
+                .await
+                .err_tip(|| "Could not store utilization from worker in inner_keep_alive()")?;
+        }
         Ok(Response::new(()))
     }
 
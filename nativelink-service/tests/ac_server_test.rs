@@ -22,7 +22,8 @@ use nativelink_error::Error;
 use nativelink_macro::nativelink_test;
 use nativelink_proto::build::bazel::remote::execution::v2::action_cache_server::ActionCache;
 use nativelink_proto::build::bazel::remote::execution::v2::{
-    ActionResult, Digest, GetActionResultRequest, UpdateActionResultRequest, digest_function,
+    ActionResult, Digest, GetActionResultRequest, OutputFile, UpdateActionResultRequest,
+    digest_function,
 };
 use nativelink_service::ac_server::AcServer;
 use nativelink_store::default_store_factory::store_factory;
@@ -81,6 +82,8 @@ fn make_ac_server(store_manager: &StoreManager) -> Result<AcServer, Error> {
             config: nativelink_config::cas_server::AcStoreConfig {
                 ac_store: "main_ac".to_string(),
                 read_only: false,
+                inline_cas_store: "main_cas".to_string(),
+                max_inline_blob_size: 0,
             },
         }],
         store_manager,
@@ -220,3 +223,65 @@ async fn one_item_update_test() -> Result<(), Box<dyn core::error::Error>> {
     assert_eq!(decoded_action_result, action_result);
     Ok(())
 }
+
+#[nativelink_test]
+async fn inlines_requested_blobs_test() -> Result<(), Box<dyn core::error::Error>> {
+    const STDOUT_HASH: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+    const FILE_HASH: &str = "0123456789abcdef000000000000000000030000000000000123456789abcdef";
+    const STDOUT: &str = "hello stdout";
+    const FILE_CONTENTS: &str = "file contents";
+
+    let store_manager = make_store_manager().await?;
+    let ac_server = make_ac_server(&store_manager)?;
+    let ac_store = store_manager.get_store("main_ac").unwrap();
+    let cas_store = store_manager.get_store("main_cas").unwrap();
+
+    let stdout_digest = DigestInfo::try_new(STDOUT_HASH, STDOUT.len())?;
+    cas_store
+        .update_oneshot(stdout_digest, STDOUT.into())
+        .await?;
+    let file_digest = DigestInfo::try_new(FILE_HASH, FILE_CONTENTS.len())?;
+    cas_store
+        .update_oneshot(file_digest, FILE_CONTENTS.into())
+        .await?;
+
+    let action_result = ActionResult {
+        stdout_digest: Some(stdout_digest.into()),
+        output_files: vec![
+            OutputFile {
+                path: "inlined".to_string(),
+                digest: Some(file_digest.into()),
+                ..Default::default()
+            },
+            OutputFile {
+                path: "not_inlined".to_string(),
+                digest: Some(file_digest.into()),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    insert_into_store(ac_store.as_pin(), HASH1, HASH1_SIZE, &action_result).await?;
+
+    let response = ac_server
+        .get_action_result(Request::new(GetActionResultRequest {
+            instance_name: INSTANCE_NAME.to_string(),
+            action_digest: Some(Digest {
+                hash: HASH1.to_string(),
+                size_bytes: HASH1_SIZE,
+            }),
+            inline_stdout: true,
+            inline_stderr: true,
+            inline_output_files: vec!["inlined".to_string()],
+            digest_function: digest_function::Value::Sha256.into(),
+        }))
+        .await?
+        .into_inner();
+
+    assert_eq!(response.stdout_raw, STDOUT);
+    // No stderr digest is set, so there is nothing to inline.
+    assert!(response.stderr_raw.is_empty());
+    assert_eq!(response.output_files[0].contents, FILE_CONTENTS);
+    assert!(response.output_files[1].contents.is_empty());
+    Ok(())
+}
@@ -0,0 +1,530 @@
+// Copyright 2025 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use axum::Router;
+use axum::body::Body;
+use axum::http::{Method, Request, StatusCode, header};
+use futures::{join, stream};
+use nativelink_config::cas_server::AdminConfig;
+use nativelink_config::schedulers::SimpleSpec;
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_scheduler::default_scheduler_factory::memory_awaited_action_db_factory;
+use nativelink_scheduler::mock_scheduler::MockActionScheduler;
+use nativelink_scheduler::simple_scheduler::SimpleScheduler;
+use nativelink_scheduler::worker_scheduler::WorkerScheduler;
+use nativelink_service::admin_server::{AdminServer, PeerCertificate};
+use nativelink_store::store_manager::StoreManager;
+use nativelink_util::operation_state_manager::ClientStateManager;
+use pretty_assertions::assert_eq;
+use rustls_pki_types::CertificateDer;
+use rustls_pki_types::pem::PemObject;
+use tokio::sync::{Notify, broadcast};
+use tower::ServiceExt;
+
+/// Self-signed certificate valid for `admin.nativelink.test`.
+const ADMIN_CERT_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIIBtTCCAVygAwIBAgIUParGRIp6Jxy4GDvKhO+ujmFCffcwCgYIKoZIzj0EAwIw
+IDEeMBwGA1UEAwwVYWRtaW4ubmF0aXZlbGluay50ZXN0MCAXDTI2MTAxNjEyMTMz
+OVoYDzIxMjYwOTIyMTIxMzM5WjAgMR4wHAYDVQQDDBVhZG1pbi5uYXRpdmVsaW5r
+LnRlc3QwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAARfgDU8In4hK4hu5NOK7j77
+8fMdkdsGT211+w6f8uUSbzgOZIVBQwyQa22j15MkdqpwgsDDRiM3k0ZvHfJ3TzFc
+o3IwcDAdBgNVHQ4EFgQUlOrk/gnNBbcSq+Y+altfkrtqAE8wHwYDVR0jBBgwFoAU
+lOrk/gnNBbcSq+Y+altfkrtqAE8wIAYDVR0RBBkwF4IVYWRtaW4ubmF0aXZlbGlu
+ay50ZXN0MAwGA1UdEwEB/wQCMAAwCgYIKoZIzj0EAwIDRwAwRAIgUHh6i6DPqOgz
+7Afj2ctbz7HNUmW2EheaUlv0dUOmuuYCIFZg4iH/tXitE3GYAtZ/THWz+pkuCbBO
+yCl3gdqLs0EU
+-----END CERTIFICATE-----
+";
+
+/// Self-signed certificate valid for `read-only.nativelink.test`.
+const READ_ONLY_CERT_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIIBwzCCAWigAwIBAgIURNdRL8SRkzvOXpdtHwk4HEjh4SswCgYIKoZIzj0EAwIw
+JDEiMCAGA1UEAwwZcmVhZC1vbmx5Lm5hdGl2ZWxpbmsudGVzdDAgFw0yNjEwMTYx
+MjEzMzlaGA8yMTI2MDkyMjEyMTMzOVowJDEiMCAGA1UEAwwZcmVhZC1vbmx5Lm5h
+dGl2ZWxpbmsudGVzdDBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABHxmsynH4asB
+XLpggFr2m2jlNLdWDTLaAph17G0F8q7hQ7LHaxS6UGPbsco8kycXaNdy2CX9dzb/
+ALgzgLUxQOejdjB0MB0GA1UdDgQWBBTYGiV4cuDZgrL9oM9831I3P+N0NDAfBgNV
+HSMEGDAWgBTYGiV4cuDZgrL9oM9831I3P+N0NDAkBgNVHREEHTAbghlyZWFkLW9u
+bHkubmF0aXZlbGluay50ZXN0MAwGA1UdEwEB/wQCMAAwCgYIKoZIzj0EAwIDSQAw
+RgIhAM7sBiFzWMlFWcrWHBL83EHXTtkUsHViHSpSNYv2fr+kAiEAtpciTYzZOMgY
+7NR/S95fViCP3HgNVb8dHRYVAjS5Cfo=
+-----END CERTIFICATE-----
+";
+
+/// Route only available to the admin role. Its scheduler doesn't exist,
+/// so authorized requests fail with 500.
+const ADMIN_ROUTE: &str = "/scheduler/missing/cancel_operation/foo";
+
+/// Route available to the read-only role.
+const READ_ONLY_ROUTE: &str = "/stores";
+
+fn make_router(config: &AdminConfig) -> Result<Router, Error> {
+    make_router_with_schedulers(config, &HashMap::new(), &HashMap::new())
+}
+
+fn make_router_with_schedulers(
+    config: &AdminConfig,
+    action_schedulers: &HashMap<String, Arc<dyn ClientStateManager>>,
+    worker_schedulers: &HashMap<String, Arc<dyn WorkerScheduler>>,
+) -> Result<Router, Error> {
+    let (shutdown_tx, _) = broadcast::channel(1);
+    Ok(AdminServer::new(
+        config,
+        action_schedulers,
+        worker_schedulers,
+        &Arc::new(StoreManager::new()),
+        shutdown_tx,
+    )?
+    .into_router())
+}
+
+async fn send_for_body(
+    router: &Router,
+    method: Method,
+    uri: &str,
+    bearer_token: Option<&str>,
+    body: &str,
+) -> Result<(StatusCode, String), Box<dyn core::error::Error>> {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(bearer_token) = bearer_token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {bearer_token}"));
+    }
+    let response = router
+        .clone()
+        .oneshot(request.body(Body::from(body.to_string()))?)
+        .await?;
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    Ok((status, String::from_utf8(body.to_vec())?))
+}
+
+async fn send(
+    router: &Router,
+    method: Method,
+    uri: &str,
+    bearer_token: Option<&str>,
+    peer_certificate_pem: Option<&str>,
+) -> Result<StatusCode, Box<dyn core::error::Error>> {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(bearer_token) = bearer_token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {bearer_token}"));
+    }
+    if let Some(peer_certificate_pem) = peer_certificate_pem {
+        let cert = CertificateDer::from_pem_slice(peer_certificate_pem.as_bytes())?;
+        request = request.extension(PeerCertificate(cert));
+    }
+    let response = router.clone().oneshot(request.body(Body::empty())?).await?;
+    Ok(response.status())
+}
+
+#[nativelink_test]
+async fn no_auth_only_allows_read_routes_test() -> Result<(), Box<dyn core::error::Error>> {
+    let router = make_router(&AdminConfig::default())?;
+
+    assert_eq!(
+        send(&router, Method::GET, READ_ONLY_ROUTE, None, None).await?,
+        StatusCode::OK
+    );
+    for admin_route in [
+        ADMIN_ROUTE,
+        "/scheduler/missing/set_drain_worker/foo/1",
+        "/scheduler/missing/drain_worker/foo/10",
+        "/shutdown",
+    ] {
+        assert_eq!(
+            send(&router, Method::POST, admin_route, None, None).await?,
+            StatusCode::NOT_FOUND
+        );
+    }
+    // Only the drain status can be read, draining needs the admin role.
+    for method in [Method::POST, Method::DELETE] {
+        assert_eq!(
+            send(&router, method, "/scheduler/missing/drain", None, None).await?,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+async fn bearer_token_roles_test() -> Result<(), Box<dyn core::error::Error>> {
+    let router = make_router(&AdminConfig {
+        bearer_token: Some("admin-token".to_string()),
+        read_only_bearer_token: Some("read-only-token".to_string()),
+        ..Default::default()
+    })?;
+
+    assert_eq!(
+        send(&router, Method::GET, READ_ONLY_ROUTE, None, None).await?,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        send(
+            &router,
+            Method::GET,
+            READ_ONLY_ROUTE,
+            Some("wrong-token"),
+            None
+        )
+        .await?,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        send(
+            &router,
+            Method::GET,
+            READ_ONLY_ROUTE,
+            Some("read-only-token"),
+            None
+        )
+        .await?,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send(
+            &router,
+            Method::POST,
+            ADMIN_ROUTE,
+            Some("read-only-token"),
+            None
+        )
+        .await?,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        send(
+            &router,
+            Method::GET,
+            READ_ONLY_ROUTE,
+            Some("admin-token"),
+            None
+        )
+        .await?,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send(
+            &router,
+            Method::POST,
+            ADMIN_ROUTE,
+            Some("admin-token"),
+            None
+        )
+        .await?,
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn client_certificate_roles_test() -> Result<(), Box<dyn core::error::Error>> {
+    let router = make_router(&AdminConfig {
+        client_cert_names: vec!["admin.nativelink.test".to_string()],
+        read_only_client_cert_names: vec!["read-only.nativelink.test".to_string()],
+        ..Default::default()
+    })?;
+
+    assert_eq!(
+        send(&router, Method::GET, READ_ONLY_ROUTE, None, None).await?,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        send(
+            &router,
+            Method::GET,
+            READ_ONLY_ROUTE,
+            None,
+            Some(READ_ONLY_CERT_PEM)
+        )
+        .await?,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send(
+            &router,
+            Method::POST,
+            ADMIN_ROUTE,
+            None,
+            Some(READ_ONLY_CERT_PEM)
+        )
+        .await?,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        send(
+            &router,
+            Method::POST,
+            ADMIN_ROUTE,
+            None,
+            Some(ADMIN_CERT_PEM)
+        )
+        .await?,
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn client_certificate_with_other_name_is_rejected_test()
+-> Result<(), Box<dyn core::error::Error>> {
+    let router = make_router(&AdminConfig {
+        client_cert_names: vec!["admin.nativelink.test".to_string()],
+        ..Default::default()
+    })?;
+
+    assert_eq!(
+        send(
+            &router,
+            Method::GET,
+            READ_ONLY_ROUTE,
+            None,
+            Some(READ_ONLY_CERT_PEM)
+        )
+        .await?,
+        StatusCode::UNAUTHORIZED
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn drain_routes_test() -> Result<(), Box<dyn core::error::Error>> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, worker_scheduler) = SimpleScheduler::new(
+        &SimpleSpec::default(),
+        memory_awaited_action_db_factory(0, &task_change_notify, SystemTime::now),
+        task_change_notify,
+        None,
+    );
+    let router = make_router_with_schedulers(
+        &AdminConfig {
+            bearer_token: Some("admin-token".to_string()),
+            ..Default::default()
+        },
+        &HashMap::from([("main".to_string(), scheduler as Arc<dyn ClientStateManager>)]),
+        &HashMap::from([("main".to_string(), worker_scheduler.clone())]),
+    )?;
+
+    assert_eq!(
+        send_for_body(
+            &router,
+            Method::GET,
+            "/scheduler/main/drain",
+            Some("admin-token"),
+            ""
+        )
+        .await?,
+        (
+            StatusCode::OK,
+            r#"{"draining":false,"executing":0}"#.to_string()
+        )
+    );
+    assert_eq!(
+        send_for_body(
+            &router,
+            Method::POST,
+            "/scheduler/main/drain",
+            Some("admin-token"),
+            ""
+        )
+        .await?,
+        (
+            StatusCode::OK,
+            r#"{"draining":true,"executing":0}"#.to_string()
+        )
+    );
+    assert!(worker_scheduler.is_draining());
+    assert_eq!(
+        send_for_body(
+            &router,
+            Method::DELETE,
+            "/scheduler/main/drain",
+            Some("admin-token"),
+            ""
+        )
+        .await?,
+        (
+            StatusCode::OK,
+            r#"{"draining":false,"executing":0}"#.to_string()
+        )
+    );
+    assert!(!worker_scheduler.is_draining());
+    assert_eq!(
+        send_for_body(
+            &router,
+            Method::POST,
+            "/scheduler/missing/drain",
+            Some("admin-token"),
+            ""
+        )
+        .await?
+        .0,
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn queue_depth_decodes_platform_properties_test() -> Result<(), Box<dyn core::error::Error>> {
+    let mock_scheduler = Arc::new(MockActionScheduler::new());
+    let router = make_router_with_schedulers(
+        &AdminConfig::default(),
+        &HashMap::from([(
+            "main".to_string(),
+            mock_scheduler.clone() as Arc<dyn ClientStateManager>,
+        )]),
+        &HashMap::new(),
+    )?;
+
+    let expectations = async {
+        let queued_filter = mock_scheduler
+            .expect_filter_operations(Ok(Box::pin(stream::empty())))
+            .await;
+        let executing_filter = mock_scheduler
+            .expect_filter_operations(Ok(Box::pin(stream::empty())))
+            .await;
+        (queued_filter, executing_filter)
+    };
+    let (response, (queued_filter, executing_filter)) = join!(
+        send_for_body(
+            &router,
+            Method::GET,
+            "/scheduler/main/queue_depth?OSFamily=linux&container-image=docker%3A%2F%2Fubuntu%3A24.04",
+            None,
+            "",
+        ),
+        expectations,
+    );
+
+    assert_eq!(
+        response?,
+        (
+            StatusCode::OK,
+            r#"{"queued":0,"executing":0,"oldest_queued_s":0}"#.to_string()
+        )
+    );
+    let expected_platform_properties = BTreeMap::from([
+        ("OSFamily".to_string(), "linux".to_string()),
+        (
+            "container-image".to_string(),
+            "docker://ubuntu:24.04".to_string(),
+        ),
+    ]);
+    assert_eq!(
+        queued_filter.platform_properties,
+        expected_platform_properties
+    );
+    assert_eq!(
+        executing_filter.platform_properties,
+        expected_platform_properties
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn store_routes_require_auth_test() -> Result<(), Box<dyn core::error::Error>> {
+    const MEMORY_STORE_SPEC: &str = r#"{"memory": {}}"#;
+
+    // Without authentication stores can only be listed.
+    let router = make_router(&AdminConfig::default())?;
+    assert_eq!(
+        send_for_body(&router, Method::PUT, "/stores/foo", None, MEMORY_STORE_SPEC)
+            .await?
+            .0,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        send_for_body(&router, Method::POST, "/stores/bar/repoint/foo", None, "")
+            .await?
+            .0,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        send_for_body(&router, Method::DELETE, "/stores/foo", None, "")
+            .await?
+            .0,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        send_for_body(&router, Method::GET, "/stores", None, "").await?,
+        (StatusCode::OK, "[]".to_string())
+    );
+
+    let router = make_router(&AdminConfig {
+        bearer_token: Some("admin-token".to_string()),
+        read_only_bearer_token: Some("read-only-token".to_string()),
+        ..Default::default()
+    })?;
+    assert_eq!(
+        send_for_body(
+            &router,
+            Method::PUT,
+            "/stores/foo",
+            Some("read-only-token"),
+            MEMORY_STORE_SPEC
+        )
+        .await?
+        .0,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        send_for_body(
+            &router,
+            Method::PUT,
+            "/stores/foo",
+            Some("admin-token"),
+            MEMORY_STORE_SPEC
+        )
+        .await?
+        .0,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send_for_body(
+            &router,
+            Method::POST,
+            "/stores/bar/repoint/foo",
+            Some("admin-token"),
+            ""
+        )
+        .await?
+        .0,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send_for_body(&router, Method::GET, "/stores", Some("read-only-token"), "").await?,
+        (StatusCode::OK, r#"["bar","foo"]"#.to_string())
+    );
+    assert_eq!(
+        send_for_body(
+            &router,
+            Method::DELETE,
+            "/stores/foo",
+            Some("admin-token"),
+            ""
+        )
+        .await?
+        .0,
+        StatusCode::OK
+    );
+    assert_eq!(
+        send_for_body(&router, Method::GET, "/stores", Some("read-only-token"), "").await?,
+        (StatusCode::OK, r#"["bar"]"#.to_string())
+    );
+    Ok(())
+}
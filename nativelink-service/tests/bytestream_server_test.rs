@@ -80,6 +80,7 @@ fn make_bytestream_server(
                 cas_store: "main_cas".to_string(),
                 persist_stream_on_disconnect_timeout: 0,
                 max_bytes_per_stream: 1024,
+                ..Default::default()
             },
         }]
     });
@@ -1041,6 +1042,107 @@ pub async fn max_decoding_message_size_test() -> Result<(), Box<dyn core::error:
     Ok(())
 }
 
+#[nativelink_test]
+pub async fn resumes_persisted_upload_after_restart_test() -> Result<(), Box<dyn core::error::Error>>
+{
+    const SEGMENT_SIZE: usize = 4;
+    const BYTES_BEFORE_RESTART: usize = 10;
+
+    let store_manager = make_store_manager().await?;
+    store_manager.add_store(
+        "upload_store",
+        store_factory(
+            &StoreSpec::Memory(MemorySpec::default()),
+            &store_manager,
+            None,
+        )
+        .await?,
+    );
+    let make_config = || {
+        vec![WithInstanceName {
+            instance_name: INSTANCE_NAME.to_string(),
+            config: ByteStreamConfig {
+                cas_store: "main_cas".to_string(),
+                upload_store: "upload_store".to_string(),
+                upload_segment_size: SEGMENT_SIZE,
+                ..Default::default()
+            },
+        }]
+    };
+
+    let raw_data = b"12456789abcdefghijk";
+    let resource_name = make_resource_name(raw_data.len());
+    let query_write_status = async |bs_server: &ByteStreamServer| {
+        bs_server
+            .query_write_status(Request::new(QueryWriteStatusRequest {
+                resource_name: resource_name.clone(),
+            }))
+            .await
+            .map(Response::into_inner)
+    };
+
+    {
+        let bs_server = Arc::new(make_bytestream_server(
+            store_manager.as_ref(),
+            Some(make_config()),
+        )?);
+        let (tx, join_handle) = make_stream_and_writer_spawn(bs_server.clone(), None);
+        tx.send(Frame::data(encode_stream_proto(&WriteRequest {
+            resource_name: resource_name.clone(),
+            write_offset: 0,
+            finish_write: false,
+            data: raw_data[..BYTES_BEFORE_RESTART].to_vec().into(),
+        })?))
+        .await?;
+        while query_write_status(&bs_server).await?.committed_size != BYTES_BEFORE_RESTART as i64 {
+            yield_now().await;
+        }
+        // The client disconnects and the server goes away.
+        drop(tx);
+        assert!(join_handle.await?.is_err());
+    }
+
+    let bs_server = Arc::new(make_bytestream_server(
+        store_manager.as_ref(),
+        Some(make_config()),
+    )?);
+    // Only complete segments survive the restart.
+    let persisted_size = BYTES_BEFORE_RESTART / SEGMENT_SIZE * SEGMENT_SIZE;
+    assert_eq!(
+        query_write_status(&bs_server).await?,
+        QueryWriteStatusResponse {
+            committed_size: persisted_size as i64,
+            complete: false,
+        }
+    );
+
+    let (tx, join_handle) = make_stream_and_writer_spawn(bs_server.clone(), None);
+    tx.send(Frame::data(encode_stream_proto(&WriteRequest {
+        resource_name: resource_name.clone(),
+        write_offset: persisted_size as i64,
+        finish_write: true,
+        data: raw_data[persisted_size..].to_vec().into(),
+    })?))
+    .await?;
+    assert_eq!(
+        join_handle.await??.into_inner(),
+        WriteResponse {
+            committed_size: raw_data.len() as i64,
+        }
+    );
+
+    let digest = DigestInfo::try_new(HASH1, raw_data.len())?;
+    let main_cas = store_manager.get_store("main_cas").unwrap();
+    assert_eq!(
+        main_cas.get_part_unchunked(digest, 0, None).await?,
+        raw_data.as_slice()
+    );
+    // The persisted segments are removed once the upload is complete.
+    let upload_store = store_manager.get_store("upload_store").unwrap();
+    assert_eq!(upload_store.list(.., |_| true).await?, 0);
+    Ok(())
+}
+
 #[nativelink_test]
 async fn write_too_many_bytes_fails() -> Result<(), Box<dyn core::error::Error>> {
     const MAX_MESSAGE_SIZE: usize = 3;
@@ -15,17 +15,24 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use futures::join;
 use nativelink_config::cas_server::{ExecutionConfig, WithInstanceName};
 use nativelink_config::stores::{MemorySpec, StoreSpec};
-use nativelink_error::Error;
+use nativelink_error::{Code, Error, make_err};
 use nativelink_macro::nativelink_test;
 use nativelink_proto::build::bazel::remote::execution::v2::execution_server::Execution;
-use nativelink_proto::build::bazel::remote::execution::v2::{ExecuteRequest, digest_function};
+use nativelink_proto::build::bazel::remote::execution::v2::platform::Property;
+use nativelink_proto::build::bazel::remote::execution::v2::{
+    Action, ExecuteRequest, ExecutionPolicy, Platform, digest_function,
+};
 use nativelink_scheduler::mock_scheduler::MockActionScheduler;
 use nativelink_service::execution_server::ExecutionServer;
 use nativelink_store::default_store_factory::store_factory;
 use nativelink_store::store_manager::StoreManager;
+use nativelink_util::common::DigestInfo;
 use nativelink_util::operation_state_manager::ClientStateManager;
+use nativelink_util::store_trait::StoreLike;
+use prost::Message;
 use tonic::Request;
 
 const INSTANCE_NAME: &str = "instance_name";
@@ -44,8 +51,11 @@ async fn make_store_manager() -> Result<Arc<StoreManager>, Error> {
     Ok(store_manager)
 }
 
-fn make_execution_server(store_manager: &StoreManager) -> Result<ExecutionServer, Error> {
-    let mock_scheduler = Arc::new(MockActionScheduler::new());
+fn make_execution_server(
+    store_manager: &StoreManager,
+    mock_scheduler: Arc<MockActionScheduler>,
+    max_priority: Option<i32>,
+) -> Result<ExecutionServer, Error> {
     let mut action_schedulers: HashMap<String, Arc<dyn ClientStateManager>> = HashMap::new();
     action_schedulers.insert("main_scheduler".to_string(), mock_scheduler);
     ExecutionServer::new(
@@ -54,6 +64,8 @@ fn make_execution_server(store_manager: &StoreManager) -> Result<ExecutionServer
             config: ExecutionConfig {
                 cas_store: "main_cas".to_string(),
                 scheduler: "main_scheduler".to_string(),
+                min_priority: None,
+                max_priority,
             },
         }],
         &action_schedulers,
@@ -64,7 +76,8 @@ fn make_execution_server(store_manager: &StoreManager) -> Result<ExecutionServer
 #[nativelink_test]
 async fn instance_name_fail() -> Result<(), Box<dyn core::error::Error>> {
     let store_manager = make_store_manager().await?;
-    let execution_server = make_execution_server(&store_manager)?;
+    let execution_server =
+        make_execution_server(&store_manager, Arc::new(MockActionScheduler::new()), None)?;
 
     let raw_response = execution_server
         .execute(Request::new(ExecuteRequest {
@@ -90,3 +103,47 @@ async fn instance_name_fail() -> Result<(), Box<dyn core::error::Error>> {
     }
     Ok(())
 }
+
+#[nativelink_test]
+async fn requested_priority_is_clamped_test() -> Result<(), Box<dyn core::error::Error>> {
+    const MAX_PRIORITY: i32 = 10;
+    let store_manager = make_store_manager().await?;
+    let mock_scheduler = Arc::new(MockActionScheduler::new());
+    let execution_server =
+        make_execution_server(&store_manager, mock_scheduler.clone(), Some(MAX_PRIORITY))?;
+
+    let action = Action {
+        command_digest: Some(DigestInfo::new([1u8; 32], 10).into()),
+        input_root_digest: Some(DigestInfo::new([2u8; 32], 10).into()),
+        platform: Some(Platform {
+            properties: vec![Property {
+                name: "OSFamily".to_string(),
+                value: "Linux".to_string(),
+            }],
+        }),
+        ..Default::default()
+    };
+    let action_data = action.encode_to_vec();
+    let action_digest = DigestInfo::new([3u8; 32], action_data.len() as u64);
+    store_manager
+        .get_store("main_cas")
+        .unwrap()
+        .update_oneshot(action_digest, action_data.into())
+        .await?;
+
+    let execute_fut = execution_server.execute(Request::new(ExecuteRequest {
+        instance_name: INSTANCE_NAME.to_string(),
+        digest_function: digest_function::Value::Sha256.into(),
+        skip_cache_lookup: false,
+        action_digest: Some(action_digest.into()),
+        execution_policy: Some(ExecutionPolicy { priority: 100 }),
+        results_cache_policy: None,
+    }));
+    let ((_, action_info), execute_result) = join!(
+        mock_scheduler.expect_add_action(Err(make_err!(Code::Internal, "Not scheduled"))),
+        execute_fut,
+    );
+    assert!(execute_result.is_err());
+    assert_eq!(action_info.priority, MAX_PRIORITY);
+    Ok(())
+}
@@ -31,12 +31,16 @@ use nativelink_proto::build::bazel::remote::execution::v2::{
 };
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::worker_api_server::WorkerApi;
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::{
-    ConnectWorkerRequest, ExecuteResult, KeepAliveRequest, execute_result, update_for_worker,
+    ConnectWorkerRequest, ExecuteResult, KeepAliveRequest, WorkerUtilization, execute_result,
+    update_for_worker,
 };
 use nativelink_proto::google::rpc::Status as ProtoStatus;
 use nativelink_scheduler::api_worker_scheduler::ApiWorkerScheduler;
 use nativelink_scheduler::platform_property_manager::PlatformPropertyManager;
-use nativelink_scheduler::worker::ActionInfoWithProps;
+use nativelink_scheduler::scheduling_policy::DefaultSchedulingPolicy;
+use nativelink_scheduler::worker::{
+    ActionInfoWithProps, WorkerUtilization as SchedulerWorkerUtilization,
+};
 use nativelink_scheduler::worker_scheduler::WorkerScheduler;
 use nativelink_service::worker_api_server::{ConnectWorkerStream, NowFn, WorkerApiServer};
 use nativelink_util::action_messages::{
@@ -152,9 +156,16 @@ async fn setup_api_server(worker_timeout: u64, now_fn: NowFn) -> Result<TestCont
     let scheduler = ApiWorkerScheduler::new(
         state_manager.clone(),
         platform_property_manager,
-        WorkerAllocationStrategy::default(),
+        Box::new(DefaultSchedulingPolicy::new(
+            WorkerAllocationStrategy::default(),
+            0,
+        )),
+        0,
+        None,
+        0,
         tasks_or_worker_change_notify,
         worker_timeout,
+        1,
     );
 
     let mut schedulers: HashMap<String, Arc<dyn WorkerScheduler>> = HashMap::new();
@@ -291,6 +302,7 @@ pub async fn server_does_not_timeout_if_keep_alive_test() -> Result<(), Box<dyn
             .worker_api_server
             .keep_alive(Request::new(KeepAliveRequest {
                 worker_id: test_context.worker_id.to_string(),
+                utilization: None,
             }))
             .await
             .err_tip(|| "Error sending keep alive")?;
@@ -312,6 +324,40 @@ pub async fn server_does_not_timeout_if_keep_alive_test() -> Result<(), Box<dyn
     Ok(())
 }
 
+#[nativelink_test]
+pub async fn keep_alive_stores_worker_utilization_test() -> Result<(), Box<dyn core::error::Error>>
+{
+    let test_context = setup_api_server(BASE_WORKER_TIMEOUT_S, Box::new(static_now_fn)).await?;
+
+    let utilization = WorkerUtilization {
+        cpu_usage: 0.5,
+        memory_used_bytes: 1024,
+        memory_total_bytes: 4096,
+        disk_used_bytes: 10,
+        disk_total_bytes: 100,
+    };
+    test_context
+        .worker_api_server
+        .keep_alive(Request::new(KeepAliveRequest {
+            worker_id: test_context.worker_id.to_string(),
+            utilization: Some(utilization),
+        }))
+        .await
+        .err_tip(|| "Error sending keep alive")?;
+
+    let stored_utilization = test_context
+        .scheduler
+        .worker_utilization_for_test(&test_context.worker_id)
+        .await
+        .err_tip(|| "Expected utilization to be stored")?;
+    assert_eq!(
+        stored_utilization,
+        SchedulerWorkerUtilization::from_proto(utilization, BASE_NOW_S)
+    );
+
+    Ok(())
+}
+
 #[nativelink_test]
 pub async fn worker_receives_keep_alive_request_test() -> Result<(), Box<dyn core::error::Error>> {
     let mut test_context = setup_api_server(BASE_WORKER_TIMEOUT_S, Box::new(static_now_fn)).await?;
@@ -398,6 +444,7 @@ pub async fn execution_response_success_test() -> Result<(), Box<dyn core::error
         load_timestamp: make_system_time(0),
         insert_timestamp: make_system_time(0),
         unique_qualifier,
+        do_not_cache: false,
     });
     let expected_operation_id = OperationId::default();
 
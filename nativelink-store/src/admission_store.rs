@@ -0,0 +1,154 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use nativelink_config::stores::AdmissionSpec;
+use nativelink_error::{Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::frequency_sketch::FrequencySketch;
+use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
+use nativelink_util::store_trait::{
+    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo,
+};
+use parking_lot::Mutex;
+
+/// Default number of accesses after which access counts are halved.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_ACCESS_WINDOW: usize = 100_000;
+
+#[derive(Debug, Default, MetricsComponent)]
+struct AdmissionMetrics {
+    #[metric(help = "Number of uploads written to the backend")]
+    admitted: AtomicU64,
+    #[metric(help = "Number of uploads discarded because they were too large")]
+    rejected_size: AtomicU64,
+    #[metric(help = "Number of uploads discarded because they were not accessed often enough")]
+    rejected_frequency: AtomicU64,
+}
+
+#[derive(Debug, MetricsComponent)]
+pub struct AdmissionStore {
+    #[metric(group = "backend")]
+    backend: Store,
+    #[metric(help = "Maximum size of an admitted upload")]
+    max_object_size: u64,
+    #[metric(help = "Number of recent accesses required to admit an upload")]
+    min_access_count: u32,
+    #[metric]
+    metrics: AdmissionMetrics,
+    sketch: Mutex<FrequencySketch>,
+}
+
+impl AdmissionStore {
+    pub fn new(spec: &AdmissionSpec, backend: Store) -> Arc<Self> {
+        let access_window = if spec.access_window == 0 {
+            DEFAULT_ACCESS_WINDOW
+        } else {
+            spec.access_window
+        };
+        Arc::new(Self {
+            backend,
+            max_object_size: spec.max_object_size,
+            min_access_count: u32::from(spec.min_access_count.max(1)),
+            metrics: AdmissionMetrics::default(),
+            sketch: Mutex::new(FrequencySketch::new(access_window, access_window)),
+        })
+    }
+
+    fn record_access(&self, key: &StoreKey<'_>) -> u8 {
+        self.sketch.lock().increment(key)
+    }
+}
+
+#[async_trait]
+impl StoreDriver for AdmissionStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        self.backend.has_with_results(keys, results).await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let size = match size_info {
+            UploadSizeInfo::ExactSize(size) | UploadSizeInfo::MaxSize(size) => size,
+        };
+        let access_count = self.record_access(&key);
+        let rejection = if self.max_object_size != 0 && size > self.max_object_size {
+            Some(&self.metrics.rejected_size)
+        } else if u32::from(access_count) < self.min_access_count {
+            Some(&self.metrics.rejected_frequency)
+        } else {
+            None
+        };
+        if let Some(rejection) = rejection {
+            rejection.fetch_add(1, Ordering::Relaxed);
+            // The data is still read, so stores it is teed to, like the slow
+            // store of a fast_slow store, receive all of it.
+            return reader
+                .drain()
+                .await
+                .err_tip(|| "Failed to drain upload not admitted in AdmissionStore");
+        }
+        self.metrics.admitted.fetch_add(1, Ordering::Relaxed);
+        self.backend.update(key, reader, size_info).await
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        self.record_access(&key);
+        self.backend.get_part(key, writer, offset, length).await
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        self.backend.remove(key).await
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        self.backend.register_remove_callback(callback)
+    }
+}
+
+default_health_status_indicator!(AdmissionStore);
@@ -0,0 +1,223 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::fmt::{Debug, Formatter};
+use core::future::Future;
+
+use azure_core::StatusCode;
+use azure_core::error::{Error as AzureError, ErrorKind};
+use azure_storage::StorageCredentials;
+use azure_storage_blobs::prelude::{
+    BlobBlockType, BlockId, BlockList, ClientBuilder, ContainerClient,
+};
+use bytes::Bytes;
+use futures::{Stream, StreamExt, TryStreamExt};
+use nativelink_config::stores::ExperimentalAzureSpec;
+use nativelink_error::{Code, Error, ResultExt, make_err, make_input_err};
+
+/// Content type of the uploaded blobs.
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The properties of a blob the store cares about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlobProperties {
+    pub content_length: u64,
+    pub last_modified_unix_s: i64,
+}
+
+/// The Azure Blob Storage operations used by `AzureStore`. This abstraction
+/// allows the store to be tested against a mock.
+pub trait AzureBlobOperations: Send + Sync + Debug {
+    /// Returns the properties of a blob, or None if it does not exist.
+    fn get_blob_properties(
+        &self,
+        blob_name: &str,
+    ) -> impl Future<Output = Result<Option<BlobProperties>, Error>> + Send;
+
+    /// Reads the content of a blob from `start` up to `end` (exclusive) or
+    /// the end of the blob.
+    fn get_blob(
+        &self,
+        blob_name: &str,
+        start: u64,
+        end: Option<u64>,
+    ) -> impl Future<
+        Output = Result<Box<dyn Stream<Item = Result<Bytes, Error>> + Send + Unpin>, Error>,
+    > + Send;
+
+    /// Uploads a whole block blob in a single request.
+    fn put_blob(
+        &self,
+        blob_name: &str,
+        content: Bytes,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Stages a block of a block blob. Staged blocks are not visible until
+    /// they are committed with `put_block_list`.
+    fn put_block(
+        &self,
+        blob_name: &str,
+        block_id: &str,
+        content: Bytes,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Commits the staged blocks, in order, as the content of the blob.
+    fn put_block_list(
+        &self,
+        blob_name: &str,
+        block_ids: &[String],
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+}
+
+/// Client for a container of an Azure storage account.
+pub struct AzureBlobClient {
+    container_client: ContainerClient,
+}
+
+impl Debug for AzureBlobClient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("AzureBlobClient")
+            .field("container", &self.container_client.container_name())
+            .finish_non_exhaustive()
+    }
+}
+
+impl AzureBlobClient {
+    /// Creates a client authenticated with the SAS token of the spec or,
+    /// if there is none, with the credentials found in the environment
+    /// (workload identity, managed identity, Azure CLI, ...).
+    pub fn new(spec: &ExperimentalAzureSpec) -> Result<Self, Error> {
+        let credentials = if let Some(sas_token) = &spec.sas_token {
+            StorageCredentials::sas_token(sas_token.trim_start_matches('?'))
+                .map_err(|e| make_input_err!("Invalid SAS token for azure store: {e}"))?
+        } else {
+            StorageCredentials::token_credential(azure_identity::create_credential().map_err(
+                |e| {
+                    make_err!(
+                        Code::Unauthenticated,
+                        "Could not find credentials for azure store: {e}"
+                    )
+                },
+            )?)
+        };
+        let container_client = ClientBuilder::new(spec.account_name.clone(), credentials)
+            .container_client(spec.container.clone());
+        Ok(Self { container_client })
+    }
+
+    fn handle_azure_error(err: &AzureError) -> Error {
+        let code = match err.kind() {
+            ErrorKind::HttpResponse { status, .. } => match *status {
+                StatusCode::NotFound => Code::NotFound,
+                StatusCode::Unauthorized | StatusCode::Forbidden => Code::PermissionDenied,
+                StatusCode::RequestTimeout | StatusCode::TooManyRequests => Code::ResourceExhausted,
+                status if status.is_server_error() => Code::Unavailable,
+                _ => Code::Unknown,
+            },
+            ErrorKind::Io => Code::Unavailable,
+            _ => Code::Internal,
+        };
+        make_err!(code, "Azure blob operation failed: {err}")
+    }
+}
+
+impl AzureBlobOperations for AzureBlobClient {
+    async fn get_blob_properties(&self, blob_name: &str) -> Result<Option<BlobProperties>, Error> {
+        match self
+            .container_client
+            .blob_client(blob_name)
+            .get_properties()
+            .await
+        {
+            Ok(response) => Ok(Some(BlobProperties {
+                content_length: response.blob.properties.content_length,
+                last_modified_unix_s: response.blob.properties.last_modified.unix_timestamp(),
+            })),
+            Err(err) => {
+                let err = Self::handle_azure_error(&err);
+                if err.code == Code::NotFound {
+                    return Ok(None);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    async fn get_blob(
+        &self,
+        blob_name: &str,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<Box<dyn Stream<Item = Result<Bytes, Error>> + Send + Unpin>, Error> {
+        let request = self.container_client.blob_client(blob_name).get();
+        let request = match end {
+            Some(end) => request.range(start..end),
+            None => request.range(start..),
+        };
+        let mut responses = request.into_stream();
+        // The first response tells us if the blob exists, so report errors
+        // of it to the caller instead of through the stream.
+        let first_response = responses
+            .next()
+            .await
+            .err_tip(|| format!("No response when reading azure blob {blob_name}"))?
+            .map_err(|e| Self::handle_azure_error(&e))?;
+        let data = futures::stream::once(async move { Ok(first_response) })
+            .chain(responses)
+            .map_err(|e| Self::handle_azure_error(&e))
+            .map_ok(|response| response.data.map_err(|e| Self::handle_azure_error(&e)))
+            .try_flatten();
+        Ok(Box::new(Box::pin(data)))
+    }
+
+    async fn put_blob(&self, blob_name: &str, content: Bytes) -> Result<(), Error> {
+        self.container_client
+            .blob_client(blob_name)
+            .put_block_blob(content)
+            .content_type(DEFAULT_CONTENT_TYPE)
+            .await
+            .map_err(|e| Self::handle_azure_error(&e))?;
+        Ok(())
+    }
+
+    async fn put_block(
+        &self,
+        blob_name: &str,
+        block_id: &str,
+        content: Bytes,
+    ) -> Result<(), Error> {
+        self.container_client
+            .blob_client(blob_name)
+            .put_block(BlockId::new(block_id.to_string()), content)
+            .await
+            .map_err(|e| Self::handle_azure_error(&e))?;
+        Ok(())
+    }
+
+    async fn put_block_list(&self, blob_name: &str, block_ids: &[String]) -> Result<(), Error> {
+        let block_list = BlockList {
+            blocks: block_ids
+                .iter()
+                .map(|block_id| BlobBlockType::new_uncommitted(BlockId::new(block_id.clone())))
+                .collect(),
+        };
+        self.container_client
+            .blob_client(blob_name)
+            .put_block_list(block_list)
+            .content_type(DEFAULT_CONTENT_TYPE)
+            .await
+            .map_err(|e| Self::handle_azure_error(&e))?;
+        Ok(())
+    }
+}
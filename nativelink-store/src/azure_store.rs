@@ -0,0 +1,442 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::pin::Pin;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{FuturesUnordered, try_unfold, unfold};
+use futures::{StreamExt, TryStreamExt};
+use nativelink_config::stores::ExperimentalAzureSpec;
+use nativelink_error::{Code, Error, ResultExt, make_err};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::health_utils::{HealthRegistryBuilder, HealthStatus, HealthStatusIndicator};
+use nativelink_util::instant_wrapper::InstantWrapper;
+use nativelink_util::retry::{Retrier, RetryResult};
+use nativelink_util::store_trait::{RemoveItemCallback, StoreDriver, StoreKey, UploadSizeInfo};
+use rand::Rng;
+use tokio::time::sleep;
+
+use crate::azure_client::{AzureBlobClient, AzureBlobOperations};
+use crate::cas_utils::is_zero_digest;
+
+/// Uploads smaller than this with a known size are sent in a single request.
+/// Larger ones are staged block by block and then committed.
+pub const MIN_BLOCK_UPLOAD_SIZE: u64 = 5 * 1024 * 1024; // 5MB.
+
+/// Default size of the blocks staged for a block upload.
+/// If this changes, remember to change the documentation in the config.
+pub const DEFAULT_BLOCK_SIZE: usize = 5 * 1024 * 1024; // 5MB.
+
+/// A block blob may be made of at most this many blocks.
+const MAX_BLOCKS: u64 = 50_000;
+
+/// Largest block a block blob may be staged with.
+const MAX_BLOCK_SIZE: u64 = 4000 * 1024 * 1024; // 4000MiB.
+
+/// Default number of blocks staged concurrently for one upload.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_CONCURRENT_UPLOADS: usize = 10;
+
+/// Default buffer size for retrying requests.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_RETRY_BUFFER_PER_REQUEST: usize = 5 * 1024 * 1024; // 5MB.
+
+/// Block ids of a blob must all have the same length.
+fn make_block_id(index: u64) -> String {
+    format!("{index:010}")
+}
+
+#[derive(MetricsComponent, Debug)]
+pub struct AzureStore<Client: AzureBlobOperations, NowFn> {
+    client: Arc<Client>,
+    now_fn: NowFn,
+    #[metric(help = "The container name for the Azure store")]
+    container: String,
+    #[metric(help = "The key prefix for the Azure store")]
+    key_prefix: String,
+    retrier: Retrier,
+    #[metric(help = "The number of seconds to consider an object expired")]
+    consider_expired_after_s: i64,
+    #[metric(help = "The number of bytes to buffer for retrying requests")]
+    max_retry_buffer_size: usize,
+    #[metric(help = "The size of the blocks staged for block uploads")]
+    block_size: u64,
+    #[metric(help = "The number of blocks staged concurrently for one upload")]
+    max_concurrent_uploads: usize,
+}
+
+impl<I, NowFn> AzureStore<AzureBlobClient, NowFn>
+where
+    I: InstantWrapper,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    pub fn new(spec: &ExperimentalAzureSpec, now_fn: NowFn) -> Result<Arc<Self>, Error> {
+        let client = Arc::new(AzureBlobClient::new(spec).err_tip(|| "In AzureStore::new")?);
+        Self::new_with_ops(spec, client, now_fn)
+    }
+}
+
+impl<I, Client, NowFn> AzureStore<Client, NowFn>
+where
+    I: InstantWrapper,
+    Client: AzureBlobOperations + Send + Sync,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    // Primarily used for injecting a mock or real operations implementation
+    pub fn new_with_ops(
+        spec: &ExperimentalAzureSpec,
+        client: Arc<Client>,
+        now_fn: NowFn,
+    ) -> Result<Arc<Self>, Error> {
+        let jitter_amt = spec.common.retry.jitter;
+        let jitter_fn = Arc::new(move |delay: tokio::time::Duration| {
+            if jitter_amt == 0.0 {
+                return delay;
+            }
+            delay.mul_f32(jitter_amt.mul_add(rand::rng().random::<f32>() - 0.5, 1.))
+        });
+
+        let block_size = spec.block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
+        let block_size = u64::try_from(block_size)
+            .err_tip(|| "Could not convert block_size to u64")?
+            .clamp(1, MAX_BLOCK_SIZE);
+
+        Ok(Arc::new(Self {
+            client,
+            now_fn,
+            container: spec.container.clone(),
+            key_prefix: spec
+                .common
+                .key_prefix
+                .as_ref()
+                .unwrap_or(&String::new())
+                .clone(),
+            retrier: Retrier::new(
+                Arc::new(|duration| Box::pin(sleep(duration))),
+                jitter_fn,
+                spec.common.retry.clone(),
+            ),
+            consider_expired_after_s: i64::from(spec.common.consider_expired_after_s),
+            max_retry_buffer_size: spec
+                .common
+                .max_retry_buffer_per_request
+                .unwrap_or(DEFAULT_MAX_RETRY_BUFFER_PER_REQUEST),
+            block_size,
+            max_concurrent_uploads: spec
+                .common
+                .multipart_max_concurrent_uploads
+                .unwrap_or(DEFAULT_MAX_CONCURRENT_UPLOADS)
+                .max(1),
+        }))
+    }
+
+    async fn has(self: Pin<&Self>, key: &StoreKey<'_>) -> Result<Option<u64>, Error> {
+        let blob_name = &self.make_blob_name(key);
+        self.retrier
+            .retry(unfold((), move |()| async move {
+                let retry_result = match self.client.get_blob_properties(blob_name).await {
+                    Ok(Some(properties)) => {
+                        if self.consider_expired_after_s != 0 {
+                            let now_s = (self.now_fn)().unix_timestamp() as i64;
+                            if properties.last_modified_unix_s + self.consider_expired_after_s
+                                <= now_s
+                            {
+                                return Some((RetryResult::Ok(None), ()));
+                            }
+                        }
+                        RetryResult::Ok(Some(properties.content_length))
+                    }
+                    Ok(None) => RetryResult::Ok(None),
+                    Err(err) => RetryResult::Retry(err.append(format!(
+                        "Error while reading properties of azure blob {blob_name}"
+                    ))),
+                };
+                Some((retry_result, ()))
+            }))
+            .await
+    }
+
+    fn make_blob_name(&self, key: &StoreKey<'_>) -> String {
+        format!("{}{}", self.key_prefix, key.as_str())
+    }
+
+    /// Uploads `content` as the whole blob in a single request.
+    async fn put_blob(&self, blob_name: &str, content: Bytes) -> Result<(), Error> {
+        self.retrier
+            .retry(unfold(content, move |content| async move {
+                let retry_result = self
+                    .client
+                    .put_blob(blob_name, content.clone())
+                    .await
+                    .map_or_else(
+                        |err| {
+                            RetryResult::Retry(
+                                err.append(format!("Failed to upload azure blob {blob_name}")),
+                            )
+                        },
+                        RetryResult::Ok,
+                    );
+                Some((retry_result, content))
+            }))
+            .await
+    }
+}
+
+#[async_trait]
+impl<I, Client, NowFn> StoreDriver for AzureStore<Client, NowFn>
+where
+    I: InstantWrapper,
+    Client: AzureBlobOperations + 'static,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        keys.iter()
+            .zip(results.iter_mut())
+            .map(|(key, result)| async move {
+                if is_zero_digest(key.borrow()) {
+                    *result = Some(0);
+                    return Ok(());
+                }
+                *result = self.has(key).await?;
+                Ok(())
+            })
+            .collect::<FuturesUnordered<_>>()
+            .try_collect()
+            .await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        upload_size: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let blob_name = &self.make_blob_name(&key);
+
+        reader.set_max_recent_data_size(
+            u64::try_from(self.max_retry_buffer_size)
+                .err_tip(|| "Could not convert max_retry_buffer_size to u64")?,
+        );
+
+        // Small uploads of a known size only need a single request.
+        if let UploadSizeInfo::ExactSize(size) = upload_size {
+            if size < MIN_BLOCK_UPLOAD_SIZE {
+                let content = reader
+                    .consume(Some(
+                        usize::try_from(size).err_tip(|| "Could not convert size to usize")?,
+                    ))
+                    .await
+                    .err_tip(|| "Failed to read data in AzureStore::update")?;
+                return self.put_blob(blob_name, content).await;
+            }
+        }
+
+        // A block blob can have at most `MAX_BLOCKS` blocks, so grow the
+        // blocks if the upload might not fit.
+        let max_size = match upload_size {
+            UploadSizeInfo::ExactSize(size) | UploadSizeInfo::MaxSize(size) => size,
+        };
+        let block_size = max_size
+            .div_ceil(MAX_BLOCKS)
+            .clamp(self.block_size, MAX_BLOCK_SIZE);
+        let block_size =
+            usize::try_from(block_size).err_tip(|| "Could not convert block_size to usize")?;
+
+        // Blocks are read in order and staged concurrently. Staged blocks
+        // are only visible once committed, so a failed upload leaves the
+        // existing blob untouched and Azure discards the staged blocks.
+        let block_ids = try_unfold((reader, 0u64), move |(mut reader, index)| async move {
+            let block = reader
+                .consume(Some(block_size))
+                .await
+                .err_tip(|| "Failed to read block in AzureStore::update")?;
+            if block.is_empty() {
+                return Ok::<_, Error>(None); // Reached EOF.
+            }
+            Ok(Some(((index, block), (reader, index + 1))))
+        })
+        .map_ok(|(index, block): (u64, Bytes)| async move {
+            let block_id = make_block_id(index);
+            let block_id_ref = &block_id;
+            self.retrier
+                .retry(unfold(block, move |block| async move {
+                    let retry_result = self
+                        .client
+                        .put_block(blob_name, block_id_ref, block.clone())
+                        .await
+                        .map_or_else(
+                            |err| {
+                                RetryResult::Retry(err.append(format!(
+                                    "Failed to stage block {index} of azure blob {blob_name}"
+                                )))
+                            },
+                            RetryResult::Ok,
+                        );
+                    Some((retry_result, block))
+                }))
+                .await?;
+            Ok::<_, Error>(block_id)
+        })
+        .try_buffered(self.max_concurrent_uploads)
+        .try_collect::<Vec<String>>()
+        .await?;
+
+        if block_ids.is_empty() {
+            // Handle streamed empty file.
+            return self.put_blob(blob_name, Bytes::new()).await;
+        }
+
+        let block_ids = &block_ids;
+        self.retrier
+            .retry(unfold((), move |()| async move {
+                let retry_result = self
+                    .client
+                    .put_block_list(blob_name, block_ids)
+                    .await
+                    .map_or_else(
+                        |err| {
+                            RetryResult::Retry(err.append(format!(
+                                "Failed to commit blocks of azure blob {blob_name}"
+                            )))
+                        },
+                        RetryResult::Ok,
+                    );
+                Some((retry_result, ()))
+            }))
+            .await
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        if is_zero_digest(key.borrow()) {
+            writer
+                .send_eof()
+                .err_tip(|| "Failed to send zero EOF in AzureStore::get_part")?;
+            return Ok(());
+        }
+
+        let blob_name = &self.make_blob_name(&key);
+        let end_offset = length
+            .map(|length| {
+                offset
+                    .checked_add(length)
+                    .err_tip(|| "Integer overflow protection triggered")
+            })
+            .transpose()?;
+
+        self.retrier
+            .retry(unfold(writer, move |writer| async move {
+                // Resume from where a previous attempt stopped.
+                let start = offset + writer.get_bytes_written();
+                let mut stream = match self.client.get_blob(blob_name, start, end_offset).await {
+                    Ok(stream) => stream,
+                    Err(err) if err.code == Code::NotFound => {
+                        return Some((RetryResult::Err(err), writer));
+                    }
+                    Err(err) => return Some((RetryResult::Retry(err), writer)),
+                };
+
+                while let Some(next_chunk) = stream.next().await {
+                    match next_chunk {
+                        Ok(bytes) => {
+                            if bytes.is_empty() {
+                                continue;
+                            }
+                            if let Err(err) = writer.send(bytes).await {
+                                return Some((
+                                    RetryResult::Err(err.append(
+                                        "Error sending bytes to consumer in AzureStore::get_part",
+                                    )),
+                                    writer,
+                                ));
+                            }
+                        }
+                        Err(err) => {
+                            let mut err =
+                                err.append(format!("Error while reading azure blob {blob_name}"));
+                            err.code = Code::Aborted;
+                            return Some((RetryResult::Retry(err), writer));
+                        }
+                    }
+                }
+
+                if let Err(err) = writer.send_eof() {
+                    return Some((
+                        RetryResult::Err(make_err!(
+                            Code::Aborted,
+                            "Failed to send EOF in AzureStore::get_part: {err:?}"
+                        )),
+                        writer,
+                    ));
+                }
+
+                Some((RetryResult::Ok(()), writer))
+            }))
+            .await
+    }
+
+    fn inner_store(&self, _digest: Option<StoreKey>) -> &'_ dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_health(self: Arc<Self>, registry: &mut HealthRegistryBuilder) {
+        registry.register_indicator(self);
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        _callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        // As we're backed by Azure, this store doesn't actually drop stuff
+        // so we can actually just ignore this
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<I, Client, NowFn> HealthStatusIndicator for AzureStore<Client, NowFn>
+where
+    I: InstantWrapper,
+    Client: AzureBlobOperations + 'static,
+    NowFn: Fn() -> I + Send + Sync + Unpin + 'static,
+{
+    fn get_name(&self) -> &'static str {
+        "AzureStore"
+    }
+
+    async fn check_health(&self, namespace: Cow<'static, str>) -> HealthStatus {
+        StoreDriver::check_health(Pin::new(self), namespace).await
+    }
+}
@@ -0,0 +1,396 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::ops::Bound;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use nativelink_config::stores::{CircuitBreakerProbe, CircuitBreakerSpec};
+use nativelink_error::{Code, Error, ResultExt, error_if, make_err};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::digest_hasher::{DigestHasher, default_digest_hasher_func};
+use nativelink_util::health_utils::{HealthRegistryBuilder, HealthStatus, HealthStatusIndicator};
+use nativelink_util::store_trait::{
+    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo,
+};
+use parking_lot::Mutex;
+use tokio::time::{sleep, timeout};
+use tracing::{info, warn};
+
+/// Default interval between probes of the backend.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Default time after which a probe counts as failed.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default number of outcomes the error rate is computed over.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_WINDOW_SIZE: usize = 20;
+
+/// Default error rate at which the circuit opens.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_FAILURE_RATE_PERCENT: u32 = 50;
+
+/// Content of the object used to probe the backend.
+const PROBE_DATA: &[u8] = b"nativelink circuit breaker probe";
+
+/// Returns true if `err` hints at an unhealthy backend rather than at a
+/// problem with the request itself.
+const fn is_backend_failure(err: &Error) -> bool {
+    matches!(
+        err.code,
+        Code::Unavailable
+            | Code::DeadlineExceeded
+            | Code::Internal
+            | Code::Unknown
+            | Code::Aborted
+            | Code::DataLoss
+    )
+}
+
+/// Outcomes of the most recent requests and probes.
+#[derive(Debug, Default)]
+struct Window {
+    /// True for each outcome that was a failure, oldest first.
+    outcomes: VecDeque<bool>,
+    /// Number of failures in `outcomes`.
+    failures: usize,
+}
+
+impl Window {
+    fn clear(&mut self) {
+        self.outcomes.clear();
+        self.failures = 0;
+    }
+}
+
+#[derive(Debug, Default, MetricsComponent)]
+struct CircuitBreakerMetrics {
+    #[metric(help = "Number of times the circuit opened")]
+    opened: AtomicU64,
+    #[metric(help = "Number of requests failed right away while the circuit was open")]
+    rejected_requests: AtomicU64,
+    #[metric(help = "Number of requests sent to the fallback store while the circuit was open")]
+    fallback_requests: AtomicU64,
+    #[metric(help = "Number of failed probes of the backend")]
+    failed_probes: AtomicU64,
+}
+
+#[derive(Debug, MetricsComponent)]
+pub struct CircuitBreakerStore {
+    #[metric(group = "backend")]
+    backend: Store,
+    #[metric(group = "fallback")]
+    fallback: Option<Store>,
+    probe: CircuitBreakerProbe,
+    probe_digest: DigestInfo,
+    probe_timeout: Duration,
+    #[metric(help = "Number of outcomes the error rate is computed over")]
+    window_size: usize,
+    #[metric(help = "Error rate in percent at which the circuit opens")]
+    failure_rate_percent: u32,
+    open: AtomicBool,
+    window: Mutex<Window>,
+    #[metric]
+    metrics: CircuitBreakerMetrics,
+}
+
+impl CircuitBreakerStore {
+    pub fn new(
+        spec: &CircuitBreakerSpec,
+        backend: Store,
+        fallback: Option<Store>,
+    ) -> Result<Arc<Self>, Error> {
+        error_if!(
+            spec.failure_rate_percent > 100,
+            "failure_rate_percent of CircuitBreakerStore must be at most 100, got {}",
+            spec.failure_rate_percent
+        );
+        let probe_interval = if spec.probe_interval_seconds == 0 {
+            DEFAULT_PROBE_INTERVAL
+        } else {
+            Duration::from_secs(spec.probe_interval_seconds)
+        };
+        let mut hasher = default_digest_hasher_func().hasher();
+        hasher.update(PROBE_DATA);
+        let store = Arc::new(Self {
+            backend,
+            fallback,
+            probe: spec.probe,
+            probe_digest: hasher.finalize_digest(),
+            probe_timeout: if spec.probe_timeout_seconds == 0 {
+                DEFAULT_PROBE_TIMEOUT
+            } else {
+                Duration::from_secs(spec.probe_timeout_seconds)
+            },
+            window_size: if spec.window_size == 0 {
+                DEFAULT_WINDOW_SIZE
+            } else {
+                spec.window_size
+            },
+            failure_rate_percent: if spec.failure_rate_percent == 0 {
+                DEFAULT_FAILURE_RATE_PERCENT
+            } else {
+                spec.failure_rate_percent
+            },
+            open: AtomicBool::new(false),
+            window: Mutex::new(Window::default()),
+            metrics: CircuitBreakerMetrics::default(),
+        });
+
+        let weak_store = Arc::downgrade(&store);
+        background_spawn!("circuit_breaker_store_probe", async move {
+            loop {
+                sleep(probe_interval).await;
+                let Some(store) = weak_store.upgrade() else {
+                    return;
+                };
+                store.probe().await;
+            }
+        });
+        Ok(store)
+    }
+
+    /// Returns true if requests currently bypass the backend.
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Acquire)
+    }
+
+    /// Probes the backend right away. A successful probe closes the circuit,
+    /// otherwise the outcome counts towards the error rate.
+    pub async fn probe(&self) {
+        let result = timeout(self.probe_timeout, self.probe_backend())
+            .await
+            .unwrap_or_else(|_| {
+                Err(make_err!(
+                    Code::DeadlineExceeded,
+                    "Probe of CircuitBreakerStore backend timed out after {:?}",
+                    self.probe_timeout
+                ))
+            });
+        if let Err(err) = &result {
+            self.metrics.failed_probes.fetch_add(1, Ordering::Relaxed);
+            warn!(?err, "Probe of CircuitBreakerStore backend failed");
+        }
+        if self.is_open() {
+            if result.is_ok() {
+                self.window.lock().clear();
+                self.open.store(false, Ordering::Release);
+                info!("Closing circuit of CircuitBreakerStore, the backend recovered");
+            }
+            return;
+        }
+        self.record(result.is_err());
+    }
+
+    async fn probe_backend(&self) -> Result<(), Error> {
+        match self.probe {
+            CircuitBreakerProbe::Has => self
+                .backend
+                .has(self.probe_digest)
+                .await
+                .map(|_| ())
+                .err_tip(|| "In CircuitBreakerStore::probe_backend"),
+            CircuitBreakerProbe::WriteRead => {
+                self.backend
+                    .update_oneshot(self.probe_digest, Bytes::from_static(PROBE_DATA))
+                    .await
+                    .err_tip(|| "Failed to write probe in CircuitBreakerStore")?;
+                let data = self
+                    .backend
+                    .get_part_unchunked(self.probe_digest, 0, None)
+                    .await
+                    .err_tip(|| "Failed to read probe in CircuitBreakerStore")?;
+                error_if!(
+                    data != PROBE_DATA,
+                    "Probe read back from CircuitBreakerStore backend differs from what was written"
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Records the outcome of a request or probe while the circuit is
+    /// closed, and opens it once the error rate is reached.
+    fn record(&self, failed: bool) {
+        let mut window = self.window.lock();
+        window.outcomes.push_back(failed);
+        window.failures += usize::from(failed);
+        if window.outcomes.len() > self.window_size && window.outcomes.pop_front() == Some(true) {
+            window.failures -= 1;
+        }
+        if window.outcomes.len() < self.window_size
+            || window.failures * 100 < self.window_size * self.failure_rate_percent as usize
+            || self.is_open()
+        {
+            return;
+        }
+        warn!(
+            failures = window.failures,
+            window_size = self.window_size,
+            "Opening circuit of CircuitBreakerStore, the backend looks unhealthy"
+        );
+        window.clear();
+        self.open.store(true, Ordering::Release);
+        self.metrics.opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the outcome of a request sent to the backend.
+    fn track<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        // Outcomes of requests started before the circuit opened are
+        // ignored, only probes close it again.
+        if !self.is_open() {
+            self.record(result.as_ref().is_err_and(is_backend_failure));
+        }
+        result
+    }
+
+    /// Returns the store to send a request to while the circuit is open, or
+    /// `None` if it is closed and the request should go to the backend.
+    fn fallback_if_open(&self) -> Result<Option<&Store>, Error> {
+        if !self.is_open() {
+            return Ok(None);
+        }
+        let Some(fallback) = &self.fallback else {
+            self.metrics
+                .rejected_requests
+                .fetch_add(1, Ordering::Relaxed);
+            return Err(make_err!(
+                Code::Unavailable,
+                "Circuit of CircuitBreakerStore is open, the backend is unhealthy"
+            ));
+        };
+        self.metrics
+            .fallback_requests
+            .fetch_add(1, Ordering::Relaxed);
+        Ok(Some(fallback))
+    }
+}
+
+#[async_trait]
+impl StoreDriver for CircuitBreakerStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        if let Some(fallback) = self.fallback_if_open()? {
+            return fallback.has_with_results(keys, results).await;
+        }
+        self.track(self.backend.has_with_results(keys, results).await)
+    }
+
+    async fn list(
+        self: Pin<&Self>,
+        range: (Bound<StoreKey<'_>>, Bound<StoreKey<'_>>),
+        handler: &mut (dyn for<'a> FnMut(&'a StoreKey) -> bool + Send + Sync + '_),
+    ) -> Result<u64, Error> {
+        let store = self.fallback_if_open()?.unwrap_or(&self.backend);
+        let result = store.as_store_driver_pin().list(range, handler).await;
+        if core::ptr::eq(store, &self.backend) {
+            return self.track(result);
+        }
+        result
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        if let Some(fallback) = self.fallback_if_open()? {
+            return fallback.remove(key).await;
+        }
+        self.track(self.backend.remove(key).await)
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        reader: DropCloserReadHalf,
+        size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        if let Some(fallback) = self.fallback_if_open()? {
+            return fallback.update(key, reader, size_info).await;
+        }
+        self.track(self.backend.update(key, reader, size_info).await)
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        if let Some(fallback) = self.fallback_if_open()? {
+            return fallback.get_part(key, writer, offset, length).await;
+        }
+        self.track(self.backend.get_part(key, writer, offset, length).await)
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_health(self: Arc<Self>, registry: &mut HealthRegistryBuilder) {
+        registry.register_indicator(self);
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        self.backend.register_remove_callback(callback)?;
+        if let Some(fallback) = &self.fallback {
+            fallback.register_remove_callback(callback)?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl HealthStatusIndicator for CircuitBreakerStore {
+    fn get_name(&self) -> &'static str {
+        "CircuitBreakerStore"
+    }
+
+    async fn check_health(&self, _namespace: Cow<'static, str>) -> HealthStatus {
+        if !self.is_open() {
+            HealthStatus::new_ok(self, "Circuit is closed".into())
+        } else if self.fallback.is_some() {
+            HealthStatus::new_warning(
+                self,
+                "Circuit is open, requests go to the fallback store".into(),
+            )
+        } else {
+            HealthStatus::new_failed(self, "Circuit is open, requests are rejected".into())
+        }
+    }
+}
@@ -13,12 +13,15 @@
 // limitations under the License.
 
 use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
 use core::{iter, mem};
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use async_trait::async_trait;
-use futures::stream::{FuturesUnordered, StreamExt};
+use futures::stream::{self, FuturesUnordered, StreamExt};
 use futures::{FutureExt, TryFutureExt, select};
+use nativelink_config::stores::{CompletenessCheckingSpec, EvictionPolicy};
 use nativelink_error::{Code, Error, ResultExt, make_err};
 use nativelink_metric::MetricsComponent;
 use nativelink_proto::build::bazel::remote::execution::v2::{
@@ -26,10 +29,11 @@ use nativelink_proto::build::bazel::remote::execution::v2::{
 };
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
 use nativelink_util::common::DigestInfo;
+use nativelink_util::evicting_map::{EvictingMap, LenEntry};
 use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
 use nativelink_util::metrics_utils::CounterWithTime;
 use nativelink_util::store_trait::{
-    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo,
+    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreKeyBorrow, StoreLike, UploadSizeInfo,
 };
 use parking_lot::Mutex;
 use tokio::sync::Notify;
@@ -37,6 +41,30 @@ use tracing::warn;
 
 use crate::ac_utils::{get_and_decode_digest, get_size_and_decode_digest};
 
+/// Default maximum number of concurrent requests to the CAS store.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_CONCURRENT_CHECKS: usize = 16;
+
+/// Default maximum number of verified action results remembered.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_VERIFIED_RESULTS: u64 = 100_000;
+
+/// Size of an action result that was found to be complete.
+#[derive(Clone, Copy, Debug)]
+struct VerifiedActionResult(u64);
+
+impl LenEntry for VerifiedActionResult {
+    #[inline]
+    fn len(&self) -> u64 {
+        self.0
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
 /// Given a proto action result, return all relevant digests and
 /// output directories that need to be checked.
 fn get_digests_and_output_dirs(
@@ -61,22 +89,22 @@ fn get_digests_and_output_dirs(
 
 /// Given a list of output directories recursively get all digests
 /// that need to be checked and pass them into `handle_digest_infos_fn`
-/// as they are found.
+/// as they are found. At most `max_concurrent_checks` trees are
+/// fetched at the same time.
 #[expect(clippy::future_not_send)] // TODO(jhpratt) remove this
 async fn check_output_directories<'a>(
     cas_store: &Store,
     output_directories: Vec<ProtoOutputDirectory>,
+    max_concurrent_checks: usize,
     handle_digest_infos_fn: &impl Fn(Vec<StoreKey<'a>>),
 ) -> Result<(), Error> {
-    let mut futures = FuturesUnordered::new();
-
     let tree_digests = output_directories
         .into_iter()
-        .filter_map(|output_dir| output_dir.tree_digest.map(DigestInfo::try_from));
-    for maybe_tree_digest in tree_digests {
-        let tree_digest = maybe_tree_digest
-            .err_tip(|| "Could not decode tree digest CompletenessCheckingStore::has")?;
-        futures.push(async move {
+        .filter_map(|output_dir| output_dir.tree_digest.map(DigestInfo::try_from))
+        .collect::<Result<Vec<_>, _>>()
+        .err_tip(|| "Could not decode tree digest CompletenessCheckingStore::has")?;
+    let mut futures = stream::iter(tree_digests)
+        .map(|tree_digest| async move {
             let tree = get_and_decode_digest::<ProtoTree>(cas_store, tree_digest.into()).await?;
             // TODO(palfrey) When `try_collect()` is stable we can use it instead.
             // https://github.com/rust-lang/rust/issues/94047
@@ -95,8 +123,8 @@ async fn check_output_directories<'a>(
                 .err_tip(|| "Expected digest to exist and be convertible")?;
             handle_digest_infos_fn(digest_infos);
             Ok(())
-        });
-    }
+        })
+        .buffer_unordered(max_concurrent_checks);
 
     while let Some(result) = futures.next().await {
         match result {
@@ -112,22 +140,122 @@ pub struct CompletenessCheckingStore {
     cas_store: Store,
     ac_store: Store,
 
+    #[metric(help = "Maximum number of concurrent requests to the CAS store")]
+    max_concurrent_checks: usize,
+    // Action results recently found to be complete, if enabled.
+    verified_results:
+        Option<EvictingMap<StoreKeyBorrow, StoreKey<'static>, VerifiedActionResult, SystemTime>>,
+    // Incremented whenever an action result is uploaded, so a verification
+    // that raced with an upload doesn't remember the uploaded action result
+    // as complete.
+    upload_generation: AtomicU64,
+
     #[metric(help = "Incomplete entries hit in CompletenessCheckingStore")]
     incomplete_entries_counter: CounterWithTime,
     #[metric(help = "Complete entries hit in CompletenessCheckingStore")]
     complete_entries_counter: CounterWithTime,
+    #[metric(help = "Entries reported as complete without verifying them again")]
+    verified_results_hits: AtomicU64,
 }
 
 impl CompletenessCheckingStore {
-    pub fn new(ac_store: Store, cas_store: Store) -> Arc<Self> {
+    pub fn new(spec: &CompletenessCheckingSpec, ac_store: Store, cas_store: Store) -> Arc<Self> {
+        let verified_results = (spec.verified_results_ttl_seconds != 0).then(|| {
+            let eviction_policy = EvictionPolicy {
+                max_seconds: spec.verified_results_ttl_seconds,
+                max_count: if spec.max_verified_results == 0 {
+                    DEFAULT_MAX_VERIFIED_RESULTS
+                } else {
+                    spec.max_verified_results
+                },
+                ..Default::default()
+            };
+            EvictingMap::new(&eviction_policy, SystemTime::now())
+        });
         Arc::new(Self {
             cas_store,
             ac_store,
+            max_concurrent_checks: if spec.max_concurrent_checks == 0 {
+                DEFAULT_MAX_CONCURRENT_CHECKS
+            } else {
+                spec.max_concurrent_checks
+            },
+            verified_results,
+            upload_generation: AtomicU64::new(0),
             incomplete_entries_counter: CounterWithTime::default(),
             complete_entries_counter: CounterWithTime::default(),
+            verified_results_hits: AtomicU64::new(0),
         })
     }
 
+    /// Same as `inner_has_with_results`, but action results which were
+    /// recently found to be complete are not verified again.
+    async fn memoized_has_with_results(
+        &self,
+        action_result_digests: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        let Some(verified_results) = &self.verified_results else {
+            return self
+                .inner_has_with_results(action_result_digests, results)
+                .await;
+        };
+        let owned_digests: Vec<_> = action_result_digests
+            .iter()
+            .map(|digest| digest.borrow().into_owned())
+            .collect();
+        // Peek, so entries expire based on when they were verified and not
+        // when they were last used.
+        verified_results
+            .sizes_for_keys(owned_digests.iter(), results, true /* peek */)
+            .await;
+        let (unverified_idxs, unverified_digests): (Vec<_>, Vec<_>) = owned_digests
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| results[*i].is_none())
+            .unzip();
+        self.verified_results_hits.fetch_add(
+            (results.len() - unverified_idxs.len()) as u64,
+            Ordering::Relaxed,
+        );
+        if unverified_digests.is_empty() {
+            return Ok(());
+        }
+
+        let upload_generation = self.upload_generation.load(Ordering::Acquire);
+        let mut unverified_results = vec![None; unverified_digests.len()];
+        self.inner_has_with_results(&unverified_digests, &mut unverified_results)
+            .await?;
+        if upload_generation == self.upload_generation.load(Ordering::Acquire) {
+            let verified: Vec<_> = unverified_digests
+                .iter()
+                .zip(unverified_results.iter())
+                .filter_map(|(digest, result)| result.map(|size| (digest.clone(), size)))
+                .collect();
+            drop(
+                verified_results
+                    .insert_many(
+                        verified.iter().map(|(digest, size)| {
+                            (digest.clone().into(), VerifiedActionResult(*size))
+                        }),
+                    )
+                    .await,
+            );
+            // An upload that bumped the generation between the check above and
+            // the insert may have already removed its entry, so it has to be
+            // removed again here.
+            if upload_generation != self.upload_generation.load(Ordering::Acquire) {
+                for (digest, _) in verified {
+                    verified_results.remove(&digest).await;
+                }
+            }
+        }
+        for (i, result) in unverified_idxs.into_iter().zip(unverified_results) {
+            results[i] = result;
+        }
+        Ok(())
+    }
+
     /// Check that all files and directories in action results
     /// exist in the CAS. Does this by decoding digests and
     /// checking their existence in two separate sets of futures that
@@ -206,6 +334,7 @@ impl CompletenessCheckingStore {
                     check_output_directories(
                         &self.cas_store,
                         output_directories,
+                        self.max_concurrent_checks,
                         &move |digest_infos| {
                             let mut state = state_mux.lock();
                             let rep_len = digest_infos.len();
@@ -235,16 +364,32 @@ impl CompletenessCheckingStore {
 
         // This future will wait for the notify to be notified and then
         // check the CAS store for the digest's existence.
-        // For optimization reasons we only allow one outstanding call to
-        // the underlying `has_with_results()` at a time. This is because
-        // we want to give the ability for stores to batch requests together
-        // whenever possible.
+        // For optimization reasons we allow at most `max_concurrent_checks`
+        // outstanding calls to the underlying `has_with_results()` at a time.
+        // While the limit is reached digests keep accumulating, which gives
+        // stores the ability to batch requests together whenever possible.
         // The most common case is only one notify will ever happen.
         let check_existence_fut = async {
-            let mut has_results = vec![];
             let notify = state_mux.lock().notify.clone();
+            let mut in_flight_checks = FuturesUnordered::new();
             loop {
-                notify.notified().await;
+                if in_flight_checks.len() >= self.max_concurrent_checks {
+                    if let Some(result) = in_flight_checks.next().await {
+                        result?;
+                    }
+                    continue;
+                }
+                if in_flight_checks.is_empty() {
+                    notify.notified().await;
+                } else {
+                    select! {
+                        () = notify.notified().fuse() => {}
+                        result = in_flight_checks.select_next_some() => {
+                            result?;
+                            continue;
+                        }
+                    }
+                }
                 let (digests, indexes) = {
                     let mut state = state_mux.lock();
                     if state.done {
@@ -268,26 +413,31 @@ impl CompletenessCheckingStore {
                     indexes.len(),
                     "Expected sizes to match in CompletenessCheckingStore::has"
                 );
+                if digests.is_empty() {
+                    continue;
+                }
 
-                // Recycle our results vector to avoid needless allocations.
-                has_results.clear();
-                has_results.resize(digests.len(), None);
-                self.cas_store
-                    .has_with_results(&digests, &mut has_results[..])
-                    .await
-                    .err_tip(
-                        || "Error calling has_with_results() inside CompletenessCheckingStore::has",
-                    )?;
-                let missed_indexes = has_results
-                    .iter()
-                    .zip(indexes)
-                    .filter_map(|(r, index)| r.map_or_else(|| Some(index), |_| None));
-                {
+                in_flight_checks.push(async move {
+                    let mut has_results = vec![None; digests.len()];
+                    self.cas_store
+                        .has_with_results(&digests, &mut has_results[..])
+                        .await
+                        .err_tip(
+                            || "Error calling has_with_results() inside CompletenessCheckingStore::has",
+                        )?;
+                    let missed_indexes = has_results
+                        .iter()
+                        .zip(indexes)
+                        .filter_map(|(r, index)| r.map_or_else(|| Some(index), |_| None));
                     let mut state = state_mux.lock();
                     for index in missed_indexes {
                         state.results[index] = None;
                     }
-                }
+                    Result::<(), Error>::Ok(())
+                });
+            }
+            while let Some(result) = in_flight_checks.next().await {
+                result?;
             }
             Result::<(), Error>::Ok(())
         }
@@ -346,7 +496,7 @@ impl StoreDriver for CompletenessCheckingStore {
         keys: &[StoreKey<'_>],
         results: &mut [Option<u64>],
     ) -> Result<(), Error> {
-        self.inner_has_with_results(keys, results).await
+        self.memoized_has_with_results(keys, results).await
     }
 
     async fn update(
@@ -355,7 +505,12 @@ impl StoreDriver for CompletenessCheckingStore {
         reader: DropCloserReadHalf,
         size_info: UploadSizeInfo,
     ) -> Result<(), Error> {
-        self.ac_store.update(key, reader, size_info).await
+        let result = self.ac_store.update(key.borrow(), reader, size_info).await;
+        if let Some(verified_results) = &self.verified_results {
+            self.upload_generation.fetch_add(1, Ordering::AcqRel);
+            verified_results.remove(&key.into_owned()).await;
+        }
+        result
     }
 
     async fn get_part(
@@ -366,7 +521,7 @@ impl StoreDriver for CompletenessCheckingStore {
         length: Option<u64>,
     ) -> Result<(), Error> {
         let results = &mut [None];
-        self.inner_has_with_results(&[key.borrow()], results)
+        self.memoized_has_with_results(&[key.borrow()], results)
             .await
             .err_tip(|| "when calling CompletenessCheckingStore::get_part")?;
         if results[0].is_none() {
@@ -14,26 +14,40 @@
 
 use core::cmp;
 use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use async_trait::async_trait;
 use bincode::serde::{decode_from_slice, encode_to_vec};
 use byteorder::{ByteOrder, LittleEndian};
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use futures::future::FutureExt;
 use lz4_flex::block::{compress_into, decompress_into, get_maximum_output_size};
-use nativelink_config::stores::CompressionSpec;
-use nativelink_error::{Code, Error, ResultExt, error_if, make_err};
+use nativelink_config::stores::{
+    CompressionAlgorithm, CompressionSpec, SkipCompressionConfig, ZstdConfig,
+    ZstdDictionaryTrainingConfig,
+};
+use nativelink_error::{Code, Error, ResultExt, error_if, make_err, make_input_err};
 use nativelink_metric::MetricsComponent;
 use nativelink_util::buf_channel::{
     DropCloserReadHalf, DropCloserWriteHalf, make_buf_channel_pair,
 };
 use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
-use nativelink_util::spawn;
 use nativelink_util::store_trait::{
     RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo,
 };
+use nativelink_util::{background_spawn, spawn, spawn_blocking};
+use parking_lot::Mutex;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
+use zstd::bulk::{Compressor, Decompressor};
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+use zstd::zstd_safe;
 
 use crate::cas_utils::is_zero_digest;
 
@@ -41,6 +55,43 @@ use crate::cas_utils::is_zero_digest;
 // backwards compatibility issues.
 pub const CURRENT_STREAM_FORMAT_VERSION: u8 = 1;
 
+/// Version of streams whose blocks are zstd frames instead of lz4 blocks. The
+/// framing is the same as for `CURRENT_STREAM_FORMAT_VERSION`.
+pub const ZSTD_STREAM_FORMAT_VERSION: u8 = 2;
+
+/// Version of streams whose blocks are stored uncompressed, because the data
+/// did not compress well. The framing is the same as for
+/// `CURRENT_STREAM_FORMAT_VERSION`.
+pub const UNCOMPRESSED_STREAM_FORMAT_VERSION: u8 = 3;
+
+/// Prefix of the keys zstd dictionaries are stored under in the inner store.
+/// The dictionary id is appended to it.
+pub const ZSTD_DICTIONARY_KEY_PREFIX: &str = "zstd_dictionary_";
+
+// Defaults of the zstd config.
+// Note: If these change, remember to change the documentation in the config.
+const DEFAULT_ZSTD_LEVEL: i32 = 3;
+const DEFAULT_TRAINING_INTERVAL_S: u64 = 60 * 60;
+const DEFAULT_MAX_SAMPLE_SIZE: u32 = 16 * 1024;
+const DEFAULT_MAX_SAMPLES: u32 = 10_000;
+const DEFAULT_MIN_SAMPLES: u32 = 1_000;
+const DEFAULT_DICTIONARY_SIZE: u32 = 110 * 1024;
+
+// Defaults of the skip compression config.
+// Note: If these change, remember to change the documentation in the config.
+const DEFAULT_SKIP_SAMPLE_SIZE: u32 = 64 * 1024;
+const DEFAULT_MIN_SAVINGS_PERCENT: u8 = 1;
+
+/// Magic numbers of formats which are already compressed.
+const COMPRESSED_FORMAT_MAGICS: &[&[u8]] = &[
+    &[0x28, 0xb5, 0x2f, 0xfd],             // zstd
+    &[0x1f, 0x8b],                         // gzip
+    &[0xfd, b'7', b'z', b'X', b'Z', 0x00], // xz
+    &[0xff, 0xd8, 0xff],                   // jpeg
+    &[0x89, b'P', b'N', b'G'],             // png
+    &[b'P', b'K', 0x03, 0x04],             // zip, jar
+];
+
 // Default block size that will be used to slice stream into.
 pub const DEFAULT_BLOCK_SIZE: u32 = 64 * 1024;
 
@@ -78,7 +129,10 @@ const U32_SZ: u64 = size_of::<u8>() as u64;
 // |---------------------------------------------------------------------------------|
 //
 // version              - A constant number used to define what version of this format is being
-//                        used. Version in header and footer must match.
+//                        used. Version in header and footer must match. Version 1 blocks are
+//                        lz4 compressed, version 2 blocks are zstd frames, which record the id
+//                        of the dictionary they were compressed with, version 3 blocks are not
+//                        compressed at all.
 // block_size           - Size of each block uncompressed except for last block. This means that
 //                        every block uncompressed will be a constant size except last block may
 //                        be variable size. Block size in header and footer must match.
@@ -164,6 +218,120 @@ where
     Ok(size_writer.bytes_written as u64)
 }
 
+fn zstd_dictionary_key(id: u32) -> StoreKey<'static> {
+    StoreKey::Str(Cow::Owned(format!("{ZSTD_DICTIONARY_KEY_PREFIX}{id}")))
+}
+
+/// A zstd dictionary new uploads are compressed with.
+struct ZstdDictionary {
+    id: u32,
+    raw: Bytes,
+    encoder: EncoderDictionary<'static>,
+    /// Set once the dictionary was written to the inner store.
+    persisted: OnceCell<()>,
+}
+
+impl core::fmt::Debug for ZstdDictionary {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ZstdDictionary")
+            .field("id", &self.id)
+            .field("size", &self.raw.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ZstdDictionary {
+    fn new(raw: Bytes, level: i32) -> Result<Self, Error> {
+        let id = zstd_safe::get_dict_id_from_dict(&raw)
+            .err_tip(|| "zstd dictionary has no dictionary id, create it with `zstd --train`")?
+            .get();
+        Ok(Self {
+            id,
+            encoder: EncoderDictionary::copy(&raw, level),
+            raw,
+            persisted: OnceCell::new(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct ZstdTraining {
+    config: ZstdDictionaryTrainingConfig,
+    samples: Mutex<Vec<Bytes>>,
+    samples_seen: AtomicU64,
+}
+
+impl ZstdTraining {
+    fn new(config: &ZstdDictionaryTrainingConfig) -> Self {
+        fn or_default<T: PartialEq + Default>(value: T, default: T) -> T {
+            if value == T::default() {
+                default
+            } else {
+                value
+            }
+        }
+        let config = ZstdDictionaryTrainingConfig {
+            interval_s: or_default(config.interval_s, DEFAULT_TRAINING_INTERVAL_S),
+            max_sample_size: or_default(config.max_sample_size, DEFAULT_MAX_SAMPLE_SIZE),
+            max_samples: or_default(config.max_samples, DEFAULT_MAX_SAMPLES),
+            min_samples: or_default(config.min_samples, DEFAULT_MIN_SAMPLES),
+            dictionary_size: or_default(config.dictionary_size, DEFAULT_DICTIONARY_SIZE),
+        };
+        Self {
+            config,
+            samples: Mutex::new(Vec::new()),
+            samples_seen: AtomicU64::new(0),
+        }
+    }
+
+    /// Keeps a uniformly random subset of all samples (reservoir sampling).
+    fn add_sample(&self, sample: &[u8]) {
+        let seen = self.samples_seen.fetch_add(1, Ordering::Relaxed);
+        let mut samples = self.samples.lock();
+        if samples.len() < self.config.max_samples as usize {
+            samples.push(Bytes::copy_from_slice(sample));
+            return;
+        }
+        let index = rand::rng().random_range(0..=seen);
+        if let Some(slot) = samples.get_mut(index as usize) {
+            *slot = Bytes::copy_from_slice(sample);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ZstdState {
+    level: i32,
+    dictionary: Mutex<Option<Arc<ZstdDictionary>>>,
+    training: Option<ZstdTraining>,
+}
+
+impl ZstdState {
+    fn new(config: &ZstdConfig) -> Result<Self, Error> {
+        let level = if config.level == 0 {
+            DEFAULT_ZSTD_LEVEL
+        } else {
+            config.level
+        };
+        let dictionary = config
+            .dictionary_path
+            .as_ref()
+            .map(|path| {
+                let raw = std::fs::read(path)
+                    .map_err(|e| make_input_err!("Could not read zstd dictionary {path}: {e}"))?;
+                ZstdDictionary::new(raw.into(), level)
+                    .err_tip(|| format!("While loading zstd dictionary {path}"))
+                    .map(Arc::new)
+            })
+            .transpose()?;
+        Ok(Self {
+            level,
+            dictionary: Mutex::new(dictionary),
+            training: config.dictionary_training.as_ref().map(ZstdTraining::new),
+        })
+    }
+}
+
 struct UploadState {
     header: Header,
     footer: Footer,
@@ -180,12 +348,17 @@ impl UploadState {
             UploadSizeInfo::MaxSize(sz) | UploadSizeInfo::ExactSize(sz) => sz,
         };
 
-        let max_index_count = (input_max_size / u64::from(store.config.block_size)) + 1;
+        let max_index_count = (input_max_size / u64::from(store.block_size)) + 1;
 
+        let version = if store.zstd.is_some() {
+            ZSTD_STREAM_FORMAT_VERSION
+        } else {
+            CURRENT_STREAM_FORMAT_VERSION
+        };
         let header = Header {
-            version: CURRENT_STREAM_FORMAT_VERSION,
+            version,
             config: Lz4Config {
-                block_size: store.config.block_size,
+                block_size: store.block_size,
             },
             upload_size,
         };
@@ -198,11 +371,16 @@ impl UploadState {
             index_count: max_index_count as u32,
             uncompressed_data_size: 0, // Updated later.
             config: header.config,
-            version: CURRENT_STREAM_FORMAT_VERSION,
+            version,
         };
 
-        // This is more accurate of an estimate than what get_maximum_output_size calculates.
-        let max_block_size = lz4_compress_bound(u64::from(store.config.block_size)) + U32_SZ + 1;
+        let max_compressed_block_size = if store.zstd.is_some() {
+            zstd_safe::compress_bound(store.block_size as usize) as u64
+        } else {
+            // This is more accurate of an estimate than what get_maximum_output_size calculates.
+            lz4_compress_bound(u64::from(store.block_size))
+        };
+        let max_block_size = max_compressed_block_size + U32_SZ + 1;
 
         let max_output_size = {
             let header_size = serialized_size(&header, store.bincode_config)?;
@@ -235,7 +413,18 @@ type LegacyBincodeConfig = bincode::config::Configuration<
 pub struct CompressionStore {
     #[metric(group = "inner_store")]
     inner_store: Store,
-    config: nativelink_config::stores::Lz4Config,
+    #[metric(help = "Size of the blocks the data is compressed in")]
+    block_size: u32,
+    #[metric(help = "Maximum block size of data allowed to be decompressed")]
+    max_decode_block_size: u32,
+    /// Set if new data is compressed with zstd instead of lz4.
+    zstd: Option<ZstdState>,
+    /// Dictionaries needed to read zstd compressed data, by id.
+    decoder_dictionaries: Mutex<HashMap<u32, Arc<DecoderDictionary<'static>>>>,
+    /// Set if uploads which do not compress well are stored uncompressed.
+    skip_incompressible: Option<SkipCompressionConfig>,
+    #[metric(help = "Number of uploads stored uncompressed because they did not compress well")]
+    uncompressed_uploads: AtomicU64,
     bincode_config: LegacyBincodeConfig,
 }
 
@@ -243,29 +432,206 @@ impl core::fmt::Debug for CompressionStore {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("CompressionStore")
             .field("inner_store", &self.inner_store)
-            .field("config", &self.config)
+            .field("block_size", &self.block_size)
+            .field("max_decode_block_size", &self.max_decode_block_size)
+            .field("zstd", &self.zstd)
+            .field("skip_incompressible", &self.skip_incompressible)
             .finish_non_exhaustive()
     }
 }
 
 impl CompressionStore {
     pub fn new(spec: &CompressionSpec, inner_store: Store) -> Result<Arc<Self>, Error> {
-        let lz4_config = match spec.compression_algorithm {
-            nativelink_config::stores::CompressionAlgorithm::Lz4(mut lz4_config) => {
-                if lz4_config.block_size == 0 {
-                    lz4_config.block_size = DEFAULT_BLOCK_SIZE;
-                }
-                if lz4_config.max_decode_block_size == 0 {
-                    lz4_config.max_decode_block_size = lz4_config.block_size;
-                }
-                lz4_config
-            }
+        let (block_size, max_decode_block_size, zstd) = match &spec.compression_algorithm {
+            CompressionAlgorithm::Lz4(lz4_config) => (
+                lz4_config.block_size,
+                lz4_config.max_decode_block_size,
+                None,
+            ),
+            CompressionAlgorithm::Zstd(zstd_config) => (
+                zstd_config.block_size,
+                zstd_config.max_decode_block_size,
+                Some(ZstdState::new(zstd_config)?),
+            ),
         };
-        Ok(Arc::new(Self {
+        let block_size = if block_size == 0 {
+            DEFAULT_BLOCK_SIZE
+        } else {
+            block_size
+        };
+        let max_decode_block_size = if max_decode_block_size == 0 {
+            block_size
+        } else {
+            max_decode_block_size
+        };
+        let store = Arc::new(Self {
             inner_store,
-            config: lz4_config,
+            block_size,
+            max_decode_block_size,
+            zstd,
+            decoder_dictionaries: Mutex::new(HashMap::new()),
+            skip_incompressible: spec
+                .skip_incompressible
+                .map(|config| SkipCompressionConfig {
+                    sample_size: if config.sample_size == 0 {
+                        DEFAULT_SKIP_SAMPLE_SIZE
+                    } else {
+                        config.sample_size
+                    },
+                    min_savings_percent: if config.min_savings_percent == 0 {
+                        DEFAULT_MIN_SAVINGS_PERCENT
+                    } else {
+                        config.min_savings_percent.min(100)
+                    },
+                }),
+            uncompressed_uploads: AtomicU64::new(0),
             bincode_config: bincode::config::legacy(),
-        }))
+        });
+        if let Some(dictionary) = store.current_zstd_dictionary() {
+            store.add_decoder_dictionary(&dictionary);
+        }
+
+        if let Some(training) = store.zstd.as_ref().and_then(|zstd| zstd.training.as_ref()) {
+            let interval = Duration::from_secs(training.config.interval_s);
+            let weak_store = Arc::downgrade(&store);
+            background_spawn!("compression_store_dictionary_training", async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    let Some(store) = weak_store.upgrade() else {
+                        return;
+                    };
+                    if let Err(err) = store.train_zstd_dictionary().await {
+                        warn!(?err, "Failed to train zstd dictionary in compression store");
+                    }
+                }
+            });
+        }
+        Ok(store)
+    }
+
+    fn current_zstd_dictionary(&self) -> Option<Arc<ZstdDictionary>> {
+        self.zstd
+            .as_ref()
+            .and_then(|zstd| zstd.dictionary.lock().clone())
+    }
+
+    fn add_decoder_dictionary(&self, dictionary: &ZstdDictionary) {
+        self.decoder_dictionaries
+            .lock()
+            .entry(dictionary.id)
+            .or_insert_with(|| Arc::new(DecoderDictionary::copy(&dictionary.raw)));
+    }
+
+    /// Writes the dictionary to the inner store once, so data compressed
+    /// with it can still be read after it was replaced or on other instances.
+    async fn persist_zstd_dictionary(&self, dictionary: &ZstdDictionary) -> Result<(), Error> {
+        dictionary
+            .persisted
+            .get_or_try_init(|| async {
+                self.inner_store
+                    .update_oneshot(zstd_dictionary_key(dictionary.id), dictionary.raw.clone())
+                    .await
+                    .err_tip(|| {
+                        format!(
+                            "Failed to store zstd dictionary {} in compression store",
+                            dictionary.id
+                        )
+                    })
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn zstd_decoder_dictionary(
+        &self,
+        id: u32,
+    ) -> Result<Arc<DecoderDictionary<'static>>, Error> {
+        if let Some(dictionary) = self.decoder_dictionaries.lock().get(&id) {
+            return Ok(dictionary.clone());
+        }
+        let raw = self
+            .inner_store
+            .get_part_unchunked(zstd_dictionary_key(id), 0, None)
+            .await
+            .err_tip(|| format!("Failed to load zstd dictionary {id} in compression store"))?;
+        let dictionary = Arc::new(DecoderDictionary::copy(&raw));
+        self.decoder_dictionaries
+            .lock()
+            .insert(id, dictionary.clone());
+        Ok(dictionary)
+    }
+
+    /// Trains a dictionary on the sampled objects and uses it for new uploads.
+    async fn train_zstd_dictionary(&self) -> Result<(), Error> {
+        let Some(zstd) = &self.zstd else {
+            return Ok(());
+        };
+        let Some(training) = &zstd.training else {
+            return Ok(());
+        };
+        let samples = training.samples.lock().clone();
+        if samples.len() < training.config.min_samples as usize {
+            return Ok(());
+        }
+        let sample_count = samples.len();
+        let dictionary_size = training.config.dictionary_size as usize;
+        let raw = spawn_blocking!("compression_store_train_dictionary", move || {
+            zstd::dict::from_samples(&samples, dictionary_size)
+        })
+        .await
+        .map_err(|e| make_err!(Code::Internal, "Dictionary training task failed: {e:?}"))?
+        .map_err(|e| make_err!(Code::Internal, "Failed to train zstd dictionary: {e}"))?;
+
+        let dictionary = Arc::new(ZstdDictionary::new(raw.into(), zstd.level)?);
+        self.persist_zstd_dictionary(&dictionary).await?;
+        self.add_decoder_dictionary(&dictionary);
+        info!(
+            id = dictionary.id,
+            sample_count,
+            size = dictionary.raw.len(),
+            "Trained new zstd dictionary in compression store"
+        );
+        *zstd.dictionary.lock() = Some(dictionary);
+        Ok(())
+    }
+
+    /// Returns true if data starting with `sample` is not worth compressing,
+    /// because it is in an already compressed format or `sample` shrinks by
+    /// less than `min_savings_percent` when compressed.
+    fn is_incompressible(&self, config: &SkipCompressionConfig, sample: &[u8]) -> bool {
+        if sample.is_empty() {
+            return false;
+        }
+        if COMPRESSED_FORMAT_MAGICS
+            .iter()
+            .any(|magic| sample.starts_with(magic))
+        {
+            return true;
+        }
+        let compressed_size = match &self.zstd {
+            Some(zstd) => zstd::bulk::compress(sample, zstd.level).map(|data| data.len()),
+            None => Ok(lz4_flex::block::compress(sample).len()),
+        };
+        let Ok(compressed_size) = compressed_size else {
+            // Let the upload itself report the compression error.
+            return false;
+        };
+        (compressed_size as u64) * 100
+            > (sample.len() as u64) * u64::from(100 - config.min_savings_percent)
+    }
+
+    fn zstd_decompress(
+        chunk: &[u8],
+        capacity: usize,
+        dictionary: Option<&DecoderDictionary<'static>>,
+    ) -> Result<Bytes, Error> {
+        let decompressed = match dictionary {
+            Some(dictionary) => Decompressor::with_prepared_dictionary(dictionary)
+                .and_then(|mut decompressor| decompressor.decompress(chunk, capacity)),
+            None => zstd::bulk::decompress(chunk, capacity),
+        }
+        .map_err(|e| make_err!(Code::Internal, "Decompression error {:?}", e))?;
+        Ok(Bytes::from(decompressed))
     }
 }
 
@@ -287,6 +653,12 @@ impl StoreDriver for CompressionStore {
     ) -> Result<(), Error> {
         let mut output_state = UploadState::new(&self, upload_size)?;
 
+        let zstd_dictionary = self.current_zstd_dictionary();
+        if let Some(dictionary) = &zstd_dictionary {
+            self.persist_zstd_dictionary(dictionary).await?;
+        }
+        let training = self.zstd.as_ref().and_then(|zstd| zstd.training.as_ref());
+
         let (mut tx, rx) = make_buf_channel_pair();
 
         let inner_store = self.inner_store.clone();
@@ -311,6 +683,39 @@ impl StoreDriver for CompressionStore {
         );
 
         let write_fut = async move {
+            // Data read to decide whether the upload is compressed. It is
+            // consumed before the rest of the upload.
+            let mut skip_sample = match &self.skip_incompressible {
+                Some(config) => reader
+                    .consume(Some(config.sample_size as usize))
+                    .await
+                    .err_tip(|| "Failed to read sample in update in compression store")?,
+                None => Bytes::new(),
+            };
+            let skip_compression = self
+                .skip_incompressible
+                .as_ref()
+                .is_some_and(|config| self.is_incompressible(config, &skip_sample));
+            let training = if skip_compression {
+                self.uncompressed_uploads.fetch_add(1, Ordering::Relaxed);
+                output_state.header.version = UNCOMPRESSED_STREAM_FORMAT_VERSION;
+                output_state.footer.version = UNCOMPRESSED_STREAM_FORMAT_VERSION;
+                // Incompressible data makes for bad dictionaries.
+                None
+            } else {
+                training
+            };
+
+            let mut zstd_compressor = match (&self.zstd, &zstd_dictionary) {
+                (Some(_), Some(dictionary)) => {
+                    Some(Compressor::with_prepared_dictionary(&dictionary.encoder))
+                }
+                (Some(zstd), None) => Some(Compressor::new(zstd.level)),
+                (None, _) => None,
+            }
+            .transpose()
+            .map_err(|e| make_err!(Code::Internal, "Failed to create zstd compressor {:?}", e))?;
+            let mut sample = None;
             {
                 // Write Header.
                 let serialized_header = encode_to_vec(output_state.header, self.bincode_config)
@@ -325,10 +730,24 @@ impl StoreDriver for CompressionStore {
             let mut received_amt = 0;
             let mut index_count: u32 = 0;
             for index in &mut output_state.footer.indexes {
-                let chunk = reader
-                    .consume(Some(self.config.block_size as usize))
-                    .await
-                    .err_tip(|| "Failed to read take in update in compression store")?;
+                let block_size = self.block_size as usize;
+                let chunk = if skip_sample.is_empty() {
+                    reader
+                        .consume(Some(block_size))
+                        .await
+                        .err_tip(|| "Failed to read take in update in compression store")?
+                } else {
+                    let head = skip_sample.split_to(cmp::min(block_size, skip_sample.len()));
+                    if head.len() == block_size {
+                        head
+                    } else {
+                        let tail = reader
+                            .consume(Some(block_size - head.len()))
+                            .await
+                            .err_tip(|| "Failed to read take in update in compression store")?;
+                        [head, tail].concat().into()
+                    }
+                };
                 if chunk.is_empty() {
                     break; // EOF.
                 }
@@ -339,29 +758,57 @@ impl StoreDriver for CompressionStore {
                     received_amt > output_state.input_max_size,
                     "Got more data than stated in compression store upload request"
                 );
+                // Only objects fitting in a single block are sampled.
+                sample = training
+                    .filter(|training| {
+                        index_count == 0 && chunk.len() <= training.config.max_sample_size as usize
+                    })
+                    .map(|_| chunk.clone());
+
+                let (compressed_data_buf, compressed_data_sz) = if skip_compression {
+                    let mut data_buf = BytesMut::with_capacity(1 + 4 + chunk.len());
+                    data_buf.put_u8(CHUNK_FRAME_TYPE);
+                    data_buf.put_u32_le(chunk.len() as u32);
+                    data_buf.extend_from_slice(&chunk);
+                    (data_buf, chunk.len())
+                } else if let Some(compressor) = &mut zstd_compressor {
+                    let compressed_data = compressor
+                        .compress(&chunk)
+                        .map_err(|e| make_err!(Code::Internal, "Compression error {:?}", e))?;
+                    let mut compressed_data_buf =
+                        BytesMut::with_capacity(1 + 4 + compressed_data.len());
+                    compressed_data_buf.put_u8(CHUNK_FRAME_TYPE);
+                    compressed_data_buf.put_u32_le(compressed_data.len() as u32);
+                    compressed_data_buf.extend_from_slice(&compressed_data);
+                    (compressed_data_buf, compressed_data.len())
+                } else {
+                    let max_output_size = get_maximum_output_size(self.block_size as usize);
+                    let mut compressed_data_buf = BytesMut::with_capacity(max_output_size);
+                    compressed_data_buf.put_u8(CHUNK_FRAME_TYPE);
+                    compressed_data_buf.put_u32_le(0); // Filled later.
 
-                let max_output_size = get_maximum_output_size(self.config.block_size as usize);
-                let mut compressed_data_buf = BytesMut::with_capacity(max_output_size);
-                compressed_data_buf.put_u8(CHUNK_FRAME_TYPE);
-                compressed_data_buf.put_u32_le(0); // Filled later.
-
-                // For efficiency reasons we do some raw slice manipulation so we can write directly
-                // into our buffer instead of having to do another allocation.
-                let raw_compressed_data = unsafe {
-                    core::slice::from_raw_parts_mut(
-                        compressed_data_buf.chunk_mut().as_mut_ptr(),
-                        max_output_size,
-                    )
-                };
+                    // For efficiency reasons we do some raw slice manipulation so we can write directly
+                    // into our buffer instead of having to do another allocation.
+                    let raw_compressed_data = unsafe {
+                        core::slice::from_raw_parts_mut(
+                            compressed_data_buf.chunk_mut().as_mut_ptr(),
+                            max_output_size,
+                        )
+                    };
 
-                let compressed_data_sz = compress_into(&chunk, raw_compressed_data)
-                    .map_err(|e| make_err!(Code::Internal, "Compression error {:?}", e))?;
-                unsafe {
-                    compressed_data_buf.advance_mut(compressed_data_sz);
-                }
+                    let compressed_data_sz = compress_into(&chunk, raw_compressed_data)
+                        .map_err(|e| make_err!(Code::Internal, "Compression error {:?}", e))?;
+                    unsafe {
+                        compressed_data_buf.advance_mut(compressed_data_sz);
+                    }
 
-                // Now fill the size in our slice.
-                LittleEndian::write_u32(&mut compressed_data_buf[1..5], compressed_data_sz as u32);
+                    // Now fill the size in our slice.
+                    LittleEndian::write_u32(
+                        &mut compressed_data_buf[1..5],
+                        compressed_data_sz as u32,
+                    );
+                    (compressed_data_buf, compressed_data_sz)
+                };
 
                 // Now send our chunk.
                 tx.send(compressed_data_buf.freeze())
@@ -404,6 +851,9 @@ impl StoreDriver for CompressionStore {
                 tx.send_eof()
                     .err_tip(|| "Failed writing EOF in compression store update")?;
             }
+            if let (Some(training), Some(sample)) = (training, sample) {
+                training.add_sample(&sample);
+            }
 
             Result::<(), Error>::Ok(())
         };
@@ -471,17 +921,22 @@ impl StoreDriver for CompressionStore {
             };
 
             error_if!(
-                header.version != CURRENT_STREAM_FORMAT_VERSION,
-                "Expected header version to match in get compression, got {}, want {}",
+                header.version != CURRENT_STREAM_FORMAT_VERSION
+                    && header.version != ZSTD_STREAM_FORMAT_VERSION
+                    && header.version != UNCOMPRESSED_STREAM_FORMAT_VERSION,
+                "Expected header version to match in get compression, got {}, want {}, {} or {}",
                 header.version,
-                CURRENT_STREAM_FORMAT_VERSION
+                CURRENT_STREAM_FORMAT_VERSION,
+                ZSTD_STREAM_FORMAT_VERSION,
+                UNCOMPRESSED_STREAM_FORMAT_VERSION
             );
             error_if!(
-                header.config.block_size > self.config.max_decode_block_size,
+                header.config.block_size > self.max_decode_block_size,
                 "Block size is too large in compression, got {} > {}",
                 header.config.block_size,
-                self.config.max_decode_block_size
+                self.max_decode_block_size
             );
+            let mut zstd_dictionary: Option<(u32, Arc<DecoderDictionary<'static>>)> = None;
 
             let mut chunk = rx
                 .consume(Some(1 + 4))
@@ -517,22 +972,56 @@ impl StoreDriver for CompressionStore {
                     ));
                 }
                 {
-                    let max_output_size =
-                        get_maximum_output_size(header.config.block_size as usize);
-                    let mut uncompressed_data = BytesMut::with_capacity(max_output_size);
-
-                    // For efficiency reasons we do some raw slice manipulation so we can write directly
-                    // into our buffer instead of having to do another allocation.
-                    let raw_decompressed_data = unsafe {
-                        core::slice::from_raw_parts_mut(
-                            uncompressed_data.chunk_mut().as_mut_ptr(),
-                            max_output_size,
-                        )
+                    let uncompressed_data = if header.version == UNCOMPRESSED_STREAM_FORMAT_VERSION
+                    {
+                        error_if!(
+                            chunk.len() > header.config.block_size as usize,
+                            "Uncompressed block is larger than the block size in compression store, {} > {}",
+                            chunk.len(),
+                            header.config.block_size
+                        );
+                        chunk
+                    } else if header.version == ZSTD_STREAM_FORMAT_VERSION {
+                        let dictionary = match zstd_safe::get_dict_id_from_frame(&chunk) {
+                            Some(id) => {
+                                let id = id.get();
+                                if zstd_dictionary.as_ref().is_none_or(|(cur, _)| *cur != id) {
+                                    zstd_dictionary =
+                                        Some((id, self.zstd_decoder_dictionary(id).await?));
+                                }
+                                zstd_dictionary
+                                    .as_ref()
+                                    .map(|(_, dictionary)| &**dictionary)
+                            }
+                            None => None,
+                        };
+                        Self::zstd_decompress(
+                            &chunk,
+                            header.config.block_size as usize,
+                            dictionary,
+                        )?
+                    } else {
+                        let max_output_size =
+                            get_maximum_output_size(header.config.block_size as usize);
+                        let mut uncompressed_data = BytesMut::with_capacity(max_output_size);
+
+                        // For efficiency reasons we do some raw slice manipulation so we can write directly
+                        // into our buffer instead of having to do another allocation.
+                        let raw_decompressed_data = unsafe {
+                            core::slice::from_raw_parts_mut(
+                                uncompressed_data.chunk_mut().as_mut_ptr(),
+                                max_output_size,
+                            )
+                        };
+
+                        let uncompressed_chunk_sz = decompress_into(&chunk, raw_decompressed_data)
+                            .map_err(|e| {
+                                make_err!(Code::Internal, "Decompression error {:?}", e)
+                            })?;
+                        unsafe { uncompressed_data.advance_mut(uncompressed_chunk_sz) };
+                        uncompressed_data.freeze()
                     };
-
-                    let uncompressed_chunk_sz = decompress_into(&chunk, raw_decompressed_data)
-                        .map_err(|e| make_err!(Code::Internal, "Decompression error {:?}", e))?;
-                    unsafe { uncompressed_data.advance_mut(uncompressed_chunk_sz) };
+                    let uncompressed_chunk_sz = uncompressed_data.len();
                     let new_uncompressed_data_sz =
                         uncompressed_data_sz + uncompressed_chunk_sz as u64;
                     if new_uncompressed_data_sz >= offset && remaining_bytes_to_send > 0 {
@@ -544,7 +1033,7 @@ impl StoreDriver for CompressionStore {
                         if end_pos != start_pos {
                             // Make sure we don't send an EOF by accident.
                             writer
-                                .send(uncompressed_data.freeze().slice(start_pos..end_pos))
+                                .send(uncompressed_data.slice(start_pos..end_pos))
                                 .await
                                 .err_tip(|| "Failed sending chunk in compression store")?;
                         }
@@ -14,25 +14,31 @@
 
 use core::cmp;
 use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use async_trait::async_trait;
 use bincode::serde::{decode_from_slice, encode_to_vec};
 use futures::stream::{self, FuturesOrdered, StreamExt, TryStreamExt};
 use nativelink_config::stores::DedupSpec;
-use nativelink_error::{Code, Error, ResultExt, make_err};
+use nativelink_error::{Code, Error, ResultExt, error_if, make_err};
 use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
 use nativelink_util::common::DigestInfo;
-use nativelink_util::fastcdc::FastCDC;
+use nativelink_util::fastcdc::{DEFAULT_NORMALIZATION_LEVEL, FastCDC, MAX_NORMALIZATION_LEVEL};
 use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
 use nativelink_util::store_trait::{
     RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo,
 };
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
 use tokio_util::codec::FramedRead;
 use tokio_util::io::StreamReader;
-use tracing::warn;
+use tracing::{info, warn};
 
 use crate::cas_utils::is_zero_digest;
 
@@ -42,12 +48,70 @@ const DEFAULT_MIN_SIZE: u64 = 64 * 1024;
 const DEFAULT_NORM_SIZE: u64 = 256 * 1024;
 const DEFAULT_MAX_SIZE: u64 = 512 * 1024;
 const DEFAULT_MAX_CONCURRENT_FETCH_PER_GET: usize = 10;
+const DEFAULT_GC_INTERVAL_S: u64 = 24 * 60 * 60;
+
+// Number of orphaned chunks deleted at once while uploads are paused.
+const GC_SWEEP_BATCH_SIZE: usize = 256;
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone)]
 pub struct DedupIndex {
     pub entries: Vec<DigestInfo>,
 }
 
+/// Outcome of a garbage collection pass of the dedup store.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GarbageCollectionReport {
+    /// Number of indexes read from the index store.
+    pub indexes: u64,
+    /// Number of chunks in the content store when the pass started.
+    pub chunks: u64,
+    /// Number of chunks no index referred to.
+    pub orphaned_chunks: u64,
+    /// Total size of the orphaned chunks.
+    pub orphaned_bytes: u64,
+    /// Number of orphaned chunks deleted. Always zero for a dry run.
+    pub removed_chunks: u64,
+}
+
+/// Chunks uploads reference, tracked so a garbage collection pass never
+/// deletes chunks of an index that is not written yet.
+#[derive(Debug, Default)]
+struct ChunkReferences {
+    next_upload_id: u64,
+    /// Chunks of uploads whose index is not written yet, by upload.
+    in_flight: HashMap<u64, Vec<DigestInfo>>,
+    /// Set while a garbage collection pass runs. Holds the chunks of the
+    /// indexes the pass read and of the uploads that finished meanwhile.
+    marks: Option<HashSet<DigestInfo>>,
+}
+
+/// Moves the chunks of an upload from `in_flight` to `marks` once the
+/// upload finished or failed.
+struct UploadReferences<'a> {
+    chunk_references: &'a Mutex<ChunkReferences>,
+    upload_id: u64,
+}
+
+impl Drop for UploadReferences<'_> {
+    fn drop(&mut self) {
+        let mut chunk_references = self.chunk_references.lock();
+        let chunks = chunk_references.in_flight.remove(&self.upload_id);
+        if let (Some(marks), Some(chunks)) = (&mut chunk_references.marks, chunks) {
+            marks.extend(chunks);
+        }
+    }
+}
+
+/// Stops tracking marks once a garbage collection pass finished or was
+/// cancelled.
+struct MarksGuard<'a>(&'a Mutex<ChunkReferences>);
+
+impl Drop for MarksGuard<'_> {
+    fn drop(&mut self) {
+        self.0.lock().marks = None;
+    }
+}
+
 type LegacyBincodeConfig = bincode::config::Configuration<
     bincode::config::LittleEndian,
     bincode::config::Fixint,
@@ -64,6 +128,15 @@ pub struct DedupStore {
     #[metric(help = "Maximum number of concurrent fetches per get")]
     max_concurrent_fetch_per_get: usize,
     bincode_config: LegacyBincodeConfig,
+    chunk_references: Mutex<ChunkReferences>,
+    /// Held for reading while an upload references a chunk and for writing
+    /// while garbage collection deletes chunks, so a chunk can't be deleted
+    /// between an upload finding it in the content store and recording it.
+    gc_sweep_lock: RwLock<()>,
+    /// Only one garbage collection pass runs at a time.
+    gc_pass_lock: tokio::sync::Mutex<()>,
+    #[metric(help = "Number of orphaned chunks deleted by garbage collection")]
+    gc_removed_chunks: AtomicU64,
 }
 
 impl core::fmt::Debug for DedupStore {
@@ -101,23 +174,182 @@ impl DedupStore {
         } else {
             u64::from(spec.max_size)
         };
+        let normalization_level = spec
+            .normalization_level
+            .unwrap_or(DEFAULT_NORMALIZATION_LEVEL);
+        error_if!(
+            min_size >= normal_size || normal_size >= max_size,
+            "Expected min_size < normal_size < max_size in dedup store, got {min_size}, {normal_size}, {max_size}"
+        );
+        error_if!(
+            normalization_level > MAX_NORMALIZATION_LEVEL,
+            "Expected normalization_level <= {MAX_NORMALIZATION_LEVEL} in dedup store, got {normalization_level}"
+        );
         let max_concurrent_fetch_per_get = if spec.max_concurrent_fetch_per_get == 0 {
             DEFAULT_MAX_CONCURRENT_FETCH_PER_GET
         } else {
             spec.max_concurrent_fetch_per_get as usize
         };
-        Ok(Arc::new(Self {
+        let store = Arc::new(Self {
             index_store,
             content_store,
-            fast_cdc_decoder: FastCDC::new(
+            fast_cdc_decoder: FastCDC::with_normalization(
                 usize::try_from(min_size).err_tip(|| "Could not convert min_size to usize")?,
                 usize::try_from(normal_size)
                     .err_tip(|| "Could not convert normal_size to usize")?,
                 usize::try_from(max_size).err_tip(|| "Could not convert max_size to usize")?,
+                normalization_level,
             ),
             max_concurrent_fetch_per_get,
             bincode_config: bincode::config::legacy(),
-        }))
+            chunk_references: Mutex::new(ChunkReferences::default()),
+            gc_sweep_lock: RwLock::new(()),
+            gc_pass_lock: tokio::sync::Mutex::new(()),
+            gc_removed_chunks: AtomicU64::new(0),
+        });
+
+        if let Some(gc_spec) = spec.garbage_collection {
+            let interval = Duration::from_secs(if gc_spec.interval_s == 0 {
+                DEFAULT_GC_INTERVAL_S
+            } else {
+                gc_spec.interval_s
+            });
+            let weak_store = Arc::downgrade(&store);
+            background_spawn!("dedup_store_garbage_collection", async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    let Some(store) = weak_store.upgrade() else {
+                        return;
+                    };
+                    if let Err(err) = store.collect_garbage(gc_spec.dry_run).await {
+                        warn!(?err, "Failed to collect garbage in dedup store");
+                    }
+                }
+            });
+        }
+        Ok(store)
+    }
+
+    /// Deletes the chunks in the content store no index in the index store
+    /// refers to. With `dry_run` the orphaned chunks are only counted.
+    pub async fn collect_garbage(&self, dry_run: bool) -> Result<GarbageCollectionReport, Error> {
+        let _pass = self.gc_pass_lock.try_lock().map_err(|_| {
+            make_err!(
+                Code::FailedPrecondition,
+                "Garbage collection is already running in dedup store"
+            )
+        })?;
+        self.chunk_references.lock().marks = Some(HashSet::new());
+        let _marks_guard = MarksGuard(&self.chunk_references);
+
+        // Indexes written after this listing belong to uploads which
+        // finished during the pass, so their chunks are in the marks.
+        let mut index_keys = HashSet::new();
+        self.index_store
+            .list(.., |key| {
+                index_keys.insert(key.borrow().into_owned());
+                true
+            })
+            .await
+            .err_tip(|| "Failed to list index store in dedup store garbage collection")?;
+        let mut chunks = Vec::new();
+        self.content_store
+            .list(.., |key| {
+                let StoreKey::Digest(digest) = key else {
+                    return true;
+                };
+                // The index store may share the content store, don't treat
+                // indexes as chunks.
+                if !index_keys.contains(&StoreKey::Digest(*digest)) {
+                    chunks.push(*digest);
+                }
+                true
+            })
+            .await
+            .err_tip(|| "Failed to list content store in dedup store garbage collection")?;
+
+        let mut report = GarbageCollectionReport {
+            indexes: index_keys.len() as u64,
+            chunks: chunks.len() as u64,
+            ..Default::default()
+        };
+        stream::iter(index_keys)
+            .map(|key| async move {
+                let data = match self
+                    .index_store
+                    .get_part_unchunked(key.borrow(), 0, None)
+                    .await
+                {
+                    Ok(data) => data,
+                    // Evicted since it was listed, so its chunks are orphaned.
+                    Err(err) if err.code == Code::NotFound => return Ok(Vec::new()),
+                    Err(err) => {
+                        return Err(err.append(
+                            "Failed to read index store in dedup store garbage collection",
+                        ));
+                    }
+                };
+                match decode_from_slice::<DedupIndex, _>(&data, self.bincode_config) {
+                    Ok((dedup_index, _)) => Ok(dedup_index.entries),
+                    Err(err) => {
+                        warn!(?key, ?err, "Failed to deserialize index in dedup store",);
+                        Ok(Vec::new())
+                    }
+                }
+            })
+            .buffer_unordered(self.max_concurrent_fetch_per_get)
+            .try_for_each(|entries| {
+                if let Some(marks) = &mut self.chunk_references.lock().marks {
+                    marks.extend(entries);
+                }
+                futures::future::ready(Ok(()))
+            })
+            .await?;
+
+        for batch in chunks.chunks(GC_SWEEP_BATCH_SIZE) {
+            let _sweep = self.gc_sweep_lock.write().await;
+            let orphans: Vec<DigestInfo> = {
+                let chunk_references = self.chunk_references.lock();
+                let in_flight: HashSet<&DigestInfo> =
+                    chunk_references.in_flight.values().flatten().collect();
+                let marks = chunk_references.marks.as_ref();
+                batch
+                    .iter()
+                    .filter(|chunk| {
+                        !in_flight.contains(chunk) && marks.is_none_or(|m| !m.contains(*chunk))
+                    })
+                    .copied()
+                    .collect()
+            };
+            report.orphaned_chunks += orphans.len() as u64;
+            report.orphaned_bytes += orphans.iter().map(DigestInfo::size_bytes).sum::<u64>();
+            if dry_run {
+                continue;
+            }
+            let removed = stream::iter(orphans)
+                .map(|chunk| self.content_store.remove(chunk))
+                .buffer_unordered(self.max_concurrent_fetch_per_get)
+                .try_fold(0, |removed, existed| {
+                    futures::future::ready(Ok(removed + u64::from(existed)))
+                })
+                .await
+                .err_tip(|| "Failed to remove chunk in dedup store garbage collection")?;
+            report.removed_chunks += removed;
+            self.gc_removed_chunks.fetch_add(removed, Ordering::Relaxed);
+        }
+        info!(?report, dry_run, "Dedup store garbage collection finished");
+        Ok(report)
+    }
+
+    fn register_upload(&self) -> UploadReferences<'_> {
+        let mut chunk_references = self.chunk_references.lock();
+        let upload_id = chunk_references.next_upload_id;
+        chunk_references.next_upload_id += 1;
+        chunk_references.in_flight.insert(upload_id, Vec::new());
+        UploadReferences {
+            chunk_references: &self.chunk_references,
+            upload_id,
+        }
     }
 
     async fn has(self: Pin<&Self>, key: StoreKey<'_>) -> Result<Option<u64>, Error> {
@@ -202,6 +434,8 @@ impl StoreDriver for DedupStore {
         reader: DropCloserReadHalf,
         _size_info: UploadSizeInfo,
     ) -> Result<(), Error> {
+        let upload_references = self.get_ref().register_upload();
+        let upload_id = upload_references.upload_id;
         let mut bytes_reader = StreamReader::new(reader);
         let frame_reader = FramedRead::new(&mut bytes_reader, self.fast_cdc_decoder.clone());
         let index_entries = frame_reader
@@ -209,6 +443,10 @@ impl StoreDriver for DedupStore {
             .map_ok(|frame| async move {
                 let hash = blake3::hash(&frame[..]).into();
                 let index_entry = DigestInfo::new(hash, frame.len() as u64);
+                let _sweep = self.gc_sweep_lock.read().await;
+                if let Some(chunks) = self.chunk_references.lock().in_flight.get_mut(&upload_id) {
+                    chunks.push(index_entry);
+                }
                 if self
                     .content_store
                     .has(index_entry)
@@ -248,6 +486,7 @@ impl StoreDriver for DedupStore {
             .await
             .err_tip(|| "Failed to insert our index entry to index_store in dedup_store")?;
 
+        drop(upload_references);
         Ok(())
     }
 
@@ -23,6 +23,9 @@ use nativelink_error::Error;
 use nativelink_util::health_utils::HealthRegistryBuilder;
 use nativelink_util::store_trait::{Store, StoreDriver};
 
+use crate::admission_store::AdmissionStore;
+use crate::azure_store::AzureStore;
+use crate::circuit_breaker_store::CircuitBreakerStore;
 use crate::completeness_checking_store::CompletenessCheckingStore;
 use crate::compression_store::CompressionStore;
 use crate::dedup_store::DedupStore;
@@ -31,17 +34,26 @@ use crate::fast_slow_store::FastSlowStore;
 use crate::filesystem_store::FilesystemStore;
 use crate::gcs_store::GcsStore;
 use crate::grpc_store::GrpcStore;
+use crate::http_store::HttpStore;
 use crate::memory_store::MemoryStore;
+use crate::migration_store::MigrationStore;
 use crate::mongo_store::ExperimentalMongoStore;
+use crate::negative_cache_store::NegativeCacheStore;
 use crate::noop_store::NoopStore;
 use crate::ontap_s3_existence_cache_store::OntapS3ExistenceCache;
 use crate::ontap_s3_store::OntapS3Store;
+use crate::packing_store::PackingStore;
+use crate::quota_store::QuotaStore;
 use crate::redis_store::RedisStore;
 use crate::ref_store::RefStore;
+use crate::replication_store::ReplicationStore;
+use crate::rocksdb_store::RocksdbStore;
 use crate::s3_store::S3Store;
 use crate::shard_store::ShardStore;
 use crate::size_partitioning_store::SizePartitioningStore;
+use crate::sqlite_store::SqliteStore;
 use crate::store_manager::StoreManager;
+use crate::tiered_store::TieredStore;
 use crate::verify_store::VerifyStore;
 
 type FutureMaybeStore<'a> = Box<dyn Future<Output = Result<Store, Error>> + Send + 'a>;
@@ -64,6 +76,9 @@ pub fn store_factory<'a>(
                 ExperimentalCloudObjectSpec::Gcs(gcs_config) => {
                     GcsStore::new(gcs_config, SystemTime::now).await?
                 }
+                ExperimentalCloudObjectSpec::Azure(azure_config) => {
+                    AzureStore::new(azure_config, SystemTime::now)?
+                }
             },
             StoreSpec::RedisStore(spec) => RedisStore::new(spec.clone())?,
             StoreSpec::Verify(spec) => VerifyStore::new(
@@ -79,14 +94,43 @@ pub fn store_factory<'a>(
                 store_factory(&spec.index_store, store_manager, None).await?,
                 store_factory(&spec.content_store, store_manager, None).await?,
             )?,
+            StoreSpec::Packing(spec) => PackingStore::new(
+                spec,
+                store_factory(&spec.index_store, store_manager, None).await?,
+                store_factory(&spec.segment_store, store_manager, None).await?,
+            )?,
             StoreSpec::ExistenceCache(spec) => ExistenceCacheStore::new(
                 spec,
                 store_factory(&spec.backend, store_manager, None).await?,
             ),
+            StoreSpec::NegativeCache(spec) => NegativeCacheStore::new(
+                spec,
+                store_factory(&spec.backend, store_manager, None).await?,
+            ),
+            StoreSpec::Quota(spec) => QuotaStore::new(
+                spec,
+                store_factory(&spec.backend, store_manager, None).await?,
+            )?,
+            StoreSpec::Admission(spec) => AdmissionStore::new(
+                spec,
+                store_factory(&spec.backend, store_manager, None).await?,
+            ),
+            StoreSpec::CircuitBreaker(spec) => {
+                let fallback = match &spec.fallback {
+                    Some(fallback) => Some(store_factory(fallback, store_manager, None).await?),
+                    None => None,
+                };
+                CircuitBreakerStore::new(
+                    spec,
+                    store_factory(&spec.backend, store_manager, None).await?,
+                    fallback,
+                )?
+            }
             StoreSpec::OntapS3ExistenceCache(spec) => {
                 OntapS3ExistenceCache::new(spec, SystemTime::now).await?
             }
             StoreSpec::CompletenessChecking(spec) => CompletenessCheckingStore::new(
+                spec,
                 store_factory(&spec.backend, store_manager, None).await?,
                 store_factory(&spec.cas_store, store_manager, None).await?,
             ),
@@ -96,13 +140,26 @@ pub fn store_factory<'a>(
                 store_factory(&spec.slow, store_manager, None).await?,
             ),
             StoreSpec::Filesystem(spec) => <FilesystemStore>::new(spec).await?,
+            StoreSpec::Sqlite(spec) => SqliteStore::new(spec).await?,
+            StoreSpec::Rocksdb(spec) => RocksdbStore::new(spec).await?,
             StoreSpec::RefStore(spec) => RefStore::new(spec, Arc::downgrade(store_manager)),
-            StoreSpec::SizePartitioning(spec) => SizePartitioningStore::new(
-                spec,
-                store_factory(&spec.lower_store, store_manager, None).await?,
-                store_factory(&spec.upper_store, store_manager, None).await?,
-            ),
+            StoreSpec::SizePartitioning(spec) => {
+                let middle_stores = spec
+                    .middle_stores
+                    .iter()
+                    .map(|partition_spec| store_factory(&partition_spec.store, store_manager, None))
+                    .collect::<FuturesOrdered<_>>()
+                    .try_collect::<Vec<_>>()
+                    .await?;
+                SizePartitioningStore::new(
+                    spec,
+                    store_factory(&spec.lower_store, store_manager, None).await?,
+                    middle_stores,
+                    store_factory(&spec.upper_store, store_manager, None).await?,
+                )?
+            }
             StoreSpec::Grpc(spec) => GrpcStore::new(spec).await?,
+            StoreSpec::Http(spec) => HttpStore::new(spec)?,
             StoreSpec::Noop(_) => NoopStore::new(),
             StoreSpec::ExperimentalMongo(spec) => ExperimentalMongoStore::new(spec.clone()).await?,
             StoreSpec::Shard(spec) => {
@@ -115,6 +172,31 @@ pub fn store_factory<'a>(
                     .await?;
                 ShardStore::new(spec, stores)?
             }
+            StoreSpec::Tiered(spec) => {
+                let stores = spec
+                    .tiers
+                    .iter()
+                    .map(|tier_spec| store_factory(&tier_spec.store, store_manager, None))
+                    .collect::<FuturesOrdered<_>>()
+                    .try_collect::<Vec<_>>()
+                    .await?;
+                TieredStore::new(spec, stores)?
+            }
+            StoreSpec::Replication(spec) => {
+                let stores = spec
+                    .replicas
+                    .iter()
+                    .map(|replica_spec| store_factory(replica_spec, store_manager, None))
+                    .collect::<FuturesOrdered<_>>()
+                    .try_collect::<Vec<_>>()
+                    .await?;
+                ReplicationStore::new(spec, stores)?
+            }
+            StoreSpec::Migration(spec) => MigrationStore::new(
+                spec,
+                store_factory(&spec.source, store_manager, None).await?,
+                store_factory(&spec.destination, store_manager, None).await?,
+            ),
         };
 
         if let Some(health_registry_builder) = maybe_health_registry_builder {
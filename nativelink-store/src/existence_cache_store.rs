@@ -13,24 +13,98 @@
 // limitations under the License.
 
 use core::pin::Pin;
+use core::time::Duration;
 use std::borrow::Cow;
+use std::collections::HashSet;
 use std::sync::{Arc, Weak};
-use std::time::SystemTime;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use async_trait::async_trait;
 use nativelink_config::stores::{EvictionPolicy, ExistenceCacheSpec};
 use nativelink_error::{Error, ResultExt, error_if};
 use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
+use nativelink_util::bloom_filter::BloomFilter;
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
-use nativelink_util::common::DigestInfo;
+use nativelink_util::common::{DigestInfo, fs};
 use nativelink_util::evicting_map::{EvictingMap, LenEntry};
 use nativelink_util::health_utils::{HealthStatus, HealthStatusIndicator};
 use nativelink_util::instant_wrapper::InstantWrapper;
+use nativelink_util::metrics_utils::Counter;
 use nativelink_util::store_trait::{
     RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo,
 };
+use nativelink_util::task::JoinHandleDropGuard;
 use parking_lot::Mutex;
-use tracing::{debug, info, trace};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, error, info, trace, warn};
+
+// Default number of seconds between two snapshots of the cache.
+// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_SNAPSHOT_INTERVAL_SECONDS: u64 = 300;
+
+// Default number of false positives per million lookups of the snapshot.
+// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_FALSE_POSITIVES_PER_MILLION: u32 = 1000;
+
+/// Version of the snapshot file layout: the version byte, the creation time
+/// in seconds since the unix epoch and the serialized bloom filter.
+const SNAPSHOT_VERSION: u8 = 1;
+
+fn unix_timestamp_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Bloom filter loaded on startup, consulted for digests missing from the
+/// cache until it expires.
+#[derive(Debug)]
+struct RestoredFilter {
+    filter: BloomFilter,
+    created: u64,
+    // Digests removed from the backend since the filter was loaded. The
+    // filter can't forget them, so they are masked here instead.
+    removed: HashSet<DigestInfo>,
+}
+
+#[derive(Debug)]
+struct Persistence {
+    path: String,
+    false_positive_rate: f64,
+    // Seconds after which a snapshot is too old to be used, zero if never.
+    max_age: u64,
+    restored: Mutex<Option<RestoredFilter>>,
+}
+
+impl Persistence {
+    fn load_snapshot(&self) -> Result<Option<RestoredFilter>, Error> {
+        let bytes = match std::fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).err_tip(|| "Failed to read existence cache snapshot"),
+        };
+        error_if!(
+            bytes.len() < 9 || bytes[0] != SNAPSHOT_VERSION,
+            "Existence cache snapshot {} has an unknown format",
+            self.path
+        );
+        let created = u64::from_le_bytes(bytes[1..9].try_into().unwrap());
+        if self.max_age != 0 && unix_timestamp_now().saturating_sub(created) > self.max_age {
+            info!(
+                path = self.path,
+                "Ignoring expired existence cache snapshot"
+            );
+            return Ok(None);
+        }
+        Ok(Some(RestoredFilter {
+            filter: BloomFilter::from_bytes(&bytes[9..])
+                .err_tip(|| "In existence cache snapshot")?,
+            created,
+            removed: HashSet::new(),
+        }))
+    }
+}
 
 #[derive(Clone, Debug)]
 struct ExistenceItem(u64);
@@ -53,6 +127,11 @@ pub struct ExistenceCacheStore<I: InstantWrapper> {
     inner_store: Store,
     existence_cache: EvictingMap<DigestInfo, DigestInfo, ExistenceItem, I>,
 
+    persistence: Option<Persistence>,
+    #[metric(help = "Number of digests reported as existing by the snapshot loaded on startup")]
+    restored_filter_hits: Counter,
+    _snapshot_task: Option<JoinHandleDropGuard<()>>,
+
     // We need to pause them temporarily when inserting into the inner store
     // as if it immediately expires them, we should only apply the remove callbacks
     // afterwards. If this is None, we're not pausing; if it's Some it's the location to
@@ -71,7 +150,9 @@ impl<I: InstantWrapper> RemoveItemCallback for ExistenceCacheStore<I> {
     async fn callback(&self, store_key: &StoreKey<'_>) {
         debug!(?store_key, "Removing item from cache due to callback");
         let new_key = store_key.borrow();
-        let deleted_key = self.existence_cache.remove(&new_key.into_digest()).await;
+        let digest = new_key.into_digest();
+        self.mark_removed(&digest);
+        let deleted_key = self.existence_cache.remove(&digest).await;
         if !deleted_key {
             info!(?store_key, "Failed to delete key from cache on callback");
         }
@@ -107,10 +188,54 @@ impl<I: InstantWrapper> ExistenceCacheStore<I> {
     ) -> Arc<Self> {
         let empty_policy = EvictionPolicy::default();
         let eviction_policy = spec.eviction_policy.as_ref().unwrap_or(&empty_policy);
-        let existence_cache_store = Arc::new(Self {
-            inner_store,
-            existence_cache: EvictingMap::new(eviction_policy, anchor_time),
-            pause_remove_callbacks: Arc::new(Mutex::new(None)),
+        let persistence = spec.persistence.as_ref().map(|persistence_spec| {
+            let false_positives_per_million = if persistence_spec.false_positives_per_million == 0 {
+                DEFAULT_FALSE_POSITIVES_PER_MILLION
+            } else {
+                persistence_spec.false_positives_per_million
+            };
+            let persistence = Persistence {
+                path: persistence_spec.path.clone(),
+                false_positive_rate: f64::from(false_positives_per_million) / 1_000_000.0,
+                max_age: u64::from(eviction_policy.max_seconds),
+                restored: Mutex::new(None),
+            };
+            match persistence.load_snapshot() {
+                Ok(restored) => *persistence.restored.lock() = restored,
+                Err(err) => warn!(?err, "Failed to load existence cache snapshot"),
+            }
+            persistence
+        });
+        let snapshot_interval = spec.persistence.as_ref().map(|persistence_spec| {
+            Duration::from_secs(if persistence_spec.snapshot_interval_seconds == 0 {
+                DEFAULT_SNAPSHOT_INTERVAL_SECONDS
+            } else {
+                persistence_spec.snapshot_interval_seconds
+            })
+        });
+        let existence_cache_store = Arc::new_cyclic(|weak_self: &Weak<Self>| {
+            let snapshot_task = snapshot_interval.map(|snapshot_interval| {
+                let weak_self = weak_self.clone();
+                background_spawn!("existence_cache_snapshot", async move {
+                    loop {
+                        tokio::time::sleep(snapshot_interval).await;
+                        let Some(store) = weak_self.upgrade() else {
+                            return;
+                        };
+                        if let Err(err) = store.write_snapshot().await {
+                            error!(?err, "Failed to write existence cache snapshot");
+                        }
+                    }
+                })
+            });
+            Self {
+                inner_store,
+                existence_cache: EvictingMap::new(eviction_policy, anchor_time),
+                persistence,
+                restored_filter_hits: Counter::default(),
+                _snapshot_task: snapshot_task,
+                pause_remove_callbacks: Arc::new(Mutex::new(None)),
+            }
         });
         let other_ref = Arc::downgrade(&existence_cache_store);
         existence_cache_store
@@ -131,17 +256,104 @@ impl<I: InstantWrapper> ExistenceCacheStore<I> {
     }
 
     pub async fn remove_from_cache(&self, digest: &DigestInfo) {
+        self.mark_removed(digest);
         self.existence_cache.remove(digest).await;
     }
 
+    /// Writes a bloom filter of the digests in the cache to the configured
+    /// snapshot path right away instead of waiting for the next periodic
+    /// snapshot. Does nothing if persistence is not configured.
+    pub async fn write_snapshot(&self) -> Result<(), Error> {
+        let Some(persistence) = &self.persistence else {
+            return Ok(());
+        };
+        let items = self.existence_cache.items_with_time().await;
+        let mut filter = BloomFilter::new(items.len(), persistence.false_positive_rate);
+        for (digest, _, _) in &items {
+            filter.insert(digest);
+        }
+        let mut snapshot = vec![SNAPSHOT_VERSION];
+        snapshot.extend_from_slice(&unix_timestamp_now().to_le_bytes());
+        snapshot.extend_from_slice(&filter.to_bytes());
+
+        let temp_path = format!("{}.tmp", persistence.path);
+        let mut file = fs::create_file(&temp_path)
+            .await
+            .err_tip(|| "Failed to create existence cache snapshot")?;
+        file.write_all(&snapshot)
+            .await
+            .err_tip(|| "Failed to write existence cache snapshot")?;
+        file.as_ref()
+            .sync_all()
+            .await
+            .err_tip(|| "Failed to sync existence cache snapshot")?;
+        drop(file);
+        fs::rename(&temp_path, &persistence.path)
+            .await
+            .err_tip(|| "Failed to move existence cache snapshot into place")
+    }
+
+    /// Keeps the filter loaded on startup from reporting `digest` as existing.
+    fn mark_removed(&self, digest: &DigestInfo) {
+        if let Some(persistence) = &self.persistence {
+            if let Some(restored) = &mut *persistence.restored.lock() {
+                restored.removed.insert(*digest);
+            }
+        }
+    }
+
+    /// Fills in the `results` missing from the cache which the filter loaded
+    /// on startup reports as existing, and adds them to the cache so they
+    /// are part of the next snapshot.
+    async fn fill_from_restored_filter(&self, keys: &[DigestInfo], results: &mut [Option<u64>]) {
+        let Some(persistence) = &self.persistence else {
+            return;
+        };
+        let hits: Vec<_> = {
+            let mut restored = persistence.restored.lock();
+            let expired = restored.as_ref().is_some_and(|restored| {
+                persistence.max_age != 0
+                    && unix_timestamp_now().saturating_sub(restored.created) > persistence.max_age
+            });
+            if expired {
+                info!("Dropping expired existence cache snapshot");
+                *restored = None;
+            }
+            let Some(restored) = restored.as_ref() else {
+                return;
+            };
+            keys.iter()
+                .zip(results.iter_mut())
+                .filter(|(digest, result)| {
+                    result.is_none()
+                        && restored.filter.contains(digest)
+                        && !restored.removed.contains(*digest)
+                })
+                .map(|(digest, result)| {
+                    *result = Some(digest.size_bytes());
+                    (*digest, ExistenceItem(digest.size_bytes()))
+                })
+                .collect()
+        };
+        if hits.is_empty() {
+            return;
+        }
+        self.restored_filter_hits.add(hits.len() as u64);
+        drop(self.existence_cache.insert_many(hits).await);
+    }
+
     async fn inner_has_with_results(
         self: Pin<&Self>,
         keys: &[DigestInfo],
         results: &mut [Option<u64>],
+        use_restored_filter: bool,
     ) -> Result<(), Error> {
         self.existence_cache
             .sizes_for_keys(keys, results, true /* peek */)
             .await;
+        if use_restored_filter {
+            self.fill_from_restored_filter(keys, results).await;
+        }
 
         let not_cached_keys: Vec<_> = keys
             .iter()
@@ -213,7 +425,7 @@ impl<I: InstantWrapper> StoreDriver for ExistenceCacheStore<I> {
             .iter()
             .map(|key| key.borrow().into_digest())
             .collect();
-        self.inner_has_with_results(&digests, results).await
+        self.inner_has_with_results(&digests, results, true).await
     }
 
     async fn update(
@@ -224,7 +436,8 @@ impl<I: InstantWrapper> StoreDriver for ExistenceCacheStore<I> {
     ) -> Result<(), Error> {
         let digest = key.into_digest();
         let mut exists = [None];
-        self.inner_has_with_results(&[digest], &mut exists)
+        // A false positive of the restored filter must not drop an upload.
+        self.inner_has_with_results(&[digest], &mut exists, false)
             .await
             .err_tip(|| "In ExistenceCacheStore::update")?;
         if exists[0].is_some() {
@@ -26,6 +26,7 @@ use futures::{FutureExt, join};
 use nativelink_config::stores::FastSlowSpec;
 use nativelink_error::{Code, Error, ResultExt, make_err};
 use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
 use nativelink_util::buf_channel::{
     DropCloserReadHalf, DropCloserWriteHalf, make_buf_channel_pair,
 };
@@ -37,6 +38,7 @@ use nativelink_util::store_trait::{
 };
 use parking_lot::Mutex;
 use tokio::sync::OnceCell;
+use tracing::warn;
 
 // TODO(palfrey) This store needs to be evaluated for more efficient memory usage,
 // there are many copies happening internally.
@@ -53,6 +55,8 @@ pub struct FastSlowStore {
     fast_store: Store,
     #[metric(group = "slow_store")]
     slow_store: Store,
+    #[metric(help = "Whether ranged reads populate the fast store in the background")]
+    read_ahead: bool,
     weak_self: Weak<Self>,
     #[metric]
     metrics: FastSlowStoreMetrics,
@@ -113,10 +117,11 @@ impl Drop for LoaderGuard<'_> {
 }
 
 impl FastSlowStore {
-    pub fn new(_spec: &FastSlowSpec, fast_store: Store, slow_store: Store) -> Arc<Self> {
+    pub fn new(spec: &FastSlowSpec, fast_store: Store, slow_store: Store) -> Arc<Self> {
         Arc::new_cyclic(|weak_self| Self {
             fast_store,
             slow_store,
+            read_ahead: spec.read_ahead,
             weak_self: weak_self.clone(),
             metrics: FastSlowStoreMetrics::default(),
             populating_digests: Mutex::new(HashMap::new()),
@@ -271,6 +276,27 @@ impl FastSlowStore {
             .err_tip(|| "Failed to populate()")
     }
 
+    /// Copies the object into the fast store in the background, unless it
+    /// is already being copied.
+    fn populate_fast_store_in_background(&self, key: StoreKey<'_>) {
+        if self
+            .populating_digests
+            .lock()
+            .contains_key(&key.borrow().into_owned())
+        {
+            return;
+        }
+        let Some(store) = self.get_arc() else {
+            return;
+        };
+        let key = key.into_owned();
+        background_spawn!("fast_slow_store_read_ahead", async move {
+            if let Err(err) = store.populate_fast_store(key.borrow()).await {
+                warn!(?err, ?key, "Failed to read ahead into the fast store");
+            }
+        });
+    }
+
     /// Returns the range of bytes that should be sent given a slice bounds
     /// offset so the output range maps the `received_range.start` to 0.
     // TODO(palfrey) This should be put into utils, as this logic is used
@@ -474,6 +500,20 @@ impl StoreDriver for FastSlowStore {
             return Ok(());
         }
 
+        // Don't make ranged reads wait for the whole object, which would
+        // cost a slow store round trip per chunk of a sequential read.
+        if self.read_ahead && (offset != 0 || length.is_some()) {
+            self.populate_fast_store_in_background(key.borrow());
+            self.metrics
+                .slow_store_ranged_read_count
+                .fetch_add(1, Ordering::Acquire);
+            return self
+                .slow_store
+                .get_part(key, writer, offset, length)
+                .await
+                .err_tip(|| "In FastSlowStore::get_part read ahead");
+        }
+
         let loader = self.get_loader(key.borrow());
         let mut writer = Some(writer);
         loader
@@ -533,6 +573,8 @@ struct FastSlowStoreMetrics {
     slow_store_hit_count: AtomicU64,
     #[metric(help = "Downloaded bytes from the slow store")]
     slow_store_downloaded_bytes: AtomicU64,
+    #[metric(help = "Ranged reads served by the slow store while reading ahead")]
+    slow_store_ranged_read_count: AtomicU64,
 }
 
 default_health_status_indicator!(FastSlowStore);
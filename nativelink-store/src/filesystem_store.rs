@@ -12,11 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use core::borrow::Borrow;
 use core::fmt::{Debug, Formatter};
+use core::ops::Bound;
 use core::pin::Pin;
 use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
+use std::path::Path;
 use std::sync::{Arc, Weak};
 use std::time::SystemTime;
 
@@ -34,13 +39,17 @@ use nativelink_util::buf_channel::{
 };
 use nativelink_util::common::{DigestInfo, fs};
 use nativelink_util::evicting_map::{EvictingMap, LenEntry};
+use nativelink_util::fs_uring::{DEFAULT_QUEUE_DEPTH, UringFile, UringFs};
 use nativelink_util::health_utils::{HealthRegistryBuilder, HealthStatus, HealthStatusIndicator};
+use nativelink_util::instant_wrapper::InstantWrapper;
 use nativelink_util::store_trait::{
     RemoveItemCallback, StoreDriver, StoreKey, StoreKeyBorrow, StoreOptimizations, UploadSizeInfo,
 };
-use tokio::io::{AsyncReadExt, AsyncWriteExt, Take};
+use nativelink_util::task::JoinHandleDropGuard;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter, Take};
 use tokio_stream::wrappers::ReadDirStream;
 use tracing::{debug, error, warn};
+use uuid::Uuid;
 
 use crate::callback_utils::RemoveItemCallbackHolder;
 use crate::cas_utils::is_zero_digest;
@@ -49,6 +58,17 @@ use crate::cas_utils::is_zero_digest;
 const DEFAULT_BUFF_SIZE: usize = 32 * 1024;
 // Default block size of all major filesystems is 4KB
 const DEFAULT_BLOCK_SIZE: u64 = 4 * 1024;
+// Default number of seconds between two writes of the index.
+// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_INDEX_SYNC_INTERVAL_SECONDS: u64 = 60;
+// Name of the lock file every instance keeps in its own temp directory when
+// `shared_filesystem` is set.
+const LOCK_FILE_NAME: &str = "lock";
+// How often the lock file of an instance is refreshed.
+const LOCK_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+// Temp directories whose lock file was not refreshed for this long belong to
+// instances that are gone and are deleted on startup.
+const STALE_LOCK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
 
 pub const STR_FOLDER: &str = "s";
 pub const DIGEST_FOLDER: &str = "d";
@@ -71,6 +91,8 @@ pub struct SharedContext {
     temp_path: String,
     #[metric(help = "Path to the configured content path")]
     content_path: String,
+    #[metric(help = "Whether the content path is shared with other instances")]
+    shared_filesystem: bool,
 }
 
 #[derive(Eq, PartialEq, Debug)]
@@ -188,6 +210,9 @@ pub trait FileEntry: LenEntry + Send + Sync + Debug + 'static {
     /// Returns the underlying reference to the size of the data in bytes
     fn data_size_mut(&mut self) -> &mut u64;
 
+    /// Returns the size of the data in bytes.
+    fn data_size(&self) -> u64;
+
     /// Returns the actual size of the underlying file on the disk after accounting for filesystem block size.
     fn size_on_disk(&self) -> u64;
 
@@ -282,6 +307,10 @@ impl FileEntry for FileEntryImpl {
         &mut self.data_size
     }
 
+    fn data_size(&self) -> u64 {
+        self.data_size
+    }
+
     fn size_on_disk(&self) -> u64 {
         self.data_size.div_ceil(self.block_size) * self.block_size
     }
@@ -376,6 +405,15 @@ impl LenEntry for FileEntryImpl {
                 to_full_path_from_key(&encoded_file_path.shared_context.temp_path, &new_key);
 
             if let Err(err) = fs::rename(&from_path, &to_path).await {
+                if err.code == Code::NotFound && encoded_file_path.shared_context.shared_filesystem
+                {
+                    debug!(
+                        key = ?encoded_file_path.key,
+                        ?from_path,
+                        "File was already removed by another instance",
+                    );
+                    return;
+                }
                 warn!(
                     key = ?encoded_file_path.key,
                     ?from_path,
@@ -417,6 +455,56 @@ const SIMULTANEOUS_METADATA_READS: usize = 200;
 
 type FsEvictingMap<'a, Fe> = EvictingMap<StoreKeyBorrow, StoreKey<'a>, Arc<Fe>, SystemTime>;
 
+async fn read_files(
+    folder: Option<&str>,
+    shared_context: &SharedContext,
+) -> Result<Vec<(String, SystemTime, u64, bool)>, Error> {
+    // Note: In Dec 2024 this is for backwards compatibility with the old
+    // way files were stored on disk. Previously all files were in a single
+    // folder regardless of the StoreKey type. This allows old versions of
+    // nativelink file layout to be upgraded at startup time.
+    // This logic can be removed once more time has passed.
+    let read_dir = folder.map_or_else(
+        || format!("{}/", shared_context.content_path),
+        |folder| format!("{}/{folder}/", shared_context.content_path),
+    );
+
+    let (_permit, dir_handle) = fs::read_dir(read_dir)
+        .await
+        .err_tip(|| "Failed opening content directory for iterating in filesystem store")?
+        .into_inner();
+
+    let read_dir_stream = ReadDirStream::new(dir_handle);
+    read_dir_stream
+        .map(|dir_entry| async move {
+            let dir_entry = dir_entry.unwrap();
+            let file_name = dir_entry.file_name().into_string().unwrap();
+            let metadata = dir_entry
+                .metadata()
+                .await
+                .err_tip(|| "Failed to get metadata in filesystem store")?;
+            // We need to filter out folders - we do not want to try to cache the s and d folders.
+            let is_file =
+                metadata.is_file() || !(file_name == STR_FOLDER || file_name == DIGEST_FOLDER);
+            // Using access time is not perfect, but better than random. We do not update the
+            // atime when a file is actually "touched", we rely on whatever the filesystem does
+            // when we read the file (usually update on read).
+            let atime = metadata
+                .accessed()
+                .or_else(|_| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            Result::<(String, SystemTime, u64, bool), Error>::Ok((
+                file_name,
+                atime,
+                metadata.len(),
+                is_file,
+            ))
+        })
+        .buffer_unordered(SIMULTANEOUS_METADATA_READS)
+        .try_collect()
+        .await
+}
+
 async fn add_files_to_cache<Fe: FileEntry>(
     evicting_map: &FsEvictingMap<'_, Fe>,
     anchor_time: &SystemTime,
@@ -459,56 +547,6 @@ async fn add_files_to_cache<Fe: FileEntry>(
         Ok(())
     }
 
-    async fn read_files(
-        folder: Option<&str>,
-        shared_context: &SharedContext,
-    ) -> Result<Vec<(String, SystemTime, u64, bool)>, Error> {
-        // Note: In Dec 2024 this is for backwards compatibility with the old
-        // way files were stored on disk. Previously all files were in a single
-        // folder regardless of the StoreKey type. This allows old versions of
-        // nativelink file layout to be upgraded at startup time.
-        // This logic can be removed once more time has passed.
-        let read_dir = folder.map_or_else(
-            || format!("{}/", shared_context.content_path),
-            |folder| format!("{}/{folder}/", shared_context.content_path),
-        );
-
-        let (_permit, dir_handle) = fs::read_dir(read_dir)
-            .await
-            .err_tip(|| "Failed opening content directory for iterating in filesystem store")?
-            .into_inner();
-
-        let read_dir_stream = ReadDirStream::new(dir_handle);
-        read_dir_stream
-            .map(|dir_entry| async move {
-                let dir_entry = dir_entry.unwrap();
-                let file_name = dir_entry.file_name().into_string().unwrap();
-                let metadata = dir_entry
-                    .metadata()
-                    .await
-                    .err_tip(|| "Failed to get metadata in filesystem store")?;
-                // We need to filter out folders - we do not want to try to cache the s and d folders.
-                let is_file =
-                    metadata.is_file() || !(file_name == STR_FOLDER || file_name == DIGEST_FOLDER);
-                // Using access time is not perfect, but better than random. We do not update the
-                // atime when a file is actually "touched", we rely on whatever the filesystem does
-                // when we read the file (usually update on read).
-                let atime = metadata
-                    .accessed()
-                    .or_else(|_| metadata.modified())
-                    .unwrap_or(SystemTime::UNIX_EPOCH);
-                Result::<(String, SystemTime, u64, bool), Error>::Ok((
-                    file_name,
-                    atime,
-                    metadata.len(),
-                    is_file,
-                ))
-            })
-            .buffer_unordered(SIMULTANEOUS_METADATA_READS)
-            .try_collect()
-            .await
-    }
-
     /// Note: In Dec 2024 this is for backwards compatibility with the old
     /// way files were stored on disk. Previously all files were in a single
     /// folder regardless of the [`StoreKey`] type. This moves files from the old cache
@@ -619,6 +657,283 @@ async fn prune_temp_path(temp_path: &str) -> Result<(), Error> {
     Ok(())
 }
 
+/// Deletes the temp directories of instances sharing `temp_path` whose lock
+/// file was not refreshed for `STALE_LOCK_TIMEOUT`. Directories without a
+/// lock file are judged by their own modification time, so an instance which
+/// is just starting up is left alone.
+async fn prune_stale_instances(temp_path: &str) -> Result<(), Error> {
+    let (_permit, dir_handle) = fs::read_dir(temp_path)
+        .await
+        .err_tip(|| "Failed opening temp directory to prune stale instances in filesystem store")?
+        .into_inner();
+
+    let mut read_dir_stream = ReadDirStream::new(dir_handle);
+    while let Some(dir_entry) = read_dir_stream.next().await {
+        let dir_entry = dir_entry?;
+        let path = dir_entry.path();
+        let is_instance_dir = dir_entry.file_type().await?.is_dir()
+            && path
+                .file_name()
+                .is_some_and(|name| name != STR_FOLDER && name != DIGEST_FOLDER);
+        if !is_instance_dir {
+            continue;
+        }
+        let metadata = match fs::metadata(path.join(LOCK_FILE_NAME)).await {
+            Ok(metadata) => metadata,
+            Err(_) => fs::metadata(&path).await?,
+        };
+        let is_stale = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > STALE_LOCK_TIMEOUT);
+        if !is_stale {
+            continue;
+        }
+        if let Err(err) = fs::remove_dir_all(&path).await {
+            warn!(
+                ?path,
+                ?err,
+                "Failed to delete temp directory of stale instance"
+            );
+        } else {
+            debug!(?path, "Deleted temp directory of stale instance");
+        }
+    }
+    Ok(())
+}
+
+/// Creates the lock file of an instance. It is created exclusively, which
+/// is safe on NFS v3 and later.
+async fn create_lock_file(lock_path: String) -> Result<(), Error> {
+    fs::call_with_permit(move |_permit| {
+        std::fs::File::options()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+            .err_tip(|| format!("Could not create lock file {lock_path}"))?;
+        Ok(())
+    })
+    .await
+}
+
+/// Marks the instance owning `lock_path` as alive.
+async fn refresh_lock_file(lock_path: String) -> Result<(), Error> {
+    fs::call_with_permit(move |_permit| {
+        std::fs::File::options()
+            .write(true)
+            .open(&lock_path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+            .err_tip(|| format!("Could not refresh lock file {lock_path}"))
+    })
+    .await
+}
+
+/// Renames `from` to `to`. NFS clients retry a rename whose reply got lost,
+/// and the retry fails with `NotFound` because the first attempt already
+/// moved the file, so that case is treated as success.
+fn nfs_safe_rename(from: &OsStr, to: &OsStr) -> Result<(), std::io::Error> {
+    match std::fs::rename(from, to) {
+        Err(err)
+            if err.kind() == std::io::ErrorKind::NotFound
+                && !Path::new(from).exists()
+                && Path::new(to).exists() =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Converts `time` into the seconds relative to `anchor_time` used by the
+/// evicting map. Times before the anchor are negative.
+fn seconds_since_anchor(anchor_time: &SystemTime, time: SystemTime) -> i32 {
+    match time.duration_since(*anchor_time) {
+        Ok(after) => i32::try_from(after.as_secs()).unwrap_or(i32::MAX),
+        Err(before) => i32::try_from(before.duration().as_secs()).map_or(i32::MIN, |secs| -secs),
+    }
+}
+
+/// Parses a line of the index written by `write_index()` into the access
+/// time, data size and key of an item.
+fn parse_index_line(line: &str) -> Option<(u64, u64, StoreKey<'static>)> {
+    let mut parts = line.splitn(4, ' ');
+    let access_time = parts.next()?.parse().ok()?;
+    let data_size = parts.next()?.parse().ok()?;
+    let file_type = match parts.next()? {
+        STR_FOLDER => FileType::String,
+        DIGEST_FOLDER => FileType::Digest,
+        _ => return None,
+    };
+    let key = key_from_file(parts.next()?, file_type).ok()?;
+    Some((access_time, data_size, key.into_owned()))
+}
+
+/// Writes the key, data size and last access time of every item in the
+/// `evicting_map` to `index_path`, one item per line. The index is written
+/// to a temporary file first, so a crash never leaves a partial index.
+async fn write_index<Fe: FileEntry>(
+    evicting_map: &FsEvictingMap<'_, Fe>,
+    anchor_time: &SystemTime,
+    index_path: &str,
+) -> Result<(), Error> {
+    let anchor_timestamp = anchor_time.unix_timestamp();
+    let temp_index_path = format!("{index_path}.tmp");
+    let mut writer = BufWriter::new(
+        fs::create_file(&temp_index_path)
+            .await
+            .err_tip(|| "Failed to create index in filesystem store")?,
+    );
+    for (key, entry, seconds_since_anchor) in evicting_map.items_with_time().await {
+        let key: &StoreKey<'static> = key.borrow();
+        let (folder, file_name) = match key {
+            StoreKey::Str(name) => (STR_FOLDER, name.to_string()),
+            StoreKey::Digest(digest) => (DIGEST_FOLDER, digest.to_string()),
+        };
+        if file_name.contains('\n') {
+            // Can't be represented in the index, the file is picked up by
+            // walking the content path on startup instead.
+            continue;
+        }
+        let access_time = anchor_timestamp.saturating_add_signed(i64::from(seconds_since_anchor));
+        writer
+            .write_all(
+                format!("{access_time} {} {folder} {file_name}\n", entry.data_size()).as_bytes(),
+            )
+            .await
+            .err_tip(|| "Failed to write index in filesystem store")?;
+    }
+    writer
+        .flush()
+        .await
+        .err_tip(|| "Failed to flush index in filesystem store")?;
+    writer
+        .into_inner()
+        .as_ref()
+        .sync_all()
+        .await
+        .err_tip(|| "Failed to sync index in filesystem store")?;
+    fs::rename(&temp_index_path, index_path)
+        .await
+        .err_tip(|| "Failed to move index into place in filesystem store")
+}
+
+/// Restores the items recorded in the index at `index_path` into the
+/// `evicting_map`, least recently used first so the access order survives.
+/// Returns the restored items, or `None` if there is no index.
+async fn load_index<Fe: FileEntry>(
+    evicting_map: &FsEvictingMap<'_, Fe>,
+    anchor_time: &SystemTime,
+    shared_context: &Arc<SharedContext>,
+    block_size: u64,
+    index_path: &str,
+) -> Result<Option<Vec<(StoreKey<'static>, Arc<Fe>)>>, Error> {
+    let contents = match fs::read(index_path).await {
+        Ok(contents) => contents,
+        Err(err) if err.code == Code::NotFound => return Ok(None),
+        Err(err) => return Err(err).err_tip(|| "Failed to read index in filesystem store"),
+    };
+    let contents = String::from_utf8(contents)
+        .map_err(|e| make_err!(Code::DataLoss, "Index is not valid utf8: {e:?}"))?;
+
+    let mut items = Vec::new();
+    for line in contents.lines() {
+        match parse_index_line(line) {
+            Some(item) => items.push(item),
+            None => warn!(?line, "Ignoring malformed line in filesystem store index"),
+        }
+    }
+    items.sort_unstable_by_key(|(access_time, _, _)| *access_time);
+
+    let mut restored = Vec::with_capacity(items.len());
+    for (access_time, data_size, key) in items {
+        let file_entry = Arc::new(Fe::create(
+            data_size,
+            block_size,
+            RwLock::new(EncodedFilePath {
+                shared_context: shared_context.clone(),
+                path_type: PathType::Content,
+                key: key.borrow().into_owned(),
+            }),
+        ));
+        let access_time = SystemTime::UNIX_EPOCH + Duration::from_secs(access_time);
+        evicting_map
+            .insert_with_time(
+                key.clone().into(),
+                file_entry.clone(),
+                seconds_since_anchor(anchor_time, access_time),
+            )
+            .await;
+        restored.push((key, file_entry));
+    }
+    Ok(Some(restored))
+}
+
+/// Brings the items restored by `load_index()` in line with the files in the
+/// content path: files written after the index was last synced are added and
+/// restored items whose files are gone are removed. Runs while the store is
+/// serving, so items inserted in the meantime are never replaced.
+async fn reconcile_index<Fe: FileEntry>(
+    evicting_map: &FsEvictingMap<'_, Fe>,
+    anchor_time: &SystemTime,
+    shared_context: &Arc<SharedContext>,
+    block_size: u64,
+    restored: Vec<(StoreKey<'static>, Arc<Fe>)>,
+) -> Result<(), Error> {
+    let mut restored: HashMap<StoreKey<'static>, Arc<Fe>> = restored.into_iter().collect();
+    for (folder, file_type) in [
+        (DIGEST_FOLDER, FileType::Digest),
+        (STR_FOLDER, FileType::String),
+    ] {
+        let file_infos = read_files(Some(folder), shared_context).await?;
+        for (file_name, atime, data_size, _) in file_infos.into_iter().filter(|x| x.3) {
+            let key = match key_from_file(&file_name, file_type) {
+                Ok(key) => key.into_owned(),
+                Err(err) => {
+                    warn!(?file_name, ?err, "Failed to add file to eviction cache",);
+                    // Ignore result.
+                    drop(
+                        fs::remove_file(format!(
+                            "{}/{folder}/{file_name}",
+                            shared_context.content_path
+                        ))
+                        .await,
+                    );
+                    continue;
+                }
+            };
+            if restored.remove(&key).is_some() {
+                continue;
+            }
+            let file_entry = Fe::create(
+                data_size,
+                block_size,
+                RwLock::new(EncodedFilePath {
+                    shared_context: shared_context.clone(),
+                    path_type: PathType::Content,
+                    key: key.clone(),
+                }),
+            );
+            evicting_map
+                .insert_with_time_if_absent(
+                    key.into(),
+                    Arc::new(file_entry),
+                    seconds_since_anchor(anchor_time, atime),
+                )
+                .await;
+        }
+    }
+
+    for (key, entry) in restored {
+        // The item may have been replaced since it was restored, in which
+        // case the new file is fine.
+        evicting_map
+            .remove_if(&key, |map_entry| Arc::<Fe>::ptr_eq(map_entry, &entry))
+            .await;
+    }
+    Ok(())
+}
+
 #[derive(Debug, MetricsComponent)]
 pub struct FilesystemStore<Fe: FileEntry = FileEntryImpl> {
     #[metric]
@@ -629,13 +944,27 @@ pub struct FilesystemStore<Fe: FileEntry = FileEntryImpl> {
     block_size: u64,
     #[metric(help = "Size of the configured read buffer size")]
     read_buffer_size: usize,
+    #[metric(help = "Whether file contents are read and written through io_uring")]
+    io_uring_enabled: bool,
+    uring: Option<UringFs>,
+    #[metric(help = "Path to the configured index file")]
+    index_path: String,
+    anchor_time: SystemTime,
+    _index_sync_task: Option<JoinHandleDropGuard<()>>,
+    _lock_refresh_task: Option<JoinHandleDropGuard<()>>,
     weak_self: Weak<Self>,
     rename_fn: fn(&OsStr, &OsStr) -> Result<(), std::io::Error>,
 }
 
 impl<Fe: FileEntry> FilesystemStore<Fe> {
     pub async fn new(spec: &FilesystemSpec) -> Result<Arc<Self>, Error> {
-        Self::new_with_timeout_and_rename_fn(spec, |from, to| std::fs::rename(from, to)).await
+        let rename_fn: fn(&OsStr, &OsStr) -> Result<(), std::io::Error> = if spec.shared_filesystem
+        {
+            nfs_safe_rename
+        } else {
+            |from, to| std::fs::rename(from, to)
+        };
+        Self::new_with_timeout_and_rename_fn(spec, rename_fn).await
     }
 
     pub async fn new_with_timeout_and_rename_fn(
@@ -654,18 +983,36 @@ impl<Fe: FileEntry> FilesystemStore<Fe> {
         let now = SystemTime::now();
 
         let empty_policy = nativelink_config::stores::EvictionPolicy::default();
-        let eviction_policy = spec.eviction_policy.as_ref().unwrap_or(&empty_policy);
+        let eviction_policy = if spec.external_eviction {
+            &empty_policy
+        } else {
+            spec.eviction_policy.as_ref().unwrap_or(&empty_policy)
+        };
         let evicting_map = Arc::new(EvictingMap::new(eviction_policy, now));
 
         // Create temp and content directories and the s and d subdirectories.
-
-        create_subdirs(&spec.temp_path).await?;
+        // Instances sharing the filesystem each get their own temp directory,
+        // so they never delete each other's partial uploads.
+        let temp_path = if spec.shared_filesystem {
+            fs::create_dir_all(&spec.temp_path)
+                .await
+                .err_tip(|| format!("Failed to create directory {}", spec.temp_path))?;
+            prune_stale_instances(&spec.temp_path).await?;
+            let instance_path = format!("{}/{}", spec.temp_path, Uuid::new_v4());
+            create_subdirs(&instance_path).await?;
+            create_lock_file(format!("{instance_path}/{LOCK_FILE_NAME}")).await?;
+            instance_path
+        } else {
+            create_subdirs(&spec.temp_path).await?;
+            spec.temp_path.clone()
+        };
         create_subdirs(&spec.content_path).await?;
 
         let shared_context = Arc::new(SharedContext {
             active_drop_spawns: AtomicU64::new(0),
-            temp_path: spec.temp_path.clone(),
+            temp_path,
             content_path: spec.content_path.clone(),
+            shared_filesystem: spec.shared_filesystem,
         });
 
         let block_size = if spec.block_size == 0 {
@@ -673,14 +1020,40 @@ impl<Fe: FileEntry> FilesystemStore<Fe> {
         } else {
             spec.block_size
         };
-        add_files_to_cache(
-            evicting_map.as_ref(),
-            &now,
-            &shared_context,
-            block_size,
-            rename_fn,
-        )
-        .await?;
+        let restored = if spec.index_path.is_empty() {
+            None
+        } else {
+            if let Some(index_dir) = Path::new(&spec.index_path).parent() {
+                fs::create_dir_all(index_dir)
+                    .await
+                    .err_tip(|| format!("Failed to create directory {}", index_dir.display()))?;
+            }
+            load_index(
+                evicting_map.as_ref(),
+                &now,
+                &shared_context,
+                block_size,
+                &spec.index_path,
+            )
+            .await
+            .unwrap_or_else(|err| {
+                warn!(
+                    ?err,
+                    "Failed to load filesystem store index, walking content path"
+                );
+                None
+            })
+        };
+        if restored.is_none() {
+            add_files_to_cache(
+                evicting_map.as_ref(),
+                &now,
+                &shared_context,
+                block_size,
+                rename_fn,
+            )
+            .await?;
+        }
         prune_temp_path(&shared_context.temp_path).await?;
 
         let read_buffer_size = if spec.read_buffer_size == 0 {
@@ -688,27 +1061,172 @@ impl<Fe: FileEntry> FilesystemStore<Fe> {
         } else {
             spec.read_buffer_size as usize
         };
-        Ok(Arc::new_cyclic(|weak_self| Self {
-            shared_context,
-            evicting_map,
-            block_size,
-            read_buffer_size,
-            weak_self: weak_self.clone(),
-            rename_fn,
+        let uring = if spec.io_uring {
+            let queue_depth = if spec.io_uring_queue_depth == 0 {
+                DEFAULT_QUEUE_DEPTH
+            } else {
+                spec.io_uring_queue_depth
+            };
+            UringFs::new(queue_depth, read_buffer_size)
+                .inspect_err(|err| {
+                    warn!(
+                        ?err,
+                        "Could not set up io_uring for filesystem store, using regular file operations"
+                    );
+                })
+                .ok()
+        } else {
+            None
+        };
+        let index_sync_interval = Duration::from_secs(if spec.index_sync_interval_seconds == 0 {
+            DEFAULT_INDEX_SYNC_INTERVAL_SECONDS
+        } else {
+            u64::from(spec.index_sync_interval_seconds)
+        });
+        Ok(Arc::new_cyclic(|weak_self: &Weak<Self>| {
+            let index_sync_task = (!spec.index_path.is_empty()).then(|| {
+                let weak_self = weak_self.clone();
+                let evicting_map = evicting_map.clone();
+                let shared_context = shared_context.clone();
+                background_spawn!("filesystem_store_index_sync", async move {
+                    if let Some(restored) = restored {
+                        let result = reconcile_index(
+                            evicting_map.as_ref(),
+                            &now,
+                            &shared_context,
+                            block_size,
+                            restored,
+                        )
+                        .await;
+                        if let Err(err) = result {
+                            error!(?err, "Failed to reconcile filesystem store index");
+                        }
+                    }
+                    drop(evicting_map);
+                    loop {
+                        tokio::time::sleep(index_sync_interval).await;
+                        let Some(store) = weak_self.upgrade() else {
+                            return;
+                        };
+                        if let Err(err) = store.sync_index().await {
+                            error!(?err, "Failed to sync filesystem store index");
+                        }
+                    }
+                })
+            });
+            let lock_refresh_task = spec.shared_filesystem.then(|| {
+                let lock_path = format!("{}/{LOCK_FILE_NAME}", shared_context.temp_path);
+                background_spawn!("filesystem_store_lock_refresh", async move {
+                    loop {
+                        tokio::time::sleep(LOCK_REFRESH_INTERVAL).await;
+                        if let Err(err) = refresh_lock_file(lock_path.clone()).await {
+                            error!(?err, "Failed to refresh filesystem store lock file");
+                        }
+                    }
+                })
+            });
+            Self {
+                shared_context,
+                evicting_map,
+                block_size,
+                read_buffer_size,
+                io_uring_enabled: uring.is_some(),
+                uring,
+                index_path: spec.index_path.clone(),
+                anchor_time: now,
+                _index_sync_task: index_sync_task,
+                _lock_refresh_task: lock_refresh_task,
+                weak_self: weak_self.clone(),
+                rename_fn,
+            }
         }))
     }
 
+    /// Writes the index to the configured `index_path` right away instead of
+    /// waiting for the next periodic sync. Does nothing if no index is
+    /// configured.
+    pub async fn sync_index(&self) -> Result<(), Error> {
+        if self.index_path.is_empty() {
+            return Ok(());
+        }
+        write_index(
+            self.evicting_map.as_ref(),
+            &self.anchor_time,
+            &self.index_path,
+        )
+        .await
+    }
+
     pub fn get_arc(&self) -> Option<Arc<Self>> {
         self.weak_self.upgrade()
     }
 
     pub async fn get_file_entry_for_digest(&self, digest: &DigestInfo) -> Result<Arc<Fe>, Error> {
-        self.evicting_map
-            .get(&digest.into())
+        self.get_entry(&StoreKey::Digest(*digest))
             .await
             .ok_or_else(|| make_err!(Code::NotFound, "{digest} not found in filesystem store. This may indicate the file was evicted due to cache pressure. Consider increasing 'max_bytes' in your filesystem store's eviction_policy configuration."))
     }
 
+    /// Looks up the entry of `key`. With `shared_filesystem` set, entries
+    /// whose file was removed by another instance are dropped and files
+    /// written by other instances are added to the map.
+    async fn get_entry(&self, key: &StoreKey<'static>) -> Option<Arc<Fe>> {
+        if !self.shared_context.shared_filesystem {
+            return self.evicting_map.get(key).await;
+        }
+        if let Some(entry) = self.evicting_map.get(key).await {
+            let exists = entry
+                .get_file_path_locked(|full_path| async move {
+                    Ok(match fs::metadata(&full_path).await {
+                        Ok(_) => true,
+                        Err(err) => err.code != Code::NotFound,
+                    })
+                })
+                .await
+                .unwrap_or(true);
+            if exists {
+                return Some(entry);
+            }
+            debug!(?key, "File was removed by another instance");
+            self.evicting_map
+                .remove_if(key, |map_entry| Arc::<Fe>::ptr_eq(map_entry, &entry))
+                .await;
+        }
+
+        let full_path = to_full_path_from_key(&self.shared_context.content_path, key);
+        let metadata = fs::metadata(&full_path).await.ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        let entry = Arc::new(Fe::create(
+            metadata.len(),
+            self.block_size,
+            RwLock::new(EncodedFilePath {
+                shared_context: self.shared_context.clone(),
+                path_type: PathType::Content,
+                key: key.borrow().into_owned(),
+            }),
+        ));
+        let seconds_since_anchor = self
+            .anchor_time
+            .elapsed()
+            .map_or(0, |elapsed| elapsed.as_secs() as i32);
+        let inserted = self
+            .evicting_map
+            .insert_with_time_if_absent(
+                key.borrow().into_owned().into(),
+                entry.clone(),
+                seconds_since_anchor,
+            )
+            .await;
+        if inserted {
+            debug!(?key, "Added file written by another instance");
+            return Some(entry);
+        }
+        // Someone else inserted the key in the meantime.
+        self.evicting_map.get(key).await
+    }
+
     async fn update_file(
         self: Pin<&Self>,
         mut entry: Fe,
@@ -716,6 +1234,34 @@ impl<Fe: FileEntry> FilesystemStore<Fe> {
         final_key: StoreKey<'static>,
         mut reader: DropCloserReadHalf,
     ) -> Result<(), Error> {
+        if let Some(uring) = &self.uring {
+            let temp_file = UringFile::from_slot(temp_file).await;
+            let mut data_size = 0;
+            loop {
+                let data = reader
+                    .recv()
+                    .await
+                    .err_tip(|| "Failed to receive data in filesystem store")?;
+                let data_len = data.len();
+                if data_len == 0 {
+                    break; // EOF.
+                }
+                uring
+                    .write_all_at(&temp_file, data_size, data)
+                    .await
+                    .err_tip(|| "Failed to write data into filesystem store")?;
+                data_size += data_len as u64;
+            }
+            temp_file
+                .sync_all()
+                .await
+                .err_tip(|| "Failed to sync_data in filesystem store")?;
+            drop(temp_file);
+
+            *entry.data_size_mut() = data_size;
+            return self.emplace_file(final_key, Arc::new(entry)).await;
+        }
+
         let mut data_size = 0;
         loop {
             let mut data = reader
@@ -828,9 +1374,15 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
             .iter()
             .map(|sk| sk.borrow().into_owned())
             .collect::<Vec<_>>();
-        self.evicting_map
-            .sizes_for_keys(own_keys.iter(), results, false /* peek */)
-            .await;
+        if self.shared_context.shared_filesystem {
+            for (key, result) in own_keys.iter().zip(results.iter_mut()) {
+                *result = self.get_entry(key).await.map(|entry| entry.len());
+            }
+        } else {
+            self.evicting_map
+                .sizes_for_keys(own_keys.iter(), results, false /* peek */)
+                .await;
+        }
         // We need to do a special pass to ensure our zero files exist.
         // If our results failed and the result was a zero file, we need to
         // create the file by spec.
@@ -853,6 +1405,27 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
         Ok(())
     }
 
+    async fn list(
+        self: Pin<&Self>,
+        range: (Bound<StoreKey<'_>>, Bound<StoreKey<'_>>),
+        handler: &mut (dyn for<'a> FnMut(&'a StoreKey) -> bool + Send + Sync + '_),
+    ) -> Result<u64, Error> {
+        let range = (
+            range.0.map(StoreKey::into_owned),
+            range.1.map(StoreKey::into_owned),
+        );
+        let iterations = self
+            .evicting_map
+            .range(range, move |key, _value| handler(key.borrow()))
+            .await;
+        Ok(iterations)
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        // The file is deleted once the last reader of it finished.
+        Ok(self.evicting_map.remove(&key.into_owned()).await)
+    }
+
     async fn update(
         self: Pin<&Self>,
         key: StoreKey<'_>,
@@ -935,7 +1508,7 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
             return Ok(());
         }
         let owned_key = key.into_owned();
-        let entry = self.evicting_map.get(&owned_key).await.ok_or_else(|| {
+        let entry = self.get_entry(&owned_key).await.ok_or_else(|| {
             make_err!(
                 Code::NotFound,
                 "{} not found in filesystem store here",
@@ -945,7 +1518,10 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
         let read_limit = length.unwrap_or(u64::MAX);
         let mut temp_file = entry.read_file_part(offset, read_limit).or_else(|err| async move {
             // If the file is not found, we need to remove it from the eviction map.
-            if err.code == Code::NotFound {
+            if err.code == Code::NotFound && self.shared_context.shared_filesystem {
+                debug!(?err, key = ?owned_key, "File was removed by another instance");
+                self.evicting_map.remove(&owned_key).await;
+            } else if err.code == Code::NotFound {
                 error!(
                     ?err,
                     key = ?owned_key,
@@ -956,6 +1532,34 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
             Err(err)
         }).await?;
 
+        if let Some(uring) = &self.uring {
+            let temp_file = UringFile::from_slot(temp_file.into_inner()).await;
+            let mut read_offset = offset;
+            let mut remaining = read_limit;
+            while remaining > 0 {
+                let read_len = usize::try_from(remaining)
+                    .unwrap_or(usize::MAX)
+                    .min(uring.buffer_size());
+                let buf = uring
+                    .read_at(&temp_file, read_offset, read_len)
+                    .await
+                    .err_tip(|| "Failed to read data in filesystem store")?;
+                if buf.is_empty() {
+                    break; // EOF.
+                }
+                read_offset += buf.len() as u64;
+                remaining -= buf.len() as u64;
+                writer
+                    .send(buf)
+                    .await
+                    .err_tip(|| "Failed to send chunk in filesystem store get_part")?;
+            }
+            writer
+                .send_eof()
+                .err_tip(|| "Filed to send EOF in filesystem store get_part")?;
+            return Ok(());
+        }
+
         loop {
             let mut buf = BytesMut::with_capacity(self.read_buffer_size);
             temp_file
@@ -105,6 +105,7 @@ pub struct GcsClient {
     client: Client,
     resumable_chunk_size: usize,
     semaphore: Arc<Semaphore>,
+    kms_key_name: Option<String>,
 }
 
 impl GcsClient {
@@ -146,6 +147,7 @@ impl GcsClient {
             client,
             resumable_chunk_size,
             semaphore: Arc::new(Semaphore::new(max_connections)),
+            kms_key_name: spec.kms_key_name.clone(),
         })
     }
 
@@ -236,6 +238,7 @@ impl GcsClient {
         self.with_connection(|| async {
             let request = UploadObjectRequest {
                 bucket: object_path.bucket.clone(),
+                kms_key_name: self.kms_key_name.clone(),
                 ..Default::default()
             };
 
@@ -323,6 +326,7 @@ impl Debug for GcsClient {
         f.debug_struct("GcsClient")
             .field("resumable_chunk_size", &self.resumable_chunk_size)
             .field("max_connections", &self.semaphore.available_permits())
+            .field("kms_key_name", &self.kms_key_name)
             .finish_non_exhaustive()
     }
 }
@@ -424,6 +428,7 @@ impl GcsOperations for GcsClient {
         self.with_connection(|| async {
             let request = UploadObjectRequest {
                 bucket: object_path.bucket.clone(),
+                kms_key_name: self.kms_key_name.clone(),
                 ..Default::default()
             };
 
@@ -444,6 +449,7 @@ impl GcsOperations for GcsClient {
         self.with_connection(|| async {
             let request = UploadObjectRequest {
                 bucket: object_path.bucket.clone(),
+                kms_key_name: self.kms_key_name.clone(),
                 ..Default::default()
             };
 
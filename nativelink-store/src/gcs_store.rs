@@ -14,6 +14,7 @@
 
 use core::fmt::Debug;
 use core::pin::Pin;
+use core::sync::atomic::AtomicU64;
 use std::borrow::Cow;
 use std::sync::Arc;
 
@@ -38,6 +39,7 @@ use crate::gcs_client::types::{
     CHUNK_SIZE, DEFAULT_CONCURRENT_UPLOADS, DEFAULT_MAX_RETRY_BUFFER_PER_REQUEST,
     MIN_MULTIPART_SIZE, ObjectPath,
 };
+use crate::object_store_utils::{ObjectRequestConfig, hedged};
 
 #[derive(MetricsComponent, Debug)]
 pub struct GcsStore<Client: GcsOperations, NowFn> {
@@ -56,6 +58,9 @@ pub struct GcsStore<Client: GcsOperations, NowFn> {
     max_chunk_size: usize,
     #[metric(help = "The number of concurrent uploads allowed")]
     max_concurrent_uploads: usize,
+    request_config: ObjectRequestConfig,
+    #[metric(help = "Number of reads for which a second request was sent")]
+    hedged_requests: AtomicU64,
 }
 
 impl<I, NowFn> GcsStore<GcsClient, NowFn>
@@ -118,6 +123,7 @@ where
             max_retry_buffer_size
         };
 
+        let request_config = ObjectRequestConfig::new(&spec.common);
         Ok(Arc::new(Self {
             client,
             now_fn,
@@ -128,15 +134,17 @@ where
                 .as_ref()
                 .unwrap_or(&String::new())
                 .clone(),
-            retrier: Retrier::new(
+            retrier: request_config.apply_budget(Retrier::new(
                 Arc::new(|duration| Box::pin(sleep(duration))),
                 jitter_fn,
                 spec.common.retry.clone(),
-            ),
+            )),
             consider_expired_after_s: i64::from(spec.common.consider_expired_after_s),
             max_retry_buffer_size,
             max_chunk_size,
             max_concurrent_uploads: max_connections,
+            request_config,
+            hedged_requests: AtomicU64::new(0),
         }))
     }
 
@@ -145,10 +153,19 @@ where
         let client = &self.client;
         let consider_expired_after_s = self.consider_expired_after_s;
         let now_fn = &self.now_fn;
+        let head_timeouts = &self.request_config.head_timeouts;
 
         self.retrier
             .retry(unfold(object_path, move |object_path| async move {
-                match client.read_object_metadata(&object_path).await.err_tip(|| {
+                let result = match head_timeouts
+                    .start()
+                    .response(client.read_object_metadata(&object_path))
+                    .await
+                {
+                    Ok(result) => result,
+                    Err(e) => return Some((RetryResult::Retry(e), object_path)),
+                };
+                match result.err_tip(|| {
                     format!(
                         "Error while trying to read - bucket: {} path: {}",
                         object_path.bucket, object_path.path
@@ -240,13 +257,18 @@ where
             if size < MIN_MULTIPART_SIZE {
                 let content = reader.consume(Some(size as usize)).await?;
                 let client = &self.client;
+                let write_timeouts = &self.request_config.write_timeouts;
 
                 return self
                     .retrier
                     .retry(unfold(content, |content| async {
-                        match client.write_object(&object_path, content.to_vec()).await {
-                            Ok(()) => Some((RetryResult::Ok(()), content)),
-                            Err(e) => Some((RetryResult::Retry(e), content)),
+                        match write_timeouts
+                            .start()
+                            .response(client.write_object(&object_path, content.to_vec()))
+                            .await
+                        {
+                            Ok(Ok(())) => Some((RetryResult::Ok(()), content)),
+                            Ok(Err(e)) | Err(e) => Some((RetryResult::Retry(e), content)),
                         }
                     }))
                     .await;
@@ -263,6 +285,7 @@ where
         };
         let mut upload_id: Option<String> = None;
         let client = &self.client;
+        let write_timeouts = &self.request_config.write_timeouts;
 
         loop {
             let chunk = reader.consume(Some(self.max_chunk_size)).await?;
@@ -305,19 +328,20 @@ where
             let object_path_ref = &object_path;
             self.retrier
                 .retry(unfold(chunk, |chunk| async move {
-                    match client
-                        .upload_chunk(
+                    match write_timeouts
+                        .start()
+                        .response(client.upload_chunk(
                             upload_id_ref,
                             object_path_ref,
                             chunk.clone(),
                             current_offset,
                             offset,
                             total_size,
-                        )
+                        ))
                         .await
                     {
-                        Ok(()) => Some((RetryResult::Ok(()), chunk)),
-                        Err(e) => Some((RetryResult::Retry(e), chunk)),
+                        Ok(Ok(())) => Some((RetryResult::Ok(()), chunk)),
+                        Ok(Err(e)) | Err(e) => Some((RetryResult::Retry(e), chunk)),
                     }
                 }))
                 .await?;
@@ -397,22 +421,41 @@ where
         let client = &self.client;
 
         let object_path_ref = &object_path;
+        let read_timeouts = &self.request_config.read_timeouts;
         self.retrier
             .retry(unfold(
                 (offset, writer),
                 |(mut offset, writer)| async move {
-                    let mut stream = match client
-                        .read_object_content(object_path_ref, offset, end_offset)
-                        .await
-                    {
-                        Ok(stream) => stream,
+                    let result = hedged(
+                        self.request_config.hedge_delay,
+                        &self.hedged_requests,
+                        move || async move {
+                            let deadline = read_timeouts.start();
+                            let stream = deadline
+                                .response(client.read_object_content(
+                                    object_path_ref,
+                                    offset,
+                                    end_offset,
+                                ))
+                                .await??;
+                            Ok((stream, deadline))
+                        },
+                    )
+                    .await;
+                    let (mut stream, deadline) = match result {
+                        Ok(response) => response,
                         Err(e) if e.code == Code::NotFound => {
                             return Some((RetryResult::Err(e), (offset, writer)));
                         }
                         Err(e) => return Some((RetryResult::Retry(e), (offset, writer))),
                     };
 
-                    while let Some(next_chunk) = stream.next().await {
+                    loop {
+                        let next_chunk = match deadline.body(stream.next()).await {
+                            Ok(Some(next_chunk)) => next_chunk,
+                            Ok(None) => break,
+                            Err(e) => return Some((RetryResult::Retry(e), (offset, writer))),
+                        };
                         match next_chunk {
                             Ok(bytes) => {
                                 offset += bytes.len() as u64;
@@ -38,7 +38,7 @@ use nativelink_proto::google::bytestream::{
 };
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
 use nativelink_util::common::DigestInfo;
-use nativelink_util::connection_manager::ConnectionManager;
+use nativelink_util::connection_manager::{ConnectionManager, LoadBalancing};
 use nativelink_util::digest_hasher::{DigestHasherFunc, default_digest_hasher_func};
 use nativelink_util::health_utils::HealthStatusIndicator;
 use nativelink_util::proto_stream_utils::{
@@ -102,6 +102,11 @@ impl GrpcStore {
                 spec.max_concurrent_requests,
                 spec.retry.clone(),
                 jitter_fn,
+                LoadBalancing {
+                    policy: spec.load_balancing,
+                    max_endpoint_failures: spec.max_endpoint_failures,
+                    ejection_duration: Duration::from_secs(spec.endpoint_ejection_seconds),
+                },
             ),
         }))
     }
@@ -0,0 +1,380 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::TryStreamExt;
+use futures::stream::{FuturesUnordered, unfold};
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Incoming;
+use hyper::header::{CONTENT_LENGTH, RANGE};
+use hyper::{Method, Request, Response, StatusCode, Uri};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::Client as LegacyClient;
+use hyper_util::client::legacy::connect::HttpConnector as LegacyHttpConnector;
+use hyper_util::rt::TokioExecutor;
+use nativelink_config::stores::HttpSpec;
+use nativelink_error::{Code, Error, ResultExt, error_if, make_err};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
+use nativelink_util::retry::{Retrier, RetryResult};
+use nativelink_util::store_trait::{
+    RemoveItemCallback, StoreDriver, StoreKey, StoreOptimizations, UploadSizeInfo,
+};
+use tokio::time::sleep;
+
+use crate::cas_utils::is_zero_digest;
+
+/// Placeholder of the URL template replaced by the hex hash of the digest.
+const HASH_PLACEHOLDER: &str = "{hash}";
+
+/// Placeholder of the URL template replaced by the size of the digest.
+const SIZE_PLACEHOLDER: &str = "{size}";
+
+#[derive(Debug, Default, MetricsComponent)]
+struct HttpMetrics {
+    #[metric(help = "Number of objects fetched from the mirror")]
+    fetched_objects: AtomicU64,
+    #[metric(help = "Number of bytes fetched from the mirror")]
+    fetched_bytes: AtomicU64,
+    #[metric(help = "Number of uploads discarded because the store is read-only")]
+    discarded_uploads: AtomicU64,
+}
+
+#[derive(MetricsComponent)]
+pub struct HttpStore {
+    client: LegacyClient<HttpsConnector<LegacyHttpConnector>, Empty<Bytes>>,
+    #[metric(help = "Template of the URLs objects are fetched from")]
+    url_template: String,
+    retrier: Retrier,
+    #[metric]
+    metrics: HttpMetrics,
+}
+
+impl core::fmt::Debug for HttpStore {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("HttpStore")
+            .field("url_template", &self.url_template)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HttpStore {
+    pub fn new(spec: &HttpSpec) -> Result<Arc<Self>, Error> {
+        error_if!(
+            !spec.url_template.contains(HASH_PLACEHOLDER),
+            "url_template of the HTTP store must contain {HASH_PLACEHOLDER}, got {}",
+            spec.url_template
+        );
+        let connector_with_roots = HttpsConnectorBuilder::new().with_platform_verifier();
+        let connector_with_schemes = if spec.insecure_allow_http {
+            connector_with_roots.https_or_http()
+        } else {
+            connector_with_roots.https_only()
+        };
+        let connector = if spec.disable_http2 {
+            connector_with_schemes.enable_http1().build()
+        } else {
+            connector_with_schemes.enable_http1().enable_http2().build()
+        };
+
+        Ok(Arc::new(Self {
+            client: LegacyClient::builder(TokioExecutor::new()).build(connector),
+            url_template: spec.url_template.clone(),
+            retrier: Retrier::new(
+                Arc::new(|duration| Box::pin(sleep(duration))),
+                spec.retry.make_jitter_fn(),
+                spec.retry.clone(),
+            ),
+            metrics: HttpMetrics::default(),
+        }))
+    }
+
+    /// Returns the URL of `digest` on the mirror.
+    fn make_uri(&self, digest: &DigestInfo) -> Result<Uri, Error> {
+        let url = self
+            .url_template
+            .replace(HASH_PLACEHOLDER, &digest.packed_hash().to_string())
+            .replace(SIZE_PLACEHOLDER, &digest.size_bytes().to_string());
+        url.parse().map_err(|e| {
+            make_err!(
+                Code::InvalidArgument,
+                "Invalid URL {url} in HTTP store: {e}"
+            )
+        })
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        uri: Uri,
+        range: Option<&str>,
+    ) -> Result<Response<Incoming>, Error> {
+        let mut builder = Request::builder().method(method).uri(uri);
+        if let Some(range) = range {
+            builder = builder.header(RANGE, range);
+        }
+        let request = builder
+            .body(Empty::new())
+            .map_err(|e| make_err!(Code::Internal, "Failed to build request in HTTP store: {e}"))?;
+        self.client
+            .request(request)
+            .await
+            .map_err(|e| make_err!(Code::Unavailable, "Request failed in HTTP store: {e}"))
+    }
+
+    async fn has_digest(&self, digest: DigestInfo) -> Result<Option<u64>, Error> {
+        let uri = self.make_uri(&digest)?;
+        self.retrier
+            .retry(unfold((), move |state| {
+                let uri = uri.clone();
+                async move {
+                    let response = match self.send(Method::HEAD, uri.clone(), None).await {
+                        Ok(response) => response,
+                        Err(e) => return Some((RetryResult::Retry(e), state)),
+                    };
+                    let status = response.status();
+                    let result = if status.is_success() {
+                        let content_length = response
+                            .headers()
+                            .get(CONTENT_LENGTH)
+                            .and_then(|value| value.to_str().ok()?.parse().ok());
+                        RetryResult::Ok(Some(content_length.unwrap_or(digest.size_bytes())))
+                    } else if status == StatusCode::NOT_FOUND {
+                        RetryResult::Ok(None)
+                    } else {
+                        status_error(status, &uri)
+                    };
+                    Some((result, state))
+                }
+            }))
+            .await
+    }
+}
+
+/// Turns an unexpected response status into an error, retrying the request
+/// if the failure may be temporary.
+fn status_error<T>(status: StatusCode, uri: &Uri) -> RetryResult<T> {
+    let err = make_err!(
+        Code::Unavailable,
+        "Unexpected status {status} fetching {uri} in HTTP store"
+    );
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        RetryResult::Retry(err)
+    } else {
+        RetryResult::Err(err)
+    }
+}
+
+#[async_trait]
+impl StoreDriver for HttpStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        keys.iter()
+            .zip(results.iter_mut())
+            .map(|(key, result)| async move {
+                // We need to do a special pass to ensure our zero key exist.
+                if is_zero_digest(key.borrow()) {
+                    *result = Some(0);
+                    return Ok::<_, Error>(());
+                }
+                *result = match key {
+                    StoreKey::Digest(digest) => self.has_digest(*digest).await?,
+                    // Only digests can be fetched from the mirror.
+                    StoreKey::Str(_) => None,
+                };
+                Ok::<_, Error>(())
+            })
+            .collect::<FuturesUnordered<_>>()
+            .try_collect()
+            .await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        _key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        _size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        self.metrics
+            .discarded_uploads
+            .fetch_add(1, Ordering::Relaxed);
+        reader.drain().await.err_tip(|| "In HttpStore::update")
+    }
+
+    fn optimized_for(&self, optimization: StoreOptimizations) -> bool {
+        optimization == StoreOptimizations::NoopUpdates
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        if is_zero_digest(key.borrow()) {
+            writer
+                .send_eof()
+                .err_tip(|| "Failed to send zero EOF in HTTP store get_part")?;
+            return Ok(());
+        }
+        let StoreKey::Digest(digest) = key else {
+            return Err(make_err!(
+                Code::NotFound,
+                "Only digests can be fetched from the HTTP store, got {key:?}"
+            ));
+        };
+        let uri = self.make_uri(&digest)?;
+        let end = length
+            .map(|length| {
+                offset
+                    .checked_add(length)
+                    .err_tip(|| "Integer overflow protection triggered")
+            })
+            .transpose()?;
+
+        self.retrier
+            .retry(unfold(writer, move |writer| {
+                let uri = uri.clone();
+                async move {
+                    // Reads resume after the bytes already sent.
+                    let start = offset + writer.get_bytes_written();
+                    let range = match end {
+                        Some(end) if start >= end => return Some((send_eof(writer), writer)),
+                        Some(end) => Some(format!("bytes={start}-{}", end - 1)),
+                        None if start > 0 => Some(format!("bytes={start}-")),
+                        None => None,
+                    };
+                    let response = match self.send(Method::GET, uri.clone(), range.as_deref()).await
+                    {
+                        Ok(response) => response,
+                        Err(e) => return Some((RetryResult::Retry(e), writer)),
+                    };
+                    let status = response.status();
+                    if status == StatusCode::NOT_FOUND {
+                        return Some((
+                            RetryResult::Err(make_err!(
+                                Code::NotFound,
+                                "{uri} not found in HTTP store"
+                            )),
+                            writer,
+                        ));
+                    }
+                    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+                        // The read starts past the end of the object.
+                        return Some((send_eof(writer), writer));
+                    }
+                    if !status.is_success() {
+                        return Some((status_error(status, &uri), writer));
+                    }
+
+                    // Servers ignoring the range header send the whole object.
+                    let mut skip = if range.is_some() && status != StatusCode::PARTIAL_CONTENT {
+                        start
+                    } else {
+                        0
+                    };
+                    let mut remaining = end.map(|end| end - start);
+                    let mut body = response.into_body();
+                    while remaining != Some(0) {
+                        let Some(frame) = body.frame().await else {
+                            break;
+                        };
+                        let data = match frame {
+                            Ok(frame) => match frame.into_data() {
+                                Ok(data) => data,
+                                Err(_) => continue,
+                            },
+                            Err(e) => {
+                                return Some((
+                                    RetryResult::Retry(make_err!(
+                                        Code::Aborted,
+                                        "Failed to read body of {uri} in HTTP store: {e}"
+                                    )),
+                                    writer,
+                                ));
+                            }
+                        };
+                        let skipped = skip.min(data.len() as u64);
+                        skip -= skipped;
+                        let mut data = data.slice(skipped as usize..);
+                        if let Some(remaining) = &mut remaining {
+                            data.truncate(data.len().min(*remaining as usize));
+                            *remaining -= data.len() as u64;
+                        }
+                        if data.is_empty() {
+                            continue;
+                        }
+                        self.metrics
+                            .fetched_bytes
+                            .fetch_add(data.len() as u64, Ordering::Relaxed);
+                        if let Err(e) = writer.send(data).await {
+                            return Some((
+                                RetryResult::Err(make_err!(
+                                    Code::Aborted,
+                                    "Error sending bytes to consumer in HTTP store: {e}"
+                                )),
+                                writer,
+                            ));
+                        }
+                    }
+                    self.metrics.fetched_objects.fetch_add(1, Ordering::Relaxed);
+                    Some((send_eof(writer), writer))
+                }
+            }))
+            .await
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        _callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        // Objects are never removed from the mirror by this store.
+        Ok(())
+    }
+}
+
+fn send_eof(writer: &mut DropCloserWriteHalf) -> RetryResult<()> {
+    match writer.send_eof() {
+        Ok(()) => RetryResult::Ok(()),
+        Err(e) => RetryResult::Err(make_err!(
+            Code::Aborted,
+            "Failed to send EOF in HTTP store get_part: {e}"
+        )),
+    }
+}
+
+default_health_status_indicator!(HttpStore);
@@ -13,8 +13,12 @@
 // limitations under the License.
 
 pub mod ac_utils;
+pub mod admission_store;
+pub mod azure_client;
+pub mod azure_store;
 pub mod callback_utils;
 pub mod cas_utils;
+pub mod circuit_breaker_store;
 pub mod common_s3_utils;
 pub mod completeness_checking_store;
 pub mod compression_store;
@@ -26,16 +30,26 @@ pub mod filesystem_store;
 pub mod gcs_client;
 pub mod gcs_store;
 pub mod grpc_store;
+pub mod http_store;
 pub mod memory_store;
+pub mod migration_store;
 pub mod mongo_store;
+pub mod negative_cache_store;
 pub mod noop_store;
+pub mod object_store_utils;
 pub mod ontap_s3_existence_cache_store;
 pub mod ontap_s3_store;
+pub mod packing_store;
+pub mod quota_store;
 pub mod redis_store;
 mod redis_utils;
 pub mod ref_store;
+pub mod replication_store;
+pub mod rocksdb_store;
 pub mod s3_store;
 pub mod shard_store;
 pub mod size_partitioning_store;
+pub mod sqlite_store;
 pub mod store_manager;
+pub mod tiered_store;
 pub mod verify_store;
@@ -22,8 +22,9 @@ use std::time::SystemTime;
 
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
-use nativelink_config::stores::MemorySpec;
-use nativelink_error::{Code, Error, ResultExt};
+use lz4_flex::block::{compress_into, decompress_into, get_maximum_output_size};
+use nativelink_config::stores::{MemoryCompression, MemorySpec};
+use nativelink_error::{Code, Error, ResultExt, make_err};
 use nativelink_metric::MetricsComponent;
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
 use nativelink_util::evicting_map::{EvictingMap, LenEntry};
@@ -38,7 +39,33 @@ use crate::callback_utils::RemoveItemCallbackHolder;
 use crate::cas_utils::is_zero_digest;
 
 #[derive(Clone)]
-pub struct BytesWrapper(Bytes);
+pub struct BytesWrapper {
+    data: Bytes,
+    /// Length of the uploaded data, which is larger than `data` if it was
+    /// compressed.
+    len: u64,
+    compressed: bool,
+}
+
+impl BytesWrapper {
+    /// Returns the uploaded data, decompressing it if needed.
+    fn uncompressed_data(&self) -> Result<Bytes, Error> {
+        if !self.compressed {
+            return Ok(self.data.clone());
+        }
+        let len = usize::try_from(self.len).err_tip(|| "Could not convert len to usize")?;
+        let mut uncompressed = vec![0; len];
+        let uncompressed_len = decompress_into(&self.data, &mut uncompressed)
+            .map_err(|e| make_err!(Code::Internal, "Failed to decompress entry: {e:?}"))?;
+        if uncompressed_len != len {
+            return Err(make_err!(
+                Code::Internal,
+                "Decompressed entry has {uncompressed_len} bytes, expected {len}"
+            ));
+        }
+        Ok(Bytes::from(uncompressed))
+    }
+}
 
 impl Debug for BytesWrapper {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -49,12 +76,17 @@ impl Debug for BytesWrapper {
 impl LenEntry for BytesWrapper {
     #[inline]
     fn len(&self) -> u64 {
-        Bytes::len(&self.0) as u64
+        self.len
     }
 
     #[inline]
     fn is_empty(&self) -> bool {
-        Bytes::is_empty(&self.0)
+        self.len == 0
+    }
+
+    #[inline]
+    fn heap_size(&self) -> u64 {
+        Bytes::len(&self.data) as u64
     }
 }
 
@@ -62,6 +94,7 @@ impl LenEntry for BytesWrapper {
 pub struct MemoryStore {
     #[metric(group = "evicting_map")]
     evicting_map: EvictingMap<StoreKeyBorrow, StoreKey<'static>, BytesWrapper, SystemTime>,
+    compression: MemoryCompression,
 }
 
 impl MemoryStore {
@@ -69,7 +102,37 @@ impl MemoryStore {
         let empty_policy = nativelink_config::stores::EvictionPolicy::default();
         let eviction_policy = spec.eviction_policy.as_ref().unwrap_or(&empty_policy);
         Arc::new(Self {
-            evicting_map: EvictingMap::new(eviction_policy, SystemTime::now()),
+            evicting_map: EvictingMap::new(eviction_policy, SystemTime::now())
+                .with_max_heap_bytes(spec.max_heap_bytes as u64),
+            compression: spec.compression,
+        })
+    }
+
+    /// Returns the bytes of memory held by the entries of the store.
+    pub async fn heap_size(&self) -> u64 {
+        self.evicting_map.heap_size().await
+    }
+
+    fn make_entry(&self, buffer: &[u8]) -> Result<BytesWrapper, Error> {
+        let len = buffer.len() as u64;
+        if self.compression == MemoryCompression::Lz4 && !buffer.is_empty() {
+            let mut compressed = vec![0; get_maximum_output_size(buffer.len())];
+            let compressed_len = compress_into(buffer, &mut compressed)
+                .map_err(|e| make_err!(Code::Internal, "Failed to compress entry: {e:?}"))?;
+            if compressed_len < buffer.len() {
+                return Ok(BytesWrapper {
+                    data: Bytes::copy_from_slice(&compressed[..compressed_len]),
+                    len,
+                    compressed: true,
+                });
+            }
+        }
+        let mut new_buffer = BytesMut::with_capacity(buffer.len());
+        new_buffer.extend_from_slice(buffer);
+        Ok(BytesWrapper {
+            data: new_buffer.freeze(),
+            len,
+            compressed: false,
         })
     }
 
@@ -125,6 +188,10 @@ impl StoreDriver for MemoryStore {
         Ok(iterations)
     }
 
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        Ok(self.remove_entry(key).await)
+    }
+
     async fn update(
         self: Pin<&Self>,
         key: StoreKey<'_>,
@@ -132,19 +199,19 @@ impl StoreDriver for MemoryStore {
         _size_info: UploadSizeInfo,
     ) -> Result<(), Error> {
         // Internally Bytes might hold a reference to more data than just our data. To prevent
-        // this potential case, we make a full copy of our data for long-term storage.
-        let final_buffer = {
+        // this potential case, we make a full copy (or compressed copy) of our data for
+        // long-term storage.
+        let entry = {
             let buffer = reader
                 .consume(None)
                 .await
                 .err_tip(|| "Failed to collect all bytes from reader in memory_store::update")?;
-            let mut new_buffer = BytesMut::with_capacity(buffer.len());
-            new_buffer.extend_from_slice(&buffer[..]);
-            new_buffer.freeze()
+            self.make_entry(&buffer[..])
+                .err_tip(|| "In memory_store::update")?
         };
 
         self.evicting_map
-            .insert(key.into_owned().into(), BytesWrapper(final_buffer))
+            .insert(key.into_owned().into(), entry)
             .await;
         Ok(())
     }
@@ -179,8 +246,11 @@ impl StoreDriver for MemoryStore {
             .saturating_sub(offset);
         let length = length.unwrap_or(default_len).min(default_len);
         if length > 0 {
+            let data = value
+                .uncompressed_data()
+                .err_tip(|| "In memory_store::get_part")?;
             writer
-                .send(value.0.slice(offset..(offset + length)))
+                .send(data.slice(offset..(offset + length)))
                 .await
                 .err_tip(|| "Failed to write data in memory store")?;
         }
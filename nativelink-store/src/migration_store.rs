@@ -0,0 +1,423 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::ops::Bound;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use std::borrow::Cow;
+use std::sync::{Arc, Weak};
+
+use async_trait::async_trait;
+use futures::join;
+use futures::stream::{self, StreamExt};
+use nativelink_config::stores::MigrationSpec;
+use nativelink_error::{Code, Error, ResultExt, make_err};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
+use nativelink_util::buf_channel::{
+    DropCloserReadHalf, DropCloserWriteHalf, make_buf_channel_pair,
+};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
+use nativelink_util::store_trait::{
+    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::time::{Instant, sleep, sleep_until};
+use tracing::{info, warn};
+
+/// Default maximum number of objects copied at the same time.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_CONCURRENT_COPIES: usize = 8;
+
+/// Number of keys listed from the source at a time. Progress is saved
+/// after each batch.
+const LIST_BATCH_SIZE: usize = 1000;
+
+/// Delay before listing the source again after it failed, or before
+/// starting over when some objects failed to copy.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Key saved in the progress file. `StoreKey` keeps the variant, as it
+/// decides the order keys are listed in.
+#[derive(Serialize, Deserialize)]
+enum ProgressKey {
+    Str(String),
+    Digest(DigestInfo),
+}
+
+impl ProgressKey {
+    fn to_store_key(&self) -> StoreKey<'_> {
+        match self {
+            Self::Str(key) => StoreKey::Str(Cow::Borrowed(key)),
+            Self::Digest(digest) => StoreKey::Digest(*digest),
+        }
+    }
+}
+
+impl From<&StoreKey<'_>> for ProgressKey {
+    fn from(key: &StoreKey<'_>) -> Self {
+        match key {
+            StoreKey::Str(key) => Self::Str(key.to_string()),
+            StoreKey::Digest(digest) => Self::Digest(*digest),
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Progress {
+    /// Last key of the source which was handled.
+    last_key: Option<ProgressKey>,
+    /// Whether some objects failed to copy, in which case the source is
+    /// copied again once the end is reached.
+    copy_failed: bool,
+    /// Whether every object of the source is in the destination.
+    completed: bool,
+}
+
+#[derive(Debug, Default, MetricsComponent)]
+struct MigrationMetrics {
+    #[metric(help = "Number of objects copied from the source into the destination")]
+    copied_objects: AtomicU64,
+    #[metric(help = "Bytes copied from the source into the destination")]
+    copied_bytes: AtomicU64,
+    #[metric(help = "Number of objects not copied because the destination already had them")]
+    skipped_objects: AtomicU64,
+    #[metric(help = "Number of objects which failed to copy")]
+    failed_objects: AtomicU64,
+    #[metric(help = "Number of reads served by the source")]
+    source_reads: AtomicU64,
+    #[metric(help = "1 once every object of the source was copied into the destination")]
+    completed: AtomicU64,
+}
+
+#[derive(Debug, MetricsComponent)]
+pub struct MigrationStore {
+    #[metric(group = "source")]
+    source: Store,
+    #[metric(group = "destination")]
+    destination: Store,
+    #[metric(help = "File the migration progress is saved to")]
+    progress_file: String,
+    #[metric(help = "Maximum number of bytes copied per second")]
+    max_bytes_per_second: u64,
+    #[metric(help = "Maximum number of objects copied at the same time")]
+    max_concurrent_copies: usize,
+    #[metric]
+    metrics: MigrationMetrics,
+    /// Time the next copy may start at to stay under `max_bytes_per_second`.
+    next_copy_at: Mutex<Instant>,
+}
+
+impl MigrationStore {
+    pub fn new(spec: &MigrationSpec, source: Store, destination: Store) -> Arc<Self> {
+        let max_concurrent_copies = if spec.max_concurrent_copies == 0 {
+            DEFAULT_MAX_CONCURRENT_COPIES
+        } else {
+            spec.max_concurrent_copies
+        };
+        let store = Arc::new(Self {
+            source,
+            destination,
+            progress_file: spec.progress_file.clone(),
+            max_bytes_per_second: spec.max_bytes_per_second,
+            max_concurrent_copies,
+            metrics: MigrationMetrics::default(),
+            next_copy_at: Mutex::new(Instant::now()),
+        });
+
+        let weak_store = Arc::downgrade(&store);
+        background_spawn!("migration_store_copy", async move {
+            Self::migrate(weak_store).await;
+        });
+        store
+    }
+
+    /// Whether every object of the source was copied into the destination.
+    pub fn is_completed(&self) -> bool {
+        self.metrics.completed.load(Ordering::Acquire) != 0
+    }
+
+    /// Copies the source into the destination until every object is
+    /// copied or the store is dropped.
+    async fn migrate(weak_store: Weak<Self>) {
+        let Some(store) = weak_store.upgrade() else {
+            return;
+        };
+        let mut progress = store.load_progress().await;
+        drop(store);
+        while !progress.completed {
+            let Some(store) = weak_store.upgrade() else {
+                return;
+            };
+            if let Err(err) = store.copy_batch(&mut progress).await {
+                warn!(?err, "Failed to copy objects in migration store, retrying");
+                drop(store);
+                sleep(RETRY_DELAY).await;
+                continue;
+            }
+            if progress.completed && progress.copy_failed {
+                warn!("Some objects failed to copy in migration store, copying the source again");
+                progress = Progress::default();
+                store.save_progress(&progress).await;
+                drop(store);
+                sleep(RETRY_DELAY).await;
+                continue;
+            }
+            store.save_progress(&progress).await;
+        }
+        if let Some(store) = weak_store.upgrade() {
+            store.metrics.completed.store(1, Ordering::Release);
+            info!("Migration store copied every object of the source");
+        }
+    }
+
+    async fn load_progress(&self) -> Progress {
+        if self.progress_file.is_empty() {
+            return Progress::default();
+        }
+        match fs::read_to_string(&self.progress_file).await {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                warn!(
+                    ?err,
+                    progress_file = %self.progress_file,
+                    "Failed to parse migration progress, starting over"
+                );
+                Progress::default()
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Progress::default(),
+            Err(err) => {
+                warn!(
+                    ?err,
+                    progress_file = %self.progress_file,
+                    "Failed to read migration progress, starting over"
+                );
+                Progress::default()
+            }
+        }
+    }
+
+    async fn save_progress(&self, progress: &Progress) {
+        if self.progress_file.is_empty() {
+            return;
+        }
+        let result = async {
+            let json = serde_json::to_string(progress)
+                .map_err(|e| make_err!(Code::Internal, "Failed to serialize progress: {e}"))?;
+            // Write a temporary file first, so a crash never leaves a
+            // truncated progress file behind.
+            let temp_path = format!("{}.tmp", self.progress_file);
+            fs::write(&temp_path, json)
+                .await
+                .map_err(|e| make_err!(Code::Internal, "Failed to write progress: {e}"))?;
+            fs::rename(&temp_path, &self.progress_file)
+                .await
+                .map_err(|e| make_err!(Code::Internal, "Failed to rename progress: {e}"))
+        }
+        .await;
+        if let Err(err) = result {
+            warn!(
+                ?err,
+                progress_file = %self.progress_file,
+                "Failed to save migration progress"
+            );
+        }
+    }
+
+    /// Copies the next batch of keys after `progress.last_key` which the
+    /// destination doesn't have, and moves `progress` past them.
+    async fn copy_batch(&self, progress: &mut Progress) -> Result<(), Error> {
+        let start = progress
+            .last_key
+            .as_ref()
+            .map_or(Bound::Unbounded, |key| Bound::Excluded(key.to_store_key()));
+        let mut keys = Vec::with_capacity(LIST_BATCH_SIZE);
+        self.source
+            .list((start, Bound::Unbounded), |key| {
+                keys.push(key.borrow().into_owned());
+                keys.len() < LIST_BATCH_SIZE
+            })
+            .await
+            .err_tip(|| "Failed to list source in migration store")?;
+        let Some(last_key) = keys.last() else {
+            progress.completed = true;
+            return Ok(());
+        };
+        let last_key = ProgressKey::from(last_key);
+
+        let (source_sizes, destination_sizes) = join!(
+            self.source.has_many(&keys),
+            self.destination.has_many(&keys)
+        );
+        let source_sizes = source_sizes.err_tip(|| "Failed to run has() on migration source")?;
+        let destination_sizes =
+            destination_sizes.err_tip(|| "Failed to run has() on migration destination")?;
+
+        let mut copies = stream::iter(keys.iter().zip(source_sizes).zip(destination_sizes))
+            .filter_map(|((key, source_size), destination_size)| async move {
+                if destination_size.is_some() {
+                    self.metrics.skipped_objects.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+                // Objects removed from the source since listing are skipped.
+                source_size.map(|size| (key, size))
+            })
+            .map(|(key, size)| async move { (key, size, self.copy(key.borrow(), size).await) })
+            .buffer_unordered(self.max_concurrent_copies);
+        while let Some((key, size, result)) = copies.next().await {
+            match result {
+                Ok(()) => {
+                    self.metrics.copied_objects.fetch_add(1, Ordering::Relaxed);
+                    self.metrics.copied_bytes.fetch_add(size, Ordering::Relaxed);
+                }
+                Err(err) => {
+                    self.metrics.failed_objects.fetch_add(1, Ordering::Relaxed);
+                    progress.copy_failed = true;
+                    warn!(?err, ?key, "Failed to copy object in migration store");
+                }
+            }
+        }
+        progress.last_key = Some(last_key);
+        Ok(())
+    }
+
+    async fn copy(&self, key: StoreKey<'_>, size: u64) -> Result<(), Error> {
+        if self.max_bytes_per_second != 0 {
+            let copy_at = {
+                let mut next_copy_at = self.next_copy_at.lock();
+                let copy_at = (*next_copy_at).max(Instant::now());
+                *next_copy_at = copy_at
+                    + Duration::from_secs_f64(size as f64 / self.max_bytes_per_second as f64);
+                copy_at
+            };
+            sleep_until(copy_at).await;
+        }
+        let (tx, rx) = make_buf_channel_pair();
+        let (get_res, update_res) = join!(
+            self.source.get(key.borrow(), tx),
+            self.destination
+                .update(key.borrow(), rx, UploadSizeInfo::ExactSize(size))
+        );
+        get_res
+            .merge(update_res)
+            .err_tip(|| "Failed to copy object in migration store")
+    }
+}
+
+#[async_trait]
+impl StoreDriver for MigrationStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        self.destination
+            .has_with_results(keys, results)
+            .await
+            .err_tip(|| "Failed to run has() on migration destination")?;
+        let missing: Vec<usize> = results
+            .iter()
+            .enumerate()
+            .filter_map(|(index, result)| result.is_none().then_some(index))
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let missing_keys: Vec<StoreKey<'_>> =
+            missing.iter().map(|&index| keys[index].borrow()).collect();
+        let source_results = self
+            .source
+            .has_many(&missing_keys)
+            .await
+            .err_tip(|| "Failed to run has() on migration source")?;
+        for (index, source_result) in missing.into_iter().zip(source_results) {
+            results[index] = source_result;
+        }
+        Ok(())
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        reader: DropCloserReadHalf,
+        size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        self.destination
+            .update(key, reader, size_info)
+            .await
+            .err_tip(|| "Failed to upload to migration destination")
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        match self
+            .destination
+            .get_part(key.borrow(), writer, offset, length)
+            .await
+        {
+            // The object was not copied yet.
+            Err(err) if err.code == Code::NotFound && writer.get_bytes_written() == 0 => {
+                self.metrics.source_reads.fetch_add(1, Ordering::Relaxed);
+                self.source
+                    .get_part(key, writer, offset, length)
+                    .await
+                    .err_tip(|| "Failed to read from migration source")
+            }
+            result => result.err_tip(|| "Failed to read from migration destination"),
+        }
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        let (destination_res, source_res) = join!(
+            self.destination.remove(key.borrow()),
+            self.source.remove(key.borrow())
+        );
+        let destination_removed =
+            destination_res.err_tip(|| "Failed to remove object in migration destination")?;
+        let source_removed =
+            source_res.err_tip(|| "Failed to remove object in migration source")?;
+        Ok(destination_removed || source_removed)
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        self.source.register_remove_callback(callback)?;
+        self.destination.register_remove_callback(callback)?;
+        Ok(())
+    }
+}
+
+default_health_status_indicator!(MigrationStore);
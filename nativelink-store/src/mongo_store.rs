@@ -1100,4 +1100,58 @@ impl SchedulerStore for ExperimentalMongoStore {
             make_err!(Code::Internal, "Failed to decode in get_and_decode: {e}")
         })?))
     }
+
+    async fn delete_data<K>(&self, key: K) -> Result<bool, Error>
+    where
+        K: SchedulerStoreKeyProvider + Send,
+    {
+        let key = key.get_key();
+        let encoded_key = self.encode_key(&key);
+        let result = self
+            .scheduler_collection
+            .delete_one(doc! { KEY_FIELD: encoded_key.as_ref() })
+            .await
+            .map_err(|e| {
+                make_err!(
+                    Code::Internal,
+                    "Failed to delete document in delete_data: {e}"
+                )
+            })?;
+        Ok(result.deleted_count > 0)
+    }
+
+    async fn list_keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let filter = doc! {
+            KEY_FIELD: {
+                "$regex": format!("^{}{}", regex::escape(&self.key_prefix), regex::escape(prefix)),
+            }
+        };
+        let mut cursor = self
+            .scheduler_collection
+            .find(filter)
+            .projection(doc! { KEY_FIELD: 1 })
+            .await
+            .map_err(|e| {
+                make_err!(
+                    Code::Internal,
+                    "Failed to create cursor in list_keys_with_prefix: {e}"
+                )
+            })?;
+        let mut keys = Vec::new();
+        while let Some(doc) = cursor.try_next().await.map_err(|e| {
+            make_err!(
+                Code::Internal,
+                "Failed to get next document in list_keys_with_prefix: {e}"
+            )
+        })? {
+            if let Some(key) = doc
+                .get_str(KEY_FIELD)
+                .ok()
+                .and_then(|key| self.decode_key(key))
+            {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
 }
@@ -0,0 +1,262 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use nativelink_config::stores::{EvictionPolicy, NegativeCacheSpec};
+use nativelink_error::{Error, ResultExt, error_if};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::evicting_map::{EvictingMap, LenEntry};
+use nativelink_util::health_utils::{HealthStatus, HealthStatusIndicator};
+use nativelink_util::instant_wrapper::InstantWrapper;
+use nativelink_util::store_trait::{
+    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreKeyBorrow, StoreLike, UploadSizeInfo,
+};
+use tracing::debug;
+
+/// Default number of seconds a missing key is remembered.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_TTL_SECONDS: u32 = 10;
+
+/// Default number of missing keys remembered.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_ENTRIES: u64 = 1_000_000;
+
+#[derive(Clone, Copy, Debug)]
+struct MissingItem;
+
+impl LenEntry for MissingItem {
+    #[inline]
+    fn len(&self) -> u64 {
+        0
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Default, MetricsComponent)]
+struct NegativeCacheMetrics {
+    #[metric(help = "Number of keys checked for existence")]
+    lookups: AtomicU64,
+    #[metric(help = "Number of keys reported as missing without asking the backend")]
+    negative_hits: AtomicU64,
+    #[metric(help = "Number of keys cached as missing which were then read from the backend")]
+    stale_negative_corrections: AtomicU64,
+}
+
+#[derive(Debug, MetricsComponent)]
+pub struct NegativeCacheStore<I: InstantWrapper> {
+    #[metric(group = "backend")]
+    backend: Store,
+    missing: EvictingMap<StoreKeyBorrow, StoreKey<'static>, MissingItem, I>,
+    // Incremented whenever a key is uploaded, so an existence check that
+    // raced with an upload doesn't cache the uploaded key as missing.
+    upload_generation: AtomicU64,
+    #[metric]
+    metrics: NegativeCacheMetrics,
+}
+
+impl NegativeCacheStore<SystemTime> {
+    pub fn new(spec: &NegativeCacheSpec, backend: Store) -> Arc<Self> {
+        Self::new_with_time(spec, backend, SystemTime::now())
+    }
+}
+
+impl<I: InstantWrapper> NegativeCacheStore<I> {
+    pub fn new_with_time(spec: &NegativeCacheSpec, backend: Store, anchor_time: I) -> Arc<Self> {
+        let eviction_policy = EvictionPolicy {
+            max_seconds: if spec.ttl_seconds == 0 {
+                DEFAULT_TTL_SECONDS
+            } else {
+                spec.ttl_seconds
+            },
+            max_count: if spec.max_entries == 0 {
+                DEFAULT_MAX_ENTRIES
+            } else {
+                spec.max_entries
+            },
+            ..Default::default()
+        };
+        Arc::new(Self {
+            backend,
+            missing: EvictingMap::new(&eviction_policy, anchor_time),
+            upload_generation: AtomicU64::new(0),
+            metrics: NegativeCacheMetrics::default(),
+        })
+    }
+
+    /// Forgets that `key` is missing. Returns true if it was cached as
+    /// missing.
+    async fn forget_missing(&self, key: StoreKey<'_>) -> bool {
+        self.missing.remove(&key.into_owned()).await
+    }
+}
+
+#[async_trait]
+impl<I: InstantWrapper> StoreDriver for NegativeCacheStore<I> {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        let owned_keys: Vec<_> = keys.iter().map(|key| key.borrow().into_owned()).collect();
+        let mut cached = vec![None; keys.len()];
+        self.missing
+            .sizes_for_keys(owned_keys.iter(), &mut cached, true /* peek */)
+            .await;
+        let negative_hits = cached.iter().filter(|cached| cached.is_some()).count();
+        self.metrics
+            .lookups
+            .fetch_add(keys.len() as u64, Ordering::Relaxed);
+        self.metrics
+            .negative_hits
+            .fetch_add(negative_hits as u64, Ordering::Relaxed);
+
+        let unknown_keys: Vec<_> = owned_keys
+            .into_iter()
+            .zip(cached.iter())
+            .filter_map(|(key, cached)| cached.is_none().then_some(key))
+            .collect();
+        if unknown_keys.is_empty() {
+            results.fill(None);
+            return Ok(());
+        }
+
+        let upload_generation = self.upload_generation.load(Ordering::Acquire);
+        let mut unknown_results = vec![None; unknown_keys.len()];
+        self.backend
+            .has_with_results(&unknown_keys, &mut unknown_results)
+            .await
+            .err_tip(|| "In NegativeCacheStore::has_with_results")?;
+
+        // Something was uploaded while the backend was asked, which might be
+        // one of the keys it reported as missing.
+        if upload_generation == self.upload_generation.load(Ordering::Acquire) {
+            let missing_keys: Vec<_> = unknown_keys
+                .iter()
+                .zip(unknown_results.iter())
+                .filter(|(_, result)| result.is_none())
+                .map(|(key, _)| key.borrow().into_owned())
+                .collect();
+            drop(
+                self.missing
+                    .insert_many(
+                        missing_keys
+                            .iter()
+                            .map(|key| (key.clone().into(), MissingItem)),
+                    )
+                    .await,
+            );
+            // An upload that bumped the generation between the check above and
+            // the insert may have already forgotten its key, so it has to be
+            // forgotten again here.
+            if upload_generation != self.upload_generation.load(Ordering::Acquire) {
+                for key in missing_keys {
+                    self.forget_missing(key).await;
+                }
+            }
+        }
+
+        let mut unknown_results_iter = unknown_results.into_iter();
+        for (result, cached) in results.iter_mut().zip(cached) {
+            *result = if cached.is_some() {
+                None
+            } else {
+                unknown_results_iter
+                    .next()
+                    .expect("has_with_results returned less results than expected")
+            };
+        }
+        error_if!(
+            unknown_results_iter.next().is_some(),
+            "has_with_results returned more results than expected"
+        );
+        Ok(())
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        reader: DropCloserReadHalf,
+        size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let result = self.backend.update(key.borrow(), reader, size_info).await;
+        self.upload_generation.fetch_add(1, Ordering::AcqRel);
+        self.forget_missing(key).await;
+        result
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        let result = self
+            .backend
+            .get_part(key.borrow(), writer, offset, length)
+            .await;
+        if result.is_ok() && self.forget_missing(key.borrow()).await {
+            debug!(?key, "Key cached as missing was read from the backend");
+            self.metrics
+                .stale_negative_corrections
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        self.backend.remove(key).await
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        self.backend.register_remove_callback(callback)
+    }
+}
+
+#[async_trait]
+impl<I: InstantWrapper> HealthStatusIndicator for NegativeCacheStore<I> {
+    fn get_name(&self) -> &'static str {
+        "NegativeCacheStore"
+    }
+
+    async fn check_health(&self, namespace: Cow<'static, str>) -> HealthStatus {
+        StoreDriver::check_health(Pin::new(self), namespace).await
+    }
+}
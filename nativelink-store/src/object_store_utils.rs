@@ -0,0 +1,163 @@
+// Copyright 2025 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::future::Future;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use std::sync::Arc;
+
+use nativelink_config::stores::{CommonObjectSpec, RequestTimeoutSpec};
+use nativelink_error::{Code, Error, make_err};
+use nativelink_util::retry::{Retrier, RetryBudget};
+use tokio::time::{Instant, sleep, timeout_at};
+
+/// Default delay before a read is hedged with a second request.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_HEDGE_DELAY: Duration = Duration::from_millis(100);
+
+/// Timeouts of a single attempt of one type of request.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestTimeouts {
+    first_byte: Option<Duration>,
+    total: Option<Duration>,
+}
+
+impl RequestTimeouts {
+    pub fn new(spec: &RequestTimeoutSpec) -> Self {
+        let to_duration = |ms: u64| (ms != 0).then(|| Duration::from_millis(ms));
+        Self {
+            first_byte: to_duration(spec.first_byte_timeout_ms),
+            total: to_duration(spec.total_timeout_ms),
+        }
+    }
+
+    /// Returns the deadlines of an attempt which starts now.
+    pub fn start(&self) -> RequestDeadline {
+        let now = Instant::now();
+        RequestDeadline {
+            first_byte: self.first_byte.map(|timeout| now + timeout),
+            total: self.total.map(|timeout| now + timeout),
+        }
+    }
+}
+
+/// Deadlines of a single attempt of a request.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestDeadline {
+    first_byte: Option<Instant>,
+    total: Option<Instant>,
+}
+
+impl RequestDeadline {
+    /// Waits for the response of the request, which must start before
+    /// both deadlines.
+    pub async fn response<T>(&self, fut: impl Future<Output = T>) -> Result<T, Error> {
+        let deadline = match (self.first_byte, self.total) {
+            (Some(first_byte), Some(total)) => Some(first_byte.min(total)),
+            (first_byte, total) => first_byte.or(total),
+        };
+        Self::until(deadline, fut).await
+    }
+
+    /// Waits for a part of the response of the request, which must be
+    /// received before the total deadline.
+    pub async fn body<T>(&self, fut: impl Future<Output = T>) -> Result<T, Error> {
+        Self::until(self.total, fut).await
+    }
+
+    async fn until<T>(deadline: Option<Instant>, fut: impl Future<Output = T>) -> Result<T, Error> {
+        let Some(deadline) = deadline else {
+            return Ok(fut.await);
+        };
+        timeout_at(deadline, fut)
+            .await
+            .map_err(|_| make_err!(Code::DeadlineExceeded, "Object store request timed out"))
+    }
+}
+
+/// Per request type timeouts, retry budget and hedging shared by the
+/// object stores.
+#[derive(Debug)]
+pub struct ObjectRequestConfig {
+    pub read_timeouts: RequestTimeouts,
+    pub head_timeouts: RequestTimeouts,
+    pub write_timeouts: RequestTimeouts,
+    pub retry_budget: Option<Arc<RetryBudget>>,
+    pub hedge_delay: Option<Duration>,
+}
+
+impl ObjectRequestConfig {
+    pub fn new(common: &CommonObjectSpec) -> Self {
+        Self {
+            read_timeouts: RequestTimeouts::new(&common.timeouts.read),
+            head_timeouts: RequestTimeouts::new(&common.timeouts.head),
+            write_timeouts: RequestTimeouts::new(&common.timeouts.write),
+            retry_budget: common
+                .retry_budget
+                .as_ref()
+                .map(|spec| Arc::new(RetryBudget::new(spec))),
+            hedge_delay: common.hedged_reads.map(|spec| {
+                if spec.delay_ms == 0 {
+                    DEFAULT_HEDGE_DELAY
+                } else {
+                    Duration::from_millis(spec.delay_ms)
+                }
+            }),
+        }
+    }
+
+    /// Makes `retrier` respect the retry budget, if there is one.
+    pub fn apply_budget(&self, retrier: Retrier) -> Retrier {
+        match &self.retry_budget {
+            Some(budget) => retrier.with_budget(budget.clone()),
+            None => retrier,
+        }
+    }
+}
+
+/// Sends the request made by `make_request`, and if it did not complete
+/// after `delay` a second one, returning whichever succeeds first. If one
+/// of the requests fails the result of the other one is returned.
+pub async fn hedged<T, Fut>(
+    delay: Option<Duration>,
+    hedged_requests: &AtomicU64,
+    make_request: impl Fn() -> Fut,
+) -> Result<T, Error>
+where
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let first = make_request();
+    let Some(delay) = delay else {
+        return first.await;
+    };
+    tokio::pin!(first);
+    tokio::select! {
+        result = &mut first => return result,
+        () = sleep(delay) => {}
+    }
+
+    hedged_requests.fetch_add(1, Ordering::Relaxed);
+    let second = make_request();
+    tokio::pin!(second);
+    tokio::select! {
+        result = &mut first => match result {
+            Ok(value) => Ok(value),
+            Err(_) => second.await,
+        },
+        result = &mut second => match result {
+            Ok(value) => Ok(value),
+            Err(_) => first.await,
+        },
+    }
+}
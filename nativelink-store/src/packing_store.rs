@@ -0,0 +1,406 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::mem;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+use async_trait::async_trait;
+use bincode::serde::{decode_from_slice, encode_to_vec};
+use bytes::{Bytes, BytesMut};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use nativelink_config::stores::PackingSpec;
+use nativelink_error::{Code, Error, ResultExt, error_if, make_err};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
+use nativelink_util::store_trait::{
+    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo,
+};
+use nativelink_util::{background_spawn, spawn_blocking};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, oneshot};
+use tracing::warn;
+
+// NOTE: If these change update the comments in `stores.rs` to reflect
+// the new defaults.
+const DEFAULT_MAX_PACKED_OBJECT_SIZE: u64 = 16 * 1024;
+const DEFAULT_SEGMENT_SIZE: u64 = 4 * 1024 * 1024;
+const DEFAULT_FLUSH_INTERVAL_MS: u64 = 500;
+
+// Number of index entries written to the index store at once.
+const MAX_CONCURRENT_INDEX_UPDATES: usize = 64;
+
+/// Where a packed object is stored, written to the index store under the
+/// digest of the object.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct PackedLocation {
+    pub segment: DigestInfo,
+    pub offset: u64,
+}
+
+type LegacyBincodeConfig = bincode::config::Configuration<
+    bincode::config::LittleEndian,
+    bincode::config::Fixint,
+    bincode::config::NoLimit,
+>;
+
+/// Objects collected for the next segment.
+#[derive(Debug, Default)]
+struct PendingSegment {
+    data: BytesMut,
+    offsets: HashMap<DigestInfo, u64>,
+    waiters: Vec<oneshot::Sender<Result<(), Error>>>,
+}
+
+#[derive(MetricsComponent)]
+pub struct PackingStore {
+    #[metric(group = "index_store")]
+    index_store: Store,
+    #[metric(group = "segment_store")]
+    segment_store: Store,
+    #[metric(help = "Objects up to this size are packed into segments")]
+    max_packed_object_size: u64,
+    #[metric(help = "Size at which a segment is uploaded")]
+    segment_size: u64,
+    bincode_config: LegacyBincodeConfig,
+    pending: Mutex<PendingSegment>,
+    /// Notified once the pending segment reached `segment_size`.
+    segment_full: Notify,
+    #[metric(help = "Number of segments written to the segment store")]
+    segments_written: AtomicU64,
+    #[metric(help = "Number of objects packed into segments")]
+    objects_packed: AtomicU64,
+}
+
+impl core::fmt::Debug for PackingStore {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PackingStore")
+            .field("index_store", &self.index_store)
+            .field("segment_store", &self.segment_store)
+            .field("max_packed_object_size", &self.max_packed_object_size)
+            .field("segment_size", &self.segment_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PackingStore {
+    pub fn new(
+        spec: &PackingSpec,
+        index_store: Store,
+        segment_store: Store,
+    ) -> Result<Arc<Self>, Error> {
+        let max_packed_object_size = if spec.max_packed_object_size == 0 {
+            DEFAULT_MAX_PACKED_OBJECT_SIZE
+        } else {
+            spec.max_packed_object_size
+        };
+        let segment_size = if spec.segment_size == 0 {
+            DEFAULT_SEGMENT_SIZE
+        } else {
+            spec.segment_size
+        };
+        error_if!(
+            max_packed_object_size > segment_size,
+            "Expected max_packed_object_size <= segment_size in packing store, got {max_packed_object_size}, {segment_size}"
+        );
+        let flush_interval = Duration::from_millis(if spec.flush_interval_ms == 0 {
+            DEFAULT_FLUSH_INTERVAL_MS
+        } else {
+            spec.flush_interval_ms
+        });
+        let store = Arc::new(Self {
+            index_store,
+            segment_store,
+            max_packed_object_size,
+            segment_size,
+            bincode_config: bincode::config::legacy(),
+            pending: Mutex::new(PendingSegment::default()),
+            segment_full: Notify::new(),
+            segments_written: AtomicU64::new(0),
+            objects_packed: AtomicU64::new(0),
+        });
+        let weak_store = Arc::downgrade(&store);
+        background_spawn!("packing_store_flush", async move {
+            Self::flush_loop(weak_store, flush_interval).await;
+        });
+        Ok(store)
+    }
+
+    /// Uploads the pending segment whenever it is full or `flush_interval`
+    /// passed, until the store is dropped.
+    async fn flush_loop(weak_store: Weak<Self>, flush_interval: Duration) {
+        loop {
+            let segment_full = {
+                let Some(store) = weak_store.upgrade() else {
+                    return;
+                };
+                // Keeps the store alive for at most one more interval once
+                // it is dropped elsewhere.
+                tokio::time::timeout(flush_interval, store.segment_full.notified())
+                    .await
+                    .is_ok()
+            };
+            let Some(store) = weak_store.upgrade() else {
+                return;
+            };
+            if let Err(err) = store.flush().await {
+                warn!(
+                    ?err,
+                    segment_full, "Failed to flush segment in packing store"
+                );
+            }
+        }
+    }
+
+    /// Uploads the objects collected so far as one segment and writes their
+    /// locations to the index store. Uploads waiting on these objects are
+    /// completed with the outcome.
+    async fn flush(&self) -> Result<(), Error> {
+        let pending = mem::take(&mut *self.pending.lock());
+        if pending.offsets.is_empty() {
+            return Ok(());
+        }
+        let result = self
+            .write_segment(pending.data.freeze(), pending.offsets)
+            .await;
+        for waiter in pending.waiters {
+            // The upload may have been cancelled, nothing to do then.
+            drop(waiter.send(result.clone()));
+        }
+        result
+    }
+
+    async fn write_segment(
+        &self,
+        data: Bytes,
+        offsets: HashMap<DigestInfo, u64>,
+    ) -> Result<(), Error> {
+        let segment = {
+            let data = data.clone();
+            spawn_blocking!("packing_store_hash_segment", move || {
+                DigestInfo::new(blake3::hash(&data).into(), data.len() as u64)
+            })
+            .await
+            .map_err(|e| {
+                make_err!(
+                    Code::Internal,
+                    "Failed to hash segment in packing store: {e:?}"
+                )
+            })?
+        };
+        self.segment_store
+            .update_oneshot(segment, data)
+            .await
+            .err_tip(|| "Failed to write segment in packing store")?;
+        self.segments_written.fetch_add(1, Ordering::Relaxed);
+
+        let num_objects = offsets.len() as u64;
+        stream::iter(offsets)
+            .map(|(digest, offset)| async move {
+                let location =
+                    encode_to_vec(PackedLocation { segment, offset }, self.bincode_config)
+                        .map_err(|e| {
+                            make_err!(
+                                Code::Internal,
+                                "Failed to serialize location in packing store : {e:?}"
+                            )
+                        })?;
+                self.index_store
+                    .update_oneshot(digest, location.into())
+                    .await
+                    .err_tip(|| "Failed to write location to index store in packing store")
+            })
+            .buffer_unordered(MAX_CONCURRENT_INDEX_UPDATES)
+            .try_collect::<()>()
+            .await?;
+        self.objects_packed
+            .fetch_add(num_objects, Ordering::Relaxed);
+        Ok(())
+    }
+
+    const fn is_packed(&self, key: &StoreKey<'_>) -> bool {
+        match key {
+            StoreKey::Digest(digest) => digest.size_bytes() <= self.max_packed_object_size,
+            StoreKey::Str(_) => false,
+        }
+    }
+
+    async fn get_location(&self, digest: DigestInfo) -> Result<PackedLocation, Error> {
+        let data = self
+            .index_store
+            .get_part_unchunked(digest, 0, None)
+            .await
+            .err_tip(|| "Failed to read index store in packing store")?;
+        let (location, _) = decode_from_slice::<PackedLocation, _>(&data, self.bincode_config)
+            .map_err(|e| {
+                make_err!(
+                    Code::Internal,
+                    "Failed to deserialize location in packing store : {e:?}"
+                )
+            })?;
+        Ok(location)
+    }
+}
+
+#[async_trait]
+impl StoreDriver for PackingStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        let (packed_keys, other_keys): (Vec<_>, Vec<_>) = keys
+            .iter()
+            .map(StoreKey::borrow)
+            .partition(|key| self.is_packed(key));
+        let (packed_results, other_results) = futures::join!(
+            self.index_store.has_many(&packed_keys),
+            self.segment_store.has_many(&other_keys),
+        );
+        let mut packed_results = packed_results
+            .err_tip(|| "Failed to call has() on index store in packing store")?
+            .into_iter();
+        let mut other_results = other_results?.into_iter();
+        for (key, result) in keys.iter().zip(results.iter_mut()) {
+            *result = if self.is_packed(key) {
+                // The index store holds the location, not the object.
+                packed_results
+                    .next()
+                    .err_tip(|| "packed_results out of sync with packed_keys")?
+                    .map(|_| key.borrow().into_digest().size_bytes())
+            } else {
+                other_results
+                    .next()
+                    .err_tip(|| "other_results out of sync with other_keys")?
+            };
+        }
+        Ok(())
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        if !self.is_packed(&key) {
+            return self.segment_store.update(key, reader, size_info).await;
+        }
+        let digest = key.into_digest();
+        let data = reader
+            .consume(Some(
+                usize::try_from(digest.size_bytes()).unwrap_or(usize::MAX),
+            ))
+            .await
+            .err_tip(|| "Failed to read object in packing store")?;
+        error_if!(
+            data.len() as u64 != digest.size_bytes(),
+            "Expected {} bytes for {digest} in packing store, got {}",
+            digest.size_bytes(),
+            data.len()
+        );
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut pending = self.pending.lock();
+            if !pending.offsets.contains_key(&digest) {
+                let offset = pending.data.len() as u64;
+                pending.data.extend_from_slice(&data);
+                pending.offsets.insert(digest, offset);
+            }
+            pending.waiters.push(tx);
+            if pending.data.len() as u64 >= self.segment_size {
+                self.segment_full.notify_one();
+            }
+        }
+        rx.await
+            .map_err(|_| make_err!(Code::Internal, "Packing store dropped pending segment"))?
+            .err_tip(|| "Failed to pack object in packing store")
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        if !self.is_packed(&key) {
+            return self
+                .segment_store
+                .get_part(key, writer, offset, length)
+                .await;
+        }
+        let digest = key.into_digest();
+        let size = digest.size_bytes();
+        error_if!(
+            offset > size,
+            "Offset {offset} is past the end of {digest} in packing store"
+        );
+        let location = self.get_location(digest).await?;
+        let length = length.map_or(size - offset, |length| length.min(size - offset));
+        if length == 0 {
+            writer
+                .send_eof()
+                .err_tip(|| "Failed to write EOF in packing store get_part")?;
+            return Ok(());
+        }
+        self.segment_store
+            .get_part(
+                location.segment,
+                writer,
+                location.offset + offset,
+                Some(length),
+            )
+            .await
+            .err_tip(|| "Failed to read segment in packing store")
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        if self.is_packed(&key) {
+            // The segment is shared with other objects, so only the index
+            // entry can be removed.
+            return self.index_store.remove(key).await;
+        }
+        self.segment_store.remove(key).await
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        self.index_store.register_remove_callback(callback)?;
+        self.segment_store.register_remove_callback(callback)?;
+        Ok(())
+    }
+}
+
+default_health_status_indicator!(PackingStore);
@@ -0,0 +1,433 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::ops::Bound;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use nativelink_config::stores::{EvictionPolicy, QuotaSpec};
+use nativelink_error::{Code, Error, ResultExt, error_if, make_err};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::evicting_map::{EvictingMap, LenEntry};
+use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
+use nativelink_util::store_trait::{
+    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreKeyBorrow, StoreLike, UploadSizeInfo,
+};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+// Number of objects whose sizes are looked up at once when counting the
+// objects already in the backend.
+const SCAN_BATCH_SIZE: usize = 1024;
+
+/// Request to remove an object of the given size from the backend.
+type Eviction = (StoreKey<'static>, u64);
+
+/// An object in the backend accounted by the quota store. Once evicted
+/// from the usage map the object is removed from the backend.
+#[derive(Debug)]
+struct TrackedObject {
+    evictions: mpsc::UnboundedSender<Eviction>,
+    key: StoreKey<'static>,
+    size: u64,
+    /// Set if the backend removed the object on its own.
+    removed: AtomicBool,
+}
+
+impl LenEntry for TrackedObject {
+    fn len(&self) -> u64 {
+        self.size
+    }
+
+    fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    async fn unref(&self) {
+        if !self.removed.load(Ordering::Acquire) {
+            // Fails only if the store is being dropped.
+            drop(self.evictions.send((self.key.clone(), self.size)));
+        }
+    }
+}
+
+type UsageMap = EvictingMap<StoreKeyBorrow, StoreKey<'static>, Arc<TrackedObject>, SystemTime>;
+
+/// Usage of the backend of a `QuotaStore`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// Total size of the objects in the backend.
+    pub bytes: u64,
+    /// Number of objects in the backend.
+    pub objects: u64,
+    /// Bytes reserved by uploads in progress.
+    pub reserved_bytes: u64,
+}
+
+#[derive(Debug, Default, MetricsComponent)]
+struct QuotaMetrics {
+    #[metric(help = "Bytes reserved by uploads in progress")]
+    reserved_bytes: AtomicU64,
+    #[metric(help = "Number of uploads rejected for exceeding the hard limit")]
+    rejected_uploads: AtomicU64,
+    #[metric(help = "Bytes of the uploads rejected for exceeding the hard limit")]
+    rejected_bytes: AtomicU64,
+    #[metric(help = "Number of objects removed from the backend for exceeding the soft limit")]
+    evicted_objects: AtomicU64,
+    #[metric(help = "Bytes removed from the backend for exceeding the soft limit")]
+    evicted_bytes: AtomicU64,
+}
+
+/// Bytes reserved by an upload in progress, released once dropped.
+struct Reservation<'a> {
+    reserved_bytes: &'a AtomicU64,
+    size: u64,
+}
+
+impl<'a> Reservation<'a> {
+    fn new(reserved_bytes: &'a AtomicU64, size: u64) -> Self {
+        reserved_bytes.fetch_add(size, Ordering::Relaxed);
+        Self {
+            reserved_bytes,
+            size,
+        }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.reserved_bytes.fetch_sub(self.size, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, MetricsComponent)]
+pub struct QuotaStore {
+    #[metric(group = "backend")]
+    backend: Store,
+    #[metric(help = "Size above which objects are removed from the backend")]
+    soft_limit_bytes: u64,
+    #[metric(help = "Size above which uploads are rejected")]
+    hard_limit_bytes: u64,
+    /// Objects in the backend, sized and ordered by their last use.
+    #[metric(group = "usage")]
+    usage: UsageMap,
+    #[metric]
+    metrics: QuotaMetrics,
+    evictions: mpsc::UnboundedSender<Eviction>,
+}
+
+#[derive(Debug)]
+struct QuotaRemoveCallback {
+    store: Weak<QuotaStore>,
+}
+
+#[async_trait]
+impl RemoveItemCallback for QuotaRemoveCallback {
+    async fn callback(&self, store_key: &StoreKey<'_>) {
+        if let Some(store) = self.store.upgrade() {
+            store.forget(store_key).await;
+        }
+    }
+}
+
+impl QuotaStore {
+    pub fn new(spec: &QuotaSpec, backend: Store) -> Result<Arc<Self>, Error> {
+        error_if!(
+            spec.hard_limit_bytes != 0 && spec.hard_limit_bytes < spec.soft_limit_bytes,
+            "hard_limit_bytes of QuotaStore must not be less than soft_limit_bytes"
+        );
+        let (evictions, mut evictions_rx) = mpsc::unbounded_channel();
+        let store = Arc::new(Self {
+            backend,
+            soft_limit_bytes: spec.soft_limit_bytes,
+            hard_limit_bytes: spec.hard_limit_bytes,
+            usage: EvictingMap::new(
+                &EvictionPolicy {
+                    max_bytes: spec.soft_limit_bytes as usize,
+                    ..Default::default()
+                },
+                SystemTime::now(),
+            ),
+            metrics: QuotaMetrics::default(),
+            evictions,
+        });
+        store
+            .backend
+            .register_remove_callback(&Arc::new(Box::new(QuotaRemoveCallback {
+                store: Arc::downgrade(&store),
+            })))
+            .err_tip(|| "Failed to register remove callback in QuotaStore")?;
+
+        if store.soft_limit_bytes != 0 {
+            let weak_store = Arc::downgrade(&store);
+            background_spawn!("quota_store_evictions", async move {
+                while let Some((key, size)) = evictions_rx.recv().await {
+                    let Some(store) = weak_store.upgrade() else {
+                        return;
+                    };
+                    store.evict(key, size).await;
+                }
+            });
+        }
+        let weak_store = Arc::downgrade(&store);
+        background_spawn!("quota_store_scan", async move {
+            let Some(store) = weak_store.upgrade() else {
+                return;
+            };
+            match store.scan_backend().await {
+                Ok(()) => {}
+                Err(err) if err.code == Code::Unimplemented => {
+                    info!("QuotaStore backend can't list objects, counting them once read");
+                }
+                Err(err) => warn!(?err, "Failed to count objects in QuotaStore backend"),
+            }
+        });
+        Ok(store)
+    }
+
+    /// Returns the usage of the backend.
+    pub async fn usage(&self) -> QuotaUsage {
+        let (bytes, objects) = self.usage.size_and_count().await;
+        QuotaUsage {
+            bytes,
+            objects,
+            reserved_bytes: self.metrics.reserved_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Counts the objects which were in the backend before the store
+    /// started.
+    async fn scan_backend(&self) -> Result<(), Error> {
+        let mut keys = Vec::new();
+        self.backend
+            .list(.., |key| {
+                keys.push(key.borrow().into_owned());
+                true
+            })
+            .await
+            .err_tip(|| "Failed to list backend in QuotaStore")?;
+        for batch in keys.chunks(SCAN_BATCH_SIZE) {
+            let sizes = self
+                .backend
+                .has_many(batch)
+                .await
+                .err_tip(|| "Failed to run has() in QuotaStore scan")?;
+            for (key, size) in batch.iter().zip(sizes) {
+                if let Some(size) = size {
+                    self.record_if_missing(key.borrow(), size).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn record(&self, key: StoreKey<'_>, size: u64) {
+        let key = key.into_owned();
+        self.usage
+            .insert(
+                key.clone().into(),
+                Arc::new(TrackedObject {
+                    evictions: self.evictions.clone(),
+                    key,
+                    size,
+                    removed: AtomicBool::new(false),
+                }),
+            )
+            .await;
+    }
+
+    async fn record_if_missing(&self, key: StoreKey<'_>, size: u64) {
+        if !self.is_recorded(&key.borrow().into_owned()).await {
+            self.record(key, size).await;
+        }
+    }
+
+    /// Checks if `key` is accounted, without marking it as recently used.
+    async fn is_recorded(&self, key: &StoreKey<'static>) -> bool {
+        let mut results = [None];
+        self.usage
+            .sizes_for_keys([key], &mut results[..], true /* peek */)
+            .await;
+        results[0].is_some()
+    }
+
+    /// Stops accounting an object the backend removed.
+    async fn forget(&self, key: &StoreKey<'_>) {
+        let key = key.borrow().into_owned();
+        let Some(object) = self.usage.get(&key).await else {
+            return;
+        };
+        object.removed.store(true, Ordering::Release);
+        self.usage
+            .remove_if(&key, |current| Arc::ptr_eq(current, &object))
+            .await;
+    }
+
+    async fn evict(&self, key: StoreKey<'static>, size: u64) {
+        // The object was uploaded again meanwhile.
+        if self.is_recorded(&key).await {
+            return;
+        }
+        match self.backend.remove(key.borrow()).await {
+            Ok(true) => {
+                self.metrics.evicted_objects.fetch_add(1, Ordering::Relaxed);
+                self.metrics
+                    .evicted_bytes
+                    .fetch_add(size, Ordering::Relaxed);
+            }
+            Ok(false) => {}
+            Err(err) => warn!(
+                ?err,
+                ?key,
+                "Failed to remove object exceeding the soft limit in QuotaStore"
+            ),
+        }
+    }
+
+    fn reserve(&self, size: u64, used_bytes: u64) -> Result<Reservation<'_>, Error> {
+        let reservation = Reservation::new(&self.metrics.reserved_bytes, size);
+        if self.hard_limit_bytes == 0 {
+            return Ok(reservation);
+        }
+        let reserved_bytes = self.metrics.reserved_bytes.load(Ordering::Relaxed);
+        if used_bytes + reserved_bytes > self.hard_limit_bytes {
+            self.metrics
+                .rejected_uploads
+                .fetch_add(1, Ordering::Relaxed);
+            self.metrics
+                .rejected_bytes
+                .fetch_add(size, Ordering::Relaxed);
+            return Err(make_err!(
+                Code::ResourceExhausted,
+                "Upload of {size} bytes exceeds the hard limit of {} bytes in QuotaStore, {used_bytes} bytes are stored and {} bytes reserved by other uploads",
+                self.hard_limit_bytes,
+                reserved_bytes.saturating_sub(size)
+            ));
+        }
+        Ok(reservation)
+    }
+}
+
+#[async_trait]
+impl StoreDriver for QuotaStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        self.backend.has_with_results(keys, results).await
+    }
+
+    async fn list(
+        self: Pin<&Self>,
+        range: (Bound<StoreKey<'_>>, Bound<StoreKey<'_>>),
+        handler: &mut (dyn for<'a> FnMut(&'a StoreKey) -> bool + Send + Sync + '_),
+    ) -> Result<u64, Error> {
+        self.backend
+            .as_store_driver_pin()
+            .list(range, handler)
+            .await
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        let removed = self
+            .backend
+            .remove(key.borrow())
+            .await
+            .err_tip(|| "In QuotaStore::remove")?;
+        self.forget(&key).await;
+        Ok(removed)
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        reader: DropCloserReadHalf,
+        size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let (UploadSizeInfo::ExactSize(max_size) | UploadSizeInfo::MaxSize(max_size)) = size_info;
+        let (used_bytes, _) = self.usage.size_and_count().await;
+        let reservation = self.reserve(max_size, used_bytes)?;
+        self.backend
+            .update(key.borrow(), reader, size_info)
+            .await
+            .err_tip(|| "In QuotaStore::update")?;
+        let size = match size_info {
+            UploadSizeInfo::ExactSize(size) => Some(size),
+            UploadSizeInfo::MaxSize(_) => self
+                .backend
+                .has(key.borrow())
+                .await
+                .err_tip(|| "Failed to run has() after upload in QuotaStore")?,
+        };
+        // The backend may have evicted the object right away.
+        if let Some(size) = size {
+            self.record(key, size).await;
+        }
+        drop(reservation);
+        Ok(())
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        self.backend
+            .get_part(key.borrow(), writer, offset, length)
+            .await?;
+        // Reads keep objects from being removed for exceeding the soft
+        // limit, and count objects the backend couldn't list.
+        if self.usage.get(&key.borrow().into_owned()).await.is_some() {
+            return Ok(());
+        }
+        let size = self
+            .backend
+            .has(key.borrow())
+            .await
+            .err_tip(|| "Failed to run has() after read in QuotaStore")?;
+        if let Some(size) = size {
+            self.record(key, size).await;
+        }
+        Ok(())
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        self.backend.register_remove_callback(callback)
+    }
+}
+
+default_health_status_indicator!(QuotaStore);
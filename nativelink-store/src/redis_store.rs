@@ -23,19 +23,21 @@ use async_trait::async_trait;
 use bytes::Bytes;
 use const_format::formatcp;
 use fred::clients::{Pool as RedisPool, SubscriberClient};
+use fred::error::Error as RedisError;
 use fred::interfaces::{ClientLike, KeysInterface, PubsubInterface};
 use fred::prelude::{Client, EventInterface, HashesInterface, RediSearchInterface};
 use fred::types::config::{
-    Config as RedisConfig, ConnectionConfig, PerformanceConfig, ReconnectPolicy, UnresponsiveConfig,
+    Config as RedisConfig, ConnectionConfig, PerformanceConfig, ReconnectPolicy, ServerConfig,
+    UnresponsiveConfig,
 };
 use fred::types::redisearch::{
-    AggregateOperation, FtAggregateOptions, FtCreateOptions, IndexKind, Load, SearchField,
-    SearchSchema, SearchSchemaKind, WithCursor,
+    AggregateOperation, FtAggregateOptions, FtCreateOptions, FtSearchOptions, IndexKind, Load,
+    SearchField, SearchSchema, SearchSchemaKind, WithCursor,
 };
-use fred::types::scan::Scanner;
+use fred::types::scan::{ScanResult, Scanner};
 use fred::types::scripts::Script;
-use fred::types::{Builder, Key as RedisKey, Map as RedisMap, SortOrder, Value as RedisValue};
-use futures::stream::FuturesUnordered;
+use fred::types::{Builder, Key as RedisKey, RespVersion, SortOrder, Value as RedisValue};
+use futures::stream::{BoxStream, FuturesUnordered};
 use futures::{FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt, future};
 use nativelink_config::stores::{RedisMode, RedisSpec};
 use nativelink_error::{Code, Error, ResultExt, make_err, make_input_err};
@@ -57,7 +59,7 @@ use tracing::{error, info, warn};
 use uuid::Uuid;
 
 use crate::cas_utils::is_zero_digest;
-use crate::redis_utils::ft_aggregate;
+use crate::redis_utils::{ElastiCacheIamCredentials, ExistenceCache, ft_aggregate, tls_connector};
 
 /// The default size of the read chunk when reading data from Redis.
 /// Note: If this changes it should be updated in the config documentation.
@@ -94,6 +96,24 @@ const DEFAULT_MAX_CHUNK_UPLOADS_PER_UPDATE: usize = 10;
 /// Note: If this changes it should be updated in the config documentation.
 const DEFAULT_SCAN_COUNT: u32 = 10_000;
 
+/// The default maximum number of `MOVED` or `ASK` redirections followed per
+/// command in cluster mode.
+/// Note: If this changes it should be updated in the config documentation.
+const DEFAULT_MAX_REDIRECTIONS: u32 = 5;
+
+/// Returns the part of `key` Redis hashes to find the cluster slot of the
+/// key. That is the content of the first `{...}` section if it is not
+/// empty, otherwise the whole key.
+fn hash_tag(key: &str) -> &str {
+    let Some(start) = key.find('{') else {
+        return key;
+    };
+    match key[start + 1..].find('}') {
+        Some(len) if len != 0 => &key[start + 1..start + 1 + len],
+        _ => key,
+    }
+}
+
 /// A [`StoreDriver`] implementation that uses Redis as a backing store.
 #[derive(Debug, MetricsComponent)]
 pub struct RedisStore {
@@ -133,13 +153,30 @@ pub struct RedisStore {
     #[metric(help = "The COUNT value passed when scanning keys in Redis")]
     scan_count: u32,
 
+    /// Number of seconds after which keys written by the store expire.
+    /// Zero means keys never expire.
+    #[metric(help = "Number of seconds after which keys written by the store expire")]
+    key_ttl_s: u64,
+
+    /// If reading a key restarts its expiration.
+    #[metric(help = "If reading a key restarts its expiration")]
+    refresh_ttl_on_read: bool,
+
     /// Redis script used to update a value in redis if the version matches.
     /// This is done by incrementing the version number and then setting the new data
     /// only if the version number matches the existing version number.
     update_if_version_matches_script: Script,
 
+    /// Redis script used to set an unversioned value in redis and publish
+    /// the change in the same round trip.
+    set_and_publish_script: Script,
+
     /// A manager for subscriptions to keys in Redis.
     subscription_manager: Mutex<Option<Arc<RedisSubscriptionManager>>>,
+
+    /// Cache of the results of existence checks, if enabled.
+    #[metric(group = "existence_cache")]
+    existence_cache: Option<ExistenceCache>,
 }
 
 impl RedisStore {
@@ -151,23 +188,82 @@ impl RedisStore {
                 "No addresses were specified in redis store configuration."
             ));
         }
-        let [addr] = spec.addresses.as_slice() else {
+        if spec.mode == RedisMode::Standard && spec.addresses.len() > 1 {
             return Err(make_err!(
-                Code::Unimplemented,
-                "Connecting directly to multiple redis nodes in a cluster is currently unsupported. Please specify a single URL to a single node, and nativelink will use cluster discover to find the other nodes."
+                Code::InvalidArgument,
+                "Multiple redis addresses are only supported in cluster and sentinel mode. In standard mode please specify a single URL to a single node."
             ));
-        };
-        let redis_config = match spec.mode {
-            RedisMode::Cluster => RedisConfig::from_url_clustered(addr),
-            RedisMode::Sentinel => RedisConfig::from_url_sentinel(addr),
-            RedisMode::Standard => RedisConfig::from_url_centralized(addr),
         }
-        .err_tip_with_code(|e| {
-            (
-                Code::InvalidArgument,
-                format!("while parsing redis node address: {e}"),
-            )
-        })?;
+        let mut redis_configs = spec.addresses.iter().map(|addr| {
+            match spec.mode {
+                RedisMode::Cluster => RedisConfig::from_url_clustered(addr),
+                RedisMode::Sentinel => RedisConfig::from_url_sentinel(addr),
+                RedisMode::Standard => RedisConfig::from_url_centralized(addr),
+            }
+            .err_tip_with_code(|e| {
+                (
+                    Code::InvalidArgument,
+                    format!("while parsing redis node address: {e}"),
+                )
+            })
+        });
+        let mut redis_config = redis_configs
+            .next()
+            .err_tip(|| "No addresses were specified in redis store configuration.")??;
+        // The other addresses are further cluster nodes to discover the
+        // cluster from, or further sentinels to discover the primary from.
+        for other_config in redis_configs {
+            match (&mut redis_config.server, other_config?.server) {
+                (
+                    ServerConfig::Clustered { hosts, .. },
+                    ServerConfig::Clustered {
+                        hosts: other_hosts, ..
+                    },
+                )
+                | (
+                    ServerConfig::Sentinel { hosts, .. },
+                    ServerConfig::Sentinel {
+                        hosts: other_hosts, ..
+                    },
+                ) => hosts.extend(other_hosts),
+                _ => {
+                    return Err(make_input_err!(
+                        "Redis addresses don't match the {:?} mode in redis store configuration",
+                        spec.mode
+                    ));
+                }
+            }
+        }
+
+        if let Some(username) = &spec.username {
+            redis_config.username = Some(username.clone());
+        }
+        if let Some(password) = &spec.password {
+            redis_config.password = Some(password.clone());
+        }
+        if let Some(tls_config) = &spec.tls_config {
+            redis_config.tls = Some(
+                tls_connector(tls_config)
+                    .err_tip(|| "while loading tls_config in redis store configuration")?
+                    .into(),
+            );
+        }
+        if let Some(iam_auth) = &spec.elasticache_iam_auth {
+            let username = redis_config.username.clone().ok_or_else(|| {
+                make_input_err!(
+                    "ElastiCache IAM auth requires a username in redis store configuration"
+                )
+            })?;
+            if redis_config.tls.is_none() {
+                return Err(make_input_err!(
+                    "ElastiCache IAM auth requires TLS, use rediss:// addresses or set tls_config in redis store configuration"
+                ));
+            }
+            redis_config.credential_provider = Some(Arc::new(ElastiCacheIamCredentials::new(
+                iam_auth.clone(),
+                username,
+            )));
+        }
 
         let reconnect_policy = {
             if spec.retry.delay == 0.0 {
@@ -215,30 +311,38 @@ impl RedisStore {
             if spec.scan_count == 0 {
                 spec.scan_count = DEFAULT_SCAN_COUNT;
             }
+            if spec.max_redirections == 0 {
+                spec.max_redirections = DEFAULT_MAX_REDIRECTIONS;
+            }
         }
         let connection_timeout = Duration::from_millis(spec.connection_timeout_ms);
         let command_timeout = Duration::from_millis(spec.command_timeout_ms);
 
-        let mut builder = Builder::from_config(redis_config);
-        builder
-            .set_performance_config(PerformanceConfig {
-                default_command_timeout: command_timeout,
-                broadcast_channel_capacity: spec.broadcast_channel_capacity,
-                ..Default::default()
-            })
-            .set_connection_config(ConnectionConfig {
-                connection_timeout,
-                internal_command_timeout: command_timeout,
-                unresponsive: UnresponsiveConfig {
-                    max_timeout: Some(connection_timeout),
-                    // This number needs to be less than the connection timeout.
-                    // We use 4 as it is a good balance between not spamming the server
-                    // and not waiting too long.
-                    interval: connection_timeout / 4,
-                },
-                ..Default::default()
-            })
-            .set_policy(reconnect_policy);
+        let make_builder = |config: RedisConfig| {
+            let mut builder = Builder::from_config(config);
+            builder
+                .set_performance_config(PerformanceConfig {
+                    default_command_timeout: command_timeout,
+                    broadcast_channel_capacity: spec.broadcast_channel_capacity,
+                    ..Default::default()
+                })
+                .set_connection_config(ConnectionConfig {
+                    connection_timeout,
+                    internal_command_timeout: command_timeout,
+                    max_redirections: spec.max_redirections,
+                    unresponsive: UnresponsiveConfig {
+                        max_timeout: Some(connection_timeout),
+                        // This number needs to be less than the connection timeout.
+                        // We use 4 as it is a good balance between not spamming the server
+                        // and not waiting too long.
+                        interval: connection_timeout / 4,
+                    },
+                    ..Default::default()
+                })
+                .set_policy(reconnect_policy.clone());
+            builder
+        };
+        let builder = make_builder(redis_config.clone());
 
         let client_pool = builder
             .build_pool(spec.connection_pool_size)
@@ -248,6 +352,21 @@ impl RedisStore {
             .build_subscriber_client()
             .err_tip(|| "while creating redis subscriber client")?;
 
+        // Client tracking requires RESP3, so the existence checks are sent
+        // on a separate client to keep the other commands on RESP2.
+        let existence_cache = spec
+            .existence_cache
+            .as_ref()
+            .map(|cache_spec| {
+                let mut tracking_config = redis_config;
+                tracking_config.version = RespVersion::RESP3;
+                make_builder(tracking_config)
+                    .build()
+                    .err_tip(|| "while creating redis existence cache client")
+                    .map(|client| ExistenceCache::new(client, cache_spec))
+            })
+            .transpose()?;
+
         Self::new_from_builder_and_parts(
             client_pool,
             subscriber_client,
@@ -258,7 +377,11 @@ impl RedisStore {
             spec.max_chunk_uploads_per_update,
             spec.scan_count,
         )
-        .map(Arc::new)
+        .map(|store| {
+            let mut store = store.with_key_ttl(spec.key_ttl_s, spec.refresh_ttl_on_read);
+            store.existence_cache = existence_cache;
+            Arc::new(store)
+        })
     }
 
     /// Used for testing when determinism is required.
@@ -288,11 +411,34 @@ impl RedisStore {
             read_chunk_size,
             max_chunk_uploads_per_update,
             scan_count,
+            key_ttl_s: 0,
+            refresh_ttl_on_read: false,
             update_if_version_matches_script: Script::from_lua(LUA_VERSION_SET_SCRIPT),
+            set_and_publish_script: Script::from_lua(LUA_SET_AND_PUBLISH_SCRIPT),
             subscription_manager: Mutex::new(None),
+            existence_cache: None,
         })
     }
 
+    /// Makes keys written by the store expire after `key_ttl_s` seconds,
+    /// restarting the expiration whenever a key is read if
+    /// `refresh_ttl_on_read` is set. Zero means keys never expire.
+    pub const fn with_key_ttl(mut self, key_ttl_s: u64, refresh_ttl_on_read: bool) -> Self {
+        self.key_ttl_s = key_ttl_s;
+        self.refresh_ttl_on_read = refresh_ttl_on_read;
+        self
+    }
+
+    /// Sets the expiration of `encoded_key` to `key_ttl_s`.
+    async fn expire_key(&self, client: &Client, encoded_key: &str) -> Result<(), Error> {
+        let key_ttl_s =
+            i64::try_from(self.key_ttl_s).err_tip(|| "key_ttl_s does not fit in i64")?;
+        client
+            .expire::<(), _>(encoded_key, key_ttl_s, None)
+            .await
+            .err_tip(|| format!("In RedisStore::expire_key for {encoded_key}"))
+    }
+
     async fn get_client(&'_ self) -> Result<&'_ Client, Error> {
         let client = self.client_pool.next();
         let config = client.client_config();
@@ -309,6 +455,22 @@ impl RedisStore {
         Ok(client)
     }
 
+    /// Scans the keys matching `pattern`. In cluster mode every node holds a
+    /// part of the keys, so all of them are scanned.
+    fn scan<'a>(
+        &self,
+        client: &'a Client,
+        pattern: String,
+    ) -> BoxStream<'a, Result<ScanResult, RedisError>> {
+        if client.is_clustered() {
+            client
+                .scan_cluster(pattern, Some(self.scan_count), None)
+                .boxed()
+        } else {
+            client.scan(pattern, Some(self.scan_count), None).boxed()
+        }
+    }
+
     /// Encode a [`StoreKey`] so it can be sent to Redis.
     fn encode_key<'a>(&self, key: &'a StoreKey<'a>) -> Cow<'a, str> {
         let key_body = key.as_str();
@@ -344,7 +506,10 @@ impl StoreDriver for RedisStore {
         // difficult and it doesn't work very well in cluster mode.
         // If we wanted to optimize this with pipeline be careful to
         // implement retry and to support cluster mode.
-        let client = self.get_client().await?;
+        let client = match &self.existence_cache {
+            Some(existence_cache) => existence_cache.client().await?,
+            None => self.get_client().await?,
+        };
         keys.iter()
             .zip(results.iter_mut())
             .map(|(key, result)| async move {
@@ -354,6 +519,14 @@ impl StoreDriver for RedisStore {
                     return Ok::<_, Error>(());
                 }
                 let encoded_key = self.encode_key(key);
+                let mut existence_read = None;
+                if let Some(existence_cache) = &self.existence_cache {
+                    if let Some(cached) = existence_cache.get(&encoded_key).await {
+                        *result = cached;
+                        return Ok(());
+                    }
+                    existence_read = existence_cache.start_read();
+                }
                 let pipeline = client.pipeline();
                 pipeline
                     .strlen::<(), _>(encoded_key.as_ref())
@@ -378,6 +551,14 @@ impl StoreDriver for RedisStore {
 
                 *result = if exists { Some(blob_len) } else { None };
 
+                if let (Some(existence_cache), Some(existence_read)) =
+                    (&self.existence_cache, existence_read)
+                {
+                    existence_cache
+                        .insert(encoded_key.into_owned(), *result, existence_read)
+                        .await;
+                }
+
                 Ok::<_, Error>(())
             })
             .collect::<FuturesUnordered<_>>()
@@ -412,7 +593,7 @@ impl StoreDriver for RedisStore {
             Bound::Unbounded => format!("{}*", self.key_prefix),
         };
         let client = self.get_client().await?;
-        let mut scan_stream = client.scan(pattern, Some(self.scan_count), None);
+        let mut scan_stream = self.scan(client, pattern);
         let mut iterations = 0;
         'outer: while let Some(mut page) = scan_stream.try_next().await? {
             if let Some(keys) = page.take_results() {
@@ -453,10 +634,11 @@ impl StoreDriver for RedisStore {
         // The TL;DR is that if we're in cluster mode and the names hash differently, we can't use request
         // pipelining. By using these braces, we tell redis to only hash the part of the temporary key that's
         // identical to the final key -- so they will always hash to the same node.
+        // If the final key has a hash tag already, the temporary key needs the same one.
         let temp_key = format!(
             "temp-{}-{{{}}}",
             (self.temp_name_generator_fn)(),
-            &final_key
+            hash_tag(&final_key)
         );
 
         if is_zero_digest(key.borrow()) {
@@ -537,6 +719,14 @@ impl StoreDriver for RedisStore {
             .rename::<(), _, _>(&temp_key, final_key.as_ref())
             .await
             .err_tip(|| "While queueing key rename in RedisStore::update()")?;
+        if let Some(existence_cache) = &self.existence_cache {
+            existence_cache.forget(&final_key).await;
+        }
+        if self.key_ttl_s != 0 {
+            self.expire_key(client, final_key.as_ref())
+                .await
+                .err_tip(|| "In RedisStore::update")?;
+        }
 
         // If we have a publish channel configured, send a notice that the key has been set.
         if let Some(pub_sub_channel) = &self.pub_sub_channel {
@@ -637,6 +827,17 @@ impl StoreDriver for RedisStore {
             }
         }
 
+        // Keep keys which are read from expiring, like stores evicting the
+        // least recently used objects do.
+        if self.refresh_ttl_on_read && self.key_ttl_s != 0 {
+            if let Err(err) = self.expire_key(client, encoded_key).await {
+                warn!(
+                    ?err,
+                    "Failed to refresh key expiration in RedisStore::get_part"
+                );
+            }
+        }
+
         writer
             .send_eof()
             .err_tip(|| "Failed to write EOF in redis store get_part")
@@ -698,7 +899,9 @@ const INDEX_TTL_S: u64 = 60 * 60 * 24; // 24 hours.
 ///   KEYS[1]: The key where the version is stored.
 ///   ARGV[1]: The expected version.
 ///   ARGV[2]: The new data.
-///   ARGV[3*]: Key-value pairs of additional data to include.
+///   ARGV[3]: The channel to publish the key to once set, or empty.
+///   ARGV[4]: The number of milliseconds after which the key expires, or 0.
+///   ARGV[5*]: Key-value pairs of additional data to include.
 /// Returns:
 ///   The new version if the version matches. nil is returned if the
 ///   value was not set.
@@ -707,6 +910,8 @@ const LUA_VERSION_SET_SCRIPT: &str = formatcp!(
 local key = KEYS[1]
 local expected_version = tonumber(ARGV[1])
 local new_data = ARGV[2]
+local pub_sub_channel = ARGV[3]
+local expiry_ms = tonumber(ARGV[4])
 local new_version = redis.call('HINCRBY', key, '{VERSION_FIELD_NAME}', 1)
 local i
 local indexes = {{}}
@@ -715,10 +920,10 @@ if new_version-1 ~= expected_version then
     redis.call('HINCRBY', key, '{VERSION_FIELD_NAME}', -1)
     return {{ 0, new_version-1 }}
 end
--- Skip first 2 argvs, as they are known inputs.
+-- Skip first 4 argvs, as they are known inputs.
 -- Remember: Lua is 1-indexed.
-for i=3, #ARGV do
-    indexes[i-2] = ARGV[i]
+for i=5, #ARGV do
+    indexes[i-4] = ARGV[i]
 end
 
 -- In testing we witnessed redis sometimes not update our FT indexes
@@ -726,17 +931,50 @@ end
 -- them again it works and reduces risk significantly.
 redis.call('DEL', key)
 redis.call('HSET', key, '{DATA_FIELD_NAME}', new_data, '{VERSION_FIELD_NAME}', new_version, unpack(indexes))
+if expiry_ms > 0 then
+    redis.call('PEXPIRE', key, expiry_ms)
+end
+
+if pub_sub_channel ~= '' then
+    redis.call('PUBLISH', pub_sub_channel, key)
+end
 
 return {{ 1, new_version }}
 "
 );
 
+/// Lua script to set the fields of an unversioned key.
+/// Args:
+///   KEYS[1]: The key to set.
+///   ARGV[1]: The channel to publish the key to once set, or empty.
+///   ARGV[2]: The number of milliseconds after which the key expires, or 0.
+///   ARGV[3*]: Key-value pairs of the fields to set.
+/// Returns:
+///   The number of fields added.
+const LUA_SET_AND_PUBLISH_SCRIPT: &str = r"
+local key = KEYS[1]
+local pub_sub_channel = ARGV[1]
+local expiry_ms = tonumber(ARGV[2])
+
+-- Skip the first 2 argvs, as they are known inputs.
+local added = redis.call('HSET', key, unpack(ARGV, 3))
+if expiry_ms > 0 then
+    redis.call('PEXPIRE', key, expiry_ms)
+end
+
+if pub_sub_channel ~= '' then
+    redis.call('PUBLISH', pub_sub_channel, key)
+end
+
+return added
+";
+
 /// This is the output of the calculations below hardcoded into the executable.
 const FINGERPRINT_CREATE_INDEX_HEX: &str = "3e762c15";
 
 #[cfg(test)]
 mod test {
-    use super::FINGERPRINT_CREATE_INDEX_HEX;
+    use super::{FINGERPRINT_CREATE_INDEX_HEX, hash_tag};
 
     /// String of the `FT.CREATE` command used to create the index template.
     const CREATE_INDEX_TEMPLATE: &str = "FT.CREATE {} ON HASH PREFIX 1 {} NOOFFSETS NOHL NOFIELDS NOFREQS SCHEMA {} TAG CASESENSITIVE SORTABLE";
@@ -776,6 +1014,15 @@ mod test {
             FINGERPRINT_CREATE_INDEX_HEX,
         );
     }
+
+    #[test]
+    fn test_hash_tag() {
+        assert_eq!(hash_tag("prefix-key"), "prefix-key");
+        assert_eq!(hash_tag("prefix-{tag}-key"), "tag");
+        assert_eq!(hash_tag("prefix-{tag}-{other}"), "tag");
+        assert_eq!(hash_tag("prefix-{}-{tag}"), "prefix-{}-{tag}");
+        assert_eq!(hash_tag("prefix-{tag"), "prefix-{tag");
+    }
 }
 
 /// Get the name of the index to create for the given field.
@@ -1080,14 +1327,25 @@ impl SchedulerStore for RedisStore {
         let maybe_index = data.get_indexes().err_tip(|| {
             format!("Err getting index in RedisStore::update_data::versioned for {key:?}")
         })?;
+        // The scripts publish the key themselves, so subscribers are
+        // notified in the same round trip as the update.
+        let pub_sub_channel = Bytes::from(self.pub_sub_channel.clone().unwrap_or_default());
+        // Redis rejects an expiry of 0, so anything shorter than a
+        // millisecond is rounded up.
+        let expiry_ms = Bytes::from(format!(
+            "{}",
+            data.expiry().map_or(0, |expiry| expiry.as_millis().max(1))
+        ));
         if <T as SchedulerStoreKeyProvider>::Versioned::VALUE {
             let current_version = data.current_version();
             let data = data.try_into_bytes().err_tip(|| {
                 format!("Could not convert value to bytes in RedisStore::update_data::versioned for {key:?}")
             })?;
-            let mut argv = Vec::with_capacity(3 + maybe_index.len() * 2);
+            let mut argv = Vec::with_capacity(4 + maybe_index.len() * 2);
             argv.push(Bytes::from(format!("{current_version}")));
             argv.push(data);
+            argv.push(pub_sub_channel);
+            argv.push(expiry_ms);
             for (name, value) in maybe_index {
                 argv.push(Bytes::from_static(name.as_bytes()));
                 argv.push(value);
@@ -1103,29 +1361,25 @@ impl SchedulerStore for RedisStore {
                 );
                 return Ok(None);
             }
-            // If we have a publish channel configured, send a notice that the key has been set.
-            if let Some(pub_sub_channel) = &self.pub_sub_channel {
-                return Ok(client.publish(pub_sub_channel, key.as_ref()).await?);
-            }
             Ok(Some(new_version))
         } else {
             let data = data.try_into_bytes().err_tip(|| {
                 format!("Could not convert value to bytes in RedisStore::update_data::noversion for {key:?}")
             })?;
-            let mut fields = RedisMap::new();
-            fields.reserve(1 + maybe_index.len());
-            fields.insert(DATA_FIELD_NAME.into(), data.into());
+            let mut argv = Vec::with_capacity(4 + maybe_index.len() * 2);
+            argv.push(pub_sub_channel);
+            argv.push(expiry_ms);
+            argv.push(Bytes::from_static(DATA_FIELD_NAME.as_bytes()));
+            argv.push(data);
             for (name, value) in maybe_index {
-                fields.insert(name.into(), value.into());
+                argv.push(Bytes::from_static(name.as_bytes()));
+                argv.push(value);
             }
-            client
-                .hset::<(), _, _>(key.as_ref(), fields)
+            let _fields_added: i64 = self
+                .set_and_publish_script
+                .evalsha_with_reload(client, vec![key.as_ref()], argv)
                 .await
                 .err_tip(|| format!("In RedisStore::update_data::noversion for {key:?}"))?;
-            // If we have a publish channel configured, send a notice that the key has been set.
-            if let Some(pub_sub_channel) = &self.pub_sub_channel {
-                return Ok(client.publish(pub_sub_channel, key.as_ref()).await?);
-            }
             Ok(Some(0)) // Always use "0" version since this is not a versioned request.
         }
     }
@@ -1273,6 +1527,57 @@ impl SchedulerStore for RedisStore {
         }))
     }
 
+    async fn count_by_index_prefix<K>(&self, index: K) -> Result<usize, Error>
+    where
+        K: SchedulerIndexProvider + SchedulerStoreDecodeTo + Send,
+    {
+        let query = {
+            let index_value = index.index_value();
+            let sanitized_field = try_sanitize(index_value.as_ref()).err_tip(|| {
+                format!("In RedisStore::count_by_index_prefix::try_sanitize - {index_value:?}")
+            })?;
+            format!("@{}:{{ {} }}", K::INDEX_NAME, sanitized_field)
+        };
+        // With `LIMIT 0 0` only the number of matches is returned.
+        let search_result = self
+            .client_pool
+            .next()
+            .ft_search::<RedisValue, _, _>(
+                format!(
+                    "{}",
+                    get_index_name!(K::KEY_PREFIX, K::INDEX_NAME, K::MAYBE_SORT_KEY)
+                ),
+                query,
+                FtSearchOptions {
+                    nocontent: true,
+                    limit: Some((0, 0)),
+                    ..Default::default()
+                },
+            )
+            .await;
+        match search_result {
+            Ok(value) => {
+                let total = value
+                    .into_array()
+                    .first()
+                    .and_then(RedisValue::as_u64)
+                    .err_tip(
+                        || "Expected the number of matches in RedisStore::count_by_index_prefix",
+                    )?;
+                usize::try_from(total).err_tip(|| "In RedisStore::count_by_index_prefix")
+            }
+            // The index is only created by `search_by_index_prefix`, so fall
+            // back to it if the index doesn't exist yet.
+            Err(_) => self
+                .search_by_index_prefix(index)
+                .await
+                .err_tip(|| "In RedisStore::count_by_index_prefix")?
+                .try_fold(0, |count, _| async move { Ok(count + 1) })
+                .await
+                .err_tip(|| "In RedisStore::count_by_index_prefix"),
+        }
+    }
+
     async fn get_and_decode<K>(
         &self,
         key: K,
@@ -1300,4 +1605,40 @@ impl SchedulerStore for RedisStore {
             || format!("In RedisStore::get_with_version::notversioned::decode {key}"),
         )?))
     }
+
+    async fn delete_data<K>(&self, key: K) -> Result<bool, Error>
+    where
+        K: SchedulerStoreKeyProvider + Send,
+    {
+        let key = key.get_key();
+        let key = self.encode_key(&key);
+        let client = self.get_client().await?;
+        let deleted_keys = client
+            .del::<u64, _>(key.as_ref())
+            .await
+            .err_tip(|| format!("In RedisStore::delete_data for {key}"))?;
+        Ok(deleted_keys > 0)
+    }
+
+    async fn list_keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let pattern = format!("{}{prefix}*", self.key_prefix);
+        let client = self.get_client().await?;
+        let mut scan_stream = self.scan(client, pattern);
+        let mut keys = Vec::new();
+        while let Some(mut page) = scan_stream
+            .try_next()
+            .await
+            .err_tip(|| "In RedisStore::list_keys_with_prefix")?
+        {
+            if let Some(page_keys) = page.take_results() {
+                keys.extend(page_keys.iter().filter_map(|key| {
+                    key.as_str()?
+                        .strip_prefix(&self.key_prefix)
+                        .map(ToString::to_string)
+                }));
+            }
+            page.next();
+        }
+        Ok(keys)
+    }
 }
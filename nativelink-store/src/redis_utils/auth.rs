@@ -0,0 +1,232 @@
+// Copyright 2025 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::time::Duration;
+use std::fs::File;
+use std::io::BufReader;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use aws_config::default_provider::credentials::DefaultCredentialsChain;
+use aws_config::provider_config::ProviderConfig;
+use aws_credential_types::provider::{ProvideCredentials, SharedCredentialsProvider};
+use aws_sdk_s3::config::Region;
+use aws_sigv4::http_request::{
+    SignableBody, SignableRequest, SignatureLocation, SigningSettings, sign,
+};
+use aws_sigv4::sign::v4;
+use aws_smithy_runtime_api::client::identity::Identity;
+use fred::error::{Error as RedisError, ErrorKind as RedisErrorKind};
+use fred::types::config::{CredentialProvider, Server, TlsConnector};
+use hyper_rustls::ConfigBuilderExt;
+use nativelink_config::stores::{ClientTlsConfig, CommonObjectSpec, ElastiCacheIamAuthSpec};
+use nativelink_error::{Code, Error, ResultExt, make_err};
+use rustls::{ClientConfig, RootCertStore};
+use rustls_pemfile::{certs, private_key};
+use tokio::sync::OnceCell;
+use tracing::warn;
+
+use crate::common_s3_utils::TlsClient;
+
+/// `ElastiCache` rejects IAM authentication tokens older than this.
+const IAM_TOKEN_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+/// How often connections authenticate again with a new IAM token. This
+/// must be well within `IAM_TOKEN_LIFETIME`.
+const IAM_REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Builds the TLS connector of the connections to Redis from `config`.
+pub(crate) fn tls_connector(config: &ClientTlsConfig) -> Result<TlsConnector, Error> {
+    let builder = ClientConfig::builder();
+    let builder = if config.use_native_roots == Some(true) {
+        if config.ca_file.is_some() {
+            warn!("Native root certificates are being used, the CA certificate file is ignored");
+        }
+        builder
+            .with_native_roots()
+            .err_tip(|| "Failed to load native root certificates for redis")?
+    } else {
+        let Some(ca_file) = &config.ca_file else {
+            return Err(make_err!(
+                Code::InvalidArgument,
+                "CA certificate must be provided if not using native root certificates"
+            ));
+        };
+        let mut root_store = RootCertStore::empty();
+        for cert in certs(&mut BufReader::new(
+            File::open(ca_file).err_tip(|| format!("Failed to open CA file {ca_file}"))?,
+        )) {
+            let cert = cert.err_tip(|| format!("Failed to parse certificate in {ca_file}"))?;
+            root_store.add(cert).map_err(|e| {
+                make_err!(
+                    Code::InvalidArgument,
+                    "Failed to add certificate in {ca_file} to root store: {e:?}"
+                )
+            })?;
+        }
+        builder.with_root_certificates(root_store)
+    };
+
+    let client_config = match (&config.cert_file, &config.key_file) {
+        (Some(cert_file), Some(key_file)) => {
+            let cert_chain = certs(&mut BufReader::new(
+                File::open(cert_file)
+                    .err_tip(|| format!("Failed to open certificate file {cert_file}"))?,
+            ))
+            .collect::<Result<Vec<_>, _>>()
+            .err_tip(|| format!("Failed to parse certificates in {cert_file}"))?;
+            let key = private_key(&mut BufReader::new(
+                File::open(key_file).err_tip(|| format!("Failed to open key file {key_file}"))?,
+            ))
+            .err_tip(|| format!("Failed to parse private key in {key_file}"))?
+            .ok_or_else(|| {
+                make_err!(Code::InvalidArgument, "No private key found in {key_file}")
+            })?;
+            builder
+                .with_client_auth_cert(cert_chain, key)
+                .map_err(|e| make_err!(Code::InvalidArgument, "Invalid client certificate: {e}"))?
+        }
+        (None, None) => builder.with_no_client_auth(),
+        (Some(_), None) => {
+            return Err(make_err!(
+                Code::InvalidArgument,
+                "Client certificate specified, but no key"
+            ));
+        }
+        (None, Some(_)) => {
+            return Err(make_err!(
+                Code::InvalidArgument,
+                "Client key specified, but no certificate"
+            ));
+        }
+    };
+    Ok(TlsConnector::from(client_config))
+}
+
+/// Authenticates connections to an `ElastiCache` cache with IAM. The
+/// password is a short lived token, which is a `connect` request to the
+/// cache presigned with the AWS credentials.
+#[derive(Debug)]
+pub(crate) struct ElastiCacheIamCredentials {
+    spec: ElastiCacheIamAuthSpec,
+    username: String,
+    credentials_provider: OnceCell<SharedCredentialsProvider>,
+}
+
+impl ElastiCacheIamCredentials {
+    pub(crate) fn new(spec: ElastiCacheIamAuthSpec, username: String) -> Self {
+        Self {
+            spec,
+            username,
+            credentials_provider: OnceCell::new(),
+        }
+    }
+
+    async fn credentials_provider(&self) -> &SharedCredentialsProvider {
+        self.credentials_provider
+            .get_or_init(|| async {
+                // Instance metadata is only served over plain HTTP.
+                let http_client = TlsClient::new(&CommonObjectSpec {
+                    insecure_allow_http: true,
+                    ..Default::default()
+                });
+                SharedCredentialsProvider::new(
+                    DefaultCredentialsChain::builder()
+                        .configure(
+                            ProviderConfig::without_region()
+                                .with_region(Some(Region::new(self.spec.region.clone())))
+                                .with_http_client(http_client),
+                        )
+                        .build()
+                        .await,
+                )
+            })
+            .await
+    }
+
+    async fn generate_token(&self) -> Result<String, Error> {
+        let credentials = self
+            .credentials_provider()
+            .await
+            .provide_credentials()
+            .await
+            .map_err(|e| {
+                make_err!(
+                    Code::Unauthenticated,
+                    "Failed to load AWS credentials for ElastiCache IAM auth: {e}"
+                )
+            })?;
+        let identity = Identity::from(credentials);
+        let mut settings = SigningSettings::default();
+        settings.signature_location = SignatureLocation::QueryParams;
+        settings.expires_in = Some(IAM_TOKEN_LIFETIME);
+        let signing_params = v4::SigningParams::builder()
+            .identity(&identity)
+            .region(&self.spec.region)
+            .name("elasticache")
+            .time(SystemTime::now())
+            .settings(settings)
+            .build()
+            .map_err(|e| make_err!(Code::Internal, "Invalid ElastiCache signing params: {e}"))?
+            .into();
+
+        let mut url = format!(
+            "http://{}/?Action=connect&User={}",
+            self.spec.cache_name, self.username
+        );
+        if self.spec.serverless {
+            url.push_str("&ResourceType=ServerlessCache");
+        }
+        let signable_request = SignableRequest::new(
+            "GET",
+            url.as_str(),
+            core::iter::empty(),
+            SignableBody::Bytes(&[]),
+        )
+        .map_err(|e| make_err!(Code::Internal, "Invalid ElastiCache connect request: {e}"))?;
+        let (instructions, _signature) = sign(signable_request, &signing_params)
+            .map_err(|e| make_err!(Code::Internal, "Failed to sign ElastiCache token: {e}"))?
+            .into_parts();
+        let mut request = http::Request::builder()
+            .uri(url.as_str())
+            .body(())
+            .map_err(|e| make_err!(Code::Internal, "Invalid ElastiCache connect request: {e}"))?;
+        instructions.apply_to_request_http1x(&mut request);
+        Ok(request
+            .uri()
+            .to_string()
+            .trim_start_matches("http://")
+            .to_string())
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for ElastiCacheIamCredentials {
+    async fn fetch(
+        &self,
+        _server: Option<&Server>,
+    ) -> Result<(Option<String>, Option<String>), RedisError> {
+        let token = self.generate_token().await.map_err(|err| {
+            RedisError::new(
+                RedisErrorKind::Auth,
+                format!("Failed to generate ElastiCache IAM token: {err}"),
+            )
+        })?;
+        Ok((Some(self.username.clone()), Some(token)))
+    }
+
+    fn refresh_interval(&self) -> Option<Duration> {
+        Some(IAM_REFRESH_INTERVAL)
+    }
+}
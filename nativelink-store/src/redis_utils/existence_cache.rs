@@ -0,0 +1,275 @@
+// Copyright 2025 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+use std::sync::{Arc, Weak};
+use std::time::SystemTime;
+
+use fred::interfaces::{ClientLike, EventInterface, TrackingInterface};
+use fred::prelude::Client;
+use fred::types::Key as RedisKey;
+use nativelink_config::stores::{EvictionPolicy, RedisExistenceCacheSpec};
+use nativelink_error::{Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::evicting_map::{EvictingMap, LenEntry};
+use nativelink_util::spawn;
+use nativelink_util::task::JoinHandleDropGuard;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::sleep;
+use tracing::{error, warn};
+
+/// Default maximum number of cached existence check results.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_ENTRIES: u64 = 1_000_000;
+
+/// Result of an existence check made while the connection was in `epoch`.
+#[derive(Clone, Copy, Debug)]
+struct CachedExistence {
+    size: Option<u64>,
+    epoch: u64,
+}
+
+impl LenEntry for CachedExistence {
+    #[inline]
+    fn len(&self) -> u64 {
+        self.size.unwrap_or(0)
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, MetricsComponent)]
+struct CacheState {
+    #[metric(group = "entries")]
+    entries: EvictingMap<String, String, CachedExistence, SystemTime>,
+    // Whether Redis tracks the keys read on the connection, so cached
+    // results are invalidated when their keys change.
+    tracking: AtomicBool,
+    // Incremented whenever all cached results become stale, which is when
+    // the connection is reestablished, as Redis forgets the tracked keys
+    // and invalidations may have been missed, or the database is flushed.
+    #[metric(help = "Number of times all cached existence results were dropped")]
+    epoch: AtomicU64,
+    // Incremented on every invalidation, so a result read while its key
+    // was invalidated isn't cached.
+    generation: AtomicU64,
+    #[metric(help = "Number of existence checks answered from the cache")]
+    hits: AtomicU64,
+    #[metric(help = "Number of keys invalidated by Redis")]
+    invalidations: AtomicU64,
+}
+
+impl CacheState {
+    fn reset(&self) {
+        self.tracking.store(false, Ordering::Release);
+        self.epoch.fetch_add(1, Ordering::AcqRel);
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+
+    async fn invalidate(&self, keys: Vec<RedisKey>) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        // Redis invalidates all keys at once when the database is flushed.
+        if keys.is_empty() {
+            self.epoch.fetch_add(1, Ordering::AcqRel);
+            return;
+        }
+        self.invalidations
+            .fetch_add(keys.len() as u64, Ordering::Relaxed);
+        for key in keys {
+            if let Some(key) = key.as_str() {
+                self.entries.remove(&key.to_owned()).await;
+            }
+        }
+    }
+}
+
+/// The state of the cache when the existence of a key was read, so its
+/// result is only cached if the key wasn't invalidated in the meantime.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ExistenceRead {
+    epoch: u64,
+    generation: u64,
+}
+
+/// Caches the results of existence checks, which are sent on a dedicated
+/// RESP3 connection with client tracking enabled. Redis then notifies the
+/// connection whenever one of the checked keys changes, and the result of
+/// the key is dropped from the cache.
+#[derive(Debug, MetricsComponent)]
+pub(crate) struct ExistenceCache {
+    client: Client,
+    #[metric]
+    state: Arc<CacheState>,
+    _tracking_spawn: JoinHandleDropGuard<()>,
+}
+
+impl ExistenceCache {
+    /// Creates the cache of the existence checks sent on `client`, which
+    /// must use RESP3.
+    pub(crate) fn new(client: Client, spec: &RedisExistenceCacheSpec) -> Self {
+        let eviction_policy = EvictionPolicy {
+            max_count: if spec.max_entries == 0 {
+                DEFAULT_MAX_ENTRIES
+            } else {
+                spec.max_entries
+            },
+            ..Default::default()
+        };
+        let state = Arc::new(CacheState {
+            entries: EvictingMap::new(&eviction_policy, SystemTime::now()),
+            tracking: AtomicBool::new(false),
+            epoch: AtomicU64::new(0),
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        });
+        client.connect();
+        let tracking_client = client.clone();
+        let state_weak = Arc::downgrade(&state);
+        Self {
+            client,
+            state,
+            _tracking_spawn: spawn!(
+                "redis_existence_cache_spawn",
+                track_keys(tracking_client, state_weak)
+            ),
+        }
+    }
+
+    /// Returns the client to send existence checks on, once connected.
+    pub(crate) async fn client(&self) -> Result<&Client, Error> {
+        self.client
+            .wait_for_connect()
+            .await
+            .err_tip(|| "While connecting redis existence cache client")?;
+        Ok(&self.client)
+    }
+
+    /// Returns the cached result of the existence check of `encoded_key`.
+    pub(crate) async fn get(&self, encoded_key: &str) -> Option<Option<u64>> {
+        if !self.state.tracking.load(Ordering::Acquire) {
+            return None;
+        }
+        let cached = self.state.entries.get(&encoded_key.to_owned()).await?;
+        if cached.epoch != self.state.epoch.load(Ordering::Acquire) {
+            return None;
+        }
+        self.state.hits.fetch_add(1, Ordering::Relaxed);
+        Some(cached.size)
+    }
+
+    /// Must be called before the existence of a key is read, to cache the
+    /// result with `insert` afterwards. Returns `None` if results can't be
+    /// cached, because Redis doesn't track the keys read.
+    pub(crate) fn start_read(&self) -> Option<ExistenceRead> {
+        let read = ExistenceRead {
+            epoch: self.state.epoch.load(Ordering::Acquire),
+            generation: self.state.generation.load(Ordering::Acquire),
+        };
+        self.state.tracking.load(Ordering::Acquire).then_some(read)
+    }
+
+    /// Caches the result of the existence check of `encoded_key` started
+    /// with `read`.
+    pub(crate) async fn insert(&self, encoded_key: String, size: Option<u64>, read: ExistenceRead) {
+        self.state
+            .entries
+            .insert(
+                encoded_key.clone(),
+                CachedExistence {
+                    size,
+                    epoch: read.epoch,
+                },
+            )
+            .await;
+        // An invalidation which raced with the check might have been
+        // handled before the result was inserted.
+        if self.state.generation.load(Ordering::Acquire) != read.generation {
+            self.state.entries.remove(&encoded_key).await;
+        }
+    }
+
+    /// Drops the cached result of `encoded_key`, which was written by this
+    /// store. Redis notifies the cache of the write too, but only after the
+    /// write completed, so checks right after the write might still see
+    /// the cached result otherwise.
+    pub(crate) async fn forget(&self, encoded_key: &str) {
+        self.state.generation.fetch_add(1, Ordering::AcqRel);
+        self.state.entries.remove(&encoded_key.to_owned()).await;
+    }
+}
+
+/// Enables client tracking on every connection of `client`, and drops the
+/// results of the keys Redis invalidates from the cache.
+async fn track_keys(client: Client, state_weak: Weak<CacheState>) {
+    let mut invalidation_rx = client.invalidation_rx();
+    let mut reconnect_rx = client.reconnect_rx();
+    loop {
+        // Redis forgets the tracked keys when the connection drops, so
+        // every connection starts with stale results.
+        let Some(state) = state_weak.upgrade() else {
+            return;
+        };
+        state.reset();
+        drop(state);
+
+        let tracking = async {
+            client.wait_for_connect().await?;
+            client
+                .start_tracking(Vec::<String>::new(), false, false, false, false)
+                .await
+        };
+        if let Err(e) = tracking.await {
+            error!("Error enabling redis client tracking, existence checks aren't cached - {e}");
+            // Sleep for a small amount of time to ensure we don't retry too quickly.
+            sleep(Duration::from_secs(1)).await;
+            continue;
+        }
+        let Some(state) = state_weak.upgrade() else {
+            return;
+        };
+        state.tracking.store(true, Ordering::Release);
+        drop(state);
+
+        loop {
+            select! {
+                invalidation = invalidation_rx.recv() => match invalidation {
+                    Ok(invalidation) => {
+                        let Some(state) = state_weak.upgrade() else {
+                            return;
+                        };
+                        state.invalidate(invalidation.keys).await;
+                    }
+                    Err(RecvError::Lagged(_)) => {
+                        warn!("Missed redis invalidations, dropping all cached existence results");
+                        break;
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+                reconnect = reconnect_rx.recv() => match reconnect {
+                    Ok(_) | Err(RecvError::Lagged(_)) => {
+                        warn!("Redis reconnected, dropping all cached existence results");
+                        break;
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+            }
+        }
+    }
+}
@@ -12,5 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod auth;
+mod existence_cache;
 mod ft_aggregate;
+pub(crate) use auth::{ElastiCacheIamCredentials, tls_connector};
+pub(crate) use existence_cache::ExistenceCache;
 pub(crate) use ft_aggregate::ft_aggregate;
@@ -12,98 +12,116 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use core::cell::UnsafeCell;
 use core::pin::Pin;
+use core::ptr;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::ffi::OsString;
 use std::sync::{Arc, Mutex, Weak};
 
 use async_trait::async_trait;
 use nativelink_config::stores::RefSpec;
-use nativelink_error::{Code, Error, ResultExt, make_err, make_input_err};
+use nativelink_error::{Error, ResultExt, make_input_err};
 use nativelink_metric::MetricsComponent;
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::fs;
 use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
 use nativelink_util::store_trait::{
-    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo,
+    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, StoreOptimizations, UploadSizeInfo,
 };
-use tracing::error;
+use parking_lot::RwLock;
+use tracing::info;
 
 use crate::store_manager::StoreManager;
 
-#[repr(C, align(8))]
-#[derive(Debug)]
-struct AlignedStoreCell(UnsafeCell<Option<Store>>);
-
 #[derive(Debug)]
 struct StoreReference {
-    cell: AlignedStoreCell,
-    mux: Mutex<()>,
+    // The store the name currently resolves to, None until it was resolved.
+    current: RwLock<Option<Store>>,
+    // `StoreManager` generation `current` was resolved at.
+    generation: AtomicU64,
 }
 
-unsafe impl Sync for StoreReference {}
-
 #[derive(Debug, MetricsComponent)]
 pub struct RefStore {
     #[metric(help = "The store we are referencing")]
     name: String,
     store_manager: Weak<StoreManager>,
+    // Generation of the `StoreManager`, shared with it so that checking for
+    // changes doesn't need to upgrade `store_manager`.
+    store_manager_generation: Arc<AtomicU64>,
     inner: StoreReference,
     remove_callbacks: Mutex<Vec<Arc<Box<dyn RemoveItemCallback>>>>,
 }
 
 impl RefStore {
     pub fn new(spec: &RefSpec, store_manager: Weak<StoreManager>) -> Arc<Self> {
+        let store_manager_generation = store_manager
+            .upgrade()
+            .map_or_else(Arc::default, |store_manager| {
+                store_manager.generation_counter()
+            });
         Arc::new(Self {
             name: spec.name.clone(),
             store_manager,
+            store_manager_generation,
             inner: StoreReference {
-                mux: Mutex::new(()),
-                cell: AlignedStoreCell(UnsafeCell::new(None)),
+                current: RwLock::new(None),
+                generation: AtomicU64::new(0),
             },
             remove_callbacks: Mutex::new(vec![]),
         })
     }
 
-    // This will get the store or populate it if needed. It is designed to be quite fast on the
-    // common path, but slow on the uncommon path. It does use some unsafe functions because we
-    // wanted it to be fast. It is technically possible on some platforms for this function to
-    // create a data race here is the reason I do not believe it is an issue:
-    // 1. It would only happen on the very first call of the function (after first call we are safe)
-    // 2. It should only happen on platforms that are < 64 bit address space
-    // 3. It is likely that the internals of how Option work protect us anyway.
+    // This will get the store or resolve it again if the stores in the `StoreManager` changed
+    // since it was last resolved. It is designed to be quite fast on the common path, which
+    // only compares the generations and clones the store, but slow on the uncommon path.
     #[inline]
-    fn get_store(&self) -> Result<&Store, Error> {
-        let ref_store = self.inner.cell.0.get();
-        unsafe {
-            if let Some(ref store) = *ref_store {
-                return Ok(store);
+    fn get_store(&self) -> Result<Store, Error> {
+        let generation = self.store_manager_generation.load(Ordering::Acquire);
+        if self.inner.generation.load(Ordering::Acquire) == generation {
+            if let Some(store) = self.inner.current.read().as_ref() {
+                return Ok(store.clone());
             }
         }
-        // This should protect us against multiple writers writing the same location at the same
-        // time.
-        let _lock = self.inner.mux.lock().map_err(|e| {
-            make_err!(
-                Code::Internal,
-                "Failed to lock mutex in ref_store : {:?}",
-                e
-            )
-        })?;
-        let store_manager = self
-            .store_manager
-            .upgrade()
-            .err_tip(|| "Store manager is gone")?;
-        if let Some(store) = store_manager.get_store(&self.name) {
+        // This should protect us against multiple writers resolving the store at the same time.
+        let mut current = self.inner.current.write();
+        let generation = self.store_manager_generation.load(Ordering::Acquire);
+        if self.inner.generation.load(Ordering::Acquire) == generation {
+            if let Some(store) = current.as_ref() {
+                return Ok(store.clone());
+            }
+        }
+        let Some(store_manager) = self.store_manager.upgrade() else {
+            // Keep serving the last resolved store during shutdown.
+            return current.clone().err_tip(|| "Store manager is gone");
+        };
+        let Some(store) = store_manager.get_store(&self.name) else {
+            // Release the store of a name that was removed.
+            *current = None;
+            return Err(make_input_err!(
+                "Failed to find store '{}' in StoreManager in RefStore",
+                self.name
+            ));
+        };
+        let is_current = current.as_ref().is_some_and(|current| {
+            ptr::addr_eq(current.as_store_driver(), store.as_store_driver())
+        });
+        if !is_current {
             for callback in self.remove_callbacks.lock().unwrap().iter() {
                 store.register_remove_callback(callback)?;
             }
-            unsafe {
-                *ref_store = Some(store);
-                return Ok((*ref_store).as_ref().unwrap());
+            if current.is_some() {
+                info!(
+                    name = self.name,
+                    "RefStore now references a different store"
+                );
             }
+            // The previous store is dropped once the requests still running
+            // on it finished.
+            *current = Some(store.clone());
         }
-        Err(make_input_err!(
-            "Failed to find store '{}' in StoreManager in RefStore",
-            self.name
-        ))
+        self.inner.generation.store(generation, Ordering::Release);
+        Ok(store)
     }
 }
 
@@ -138,14 +156,28 @@ impl StoreDriver for RefStore {
             .await
     }
 
-    fn inner_store(&self, key: Option<StoreKey>) -> &'_ dyn StoreDriver {
-        match self.get_store() {
-            Ok(store) => store.inner_store(key),
-            Err(err) => {
-                error!(?key, ?err, "Failed to get store for key",);
-                self
-            }
-        }
+    fn optimized_for(&self, optimization: StoreOptimizations) -> bool {
+        self.get_store()
+            .is_ok_and(|store| store.optimized_for(optimization))
+    }
+
+    async fn update_with_whole_file(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        path: OsString,
+        file: fs::FileSlot,
+        upload_size: UploadSizeInfo,
+    ) -> Result<Option<fs::FileSlot>, Error> {
+        self.get_store()?
+            .update_with_whole_file(key, path, file, upload_size)
+            .await
+    }
+
+    // The referenced store may be swapped for another one at any time, so
+    // references into it can't be handed out. Optimizations of the store are
+    // forwarded by `optimized_for()` and `update_with_whole_file()` instead.
+    fn inner_store(&self, _digest: Option<StoreKey>) -> &'_ dyn StoreDriver {
+        self
     }
 
     fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
@@ -161,12 +193,9 @@ impl StoreDriver for RefStore {
         callback: &Arc<Box<dyn RemoveItemCallback>>,
     ) -> Result<(), Error> {
         self.remove_callbacks.lock()?.push(callback.clone());
-        let ref_store = self.inner.cell.0.get();
-        unsafe {
-            if let Some(ref store) = *ref_store {
-                store.register_remove_callback(callback)?;
-            }
-        };
+        if let Some(store) = self.inner.current.read().as_ref() {
+            store.register_remove_callback(callback)?;
+        }
         Ok(())
     }
 }
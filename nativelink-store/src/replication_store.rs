@@ -0,0 +1,417 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use futures::join;
+use nativelink_config::stores::ReplicationSpec;
+use nativelink_error::{Code, Error, ResultExt, error_if, make_err};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
+use nativelink_util::buf_channel::{
+    DropCloserReadHalf, DropCloserWriteHalf, make_buf_channel_pair,
+};
+use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
+use nativelink_util::store_trait::{
+    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo,
+};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Default maximum number of objects waiting to be repaired.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_PENDING_REPAIRS: usize = 10_000;
+
+#[derive(Debug, Default, MetricsComponent)]
+struct ReplicaMetrics {
+    #[metric(help = "Number of reads served by this replica")]
+    read_count: AtomicU64,
+    #[metric(help = "Number of reads this replica failed, which moved on to the next replica")]
+    read_failures: AtomicU64,
+    #[metric(help = "Number of uploads which failed on this replica")]
+    write_failures: AtomicU64,
+    #[metric(help = "Number of objects copied into this replica because it was missing them")]
+    repair_count: AtomicU64,
+    #[metric(help = "Bytes copied into this replica because it was missing them")]
+    repaired_bytes: AtomicU64,
+}
+
+#[derive(Debug, MetricsComponent)]
+struct Replica {
+    #[metric(group = "store")]
+    store: Store,
+    #[metric]
+    metrics: ReplicaMetrics,
+}
+
+#[derive(Debug, MetricsComponent)]
+pub struct ReplicationStore {
+    #[metric(group = "replicas")]
+    replicas: Vec<Replica>,
+    #[metric(help = "Number of replicas an upload must succeed on")]
+    write_quorum: usize,
+    #[metric(help = "Number of objects not repaired because too many repairs were pending")]
+    dropped_repairs: AtomicU64,
+    repairs: mpsc::Sender<StoreKey<'static>>,
+}
+
+impl ReplicationStore {
+    pub fn new(spec: &ReplicationSpec, stores: Vec<Store>) -> Result<Arc<Self>, Error> {
+        error_if!(
+            spec.replicas.len() != stores.len(),
+            "Config replicas do not match stores length"
+        );
+        error_if!(
+            stores.is_empty(),
+            "ReplicationStore must have at least one replica"
+        );
+        error_if!(
+            spec.write_quorum > stores.len(),
+            "write_quorum of ReplicationStore can't exceed the number of replicas, got {} for {} replicas",
+            spec.write_quorum,
+            stores.len()
+        );
+        let write_quorum = if spec.write_quorum == 0 {
+            stores.len()
+        } else {
+            spec.write_quorum
+        };
+        let max_pending_repairs = if spec.max_pending_repairs == 0 {
+            DEFAULT_MAX_PENDING_REPAIRS
+        } else {
+            spec.max_pending_repairs
+        };
+        let (repairs, mut repairs_rx) = mpsc::channel(max_pending_repairs);
+        let store = Arc::new(Self {
+            replicas: stores
+                .into_iter()
+                .map(|store| Replica {
+                    store,
+                    metrics: ReplicaMetrics::default(),
+                })
+                .collect(),
+            write_quorum,
+            dropped_repairs: AtomicU64::new(0),
+            repairs,
+        });
+
+        let weak_store = Arc::downgrade(&store);
+        background_spawn!("replication_store_repairs", async move {
+            while let Some(key) = repairs_rx.recv().await {
+                let Some(store) = weak_store.upgrade() else {
+                    return;
+                };
+                if let Err(err) = store.repair(key.borrow()).await {
+                    warn!(?err, ?key, "Failed to repair object in replication store");
+                }
+            }
+        });
+        Ok(store)
+    }
+
+    pub fn replica_store(&self, index: usize) -> Option<&Store> {
+        self.replicas.get(index).map(|replica| &replica.store)
+    }
+
+    /// Queues `key` to be copied into the replicas missing it.
+    fn schedule_repair(&self, key: StoreKey<'_>) {
+        match self.repairs.try_send(key.into_owned()) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(key)) => {
+                self.dropped_repairs.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    ?key,
+                    "Too many pending repairs in replication store, dropping repair"
+                );
+            }
+            // The store is being dropped.
+            Err(mpsc::error::TrySendError::Closed(_)) => {}
+        }
+    }
+
+    /// Copies `key` into the replicas which don't have it from the first
+    /// replica which does. Replicas which fail to answer are skipped.
+    async fn repair(&self, key: StoreKey<'_>) -> Result<(), Error> {
+        let sizes = join_all(
+            self.replicas
+                .iter()
+                .map(|replica| replica.store.has(key.borrow())),
+        )
+        .await;
+        let Some((source_index, size)) =
+            sizes
+                .iter()
+                .enumerate()
+                .find_map(|(index, size)| match size {
+                    Ok(Some(size)) => Some((index, *size)),
+                    _ => None,
+                })
+        else {
+            return Err(make_err!(
+                Code::NotFound,
+                "No replica holds {} to repair the others from in replication store",
+                key.as_str()
+            ));
+        };
+        let source = &self.replicas[source_index].store;
+        for (replica, _) in self
+            .replicas
+            .iter()
+            .zip(&sizes)
+            .filter(|(_, size)| matches!(size, Ok(None)))
+        {
+            let (tx, rx) = make_buf_channel_pair();
+            let (get_res, update_res) = join!(
+                source.get(key.borrow(), tx),
+                replica
+                    .store
+                    .update(key.borrow(), rx, UploadSizeInfo::ExactSize(size))
+            );
+            get_res
+                .merge(update_res)
+                .err_tip(|| "Failed to copy object to replica in replication store")?;
+            replica.metrics.repair_count.fetch_add(1, Ordering::Relaxed);
+            replica
+                .metrics
+                .repaired_bytes
+                .fetch_add(size, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StoreDriver for ReplicationStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        results.fill(None);
+        let mut replica_err: Option<Error> = None;
+        for replica in &self.replicas {
+            let missing: Vec<usize> = results
+                .iter()
+                .enumerate()
+                .filter_map(|(index, result)| result.is_none().then_some(index))
+                .collect();
+            if missing.is_empty() {
+                return Ok(());
+            }
+            let missing_keys: Vec<StoreKey<'_>> =
+                missing.iter().map(|&index| keys[index].borrow()).collect();
+            match replica.store.has_many(&missing_keys).await {
+                Ok(replica_results) => {
+                    for (index, replica_result) in missing.into_iter().zip(replica_results) {
+                        results[index] = replica_result;
+                    }
+                }
+                // Another replica may have the objects.
+                Err(err) => replica_err = Error::merge_option(replica_err, Some(err)),
+            }
+        }
+        // Objects are only known to be missing if all replicas answered.
+        match replica_err {
+            Some(err) if results.iter().any(Option::is_none) => {
+                Err(err.append("Failed to run has() in replication store"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let (txs, rxs): (Vec<_>, Vec<_>) = self
+            .replicas
+            .iter()
+            .map(|_| make_buf_channel_pair())
+            .unzip();
+        let mut txs: Vec<Option<DropCloserWriteHalf>> = txs.into_iter().map(Some).collect();
+        let data_stream_fut = async move {
+            loop {
+                let buffer = reader
+                    .recv()
+                    .await
+                    .err_tip(|| "Failed to read buffer in replication store")?;
+                if buffer.is_empty() {
+                    for tx in txs.iter_mut().flatten() {
+                        // Failures show up in the result of the replica's update.
+                        drop(tx.send_eof());
+                    }
+                    return Result::<(), Error>::Ok(());
+                }
+                let sent = join_all(txs.iter_mut().map(|tx| {
+                    let buffer = buffer.clone();
+                    async move {
+                        match tx {
+                            Some(tx) => tx.send(buffer).await.is_ok(),
+                            None => false,
+                        }
+                    }
+                }))
+                .await;
+                // Replicas which stopped taking data failed their update,
+                // keep going with the others.
+                for (tx, sent) in txs.iter_mut().zip(sent) {
+                    if !sent {
+                        *tx = None;
+                    }
+                }
+                if txs.iter().all(Option::is_none) {
+                    return Err(make_err!(
+                        Code::Unavailable,
+                        "All replicas stopped taking data in replication store"
+                    ));
+                }
+            }
+        };
+        let updates_fut = join_all(
+            self.replicas
+                .iter()
+                .zip(rxs)
+                .map(|(replica, rx)| replica.store.update(key.borrow(), rx, size_info)),
+        );
+        let (data_stream_res, updates_res) = join!(data_stream_fut, updates_fut);
+
+        let mut stored = 0;
+        let mut replica_err: Option<Error> = None;
+        for (replica, update_res) in self.replicas.iter().zip(updates_res) {
+            match update_res {
+                Ok(()) => stored += 1,
+                Err(err) => {
+                    replica
+                        .metrics
+                        .write_failures
+                        .fetch_add(1, Ordering::Relaxed);
+                    replica_err = Error::merge_option(replica_err, Some(err));
+                }
+            }
+        }
+        if let Err(err) = data_stream_res {
+            return Err(match replica_err {
+                Some(replica_err) => err.merge(replica_err),
+                None => err,
+            });
+        }
+        if stored < self.write_quorum {
+            let err = make_err!(
+                Code::Unavailable,
+                "Upload stored in {stored} replicas, {} are required in replication store",
+                self.write_quorum
+            );
+            return Err(match replica_err {
+                Some(replica_err) => err.merge(replica_err),
+                None => err,
+            });
+        }
+        if let Some(err) = replica_err {
+            warn!(
+                ?err,
+                ?key,
+                "Upload failed on some replicas in replication store, repairing them"
+            );
+            self.schedule_repair(key);
+        }
+        Ok(())
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        let mut replica_err: Option<Error> = None;
+        for replica in &self.replicas {
+            match replica
+                .store
+                .get_part(key.borrow(), writer, offset, length)
+                .await
+            {
+                Ok(()) => {
+                    replica.metrics.read_count.fetch_add(1, Ordering::Relaxed);
+                    // An earlier replica failed to serve the object.
+                    if replica_err.is_some() {
+                        self.schedule_repair(key);
+                    }
+                    return Ok(());
+                }
+                // Nothing was sent yet, so the next replica can serve the read.
+                Err(err) if writer.get_bytes_written() == 0 => {
+                    replica
+                        .metrics
+                        .read_failures
+                        .fetch_add(1, Ordering::Relaxed);
+                    replica_err = Error::merge_option(replica_err, Some(err));
+                }
+                Err(err) => {
+                    return Err(err.append("Failed to read from replica in replication store"));
+                }
+            }
+        }
+        Err(replica_err
+            .expect("ReplicationStore has at least one replica")
+            .append(format!(
+                "No replica could serve {} in replication store",
+                key.as_str()
+            )))
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        let results = join_all(
+            self.replicas
+                .iter()
+                .map(|replica| replica.store.remove(key.borrow())),
+        )
+        .await;
+        let mut removed = false;
+        for result in results {
+            removed |= result.err_tip(|| "Failed to remove object in replication store")?;
+        }
+        Ok(removed)
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        for replica in &self.replicas {
+            replica.store.register_remove_callback(callback)?;
+        }
+        Ok(())
+    }
+}
+
+default_health_status_indicator!(ReplicationStore);
@@ -0,0 +1,366 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use nativelink_config::stores::RocksdbSpec;
+use nativelink_error::{Code, Error, ResultExt, make_err};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
+use nativelink_util::spawn_blocking;
+use nativelink_util::store_trait::{RemoveItemCallback, StoreDriver, StoreKey, UploadSizeInfo};
+use parking_lot::{Mutex, const_mutex};
+use rocksdb::compaction_filter::Decision;
+use rocksdb::{
+    BoundColumnFamily, ColumnFamilyDescriptor, DBWithThreadMode, DEFAULT_COLUMN_FAMILY_NAME,
+    MultiThreaded, Options,
+};
+
+use crate::cas_utils::is_zero_digest;
+
+// NOTE: If this changes update the comments in `stores.rs` to reflect
+// the new default.
+const DEFAULT_MIN_BLOB_SIZE: u64 = 64 * 1024;
+
+/// Every value starts with the time it expires at, in seconds since the
+/// epoch, or zero if it never expires. This lets one compaction filter
+/// serve all column families, whichever store opened the database first.
+const HEADER_SIZE: usize = 8;
+
+type Db = DBWithThreadMode<MultiThreaded>;
+
+/// Databases opened by this process, by path, so stores configured with
+/// the same path share one database.
+static DATABASES: Mutex<Vec<(String, Weak<Db>)>> = const_mutex(Vec::new());
+
+fn rocksdb_error(err: rocksdb::Error) -> Error {
+    make_err!(Code::Internal, "RocksDB error: {err}")
+}
+
+fn now_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn is_expired(value: &[u8], now: u64) -> bool {
+    let Some(header) = value.get(..HEADER_SIZE) else {
+        return false;
+    };
+    let expires_at = u64::from_le_bytes(header.try_into().unwrap());
+    expires_at != 0 && expires_at <= now
+}
+
+fn column_family_options() -> Options {
+    let mut options = Options::default();
+    options.set_compaction_filter("nativelink_expiry", |_level, _key, value| {
+        if is_expired(value, now_seconds()) {
+            Decision::Remove
+        } else {
+            Decision::Keep
+        }
+    });
+    options
+}
+
+/// Opens the database at `path`, or returns it if it is open already, and
+/// makes sure it has `column_family`.
+fn open_database(path: &str, column_family: &str) -> Result<Arc<Db>, Error> {
+    let mut databases = DATABASES.lock();
+    databases.retain(|(_, database)| database.strong_count() > 0);
+    let database = if let Some(database) = databases
+        .iter()
+        .find(|(database_path, _)| database_path == path)
+        .and_then(|(_, database)| database.upgrade())
+    {
+        database
+    } else {
+        let mut options = column_family_options();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        // All existing column families must be opened, not only ours.
+        let column_families = Db::list_cf(&options, path)
+            .unwrap_or_else(|_| vec![DEFAULT_COLUMN_FAMILY_NAME.to_string()]);
+        let database = Arc::new(
+            Db::open_cf_descriptors(
+                &options,
+                path,
+                column_families
+                    .into_iter()
+                    .map(|name| ColumnFamilyDescriptor::new(name, column_family_options())),
+            )
+            .map_err(rocksdb_error)
+            .err_tip(|| format!("Failed to open {path} in rocksdb store"))?,
+        );
+        databases.push((path.to_string(), Arc::downgrade(&database)));
+        database
+    };
+    if database.cf_handle(column_family).is_none() {
+        database
+            .create_cf(column_family, &column_family_options())
+            .map_err(rocksdb_error)
+            .err_tip(|| format!("Failed to create column family {column_family} in {path}"))?;
+    }
+    Ok(database)
+}
+
+#[derive(MetricsComponent)]
+pub struct RocksdbStore {
+    #[metric(help = "Path of the database")]
+    path: String,
+    #[metric(help = "Column family the items are kept in")]
+    column_family: String,
+    #[metric(help = "Seconds after which items expire, zero if they never do")]
+    max_age_seconds: u64,
+    database: Arc<Db>,
+}
+
+impl core::fmt::Debug for RocksdbStore {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RocksdbStore")
+            .field("path", &self.path)
+            .field("column_family", &self.column_family)
+            .field("max_age_seconds", &self.max_age_seconds)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RocksdbStore {
+    pub async fn new(spec: &RocksdbSpec) -> Result<Arc<Self>, Error> {
+        let path = spec.path.clone();
+        let column_family = if spec.column_family.is_empty() {
+            DEFAULT_COLUMN_FAMILY_NAME.to_string()
+        } else {
+            spec.column_family.clone()
+        };
+        let min_blob_size = if spec.min_blob_size == 0 {
+            DEFAULT_MIN_BLOB_SIZE
+        } else {
+            spec.min_blob_size
+        };
+        let max_age_seconds = spec.max_age_seconds;
+        let database = {
+            let path = path.clone();
+            let column_family = column_family.clone();
+            spawn_blocking!("rocksdb_store_open", move || {
+                let database = open_database(&path, &column_family)?;
+                let cf = database
+                    .cf_handle(&column_family)
+                    .err_tip(|| format!("Column family {column_family} missing in {path}"))?;
+                // Mutable options, so each store sharing a database can
+                // set its own.
+                let min_blob_size = min_blob_size.to_string();
+                let periodic_compaction_seconds = max_age_seconds.to_string();
+                let mut mutable_options = vec![
+                    ("enable_blob_files", "true"),
+                    ("min_blob_size", min_blob_size.as_str()),
+                ];
+                if max_age_seconds != 0 {
+                    // Makes sure files holding only old items get compacted,
+                    // which runs the expiry filter over them.
+                    mutable_options.push((
+                        "periodic_compaction_seconds",
+                        periodic_compaction_seconds.as_str(),
+                    ));
+                }
+                database
+                    .set_options_cf(&cf, &mutable_options)
+                    .map_err(rocksdb_error)
+                    .err_tip(|| format!("Failed to set options of {column_family} in {path}"))?;
+                drop(cf);
+                Ok::<_, Error>(database)
+            })
+            .await
+            .map_err(|e| make_err!(Code::Internal, "Failed to open rocksdb store: {e:?}"))??
+        };
+        Ok(Arc::new(Self {
+            path,
+            column_family,
+            max_age_seconds,
+            database,
+        }))
+    }
+
+    /// Runs `f` on a blocking thread with the column family of this store,
+    /// as `RocksDB` calls block on disk IO.
+    async fn with_column_family<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Db, &Arc<BoundColumnFamily<'_>>) -> Result<T, Error> + Send + 'static,
+    {
+        let database = self.database.clone();
+        let column_family = self.column_family.clone();
+        spawn_blocking!("rocksdb_store_query", move || {
+            let cf = database
+                .cf_handle(&column_family)
+                .err_tip(|| format!("Column family {column_family} missing in rocksdb store"))?;
+            f(&database, &cf)
+        })
+        .await
+        .map_err(|e| {
+            make_err!(
+                Code::Internal,
+                "RocksDB task failed in rocksdb store: {e:?}"
+            )
+        })?
+    }
+
+    /// Returns the size of the item of `key`, if it exists and did not
+    /// expire.
+    async fn size_of(&self, key: String) -> Result<Option<u64>, Error> {
+        self.with_column_family(move |database, cf| {
+            let value = database.get_pinned_cf(cf, &key).map_err(rocksdb_error)?;
+            Ok(value
+                .filter(|value| !is_expired(value, now_seconds()))
+                .map(|value| value.len().saturating_sub(HEADER_SIZE) as u64))
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl StoreDriver for RocksdbStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        for (key, result) in keys.iter().zip(results.iter_mut()) {
+            if is_zero_digest(key.borrow()) {
+                *result = Some(0);
+                continue;
+            }
+            *result = self
+                .size_of(key.as_str().into_owned())
+                .await
+                .err_tip(|| "In RocksdbStore::has_with_results")?;
+        }
+        Ok(())
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        let key = key.as_str().into_owned();
+        self.with_column_family(move |database, cf| {
+            let existed = database
+                .get_pinned_cf(cf, &key)
+                .map_err(rocksdb_error)?
+                .is_some_and(|value| !is_expired(&value, now_seconds()));
+            database.delete_cf(cf, &key).map_err(rocksdb_error)?;
+            Ok(existed)
+        })
+        .await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        _size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let data = reader
+            .consume(None)
+            .await
+            .err_tip(|| "Failed to collect all bytes from reader in rocksdb_store::update")?;
+        let expires_at = if self.max_age_seconds == 0 {
+            0
+        } else {
+            now_seconds() + self.max_age_seconds
+        };
+        let mut value = Vec::with_capacity(HEADER_SIZE + data.len());
+        value.extend_from_slice(&expires_at.to_le_bytes());
+        value.extend_from_slice(&data);
+        let key = key.as_str().into_owned();
+        self.with_column_family(move |database, cf| {
+            database.put_cf(cf, &key, &value).map_err(rocksdb_error)
+        })
+        .await
+        .err_tip(|| "In RocksdbStore::update")
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        if is_zero_digest(key.borrow()) {
+            writer
+                .send_eof()
+                .err_tip(|| "Failed to send zero EOF in rocksdb store get_part")?;
+            return Ok(());
+        }
+        let owned_key = key.as_str().into_owned();
+        let data = self
+            .with_column_family(move |database, cf| {
+                let Some(value) = database
+                    .get_pinned_cf(cf, &owned_key)
+                    .map_err(rocksdb_error)?
+                    .filter(|value| !is_expired(value, now_seconds()))
+                else {
+                    return Ok(None);
+                };
+                let data = value.get(HEADER_SIZE..).unwrap_or_default();
+                let start = usize::try_from(offset)
+                    .unwrap_or(usize::MAX)
+                    .min(data.len());
+                let end = length.map_or(data.len(), |length| {
+                    start
+                        .saturating_add(usize::try_from(length).unwrap_or(usize::MAX))
+                        .min(data.len())
+                });
+                Ok(Some(Bytes::copy_from_slice(&data[start..end])))
+            })
+            .await
+            .err_tip(|| "In RocksdbStore::get_part")?
+            .err_tip_with_code(|_| (Code::NotFound, format!("Key {key:?} not found")))?;
+        if !data.is_empty() {
+            writer
+                .send(data)
+                .await
+                .err_tip(|| "Failed to write data in rocksdb store")?;
+        }
+        writer
+            .send_eof()
+            .err_tip(|| "Failed to write EOF in rocksdb store get_part")?;
+        Ok(())
+    }
+
+    fn inner_store(&self, _digest: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        _callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        // Items are only removed by compactions, which can't be observed.
+        Ok(())
+    }
+}
+
+default_health_status_indicator!(RocksdbStore);
@@ -15,6 +15,7 @@
 use core::cmp;
 use core::future::Future;
 use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
 use core::task::{Context, Poll};
 use core::time::Duration;
 use std::borrow::Cow;
@@ -24,14 +25,18 @@ use async_trait::async_trait;
 use aws_config::default_provider::credentials;
 use aws_config::provider_config::ProviderConfig;
 use aws_config::retry::ErrorKind::TransientError;
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::web_identity_token::{StaticConfiguration, WebIdentityTokenCredentialsProvider};
 use aws_config::{AppName, BehaviorVersion};
 use aws_sdk_s3::Client;
-use aws_sdk_s3::config::Region;
+use aws_sdk_s3::config::{Region, SharedCredentialsProvider};
+use aws_sdk_s3::error::ProvideErrorMetadata;
 use aws_sdk_s3::operation::create_multipart_upload::CreateMultipartUploadOutput;
 use aws_sdk_s3::operation::get_object::GetObjectError;
 use aws_sdk_s3::operation::head_object::HeadObjectError;
 use aws_sdk_s3::primitives::ByteStream; // SdkBody
 use aws_sdk_s3::types::builders::{CompletedMultipartUploadBuilder, CompletedPartBuilder};
+use aws_sdk_s3::types::{ChecksumAlgorithm, ChecksumMode, ChecksumType};
 use aws_smithy_runtime_api::client::http::{
     HttpClient as SmithyHttpClient, HttpConnector as SmithyHttpConnector, HttpConnectorFuture,
     HttpConnectorSettings, SharedHttpConnector,
@@ -52,7 +57,7 @@ use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
 use hyper_util::client::legacy::Client as LegacyClient;
 use hyper_util::client::legacy::connect::HttpConnector as LegacyHttpConnector;
 use hyper_util::rt::TokioExecutor;
-use nativelink_config::stores::ExperimentalAwsSpec;
+use nativelink_config::stores::{ExistingObjectCheck, ExperimentalAwsSpec};
 // Note: S3 store should be very careful about the error codes it returns
 // when in a retryable wrapper. Always prefer Code::Aborted or another
 // retryable code over Code::InvalidArgument or make_input_err!().
@@ -67,13 +72,15 @@ use nativelink_util::health_utils::{HealthRegistryBuilder, HealthStatus, HealthS
 use nativelink_util::instant_wrapper::InstantWrapper;
 use nativelink_util::retry::{Retrier, RetryResult};
 use nativelink_util::store_trait::{RemoveItemCallback, StoreDriver, StoreKey, UploadSizeInfo};
+use nativelink_util::token_bucket::TokenBucket;
 use parking_lot::Mutex;
 use rand::Rng;
 use tokio::sync::mpsc;
 use tokio::time::sleep;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::cas_utils::is_zero_digest;
+use crate::object_store_utils::{ObjectRequestConfig, hedged};
 
 // S3 parts cannot be smaller than this number. See:
 // https://docs.aws.amazon.com/AmazonS3/latest/userguide/qfacts.html
@@ -95,6 +102,21 @@ const DEFAULT_MAX_RETRY_BUFFER_PER_REQUEST: usize = 5 * 1024 * 1024; // 5MB.
 // Note: If you change this, adjust the docs in the config.
 const DEFAULT_MULTIPART_MAX_CONCURRENT_UPLOADS: usize = 10;
 
+// Default name of web identity and assumed role sessions.
+// Note: If you change this, adjust the docs in the config.
+const DEFAULT_ROLE_SESSION_NAME: &str = "nativelink";
+
+// Default time to wait for a connection to be established.
+// Note: If you change this, adjust the docs in the config.
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+fn connect_timeout(spec: &ExperimentalAwsSpec) -> Duration {
+    match spec.common.timeouts.connect_timeout_ms {
+        0 => DEFAULT_CONNECT_TIMEOUT,
+        connect_timeout_ms => Duration::from_millis(connect_timeout_ms),
+    }
+}
+
 #[derive(Clone)]
 pub struct TlsClient {
     client: LegacyClient<HttpsConnector<LegacyHttpConnector>, SdkBody>,
@@ -115,10 +137,19 @@ impl TlsClient {
             connector_with_roots.https_only()
         };
 
+        let mut http_connector = LegacyHttpConnector::new();
+        http_connector.enforce_http(false);
+        http_connector.set_connect_timeout(Some(connect_timeout(spec)));
+
         let connector = if spec.common.disable_http2 {
-            connector_with_schemes.enable_http1().build()
+            connector_with_schemes
+                .enable_http1()
+                .wrap_connector(http_connector)
         } else {
-            connector_with_schemes.enable_http1().enable_http2().build()
+            connector_with_schemes
+                .enable_http1()
+                .enable_http2()
+                .wrap_connector(http_connector)
         };
 
         let client = LegacyClient::builder(TokioExecutor::new()).build(connector);
@@ -392,6 +423,20 @@ impl SmithyHttpConnector for TlsClient {
     }
 }
 
+/// Whether `err` is the S3 client reporting that a response body did not
+/// match its checksum. The error type is private to the client, so it is
+/// recognized by its message.
+fn is_checksum_mismatch(err: &(dyn core::error::Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if err.to_string().contains("checksum mismatch") {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
 #[derive(Debug)]
 pub struct BodyWrapper {
     reader: DropCloserReadHalf,
@@ -432,6 +477,20 @@ pub struct S3Store<NowFn> {
     max_retry_buffer_per_request: usize,
     #[metric(help = "The number of concurrent uploads allowed for multipart uploads")]
     multipart_max_concurrent_uploads: usize,
+    #[metric(help = "The configured size of the parts of multipart uploads")]
+    multipart_part_size: Option<u64>,
+    upload_limiter: Option<Arc<TokenBucket>>,
+    download_limiter: Option<Arc<TokenBucket>>,
+    #[metric(help = "Whether uploads carry a checksum and whole object reads are verified")]
+    verify_checksums: bool,
+    #[metric(help = "Number of uploads and reads which failed checksum verification")]
+    checksum_mismatches: AtomicU64,
+    existing_object_check: ExistingObjectCheck,
+    #[metric(help = "Number of uploads skipped because the object already existed")]
+    skipped_uploads: AtomicU64,
+    request_config: ObjectRequestConfig,
+    #[metric(help = "Number of reads for which a second request was sent")]
+    hedged_requests: AtomicU64,
 
     remove_callbacks: Arc<Mutex<Vec<Arc<Box<dyn RemoveItemCallback>>>>>,
 }
@@ -451,22 +510,14 @@ where
         });
         let s3_client = {
             let http_client = TlsClient::new(&spec.clone(), jitter_fn.clone());
-
-            let credential_provider = credentials::DefaultCredentialsChain::builder()
-                .configure(
-                    ProviderConfig::without_region()
-                        .with_region(Some(Region::new(Cow::Owned(spec.region.clone()))))
-                        .with_http_client(http_client.clone()),
-                )
-                .build()
-                .await;
+            let credential_provider = Self::credential_provider(spec, &http_client).await;
 
             let config = aws_config::defaults(BehaviorVersion::v2025_08_07())
                 .credentials_provider(credential_provider)
                 .app_name(AppName::new("nativelink").expect("valid app name"))
                 .timeout_config(
                     aws_config::timeout::TimeoutConfig::builder()
-                        .connect_timeout(Duration::from_secs(15))
+                        .connect_timeout(connect_timeout(spec))
                         .build(),
                 )
                 .region(Region::new(Cow::Owned(spec.region.clone())))
@@ -479,12 +530,72 @@ where
         Self::new_with_client_and_jitter(spec, s3_client, jitter_fn, now_fn)
     }
 
+    /// Builds the provider of the credentials requests are signed with.
+    /// Every provider is wrapped in the identity cache of the client, which
+    /// asks it for new credentials shortly before the current ones expire.
+    async fn credential_provider(
+        spec: &ExperimentalAwsSpec,
+        http_client: &TlsClient,
+    ) -> SharedCredentialsProvider {
+        let region = Region::new(Cow::Owned(spec.region.clone()));
+        let provider_config = ProviderConfig::without_region()
+            .with_region(Some(region.clone()))
+            .with_http_client(http_client.clone());
+        let base_provider = match &spec.web_identity {
+            Some(web_identity) => SharedCredentialsProvider::new(
+                WebIdentityTokenCredentialsProvider::builder()
+                    .configure(&provider_config)
+                    .static_configuration(StaticConfiguration {
+                        web_identity_token_file: web_identity.token_file.clone().into(),
+                        role_arn: web_identity.role_arn.clone(),
+                        session_name: web_identity
+                            .session_name
+                            .clone()
+                            .unwrap_or_else(|| DEFAULT_ROLE_SESSION_NAME.to_string()),
+                    })
+                    .build(),
+            ),
+            None => SharedCredentialsProvider::new(
+                credentials::DefaultCredentialsChain::builder()
+                    .configure(provider_config)
+                    .build()
+                    .await,
+            ),
+        };
+        let Some(assume_role) = &spec.assume_role else {
+            return base_provider;
+        };
+
+        let sts_config = aws_config::defaults(BehaviorVersion::v2025_08_07())
+            .credentials_provider(base_provider)
+            .region(region)
+            .http_client(http_client.clone())
+            .load()
+            .await;
+        let mut builder = AssumeRoleProvider::builder(assume_role.role_arn.clone())
+            .configure(&sts_config)
+            .session_name(
+                assume_role
+                    .session_name
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_ROLE_SESSION_NAME.to_string()),
+            );
+        if let Some(external_id) = &assume_role.external_id {
+            builder = builder.external_id(external_id.clone());
+        }
+        if assume_role.session_duration_s != 0 {
+            builder = builder.session_length(Duration::from_secs(assume_role.session_duration_s));
+        }
+        SharedCredentialsProvider::new(builder.build().await)
+    }
+
     pub fn new_with_client_and_jitter(
         spec: &ExperimentalAwsSpec,
         s3_client: Client,
         jitter_fn: Arc<dyn Fn(Duration) -> Duration + Send + Sync>,
         now_fn: NowFn,
     ) -> Result<Arc<Self>, Error> {
+        let request_config = ObjectRequestConfig::new(&spec.common);
         Ok(Arc::new(Self {
             s3_client: Arc::new(s3_client),
             now_fn,
@@ -495,11 +606,11 @@ where
                 .as_ref()
                 .unwrap_or(&String::new())
                 .clone(),
-            retrier: Retrier::new(
+            retrier: request_config.apply_budget(Retrier::new(
                 Arc::new(|duration| Box::pin(sleep(duration))),
                 jitter_fn,
                 spec.common.retry.clone(),
-            ),
+            )),
             consider_expired_after_s: i64::from(spec.common.consider_expired_after_s),
             max_retry_buffer_per_request: spec
                 .common
@@ -509,6 +620,17 @@ where
                 .common
                 .multipart_max_concurrent_uploads
                 .map_or(DEFAULT_MULTIPART_MAX_CONCURRENT_UPLOADS, |v| v),
+            multipart_part_size: spec.multipart_part_size,
+            upload_limiter: (spec.max_upload_bytes_per_second != 0)
+                .then(|| Arc::new(TokenBucket::new(spec.max_upload_bytes_per_second))),
+            download_limiter: (spec.max_download_bytes_per_second != 0)
+                .then(|| Arc::new(TokenBucket::new(spec.max_download_bytes_per_second))),
+            verify_checksums: spec.verify_checksums,
+            checksum_mismatches: AtomicU64::new(0),
+            existing_object_check: spec.existing_object_check,
+            skipped_uploads: AtomicU64::new(0),
+            request_config,
+            hedged_requests: AtomicU64::new(0),
             remove_callbacks: Arc::new(Mutex::new(vec![])),
         }))
     }
@@ -517,19 +639,104 @@ where
         format!("{}{}", self.key_prefix, key.as_str(),)
     }
 
+    /// Waits until `bytes` may be uploaded without exceeding the configured
+    /// upload bandwidth.
+    async fn throttle_upload(&self, bytes: u64) {
+        if let Some(upload_limiter) = &self.upload_limiter {
+            upload_limiter.acquire(bytes).await;
+        }
+    }
+
+    /// Waits until `bytes` may be downloaded without exceeding the configured
+    /// download bandwidth.
+    async fn throttle_download(&self, bytes: u64) {
+        if let Some(download_limiter) = &self.download_limiter {
+            download_limiter.acquire(bytes).await;
+        }
+    }
+
+    /// Algorithm of the checksums attached to uploads, if enabled.
+    fn checksum_algorithm(&self) -> Option<ChecksumAlgorithm> {
+        self.verify_checksums.then_some(ChecksumAlgorithm::Crc32C)
+    }
+
+    /// Value of the `If-None-Match` header of uploads, if they are
+    /// conditional.
+    fn if_none_match(&self) -> Option<String> {
+        (self.existing_object_check == ExistingObjectCheck::IfNoneMatch).then(|| "*".to_string())
+    }
+
+    /// Returns the code for an upload error, counting uploads which S3
+    /// rejected because the data did not match its checksum. Conditional
+    /// uploads of objects which already exist fail with `AlreadyExists`.
+    fn upload_err_code(&self, err: &impl ProvideErrorMetadata) -> Code {
+        match err.code() {
+            Some("BadDigest") => {
+                self.checksum_mismatches.fetch_add(1, Ordering::Relaxed);
+                Code::DataLoss
+            }
+            Some("PreconditionFailed") if self.if_none_match().is_some() => Code::AlreadyExists,
+            _ => Code::Aborted,
+        }
+    }
+
+    /// Returns true if the object to be uploaded already exists with the
+    /// size of the upload, when existing objects are checked with HEAD.
+    async fn exists_with_size(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        upload_size: UploadSizeInfo,
+    ) -> bool {
+        let UploadSizeInfo::ExactSize(size) = upload_size else {
+            return false;
+        };
+        if self.existing_object_check != ExistingObjectCheck::Head {
+            return false;
+        }
+        match self.has(key).await {
+            Ok(existing_size) => existing_size == Some(size),
+            Err(err) => {
+                warn!(
+                    ?err,
+                    "Failed to check for an existing object before upload in S3Store"
+                );
+                false
+            }
+        }
+    }
+
+    /// Treats a conditional upload rejected because the object already
+    /// exists as a skipped upload.
+    fn skip_if_exists(&self, result: Result<(), Error>) -> Result<(), Error> {
+        match result {
+            Err(err) if err.code == Code::AlreadyExists => {
+                self.skipped_uploads.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
     async fn has(self: Pin<&Self>, digest: StoreKey<'_>) -> Result<Option<u64>, Error> {
         let digest_clone = digest.into_owned();
         self.retrier
             .retry(unfold((), move |state| {
                 let local_digest = digest_clone.clone();
                 async move {
-                    let result = self
-                        .s3_client
-                        .head_object()
-                        .bucket(&self.bucket)
-                        .key(self.make_s3_path(&local_digest))
-                        .send()
+                    let deadline = self.request_config.head_timeouts.start();
+                    let result = deadline
+                        .response(
+                            self.s3_client
+                                .head_object()
+                                .bucket(&self.bucket)
+                                .key(self.make_s3_path(&local_digest))
+                                .send(),
+                        )
                         .await;
+                    let result = match result {
+                        Ok(result) => result,
+                        Err(err) => return Some((RetryResult::Retry(err), state)),
+                    };
 
                     match result {
                         Ok(head_object_output) => {
@@ -617,6 +824,14 @@ where
     ) -> Result<(), Error> {
         let s3_path = &self.make_s3_path(&digest);
 
+        if self.exists_with_size(digest.borrow(), upload_size).await {
+            self.skipped_uploads.fetch_add(1, Ordering::Relaxed);
+            return reader
+                .drain()
+                .await
+                .err_tip(|| "Failed to drain reader of skipped upload in S3Store::update");
+        }
+
         let max_size = match upload_size {
             UploadSizeInfo::ExactSize(sz) | UploadSizeInfo::MaxSize(sz) => sz,
         };
@@ -637,40 +852,56 @@ where
                 u64::try_from(self.max_retry_buffer_per_request)
                     .err_tip(|| "Could not convert max_retry_buffer_per_request to u64")?,
             );
-            return self
+            let result = self
                 .retrier
                 .retry(unfold(reader, move |mut reader| async move {
                     // We need to make a new pair here because the aws sdk does not give us
                     // back the body after we send it in order to retry.
                     let (mut tx, rx) = make_buf_channel_pair();
 
+                    self.throttle_upload(sz).await;
+
                     // Upload the data to the S3 backend.
+                    let deadline = self.request_config.write_timeouts.start();
                     let result = {
                         let reader_ref = &mut reader;
-                        let (upload_res, bind_res) = tokio::join!(
-                            self.s3_client
-                                .put_object()
-                                .bucket(&self.bucket)
-                                .key(s3_path.clone())
-                                .content_length(sz as i64)
-                                .body(ByteStream::from_body_1_x(BodyWrapper {
-                                    reader: rx,
-                                    size: sz,
-                                }))
-                                .send()
-                                .map_ok_or_else(|e| Err(make_err!(Code::Aborted, "{e:?}")), |_| Ok(())),
-                            // Stream all data from the reader channel to the writer channel.
-                            tx.bind_buffered(reader_ref)
-                        );
-                        upload_res
-                            .merge(bind_res)
+                        let upload = async {
+                            tokio::join!(
+                                self.s3_client
+                                    .put_object()
+                                    .bucket(&self.bucket)
+                                    .key(s3_path.clone())
+                                    .content_length(sz as i64)
+                                    .set_checksum_algorithm(self.checksum_algorithm())
+                                    .set_if_none_match(self.if_none_match())
+                                    .body(ByteStream::from_body_1_x(BodyWrapper {
+                                        reader: rx,
+                                        size: sz,
+                                    }))
+                                    .send()
+                                    .map_ok_or_else(|e| Err(make_err!(self.upload_err_code(&e), "{e:?}")), |_| Ok(())),
+                                // Stream all data from the reader channel to the writer channel.
+                                tx.bind_buffered(reader_ref)
+                            )
+                        };
+                        deadline
+                            .response(upload)
+                            .await
+                            .and_then(|(upload_res, bind_res)| upload_res.merge(bind_res))
                             .err_tip(|| "Failed to upload file to s3 in single chunk")
                     };
 
                     // If we failed to upload the file, check to see if we can retry.
                     let retry_result = result.map_or_else(|mut err| {
+                        // The object already exists, so there is nothing to retry.
+                        if err.code == Code::AlreadyExists {
+                            return RetryResult::Err(err);
+                        }
                         // Ensure our code is Code::Aborted, so the client can retry if possible.
-                        err.code = Code::Aborted;
+                        // Checksum mismatches are kept as data loss, so they can be told apart.
+                        if err.code != Code::DataLoss {
+                            err.code = Code::Aborted;
+                        }
                         let bytes_received = reader.get_bytes_received();
                         if let Err(try_reset_err) = reader.try_reset_stream() {
                             error!(
@@ -693,6 +924,7 @@ where
                     Some((retry_result, reader))
                 }))
                 .await;
+            return self.skip_if_exists(result);
         }
 
         let upload_id = &self
@@ -703,6 +935,9 @@ where
                     .create_multipart_upload()
                     .bucket(&self.bucket)
                     .key(s3_path)
+                    .set_checksum_algorithm(self.checksum_algorithm())
+                    // A full object checksum lets reads of the whole object be verified.
+                    .set_checksum_type(self.verify_checksums.then_some(ChecksumType::FullObject))
                     .send()
                     .await
                     .map_or_else(
@@ -730,8 +965,12 @@ where
 
         // S3 requires us to upload in parts if the size is greater than 5GB. The part size must be at least
         // 5mb (except last part) and can have up to 10,000 parts.
-        let bytes_per_upload_part =
-            (max_size / (MIN_MULTIPART_SIZE - 1)).clamp(MIN_MULTIPART_SIZE, MAX_MULTIPART_SIZE);
+        let bytes_per_upload_part = self
+            .multipart_part_size
+            .map_or(max_size / (MIN_MULTIPART_SIZE - 1), |part_size| {
+                part_size.max(max_size.div_ceil(MAX_UPLOAD_PARTS as u64))
+            })
+            .clamp(MIN_MULTIPART_SIZE, MAX_MULTIPART_SIZE);
 
         let upload_parts = move || async move {
             // This will ensure we only have `multipart_max_concurrent_uploads` * `bytes_per_upload_part`
@@ -754,35 +993,45 @@ where
 
                     tx.send(retrier.retry(unfold(write_buf, move |write_buf| {
                         async move {
-                            let retry_result = self
-                                .s3_client
-                                .upload_part()
-                                .bucket(&self.bucket)
-                                .key(s3_path)
-                                .upload_id(upload_id)
-                                .body(ByteStream::new(SdkBody::from(write_buf.clone())))
-                                .part_number(part_number)
-                                .send()
-                                .await
-                                .map_or_else(
-                                    |e| {
-                                        RetryResult::Retry(make_err!(
-                                            Code::Aborted,
-                                            "Failed to upload part {part_number} in S3 store: {e:?}"
-                                        ))
-                                    },
-                                    |mut response| {
-                                        RetryResult::Ok(
-                                            CompletedPartBuilder::default()
-                                                // Only set an entity tag if it exists. This saves
-                                                // 13 bytes per part on the final request if it can
-                                                // omit the `<ETAG><ETAG/>` string.
-                                                .set_e_tag(response.e_tag.take())
-                                                .part_number(part_number)
-                                                .build(),
-                                        )
-                                    },
-                                );
+                            self.throttle_upload(write_buf.len() as u64).await;
+                            let deadline = self.request_config.write_timeouts.start();
+                            let result = deadline
+                                .response(
+                                    self.s3_client
+                                        .upload_part()
+                                        .bucket(&self.bucket)
+                                        .key(s3_path)
+                                        .upload_id(upload_id)
+                                        .set_checksum_algorithm(self.checksum_algorithm())
+                                        .body(ByteStream::new(SdkBody::from(write_buf.clone())))
+                                        .part_number(part_number)
+                                        .send(),
+                                )
+                                .await;
+                            let result = match result {
+                                Ok(result) => result,
+                                Err(err) => return Some((RetryResult::Retry(err), write_buf)),
+                            };
+                            let retry_result = result.map_or_else(
+                                |e| {
+                                    RetryResult::Retry(make_err!(
+                                        self.upload_err_code(&e),
+                                        "Failed to upload part {part_number} in S3 store: {e:?}"
+                                    ))
+                                },
+                                |mut response| {
+                                    RetryResult::Ok(
+                                        CompletedPartBuilder::default()
+                                            // Only set an entity tag if it exists. This saves
+                                            // 13 bytes per part on the final request if it can
+                                            // omit the `<ETAG><ETAG/>` string.
+                                            .set_e_tag(response.e_tag.take())
+                                            .set_checksum_crc32_c(response.checksum_crc32_c.take())
+                                            .part_number(part_number)
+                                            .build(),
+                                    )
+                                },
+                            );
                             Some((retry_result, write_buf))
                         }
                     })))
@@ -833,14 +1082,19 @@ where
                                     .build(),
                             )
                             .upload_id(upload_id)
+                            .set_if_none_match(self.if_none_match())
                             .send()
                             .await
                             .map_or_else(
-                                |e| {
-                                    RetryResult::Retry(make_err!(
+                                |e| match self.upload_err_code(&e) {
+                                    Code::AlreadyExists => RetryResult::Err(make_err!(
+                                        Code::AlreadyExists,
+                                        "Object already exists, multipart upload in S3 store not completed: {e:?}"
+                                    )),
+                                    _ => RetryResult::Retry(make_err!(
                                         Code::Aborted,
                                         "Failed to complete multipart upload in S3 store: {e:?}"
-                                    ))
+                                    )),
                                 },
                                 |_| RetryResult::Ok(()),
                             ),
@@ -851,7 +1105,7 @@ where
         };
         // Upload our parts and complete the multipart upload.
         // If we fail attempt to abort the multipart upload (cleanup).
-        upload_parts()
+        let result = upload_parts()
             .or_else(move |e| async move {
                 Result::<(), _>::Err(e).merge(
                     // Note: We don't retry here because this is just a best attempt.
@@ -875,7 +1129,8 @@ where
                         ),
                 )
             })
-            .await
+            .await;
+        self.skip_if_exists(result)
     }
 
     async fn get_part(
@@ -896,48 +1151,66 @@ where
         let end_read_byte = length
             .map_or(Some(None), |length| Some(offset.checked_add(length)))
             .err_tip(|| "Integer overflow protection triggered")?;
+        let read_whole_object = offset == 0 && length.is_none();
 
         self.retrier
             .retry(unfold(writer, move |writer| async move {
-                let result = self
+                // S3 only returns checksums when the whole object is read, so
+                // reads resumed after a failure are not verified.
+                let verify_checksum =
+                    self.verify_checksums && read_whole_object && writer.get_bytes_written() == 0;
+                let request = self
                     .s3_client
                     .get_object()
                     .bucket(&self.bucket)
-                    .key(s3_path)
-                    .range(format!(
+                    .key(s3_path);
+                let request = if verify_checksum {
+                    request.checksum_mode(ChecksumMode::Enabled)
+                } else {
+                    request.range(format!(
                         "bytes={}-{}",
                         offset + writer.get_bytes_written(),
                         end_read_byte.map_or_else(String::new, |v| v.to_string())
                     ))
-                    .send()
-                    .await;
-
-                let mut s3_in_stream = match result {
-                    Ok(head_object_output) => head_object_output.body,
-                    Err(sdk_error) => match sdk_error.into_service_error() {
-                        GetObjectError::NoSuchKey(e) => {
-                            return Some((
-                                RetryResult::Err(make_err!(
-                                    Code::NotFound,
-                                    "No such key in S3: {e}"
-                                )),
-                                writer,
-                            ));
-                        }
-                        other => {
-                            return Some((
-                                RetryResult::Retry(make_err!(
+                };
+                let request = &request;
+                let result = hedged(
+                    self.request_config.hedge_delay,
+                    &self.hedged_requests,
+                    move || async move {
+                        let deadline = self.request_config.read_timeouts.start();
+                        let result = deadline.response(request.clone().send()).await?;
+                        match result {
+                            Ok(get_object_output) => Ok((get_object_output.body, deadline)),
+                            Err(sdk_error) => match sdk_error.into_service_error() {
+                                GetObjectError::NoSuchKey(e) => {
+                                    Err(make_err!(Code::NotFound, "No such key in S3: {e}"))
+                                }
+                                other => Err(make_err!(
                                     Code::Unavailable,
                                     "Unhandled GetObjectError in S3: {other:?}",
                                 )),
-                                writer,
-                            ));
+                            },
                         }
                     },
+                )
+                .await;
+
+                let (mut s3_in_stream, deadline) = match result {
+                    Ok(response) => response,
+                    Err(err) if err.code == Code::NotFound => {
+                        return Some((RetryResult::Err(err), writer));
+                    }
+                    Err(err) => return Some((RetryResult::Retry(err), writer)),
                 };
 
                 // Copy data from s3 input stream to the writer stream.
-                while let Some(maybe_bytes) = s3_in_stream.next().await {
+                loop {
+                    let maybe_bytes = match deadline.body(s3_in_stream.next()).await {
+                        Ok(Some(maybe_bytes)) => maybe_bytes,
+                        Ok(None) => break,
+                        Err(err) => return Some((RetryResult::Retry(err), writer)),
+                    };
                     match maybe_bytes {
                         Ok(bytes) => {
                             if bytes.is_empty() {
@@ -945,6 +1218,7 @@ where
                                 // send EOF this way.
                                 continue;
                             }
+                            self.throttle_download(bytes.len() as u64).await;
                             if let Err(e) = writer.send(bytes).await {
                                 return Some((
                                     RetryResult::Err(make_err!(
@@ -955,6 +1229,16 @@ where
                                 ));
                             }
                         }
+                        Err(e) if verify_checksum && is_checksum_mismatch(&e) => {
+                            self.checksum_mismatches.fetch_add(1, Ordering::Relaxed);
+                            return Some((
+                                RetryResult::Err(make_err!(
+                                    Code::DataLoss,
+                                    "Checksum mismatch reading {s3_path} from S3: {e}"
+                                )),
+                                writer,
+                            ));
+                        }
                         Err(e) => {
                             return Some((
                                 RetryResult::Retry(make_err!(
@@ -13,36 +13,233 @@
 // limitations under the License.
 
 use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
 use std::sync::Arc;
+use std::time::Instant;
 
 use async_trait::async_trait;
+use futures::future::try_join_all;
 use nativelink_config::stores::SizePartitioningSpec;
-use nativelink_error::{Error, ResultExt, make_input_err};
+use nativelink_error::{Code, Error, error_if, make_input_err};
 use nativelink_metric::MetricsComponent;
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::common::DigestInfo;
 use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
 use nativelink_util::store_trait::{
     RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo,
 };
-use tokio::join;
+use tracing::debug;
+
+/// Default upload latency above which partition sizes are lowered.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_MAX_UPLOAD_LATENCY_MS: u64 = 100;
+
+/// Default lowest partition size in percent of the configured size.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_MIN_SIZE_PERCENT: u8 = 25;
+
+/// Number of steps a dynamic partition size takes to move from its
+/// configured size to its minimum.
+const DYNAMIC_SIZE_STEPS: u64 = 16;
+
+#[derive(Debug, MetricsComponent)]
+struct Partition {
+    #[metric(group = "store")]
+    store: Store,
+    #[metric(help = "Objects smaller than this are sent to this partition")]
+    size: AtomicU64,
+    #[metric(help = "Configured size of this partition")]
+    configured_size: u64,
+    // Lowest `size` can be moved to. Equal to `configured_size` unless the
+    // partition is dynamic.
+    min_size: u64,
+    #[metric(help = "Moving average of the upload latency of this partition in microseconds")]
+    upload_latency_us: AtomicU64,
+}
 
 #[derive(Debug, MetricsComponent)]
 pub struct SizePartitioningStore {
-    #[metric(help = "Size to partition our data")]
-    partition_size: u64,
-    #[metric(group = "lower_store")]
-    lower_store: Store,
-    #[metric(group = "upper_store")]
-    upper_store: Store,
+    #[metric(group = "partitions")]
+    partitions: Vec<Partition>,
+    // Set if partition sizes move based on the upload latency.
+    max_upload_latency: Option<Duration>,
 }
 
 impl SizePartitioningStore {
-    pub fn new(spec: &SizePartitioningSpec, lower_store: Store, upper_store: Store) -> Arc<Self> {
-        Arc::new(Self {
-            partition_size: spec.size,
-            lower_store,
-            upper_store,
-        })
+    pub fn new(
+        spec: &SizePartitioningSpec,
+        lower_store: Store,
+        middle_stores: Vec<Store>,
+        upper_store: Store,
+    ) -> Result<Arc<Self>, Error> {
+        error_if!(
+            spec.middle_stores.len() != middle_stores.len(),
+            "Config middle_stores do not match stores length"
+        );
+        let sizes: Vec<u64> = core::iter::once(spec.size)
+            .chain(spec.middle_stores.iter().map(|partition| partition.size))
+            .collect();
+        error_if!(
+            sizes.windows(2).any(|pair| pair[0] >= pair[1]),
+            "SizePartitioningStore sizes must be increasing, got {sizes:?}"
+        );
+        let min_size_percent = spec.dynamic.as_ref().map_or(100, |dynamic| {
+            if dynamic.min_size_percent == 0 {
+                DEFAULT_MIN_SIZE_PERCENT
+            } else {
+                dynamic.min_size_percent.min(100)
+            }
+        });
+        let stores = core::iter::once(lower_store)
+            .chain(middle_stores)
+            .chain(core::iter::once(upper_store));
+        let sizes_and_previous = sizes
+            .iter()
+            .enumerate()
+            .map(|(index, &size)| (size, index.checked_sub(1).map_or(0, |index| sizes[index])))
+            .chain(core::iter::once((u64::MAX, u64::MAX)));
+        let partitions = stores
+            .zip(sizes_and_previous)
+            .map(|(store, (size, previous_size))| Partition {
+                store,
+                size: AtomicU64::new(size),
+                configured_size: size,
+                min_size: if size == u64::MAX {
+                    u64::MAX
+                } else {
+                    (size / 100 * u64::from(min_size_percent)).max(previous_size)
+                },
+                upload_latency_us: AtomicU64::new(0),
+            })
+            .collect();
+        Ok(Arc::new(Self {
+            partitions,
+            max_upload_latency: spec.dynamic.as_ref().map(|dynamic| {
+                Duration::from_millis(if dynamic.max_upload_latency_ms == 0 {
+                    DEFAULT_MAX_UPLOAD_LATENCY_MS
+                } else {
+                    dynamic.max_upload_latency_ms
+                })
+            }),
+        }))
+    }
+
+    fn digest_from_key(key: StoreKey<'_>) -> Result<DigestInfo, Error> {
+        match key {
+            StoreKey::Digest(digest) => Ok(digest),
+            other @ StoreKey::Str(_) => Err(make_input_err!(
+                "SizePartitioningStore only supports Digest keys, got {other:?}"
+            )),
+        }
+    }
+
+    /// Index of the partition objects of `size` are currently sent to.
+    fn partition_index(&self, size: u64) -> usize {
+        self.partitions
+            .iter()
+            .position(|partition| size < partition.size.load(Ordering::Relaxed))
+            .unwrap_or(self.partitions.len() - 1)
+    }
+
+    /// Index of the partition an object of `size` sent to the partition at
+    /// `index` may have been sent to before the partition sizes moved.
+    fn fallback_index(&self, size: u64, index: usize) -> Option<usize> {
+        if index + 1 < self.partitions.len() && size >= self.partitions[index].min_size {
+            return Some(index + 1);
+        }
+        if index > 0 && size < self.partitions[index - 1].configured_size {
+            return Some(index - 1);
+        }
+        None
+    }
+
+    /// Looks up each of `keys` in the partition at the same position of
+    /// `partition_indexes`, batching the lookups per partition.
+    async fn has_in_partitions(
+        &self,
+        keys: &[StoreKey<'_>],
+        partition_indexes: &[usize],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        let lookups = self
+            .partitions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, partition)| {
+                let positions: Vec<usize> = partition_indexes
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(position, &partition_index)| {
+                        (partition_index == index).then_some(position)
+                    })
+                    .collect();
+                if positions.is_empty() {
+                    return None;
+                }
+                let partition_keys: Vec<StoreKey<'_>> = positions
+                    .iter()
+                    .map(|&position| keys[position].borrow())
+                    .collect();
+                Some(async move {
+                    let mut partition_results = vec![None; partition_keys.len()];
+                    partition
+                        .store
+                        .has_with_results(&partition_keys, &mut partition_results)
+                        .await?;
+                    Ok::<_, Error>((positions, partition_results))
+                })
+            });
+        for (positions, partition_results) in try_join_all(lookups).await? {
+            for (position, result) in positions.into_iter().zip(partition_results) {
+                results[position] = result;
+            }
+        }
+        Ok(())
+    }
+
+    /// Feeds the latency of an upload to the partition at `index` into its
+    /// moving average and moves its size one step if the average is out of
+    /// bounds.
+    fn record_upload_latency(&self, index: usize, latency: Duration) {
+        let Some(max_upload_latency) = self.max_upload_latency else {
+            return;
+        };
+        let partition = &self.partitions[index];
+        if partition.min_size >= partition.configured_size {
+            return;
+        }
+        let sample = latency.as_micros() as u64;
+        let previous = partition.upload_latency_us.load(Ordering::Relaxed);
+        let average = if previous == 0 {
+            sample
+        } else {
+            (previous * 7 + sample) / 8
+        };
+        partition
+            .upload_latency_us
+            .store(average, Ordering::Relaxed);
+
+        let max_latency_us = max_upload_latency.as_micros() as u64;
+        let step = ((partition.configured_size - partition.min_size) / DYNAMIC_SIZE_STEPS).max(1);
+        let size = partition.size.load(Ordering::Relaxed);
+        let new_size = if average > max_latency_us {
+            size.saturating_sub(step).max(partition.min_size)
+        } else if average < max_latency_us / 2 {
+            size.saturating_add(step).min(partition.configured_size)
+        } else {
+            size
+        };
+        if new_size != size {
+            debug!(
+                index,
+                size,
+                new_size,
+                average_latency_us = average,
+                "Moving partition size"
+            );
+            partition.size.store(new_size, Ordering::Relaxed);
+        }
     }
 }
 
@@ -53,44 +250,40 @@ impl StoreDriver for SizePartitioningStore {
         keys: &[StoreKey<'_>],
         results: &mut [Option<u64>],
     ) -> Result<(), Error> {
-        let mut non_digest_sample = None;
-        let (lower_digests, upper_digests): (Vec<_>, Vec<_>) =
-            keys.iter().map(StoreKey::borrow).partition(|k| {
-                let StoreKey::Digest(digest) = k else {
-                    non_digest_sample = Some(k.borrow().into_owned());
-                    return false;
-                };
-                digest.size_bytes() < self.partition_size
-            });
-        if let Some(non_digest) = non_digest_sample {
-            return Err(make_input_err!(
-                "SizePartitioningStore only supports Digest keys, got {non_digest:?}"
-            ));
+        let sizes = keys
+            .iter()
+            .map(|key| Self::digest_from_key(key.borrow()).map(|digest| digest.size_bytes()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let partition_indexes: Vec<usize> = sizes
+            .iter()
+            .map(|&size| self.partition_index(size))
+            .collect();
+        self.has_in_partitions(keys, &partition_indexes, results)
+            .await?;
+
+        // Objects which are missing may have been sent to a neighbouring
+        // partition before the partition sizes moved.
+        let (fallback_positions, fallback_indexes): (Vec<usize>, Vec<usize>) = results
+            .iter()
+            .enumerate()
+            .filter(|(_, result)| result.is_none())
+            .filter_map(|(position, _)| {
+                self.fallback_index(sizes[position], partition_indexes[position])
+                    .map(|index| (position, index))
+            })
+            .unzip();
+        if fallback_positions.is_empty() {
+            return Ok(());
         }
-        let (lower_results, upper_results) = join!(
-            self.lower_store.has_many(&lower_digests),
-            self.upper_store.has_many(&upper_digests),
-        );
-        let mut lower_results = match lower_results {
-            Ok(lower_results) => lower_results.into_iter(),
-            Err(err) => match upper_results {
-                Ok(_) => return Err(err),
-                Err(upper_err) => return Err(err.merge(upper_err)),
-            },
-        };
-        let mut upper_digests = upper_digests.into_iter().peekable();
-        let mut upper_results = upper_results?.into_iter();
-        for (digest, result) in keys.iter().zip(results.iter_mut()) {
-            if Some(digest) == upper_digests.peek() {
-                upper_digests.next();
-                *result = upper_results
-                    .next()
-                    .err_tip(|| "upper_results out of sync with upper_digests")?;
-            } else {
-                *result = lower_results
-                    .next()
-                    .err_tip(|| "lower_results out of sync with lower_digests")?;
-            }
+        let fallback_keys: Vec<StoreKey<'_>> = fallback_positions
+            .iter()
+            .map(|&position| keys[position].borrow())
+            .collect();
+        let mut fallback_results = vec![None; fallback_keys.len()];
+        self.has_in_partitions(&fallback_keys, &fallback_indexes, &mut fallback_results)
+            .await?;
+        for (position, result) in fallback_positions.into_iter().zip(fallback_results) {
+            results[position] = result;
         }
         Ok(())
     }
@@ -101,18 +294,17 @@ impl StoreDriver for SizePartitioningStore {
         reader: DropCloserReadHalf,
         size_info: UploadSizeInfo,
     ) -> Result<(), Error> {
-        let digest = match key {
-            StoreKey::Digest(digest) => digest,
-            other @ StoreKey::Str(_) => {
-                return Err(make_input_err!(
-                    "SizePartitioningStore only supports Digest keys, got {other:?}"
-                ));
-            }
-        };
-        if digest.size_bytes() < self.partition_size {
-            return self.lower_store.update(digest, reader, size_info).await;
+        let digest = Self::digest_from_key(key)?;
+        let index = self.partition_index(digest.size_bytes());
+        let start = Instant::now();
+        let result = self.partitions[index]
+            .store
+            .update(digest, reader, size_info)
+            .await;
+        if result.is_ok() {
+            self.record_upload_latency(index, start.elapsed());
         }
-        self.upper_store.update(digest, reader, size_info).await
+        result
     }
 
     async fn get_part(
@@ -122,23 +314,25 @@ impl StoreDriver for SizePartitioningStore {
         offset: u64,
         length: Option<u64>,
     ) -> Result<(), Error> {
-        let digest = match key {
-            StoreKey::Digest(digest) => digest,
-            other @ StoreKey::Str(_) => {
-                return Err(make_input_err!(
-                    "SizePartitioningStore only supports Digest keys, got {other:?}"
-                ));
+        let digest = Self::digest_from_key(key)?;
+        let index = self.partition_index(digest.size_bytes());
+        let result = self.partitions[index]
+            .store
+            .get_part(digest, writer, offset, length)
+            .await;
+        match result {
+            Err(err) if err.code == Code::NotFound => {
+                let Some(fallback_index) = self.fallback_index(digest.size_bytes(), index) else {
+                    return Err(err);
+                };
+                self.partitions[fallback_index]
+                    .store
+                    .get_part(digest, writer, offset, length)
+                    .await
+                    .map_err(|fallback_err| err.merge(fallback_err))
             }
-        };
-        if digest.size_bytes() < self.partition_size {
-            return self
-                .lower_store
-                .get_part(digest, writer, offset, length)
-                .await;
+            result => result,
         }
-        self.upper_store
-            .get_part(digest, writer, offset, length)
-            .await
     }
 
     fn inner_store(&self, key: Option<StoreKey>) -> &'_ dyn StoreDriver {
@@ -148,10 +342,9 @@ impl StoreDriver for SizePartitioningStore {
         let StoreKey::Digest(digest) = key else {
             return self;
         };
-        if digest.size_bytes() < self.partition_size {
-            return self.lower_store.inner_store(Some(digest));
-        }
-        self.upper_store.inner_store(Some(digest))
+        self.partitions[self.partition_index(digest.size_bytes())]
+            .store
+            .inner_store(Some(digest))
     }
 
     fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
@@ -166,8 +359,9 @@ impl StoreDriver for SizePartitioningStore {
         self: Arc<Self>,
         callback: &Arc<Box<dyn RemoveItemCallback>>,
     ) -> Result<(), Error> {
-        self.lower_store.register_remove_callback(callback)?;
-        self.upper_store.register_remove_callback(callback)?;
+        for partition in &self.partitions {
+            partition.store.register_remove_callback(callback)?;
+        }
         Ok(())
     }
 }
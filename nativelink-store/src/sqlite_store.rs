@@ -0,0 +1,410 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use nativelink_config::stores::{EvictionPolicy, SqliteSpec};
+use nativelink_error::{Code, Error, ResultExt, make_err};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
+use nativelink_util::spawn_blocking;
+use nativelink_util::store_trait::{RemoveItemCallback, StoreDriver, StoreKey, UploadSizeInfo};
+use parking_lot::Mutex;
+use rusqlite::{Connection, OptionalExtension, Params, params};
+
+use crate::cas_utils::is_zero_digest;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS objects (
+        key TEXT PRIMARY KEY NOT NULL,
+        size INTEGER NOT NULL,
+        last_access INTEGER NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS objects_last_access ON objects (last_access);
+";
+
+// How long to wait for a lock held by another connection to the database,
+// eg: a backup tool.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Most items evicted by a single statement.
+const EVICTION_BATCH_SIZE: u64 = 64;
+
+fn sqlite_error(err: rusqlite::Error) -> Error {
+    make_err!(Code::Internal, "SQLite error: {err:?}")
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as i64)
+}
+
+/// Turns a key read back from the database into a store key, so remove
+/// callbacks see digests as digests.
+fn parse_key(key: String) -> StoreKey<'static> {
+    let digest = key
+        .split_once('-')
+        .and_then(|(hash, size)| DigestInfo::try_new(hash, size.parse::<u64>().ok()?).ok());
+    digest.map_or_else(|| StoreKey::Str(key.into()), StoreKey::Digest)
+}
+
+/// Runs a `DELETE ... RETURNING key, size` statement and returns the keys
+/// and sizes of the deleted rows.
+fn delete_returning(
+    connection: &Connection,
+    sql: &str,
+    params: impl Params,
+) -> Result<Vec<(String, u64)>, Error> {
+    let mut statement = connection.prepare_cached(sql).map_err(sqlite_error)?;
+    statement
+        .query_map(params, |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+        })
+        .map_err(sqlite_error)?
+        .map(|row| row.map_err(sqlite_error))
+        .collect()
+}
+
+#[derive(Debug)]
+struct SqliteState {
+    connection: Connection,
+    /// Sum of the sizes of all items, kept in memory so eviction doesn't
+    /// have to scan the table.
+    total_bytes: u64,
+    count: u64,
+}
+
+impl SqliteState {
+    fn open(path: &str) -> Result<Self, Error> {
+        let connection = Connection::open(path)
+            .map_err(sqlite_error)
+            .err_tip(|| format!("Failed to open {path} in sqlite store"))?;
+        connection
+            .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+            .map_err(sqlite_error)
+            .err_tip(|| "Failed to enable WAL mode in sqlite store")?;
+        // In WAL mode this only risks losing the latest writes on power
+        // loss, never corrupting the database.
+        connection
+            .pragma_update(None, "synchronous", "NORMAL")
+            .map_err(sqlite_error)?;
+        connection
+            .busy_timeout(BUSY_TIMEOUT)
+            .map_err(sqlite_error)?;
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(sqlite_error)
+            .err_tip(|| "Failed to create schema in sqlite store")?;
+        let (count, total_bytes) = connection
+            .query_row(
+                "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM objects",
+                [],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+            )
+            .map_err(sqlite_error)?;
+        Ok(Self {
+            connection,
+            total_bytes: total_bytes as u64,
+            count: count as u64,
+        })
+    }
+
+    fn forget(&mut self, deleted: &[(String, u64)]) {
+        let deleted_bytes = deleted.iter().map(|(_, size)| size).sum();
+        self.total_bytes = self.total_bytes.saturating_sub(deleted_bytes);
+        self.count = self.count.saturating_sub(deleted.len() as u64);
+    }
+
+    fn insert(&mut self, key: &str, data: &[u8], now: i64) -> Result<(), Error> {
+        let transaction = self.connection.transaction().map_err(sqlite_error)?;
+        let replaced = delete_returning(
+            &transaction,
+            "DELETE FROM objects WHERE key = ?1 RETURNING key, size",
+            [key],
+        )?;
+        transaction
+            .prepare_cached(
+                "INSERT INTO objects (key, size, last_access, data) VALUES (?1, ?2, ?3, ?4)",
+            )
+            .and_then(|mut statement| statement.execute(params![key, data.len() as i64, now, data]))
+            .map_err(sqlite_error)?;
+        transaction.commit().map_err(sqlite_error)?;
+        self.forget(&replaced);
+        self.total_bytes += data.len() as u64;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Deletes the least recently used items until the store is within
+    /// `eviction_policy`. Returns the keys of the deleted items.
+    fn evict(&mut self, eviction_policy: &EvictionPolicy, now: i64) -> Result<Vec<String>, Error> {
+        let mut evicted = Vec::new();
+        if eviction_policy.max_seconds > 0 {
+            evicted.extend(delete_returning(
+                &self.connection,
+                "DELETE FROM objects WHERE last_access < ?1 RETURNING key, size",
+                [now - i64::from(eviction_policy.max_seconds) * 1000],
+            )?);
+            self.forget(&evicted);
+        }
+        let max_bytes = eviction_policy.max_bytes as u64;
+        let target_bytes = if max_bytes > 0 && self.total_bytes > max_bytes {
+            max_bytes.saturating_sub(eviction_policy.evict_bytes as u64)
+        } else {
+            u64::MAX
+        };
+        let max_count = eviction_policy.max_count;
+        loop {
+            let limit = if self.total_bytes > target_bytes {
+                EVICTION_BATCH_SIZE
+            } else if max_count > 0 && self.count > max_count {
+                (self.count - max_count).min(EVICTION_BATCH_SIZE)
+            } else {
+                break;
+            };
+            let deleted = delete_returning(
+                &self.connection,
+                "DELETE FROM objects WHERE key IN \
+                 (SELECT key FROM objects ORDER BY last_access, rowid LIMIT ?1) RETURNING key, size",
+                [limit as i64],
+            )?;
+            if deleted.is_empty() {
+                break;
+            }
+            self.forget(&deleted);
+            evicted.extend(deleted);
+        }
+        Ok(evicted.into_iter().map(|(key, _)| key).collect())
+    }
+}
+
+#[derive(MetricsComponent)]
+pub struct SqliteStore {
+    #[metric(help = "Path of the database file")]
+    path: String,
+    state: Arc<Mutex<SqliteState>>,
+    eviction_policy: EvictionPolicy,
+    remove_callbacks: Mutex<Vec<Arc<Box<dyn RemoveItemCallback>>>>,
+    #[metric(help = "Number of items evicted from the database")]
+    evicted_items: AtomicU64,
+}
+
+impl core::fmt::Debug for SqliteStore {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SqliteStore")
+            .field("path", &self.path)
+            .field("eviction_policy", &self.eviction_policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SqliteStore {
+    pub async fn new(spec: &SqliteSpec) -> Result<Arc<Self>, Error> {
+        let path = spec.path.clone();
+        let state = spawn_blocking!("sqlite_store_open", move || SqliteState::open(&path))
+            .await
+            .map_err(|e| make_err!(Code::Internal, "Failed to open sqlite store: {e:?}"))??;
+        Ok(Arc::new(Self {
+            path: spec.path.clone(),
+            state: Arc::new(Mutex::new(state)),
+            eviction_policy: spec.eviction_policy.unwrap_or_default(),
+            remove_callbacks: Mutex::new(Vec::new()),
+            evicted_items: AtomicU64::new(0),
+        }))
+    }
+
+    /// Runs `f` on a blocking thread, as `SQLite` calls block on disk IO.
+    async fn with_state<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut SqliteState) -> Result<T, Error> + Send + 'static,
+    {
+        let state = self.state.clone();
+        spawn_blocking!("sqlite_store_query", move || f(&mut state.lock()))
+            .await
+            .map_err(|e| make_err!(Code::Internal, "SQLite task failed in sqlite store: {e:?}"))?
+    }
+
+    /// Returns the size of `key` and marks it as recently used.
+    async fn touch(&self, key: String) -> Result<Option<u64>, Error> {
+        self.with_state(move |state| {
+            state
+                .connection
+                .prepare_cached("UPDATE objects SET last_access = ?1 WHERE key = ?2 RETURNING size")
+                .and_then(|mut statement| {
+                    statement
+                        .query_row(params![now_millis(), key], |row| row.get::<_, i64>(0))
+                        .optional()
+                })
+                .map(|size| size.map(|size| size as u64))
+                .map_err(sqlite_error)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl StoreDriver for SqliteStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        for (key, result) in keys.iter().zip(results.iter_mut()) {
+            if is_zero_digest(key.borrow()) {
+                *result = Some(0);
+                continue;
+            }
+            *result = self
+                .touch(key.as_str().into_owned())
+                .await
+                .err_tip(|| "In SqliteStore::has_with_results")?;
+        }
+        Ok(())
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        let key = key.as_str().into_owned();
+        self.with_state(move |state| {
+            let deleted = delete_returning(
+                &state.connection,
+                "DELETE FROM objects WHERE key = ?1 RETURNING key, size",
+                [key],
+            )?;
+            state.forget(&deleted);
+            Ok(!deleted.is_empty())
+        })
+        .await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        _size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let data = reader
+            .consume(None)
+            .await
+            .err_tip(|| "Failed to collect all bytes from reader in sqlite_store::update")?;
+        let key = key.as_str().into_owned();
+        let eviction_policy = self.eviction_policy;
+        let evicted = self
+            .with_state(move |state| {
+                let now = now_millis();
+                state.insert(&key, &data, now)?;
+                state.evict(&eviction_policy, now)
+            })
+            .await
+            .err_tip(|| "In SqliteStore::update")?;
+        self.evicted_items
+            .fetch_add(evicted.len() as u64, Ordering::Relaxed);
+        if !evicted.is_empty() {
+            let remove_callbacks = self.remove_callbacks.lock().clone();
+            for key in evicted.into_iter().map(parse_key) {
+                for callback in &remove_callbacks {
+                    callback.callback(&key).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        if is_zero_digest(key.borrow()) {
+            writer
+                .send_eof()
+                .err_tip(|| "Failed to send zero EOF in sqlite store get_part")?;
+            return Ok(());
+        }
+        let owned_key = key.as_str().into_owned();
+        let data = self
+            .with_state(move |state| {
+                // `substr()` counts from 1 and works on bytes for blobs.
+                state
+                    .connection
+                    .prepare_cached(
+                        "UPDATE objects SET last_access = ?1 WHERE key = ?2 \
+                         RETURNING substr(data, ?3, ?4)",
+                    )
+                    .and_then(|mut statement| {
+                        statement
+                            .query_row(
+                                params![
+                                    now_millis(),
+                                    owned_key,
+                                    i64::try_from(offset).unwrap_or(i64::MAX).saturating_add(1),
+                                    length.map_or(i64::MAX, |length| {
+                                        i64::try_from(length).unwrap_or(i64::MAX)
+                                    }),
+                                ],
+                                |row| row.get::<_, Vec<u8>>(0),
+                            )
+                            .optional()
+                    })
+                    .map_err(sqlite_error)
+            })
+            .await
+            .err_tip(|| "In SqliteStore::get_part")?
+            .err_tip_with_code(|_| (Code::NotFound, format!("Key {key:?} not found")))?;
+        if !data.is_empty() {
+            writer
+                .send(Bytes::from(data))
+                .await
+                .err_tip(|| "Failed to write data in sqlite store")?;
+        }
+        writer
+            .send_eof()
+            .err_tip(|| "Failed to write EOF in sqlite store get_part")?;
+        Ok(())
+    }
+
+    fn inner_store(&self, _digest: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        self.remove_callbacks.lock().push(callback.clone());
+        Ok(())
+    }
+}
+
+default_health_status_indicator!(SqliteStore);
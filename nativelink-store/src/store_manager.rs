@@ -12,8 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use core::sync::atomic::{AtomicU64, Ordering};
 use std::collections::HashMap;
+use std::sync::Arc;
 
+use nativelink_error::{Error, make_input_err};
 use nativelink_metric::{MetricsComponent, RootMetricsComponent};
 use nativelink_util::store_trait::Store;
 use parking_lot::RwLock;
@@ -22,18 +25,26 @@ use parking_lot::RwLock;
 pub struct StoreManager {
     #[metric]
     stores: RwLock<HashMap<String, Store>>,
+    #[metric(help = "Number of times a store was added, repointed or removed")]
+    generation: Arc<AtomicU64>,
 }
 
 impl StoreManager {
     pub fn new() -> Self {
         Self {
             stores: RwLock::new(HashMap::new()),
+            generation: Arc::new(AtomicU64::new(0)),
         }
     }
 
+    /// Registers `store` under `name`, replacing the store previously
+    /// registered under that name, if any. Holders of the previous store,
+    /// like services that looked it up at startup, keep using it; only
+    /// `RefStore`s and later lookups see the new store.
     pub fn add_store(&self, name: &str, store: Store) {
         let mut stores = self.stores.write();
         stores.insert(name.to_string(), store);
+        self.generation.fetch_add(1, Ordering::AcqRel);
     }
 
     pub fn get_store(&self, name: &str) -> Option<Store> {
@@ -43,6 +54,46 @@ impl StoreManager {
         }
         None
     }
+
+    /// Makes `name` resolve to the store currently registered as `target`.
+    /// Every `RefStore` referencing `name` sends new requests to it, requests
+    /// already running finish on the previous store.
+    pub fn repoint_store(&self, name: &str, target: &str) -> Result<(), Error> {
+        let mut stores = self.stores.write();
+        let store = stores
+            .get(target)
+            .cloned()
+            .ok_or_else(|| make_input_err!("Store '{target}' does not exist"))?;
+        stores.insert(name.to_string(), store);
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    /// Unregisters the store registered under `name` and returns it. The
+    /// store lives on until requests already running on it have finished.
+    pub fn remove_store(&self, name: &str) -> Option<Store> {
+        let store = self.stores.write().remove(name)?;
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        Some(store)
+    }
+
+    /// Returns the names of all registered stores, sorted.
+    pub fn store_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.stores.read().keys().cloned().collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Number of times a store was added, repointed or removed.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Returns the counter behind `generation()`, to watch for changes
+    /// without holding on to the `StoreManager`.
+    pub(crate) fn generation_counter(&self) -> Arc<AtomicU64> {
+        self.generation.clone()
+    }
 }
 
 impl RootMetricsComponent for StoreManager {}
@@ -0,0 +1,555 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::ops::Range;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use futures::join;
+use nativelink_config::stores::{EvictionPolicy, TieredSpec};
+use nativelink_error::{Code, Error, ResultExt, error_if, make_err};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
+use nativelink_util::buf_channel::{
+    DropCloserReadHalf, DropCloserWriteHalf, make_buf_channel_pair,
+};
+use nativelink_util::evicting_map::{EvictingMap, LenEntry};
+use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
+use nativelink_util::store_trait::{
+    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreKeyBorrow, StoreLike, UploadSizeInfo,
+};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::fast_slow_store::FastSlowStore;
+
+// Number of objects whose reads from slower tiers are counted for
+// promotion. Counts of objects beyond that are forgotten.
+const MAX_READ_COUNTS: u64 = 100_000;
+
+/// Number of times an object was read from a slower tier.
+#[derive(Debug, Clone, Default)]
+struct ReadCount(Arc<AtomicU32>);
+
+impl LenEntry for ReadCount {
+    fn len(&self) -> u64 {
+        0
+    }
+
+    fn is_empty(&self) -> bool {
+        true
+    }
+}
+
+/// Request to move an object from the tier to the next tier.
+type Demotion = (usize, StoreKey<'static>);
+
+/// An object the tiered store placed in a tier that demotes objects. Once
+/// evicted from the tier's placement map the object moves to the next tier.
+#[derive(Debug)]
+struct PlacedObject {
+    demotions: mpsc::UnboundedSender<Demotion>,
+    tier_index: usize,
+    key: StoreKey<'static>,
+    size: u64,
+}
+
+impl LenEntry for PlacedObject {
+    fn len(&self) -> u64 {
+        self.size
+    }
+
+    fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    async fn unref(&self) {
+        // Fails only if the store is being dropped.
+        drop(self.demotions.send((self.tier_index, self.key.clone())));
+    }
+}
+
+type PlacementMap = EvictingMap<StoreKeyBorrow, StoreKey<'static>, Arc<PlacedObject>, SystemTime>;
+
+#[derive(Debug, Default, MetricsComponent)]
+struct TierMetrics {
+    #[metric(help = "Number of reads served by this tier")]
+    hit_count: AtomicU64,
+    #[metric(help = "Bytes downloaded from this tier")]
+    downloaded_bytes: AtomicU64,
+    #[metric(help = "Number of objects copied into this tier from slower tiers")]
+    promotion_count: AtomicU64,
+    #[metric(help = "Bytes copied into this tier from slower tiers")]
+    promoted_bytes: AtomicU64,
+    #[metric(help = "Number of objects moved from this tier to the next tier")]
+    demotion_count: AtomicU64,
+    #[metric(help = "Bytes moved from this tier to the next tier")]
+    demoted_bytes: AtomicU64,
+}
+
+#[derive(Debug, MetricsComponent)]
+struct Tier {
+    #[metric(group = "store")]
+    store: Store,
+    #[metric(help = "Reads from slower tiers after which an object is copied into this tier")]
+    promote_after_reads: u32,
+    #[metric(help = "If uploads skip this tier")]
+    skip_uploads: bool,
+    /// Objects placed in this tier, set if the tier demotes objects.
+    #[metric(group = "placed_objects")]
+    placed: Option<PlacementMap>,
+    #[metric]
+    metrics: TierMetrics,
+}
+
+#[derive(Debug, MetricsComponent)]
+pub struct TieredStore {
+    #[metric(group = "tiers")]
+    tiers: Vec<Tier>,
+    read_counts: EvictingMap<StoreKeyBorrow, StoreKey<'static>, ReadCount, SystemTime>,
+    demotions: mpsc::UnboundedSender<Demotion>,
+}
+
+impl TieredStore {
+    pub fn new(spec: &TieredSpec, stores: Vec<Store>) -> Result<Arc<Self>, Error> {
+        error_if!(
+            spec.tiers.len() != stores.len(),
+            "Config tiers do not match stores length"
+        );
+        error_if!(
+            spec.tiers.is_empty(),
+            "TieredStore must have at least one tier"
+        );
+        error_if!(
+            spec.tiers.iter().all(|tier| tier.skip_uploads),
+            "TieredStore must have at least one tier without skip_uploads"
+        );
+        error_if!(
+            spec.tiers.last().is_some_and(|tier| tier.max_bytes != 0),
+            "The last tier of TieredStore can't set max_bytes, as there is no tier to move objects to"
+        );
+        let (demotions, mut demotions_rx) = mpsc::unbounded_channel();
+        let store = Arc::new(Self {
+            tiers: spec
+                .tiers
+                .iter()
+                .zip(stores)
+                .map(|(tier_spec, store)| Tier {
+                    store,
+                    promote_after_reads: tier_spec.promote_after_reads.max(1),
+                    skip_uploads: tier_spec.skip_uploads,
+                    placed: (tier_spec.max_bytes != 0).then(|| {
+                        EvictingMap::new(
+                            &EvictionPolicy {
+                                max_bytes: tier_spec.max_bytes as usize,
+                                ..Default::default()
+                            },
+                            SystemTime::now(),
+                        )
+                    }),
+                    metrics: TierMetrics::default(),
+                })
+                .collect(),
+            read_counts: EvictingMap::new(
+                &EvictionPolicy {
+                    max_count: MAX_READ_COUNTS,
+                    ..Default::default()
+                },
+                SystemTime::now(),
+            ),
+            demotions,
+        });
+
+        if store.tiers.iter().any(|tier| tier.placed.is_some()) {
+            let weak_store = Arc::downgrade(&store);
+            background_spawn!("tiered_store_demotions", async move {
+                while let Some((tier_index, key)) = demotions_rx.recv().await {
+                    let Some(store) = weak_store.upgrade() else {
+                        return;
+                    };
+                    store.demote(tier_index, key).await;
+                }
+            });
+        }
+        Ok(store)
+    }
+
+    pub fn tier_store(&self, index: usize) -> Option<&Store> {
+        self.tiers.get(index).map(|tier| &tier.store)
+    }
+
+    /// Records a read of `key` served by the tier at `tier_index` and
+    /// returns the faster tiers the object should be copied into.
+    async fn promotion_targets(&self, tier_index: usize, key: &StoreKey<'_>) -> Vec<usize> {
+        if tier_index == 0 {
+            return Vec::new();
+        }
+        let key = key.borrow().into_owned();
+        let read_count = if let Some(read_count) = self.read_counts.get(&key).await {
+            read_count
+        } else {
+            let read_count = ReadCount::default();
+            self.read_counts
+                .insert(key.into(), read_count.clone())
+                .await;
+            read_count
+        };
+        let reads = read_count.0.fetch_add(1, Ordering::Relaxed) + 1;
+        (0..tier_index)
+            .filter(|&index| self.tiers[index].promote_after_reads <= reads)
+            .collect()
+    }
+
+    /// Remembers that the tiered store placed `key` in the tier, so it is
+    /// moved to the next tier once the tier holds too much.
+    async fn record_placement(&self, tier_index: usize, key: StoreKey<'_>, size: u64) {
+        let Some(placed) = &self.tiers[tier_index].placed else {
+            return;
+        };
+        let key = key.into_owned();
+        // Looking the object up marks it as recently used.
+        if placed.get(&key).await.is_some() {
+            return;
+        }
+        placed
+            .insert(
+                key.clone().into(),
+                Arc::new(PlacedObject {
+                    demotions: self.demotions.clone(),
+                    tier_index,
+                    key,
+                    size,
+                }),
+            )
+            .await;
+    }
+
+    async fn demote(&self, tier_index: usize, key: StoreKey<'static>) {
+        if let Some(placed) = &self.tiers[tier_index].placed {
+            // The object was placed in the tier again meanwhile.
+            if placed.size_for_key(&key).await.is_some() {
+                return;
+            }
+        }
+        if let Err(err) = self.move_to_next_tier(tier_index, key.borrow()).await {
+            warn!(
+                ?err,
+                ?key,
+                tier_index,
+                "Failed to demote object in tiered store"
+            );
+        }
+    }
+
+    async fn move_to_next_tier(&self, tier_index: usize, key: StoreKey<'_>) -> Result<(), Error> {
+        let tier = &self.tiers[tier_index];
+        let next_index = tier_index + 1;
+        let next_tier = self
+            .tiers
+            .get(next_index)
+            .err_tip(|| "No tier to demote objects to in tiered store")?;
+        let Some(size) = tier
+            .store
+            .has(key.borrow())
+            .await
+            .err_tip(|| "Failed to run has() in tiered store demotion")?
+        else {
+            // The tier already evicted it on its own.
+            return Ok(());
+        };
+        if next_tier
+            .store
+            .has(key.borrow())
+            .await
+            .err_tip(|| "Failed to run has() in tiered store demotion")?
+            .is_none()
+        {
+            let (tx, rx) = make_buf_channel_pair();
+            let (get_res, update_res) = join!(
+                tier.store.get(key.borrow(), tx),
+                next_tier
+                    .store
+                    .update(key.borrow(), rx, UploadSizeInfo::ExactSize(size))
+            );
+            get_res
+                .merge(update_res)
+                .err_tip(|| "Failed to copy object to next tier in tiered store")?;
+        }
+        self.record_placement(next_index, key.borrow(), size).await;
+        match tier.store.remove(key.borrow()).await {
+            // Stores which can't remove objects evict them on their own.
+            Ok(_) => {}
+            Err(err) if err.code == Code::Unimplemented => {}
+            Err(err) => {
+                return Err(err.append("Failed to remove demoted object in tiered store"));
+            }
+        }
+        tier.metrics.demotion_count.fetch_add(1, Ordering::Relaxed);
+        tier.metrics
+            .demoted_bytes
+            .fetch_add(size, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Streams `send_range` of the object from the tier at `source_index`
+    /// to `writer` while copying all of it into the `targets` tiers.
+    async fn get_and_promote(
+        &self,
+        source_index: usize,
+        targets: &[usize],
+        key: StoreKey<'_>,
+        size: u64,
+        writer: &mut DropCloserWriteHalf,
+        send_range: Range<u64>,
+    ) -> Result<(), Error> {
+        let (source_tx, mut source_rx) = make_buf_channel_pair();
+        let (mut target_txs, target_rxs): (Vec<_>, Vec<_>) =
+            targets.iter().map(|_| make_buf_channel_pair()).unzip();
+
+        let data_stream_fut = async {
+            let mut bytes_received: u64 = 0;
+            loop {
+                let buffer = source_rx
+                    .recv()
+                    .await
+                    .err_tip(|| "Failed to read data buffer in tiered store")?;
+                if buffer.is_empty() {
+                    for target_tx in &mut target_txs {
+                        target_tx
+                            .send_eof()
+                            .err_tip(|| "Failed to write eof to tier in tiered store")?;
+                    }
+                    return Result::<(), Error>::Ok(());
+                }
+                let buffer_len = buffer.len() as u64;
+                if let Some(range) = FastSlowStore::calculate_range(
+                    &(bytes_received..bytes_received + buffer_len),
+                    &send_range,
+                )? {
+                    writer
+                        .send(buffer.slice(range))
+                        .await
+                        .err_tip(|| "Failed to write result to writer in tiered store")?;
+                }
+                bytes_received += buffer_len;
+                join_all(
+                    target_txs
+                        .iter_mut()
+                        .map(|target_tx| target_tx.send(buffer.clone())),
+                )
+                .await
+                .into_iter()
+                .collect::<Result<(), Error>>()
+                .err_tip(|| "Failed to write to tier in tiered store")?;
+            }
+        };
+        let source_fut = self.tiers[source_index].store.get(key.borrow(), source_tx);
+        let targets_fut = join_all(targets.iter().zip(target_rxs).map(|(&index, rx)| {
+            self.tiers[index]
+                .store
+                .update(key.borrow(), rx, UploadSizeInfo::ExactSize(size))
+        }));
+
+        let (data_stream_res, source_res, targets_res) =
+            join!(data_stream_fut, source_fut, targets_fut);
+        source_res
+            .merge(data_stream_res)
+            .merge(targets_res.into_iter().collect::<Result<(), Error>>())?;
+        writer
+            .send_eof()
+            .err_tip(|| "Failed to write eof to writer in tiered store")?;
+
+        for &index in targets {
+            let metrics = &self.tiers[index].metrics;
+            metrics.promotion_count.fetch_add(1, Ordering::Relaxed);
+            metrics.promoted_bytes.fetch_add(size, Ordering::Relaxed);
+            self.record_placement(index, key.borrow(), size).await;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StoreDriver for TieredStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        results.fill(None);
+        for tier in &self.tiers {
+            let missing: Vec<usize> = results
+                .iter()
+                .enumerate()
+                .filter_map(|(index, result)| result.is_none().then_some(index))
+                .collect();
+            if missing.is_empty() {
+                break;
+            }
+            let missing_keys: Vec<StoreKey<'_>> =
+                missing.iter().map(|&index| keys[index].borrow()).collect();
+            let tier_results = tier
+                .store
+                .has_many(&missing_keys)
+                .await
+                .err_tip(|| "Failed to run has() in tiered store")?;
+            for (index, tier_result) in missing.into_iter().zip(tier_results) {
+                results[index] = tier_result;
+            }
+        }
+        Ok(())
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let upload_tiers: Vec<usize> = (0..self.tiers.len())
+            .filter(|&index| !self.tiers[index].skip_uploads)
+            .collect();
+        if let [index] = upload_tiers[..] {
+            self.tiers[index]
+                .store
+                .update(key.borrow(), reader, size_info)
+                .await?;
+        } else {
+            let (mut txs, rxs): (Vec<_>, Vec<_>) =
+                upload_tiers.iter().map(|_| make_buf_channel_pair()).unzip();
+            let data_stream_fut = async move {
+                loop {
+                    let buffer = reader
+                        .recv()
+                        .await
+                        .err_tip(|| "Failed to read buffer in tiered store")?;
+                    if buffer.is_empty() {
+                        for tx in &mut txs {
+                            tx.send_eof()
+                                .err_tip(|| "Failed to write eof to tier in tiered store update")?;
+                        }
+                        return Result::<(), Error>::Ok(());
+                    }
+                    join_all(txs.iter_mut().map(|tx| tx.send(buffer.clone())))
+                        .await
+                        .into_iter()
+                        .collect::<Result<(), Error>>()
+                        .err_tip(|| "Failed to send buffer to tier in tiered store update")?;
+                }
+            };
+            let updates_fut =
+                join_all(upload_tiers.iter().zip(rxs).map(|(&index, rx)| {
+                    self.tiers[index].store.update(key.borrow(), rx, size_info)
+                }));
+            let (data_stream_res, updates_res) = join!(data_stream_fut, updates_fut);
+            data_stream_res.merge(updates_res.into_iter().collect::<Result<(), Error>>())?;
+        }
+
+        let size = match size_info {
+            UploadSizeInfo::ExactSize(size) => Some(size),
+            UploadSizeInfo::MaxSize(_) => None,
+        };
+        for index in upload_tiers {
+            if self.tiers[index].placed.is_none() {
+                continue;
+            }
+            let size = match size {
+                Some(size) => Some(size),
+                None => self.tiers[index].store.has(key.borrow()).await?,
+            };
+            if let Some(size) = size {
+                self.record_placement(index, key.borrow(), size).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        for (index, tier) in self.tiers.iter().enumerate() {
+            let Some(size) = tier
+                .store
+                .has(key.borrow())
+                .await
+                .err_tip(|| "Failed to run has() in tiered store")?
+            else {
+                continue;
+            };
+            tier.metrics.hit_count.fetch_add(1, Ordering::Relaxed);
+            if let Some(placed) = &tier.placed {
+                // Marks the object as recently used.
+                placed.get(&key.borrow().into_owned()).await;
+            }
+            let targets = self.promotion_targets(index, &key).await;
+            let bytes_written = writer.get_bytes_written();
+            let result = if targets.is_empty() {
+                tier.store
+                    .get_part(key.borrow(), writer, offset, length)
+                    .await
+            } else {
+                let send_range = offset..length.map_or(u64::MAX, |length| length + offset);
+                self.get_and_promote(index, &targets, key.borrow(), size, writer, send_range)
+                    .await
+            };
+            let downloaded_bytes = writer.get_bytes_written() - bytes_written;
+            tier.metrics
+                .downloaded_bytes
+                .fetch_add(downloaded_bytes, Ordering::Relaxed);
+            match result {
+                // The object was evicted or demoted since `has()`, so try
+                // the next tier.
+                Err(err) if err.code == Code::NotFound && downloaded_bytes == 0 => {}
+                result => return result,
+            }
+        }
+        Err(make_err!(
+            Code::NotFound,
+            "Object {} not found in any tier of tiered store",
+            key.as_str()
+        ))
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any<'a>(&'a self) -> &'a (dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        for tier in &self.tiers {
+            tier.store.register_remove_callback(callback)?;
+        }
+        Ok(())
+    }
+}
+
+default_health_status_indicator!(TieredStore);
@@ -0,0 +1,90 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nativelink_config::stores::{AdmissionSpec, FastSlowSpec, MemorySpec, StoreSpec};
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_store::admission_store::AdmissionStore;
+use nativelink_store::fast_slow_store::FastSlowStore;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{Store, StoreLike};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALID_HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+const DATA: &str = "0123456789";
+
+fn make_admission_store(max_object_size: u64, min_access_count: u8) -> (Store, Store) {
+    let backend = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = AdmissionStore::new(
+        &AdmissionSpec {
+            backend: StoreSpec::Memory(MemorySpec::default()),
+            max_object_size,
+            min_access_count,
+            access_window: 0,
+        },
+        backend.clone(),
+    );
+    (Store::new(store), backend)
+}
+
+#[nativelink_test]
+async fn max_object_size_test() -> Result<(), Error> {
+    let (store, backend) = make_admission_store(5, 0);
+    let large_digest = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+    store.update_oneshot(large_digest, DATA.into()).await?;
+    assert_eq!(backend.has(large_digest).await?, None);
+
+    let small_digest = DigestInfo::try_new(VALID_HASH2, 3)?;
+    store.update_oneshot(small_digest, "012".into()).await?;
+    assert_eq!(backend.has(small_digest).await?, Some(3));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn min_access_count_test() -> Result<(), Error> {
+    let (store, backend) = make_admission_store(0, 2);
+    let digest = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+    store.update_oneshot(digest, DATA.into()).await?;
+    assert_eq!(backend.has(digest).await?, None);
+
+    store.update_oneshot(digest, DATA.into()).await?;
+    assert_eq!(backend.get_part_unchunked(digest, 0, None).await?, DATA);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn fast_slow_caches_on_second_access_test() -> Result<(), Error> {
+    let (fast_store, fast_backend) = make_admission_store(0, 2);
+    let slow_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = Store::new(FastSlowStore::new(
+        &FastSlowSpec {
+            fast: StoreSpec::Memory(MemorySpec::default()),
+            slow: StoreSpec::Memory(MemorySpec::default()),
+            read_ahead: false,
+        },
+        fast_store,
+        slow_store.clone(),
+    ));
+    let digest = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+    store.update_oneshot(digest, DATA.into()).await?;
+    assert_eq!(slow_store.has(digest).await?, Some(DATA.len() as u64));
+    assert_eq!(fast_backend.has(digest).await?, None);
+
+    // Reading the object populates the fast store again, which admits it.
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, DATA);
+    assert_eq!(fast_backend.has(digest).await?, Some(DATA.len() as u64));
+    Ok(())
+}
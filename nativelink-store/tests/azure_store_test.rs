@@ -0,0 +1,285 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::time::Duration;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use futures::{Stream, stream};
+use mock_instant::thread_local::MockClock;
+use nativelink_config::stores::{CommonObjectSpec, ExperimentalAzureSpec, Retry};
+use nativelink_error::{Code, Error, make_err};
+use nativelink_macro::nativelink_test;
+use nativelink_store::azure_client::{AzureBlobOperations, BlobProperties};
+use nativelink_store::azure_store::AzureStore;
+use nativelink_util::buf_channel::make_buf_channel_pair;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::instant_wrapper::MockInstantWrapped;
+use nativelink_util::store_trait::{StoreKey, StoreLike, UploadSizeInfo};
+use pretty_assertions::assert_eq;
+
+const CONTAINER_NAME: &str = "test-container";
+const KEY_PREFIX: &str = "test-prefix/";
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+
+#[derive(Debug, Default)]
+struct MockAzureBlobOperations {
+    /// Blob name -> (content, last modified unix seconds).
+    blobs: Mutex<HashMap<String, (Bytes, i64)>>,
+    /// (Blob name, block id) -> content.
+    staged_blocks: Mutex<HashMap<(String, String), Bytes>>,
+    /// Number of requests that fail before requests succeed again.
+    failures_left: Mutex<usize>,
+}
+
+impl MockAzureBlobOperations {
+    fn add_blob(&self, blob_name: &str, content: &[u8], last_modified_unix_s: i64) {
+        self.blobs.lock().unwrap().insert(
+            blob_name.to_string(),
+            (Bytes::copy_from_slice(content), last_modified_unix_s),
+        );
+    }
+
+    fn blob(&self, blob_name: &str) -> Option<Bytes> {
+        self.blobs
+            .lock()
+            .unwrap()
+            .get(blob_name)
+            .map(|(content, _)| content.clone())
+    }
+
+    fn maybe_fail(&self) -> Result<(), Error> {
+        let mut failures_left = self.failures_left.lock().unwrap();
+        if *failures_left == 0 {
+            return Ok(());
+        }
+        *failures_left -= 1;
+        Err(make_err!(Code::Unavailable, "Simulated azure failure"))
+    }
+}
+
+impl AzureBlobOperations for MockAzureBlobOperations {
+    async fn get_blob_properties(&self, blob_name: &str) -> Result<Option<BlobProperties>, Error> {
+        self.maybe_fail()?;
+        Ok(self
+            .blobs
+            .lock()
+            .unwrap()
+            .get(blob_name)
+            .map(|(content, last_modified_unix_s)| BlobProperties {
+                content_length: content.len() as u64,
+                last_modified_unix_s: *last_modified_unix_s,
+            }))
+    }
+
+    async fn get_blob(
+        &self,
+        blob_name: &str,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<Box<dyn Stream<Item = Result<Bytes, Error>> + Send + Unpin>, Error> {
+        self.maybe_fail()?;
+        let content = self
+            .blob(blob_name)
+            .ok_or_else(|| make_err!(Code::NotFound, "Blob {blob_name} not found"))?;
+        let end = end.map_or(content.len(), |end| (end as usize).min(content.len()));
+        Ok(Box::new(stream::iter([Ok(
+            content.slice(start as usize..end)
+        )])))
+    }
+
+    async fn put_blob(&self, blob_name: &str, content: Bytes) -> Result<(), Error> {
+        self.maybe_fail()?;
+        self.blobs
+            .lock()
+            .unwrap()
+            .insert(blob_name.to_string(), (content, 0));
+        Ok(())
+    }
+
+    async fn put_block(
+        &self,
+        blob_name: &str,
+        block_id: &str,
+        content: Bytes,
+    ) -> Result<(), Error> {
+        self.maybe_fail()?;
+        self.staged_blocks
+            .lock()
+            .unwrap()
+            .insert((blob_name.to_string(), block_id.to_string()), content);
+        Ok(())
+    }
+
+    async fn put_block_list(&self, blob_name: &str, block_ids: &[String]) -> Result<(), Error> {
+        self.maybe_fail()?;
+        let mut staged_blocks = self.staged_blocks.lock().unwrap();
+        let mut content = Vec::new();
+        for block_id in block_ids {
+            let block = staged_blocks
+                .remove(&(blob_name.to_string(), block_id.clone()))
+                .ok_or_else(|| make_err!(Code::InvalidArgument, "Block {block_id} not staged"))?;
+            content.extend_from_slice(&block);
+        }
+        self.blobs
+            .lock()
+            .unwrap()
+            .insert(blob_name.to_string(), (Bytes::from(content), 0));
+        Ok(())
+    }
+}
+
+fn create_test_store(
+    ops: Arc<MockAzureBlobOperations>,
+    spec: ExperimentalAzureSpec,
+) -> Result<Arc<AzureStore<MockAzureBlobOperations, fn() -> MockInstantWrapped>>, Error> {
+    AzureStore::new_with_ops(
+        &ExperimentalAzureSpec {
+            container: CONTAINER_NAME.to_string(),
+            common: CommonObjectSpec {
+                key_prefix: Some(KEY_PREFIX.to_string()),
+                ..spec.common
+            },
+            ..spec
+        },
+        ops,
+        MockInstantWrapped::default,
+    )
+}
+
+fn blob_name(key: &StoreKey) -> String {
+    format!("{KEY_PREFIX}{}", key.as_str())
+}
+
+#[nativelink_test]
+async fn has_object_found_and_not_found() -> Result<(), Error> {
+    let ops = Arc::new(MockAzureBlobOperations::default());
+    let store = create_test_store(ops.clone(), ExperimentalAzureSpec::default())?;
+    let store_key: StoreKey = DigestInfo::try_new(VALID_HASH1, 5)?.into();
+
+    assert_eq!(store.has(store_key.clone()).await?, None);
+
+    ops.add_blob(&blob_name(&store_key), &[1, 2, 3, 4, 5], 0);
+    assert_eq!(store.has(store_key).await?, Some(5));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn has_object_expired() -> Result<(), Error> {
+    const EXPIRE_AFTER_S: u32 = 60;
+    let ops = Arc::new(MockAzureBlobOperations::default());
+    let store = create_test_store(
+        ops.clone(),
+        ExperimentalAzureSpec {
+            common: CommonObjectSpec {
+                consider_expired_after_s: EXPIRE_AFTER_S,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )?;
+    let store_key: StoreKey = DigestInfo::try_new(VALID_HASH1, 5)?.into();
+    ops.add_blob(&blob_name(&store_key), &[1, 2, 3, 4, 5], 1_000);
+
+    MockClock::set_time(Duration::from_secs(1_000 + u64::from(EXPIRE_AFTER_S) - 1));
+    assert_eq!(store.has(store_key.clone()).await?, Some(5));
+
+    MockClock::set_time(Duration::from_secs(1_000 + u64::from(EXPIRE_AFTER_S)));
+    assert_eq!(store.has(store_key).await?, None);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn small_upload_is_retried() -> Result<(), Error> {
+    let ops = Arc::new(MockAzureBlobOperations::default());
+    let store = create_test_store(
+        ops.clone(),
+        ExperimentalAzureSpec {
+            common: CommonObjectSpec {
+                retry: Retry {
+                    max_retries: 1,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )?;
+    let store_key: StoreKey = DigestInfo::try_new(VALID_HASH1, 5)?.into();
+    *ops.failures_left.lock().unwrap() = 1;
+
+    store
+        .update_oneshot(store_key.clone(), Bytes::from_static(b"hello"))
+        .await?;
+
+    assert_eq!(
+        ops.blob(&blob_name(&store_key)),
+        Some(Bytes::from_static(b"hello"))
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn unknown_size_upload_commits_blocks_in_order() -> Result<(), Error> {
+    const DATA: &[u8] = b"0123456789";
+    let ops = Arc::new(MockAzureBlobOperations::default());
+    let store = create_test_store(
+        ops.clone(),
+        ExperimentalAzureSpec {
+            block_size: Some(4),
+            ..Default::default()
+        },
+    )?;
+    let store_key: StoreKey = DigestInfo::try_new(VALID_HASH1, DATA.len() as u64)?.into();
+
+    let (mut tx, rx) = make_buf_channel_pair();
+    let send_fut = async move {
+        tx.send(Bytes::from_static(DATA)).await?;
+        tx.send_eof()
+    };
+    let (send_result, update_result) = futures::join!(
+        send_fut,
+        store.update(
+            store_key.clone(),
+            rx,
+            UploadSizeInfo::MaxSize(DATA.len() as u64)
+        )
+    );
+    send_result?;
+    update_result?;
+
+    assert_eq!(
+        ops.blob(&blob_name(&store_key)),
+        Some(Bytes::from_static(DATA))
+    );
+    assert!(
+        ops.staged_blocks.lock().unwrap().is_empty(),
+        "All staged blocks should be committed"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn get_part_reads_range() -> Result<(), Error> {
+    let ops = Arc::new(MockAzureBlobOperations::default());
+    let store = create_test_store(ops.clone(), ExperimentalAzureSpec::default())?;
+    let store_key: StoreKey = DigestInfo::try_new(VALID_HASH1, 10)?.into();
+    ops.add_blob(&blob_name(&store_key), b"0123456789", 0);
+
+    let data = store.get_part_unchunked(store_key, 2, Some(5)).await?;
+
+    assert_eq!(data, Bytes::from_static(b"23456"));
+    Ok(())
+}
@@ -0,0 +1,218 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use nativelink_config::stores::{
+    CircuitBreakerProbe, CircuitBreakerSpec, MemorySpec, NoopSpec, StoreSpec,
+};
+use nativelink_error::{Code, Error, make_err};
+use nativelink_macro::nativelink_test;
+use nativelink_metric::MetricsComponent;
+use nativelink_store::circuit_breaker_store::CircuitBreakerStore;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::health_utils::{
+    HealthStatus, HealthStatusIndicator, default_health_status_indicator,
+};
+use nativelink_util::store_trait::{
+    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo,
+};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const DATA: &str = "0123456789";
+const WINDOW_SIZE: usize = 4;
+
+/// Memory store which fails every request while it is down.
+#[derive(Debug, MetricsComponent)]
+struct FlakyStore {
+    inner: Store,
+    down: AtomicBool,
+}
+
+impl FlakyStore {
+    fn check(&self) -> Result<(), Error> {
+        if self.down.load(Ordering::Acquire) {
+            return Err(make_err!(Code::Unavailable, "Backend is down"));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StoreDriver for FlakyStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        keys: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        self.check()?;
+        self.inner.has_with_results(keys, results).await
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        reader: DropCloserReadHalf,
+        size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        self.check()?;
+        self.inner.update(key, reader, size_info).await
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        self.check()?;
+        self.inner.get_part(key, writer, offset, length).await
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &'_ dyn StoreDriver {
+        self
+    }
+
+    fn as_any(&self) -> &(dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        _callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+default_health_status_indicator!(FlakyStore);
+
+fn make_circuit_breaker_store(
+    fallback: Option<Store>,
+) -> Result<(Arc<CircuitBreakerStore>, Arc<FlakyStore>), Error> {
+    let backend = Arc::new(FlakyStore {
+        inner: Store::new(MemoryStore::new(&MemorySpec::default())),
+        down: AtomicBool::new(false),
+    });
+    let store = CircuitBreakerStore::new(
+        &CircuitBreakerSpec {
+            backend: StoreSpec::Memory(MemorySpec::default()),
+            fallback: fallback
+                .is_some()
+                .then(|| StoreSpec::Memory(MemorySpec::default())),
+            probe: CircuitBreakerProbe::WriteRead,
+            // Probes are run by hand.
+            probe_interval_seconds: 3600,
+            probe_timeout_seconds: 0,
+            window_size: WINDOW_SIZE,
+            failure_rate_percent: 50,
+        },
+        Store::new(backend.clone()),
+        fallback,
+    )?;
+    Ok((store, backend))
+}
+
+#[nativelink_test]
+async fn opens_on_failures_and_closes_after_probe_test() -> Result<(), Error> {
+    let (store, backend) = make_circuit_breaker_store(None)?;
+    let digest = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+    store.update_oneshot(digest, DATA.into()).await?;
+
+    // Missing objects are not failures of the backend.
+    let missing_digest = DigestInfo::try_new(VALID_HASH1, 1)?;
+    for _ in 0..WINDOW_SIZE {
+        assert!(
+            store
+                .get_part_unchunked(missing_digest, 0, None)
+                .await
+                .is_err()
+        );
+    }
+    assert!(!store.is_open());
+
+    backend.down.store(true, Ordering::Release);
+    for _ in 0..WINDOW_SIZE {
+        assert!(store.has(digest).await.is_err());
+    }
+    assert!(store.is_open());
+    backend.down.store(false, Ordering::Release);
+    let err = store.has(digest).await.unwrap_err();
+    assert_eq!(err.code, Code::Unavailable);
+    assert!(matches!(
+        HealthStatusIndicator::check_health(&*store, "".into()).await,
+        HealthStatus::Failed { .. }
+    ));
+
+    store.probe().await;
+    assert!(!store.is_open());
+    assert_eq!(store.has(digest).await?, Some(DATA.len() as u64));
+    assert!(matches!(
+        HealthStatusIndicator::check_health(&*store, "".into()).await,
+        HealthStatus::Ok { .. }
+    ));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn open_circuit_uses_fallback_test() -> Result<(), Error> {
+    let fallback = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let (store, backend) = make_circuit_breaker_store(Some(fallback.clone()))?;
+    let digest = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+
+    backend.down.store(true, Ordering::Release);
+    for _ in 0..WINDOW_SIZE {
+        store.probe().await;
+    }
+    assert!(store.is_open());
+
+    store.update_oneshot(digest, DATA.into()).await?;
+    assert_eq!(fallback.has(digest).await?, Some(DATA.len() as u64));
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, DATA);
+
+    // A failed probe keeps the circuit open.
+    store.probe().await;
+    assert!(store.is_open());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn rejects_invalid_failure_rate_test() -> Result<(), Error> {
+    let result = CircuitBreakerStore::new(
+        &CircuitBreakerSpec {
+            backend: StoreSpec::Noop(NoopSpec::default()),
+            fallback: None,
+            probe: CircuitBreakerProbe::Has,
+            probe_interval_seconds: 0,
+            probe_timeout_seconds: 0,
+            window_size: 0,
+            failure_rate_percent: 101,
+        },
+        Store::new(MemoryStore::new(&MemorySpec::default())),
+        None,
+    );
+    assert!(result.is_err());
+    Ok(())
+}
@@ -14,7 +14,7 @@
 
 use std::sync::Arc;
 
-use nativelink_config::stores::MemorySpec;
+use nativelink_config::stores::{CompletenessCheckingSpec, MemorySpec, StoreSpec};
 use nativelink_error::Error;
 use nativelink_macro::nativelink_test;
 use nativelink_proto::build::bazel::remote::execution::v2::{
@@ -35,11 +35,27 @@ const OUTPUT_FILE: DigestInfo = DigestInfo::new([4u8; 32], 0);
 const STDOUT: DigestInfo = DigestInfo::new([5u8; 32], 0);
 const STDERR: DigestInfo = DigestInfo::new([6u8; 32], 0);
 
+fn make_spec(verified_results_ttl_seconds: u32) -> CompletenessCheckingSpec {
+    CompletenessCheckingSpec {
+        backend: StoreSpec::Memory(MemorySpec::default()),
+        cas_store: StoreSpec::Memory(MemorySpec::default()),
+        max_concurrent_checks: 0,
+        verified_results_ttl_seconds,
+        max_verified_results: 0,
+    }
+}
+
 async fn setup() -> Result<(Arc<CompletenessCheckingStore>, Arc<MemoryStore>, DigestInfo), Error> {
+    setup_with_spec(&make_spec(0)).await
+}
+
+async fn setup_with_spec(
+    spec: &CompletenessCheckingSpec,
+) -> Result<(Arc<CompletenessCheckingStore>, Arc<MemoryStore>, DigestInfo), Error> {
     let backend_store = Store::new(MemoryStore::new(&MemorySpec::default()));
     let cas_store = MemoryStore::new(&MemorySpec::default());
     let ac_store =
-        CompletenessCheckingStore::new(backend_store.clone(), Store::new(cas_store.clone()));
+        CompletenessCheckingStore::new(spec, backend_store.clone(), Store::new(cas_store.clone()));
 
     cas_store.update_oneshot(ROOT_FILE, "".into()).await?;
     // Note: Explicitly not uploading `ROOT_DIRECTORY`. See: TraceMachina/nativelink#747.
@@ -245,3 +261,33 @@ async fn verify_completeness_get() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn verified_results_are_memoized() -> Result<(), Error> {
+    let (ac_store, cas_store, action_result_digest) = setup_with_spec(&make_spec(60)).await?;
+
+    let res = ac_store.has_many(&[action_result_digest.into()]).await?;
+    assert!(
+        res[0].is_some(),
+        "Results should be some with all items in CAS."
+    );
+
+    // The action result was verified recently, so the missing file isn't noticed.
+    cas_store.remove_entry(CHILD_FILE.into()).await;
+    let res = ac_store.has_many(&[action_result_digest.into()]).await?;
+    assert!(res[0].is_some(), "Results should be memoized.");
+
+    // Uploading the action result again forgets it was verified.
+    let action_result = ac_store
+        .get_part_unchunked(action_result_digest, 0, None)
+        .await?;
+    ac_store
+        .update_oneshot(action_result_digest, action_result)
+        .await?;
+    let res = ac_store.has_many(&[action_result_digest.into()]).await?;
+    assert!(
+        res[0].is_none(),
+        "Results should be none with missing child file."
+    );
+    Ok(())
+}
@@ -20,18 +20,21 @@ use std::sync::Arc;
 
 use bincode::serde::decode_from_slice;
 use bytes::Bytes;
-use nativelink_config::stores::{CompressionSpec, MemorySpec, StoreSpec};
+use nativelink_config::stores::{
+    CompressionAlgorithm, CompressionSpec, MemorySpec, SkipCompressionConfig, StoreSpec, ZstdConfig,
+};
 use nativelink_error::{Code, Error, ResultExt, make_err};
 use nativelink_macro::nativelink_test;
 use nativelink_store::compression_store::{
     CURRENT_STREAM_FORMAT_VERSION, CompressionStore, DEFAULT_BLOCK_SIZE, FOOTER_FRAME_TYPE, Footer,
-    Lz4Config, SliceIndex,
+    Lz4Config, SliceIndex, UNCOMPRESSED_STREAM_FORMAT_VERSION, ZSTD_DICTIONARY_KEY_PREFIX,
+    ZSTD_STREAM_FORMAT_VERSION,
 };
 use nativelink_store::memory_store::MemoryStore;
 use nativelink_util::buf_channel::make_buf_channel_pair;
 use nativelink_util::common::DigestInfo;
 use nativelink_util::spawn;
-use nativelink_util::store_trait::{Store, StoreLike, UploadSizeInfo};
+use nativelink_util::store_trait::{Store, StoreKey, StoreLike, UploadSizeInfo};
 use pretty_assertions::assert_eq;
 use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
@@ -75,6 +78,7 @@ async fn simple_smoke_test() -> Result<(), Error> {
     let store = CompressionStore::new(
         &CompressionSpec {
             backend: StoreSpec::Memory(MemorySpec::default()),
+            skip_incompressible: None,
             compression_algorithm: nativelink_config::stores::CompressionAlgorithm::Lz4(
                 nativelink_config::stores::Lz4Config::default(),
             ),
@@ -110,6 +114,7 @@ async fn partial_reads_test() -> Result<(), Error> {
     let store_owned = CompressionStore::new(
         &CompressionSpec {
             backend: StoreSpec::Memory(MemorySpec::default()),
+            skip_incompressible: None,
             compression_algorithm: nativelink_config::stores::CompressionAlgorithm::Lz4(
                 nativelink_config::stores::Lz4Config {
                     block_size: 10,
@@ -159,6 +164,7 @@ async fn rand_5mb_smoke_test() -> Result<(), Error> {
     let store_owned = CompressionStore::new(
         &CompressionSpec {
             backend: StoreSpec::Memory(MemorySpec::default()),
+            skip_incompressible: None,
             compression_algorithm: nativelink_config::stores::CompressionAlgorithm::Lz4(
                 nativelink_config::stores::Lz4Config::default(),
             ),
@@ -190,6 +196,7 @@ async fn sanity_check_zero_bytes_test() -> Result<(), Error> {
     let store_owned = CompressionStore::new(
         &CompressionSpec {
             backend: StoreSpec::Memory(MemorySpec::default()),
+            skip_incompressible: None,
             compression_algorithm: nativelink_config::stores::CompressionAlgorithm::Lz4(
                 nativelink_config::stores::Lz4Config::default(),
             ),
@@ -243,6 +250,7 @@ async fn check_header_test() -> Result<(), Error> {
     let store_owned = CompressionStore::new(
         &CompressionSpec {
             backend: StoreSpec::Memory(MemorySpec::default()),
+            skip_incompressible: None,
             compression_algorithm: nativelink_config::stores::CompressionAlgorithm::Lz4(
                 nativelink_config::stores::Lz4Config {
                     block_size: BLOCK_SIZE,
@@ -329,6 +337,7 @@ async fn check_footer_test() -> Result<(), Error> {
     let store_owned = CompressionStore::new(
         &CompressionSpec {
             backend: StoreSpec::Memory(MemorySpec::default()),
+            skip_incompressible: None,
             compression_algorithm: nativelink_config::stores::CompressionAlgorithm::Lz4(
                 nativelink_config::stores::Lz4Config {
                     block_size: BLOCK_SIZE,
@@ -475,6 +484,7 @@ async fn get_part_is_zero_digest() -> Result<(), Error> {
     let store_owned = CompressionStore::new(
         &CompressionSpec {
             backend: StoreSpec::Memory(MemorySpec::default()),
+            skip_incompressible: None,
             compression_algorithm: nativelink_config::stores::CompressionAlgorithm::Lz4(
                 nativelink_config::stores::Lz4Config {
                     block_size: BLOCK_SIZE,
@@ -509,3 +519,208 @@ async fn get_part_is_zero_digest() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn zstd_partial_reads_test() -> Result<(), Error> {
+    const RAW_DATA: [u8; 30] = [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, // BR.
+        10, 11, 12, 13, 14, 15, 16, 17, 18, 19, // BR.
+        20, 21, 22, 23, 24, 25, 26, 27, 28, 29, // BR.
+    ];
+
+    let inner_store = MemoryStore::new(&MemorySpec::default());
+    let store = CompressionStore::new(
+        &CompressionSpec {
+            backend: StoreSpec::Memory(MemorySpec::default()),
+            skip_incompressible: None,
+            compression_algorithm: CompressionAlgorithm::Zstd(ZstdConfig {
+                block_size: 10,
+                ..Default::default()
+            }),
+        },
+        Store::new(inner_store.clone()),
+    )
+    .err_tip(|| "Failed to create compression store")?;
+
+    let digest = DigestInfo::try_new(VALID_HASH, DUMMY_DATA_SIZE).unwrap();
+    store
+        .update_oneshot(digest, RAW_DATA.as_ref().into())
+        .await?;
+
+    for read_slice_size in 0..(RAW_DATA.len() + 5) {
+        for offset in 0..(RAW_DATA.len() + 5) {
+            let store_data = store
+                .get_part_unchunked(digest, offset as u64, Some(read_slice_size as u64))
+                .await
+                .err_tip(|| {
+                    format!("Failed to get from inner store at {offset} - {read_slice_size}")
+                })?;
+
+            let start_pos = cmp::min(RAW_DATA.len(), offset);
+            let end_pos = cmp::min(RAW_DATA.len(), offset + read_slice_size);
+            assert_eq!(
+                &store_data,
+                &RAW_DATA[start_pos..end_pos],
+                "Expected data to match at {} - {}",
+                offset,
+                read_slice_size,
+            );
+        }
+    }
+
+    let compressed_data = inner_store.get_part_unchunked(digest, 0, None).await?;
+    assert_eq!(
+        extract_footer(&compressed_data)?.version,
+        ZSTD_STREAM_FORMAT_VERSION
+    );
+
+    // Stores configured with lz4 can read zstd compressed data.
+    let lz4_store = CompressionStore::new(
+        &CompressionSpec {
+            backend: StoreSpec::Memory(MemorySpec::default()),
+            skip_incompressible: None,
+            compression_algorithm: CompressionAlgorithm::Lz4(
+                nativelink_config::stores::Lz4Config::default(),
+            ),
+        },
+        Store::new(inner_store),
+    )
+    .err_tip(|| "Failed to create compression store")?;
+    assert_eq!(
+        lz4_store.get_part_unchunked(digest, 0, None).await?,
+        &RAW_DATA[..]
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn zstd_dictionary_test() -> Result<(), Error> {
+    let samples: Vec<Vec<u8>> = (0..1000u32)
+        .map(|i| {
+            format!(
+                r#"{{"name": "target_{i}", "kind": "cc_library", "visibility": ["//visibility:public"], "id": {}}}"#,
+                i * 7919
+            )
+            .into_bytes()
+        })
+        .collect();
+    let dictionary = zstd::dict::from_samples(&samples, 4096)?;
+    let dictionary_id = zstd::zstd_safe::get_dict_id_from_dict(&dictionary)
+        .err_tip(|| "Trained dictionary has no id")?;
+    let temp_dir = tempfile::tempdir()?;
+    let dictionary_path = temp_dir.path().join("dictionary");
+    std::fs::write(&dictionary_path, &dictionary)?;
+
+    let inner_store = MemoryStore::new(&MemorySpec::default());
+    let store = CompressionStore::new(
+        &CompressionSpec {
+            backend: StoreSpec::Memory(MemorySpec::default()),
+            skip_incompressible: None,
+            compression_algorithm: CompressionAlgorithm::Zstd(ZstdConfig {
+                dictionary_path: Some(dictionary_path.to_string_lossy().to_string()),
+                ..Default::default()
+            }),
+        },
+        Store::new(inner_store.clone()),
+    )
+    .err_tip(|| "Failed to create compression store")?;
+
+    let value = samples[3].clone();
+    let digest = DigestInfo::try_new(VALID_HASH, value.len())?;
+    store.update_oneshot(digest, value.clone().into()).await?;
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, value);
+
+    // The dictionary is stored next to the data, so a store without it can
+    // read the data.
+    let dictionary_key = StoreKey::from(format!("{ZSTD_DICTIONARY_KEY_PREFIX}{dictionary_id}"));
+    assert_eq!(
+        inner_store
+            .get_part_unchunked(dictionary_key, 0, None)
+            .await?,
+        dictionary
+    );
+    let other_store = CompressionStore::new(
+        &CompressionSpec {
+            backend: StoreSpec::Memory(MemorySpec::default()),
+            skip_incompressible: None,
+            compression_algorithm: CompressionAlgorithm::Zstd(ZstdConfig::default()),
+        },
+        Store::new(inner_store),
+    )
+    .err_tip(|| "Failed to create compression store")?;
+    assert_eq!(
+        other_store.get_part_unchunked(digest, 0, None).await?,
+        value
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn skip_incompressible_test() -> Result<(), Error> {
+    const BLOCK_SIZE: u32 = 100;
+
+    let inner_store = MemoryStore::new(&MemorySpec::default());
+    let store_owned = CompressionStore::new(
+        &CompressionSpec {
+            backend: StoreSpec::Memory(MemorySpec::default()),
+            compression_algorithm: CompressionAlgorithm::Lz4(
+                nativelink_config::stores::Lz4Config {
+                    block_size: BLOCK_SIZE,
+                    ..Default::default()
+                },
+            ),
+            skip_incompressible: Some(SkipCompressionConfig {
+                // Not a multiple of the block size.
+                sample_size: 250,
+                min_savings_percent: 10,
+            }),
+        },
+        Store::new(inner_store.clone()),
+    )
+    .err_tip(|| "Failed to create compression store")?;
+    let store = Pin::new(&store_owned);
+
+    let mut random_value = vec![0u8; 1000];
+    let mut rng = SmallRng::seed_from_u64(1);
+    rng.fill(&mut random_value[..]);
+    let random_digest = DigestInfo::try_new(VALID_HASH, random_value.len())?;
+    store
+        .update_oneshot(random_digest, random_value.clone().into())
+        .await?;
+
+    let stored_data = Pin::new(inner_store.as_ref())
+        .get_part_unchunked(random_digest, 0, None)
+        .await?;
+    assert_eq!(stored_data[0], UNCOMPRESSED_STREAM_FORMAT_VERSION);
+    assert_eq!(
+        extract_footer(&stored_data)?.version,
+        UNCOMPRESSED_STREAM_FORMAT_VERSION
+    );
+    assert_eq!(
+        store.get_part_unchunked(random_digest, 0, None).await?,
+        random_value
+    );
+    assert_eq!(
+        store
+            .get_part_unchunked(random_digest, 150, Some(200))
+            .await?,
+        random_value[150..350]
+    );
+
+    let compressible_value = vec![b'a'; 1000];
+    let compressible_digest = DigestInfo::try_new(VALID_HASH, compressible_value.len() + 1)?;
+    store
+        .update_oneshot(compressible_digest, compressible_value.clone().into())
+        .await?;
+    let stored_data = Pin::new(inner_store.as_ref())
+        .get_part_unchunked(compressible_digest, 0, None)
+        .await?;
+    assert_eq!(stored_data[0], CURRENT_STREAM_FORMAT_VERSION);
+    assert_eq!(
+        store
+            .get_part_unchunked(compressible_digest, 0, None)
+            .await?,
+        compressible_value
+    );
+    Ok(())
+}
@@ -31,7 +31,9 @@ fn make_default_config() -> DedupSpec {
         min_size: 8 * 1024,
         normal_size: 32 * 1024,
         max_size: 128 * 1024,
+        normalization_level: None,
         max_concurrent_fetch_per_get: 10,
+        garbage_collection: None,
     }
 }
 
@@ -166,7 +168,9 @@ async fn check_length_not_set_with_chunk_read_beyond_first_chunk_regression_test
             min_size: 5,
             normal_size: 6,
             max_size: 7,
+            normalization_level: None,
             max_concurrent_fetch_per_get: 10,
+            garbage_collection: None,
         },
         Store::new(MemoryStore::new(&MemorySpec::default())), // Index store.
         Store::new(MemoryStore::new(&MemorySpec::default())), // Content store.
@@ -211,7 +215,9 @@ async fn check_chunk_boundary_reads_test() -> Result<(), Error> {
             min_size: 5,
             normal_size: 6,
             max_size: 7,
+            normalization_level: None,
             max_concurrent_fetch_per_get: 10,
+            garbage_collection: None,
         },
         Store::new(MemoryStore::new(&MemorySpec::default())), // Index store.
         Store::new(MemoryStore::new(&MemorySpec::default())), // Content store.
@@ -284,6 +290,7 @@ async fn has_checks_content_store() -> Result<(), Error> {
             max_bytes: DATA_SIZE + 1,
             ..Default::default()
         }),
+        ..Default::default()
     });
 
     let store = DedupStore::new(
@@ -349,6 +356,7 @@ async fn has_with_no_existing_index_returns_none_test() -> Result<(), Error> {
             max_count: 10,
             ..Default::default()
         }),
+        ..Default::default()
     });
 
     let store = DedupStore::new(
@@ -380,6 +388,7 @@ async fn has_with_zero_digest_returns_some_test() -> Result<(), Error> {
             max_count: 10,
             ..Default::default()
         }),
+        ..Default::default()
     });
 
     let store = DedupStore::new(
@@ -401,3 +410,137 @@ async fn has_with_zero_digest_returns_some_test() -> Result<(), Error> {
     }
     Ok(())
 }
+
+/// Data written before the chunking parameters changed must stay readable,
+/// because the index lists the chunks it was split into.
+#[nativelink_test]
+async fn changed_chunking_parameters_read_existing_data_test() -> Result<(), Error> {
+    let index_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let content_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+
+    let original_data = make_random_data(MEGABYTE_SZ);
+    let digest = DigestInfo::try_new(VALID_HASH1, MEGABYTE_SZ).unwrap();
+    DedupStore::new(
+        &make_default_config(),
+        index_store.clone(),
+        content_store.clone(),
+    )?
+    .update_oneshot(digest, original_data.clone().into())
+    .await
+    .err_tip(|| "Failed to write data to dedup store")?;
+
+    let store = DedupStore::new(
+        &DedupSpec {
+            min_size: 64 * 1024,
+            normal_size: 256 * 1024,
+            max_size: 1024 * 1024,
+            normalization_level: Some(3),
+            ..make_default_config()
+        },
+        index_store,
+        content_store,
+    )?;
+    let rt_data = store
+        .get_part_unchunked(digest, 0, None)
+        .await
+        .err_tip(|| "Failed to get_part from dedup store")?;
+    assert_eq!(rt_data, original_data, "Expected round trip data to match");
+
+    let digest2 = DigestInfo::try_new(VALID_HASH2, MEGABYTE_SZ).unwrap();
+    store
+        .update_oneshot(digest2, original_data.clone().into())
+        .await
+        .err_tip(|| "Failed to write data to dedup store")?;
+    let rt_data = store
+        .get_part_unchunked(digest2, 0, None)
+        .await
+        .err_tip(|| "Failed to get_part from dedup store")?;
+    assert_eq!(rt_data, original_data, "Expected round trip data to match");
+    Ok(())
+}
+
+#[nativelink_test]
+async fn invalid_chunking_parameters_test() -> Result<(), Error> {
+    let new_store = |spec: DedupSpec| {
+        DedupStore::new(
+            &spec,
+            Store::new(MemoryStore::new(&MemorySpec::default())),
+            Store::new(MemoryStore::new(&MemorySpec::default())),
+        )
+    };
+    let err = new_store(DedupSpec {
+        min_size: 64 * 1024,
+        normal_size: 32 * 1024,
+        ..make_default_config()
+    })
+    .unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument);
+
+    let err = new_store(DedupSpec {
+        normalization_level: Some(4),
+        ..make_default_config()
+    })
+    .unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn garbage_collection_removes_orphaned_chunks_test() -> Result<(), Error> {
+    let index_store = MemoryStore::new(&MemorySpec::default());
+    let content_store = MemoryStore::new(&MemorySpec::default());
+    let store = DedupStore::new(
+        &make_default_config(),
+        Store::new(index_store.clone()),
+        Store::new(content_store.clone()),
+    )?;
+
+    let orphaned_data = make_random_data(MEGABYTE_SZ);
+    let orphaned_digest = DigestInfo::try_new(VALID_HASH1, MEGABYTE_SZ).unwrap();
+    store
+        .update_oneshot(orphaned_digest, orphaned_data.into())
+        .await
+        .err_tip(|| "Failed to write data to dedup store")?;
+    let orphaned_chunk_count = content_store.len_for_test().await;
+
+    let kept_data: Vec<u8> = make_random_data(MEGABYTE_SZ)
+        .into_iter()
+        .map(|byte| byte ^ 0xff)
+        .collect();
+    let kept_digest = DigestInfo::try_new(VALID_HASH2, MEGABYTE_SZ).unwrap();
+    store
+        .update_oneshot(kept_digest, kept_data.clone().into())
+        .await
+        .err_tip(|| "Failed to write data to dedup store")?;
+    let chunk_count = content_store.len_for_test().await;
+
+    // Simulate the index store evicting the index.
+    assert!(index_store.remove_entry(orphaned_digest.into()).await);
+
+    let report = store.collect_garbage(true).await?;
+    assert_eq!(report.indexes, 1);
+    assert_eq!(report.chunks, chunk_count as u64);
+    assert_eq!(report.orphaned_chunks, orphaned_chunk_count as u64);
+    assert_eq!(report.orphaned_bytes, MEGABYTE_SZ as u64);
+    assert_eq!(report.removed_chunks, 0);
+    assert_eq!(content_store.len_for_test().await, chunk_count);
+
+    let report = store.collect_garbage(false).await?;
+    assert_eq!(report.orphaned_chunks, orphaned_chunk_count as u64);
+    assert_eq!(report.removed_chunks, orphaned_chunk_count as u64);
+    assert_eq!(
+        content_store.len_for_test().await,
+        chunk_count - orphaned_chunk_count
+    );
+
+    let rt_data = store
+        .get_part_unchunked(kept_digest, 0, None)
+        .await
+        .err_tip(|| "Failed to get_part from dedup store")?;
+    assert_eq!(rt_data, kept_data, "Expected round trip data to match");
+
+    // Nothing is left to collect.
+    let report = store.collect_garbage(false).await?;
+    assert_eq!(report.orphaned_chunks, 0);
+    Ok(())
+}
@@ -13,10 +13,12 @@
 // limitations under the License.
 
 use core::time::Duration;
+use std::env;
 
 use mock_instant::thread_local::MockClock;
 use nativelink_config::stores::{
-    EvictionPolicy, ExistenceCacheSpec, MemorySpec, NoopSpec, StoreSpec,
+    EvictionPolicy, ExistenceCachePersistenceSpec, ExistenceCacheSpec, MemorySpec, NoopSpec,
+    StoreSpec,
 };
 use nativelink_error::{Error, ResultExt};
 use nativelink_macro::nativelink_test;
@@ -26,8 +28,10 @@ use nativelink_util::common::DigestInfo;
 use nativelink_util::instant_wrapper::MockInstantWrapped;
 use nativelink_util::store_trait::{Store, StoreLike};
 use pretty_assertions::assert_eq;
+use rand::Rng;
 
 const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALID_HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
 
 #[nativelink_test]
 async fn simple_exist_cache_test() -> Result<(), Error> {
@@ -35,6 +39,7 @@ async fn simple_exist_cache_test() -> Result<(), Error> {
     let spec = ExistenceCacheSpec {
         backend: StoreSpec::Noop(NoopSpec::default()), // Note: Not used.
         eviction_policy: Option::default(),
+        persistence: None,
     };
     let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
     let store = ExistenceCacheStore::new(&spec, inner_store.clone());
@@ -73,6 +78,7 @@ async fn update_flags_existence_cache_test() -> Result<(), Error> {
     let spec = ExistenceCacheSpec {
         backend: StoreSpec::Noop(NoopSpec::default()),
         eviction_policy: Option::default(),
+        persistence: None,
     };
     let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
     let store = ExistenceCacheStore::new(&spec, inner_store.clone());
@@ -96,6 +102,7 @@ async fn get_part_caches_if_exact_size_set() -> Result<(), Error> {
     let spec = ExistenceCacheSpec {
         backend: StoreSpec::Noop(NoopSpec::default()),
         eviction_policy: Option::default(),
+        persistence: None,
     };
     let inner_store = Store::new(MemoryStore::new(&MemorySpec::default()));
     let digest = DigestInfo::try_new(VALID_HASH1, 3).unwrap();
@@ -136,6 +143,7 @@ async fn ensure_has_requests_do_let_evictions_happen() -> Result<(), Error> {
                 max_seconds: 0, // Explicitly set this level to "don't timeout"
                 ..Default::default()
             }),
+            persistence: None,
         },
         Store::new(inner_store.clone()),
         MockInstantWrapped::default(),
@@ -160,12 +168,14 @@ async fn copes_with_dropped_items() -> Result<(), Error> {
     let spec = ExistenceCacheSpec {
         backend: StoreSpec::Noop(NoopSpec::default()), // Note: Not used.
         eviction_policy: Option::default(),
+        persistence: None,
     };
     let inner_store = Store::new(MemoryStore::new(&MemorySpec {
         eviction_policy: Some(EvictionPolicy {
             max_bytes: 1,
             ..Default::default()
         }),
+        ..Default::default()
     }));
     let store = ExistenceCacheStore::new(&spec, inner_store.clone());
 
@@ -196,3 +206,47 @@ async fn copes_with_dropped_items() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn snapshot_restores_cache_test() -> Result<(), Error> {
+    const VALUE: &str = "123";
+    let spec = ExistenceCacheSpec {
+        backend: StoreSpec::Noop(NoopSpec::default()), // Note: Not used.
+        eviction_policy: Option::default(),
+        persistence: Some(ExistenceCachePersistenceSpec {
+            path: format!(
+                "{}/existence_cache_{}",
+                env::var("TEST_TMPDIR")
+                    .unwrap_or_else(|_| env::temp_dir().to_str().unwrap().to_string()),
+                rand::rng().random::<u64>(),
+            ),
+            // Snapshots are written by hand.
+            snapshot_interval_seconds: 3600,
+            false_positives_per_million: 0,
+        }),
+    };
+    let digest1 = DigestInfo::try_new(VALID_HASH1, 3).unwrap();
+    let digest2 = DigestInfo::try_new(VALID_HASH2, 3).unwrap();
+
+    let store =
+        ExistenceCacheStore::new(&spec, Store::new(MemoryStore::new(&MemorySpec::default())));
+    store
+        .update_oneshot(digest1, VALUE.into())
+        .await
+        .err_tip(|| "Failed to update store")?;
+    store.write_snapshot().await?;
+    drop(store);
+
+    // The backend of the restarted cache is empty, so only the snapshot
+    // can report the digest as existing.
+    let store =
+        ExistenceCacheStore::new(&spec, Store::new(MemoryStore::new(&MemorySpec::default())));
+    assert_eq!(store.has(digest1).await, Ok(Some(VALUE.len() as u64)));
+    assert!(store.exists_in_cache(&digest1).await);
+    assert_eq!(store.has(digest2).await, Ok(None));
+
+    // Removed digests are not reported by the snapshot anymore.
+    store.remove_from_cache(&digest1).await;
+    assert_eq!(store.has(digest1).await, Ok(None));
+    Ok(())
+}
@@ -42,6 +42,7 @@ fn make_stores() -> (Store, Store, Store) {
         &FastSlowSpec {
             fast: StoreSpec::Memory(MemorySpec::default()),
             slow: StoreSpec::Memory(MemorySpec::default()),
+            read_ahead: false,
         },
         fast_store.clone(),
         slow_store.clone(),
@@ -339,6 +340,7 @@ async fn drop_on_eof_completes_store_futures() -> Result<(), Error> {
         &FastSlowSpec {
             fast: StoreSpec::Memory(MemorySpec::default()),
             slow: StoreSpec::Memory(MemorySpec::default()),
+            read_ahead: false,
         },
         fast_store,
         slow_store,
@@ -380,6 +382,7 @@ async fn ignore_value_in_fast_store() -> Result<(), Error> {
         &FastSlowSpec {
             fast: StoreSpec::Memory(MemorySpec::default()),
             slow: StoreSpec::Memory(MemorySpec::default()),
+            read_ahead: false,
         },
         fast_store.clone(),
         slow_store,
@@ -403,6 +406,7 @@ async fn has_checks_fast_store_when_noop() -> Result<(), Error> {
     let fast_slow_store_config = FastSlowSpec {
         fast: StoreSpec::Memory(MemorySpec::default()),
         slow: StoreSpec::Noop(NoopSpec::default()),
+        read_ahead: false,
     };
     let fast_slow_store = Arc::new(FastSlowStore::new(
         &fast_slow_store_config,
@@ -437,3 +441,39 @@ async fn has_checks_fast_store_when_noop() -> Result<(), Error> {
     );
     Ok(())
 }
+
+#[nativelink_test]
+async fn read_ahead_serves_ranged_read_from_slow_store_test() -> Result<(), Error> {
+    let fast_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let slow_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let fast_slow_store = Store::new(FastSlowStore::new(
+        &FastSlowSpec {
+            fast: StoreSpec::Memory(MemorySpec::default()),
+            slow: StoreSpec::Memory(MemorySpec::default()),
+            read_ahead: true,
+        },
+        fast_store.clone(),
+        slow_store.clone(),
+    ));
+
+    let original_data = make_random_data(MEGABYTE_SZ);
+    let digest = DigestInfo::try_new(VALID_HASH, 100).unwrap();
+    slow_store
+        .update_oneshot(digest, original_data.clone().into())
+        .await?;
+
+    let data = fast_slow_store
+        .get_part_unchunked(digest, 10, Some(20))
+        .await?;
+    assert_eq!(data, original_data[10..30]);
+
+    // The whole object is copied into the fast store in the background.
+    for _ in 0..1000 {
+        if fast_store.has(digest).await?.is_some() {
+            break;
+        }
+        tokio::task::yield_now().await;
+    }
+    check_data(&fast_store, digest, &original_data, "fast").await?;
+    Ok(())
+}
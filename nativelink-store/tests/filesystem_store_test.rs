@@ -100,6 +100,10 @@ impl<Hooks: FileEntryHooks + 'static + Sync + Send> FileEntry for TestFileEntry<
         self.inner.as_mut().unwrap().data_size_mut()
     }
 
+    fn data_size(&self) -> u64 {
+        self.inner.as_ref().unwrap().data_size()
+    }
+
     fn size_on_disk(&self) -> u64 {
         self.inner.as_ref().unwrap().size_on_disk()
     }
@@ -307,6 +311,36 @@ async fn valid_results_after_shutdown_test() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn io_uring_read_write_test() -> Result<(), Error> {
+    // Larger than the read buffer, so the data is written and read in
+    // several operations.
+    const READ_BUFFER_SIZE: u32 = 16;
+    let value: Vec<u8> = (0..100u8).collect();
+    let digest = DigestInfo::try_new(HASH1, value.len())?;
+    // Falls back to regular file operations if io_uring is unavailable.
+    let store = Store::new(
+        FilesystemStore::<FileEntryImpl>::new(&FilesystemSpec {
+            content_path: make_temp_path("content_path"),
+            temp_path: make_temp_path("temp_path"),
+            read_buffer_size: READ_BUFFER_SIZE,
+            io_uring: true,
+            io_uring_queue_depth: 4,
+            ..Default::default()
+        })
+        .await?,
+    );
+
+    store.update_oneshot(digest, value.clone().into()).await?;
+
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, value);
+    assert_eq!(
+        store.get_part_unchunked(digest, 10, Some(30)).await?,
+        value[10..40]
+    );
+    Ok(())
+}
+
 #[nativelink_test]
 async fn temp_files_get_deleted_on_replace_test() -> Result<(), Error> {
     static DELETES_FINISHED: AtomicU32 = AtomicU32::new(0);
@@ -1095,6 +1129,85 @@ async fn deleted_file_removed_from_store() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn restores_from_index_test() -> Result<(), Error> {
+    let digest1 = DigestInfo::try_new(HASH1, VALUE1.len())?;
+    let digest2 = DigestInfo::try_new(HASH2, VALUE2.len())?;
+    let string_key = StoreKey::new_str(STRING_NAME);
+    let content_path = make_temp_path("content_path");
+    let spec = FilesystemSpec {
+        content_path: content_path.clone(),
+        temp_path: make_temp_path("temp_path"),
+        index_path: format!("{}/index", make_temp_path("index_path")),
+        // The index is synced by hand.
+        index_sync_interval_seconds: 3600,
+        ..Default::default()
+    };
+
+    {
+        let store = FilesystemStore::<FileEntryImpl>::new(&spec).await?;
+        store.update_oneshot(digest1, VALUE1.into()).await?;
+        store.update_oneshot(digest2, VALUE2.into()).await?;
+        store.sync_index().await?;
+        // Written after the last sync, so only the walk on startup finds it.
+        store
+            .update_oneshot(string_key.borrow(), VALUE1.into())
+            .await?;
+    }
+    std::fs::remove_file(format!("{content_path}/{DIGEST_FOLDER}/{digest2}"))?;
+
+    let store = FilesystemStore::<FileEntryImpl>::new(&spec).await?;
+    assert!(store.has(digest1).await?.is_some());
+    // Wait for the content path to be reconciled with the index.
+    for _ in 0..1000 {
+        if store.has(digest2).await?.is_none() && store.has(string_key.borrow()).await?.is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(store.has(digest2).await?, None);
+    assert_eq!(store.get_part_unchunked(string_key, 0, None).await?, VALUE1);
+    assert_eq!(store.get_part_unchunked(digest1, 0, None).await?, VALUE1);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn shared_filesystem_test() -> Result<(), Error> {
+    let digest1 = DigestInfo::try_new(HASH1, VALUE1.len())?;
+    let content_path = make_temp_path("content_path");
+    let temp_path = make_temp_path("temp_path");
+    let spec = FilesystemSpec {
+        content_path: content_path.clone(),
+        temp_path: temp_path.clone(),
+        shared_filesystem: true,
+        external_eviction: true,
+        ..Default::default()
+    };
+    let store1 = FilesystemStore::<FileEntryImpl>::new(&spec).await?;
+    let store2 = FilesystemStore::<FileEntryImpl>::new(&spec).await?;
+
+    // Each instance has its own temp directory with a lock file.
+    let lock_files = std::fs::read_dir(&temp_path)?
+        .filter(|entry| entry.as_ref().unwrap().path().join("lock").exists())
+        .count();
+    assert_eq!(lock_files, 2);
+
+    // Files written by one instance are found by the other.
+    store1.update_oneshot(digest1, VALUE1.into()).await?;
+    assert!(store2.has(digest1).await?.is_some());
+    assert_eq!(store2.get_part_unchunked(digest1, 0, None).await?, VALUE1);
+
+    // Files removed by an external cleaner are reported as missing.
+    std::fs::remove_file(format!("{content_path}/{DIGEST_FOLDER}/{digest1}"))?;
+    assert_eq!(store1.has(digest1).await?, None);
+    let err = store2
+        .get_part_unchunked(digest1, 0, None)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::NotFound);
+    Ok(())
+}
+
 // Ensure that get_file_size() returns the correct number
 // ceil(content length / block_size) * block_size
 // assume block size 4K
@@ -0,0 +1,136 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use nativelink_config::stores::{FastSlowSpec, HttpSpec, MemorySpec, Retry, StoreSpec};
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_store::fast_slow_store::FastSlowStore;
+use nativelink_store::http_store::HttpStore;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_util::background_spawn;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{Store, StoreLike};
+use pretty_assertions::assert_eq;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALID_HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+const DATA: &str = "0123456789";
+
+/// Serves `objects` by path over plain HTTP/1.1, honouring `Range` headers
+/// of the form `bytes=<start>-[<end>]`. Returns the address of the server.
+async fn serve(objects: HashMap<String, &'static str>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let objects = Arc::new(objects);
+    background_spawn!("http_store_test_server", async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let objects = objects.clone();
+            background_spawn!("http_store_test_connection", async move {
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut buf = [0; 1024];
+                    let len = stream.read(&mut buf).await.unwrap();
+                    if len == 0 {
+                        return;
+                    }
+                    request.extend_from_slice(&buf[..len]);
+                }
+                let request = String::from_utf8(request).unwrap();
+                let mut words = request.split_whitespace();
+                let method = words.next().unwrap();
+                let path = words.next().unwrap();
+                let range = request.lines().find_map(|line| {
+                    let (start, end) = line
+                        .to_lowercase()
+                        .strip_prefix("range: bytes=")?
+                        .split_once('-')
+                        .map(|(start, end)| (start.to_string(), end.to_string()))?;
+                    Some((start.parse::<usize>().unwrap(), end.parse::<usize>().ok()))
+                });
+                let (status, body) = match (objects.get(path), range) {
+                    (None, _) => ("404 Not Found", ""),
+                    (Some(data), None) => ("200 OK", *data),
+                    (Some(data), Some((start, end))) => {
+                        let end = end.map_or(data.len(), |end| (end + 1).min(data.len()));
+                        ("206 Partial Content", &data[start..end])
+                    }
+                };
+                let mut response = format!(
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                if method == "GET" {
+                    response.push_str(body);
+                }
+                stream.write_all(response.as_bytes()).await.unwrap();
+            });
+        }
+    });
+    format!("http://{address}")
+}
+
+async fn make_http_store() -> Result<Arc<HttpStore>, Error> {
+    let address = serve(HashMap::from([(format!("/cas/{VALID_HASH1}"), DATA)])).await;
+    HttpStore::new(&HttpSpec {
+        url_template: format!("{address}/cas/{{hash}}"),
+        retry: Retry::default(),
+        insecure_allow_http: true,
+        disable_http2: true,
+    })
+}
+
+#[nativelink_test]
+async fn has_and_get_part_test() -> Result<(), Error> {
+    let store = make_http_store().await?;
+    let digest1 = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, DATA.len())?;
+
+    assert_eq!(store.has(digest1).await?, Some(DATA.len() as u64));
+    assert_eq!(store.has(digest2).await?, None);
+    assert_eq!(store.get_part_unchunked(digest1, 0, None).await?, DATA);
+    assert_eq!(store.get_part_unchunked(digest1, 2, Some(3)).await?, "234");
+    assert_eq!(store.get_part_unchunked(digest1, 7, None).await?, "789");
+    assert!(store.get_part_unchunked(digest2, 0, None).await.is_err());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn fast_slow_falls_through_to_mirror_test() -> Result<(), Error> {
+    let fast_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = Store::new(FastSlowStore::new(
+        &FastSlowSpec {
+            fast: StoreSpec::Memory(MemorySpec::default()),
+            slow: StoreSpec::Memory(MemorySpec::default()),
+            read_ahead: false,
+        },
+        fast_store.clone(),
+        Store::new(make_http_store().await?),
+    ));
+
+    let digest1 = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+    assert_eq!(store.get_part_unchunked(digest1, 0, None).await?, DATA);
+    assert_eq!(fast_store.has(digest1).await?, Some(DATA.len() as u64));
+
+    // Uploads only reach the fast store.
+    let digest2 = DigestInfo::try_new(VALID_HASH2, DATA.len())?;
+    store.update_oneshot(digest2, DATA.into()).await?;
+    assert_eq!(fast_store.has(digest2).await?, Some(DATA.len() as u64));
+    Ok(())
+}
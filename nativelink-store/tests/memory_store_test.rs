@@ -17,7 +17,7 @@ use core::pin::Pin;
 
 use bytes::{BufMut, Bytes, BytesMut};
 use memory_stats::memory_stats;
-use nativelink_config::stores::MemorySpec;
+use nativelink_config::stores::{MemoryCompression, MemorySpec};
 use nativelink_error::{Error, ResultExt};
 use nativelink_macro::nativelink_test;
 use nativelink_store::memory_store::MemoryStore;
@@ -150,6 +150,71 @@ async fn read_partial() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn max_heap_bytes_evicts_oldest_test() -> Result<(), Error> {
+    const DATA_SIZE: usize = 1000;
+    let store = MemoryStore::new(&MemorySpec {
+        max_heap_bytes: 5 * DATA_SIZE / 2,
+        ..Default::default()
+    });
+
+    let digests = [VALID_HASH1, VALID_HASH2, VALID_HASH3]
+        .map(|hash| DigestInfo::try_new(hash, DATA_SIZE).unwrap());
+    for digest in digests {
+        store
+            .update_oneshot(digest, vec![0u8; DATA_SIZE].into())
+            .await?;
+    }
+
+    assert_eq!(store.has(digests[0]).await?, None);
+    assert_eq!(store.has(digests[1]).await?, Some(DATA_SIZE as u64));
+    assert_eq!(store.has(digests[2]).await?, Some(DATA_SIZE as u64));
+    let heap_size = store.heap_size().await;
+    assert!(
+        heap_size > 2 * DATA_SIZE as u64 && heap_size <= 5 * DATA_SIZE as u64 / 2,
+        "Unexpected heap size {heap_size}"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn lz4_compression_test() -> Result<(), Error> {
+    const DATA_SIZE: usize = 10_000;
+    let store = MemoryStore::new(&MemorySpec {
+        max_heap_bytes: DATA_SIZE,
+        compression: MemoryCompression::Lz4,
+        ..Default::default()
+    });
+
+    let value: Bytes = b"0123456789".repeat(DATA_SIZE / 10).into();
+    let digests = [VALID_HASH1, VALID_HASH2, VALID_HASH3]
+        .map(|hash| DigestInfo::try_new(hash, DATA_SIZE).unwrap());
+    for digest in digests {
+        store.update_oneshot(digest, value.clone()).await?;
+    }
+
+    // Compressed, all entries fit in the space of a single uncompressed one.
+    for digest in digests {
+        assert_eq!(store.has(digest).await?, Some(DATA_SIZE as u64));
+    }
+    assert!(store.heap_size().await < DATA_SIZE as u64);
+    assert_eq!(store.get_part_unchunked(digests[0], 0, None).await?, value);
+    assert_eq!(
+        store.get_part_unchunked(digests[1], 15, Some(10)).await?,
+        "5678901234"
+    );
+
+    // Data that does not compress is kept as is.
+    let incompressible = Bytes::from_static(b"13");
+    let digest = DigestInfo::try_new(VALID_HASH4, incompressible.len())?;
+    store.update_oneshot(digest, incompressible.clone()).await?;
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, None).await?,
+        incompressible
+    );
+    Ok(())
+}
+
 // A bug was found where reading an empty value from memory store would result in an error
 // due to internal EOF handling. This is an edge case test.
 #[nativelink_test]
@@ -0,0 +1,134 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::time::Duration;
+use std::sync::Arc;
+
+use nativelink_config::stores::{MemorySpec, MigrationSpec, StoreSpec};
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_store::migration_store::MigrationStore;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{Store, StoreLike};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALID_HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+const DATA: &str = "0123456789";
+
+fn make_memory_store() -> Store {
+    Store::new(MemoryStore::new(&MemorySpec::default()))
+}
+
+fn make_migration_store(
+    source: Store,
+    destination: Store,
+    progress_file: &str,
+) -> Arc<MigrationStore> {
+    MigrationStore::new(
+        &MigrationSpec {
+            source: StoreSpec::Memory(MemorySpec::default()),
+            destination: StoreSpec::Memory(MemorySpec::default()),
+            progress_file: progress_file.to_string(),
+            max_bytes_per_second: 0,
+            max_concurrent_copies: 0,
+        },
+        source,
+        destination,
+    )
+}
+
+/// Waits for the background copy of the migration store to finish.
+async fn wait_for_completion(store: &MigrationStore) {
+    for _ in 0..1000 {
+        if store.is_completed() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[nativelink_test]
+async fn copies_source_into_destination_test() -> Result<(), Error> {
+    let source = make_memory_store();
+    let destination = make_memory_store();
+    let digest1 = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, DATA.len())?;
+    source.update_oneshot(digest1, DATA.into()).await?;
+    source.update_oneshot(digest2, DATA.into()).await?;
+    destination.update_oneshot(digest2, DATA.into()).await?;
+
+    let migration_store = make_migration_store(source.clone(), destination.clone(), "");
+    wait_for_completion(&migration_store).await;
+    assert!(migration_store.is_completed());
+    assert_eq!(
+        destination.get_part_unchunked(digest1, 0, None).await?,
+        DATA
+    );
+    assert_eq!(destination.has(digest2).await?, Some(DATA.len() as u64));
+    // The source is left untouched.
+    assert_eq!(source.has(digest1).await?, Some(DATA.len() as u64));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn reads_fall_back_to_source_and_writes_go_to_destination_test() -> Result<(), Error> {
+    let source = make_memory_store();
+    let destination = make_memory_store();
+    let migration_store = make_migration_store(source.clone(), destination.clone(), "");
+    wait_for_completion(&migration_store).await;
+    let store = Store::new(migration_store);
+
+    let digest1 = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+    source.update_oneshot(digest1, DATA.into()).await?;
+    assert_eq!(store.has(digest1).await?, Some(DATA.len() as u64));
+    assert_eq!(store.get_part_unchunked(digest1, 2, Some(3)).await?, "234");
+
+    let digest2 = DigestInfo::try_new(VALID_HASH2, DATA.len())?;
+    store.update_oneshot(digest2, DATA.into()).await?;
+    assert_eq!(source.has(digest2).await?, None);
+    assert_eq!(destination.has(digest2).await?, Some(DATA.len() as u64));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn resumes_from_progress_file_test() -> Result<(), Error> {
+    let source = make_memory_store();
+    let destination = make_memory_store();
+    let digest1 = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, DATA.len())?;
+    source.update_oneshot(digest1, DATA.into()).await?;
+    source.update_oneshot(digest2, DATA.into()).await?;
+
+    let temp_dir = tempfile::tempdir()?;
+    let progress_file = temp_dir.path().join("progress.json");
+    std::fs::write(
+        &progress_file,
+        format!(
+            r#"{{"last_key":{{"Digest":"{VALID_HASH1}-{}"}},"copy_failed":false,"completed":false}}"#,
+            DATA.len()
+        ),
+    )?;
+
+    let migration_store =
+        make_migration_store(source, destination.clone(), progress_file.to_str().unwrap());
+    wait_for_completion(&migration_store).await;
+    assert!(migration_store.is_completed());
+    // The first object was handled before the restart.
+    assert_eq!(destination.has(digest1).await?, None);
+    assert_eq!(destination.has(digest2).await?, Some(DATA.len() as u64));
+    assert!(std::fs::read_to_string(&progress_file)?.contains(r#""completed":true"#));
+    Ok(())
+}
@@ -0,0 +1,80 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::time::Duration;
+
+use mock_instant::thread_local::MockClock;
+use nativelink_config::stores::{MemorySpec, NegativeCacheSpec, StoreSpec};
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_store::negative_cache_store::NegativeCacheStore;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::instant_wrapper::MockInstantWrapped;
+use nativelink_util::store_trait::{Store, StoreLike};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALUE: &str = "123";
+
+fn make_spec() -> NegativeCacheSpec {
+    NegativeCacheSpec {
+        backend: StoreSpec::Memory(MemorySpec::default()),
+        ttl_seconds: 10,
+        max_entries: 0,
+    }
+}
+
+#[nativelink_test]
+async fn missing_keys_are_cached_until_expired_test() -> Result<(), Error> {
+    let backend = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = NegativeCacheStore::new_with_time(
+        &make_spec(),
+        backend.clone(),
+        MockInstantWrapped::default(),
+    );
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+
+    assert_eq!(store.has(digest).await?, None);
+    // Written behind the back of the negative cache.
+    backend.update_oneshot(digest, VALUE.into()).await?;
+    assert_eq!(store.has(digest).await?, None);
+
+    MockClock::advance(Duration::from_secs(11));
+    assert_eq!(store.has(digest).await?, Some(VALUE.len() as u64));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn updates_and_reads_invalidate_missing_keys_test() -> Result<(), Error> {
+    let backend = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = NegativeCacheStore::new_with_time(
+        &make_spec(),
+        backend.clone(),
+        MockInstantWrapped::default(),
+    );
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE.len())?;
+
+    assert_eq!(store.has(digest).await?, None);
+    store.update_oneshot(digest, VALUE.into()).await?;
+    assert_eq!(store.has(digest).await?, Some(VALUE.len() as u64));
+
+    // A read of a key cached as missing corrects the cache.
+    assert!(backend.remove(digest).await?);
+    assert_eq!(store.has(digest).await?, None);
+    backend.update_oneshot(digest, VALUE.into()).await?;
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, VALUE);
+    assert_eq!(store.has(digest).await?, Some(VALUE.len() as u64));
+    Ok(())
+}
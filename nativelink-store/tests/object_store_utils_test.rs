@@ -0,0 +1,89 @@
+// Copyright 2025 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use futures::future::{pending, ready};
+use nativelink_config::stores::RequestTimeoutSpec;
+use nativelink_error::{Code, Error, make_err};
+use nativelink_macro::nativelink_test;
+use nativelink_store::object_store_utils::{RequestTimeouts, hedged};
+use pretty_assertions::assert_eq;
+
+#[nativelink_test]
+async fn hedged_request_wins_over_stuck_request_test() -> Result<(), Error> {
+    let hedged_requests = AtomicU64::new(0);
+    let calls = AtomicU64::new(0);
+    let result = hedged(Some(Duration::from_millis(10)), &hedged_requests, || {
+        let call = calls.fetch_add(1, Ordering::Relaxed);
+        async move {
+            if call == 0 {
+                pending::<()>().await;
+            }
+            Ok::<_, Error>(call)
+        }
+    })
+    .await?;
+    assert_eq!(result, 1);
+    assert_eq!(hedged_requests.load(Ordering::Relaxed), 1);
+
+    // Requests which respond in time are not hedged.
+    let result = hedged(Some(Duration::from_secs(10)), &hedged_requests, || {
+        ready(Ok::<_, Error>(2))
+    })
+    .await?;
+    assert_eq!(result, 2);
+    assert_eq!(hedged_requests.load(Ordering::Relaxed), 1);
+
+    // A failure of the slow request is replaced by the hedged response.
+    calls.store(0, Ordering::Relaxed);
+    let result = hedged(Some(Duration::from_millis(10)), &hedged_requests, || {
+        let call = calls.fetch_add(1, Ordering::Relaxed);
+        async move {
+            if call == 0 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                return Err(make_err!(Code::Unavailable, "Slow failure"));
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(call)
+        }
+    })
+    .await?;
+    assert_eq!(result, 1);
+    assert_eq!(hedged_requests.load(Ordering::Relaxed), 2);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn request_deadlines_test() -> Result<(), Error> {
+    let timeouts = RequestTimeouts::new(&RequestTimeoutSpec {
+        first_byte_timeout_ms: 10,
+        total_timeout_ms: 100,
+    });
+    let deadline = timeouts.start();
+    let err = deadline.response(pending::<()>()).await.unwrap_err();
+    assert_eq!(err.code, Code::DeadlineExceeded);
+    // The body of the response may take until the total deadline.
+    deadline
+        .body(tokio::time::sleep(Duration::from_millis(20)))
+        .await?;
+    let err = deadline.body(pending::<()>()).await.unwrap_err();
+    assert_eq!(err.code, Code::DeadlineExceeded);
+
+    // Without timeouts requests may take as long as they need.
+    let deadline = RequestTimeouts::new(&RequestTimeoutSpec::default()).start();
+    assert_eq!(deadline.response(ready(1)).await?, 1);
+    Ok(())
+}
@@ -0,0 +1,96 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use nativelink_config::stores::{MemorySpec, PackingSpec, StoreSpec};
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_store::packing_store::PackingStore;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{Store, StoreKey, StoreLike};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALID_HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+const VALID_HASH3: &str = "0123456789abcdef000000000000000000030000000000000123456789abcdef";
+
+fn make_packing_store(segment_store: &Store) -> Result<Arc<PackingStore>, Error> {
+    PackingStore::new(
+        &PackingSpec {
+            index_store: StoreSpec::Memory(MemorySpec::default()),
+            segment_store: StoreSpec::Memory(MemorySpec::default()),
+            max_packed_object_size: 8,
+            // Filled by exactly the two small objects below.
+            segment_size: 11,
+            // Long enough that only full segments are written.
+            flush_interval_ms: 60_000,
+        },
+        Store::new(MemoryStore::new(&MemorySpec::default())),
+        segment_store.clone(),
+    )
+}
+
+#[nativelink_test]
+async fn packs_small_objects_into_one_segment_test() -> Result<(), Error> {
+    let segment_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = make_packing_store(&segment_store)?;
+
+    let digest1 = DigestInfo::try_new(VALID_HASH1, 5)?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, 6)?;
+    let digest3 = DigestInfo::try_new(VALID_HASH3, 9)?;
+    let (update1, update2) = tokio::join!(
+        store.update_oneshot(digest1, "hello".into()),
+        store.update_oneshot(digest2, "world!".into()),
+    );
+    update1?;
+    update2?;
+    // Too large to be packed, so it is written on its own.
+    store.update_oneshot(digest3, "unpacked!".into()).await?;
+
+    let mut segment_keys = Vec::new();
+    segment_store
+        .list(.., |key| {
+            segment_keys.push(key.borrow().into_owned());
+            true
+        })
+        .await?;
+    // One segment with both small objects plus the large object.
+    assert_eq!(segment_keys.len(), 2);
+    assert!(segment_keys.contains(&StoreKey::Digest(digest3)));
+
+    assert_eq!(store.has(digest1).await?, Some(5));
+    assert_eq!(store.has(digest2).await?, Some(6));
+    assert_eq!(store.has(digest3).await?, Some(9));
+    assert_eq!(store.get_part_unchunked(digest1, 0, None).await?, "hello");
+    assert_eq!(store.get_part_unchunked(digest2, 0, None).await?, "world!");
+    assert_eq!(store.get_part_unchunked(digest2, 2, Some(3)).await?, "rld");
+    assert_eq!(
+        store.get_part_unchunked(digest3, 0, None).await?,
+        "unpacked!"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn missing_object_test() -> Result<(), Error> {
+    let segment_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = make_packing_store(&segment_store)?;
+
+    let digest1 = DigestInfo::try_new(VALID_HASH1, 5)?;
+    assert_eq!(store.has(digest1).await?, None);
+    assert!(store.get_part_unchunked(digest1, 0, None).await.is_err());
+    Ok(())
+}
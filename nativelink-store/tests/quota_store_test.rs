@@ -0,0 +1,162 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use nativelink_config::stores::{MemorySpec, QuotaSpec, StoreSpec};
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_store::quota_store::{QuotaStore, QuotaUsage};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{Store, StoreLike};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALID_HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+const VALID_HASH3: &str = "0123456789abcdef000000000000000000030000000000000123456789abcdef";
+
+fn make_quota_store(
+    soft_limit_bytes: u64,
+    hard_limit_bytes: u64,
+) -> Result<(Arc<QuotaStore>, Store), Error> {
+    let backend = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let store = QuotaStore::new(
+        &QuotaSpec {
+            backend: StoreSpec::Memory(MemorySpec::default()),
+            soft_limit_bytes,
+            hard_limit_bytes,
+        },
+        backend.clone(),
+    )?;
+    Ok((store, backend))
+}
+
+/// Waits for the background tasks of the quota store to catch up.
+async fn wait_for(mut condition: impl AsyncFnMut() -> bool) {
+    for _ in 0..1000 {
+        if condition().await {
+            return;
+        }
+        tokio::task::yield_now().await;
+    }
+}
+
+#[nativelink_test]
+async fn accounts_usage_test() -> Result<(), Error> {
+    let (quota_store, backend) = make_quota_store(0, 0)?;
+    let store = Store::new(quota_store.clone());
+    let digest1 = DigestInfo::try_new(VALID_HASH1, 10)?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, 20)?;
+    store.update_oneshot(digest1, vec![1u8; 10].into()).await?;
+    store.update_oneshot(digest2, vec![2u8; 20].into()).await?;
+    assert_eq!(
+        quota_store.usage().await,
+        QuotaUsage {
+            bytes: 30,
+            objects: 2,
+            reserved_bytes: 0,
+        }
+    );
+
+    assert!(store.remove(digest1).await?);
+    assert_eq!(backend.has(digest1).await?, None);
+    assert_eq!(quota_store.usage().await.bytes, 20);
+
+    // Objects the backend removes on its own are no longer accounted.
+    assert!(backend.remove(digest2).await?);
+    assert_eq!(quota_store.usage().await.objects, 0);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn soft_limit_removes_least_recently_used_test() -> Result<(), Error> {
+    let (quota_store, backend) = make_quota_store(100, 0)?;
+    let store = Store::new(quota_store.clone());
+    let digest1 = DigestInfo::try_new(VALID_HASH1, 40)?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, 40)?;
+    let digest3 = DigestInfo::try_new(VALID_HASH3, 40)?;
+    store.update_oneshot(digest1, vec![1u8; 40].into()).await?;
+    store.update_oneshot(digest2, vec![2u8; 40].into()).await?;
+    store.get_part_unchunked(digest1, 0, None).await?;
+    store.update_oneshot(digest3, vec![3u8; 40].into()).await?;
+
+    wait_for(async || backend.has(digest2).await.unwrap().is_none()).await;
+    assert_eq!(backend.has(digest1).await?, Some(40));
+    assert_eq!(backend.has(digest2).await?, None);
+    assert_eq!(backend.has(digest3).await?, Some(40));
+    assert_eq!(quota_store.usage().await.bytes, 80);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn hard_limit_rejects_uploads_test() -> Result<(), Error> {
+    let (quota_store, backend) = make_quota_store(0, 100)?;
+    let store = Store::new(quota_store.clone());
+    let digest1 = DigestInfo::try_new(VALID_HASH1, 60)?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, 60)?;
+    store.update_oneshot(digest1, vec![1u8; 60].into()).await?;
+
+    let err = store
+        .update_oneshot(digest2, vec![2u8; 60].into())
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::ResourceExhausted);
+    assert_eq!(backend.has(digest2).await?, None);
+    assert_eq!(
+        quota_store.usage().await,
+        QuotaUsage {
+            bytes: 60,
+            objects: 1,
+            reserved_bytes: 0,
+        }
+    );
+
+    // Once space is freed uploads are accepted again.
+    store.remove(digest1).await?;
+    store.update_oneshot(digest2, vec![2u8; 60].into()).await?;
+    Ok(())
+}
+
+#[nativelink_test]
+async fn counts_existing_objects_test() -> Result<(), Error> {
+    let backend = Store::new(MemoryStore::new(&MemorySpec::default()));
+    let digest1 = DigestInfo::try_new(VALID_HASH1, 10)?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, 20)?;
+    backend
+        .update_oneshot(digest1, vec![1u8; 10].into())
+        .await?;
+    backend
+        .update_oneshot(digest2, vec![2u8; 20].into())
+        .await?;
+
+    let quota_store = QuotaStore::new(
+        &QuotaSpec {
+            backend: StoreSpec::Memory(MemorySpec::default()),
+            soft_limit_bytes: 0,
+            hard_limit_bytes: 0,
+        },
+        backend,
+    )?;
+    wait_for(async || quota_store.usage().await.objects == 2).await;
+    assert_eq!(quota_store.usage().await.bytes, 30);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn invalid_limits_test() -> Result<(), Error> {
+    let err = make_quota_store(100, 50).unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument);
+    Ok(())
+}
@@ -26,7 +26,7 @@ use fred::mocks::{MockCommand, Mocks};
 use fred::prelude::{Builder, Pool as RedisPool};
 use fred::types::Value as RedisValue;
 use fred::types::config::{Config as RedisConfig, PerformanceConfig};
-use nativelink_config::stores::RedisSpec;
+use nativelink_config::stores::{ClientTlsConfig, ElastiCacheIamAuthSpec, RedisMode, RedisSpec};
 use nativelink_error::{Code, Error};
 use nativelink_macro::nativelink_test;
 use nativelink_store::cas_utils::ZERO_BYTE_DIGESTS;
@@ -304,6 +304,78 @@ async fn upload_and_get_data() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn upload_and_get_data_with_key_ttl() -> Result<(), Error> {
+    const KEY_TTL_S: u64 = 3600;
+    let data = Bytes::from_static(b"14");
+    let chunk_data = RedisValue::Bytes(data.clone());
+
+    let digest = DigestInfo::try_new(VALID_HASH1, 2)?;
+    let packed_hash_hex = format!("{digest}");
+
+    let temp_key = RedisValue::Bytes(make_temp_key(&packed_hash_hex).into());
+    let real_key = RedisValue::Bytes(packed_hash_hex.into());
+    let expire = MockCommand {
+        cmd: Str::from_static("EXPIRE"),
+        subcommand: None,
+        args: vec![real_key.clone(), RedisValue::Integer(KEY_TTL_S as i64)],
+    };
+
+    let mocks = Arc::new(MockRedisBackend::new());
+    mocks
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("SETRANGE"),
+                subcommand: None,
+                args: vec![temp_key.clone(), 0.into(), chunk_data],
+            },
+            Ok(RedisValue::Array(vec![RedisValue::Null])),
+        )
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("STRLEN"),
+                subcommand: None,
+                args: vec![temp_key.clone()],
+            },
+            Ok(RedisValue::Array(vec![RedisValue::Integer(
+                data.len() as i64
+            )])),
+        )
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("RENAME"),
+                subcommand: None,
+                args: vec![temp_key, real_key.clone()],
+            },
+            Ok(RedisValue::Array(vec![RedisValue::Null])),
+        )
+        // Writing the key sets its expiration.
+        .expect(expire.clone(), Ok(RedisValue::Integer(1)))
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("GETRANGE"),
+                subcommand: None,
+                args: vec![real_key, RedisValue::Integer(0), RedisValue::Integer(1)],
+            },
+            Ok(RedisValue::String(Str::from_static("14"))),
+        )
+        // Reading the key restarts its expiration.
+        .expect(expire, Ok(RedisValue::Integer(1)));
+
+    let store = make_mock_store(&mocks).with_key_ttl(KEY_TTL_S, true);
+
+    store.update_oneshot(digest, data.clone()).await.unwrap();
+
+    let result = store
+        .get_part_unchunked(digest, 0, Some(data.len() as u64))
+        .await
+        .unwrap();
+
+    assert_eq!(result, data, "Expected redis store to have updated value",);
+
+    Ok(())
+}
+
 #[nativelink_test]
 async fn upload_and_get_data_with_prefix() -> Result<(), Error> {
     let data = Bytes::from_static(b"14");
@@ -868,6 +940,77 @@ fn test_connection_errors() {
     );
 }
 
+#[nativelink_test]
+fn test_multiple_addresses() {
+    let spec = RedisSpec {
+        addresses: vec![
+            "redis://node-1:6379/".to_string(),
+            "redis://node-2:6379/".to_string(),
+        ],
+        ..Default::default()
+    };
+    let err = RedisStore::new(spec).expect_err("Multiple addresses in standard mode");
+    assert_eq!(err.code, Code::InvalidArgument);
+
+    let spec = RedisSpec {
+        addresses: vec![
+            "redis-cluster://node-1:6379".to_string(),
+            "redis-cluster://node-2:6379".to_string(),
+        ],
+        mode: RedisMode::Cluster,
+        ..Default::default()
+    };
+    RedisStore::new(spec).expect("Multiple addresses in cluster mode");
+
+    let spec = RedisSpec {
+        addresses: vec![
+            "redis-sentinel://sentinel-1:26379/0?sentinelServiceName=mymaster".to_string(),
+            "redis-sentinel://sentinel-2:26379/0?sentinelServiceName=mymaster".to_string(),
+        ],
+        mode: RedisMode::Sentinel,
+        ..Default::default()
+    };
+    RedisStore::new(spec).expect("Multiple addresses in sentinel mode");
+}
+
+#[nativelink_test]
+fn test_auth_config_errors() {
+    let iam_auth = ElastiCacheIamAuthSpec {
+        cache_name: "my-cache".to_string(),
+        region: "us-east-1".to_string(),
+        serverless: false,
+    };
+    let spec = RedisSpec {
+        addresses: vec!["rediss://my-cache.cache.amazonaws.com:6379/".to_string()],
+        elasticache_iam_auth: Some(iam_auth.clone()),
+        ..Default::default()
+    };
+    let err = RedisStore::new(spec).expect_err("IAM auth without a username");
+    assert_eq!(err.code, Code::InvalidArgument);
+
+    let spec = RedisSpec {
+        addresses: vec!["redis://my-cache.cache.amazonaws.com:6379/".to_string()],
+        username: Some("nativelink".to_string()),
+        elasticache_iam_auth: Some(iam_auth),
+        ..Default::default()
+    };
+    let err = RedisStore::new(spec).expect_err("IAM auth without TLS");
+    assert_eq!(err.code, Code::InvalidArgument);
+
+    let spec = RedisSpec {
+        addresses: vec!["redis://nativelink.com:6379/".to_string()],
+        tls_config: Some(ClientTlsConfig {
+            ca_file: None,
+            cert_file: None,
+            key_file: None,
+            use_native_roots: None,
+        }),
+        ..Default::default()
+    };
+    let err = RedisStore::new(spec).expect_err("TLS without root certificates");
+    assert_eq!(err.code, Code::InvalidArgument);
+}
+
 #[nativelink_test]
 fn test_health() {
     let spec = RedisSpec {
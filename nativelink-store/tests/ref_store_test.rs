@@ -19,10 +19,11 @@ use nativelink_config::stores::{MemorySpec, RefSpec};
 use nativelink_error::Error;
 use nativelink_macro::nativelink_test;
 use nativelink_store::memory_store::MemoryStore;
+use nativelink_store::noop_store::NoopStore;
 use nativelink_store::ref_store::RefStore;
 use nativelink_store::store_manager::StoreManager;
 use nativelink_util::common::DigestInfo;
-use nativelink_util::store_trait::{Store, StoreDriver, StoreLike};
+use nativelink_util::store_trait::{Store, StoreDriver, StoreLike, StoreOptimizations};
 use pretty_assertions::assert_eq;
 
 const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
@@ -158,12 +159,63 @@ async fn inner_store_test() -> Result<(), Error> {
     ));
     store_manager.add_store("ref_store_outer", ref_store_outer.clone());
 
-    // Ensure the result of inner_store() points to exact same memory store.
+    // The referenced store may be repointed, so inner_store() stops at the
+    // ref store, but the optimizations of the referenced store are forwarded.
     assert_eq!(
         from_ref::<dyn StoreDriver>(ref_store_outer.inner_store(Option::<DigestInfo>::None))
             .cast::<()>(),
-        from_ref::<dyn StoreDriver>(memory_store.into_inner().as_ref()).cast::<()>(),
-        "Expected inner store to be memory store"
+        from_ref::<dyn StoreDriver>(ref_store_outer.clone().into_inner().as_ref()).cast::<()>(),
+        "Expected inner store to be the ref store"
+    );
+    assert!(!ref_store_outer.optimized_for(StoreOptimizations::NoopUpdates));
+    store_manager.add_store("mem_store", Store::new(NoopStore::new()));
+    assert!(ref_store_outer.optimized_for(StoreOptimizations::NoopUpdates));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn follows_repointed_store_test() -> Result<(), Error> {
+    const VALUE1: &str = "13";
+    let digest = DigestInfo::try_new(VALID_HASH1, VALUE1.len())?;
+
+    let (store_manager, memory_store, ref_store) = setup_stores();
+    memory_store.update_oneshot(digest, VALUE1.into()).await?;
+    assert_eq!(ref_store.has(digest).await?, Some(VALUE1.len() as u64));
+
+    let other_memory_store = Store::new(MemoryStore::new(&MemorySpec::default()));
+    store_manager.add_store("other", other_memory_store.clone());
+    store_manager.repoint_store("foo", "other")?;
+    assert_eq!(ref_store.has(digest).await?, None);
+    ref_store.update_oneshot(digest, VALUE1.into()).await?;
+    assert_eq!(
+        other_memory_store.has(digest).await?,
+        Some(VALUE1.len() as u64)
+    );
+
+    assert!(store_manager.repoint_store("foo", "missing").is_err());
+    assert!(store_manager.remove_store("foo").is_some());
+    assert!(ref_store.has(digest).await.is_err());
+    assert_eq!(store_manager.store_names(), vec!["bar", "other"]);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn releases_previous_store_test() -> Result<(), Error> {
+    let digest = DigestInfo::try_new(VALID_HASH1, 0)?;
+
+    let (store_manager, memory_store, ref_store) = setup_stores();
+    assert_eq!(ref_store.has(digest).await?, None);
+    let memory_store = Arc::downgrade(&memory_store.into_inner());
+
+    store_manager.add_store(
+        "other",
+        Store::new(MemoryStore::new(&MemorySpec::default())),
+    );
+    store_manager.repoint_store("foo", "other")?;
+    assert_eq!(ref_store.has(digest).await?, None);
+    assert!(
+        memory_store.upgrade().is_none(),
+        "Expected the previous store to be dropped"
     );
     Ok(())
 }
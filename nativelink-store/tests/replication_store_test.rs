@@ -0,0 +1,164 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use nativelink_config::stores::{MemorySpec, ReplicationSpec, StoreSpec};
+use nativelink_error::{Code, Error, make_err};
+use nativelink_macro::nativelink_test;
+use nativelink_metric::MetricsComponent;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_store::replication_store::ReplicationStore;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::health_utils::{HealthStatusIndicator, default_health_status_indicator};
+use nativelink_util::store_trait::{
+    RemoveItemCallback, Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo,
+};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const DATA: &str = "0123456789";
+
+/// Store failing every request, like a replica which is down.
+#[derive(Debug, MetricsComponent)]
+struct UnavailableStore;
+
+#[async_trait]
+impl StoreDriver for UnavailableStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        _keys: &[StoreKey<'_>],
+        _results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        Err(make_err!(Code::Unavailable, "Replica is down"))
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        _key: StoreKey<'_>,
+        _reader: DropCloserReadHalf,
+        _size_info: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        Err(make_err!(Code::Unavailable, "Replica is down"))
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        _key: StoreKey<'_>,
+        _writer: &mut DropCloserWriteHalf,
+        _offset: u64,
+        _length: Option<u64>,
+    ) -> Result<(), Error> {
+        Err(make_err!(Code::Unavailable, "Replica is down"))
+    }
+
+    fn inner_store(&self, _key: Option<StoreKey>) -> &'_ dyn StoreDriver {
+        self
+    }
+
+    fn as_any(&self) -> &(dyn core::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn core::any::Any + Sync + Send + 'static> {
+        self
+    }
+
+    fn register_remove_callback(
+        self: Arc<Self>,
+        _callback: &Arc<Box<dyn RemoveItemCallback>>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+default_health_status_indicator!(UnavailableStore);
+
+fn make_memory_store() -> Store {
+    Store::new(MemoryStore::new(&MemorySpec::default()))
+}
+
+fn make_replication_store(replicas: Vec<Store>, write_quorum: usize) -> Result<Store, Error> {
+    let spec = ReplicationSpec {
+        replicas: replicas
+            .iter()
+            .map(|_| StoreSpec::Memory(MemorySpec::default()))
+            .collect(),
+        write_quorum,
+        max_pending_repairs: 0,
+    };
+    Ok(Store::new(ReplicationStore::new(&spec, replicas)?))
+}
+
+#[nativelink_test]
+async fn writes_to_all_replicas_test() -> Result<(), Error> {
+    let replicas = vec![make_memory_store(), make_memory_store()];
+    let store = make_replication_store(replicas.clone(), 0)?;
+    let digest = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+    store.update_oneshot(digest, DATA.into()).await?;
+
+    assert_eq!(replicas[0].has(digest).await?, Some(DATA.len() as u64));
+    assert_eq!(replicas[1].has(digest).await?, Some(DATA.len() as u64));
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, DATA);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn read_fails_over_and_repairs_test() -> Result<(), Error> {
+    let replicas = vec![make_memory_store(), make_memory_store()];
+    let store = make_replication_store(replicas.clone(), 0)?;
+    let digest = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+    replicas[1].update_oneshot(digest, DATA.into()).await?;
+
+    assert_eq!(store.has(digest).await?, Some(DATA.len() as u64));
+    assert_eq!(store.get_part_unchunked(digest, 2, Some(3)).await?, "234");
+
+    // The first replica gets the object copied in the background.
+    for _ in 0..1000 {
+        if replicas[0].has(digest).await?.is_some() {
+            break;
+        }
+        tokio::task::yield_now().await;
+    }
+    assert_eq!(replicas[0].get_part_unchunked(digest, 0, None).await?, DATA);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn write_quorum_test() -> Result<(), Error> {
+    let memory_store = make_memory_store();
+    let unavailable_store = Store::new(Arc::new(UnavailableStore));
+    let digest = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+
+    let store = make_replication_store(vec![unavailable_store.clone(), memory_store.clone()], 0)?;
+    let err = store.update_oneshot(digest, DATA.into()).await.unwrap_err();
+    assert_eq!(err.code, Code::Unavailable);
+
+    let store = make_replication_store(vec![unavailable_store, memory_store.clone()], 1)?;
+    store.update_oneshot(digest, DATA.into()).await?;
+    assert_eq!(memory_store.has(digest).await?, Some(DATA.len() as u64));
+    // Reads skip the replica which is down.
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, DATA);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn invalid_write_quorum_test() -> Result<(), Error> {
+    let err = make_replication_store(vec![make_memory_store()], 2).unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument);
+    Ok(())
+}
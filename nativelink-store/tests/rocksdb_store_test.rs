@@ -0,0 +1,104 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::time::Duration;
+use std::env;
+
+use nativelink_config::stores::RocksdbSpec;
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_store::rocksdb_store::RocksdbStore;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{StoreKey, StoreLike};
+use pretty_assertions::assert_eq;
+use rand::Rng;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+
+fn make_temp_path(name: &str) -> String {
+    format!(
+        "{}/{}-{}",
+        env::var("TEST_TMPDIR").unwrap_or_else(|_| env::temp_dir().to_str().unwrap().to_string()),
+        name,
+        rand::rng().random::<u64>(),
+    )
+}
+
+#[nativelink_test]
+async fn column_families_share_database_test() -> Result<(), Error> {
+    let path = make_temp_path("column_families_share_database");
+    let cas_spec = RocksdbSpec {
+        path: path.clone(),
+        column_family: "cas".to_string(),
+        ..Default::default()
+    };
+    let ac_spec = RocksdbSpec {
+        path,
+        column_family: "ac".to_string(),
+        ..Default::default()
+    };
+    let digest1 = DigestInfo::try_new(VALID_HASH1, 10)?;
+    {
+        let cas_store = RocksdbStore::new(&cas_spec).await?;
+        let ac_store = RocksdbStore::new(&ac_spec).await?;
+        cas_store
+            .update_oneshot(digest1, "0123456789".into())
+            .await?;
+        ac_store.update_oneshot(digest1, "ac entry".into()).await?;
+
+        assert_eq!(
+            cas_store.get_part_unchunked(digest1, 3, Some(4)).await?,
+            "3456"
+        );
+        assert_eq!(cas_store.get_part_unchunked(digest1, 8, None).await?, "89");
+        assert_eq!(
+            ac_store.get_part_unchunked(digest1, 0, None).await?,
+            "ac entry"
+        );
+    }
+
+    // Both column families are found again once the database is reopened.
+    let cas_store = RocksdbStore::new(&cas_spec).await?;
+    let ac_store = RocksdbStore::new(&ac_spec).await?;
+    assert_eq!(cas_store.has(digest1).await?, Some(10));
+    assert_eq!(ac_store.has(digest1).await?, Some(8));
+
+    assert!(cas_store.remove(digest1).await?);
+    assert_eq!(cas_store.has(digest1).await?, None);
+    assert_eq!(ac_store.has(digest1).await?, Some(8));
+    let err = cas_store
+        .get_part_unchunked(digest1, 0, None)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::NotFound);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn expired_items_are_missing_test() -> Result<(), Error> {
+    let store = RocksdbStore::new(&RocksdbSpec {
+        path: make_temp_path("expired_items_are_missing"),
+        max_age_seconds: 1,
+        ..Default::default()
+    })
+    .await?;
+    let key = StoreKey::new_str("action");
+    store.update_oneshot(key.borrow(), "result".into()).await?;
+    assert_eq!(store.has(key.borrow()).await?, Some(6));
+
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert_eq!(store.has(key.borrow()).await?, None);
+    assert!(store.get_part_unchunked(key, 0, None).await.is_err());
+    Ok(())
+}
@@ -26,7 +26,7 @@ use http::header;
 use http::status::StatusCode;
 use http_body::Frame;
 use mock_instant::thread_local::MockClock;
-use nativelink_config::stores::{CommonObjectSpec, ExperimentalAwsSpec};
+use nativelink_config::stores::{CommonObjectSpec, ExistingObjectCheck, ExperimentalAwsSpec};
 use nativelink_error::{Code, Error, ResultExt, make_err, make_input_err};
 use nativelink_macro::nativelink_test;
 use nativelink_store::s3_store::S3Store;
@@ -450,6 +450,53 @@ async fn get_part_simple_retries() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn get_part_reports_checksum_mismatch() -> Result<(), Error> {
+    const VALUE: &str = "23";
+    // CRC32C of no data, which does not match `VALUE`.
+    const WRONG_CHECKSUM: &str = "AAAAAA==";
+
+    let mock_client = StaticReplayClient::new(vec![ReplayEvent::new(
+        http::Request::builder()
+            .uri(format!(
+                "https://{BUCKET_NAME}.s3.{REGION}.amazonaws.com/{VALID_HASH1}-{}?x-id=GetObject",
+                VALUE.len()
+            ))
+            .header("x-amz-checksum-mode", "ENABLED")
+            .body(SdkBody::empty())
+            .unwrap(),
+        http::Response::builder()
+            .status(StatusCode::OK)
+            .header("x-amz-checksum-crc32c", WRONG_CHECKSUM)
+            .body(SdkBody::from(VALUE))
+            .unwrap(),
+    )]);
+    let test_config = Builder::new()
+        .behavior_version(BehaviorVersion::v2025_08_07())
+        .region(Region::from_static(REGION))
+        .http_client(mock_client.clone())
+        .build();
+    let s3_client = aws_sdk_s3::Client::from_conf(test_config);
+    let store = S3Store::new_with_client_and_jitter(
+        &ExperimentalAwsSpec {
+            bucket: BUCKET_NAME.to_string(),
+            verify_checksums: true,
+            ..Default::default()
+        },
+        s3_client,
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default,
+    )?;
+
+    let err = store
+        .get_part_unchunked(DigestInfo::try_new(VALID_HASH1, VALUE.len())?, 0, None)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::DataLoss, "{err:?}");
+    mock_client.assert_requests_match(&[]);
+    Ok(())
+}
+
 #[nativelink_test]
 async fn multipart_update_large_cas() -> Result<(), Error> {
     // Same as in s3_store.
@@ -576,6 +623,197 @@ async fn multipart_update_large_cas() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn multipart_update_uses_configured_part_size() -> Result<(), Error> {
+    const PART_SIZE: usize = 7 * 1024 * 1024; // 7mb.
+    const AC_ENTRY_SIZE: usize = PART_SIZE + 50;
+
+    let mut send_data = Vec::with_capacity(AC_ENTRY_SIZE);
+    for i in 0..send_data.capacity() {
+        send_data.push(((i * 3) % 256) as u8);
+    }
+    let digest = DigestInfo::try_new(VALID_HASH1, send_data.len())?;
+
+    let mock_client = StaticReplayClient::new(vec![
+            ReplayEvent::new(
+                http::Request::builder()
+                    .uri(format!(
+                        "https://{BUCKET_NAME}.s3.{REGION}.amazonaws.com/{VALID_HASH1}-{AC_ENTRY_SIZE}?uploads",
+                    ))
+                    .method("POST")
+                    .body(SdkBody::empty())
+                    .unwrap(),
+                http::Response::builder()
+                    .status(StatusCode::OK)
+                    .body(SdkBody::from(
+                        r#"
+                        <InitiateMultipartUploadResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+                          <UploadId>Dummy-uploadid</UploadId>
+                        </InitiateMultipartUploadResult>"#
+                            .as_bytes(),
+                    ))
+                    .unwrap(),
+            ),
+            ReplayEvent::new(
+                http::Request::builder()
+                    .uri(format!(
+                        "https://{BUCKET_NAME}.s3.{REGION}.amazonaws.com/{VALID_HASH1}-{AC_ENTRY_SIZE}?x-id=UploadPart&partNumber=1&uploadId=Dummy-uploadid",
+                    ))
+                    .method("PUT")
+                    .header("content-type", "application/octet-stream")
+                    .header("content-length", "7340032")
+                    .body(SdkBody::from(&send_data[0..PART_SIZE]))
+                    .unwrap(),
+                http::Response::builder()
+                    .status(StatusCode::OK)
+                    .body(SdkBody::empty())
+                    .unwrap(),
+            ),
+            ReplayEvent::new(
+                http::Request::builder()
+                    .uri(format!(
+                        "https://{BUCKET_NAME}.s3.{REGION}.amazonaws.com/{VALID_HASH1}-{AC_ENTRY_SIZE}?x-id=UploadPart&partNumber=2&uploadId=Dummy-uploadid",
+                    ))
+                    .method("PUT")
+                    .header("content-type", "application/octet-stream")
+                    .header("content-length", "50")
+                    .body(SdkBody::from(&send_data[PART_SIZE..AC_ENTRY_SIZE]))
+                    .unwrap(),
+                http::Response::builder()
+                    .status(StatusCode::OK)
+                    .body(SdkBody::empty())
+                    .unwrap(),
+            ),
+            ReplayEvent::new(
+                http::Request::builder()
+                    .uri(format!(
+                        "https://{BUCKET_NAME}.s3.{REGION}.amazonaws.com/{VALID_HASH1}-{AC_ENTRY_SIZE}?uploadId=Dummy-uploadid",
+                    ))
+                    .method("POST")
+                    .header("content-length", "177")
+                    .body(SdkBody::from(concat!(
+                        r#"<CompleteMultipartUpload xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+                        "<Part><PartNumber>1</PartNumber></Part>",
+                        "<Part><PartNumber>2</PartNumber></Part>",
+                        "</CompleteMultipartUpload>",
+                    )))
+                    .unwrap(),
+                http::Response::builder()
+                    .status(StatusCode::OK)
+                    .body(SdkBody::from(concat!(
+                        "<CompleteMultipartUploadResult>",
+                        "</CompleteMultipartUploadResult>",
+                    )))
+                    .unwrap(),
+            ),
+        ]);
+    let test_config = Builder::new()
+        .behavior_version(BehaviorVersion::v2025_08_07())
+        .region(Region::from_static(REGION))
+        .http_client(mock_client.clone())
+        .build();
+    let s3_client = aws_sdk_s3::Client::from_conf(test_config);
+    let store = S3Store::new_with_client_and_jitter(
+        &ExperimentalAwsSpec {
+            bucket: BUCKET_NAME.to_string(),
+            multipart_part_size: Some(PART_SIZE as u64),
+            max_upload_bytes_per_second: 1024 * 1024 * 1024,
+            ..Default::default()
+        },
+        s3_client,
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default,
+    )?;
+    store
+        .update_oneshot(digest, send_data.clone().into())
+        .await
+        .unwrap();
+    mock_client.assert_requests_match(&[]);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn update_skips_existing_object_after_head() -> Result<(), Error> {
+    const VALUE: &str = "0123456789";
+
+    let mock_client = StaticReplayClient::new(vec![ReplayEvent::new(
+        http::Request::builder()
+            .uri(format!(
+                "https://{BUCKET_NAME}.s3.{REGION}.amazonaws.com/{VALID_HASH1}-{}",
+                VALUE.len()
+            ))
+            .body(SdkBody::empty())
+            .unwrap(),
+        http::Response::builder()
+            .header(header::CONTENT_LENGTH, VALUE.len().to_string())
+            .body(SdkBody::empty())
+            .unwrap(),
+    )]);
+    let test_config = Builder::new()
+        .behavior_version(BehaviorVersion::v2025_08_07())
+        .region(Region::from_static(REGION))
+        .http_client(mock_client.clone())
+        .build();
+    let s3_client = aws_sdk_s3::Client::from_conf(test_config);
+    let store = S3Store::new_with_client_and_jitter(
+        &ExperimentalAwsSpec {
+            bucket: BUCKET_NAME.to_string(),
+            existing_object_check: ExistingObjectCheck::Head,
+            ..Default::default()
+        },
+        s3_client,
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default,
+    )?;
+
+    // Only the HEAD request is sent, the upload is skipped.
+    store
+        .update_oneshot(DigestInfo::try_new(VALID_HASH1, VALUE.len())?, VALUE.into())
+        .await?;
+    mock_client.assert_requests_match(&[]);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn update_if_none_match_keeps_existing_object() -> Result<(), Error> {
+    const VALUE: &str = "0123456789";
+
+    // The replay client fails any retry, as it has no more responses.
+    let mock_client = StaticReplayClient::new(vec![ReplayEvent::new(
+        http::Request::builder().body(SdkBody::empty()).unwrap(),
+        http::Response::builder()
+            .status(StatusCode::PRECONDITION_FAILED)
+            .body(SdkBody::from(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+                <Error><Code>PreconditionFailed</Code>\
+                <Message>At least one of the pre-conditions you specified did not hold</Message>\
+                </Error>",
+            ))
+            .unwrap(),
+    )]);
+    let test_config = Builder::new()
+        .behavior_version(BehaviorVersion::v2025_08_07())
+        .region(Region::from_static(REGION))
+        .http_client(mock_client)
+        .build();
+    let s3_client = aws_sdk_s3::Client::from_conf(test_config);
+    let store = S3Store::new_with_client_and_jitter(
+        &ExperimentalAwsSpec {
+            bucket: BUCKET_NAME.to_string(),
+            existing_object_check: ExistingObjectCheck::IfNoneMatch,
+            ..Default::default()
+        },
+        s3_client,
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default,
+    )?;
+
+    store
+        .update_oneshot(DigestInfo::try_new(VALID_HASH1, VALUE.len())?, VALUE.into())
+        .await?;
+    Ok(())
+}
+
 #[nativelink_test]
 async fn ensure_empty_string_in_stream_works_test() -> Result<(), Error> {
     const CAS_ENTRY_SIZE: usize = 10; // Length of "helloworld".
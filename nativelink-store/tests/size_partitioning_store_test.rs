@@ -14,7 +14,7 @@
 
 use std::sync::Arc;
 
-use nativelink_config::stores::{MemorySpec, SizePartitioningSpec, StoreSpec};
+use nativelink_config::stores::{MemorySpec, SizePartitionSpec, SizePartitioningSpec, StoreSpec};
 use nativelink_error::Error;
 use nativelink_macro::nativelink_test;
 use nativelink_store::memory_store::MemoryStore;
@@ -45,11 +45,15 @@ fn setup_stores(
         &SizePartitioningSpec {
             size,
             lower_store: StoreSpec::Memory(MemorySpec::default()),
+            middle_stores: vec![],
             upper_store: StoreSpec::Memory(MemorySpec::default()),
+            dynamic: None,
         },
         Store::new(lower_memory_store.clone()),
+        vec![],
         Store::new(upper_memory_store.clone()),
-    );
+    )
+    .unwrap();
     (size_part_store, lower_memory_store, upper_memory_store)
 }
 
@@ -200,3 +204,79 @@ async fn update_test() -> Result<(), Error> {
     }
     Ok(())
 }
+
+#[nativelink_test]
+async fn middle_partition_test() -> Result<(), Error> {
+    const MIDDLE_HASH: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+    const MIDDLE_VALUE: &str = "12345";
+
+    let lower_memory_store = MemoryStore::new(&MemorySpec::default());
+    let middle_memory_store = MemoryStore::new(&MemorySpec::default());
+    let upper_memory_store = MemoryStore::new(&MemorySpec::default());
+    let size_part_store = SizePartitioningStore::new(
+        &SizePartitioningSpec {
+            size: 3,
+            lower_store: StoreSpec::Memory(MemorySpec::default()),
+            middle_stores: vec![SizePartitionSpec {
+                size: 6,
+                store: StoreSpec::Memory(MemorySpec::default()),
+            }],
+            upper_store: StoreSpec::Memory(MemorySpec::default()),
+            dynamic: None,
+        },
+        Store::new(lower_memory_store.clone()),
+        vec![Store::new(middle_memory_store.clone())],
+        Store::new(upper_memory_store.clone()),
+    )?;
+
+    let small_digest = DigestInfo::try_new(SMALL_HASH, SMALL_VALUE.len())?;
+    let middle_digest = DigestInfo::try_new(MIDDLE_HASH, MIDDLE_VALUE.len())?;
+    let big_digest = DigestInfo::try_new(BIG_HASH, BIG_VALUE.len())?;
+    size_part_store
+        .update_oneshot(small_digest, SMALL_VALUE.into())
+        .await?;
+    size_part_store
+        .update_oneshot(middle_digest, MIDDLE_VALUE.into())
+        .await?;
+    size_part_store
+        .update_oneshot(big_digest, BIG_VALUE.into())
+        .await?;
+
+    assert_eq!(
+        lower_memory_store.has(small_digest).await?,
+        Some(SMALL_VALUE.len() as u64)
+    );
+    assert_eq!(
+        middle_memory_store.has(middle_digest).await?,
+        Some(MIDDLE_VALUE.len() as u64)
+    );
+    assert_eq!(middle_memory_store.has(small_digest).await?, None);
+    assert_eq!(middle_memory_store.has(big_digest).await?, None);
+    assert_eq!(
+        upper_memory_store.has(big_digest).await?,
+        Some(BIG_VALUE.len() as u64)
+    );
+
+    let mut results = vec![None; 3];
+    size_part_store
+        .has_with_results(
+            &[small_digest.into(), middle_digest.into(), big_digest.into()],
+            &mut results,
+        )
+        .await?;
+    assert_eq!(
+        results,
+        vec![
+            Some(SMALL_VALUE.len() as u64),
+            Some(MIDDLE_VALUE.len() as u64),
+            Some(BIG_VALUE.len() as u64),
+        ]
+    );
+    assert_eq!(
+        size_part_store
+            .get_part_unchunked(middle_digest, 0, None)
+            .await?,
+        MIDDLE_VALUE
+    );
+    Ok(())
+}
@@ -0,0 +1,92 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::env;
+
+use nativelink_config::stores::{EvictionPolicy, SqliteSpec};
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_store::sqlite_store::SqliteStore;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::StoreLike;
+use pretty_assertions::assert_eq;
+use rand::Rng;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALID_HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+const VALID_HASH3: &str = "0123456789abcdef000000000000000000030000000000000123456789abcdef";
+
+fn make_temp_path(name: &str) -> String {
+    format!(
+        "{}/{}-{}.sqlite",
+        env::var("TEST_TMPDIR").unwrap_or_else(|_| env::temp_dir().to_str().unwrap().to_string()),
+        name,
+        rand::rng().random::<u64>(),
+    )
+}
+
+#[nativelink_test]
+async fn persists_across_reopen_test() -> Result<(), Error> {
+    let spec = SqliteSpec {
+        path: make_temp_path("persists_across_reopen"),
+        eviction_policy: None,
+    };
+    let digest1 = DigestInfo::try_new(VALID_HASH1, 10)?;
+    {
+        let store = SqliteStore::new(&spec).await?;
+        store.update_oneshot(digest1, "0123456789".into()).await?;
+        assert_eq!(store.get_part_unchunked(digest1, 3, Some(4)).await?, "3456");
+        assert_eq!(store.get_part_unchunked(digest1, 8, None).await?, "89");
+    }
+
+    let store = SqliteStore::new(&spec).await?;
+    assert_eq!(store.has(digest1).await?, Some(10));
+    assert_eq!(
+        store.get_part_unchunked(digest1, 0, None).await?,
+        "0123456789"
+    );
+
+    assert!(store.remove(digest1).await?);
+    assert_eq!(store.has(digest1).await?, None);
+    let err = store
+        .get_part_unchunked(digest1, 0, None)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, Code::NotFound);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn evicts_least_recently_used_test() -> Result<(), Error> {
+    let store = SqliteStore::new(&SqliteSpec {
+        path: make_temp_path("evicts_least_recently_used"),
+        eviction_policy: Some(EvictionPolicy {
+            max_bytes: 25,
+            ..Default::default()
+        }),
+    })
+    .await?;
+    let digest1 = DigestInfo::try_new(VALID_HASH1, 10)?;
+    let digest2 = DigestInfo::try_new(VALID_HASH2, 10)?;
+    let digest3 = DigestInfo::try_new(VALID_HASH3, 10)?;
+
+    store.update_oneshot(digest1, "0123456789".into()).await?;
+    store.update_oneshot(digest2, "0123456789".into()).await?;
+    store.update_oneshot(digest3, "0123456789".into()).await?;
+
+    assert_eq!(store.has(digest1).await?, None);
+    assert_eq!(store.has(digest2).await?, Some(10));
+    assert_eq!(store.has(digest3).await?, Some(10));
+    Ok(())
+}
@@ -0,0 +1,157 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nativelink_config::stores::{MemorySpec, StoreSpec, TierSpec, TieredSpec};
+use nativelink_error::{Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_store::tiered_store::TieredStore;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{Store, StoreLike};
+use pretty_assertions::assert_eq;
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const DATA: &str = "0123456789";
+const VALID_HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+
+fn tier_spec() -> TierSpec {
+    TierSpec {
+        store: StoreSpec::Memory(MemorySpec::default()),
+        promote_after_reads: 0,
+        max_bytes: 0,
+        skip_uploads: false,
+    }
+}
+
+fn make_tiered_store(tier_specs: Vec<TierSpec>) -> Result<(Store, Vec<Store>), Error> {
+    let tier_stores: Vec<Store> = tier_specs
+        .iter()
+        .map(|_| Store::new(MemoryStore::new(&MemorySpec::default())))
+        .collect();
+    let store = TieredStore::new(&TieredSpec { tiers: tier_specs }, tier_stores.clone())?;
+    Ok((Store::new(store), tier_stores))
+}
+
+#[nativelink_test]
+async fn upload_skips_tiers_and_read_promotes_test() -> Result<(), Error> {
+    let (store, tiers) = make_tiered_store(vec![
+        TierSpec {
+            skip_uploads: true,
+            ..tier_spec()
+        },
+        tier_spec(),
+        tier_spec(),
+    ])?;
+    let digest = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+    store.update_oneshot(digest, DATA.into()).await?;
+
+    assert_eq!(tiers[0].has(digest).await?, None);
+    assert_eq!(tiers[1].has(digest).await?, Some(DATA.len() as u64));
+    assert_eq!(tiers[2].has(digest).await?, Some(DATA.len() as u64));
+    assert_eq!(store.has(digest).await?, Some(DATA.len() as u64));
+
+    // Partial reads still copy the whole object.
+    assert_eq!(store.get_part_unchunked(digest, 2, Some(3)).await?, "234");
+    assert_eq!(tiers[0].get_part_unchunked(digest, 0, None).await?, DATA);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn promote_after_reads_test() -> Result<(), Error> {
+    let (store, tiers) = make_tiered_store(vec![
+        TierSpec {
+            promote_after_reads: 3,
+            skip_uploads: true,
+            ..tier_spec()
+        },
+        TierSpec {
+            promote_after_reads: 2,
+            skip_uploads: true,
+            ..tier_spec()
+        },
+        tier_spec(),
+    ])?;
+    let digest = DigestInfo::try_new(VALID_HASH1, DATA.len())?;
+    store.update_oneshot(digest, DATA.into()).await?;
+
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, DATA);
+    assert_eq!(tiers[0].has(digest).await?, None);
+    assert_eq!(tiers[1].has(digest).await?, None);
+
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, DATA);
+    assert_eq!(tiers[0].has(digest).await?, None);
+    assert_eq!(tiers[1].has(digest).await?, Some(DATA.len() as u64));
+
+    // Reads from the middle tier count towards the first tier too.
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, DATA);
+    assert_eq!(tiers[0].has(digest).await?, Some(DATA.len() as u64));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn demote_on_pressure_test() -> Result<(), Error> {
+    let (store, tiers) = make_tiered_store(vec![
+        TierSpec {
+            max_bytes: 100,
+            ..tier_spec()
+        },
+        TierSpec {
+            skip_uploads: true,
+            ..tier_spec()
+        },
+    ])?;
+    let data1 = vec![1u8; 60];
+    let digest1 = DigestInfo::try_new(VALID_HASH1, data1.len())?;
+    let data2 = vec![2u8; 60];
+    let digest2 = DigestInfo::try_new(VALID_HASH2, data2.len())?;
+    store.update_oneshot(digest1, data1.clone().into()).await?;
+    store.update_oneshot(digest2, data2.clone().into()).await?;
+
+    // The least recently used object moves to the next tier in the background.
+    for _ in 0..1000 {
+        if tiers[0].has(digest1).await?.is_none() {
+            break;
+        }
+        tokio::task::yield_now().await;
+    }
+    assert_eq!(tiers[0].has(digest1).await?, None);
+    assert_eq!(tiers[1].has(digest1).await?, Some(60));
+    assert_eq!(tiers[0].has(digest2).await?, Some(60));
+    assert_eq!(tiers[1].has(digest2).await?, None);
+
+    assert_eq!(store.get_part_unchunked(digest1, 0, None).await?, data1);
+    assert_eq!(store.get_part_unchunked(digest2, 0, None).await?, data2);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn invalid_tiers_test() -> Result<(), Error> {
+    let err = make_tiered_store(vec![
+        tier_spec(),
+        TierSpec {
+            max_bytes: 100,
+            ..tier_spec()
+        },
+    ])
+    .unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument);
+
+    let err = make_tiered_store(vec![TierSpec {
+        skip_uploads: true,
+        ..tier_spec()
+    }])
+    .unwrap_err();
+    assert_eq!(err.code, Code::InvalidArgument);
+    Ok(())
+}
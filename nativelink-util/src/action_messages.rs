@@ -33,7 +33,8 @@ use nativelink_proto::google::longrunning::operation::Result as LongRunningResul
 use nativelink_proto::google::rpc::Status;
 use prost::Message;
 use prost::bytes::Bytes;
-use prost_types::Any;
+use prost_types::value::Kind;
+use prost_types::{Any, Struct, Value};
 use serde::ser::Error as SerdeError;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -300,6 +301,13 @@ pub struct ActionInfo {
     /// This is primarily used to join actions/operations together using this key.
     #[metric(help = "Info used to uniquely identify this ActionInfo and if it is cacheable.")]
     pub unique_qualifier: ActionUniqueQualifier,
+    /// Whether the client asked for the result of this action to never be
+    /// cached (`Action.do_not_cache`). Such actions are never looked up in
+    /// or written to the action cache and are never merged with other
+    /// operations.
+    #[metric(help = "If the result of the action must not be cached.")]
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    pub do_not_cache: bool,
 }
 
 impl ActionInfo {
@@ -329,7 +337,7 @@ impl ActionInfo {
                 .err_tip(|| "Expected action_digest to exist on ExecuteRequest")?
                 .try_into()?,
         };
-        let unique_qualifier = if execute_request.skip_cache_lookup {
+        let unique_qualifier = if execute_request.skip_cache_lookup || action.do_not_cache {
             ActionUniqueQualifier::Uncacheable(unique_key)
         } else {
             ActionUniqueQualifier::Cacheable(unique_key)
@@ -363,6 +371,7 @@ impl ActionInfo {
             load_timestamp,
             insert_timestamp: queued_timestamp,
             unique_qualifier,
+            do_not_cache: action.do_not_cache,
         })
     }
 }
@@ -775,6 +784,18 @@ where
 }
 
 impl ActionStage {
+    /// Name of the stage, without its action result.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Unknown => "Unknown",
+            Self::CacheCheck => "CacheCheck",
+            Self::Queued => "Queued",
+            Self::Executing => "Executing",
+            Self::Completed(_) => "Completed",
+            Self::CompletedFromCache(_) => "CompletedFromCache",
+        }
+    }
+
     pub const fn has_action_result(&self) -> bool {
         match self {
             Self::Unknown | Self::CacheCheck | Self::Queued | Self::Executing => false,
@@ -810,15 +831,7 @@ impl MetricsComponent for ActionStage {
         _kind: MetricKind,
         _field_metadata: MetricFieldData,
     ) -> Result<MetricPublishKnownKindData, nativelink_metric::Error> {
-        let value = match self {
-            Self::Unknown => "Unknown".to_string(),
-            Self::CacheCheck => "CacheCheck".to_string(),
-            Self::Queued => "Queued".to_string(),
-            Self::Executing => "Executing".to_string(),
-            Self::Completed(_) => "Completed".to_string(),
-            Self::CompletedFromCache(_) => "CompletedFromCache".to_string(),
-        };
-        Ok(MetricPublishKnownKindData::String(value))
+        Ok(MetricPublishKnownKindData::String(self.name().to_string()))
     }
 }
 
@@ -1068,6 +1081,10 @@ impl TypeUrl for ExecuteOperationMetadata {
         "type.googleapis.com/build.bazel.remote.execution.v2.ExecuteOperationMetadata";
 }
 
+impl TypeUrl for Struct {
+    const TYPE_URL: &'static str = "type.googleapis.com/google.protobuf.Struct";
+}
+
 fn from_any<T>(message: &Any) -> Result<T, Error>
 where
     T: TypeUrl + Default,
@@ -1160,6 +1177,19 @@ impl ActionState {
     }
 
     pub fn as_operation(&self, client_operation_id: OperationId) -> Operation {
+        self.as_operation_with_queue_position(client_operation_id, None)
+    }
+
+    /// Same as `as_operation`, but also tells the client how many operations
+    /// are ahead of this one in the queue. The position is reported as a
+    /// `queue_position` field of a `google.protobuf.Struct` in the auxiliary
+    /// metadata of `partial_execution_metadata`, since the execution API has
+    /// no field for it.
+    pub fn as_operation_with_queue_position(
+        &self,
+        client_operation_id: OperationId,
+        maybe_queue_position: Option<usize>,
+    ) -> Operation {
         let stage = Into::<execution_stage::Value>::into(&self.stage) as i32;
         let name = client_operation_id.into_string();
 
@@ -1177,7 +1207,22 @@ impl ActionState {
             // TODO(palfrey) We should support stderr/stdout streaming.
             stdout_stream_name: String::default(),
             stderr_stream_name: String::default(),
-            partial_execution_metadata: None,
+            partial_execution_metadata: maybe_queue_position.map(|queue_position| {
+                let queue_info = Struct {
+                    fields: [(
+                        "queue_position".to_string(),
+                        Value {
+                            // Protobuf structs only have double numbers.
+                            kind: Some(Kind::NumberValue(queue_position as f64)),
+                        },
+                    )]
+                    .into(),
+                };
+                ExecutedActionMetadata {
+                    auxiliary_metadata: vec![to_any(&queue_info)],
+                    ..ExecutedActionMetadata::default()
+                }
+            }),
         };
 
         Operation {
@@ -0,0 +1,101 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nativelink_error::{Error, error_if};
+
+use crate::common::DigestInfo;
+
+/// Smallest number of bits a filter is created with.
+const MIN_BITS: u64 = 64;
+
+/// Set of digests which may answer "maybe present" for digests that were
+/// never inserted, at a rate chosen on creation, but never misses one that
+/// was. The digest hash is used as-is instead of hashing it again, which
+/// keeps the filter stable across processes so it can be persisted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    num_hashes: u32,
+    words: Vec<u64>,
+}
+
+impl BloomFilter {
+    /// Creates a filter sized to hold `expected_items` digests with at most
+    /// `false_positive_rate` (between 0 and 1) of lookups for other digests
+    /// reporting them as present.
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> Self {
+        let items = expected_items.max(1) as f64;
+        let rate = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let bits = (-items * rate.ln() / core::f64::consts::LN_2.powi(2)).ceil();
+        let num_bits = (bits as u64).max(MIN_BITS).next_multiple_of(64);
+        let num_hashes = ((num_bits as f64 / items) * core::f64::consts::LN_2).round();
+        Self {
+            num_hashes: (num_hashes as u32).clamp(1, 32),
+            words: vec![0; (num_bits / 64) as usize],
+        }
+    }
+
+    fn bit_indexes(&self, digest: &DigestInfo) -> impl Iterator<Item = u64> + use<> {
+        let hash = digest.packed_hash();
+        let hash1 = u64::from_le_bytes(hash[..8].try_into().unwrap());
+        let hash2 = u64::from_le_bytes(hash[8..16].try_into().unwrap()) ^ digest.size_bytes();
+        let num_bits = self.words.len() as u64 * 64;
+        (0..u64::from(self.num_hashes))
+            .map(move |i| hash1.wrapping_add(i.wrapping_mul(hash2 | 1)) % num_bits)
+    }
+
+    pub fn insert(&mut self, digest: &DigestInfo) {
+        for index in self.bit_indexes(digest) {
+            self.words[(index / 64) as usize] |= 1 << (index % 64);
+        }
+    }
+
+    /// Returns `false` if `digest` was never inserted, `true` if it probably
+    /// was.
+    pub fn contains(&self, digest: &DigestInfo) -> bool {
+        self.bit_indexes(digest)
+            .all(|index| self.words[(index / 64) as usize] & (1 << (index % 64)) != 0)
+    }
+
+    /// Serializes the filter to bytes which can be read back with
+    /// `from_bytes()`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + self.words.len() * 8);
+        bytes.extend_from_slice(&self.num_hashes.to_le_bytes());
+        for word in &self.words {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        error_if!(
+            bytes.len() < 4 + 8 || !(bytes.len() - 4).is_multiple_of(8),
+            "Bloom filter has invalid length {}",
+            bytes.len()
+        );
+        let (num_hashes, words) = bytes.split_at(4);
+        let num_hashes = u32::from_le_bytes(num_hashes.try_into().unwrap());
+        error_if!(
+            !(1..=32).contains(&num_hashes),
+            "Bloom filter has invalid number of hashes {num_hashes}"
+        );
+        Ok(Self {
+            num_hashes,
+            words: words
+                .chunks_exact(8)
+                .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+                .collect(),
+        })
+    }
+}
@@ -20,9 +20,10 @@ use std::sync::Arc;
 
 use futures::Future;
 use futures::stream::{FuturesUnordered, StreamExt, unfold};
-use nativelink_config::stores::Retry;
+use nativelink_config::stores::{LoadBalancingPolicy, Retry};
 use nativelink_error::{Code, Error, make_err};
 use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
 use tonic::transport::{Channel, Endpoint, channel};
 use tracing::{debug, error, info, warn};
 
@@ -37,6 +38,23 @@ pub struct ConnectionManager {
     worker_tx: mpsc::Sender<oneshot::Sender<Connection>>,
 }
 
+/// How long an endpoint is ejected for if no duration is configured.
+const DEFAULT_EJECTION_DURATION: Duration = Duration::from_secs(30);
+
+/// Configuration for how the `ConnectionManager` spreads requests over its
+/// endpoints.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadBalancing {
+    /// How a connection is picked for a request.
+    pub policy: LoadBalancingPolicy,
+    /// The number of consecutive transport errors after which an endpoint is
+    /// ejected, zero disables ejection.
+    pub max_endpoint_failures: usize,
+    /// How long an ejected endpoint is avoided for, zero uses the default of
+    /// 30 seconds.
+    pub ejection_duration: Duration,
+}
+
 /// The index into `ConnectionManagerWorker::endpoints`.
 type EndpointIndex = usize;
 /// The identifier for a given connection to a given Endpoint, used to identify
@@ -59,7 +77,7 @@ enum ConnectionRequest {
     /// Notify that a Connection was dropped, if it was dropped while the
     /// connection was still pending, then return the pending Channel to be
     /// added back to the available channels.
-    Dropped(Option<EstablishedChannel>),
+    Dropped((ChannelIdentifier, Option<EstablishedChannel>)),
     /// Notify that a Connection was established, return the Channel to the
     /// available channels.
     Connected(EstablishedChannel),
@@ -67,6 +85,9 @@ enum ConnectionRequest {
     /// specifies whether the connection was in the process of being established
     /// or not (i.e. whether it's been returned to available channels yet).
     Error((ChannelIdentifier, bool)),
+    /// Notify that a request on the given Channel got a response, which resets
+    /// the failure count of its endpoint.
+    Succeeded(ChannelIdentifier),
 }
 
 /// The result of a Future that connects to a given Endpoint.  This is a tuple
@@ -86,13 +107,34 @@ struct EstablishedChannel {
     identifier: ChannelIdentifier,
 }
 
+/// The state the worker keeps for each endpoint.
+struct EndpointState {
+    /// The endpoint to establish Channels to.
+    endpoint: Endpoint,
+    /// The identifier of the last connection attempt to the endpoint.
+    connection_index: ConnectionIndex,
+    /// The number of Connections to this endpoint that are currently in use.
+    in_flight: usize,
+    /// The number of transport errors since the last successful request.
+    consecutive_failures: usize,
+    /// If set, the endpoint is avoided until this time.
+    ejected_until: Option<Instant>,
+}
+
+impl EndpointState {
+    fn is_ejected(&self, now: Instant) -> bool {
+        self.ejected_until.is_some_and(|until| until > now)
+    }
+}
+
 /// The context of the worker used to manage all of the connections.  This
 /// handles reconnecting to endpoints on errors and multiple connections to a
 /// given endpoint.
 struct ConnectionManagerWorker {
-    /// The endpoints to establish Channels and the identifier of the last
-    /// connection attempt to that endpoint.
-    endpoints: Vec<(ConnectionIndex, Endpoint)>,
+    /// The endpoints to establish Channels to and their load and health.
+    endpoints: Vec<EndpointState>,
+    /// How Channels are picked and when endpoints are ejected.
+    load_balancing: LoadBalancing,
     /// The channel used to communicate between a Connection and the worker.
     connection_tx: mpsc::UnboundedSender<ConnectionRequest>,
     /// The number of connections that are currently allowed to be made.
@@ -123,6 +165,7 @@ impl ConnectionManager {
         mut max_concurrent_requests: usize,
         retry: Retry,
         jitter_fn: retry::JitterFn,
+        mut load_balancing: LoadBalancing,
     ) -> Self {
         let (worker_tx, worker_rx) = mpsc::channel(WORKER_BACKLOG);
         // The connection messages always come from sync contexts (e.g. drop)
@@ -132,7 +175,13 @@ impl ConnectionManager {
         let (connection_tx, connection_rx) = mpsc::unbounded_channel();
         let endpoints = endpoints
             .into_iter()
-            .map(|endpoint| (0, endpoint))
+            .map(|endpoint| EndpointState {
+                endpoint,
+                connection_index: 0,
+                in_flight: 0,
+                consecutive_failures: 0,
+                ejected_until: None,
+            })
             .collect();
 
         if max_concurrent_requests == 0 {
@@ -141,8 +190,12 @@ impl ConnectionManager {
         if connections_per_endpoint == 0 {
             connections_per_endpoint = 1;
         }
+        if load_balancing.ejection_duration.is_zero() {
+            load_balancing.ejection_duration = DEFAULT_EJECTION_DURATION;
+        }
         let worker = ConnectionManagerWorker {
             endpoints,
+            load_balancing,
             available_connections: max_concurrent_requests,
             connection_tx,
             connecting_channels: FuturesUnordered::new(),
@@ -247,7 +300,11 @@ impl ConnectionManagerWorker {
     }
 
     fn connect_endpoint(&mut self, endpoint_index: usize, connection_index: Option<usize>) {
-        let Some((current_connection_index, endpoint)) = self.endpoints.get_mut(endpoint_index)
+        let Some(EndpointState {
+            connection_index: current_connection_index,
+            endpoint,
+            ..
+        }) = self.endpoints.get_mut(endpoint_index)
         else {
             // Unknown endpoint, this should never happen.
             error!(?endpoint_index, "Connection to unknown endpoint requested");
@@ -311,7 +368,7 @@ impl ConnectionManagerWorker {
     fn handle_worker(&mut self, tx: oneshot::Sender<Connection>) {
         if let Some(channel) = (self.available_connections > 0)
             .then_some(())
-            .and_then(|()| self.available_channels.pop_front())
+            .and_then(|()| self.take_channel())
         {
             self.provide_channel(channel, tx);
         } else {
@@ -319,10 +376,49 @@ impl ConnectionManagerWorker {
         }
     }
 
+    /// Removes the Channel to use for the next request from the available
+    /// channels according to the load balancing policy.  Channels of ejected
+    /// endpoints are only used if there is no other choice.
+    fn take_channel(&mut self) -> Option<EstablishedChannel> {
+        let now = Instant::now();
+        let endpoints = &self.endpoints;
+        let is_ejected = |channel: &EstablishedChannel| {
+            endpoints
+                .get(channel.identifier.endpoint_index)
+                .is_some_and(|endpoint| endpoint.is_ejected(now))
+        };
+        let has_healthy = self
+            .available_channels
+            .iter()
+            .any(|channel| !is_ejected(channel));
+        let mut candidates = self
+            .available_channels
+            .iter()
+            .enumerate()
+            .filter(|(_, channel)| !has_healthy || !is_ejected(channel));
+        let index = match self.load_balancing.policy {
+            // The available channels are a queue that channels are returned
+            // to the back of, so taking the first one cycles through them.
+            LoadBalancingPolicy::RoundRobin => candidates.next(),
+            // `min_by_key` returns the first minimum, which keeps the round
+            // robin order between equally loaded endpoints.
+            LoadBalancingPolicy::LeastLoaded => candidates.min_by_key(|(_, channel)| {
+                endpoints
+                    .get(channel.identifier.endpoint_index)
+                    .map_or(0, |endpoint| endpoint.in_flight)
+            }),
+        }
+        .map(|(index, _)| index)?;
+        self.available_channels.remove(index)
+    }
+
     fn provide_channel(&mut self, channel: EstablishedChannel, tx: oneshot::Sender<Connection>) {
         // We decrement here because we create Connection, this will signal when
         // it is Dropped and therefore increment this again.
         self.available_connections -= 1;
+        if let Some(endpoint) = self.endpoints.get_mut(channel.identifier.endpoint_index) {
+            endpoint.in_flight += 1;
+        }
         drop(tx.send(Connection {
             tx: self.connection_tx.clone(),
             pending_channel: Some(channel.channel.clone()),
@@ -335,7 +431,7 @@ impl ConnectionManagerWorker {
             && !self.waiting_connections.is_empty()
             && !self.available_channels.is_empty()
         {
-            if let Some(channel) = self.available_channels.pop_front() {
+            if let Some(channel) = self.take_channel() {
                 if let Some(tx) = self.waiting_connections.pop_front() {
                     self.provide_channel(channel, tx);
                 } else {
@@ -349,10 +445,13 @@ impl ConnectionManagerWorker {
     // This must never be made async otherwise the select may cancel it.
     fn handle_connection(&mut self, request: ConnectionRequest) {
         match request {
-            ConnectionRequest::Dropped(maybe_channel) => {
+            ConnectionRequest::Dropped((identifier, maybe_channel)) => {
                 if let Some(channel) = maybe_channel {
                     self.available_channels.push_back(channel);
                 }
+                if let Some(endpoint) = self.endpoints.get_mut(identifier.endpoint_index) {
+                    endpoint.in_flight = endpoint.in_flight.saturating_sub(1);
+                }
                 self.available_connections += 1;
                 self.maybe_available_connection();
             }
@@ -363,6 +462,7 @@ impl ConnectionManagerWorker {
             // Handle a transport error on a connection by making it unavailable
             // for use and establishing a new connection to the endpoint.
             ConnectionRequest::Error((identifier, was_pending)) => {
+                self.record_failure(identifier.endpoint_index);
                 let should_reconnect = if was_pending {
                     true
                 } else {
@@ -376,7 +476,37 @@ impl ConnectionManagerWorker {
                     self.connect_endpoint(identifier.endpoint_index, None);
                 }
             }
+            ConnectionRequest::Succeeded(identifier) => {
+                if let Some(endpoint) = self.endpoints.get_mut(identifier.endpoint_index) {
+                    endpoint.consecutive_failures = 0;
+                }
+            }
+        }
+    }
+
+    /// Counts a transport error against an endpoint and ejects it once it
+    /// reaches `LoadBalancing::max_endpoint_failures` consecutive errors.
+    fn record_failure(&mut self, endpoint_index: EndpointIndex) {
+        let max_endpoint_failures = self.load_balancing.max_endpoint_failures;
+        let ejection_duration = self.load_balancing.ejection_duration;
+        let Some(endpoint) = self.endpoints.get_mut(endpoint_index) else {
+            return;
+        };
+        endpoint.consecutive_failures += 1;
+        if max_endpoint_failures == 0 || endpoint.consecutive_failures < max_endpoint_failures {
+            return;
         }
+        let now = Instant::now();
+        if !endpoint.is_ejected(now) {
+            warn!(
+                endpoint = ?endpoint.endpoint.uri(),
+                failures = endpoint.consecutive_failures,
+                ?ejection_duration,
+                "Ejecting endpoint after consecutive failures"
+            );
+        }
+        endpoint.consecutive_failures = 0;
+        endpoint.ejected_until = Some(now + ejection_duration);
     }
 }
 
@@ -408,7 +538,10 @@ impl Drop for Connection {
                 channel,
                 identifier: self.channel.identifier,
             });
-        drop(self.tx.send(ConnectionRequest::Dropped(pending_channel)));
+        drop(self.tx.send(ConnectionRequest::Dropped((
+            self.channel.identifier,
+            pending_channel,
+        ))));
     }
 }
 
@@ -484,11 +617,13 @@ impl Future for ResponseFuture {
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let result = Pin::new(&mut self.inner).poll(cx);
-        if let Poll::Ready(Err(_)) = &result {
-            drop(
-                self.connection_tx
-                    .send(ConnectionRequest::Error((self.identifier, false))),
-            );
+        if let Poll::Ready(result) = &result {
+            let request = if result.is_ok() {
+                ConnectionRequest::Succeeded(self.identifier)
+            } else {
+                ConnectionRequest::Error((self.identifier, false))
+            };
+            drop(self.connection_tx.send(request));
         }
         result
     }
@@ -33,6 +33,12 @@ use crate::{fs, spawn_blocking};
 
 static DEFAULT_DIGEST_HASHER_FUNC: OnceLock<DigestHasherFunc> = OnceLock::new();
 
+/// Blake3 can only use its SIMD (multi-chunk) code paths when it is given
+/// several 1KiB chunks in a single update call. Streams often deliver much
+/// smaller pieces, so writes smaller than this are staged until enough data
+/// is available to hash in one go.
+const BLAKE3_STAGING_SIZE: usize = 16 * 1024;
+
 /// Utility function to make a context with a specific hasher function set.
 pub fn make_ctx_for_hash_func<H>(hasher: H) -> Result<Context, Error>
 where
@@ -165,6 +171,7 @@ impl From<&DigestHasherFunc> for DigestHasherImpl {
         Self {
             hashed_size: 0,
             hash_func_impl,
+            blake3_staging: Vec::new(),
         }
     }
 }
@@ -175,6 +182,7 @@ pub trait DigestHasher {
     fn update(&mut self, input: &[u8]);
 
     /// Finalize the hash function and collect the results into a digest.
+    /// The hasher is reset afterwards, so it may be reused for new data.
     fn finalize_digest(&mut self) -> DigestInfo;
 
     /// Specialized version of the hashing function that is optimized for
@@ -226,9 +234,24 @@ pub enum DigestHasherFuncImpl {
 pub struct DigestHasherImpl {
     hashed_size: u64,
     hash_func_impl: DigestHasherFuncImpl,
+    /// Small writes waiting to be fed into the blake3 hasher.
+    /// See `BLAKE3_STAGING_SIZE`.
+    blake3_staging: Vec<u8>,
 }
 
 impl DigestHasherImpl {
+    /// Feeds any staged data into the blake3 hasher.
+    #[inline]
+    fn flush_blake3_staging(&mut self) {
+        if self.blake3_staging.is_empty() {
+            return;
+        }
+        if let DigestHasherFuncImpl::Blake3(h) = &mut self.hash_func_impl {
+            h.update(&self.blake3_staging);
+            self.blake3_staging.clear();
+        }
+    }
+
     #[inline]
     async fn hash_file(
         &mut self,
@@ -249,18 +272,46 @@ impl DigestHasher for DigestHasherImpl {
         match &mut self.hash_func_impl {
             DigestHasherFuncImpl::Sha256(h) => sha2::digest::Update::update(h, input),
             DigestHasherFuncImpl::Blake3(h) => {
-                Blake3Hasher::update(h, input);
+                let mut input = input;
+                if !self.blake3_staging.is_empty() {
+                    // Only top the staged data up, so the rest of a large
+                    // write doesn't have to be copied.
+                    let top_up_len = input
+                        .len()
+                        .min(BLAKE3_STAGING_SIZE - self.blake3_staging.len());
+                    let (top_up, rest) = input.split_at(top_up_len);
+                    self.blake3_staging.extend_from_slice(top_up);
+                    if self.blake3_staging.len() < BLAKE3_STAGING_SIZE {
+                        return;
+                    }
+                    Blake3Hasher::update(h, &self.blake3_staging);
+                    self.blake3_staging.clear();
+                    input = rest;
+                }
+                // Large writes go straight to the hasher.
+                if input.len() >= BLAKE3_STAGING_SIZE {
+                    Blake3Hasher::update(h, input);
+                } else {
+                    self.blake3_staging.extend_from_slice(input);
+                }
             }
         }
     }
 
     #[inline]
     fn finalize_digest(&mut self) -> DigestInfo {
+        self.flush_blake3_staging();
         let hash = match &mut self.hash_func_impl {
             DigestHasherFuncImpl::Sha256(h) => h.finalize_reset().into(),
-            DigestHasherFuncImpl::Blake3(h) => h.finalize().into(),
+            DigestHasherFuncImpl::Blake3(h) => {
+                let hash = h.finalize().into();
+                h.reset();
+                hash
+            }
         };
-        DigestInfo::new(hash, self.hashed_size)
+        let digest = DigestInfo::new(hash, self.hashed_size);
+        self.hashed_size = 0;
+        digest
     }
 
     async fn digest_for_file(
@@ -284,6 +335,7 @@ impl DigestHasher for DigestHasherImpl {
             }
         }
         let file_path = file_path.as_ref().to_path_buf();
+        self.flush_blake3_staging();
         match self.hash_func_impl {
             DigestHasherFuncImpl::Sha256(_) => self.hash_file(file).await,
             DigestHasherFuncImpl::Blake3(mut hasher) => {
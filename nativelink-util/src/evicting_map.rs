@@ -22,13 +22,14 @@ use std::collections::BTreeSet;
 use std::sync::Arc;
 
 use lru::LruCache;
-use nativelink_config::stores::EvictionPolicy;
+use nativelink_config::stores::{EvictionAlgorithm, EvictionPolicy};
 use nativelink_metric::MetricsComponent;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use tonic::async_trait;
 use tracing::{debug, info};
 
+use crate::frequency_sketch::FrequencySketch;
 use crate::instant_wrapper::InstantWrapper;
 use crate::metrics_utils::{Counter, CounterWithTime};
 
@@ -51,6 +52,13 @@ pub trait LenEntry: 'static {
     /// Returns `true` if `self` has zero length.
     fn is_empty(&self) -> bool;
 
+    /// Number of bytes of memory held by the entry, used to enforce the
+    /// budget set with `EvictingMap::with_max_heap_bytes()`.
+    #[inline]
+    fn heap_size(&self) -> u64 {
+        self.len()
+    }
+
     /// This will be called when object is removed from map.
     /// Note: There may still be a reference to it held somewhere else, which
     /// is why it can't be mutable. This is a good place to mark the item
@@ -80,6 +88,11 @@ impl<T: LenEntry + Send + Sync> LenEntry for Arc<T> {
         T::is_empty(self.as_ref())
     }
 
+    #[inline]
+    fn heap_size(&self) -> u64 {
+        T::heap_size(self.as_ref())
+    }
+
     #[inline]
     async fn unref(&self) {
         self.as_ref().unref().await;
@@ -94,16 +107,324 @@ pub trait RemoveStateCallback<Q>: Debug + Send + Sync {
     async fn callback(&self, key: &Q);
 }
 
+/// Bytes of memory used by an entry in addition to the key, the eviction item
+/// and the data of the entry itself: the pointers linking the entry into the
+/// LRU order and the pointers of the hash table slot referencing it.
+const ENTRY_OVERHEAD: usize = 4 * size_of::<usize>();
+
+/// Percentage of all entries of a W-TinyLFU cache kept in its window.
+const WINDOW_PERCENT: usize = 1;
+
+/// Percentage of the main cache of a W-TinyLFU cache kept in its protected
+/// segment.
+const PROTECTED_PERCENT: usize = 80;
+
+/// Smallest number of counters per row of the frequency sketch of a
+/// W-TinyLFU cache. The sketch grows with the number of entries.
+const MIN_SKETCH_WIDTH: usize = 64;
+
+/// Number of accesses per counter of the frequency sketch after which its
+/// counts are halved.
+const SKETCH_ACCESSES_PER_COUNTER: usize = 10;
+
+/// LRU ordered segment of the entries of the map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Window,
+    Probation,
+    Protected,
+}
+
+/// Entries of a W-TinyLFU cache. New entries are added to a small window.
+/// Once pushed out of the window, an entry only enters the probation segment
+/// of the main cache if the frequency sketch estimates it was accessed more
+/// often than the entry the main cache would evict instead. Entries accessed
+/// while in probation are promoted to the protected segment.
+#[derive(Debug)]
+struct WTinyLfu<K: Hash + Eq, V> {
+    window: LruCache<K, V>,
+    probation: LruCache<K, V>,
+    protected: LruCache<K, V>,
+    sketch: FrequencySketch,
+}
+
+impl<K: Hash + Eq, V> WTinyLfu<K, V> {
+    fn new() -> Self {
+        Self {
+            window: LruCache::unbounded(),
+            probation: LruCache::unbounded(),
+            protected: LruCache::unbounded(),
+            sketch: FrequencySketch::new(
+                MIN_SKETCH_WIDTH,
+                MIN_SKETCH_WIDTH * SKETCH_ACCESSES_PER_COUNTER,
+            ),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.window.len() + self.probation.len() + self.protected.len()
+    }
+
+    const fn segment(&self, segment: Segment) -> &LruCache<K, V> {
+        match segment {
+            Segment::Window => &self.window,
+            Segment::Probation => &self.probation,
+            Segment::Protected => &self.protected,
+        }
+    }
+
+    const fn segment_mut(&mut self, segment: Segment) -> &mut LruCache<K, V> {
+        match segment {
+            Segment::Window => &mut self.window,
+            Segment::Probation => &mut self.probation,
+            Segment::Protected => &mut self.protected,
+        }
+    }
+
+    fn record_access<Q: Hash + ?Sized>(&mut self, key: &Q) {
+        let len = self.len();
+        if len > self.sketch.width() {
+            // Too few counters make unrelated keys share counts, so the
+            // sketch is rebuilt whenever the cache outgrows it.
+            self.sketch = FrequencySketch::new(len, len * SKETCH_ACCESSES_PER_COUNTER);
+        }
+        self.sketch.increment(key);
+    }
+
+    fn peek<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.window
+            .peek(key)
+            .or_else(|| self.probation.peek(key))
+            .or_else(|| self.protected.peek(key))
+    }
+
+    fn peek_mut<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+    {
+        if let Some(value) = self.window.peek_mut(key) {
+            return Some(value);
+        }
+        if let Some(value) = self.probation.peek_mut(key) {
+            return Some(value);
+        }
+        self.protected.peek_mut(key)
+    }
+
+    fn get_mut<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+    {
+        self.record_access(key);
+        if let Some(value) = self.window.get_mut(key) {
+            return Some(value);
+        }
+        if let Some((probation_key, value)) = self.probation.pop_entry(key) {
+            self.protected.put(probation_key, value);
+            let max_protected =
+                ((self.probation.len() + self.protected.len()) * PROTECTED_PERCENT / 100).max(1);
+            while self.protected.len() > max_protected {
+                let Some((protected_key, value)) = self.protected.pop_lru() else {
+                    break;
+                };
+                self.probation.put(protected_key, value);
+            }
+        }
+        self.protected.get_mut(key)
+    }
+
+    fn put(&mut self, key: K, value: V) -> Option<V> {
+        self.record_access(&key);
+        for segment in [&mut self.window, &mut self.probation, &mut self.protected] {
+            if let Some(existing) = segment.get_mut(&key) {
+                return Some(core::mem::replace(existing, value));
+            }
+        }
+        self.window.put(key, value);
+        None
+    }
+
+    fn pop_entry<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+    {
+        self.window
+            .pop_entry(key)
+            .or_else(|| self.probation.pop_entry(key))
+            .or_else(|| self.protected.pop_entry(key))
+    }
+
+    fn victim(&mut self, is_expired: impl Fn(&V) -> bool) -> Option<Segment> {
+        // Expired entries are evicted first, whichever segment they are in.
+        for segment in [Segment::Window, Segment::Probation, Segment::Protected] {
+            if self
+                .segment(segment)
+                .peek_lru()
+                .is_some_and(|(_, value)| is_expired(value))
+            {
+                return Some(segment);
+            }
+        }
+        let max_window = (self.len() * WINDOW_PERCENT / 100).max(1);
+        loop {
+            let main = if !self.probation.is_empty() {
+                Some(Segment::Probation)
+            } else if !self.protected.is_empty() {
+                Some(Segment::Protected)
+            } else {
+                None
+            };
+            if self.window.len() <= max_window {
+                return main.or_else(|| (!self.window.is_empty()).then_some(Segment::Window));
+            }
+            // The window is over its size, so its least recently used entry
+            // competes with the entry the main cache would evict.
+            let Some(main) = main else {
+                let (key, value) = self.window.pop_lru()?;
+                self.probation.put(key, value);
+                continue;
+            };
+            let (candidate, _) = self.window.peek_lru()?;
+            let (victim, _) = self.segment(main).peek_lru()?;
+            if self.sketch.estimate(candidate) <= self.sketch.estimate(victim) {
+                return Some(Segment::Window);
+            }
+            let (key, value) = self.window.pop_lru()?;
+            self.probation.put(key, value);
+            return Some(main);
+        }
+    }
+}
+
+/// Entries of the map, ordered by the configured `EvictionAlgorithm`.
+#[derive(Debug)]
+enum Entries<K: Hash + Eq, V> {
+    Lru(LruCache<K, V>),
+    WTinyLfu(Box<WTinyLfu<K, V>>),
+}
+
+impl<K: Hash + Eq, V> Entries<K, V> {
+    fn new(algorithm: EvictionAlgorithm) -> Self {
+        // We use unbounded because if we use the bounded version we can't call the delete
+        // function on the LenEntry properly.
+        match algorithm {
+            EvictionAlgorithm::Lru => Self::Lru(LruCache::unbounded()),
+            EvictionAlgorithm::WTinyLfu => Self::WTinyLfu(Box::new(WTinyLfu::new())),
+        }
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_> {
+        match self {
+            Self::Lru(lru) => Box::new(lru.iter().map(|(key, _)| key)),
+            Self::WTinyLfu(cache) => Box::new(
+                cache
+                    .window
+                    .iter()
+                    .chain(cache.probation.iter())
+                    .chain(cache.protected.iter())
+                    .map(|(key, _)| key),
+            ),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::Lru(lru) => lru.len(),
+            Self::WTinyLfu(cache) => cache.len(),
+        }
+    }
+
+    /// Returns the entry of `key` without counting it as an access.
+    fn peek<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        match self {
+            Self::Lru(lru) => lru.peek(key),
+            Self::WTinyLfu(cache) => cache.peek(key),
+        }
+    }
+
+    /// Returns the entry of `key` without counting it as an access.
+    fn peek_mut<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+    {
+        match self {
+            Self::Lru(lru) => lru.peek_mut(key),
+            Self::WTinyLfu(cache) => cache.peek_mut(key),
+        }
+    }
+
+    /// Returns the entry of `key` and counts it as an access.
+    fn get_mut<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+    {
+        match self {
+            Self::Lru(lru) => lru.get_mut(key),
+            Self::WTinyLfu(cache) => cache.get_mut(key),
+        }
+    }
+
+    /// Inserts or replaces the entry of `key`, returning the replaced entry.
+    fn put(&mut self, key: K, value: V) -> Option<V> {
+        match self {
+            Self::Lru(lru) => lru.put(key, value),
+            Self::WTinyLfu(cache) => cache.put(key, value),
+        }
+    }
+
+    fn pop_entry<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+    {
+        match self {
+            Self::Lru(lru) => lru.pop_entry(key),
+            Self::WTinyLfu(cache) => cache.pop_entry(key),
+        }
+    }
+
+    /// Chooses the segment whose least recently used entry is evicted next.
+    /// An LRU cache only has a window spanning all entries.
+    fn victim(&mut self, is_expired: impl Fn(&V) -> bool) -> Option<Segment> {
+        match self {
+            Self::Lru(lru) => (!lru.is_empty()).then_some(Segment::Window),
+            Self::WTinyLfu(cache) => cache.victim(is_expired),
+        }
+    }
+
+    fn peek_victim(&self, segment: Segment) -> Option<&V> {
+        let (_, value) = match self {
+            Self::Lru(lru) => lru.peek_lru(),
+            Self::WTinyLfu(cache) => cache.segment(segment).peek_lru(),
+        }?;
+        Some(value)
+    }
+
+    fn pop_victim(&mut self, segment: Segment) -> Option<(K, V)> {
+        match self {
+            Self::Lru(lru) => lru.pop_lru(),
+            Self::WTinyLfu(cache) => cache.segment_mut(segment).pop_lru(),
+        }
+    }
+}
+
 #[derive(Debug, MetricsComponent)]
 struct State<
     K: Ord + Hash + Eq + Clone + Debug + Send + Borrow<Q>,
     Q: Ord + Hash + Eq + Debug,
     T: LenEntry + Debug + Send,
 > {
-    lru: LruCache<K, EvictionItem<T>>,
+    entries: Entries<K, EvictionItem<T>>,
     btree: Option<BTreeSet<K>>,
     #[metric(help = "Total size of all items in the store")]
     sum_store_size: u64,
+    #[metric(help = "Total bytes of memory held by all items in the store")]
+    sum_heap_size: u64,
 
     #[metric(help = "Number of bytes evicted from the store")]
     evicted_bytes: Counter,
@@ -136,6 +457,7 @@ impl<
             btree.remove(key.borrow());
         }
         self.sum_store_size -= eviction_item.data.len();
+        self.sum_heap_size -= entry_heap_size::<K, T>(&eviction_item.data);
         if replaced {
             self.replaced_items.inc();
             self.replaced_bytes.add(eviction_item.data.len());
@@ -165,7 +487,7 @@ impl<
         if let Some(btree) = &mut self.btree {
             btree.insert(key.clone());
         }
-        if let Some(old_item) = self.lru.put(key.clone(), eviction_item) {
+        if let Some(old_item) = self.entries.put(key.clone(), eviction_item) {
             let old_data = self.remove(key.borrow(), &old_item, true).await;
             return Some(old_data);
         }
@@ -177,6 +499,12 @@ impl<
     }
 }
 
+/// Bytes of memory used by an entry holding `data`. Heap allocations owned by
+/// the key are not counted.
+fn entry_heap_size<K, T: LenEntry + Debug>(data: &T) -> u64 {
+    (size_of::<K>() + size_of::<EvictionItem<T>>() + ENTRY_OVERHEAD) as u64 + data.heap_size()
+}
+
 #[derive(Debug, MetricsComponent)]
 pub struct EvictingMap<
     K: Ord + Hash + Eq + Clone + Debug + Send + Borrow<Q>,
@@ -195,6 +523,8 @@ pub struct EvictingMap<
     max_seconds: i32,
     #[metric(help = "Maximum number of items to keep in the store")]
     max_count: u64,
+    #[metric(help = "Maximum bytes of memory held by the items in the store")]
+    max_heap_bytes: u64,
 }
 
 impl<K, Q, T, I> EvictingMap<K, Q, T, I>
@@ -206,12 +536,11 @@ where
 {
     pub fn new(config: &EvictionPolicy, anchor_time: I) -> Self {
         Self {
-            // We use unbounded because if we use the bounded version we can't call the delete
-            // function on the LenEntry properly.
             state: Arc::new(Mutex::new(State {
-                lru: LruCache::unbounded(),
+                entries: Entries::new(config.algorithm),
                 btree: None,
                 sum_store_size: 0,
+                sum_heap_size: 0,
                 evicted_bytes: Counter::default(),
                 evicted_items: CounterWithTime::default(),
                 replaced_bytes: Counter::default(),
@@ -224,9 +553,19 @@ where
             evict_bytes: config.evict_bytes as u64,
             max_seconds: config.max_seconds as i32,
             max_count: config.max_count,
+            max_heap_bytes: 0,
         }
     }
 
+    /// Evicts items whenever they hold more than `max_heap_bytes` bytes of
+    /// memory, as reported by `LenEntry::heap_size()` plus the memory used
+    /// by the map for each item. Zero means no limit.
+    #[must_use]
+    pub const fn with_max_heap_bytes(mut self, max_heap_bytes: u64) -> Self {
+        self.max_heap_bytes = max_heap_bytes;
+        self
+    }
+
     pub async fn enable_filtering(&self) {
         let mut state = self.state.lock_arc();
         if state.btree.is_none() {
@@ -235,7 +574,7 @@ where
     }
 
     fn rebuild_btree_index(state: &mut State<K, Q, T>) {
-        state.btree = Some(state.lru.iter().map(|(k, _)| k).cloned().collect());
+        state.btree = Some(state.entries.keys().cloned().collect());
     }
 
     /// Run the `handler` function on each key-value pair that matches the `prefix_range`
@@ -256,7 +595,7 @@ where
         };
         let mut continue_count = 0;
         for key in btree.range(prefix_range) {
-            let value = &state.lru.peek(key.borrow()).unwrap().data;
+            let value = &state.entries.peek(key.borrow()).unwrap().data;
             let should_continue = handler(key, value);
             if !should_continue {
                 break;
@@ -266,10 +605,41 @@ where
         continue_count
     }
 
+    /// Returns a copy of every item along with the number of seconds since the
+    /// anchor time it was last accessed, without promoting any of them.
+    pub async fn items_with_time(&self) -> Vec<(K, T, i32)> {
+        let state = self.state.lock_arc();
+        state
+            .entries
+            .keys()
+            .filter_map(|key| {
+                let entry = state.entries.peek(key.borrow())?;
+                Some((key.clone(), entry.data.clone(), entry.seconds_since_anchor))
+            })
+            .collect()
+    }
+
     /// Returns the number of key-value pairs that are currently in the the cache.
     /// Function is not for production code paths.
     pub async fn len_for_test(&self) -> usize {
-        self.state.lock_arc().lru.len()
+        self.state.lock_arc().entries.len()
+    }
+
+    /// Returns the total size and the number of items in the map.
+    pub async fn size_and_count(&self) -> (u64, u64) {
+        let state = self.state.lock_arc();
+        (state.sum_store_size, state.entries.len() as u64)
+    }
+
+    /// Returns the bytes of memory held by all items in the map.
+    pub async fn heap_size(&self) -> u64 {
+        self.state.lock_arc().sum_heap_size
+    }
+
+    fn is_expired(&self, entry: &EvictionItem<T>) -> bool {
+        let evict_older_than_seconds =
+            (self.anchor_time.elapsed().as_secs() as i32) - self.max_seconds;
+        self.max_seconds != 0 && entry.seconds_since_anchor < evict_older_than_seconds
     }
 
     fn should_evict(
@@ -277,32 +647,37 @@ where
         lru_len: usize,
         peek_entry: &EvictionItem<T>,
         sum_store_size: u64,
+        sum_heap_size: u64,
         max_bytes: u64,
     ) -> bool {
         let is_over_size = max_bytes != 0 && sum_store_size >= max_bytes;
 
-        let evict_older_than_seconds =
-            (self.anchor_time.elapsed().as_secs() as i32) - self.max_seconds;
-        let old_item_exists =
-            self.max_seconds != 0 && peek_entry.seconds_since_anchor < evict_older_than_seconds;
+        let is_over_heap_size = self.max_heap_bytes != 0 && sum_heap_size > self.max_heap_bytes;
+
+        let old_item_exists = self.is_expired(peek_entry);
 
         let is_over_count = self.max_count != 0 && (lru_len as u64) > self.max_count;
 
-        is_over_size || old_item_exists || is_over_count
+        is_over_size || is_over_heap_size || old_item_exists || is_over_count
     }
 
     #[must_use]
     async fn evict_items(&self, state: &mut State<K, Q, T>) -> Vec<T> {
-        let Some((_, mut peek_entry)) = state.lru.peek_lru() else {
+        let Some(mut victim) = state.entries.victim(|entry| self.is_expired(entry)) else {
             return Vec::new();
         };
+        let mut peek_entry = state
+            .entries
+            .peek_victim(victim)
+            .expect("Victim segment must not be empty");
 
         let max_bytes = if self.max_bytes != 0
             && self.evict_bytes != 0
             && self.should_evict(
-                state.lru.len(),
+                state.entries.len(),
                 peek_entry,
                 state.sum_store_size,
+                state.sum_heap_size,
                 self.max_bytes,
             ) {
             self.max_bytes.saturating_sub(self.evict_bytes)
@@ -312,20 +687,29 @@ where
 
         let mut items_to_unref = Vec::new();
 
-        while self.should_evict(state.lru.len(), peek_entry, state.sum_store_size, max_bytes) {
+        while self.should_evict(
+            state.entries.len(),
+            peek_entry,
+            state.sum_store_size,
+            state.sum_heap_size,
+            max_bytes,
+        ) {
             let (key, eviction_item) = state
-                .lru
-                .pop_lru()
+                .entries
+                .pop_victim(victim)
                 .expect("Tried to peek() then pop() but failed");
             debug!(?key, "Evicting",);
             let data = state.remove(key.borrow(), &eviction_item, false).await;
             items_to_unref.push(data);
 
-            peek_entry = if let Some((_, entry)) = state.lru.peek_lru() {
-                entry
-            } else {
+            let Some(next_victim) = state.entries.victim(|entry| self.is_expired(entry)) else {
                 break;
             };
+            victim = next_victim;
+            peek_entry = state
+                .entries
+                .peek_victim(victim)
+                .expect("Victim segment must not be empty");
         }
 
         items_to_unref
@@ -362,21 +746,21 @@ where
     {
         let mut state = self.state.lock_arc();
 
-        let lru_len = state.lru.len();
+        let lru_len = state.entries.len();
         for (key, result) in keys.into_iter().zip(results.iter_mut()) {
             let maybe_entry = if peek {
-                state.lru.peek_mut(key.borrow())
+                state.entries.peek_mut(key.borrow())
             } else {
-                state.lru.get_mut(key.borrow())
+                state.entries.get_mut(key.borrow())
             };
             match maybe_entry {
                 Some(entry) => {
                     // Note: We need to check eviction because the item might be expired
                     // based on the current time. In such case, we remove the item while
                     // we are here.
-                    if self.should_evict(lru_len, entry, 0, u64::MAX) {
+                    if self.should_evict(lru_len, entry, 0, 0, u64::MAX) {
                         *result = None;
-                        if let Some((key, eviction_item)) = state.lru.pop_entry(key.borrow()) {
+                        if let Some((key, eviction_item)) = state.entries.pop_entry(key.borrow()) {
                             info!(?key, "Item expired, evicting");
                             let data = state.remove(key.borrow(), &eviction_item, false).await;
                             // Store data for later unref - we can't drop state here as we're still iterating
@@ -400,12 +784,17 @@ where
     pub async fn get(&self, key: &Q) -> Option<T> {
         // Fast path: Check if we need eviction before acquiring lock for eviction
         let needs_eviction = {
-            let state = self.state.lock_arc();
-            if let Some((_, peek_entry)) = state.lru.peek_lru() {
+            let mut state = self.state.lock_arc();
+            if let Some(victim) = state.entries.victim(|entry| self.is_expired(entry)) {
+                let peek_entry = state
+                    .entries
+                    .peek_victim(victim)
+                    .expect("Victim segment must not be empty");
                 self.should_evict(
-                    state.lru.len(),
+                    state.entries.len(),
                     peek_entry,
                     state.sum_store_size,
+                    state.sum_heap_size,
                     self.max_bytes,
                 )
             } else {
@@ -427,7 +816,7 @@ where
 
         // Now get the item
         let mut state = self.state.lock_arc();
-        let entry = state.lru.get_mut(key.borrow())?;
+        let entry = state.entries.get_mut(key.borrow())?;
         entry.seconds_since_anchor = self.anchor_time.elapsed().as_secs() as i32;
         Some(entry.data.clone())
     }
@@ -459,6 +848,30 @@ where
         results.into_iter().next()
     }
 
+    /// Same as `insert_with_time()`, but leaves the map untouched if `key` is
+    /// already present. Returns `true` if the item was inserted.
+    pub async fn insert_with_time_if_absent(
+        &self,
+        key: K,
+        data: T,
+        seconds_since_anchor: i32,
+    ) -> bool {
+        let items_to_unref = {
+            let mut state = self.state.lock_arc();
+            if state.entries.peek(key.borrow()).is_some() {
+                return false;
+            }
+            self.inner_insert_many(&mut state, [(key, data)], seconds_since_anchor)
+                .await
+        };
+
+        // Unref items outside of lock
+        for item in items_to_unref {
+            item.unref().await;
+        }
+        true
+    }
+
     /// Same as `insert()`, but optimized for multiple inserts.
     /// Returns the replaced items if any.
     pub async fn insert_many<It>(&self, inserts: It) -> Vec<T>
@@ -506,6 +919,7 @@ where
         let mut replaced_items = Vec::new();
         for (key, data) in inserts {
             let new_item_size = data.len();
+            let new_item_heap_size = entry_heap_size::<K, T>(&data);
             let eviction_item = EvictionItem {
                 seconds_since_anchor,
                 data,
@@ -515,6 +929,7 @@ where
                 replaced_items.push(old_item);
             }
             state.sum_store_size += new_item_size;
+            state.sum_heap_size += new_item_heap_size;
             state.lifetime_inserted_bytes.add(new_item_size);
         }
 
@@ -538,7 +953,7 @@ where
             let evicted_items = self.evict_items(&mut *state).await;
 
             // Then try to remove the requested item
-            let removed = if let Some(entry) = state.lru.pop(key.borrow()) {
+            let removed = if let Some((_, entry)) = state.entries.pop_entry(key.borrow()) {
                 Some(state.remove(key, &entry, false).await)
             } else {
                 None
@@ -568,7 +983,7 @@ where
         F: FnOnce(&T) -> bool + Send,
     {
         let mut state = self.state.lock_arc();
-        if let Some(entry) = state.lru.get(key.borrow()) {
+        if let Some(entry) = state.entries.get_mut(key.borrow()) {
             if !cond(&entry.data) {
                 return false;
             }
@@ -576,7 +991,7 @@ where
             let evicted_items = self.evict_items(&mut state).await;
 
             // Then try to remove the requested item
-            let removed_item = if let Some(entry) = state.lru.pop(key.borrow()) {
+            let removed_item = if let Some((_, entry)) = state.entries.pop_entry(key.borrow()) {
                 Some(state.remove(key, &entry, false).await)
             } else {
                 None
@@ -7,6 +7,12 @@
 use bytes::{Bytes, BytesMut};
 use tokio_util::codec::Decoder;
 
+/// Normalization level used by `FastCDC::new`.
+pub const DEFAULT_NORMALIZATION_LEVEL: u32 = 1;
+
+/// Highest normalization level `FastCDC::with_normalization` accepts.
+pub const MAX_NORMALIZATION_LEVEL: u32 = 3;
+
 #[derive(Debug)]
 struct State {
     hash: u32,
@@ -54,8 +60,25 @@ pub struct FastCDC {
 
 impl FastCDC {
     pub fn new(min_size: usize, avg_size: usize, max_size: usize) -> Self {
+        Self::with_normalization(min_size, avg_size, max_size, DEFAULT_NORMALIZATION_LEVEL)
+    }
+
+    /// Same as `new`, but with a custom normalization level. Higher levels
+    /// make it harder to cut a chunk before `avg_size` and easier after it,
+    /// so chunk sizes are spread less around the average. Level 0 disables
+    /// normalization.
+    pub fn with_normalization(
+        min_size: usize,
+        avg_size: usize,
+        max_size: usize,
+        normalization_level: u32,
+    ) -> Self {
         assert!(min_size < avg_size, "Expected {min_size} < {avg_size}");
         assert!(avg_size < max_size, "Expected {avg_size} < {max_size}");
+        assert!(
+            normalization_level <= MAX_NORMALIZATION_LEVEL,
+            "Expected {normalization_level} <= {MAX_NORMALIZATION_LEVEL}"
+        );
         let norm_size = {
             let mut offset = min_size + min_size.div_ceil(2);
             if offset > avg_size {
@@ -65,6 +88,10 @@ impl FastCDC {
         };
         // Calculate the number of bits closest approximating our average.
         let bits = (avg_size as f64).log2().round() as u32;
+        assert!(
+            bits + normalization_level < 32 && bits >= normalization_level,
+            "Expected average size {avg_size} to fit normalization level {normalization_level}"
+        );
         Self {
             min_size,
             avg_size,
@@ -73,8 +100,8 @@ impl FastCDC {
             norm_size,
             // Turn our bits into a bitmask we can use later on for more
             // efficient bitwise operations.
-            mask_hard: 2u32.pow(bits + 1) - 1,
-            mask_easy: 2u32.pow(bits - 1) - 1,
+            mask_hard: 2u32.pow(bits + normalization_level) - 1,
+            mask_easy: 2u32.pow(bits - normalization_level) - 1,
 
             state: State {
                 hash: 0,
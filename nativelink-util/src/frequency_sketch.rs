@@ -0,0 +1,90 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::hash::{BuildHasher, Hash};
+use std::hash::RandomState;
+
+/// Number of counters each key is counted in.
+const SKETCH_DEPTH: usize = 4;
+
+/// Count-min sketch estimating how often keys were accessed recently,
+/// like the frequency filter of `TinyLFU`.
+#[derive(Debug)]
+pub struct FrequencySketch {
+    hasher: RandomState,
+    counters: Vec<[u8; SKETCH_DEPTH]>,
+    accesses: usize,
+    access_window: usize,
+}
+
+impl FrequencySketch {
+    /// Creates a sketch with at least `width` counters per row. All counts
+    /// are halved every `access_window` accesses, so old accesses age out.
+    pub fn new(width: usize, access_window: usize) -> Self {
+        Self {
+            hasher: RandomState::new(),
+            counters: vec![[0; SKETCH_DEPTH]; width.max(1).next_power_of_two()],
+            accesses: 0,
+            access_window,
+        }
+    }
+
+    /// Number of counters per row.
+    pub const fn width(&self) -> usize {
+        self.counters.len()
+    }
+
+    fn indexes<Q: Hash + ?Sized>(&self, key: &Q) -> [usize; SKETCH_DEPTH] {
+        let hash = self.hasher.hash_one(key);
+        let (hash1, hash2) = (hash as usize, (hash >> 32) as usize);
+        let mask = self.counters.len() - 1;
+        core::array::from_fn(|row| hash1.wrapping_add(row.wrapping_mul(hash2)) & mask)
+    }
+
+    fn count(&self, indexes: &[usize; SKETCH_DEPTH]) -> u8 {
+        indexes
+            .iter()
+            .enumerate()
+            .map(|(row, &index)| self.counters[index][row])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Returns the estimated number of recent accesses to `key`.
+    pub fn estimate<Q: Hash + ?Sized>(&self, key: &Q) -> u8 {
+        self.count(&self.indexes(key))
+    }
+
+    /// Records an access to `key` and returns the estimated number of
+    /// recent accesses, including this one.
+    pub fn increment<Q: Hash + ?Sized>(&mut self, key: &Q) -> u8 {
+        let indexes = self.indexes(key);
+        let count = self.count(&indexes).saturating_add(1);
+        // Only the counters at the minimum are raised (conservative update),
+        // which keeps collisions from inflating counts.
+        for (row, &index) in indexes.iter().enumerate() {
+            let counter = &mut self.counters[index][row];
+            *counter = (*counter).max(count);
+        }
+
+        self.accesses += 1;
+        if self.accesses >= self.access_window {
+            self.accesses = 0;
+            for counter in self.counters.iter_mut().flatten() {
+                *counter /= 2;
+            }
+        }
+        count
+    }
+}
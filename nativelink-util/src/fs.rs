@@ -41,6 +41,14 @@ pub struct FileSlot {
     inner: tokio::fs::File,
 }
 
+impl FileSlot {
+    /// Converts the slot into a `std::fs::File`. The returned permit must be
+    /// held as long as the file is open.
+    pub async fn into_std(self) -> (SemaphorePermit<'static>, std::fs::File) {
+        (self._permit, self.inner.into_std().await)
+    }
+}
+
 impl AsRef<tokio::fs::File> for FileSlot {
     fn as_ref(&self) -> &tokio::fs::File {
         &self.inner
@@ -0,0 +1,348 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reads and writes of files through `io_uring`.
+//!
+//! A dedicated thread owns the ring and a set of buffers registered with the
+//! kernel. Requests queued while the thread waits for completions are
+//! submitted together with a single syscall.
+
+use std::fs::File;
+use std::io;
+use std::sync::{Arc, mpsc};
+
+use bytes::{Buf, Bytes};
+use nativelink_error::{Code, Error, ResultExt, make_err};
+use tokio::sync::{SemaphorePermit, oneshot};
+
+use crate::fs::FileSlot;
+use crate::spawn_blocking;
+
+/// Default maximum number of operations in flight.
+/// Note: If this changes, remember to change the documentation in the config.
+pub const DEFAULT_QUEUE_DEPTH: u32 = 128;
+
+#[derive(Debug)]
+enum Op {
+    Read {
+        len: usize,
+        responder: oneshot::Sender<io::Result<Bytes>>,
+    },
+    Write {
+        data: Bytes,
+        responder: oneshot::Sender<io::Result<usize>>,
+    },
+}
+
+#[derive(Debug)]
+struct Request {
+    // Keeps the file descriptor open until the operation completed, even if
+    // the caller gave up waiting for it.
+    file: Arc<File>,
+    offset: u64,
+    op: Op,
+}
+
+/// An open file usable with `UringFs`.
+#[derive(Debug)]
+pub struct UringFile {
+    // We hold the permit because once it is dropped it goes back into the queue.
+    _permit: SemaphorePermit<'static>,
+    file: Arc<File>,
+}
+
+impl UringFile {
+    pub async fn from_slot(slot: FileSlot) -> Self {
+        let (permit, file) = slot.into_std().await;
+        Self {
+            _permit: permit,
+            file: Arc::new(file),
+        }
+    }
+
+    pub async fn sync_all(&self) -> Result<(), Error> {
+        let file = self.file.clone();
+        spawn_blocking!("fs_uring_sync_all", move || file
+            .sync_all()
+            .map_err(Into::<Error>::into))
+        .await
+        .unwrap_or_else(|e| Err(make_err!(Code::Internal, "background task failed: {e:?}")))
+    }
+}
+
+/// Handle to the `io_uring` thread. The thread exits once the handle is dropped
+/// and all operations in flight completed.
+#[derive(Debug)]
+pub struct UringFs {
+    requests: mpsc::Sender<Request>,
+    buffer_size: usize,
+}
+
+impl UringFs {
+    /// Sets up a ring allowing `queue_depth` operations in flight, each
+    /// transferring at most `buffer_size` bytes.
+    #[cfg_attr(
+        not(target_os = "linux"),
+        expect(unused_variables, reason = "io_uring is only available on Linux")
+    )]
+    pub fn new(queue_depth: u32, buffer_size: usize) -> Result<Self, Error> {
+        #[cfg(target_os = "linux")]
+        {
+            let requests = ring::spawn(queue_depth, buffer_size)?;
+            Ok(Self {
+                requests,
+                buffer_size,
+            })
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Err(make_err!(
+                Code::Unimplemented,
+                "io_uring is only available on Linux"
+            ))
+        }
+    }
+
+    /// Maximum number of bytes a single read returns.
+    pub const fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Reads up to `len` bytes, but at most `buffer_size()`, at `offset`.
+    /// An empty result means the end of the file was reached.
+    pub async fn read_at(&self, file: &UringFile, offset: u64, len: usize) -> Result<Bytes, Error> {
+        let (responder, response) = oneshot::channel();
+        self.submit(file, offset, Op::Read { len, responder })?;
+        Self::wait(response).await
+    }
+
+    /// Writes all of `data` at `offset`.
+    pub async fn write_all_at(
+        &self,
+        file: &UringFile,
+        mut offset: u64,
+        mut data: Bytes,
+    ) -> Result<(), Error> {
+        while !data.is_empty() {
+            let (responder, response) = oneshot::channel();
+            self.submit(
+                file,
+                offset,
+                Op::Write {
+                    data: data.clone(),
+                    responder,
+                },
+            )?;
+            let written = Self::wait(response).await?;
+            if written == 0 {
+                return Err(make_err!(
+                    Code::Internal,
+                    "io_uring write made no progress with {} bytes left",
+                    data.len()
+                ));
+            }
+            data.advance(written);
+            offset += written as u64;
+        }
+        Ok(())
+    }
+
+    fn submit(&self, file: &UringFile, offset: u64, op: Op) -> Result<(), Error> {
+        self.requests
+            .send(Request {
+                file: file.file.clone(),
+                offset,
+                op,
+            })
+            .map_err(|_| make_err!(Code::Internal, "io_uring thread has exited"))
+    }
+
+    async fn wait<T>(response: oneshot::Receiver<io::Result<T>>) -> Result<T, Error> {
+        response
+            .await
+            .map_err(|_| make_err!(Code::Internal, "io_uring thread dropped the request"))?
+            .map_err(Into::<Error>::into)
+            .err_tip(|| "In UringFs::wait")
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod ring {
+    use std::collections::VecDeque;
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::sync::mpsc;
+
+    use bytes::Bytes;
+    use io_uring::{IoUring, opcode, types};
+    use nativelink_error::{Code, Error, make_err};
+    use tracing::error;
+
+    use super::{Op, Request};
+
+    pub(super) fn spawn(
+        queue_depth: u32,
+        buffer_size: usize,
+    ) -> Result<mpsc::Sender<Request>, Error> {
+        let ring = IoUring::new(queue_depth)
+            .map_err(|e| make_err!(Code::Unavailable, "Could not set up io_uring: {e}"))?;
+        let mut buffers = vec![0; queue_depth as usize * buffer_size];
+        let iovecs: Vec<libc::iovec> = buffers
+            .chunks_exact_mut(buffer_size)
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.as_mut_ptr().cast(),
+                iov_len: buffer.len(),
+            })
+            .collect();
+        // SAFETY: The buffers are moved into the worker together with the
+        // ring. Moving a `Vec` does not move its allocation and the ring is
+        // dropped before the buffers.
+        unsafe { ring.submitter().register_buffers(&iovecs) }.map_err(|e| {
+            make_err!(
+                Code::Unavailable,
+                "Could not register io_uring buffers: {e}"
+            )
+        })?;
+
+        let (tx, rx) = mpsc::channel();
+        let worker = RingWorker {
+            ring,
+            buffers,
+            buffer_size,
+            free_buffers: (0..queue_depth as u16).collect(),
+            in_flight: (0..queue_depth).map(|_| None).collect(),
+            pending: VecDeque::new(),
+        };
+        std::thread::Builder::new()
+            .name("fs_uring".to_string())
+            .spawn(move || worker.run(&rx))
+            .map_err(|e| make_err!(Code::Internal, "Could not spawn io_uring thread: {e}"))?;
+        Ok(tx)
+    }
+
+    struct RingWorker {
+        // Must be dropped before `buffers`.
+        ring: IoUring,
+        buffers: Vec<u8>,
+        buffer_size: usize,
+        free_buffers: Vec<u16>,
+        // Indexed by the registered buffer the request uses.
+        in_flight: Vec<Option<Request>>,
+        pending: VecDeque<Request>,
+    }
+
+    impl RingWorker {
+        fn run(mut self, requests: &mpsc::Receiver<Request>) {
+            let mut disconnected = false;
+            loop {
+                if self.free_buffers.len() == self.in_flight.len() && self.pending.is_empty() {
+                    if disconnected {
+                        return;
+                    }
+                    // Nothing to do, so block until the next request.
+                    match requests.recv() {
+                        Ok(request) => self.pending.push_back(request),
+                        Err(mpsc::RecvError) => return,
+                    }
+                }
+                // Take everything queued meanwhile, so it is submitted together.
+                while !disconnected {
+                    match requests.try_recv() {
+                        Ok(request) => self.pending.push_back(request),
+                        Err(mpsc::TryRecvError::Empty) => break,
+                        Err(mpsc::TryRecvError::Disconnected) => disconnected = true,
+                    }
+                }
+                self.push_pending();
+                match self.ring.submit_and_wait(1) {
+                    Ok(_) => {}
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(err) => {
+                        // Dropping the requests fails all callers waiting for them.
+                        error!(?err, "io_uring submission failed, stopping io_uring thread");
+                        return;
+                    }
+                }
+                self.reap_completions();
+            }
+        }
+
+        fn push_pending(&mut self) {
+            while let Some(&buf_index) = self.free_buffers.last() {
+                let Some(request) = self.pending.pop_front() else {
+                    break;
+                };
+                self.free_buffers.pop();
+                let start = usize::from(buf_index) * self.buffer_size;
+                let fd = types::Fd(request.file.as_raw_fd());
+                let entry = match &request.op {
+                    Op::Read { len, .. } => opcode::ReadFixed::new(
+                        fd,
+                        self.buffers[start..].as_mut_ptr(),
+                        (*len).min(self.buffer_size) as u32,
+                        buf_index,
+                    )
+                    .offset(request.offset)
+                    .build(),
+                    Op::Write { data, .. } => {
+                        let len = data.len().min(self.buffer_size);
+                        self.buffers[start..start + len].copy_from_slice(&data[..len]);
+                        opcode::WriteFixed::new(
+                            fd,
+                            self.buffers[start..].as_mut_ptr(),
+                            len as u32,
+                            buf_index,
+                        )
+                        .offset(request.offset)
+                        .build()
+                    }
+                }
+                .user_data(u64::from(buf_index));
+                // SAFETY: The buffer and the file stay alive until the
+                // completion is reaped, because the request is kept in
+                // `in_flight` and the buffer is not handed out again.
+                unsafe { self.ring.submission().push(&entry) }
+                    .expect("Submission queue holds an entry per registered buffer");
+                self.in_flight[usize::from(buf_index)] = Some(request);
+            }
+        }
+
+        fn reap_completions(&mut self) {
+            for cqe in self.ring.completion() {
+                let buf_index = cqe.user_data() as usize;
+                let Some(request) = self.in_flight[buf_index].take() else {
+                    continue;
+                };
+                self.free_buffers.push(buf_index as u16);
+                let result = if cqe.result() < 0 {
+                    Err(io::Error::from_raw_os_error(-cqe.result()))
+                } else {
+                    Ok(cqe.result() as usize)
+                };
+                // The caller may have given up waiting, so ignore send errors.
+                match request.op {
+                    Op::Read { responder, .. } => {
+                        let start = buf_index * self.buffer_size;
+                        drop(responder.send(
+                            result.map(|len| {
+                                Bytes::copy_from_slice(&self.buffers[start..start + len])
+                            }),
+                        ));
+                    }
+                    Op::Write { responder, .. } => drop(responder.send(result)),
+                }
+            }
+        }
+    }
+}
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 pub mod action_messages;
+pub mod bloom_filter;
 pub mod buf_channel;
 pub mod channel_body_for_tests;
 pub mod chunked_stream;
@@ -21,7 +22,9 @@ pub mod connection_manager;
 pub mod digest_hasher;
 pub mod evicting_map;
 pub mod fastcdc;
+pub mod frequency_sketch;
 pub mod fs;
+pub mod fs_uring;
 pub mod health_utils;
 pub mod instant_wrapper;
 pub mod known_platform_property_provider;
@@ -38,6 +41,8 @@ pub mod store_trait;
 pub mod task;
 pub mod telemetry;
 pub mod tls_utils;
+pub mod token_bucket;
+pub mod worker_features;
 pub mod write_counter;
 
 // Re-export tracing mostly for use in macros.
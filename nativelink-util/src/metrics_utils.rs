@@ -14,6 +14,7 @@
 
 use core::mem::forget;
 use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use futures::Future;
@@ -322,3 +323,108 @@ impl MetricsComponent for CounterWithTime {
         Ok(MetricPublishKnownKindData::Component)
     }
 }
+
+/// Upper bounds of the buckets used by `DurationHistogram`.
+const DURATION_HISTOGRAM_BUCKETS: [(&str, Duration); 14] = [
+    ("le_1ms", Duration::from_millis(1)),
+    ("le_5ms", Duration::from_millis(5)),
+    ("le_10ms", Duration::from_millis(10)),
+    ("le_50ms", Duration::from_millis(50)),
+    ("le_100ms", Duration::from_millis(100)),
+    ("le_500ms", Duration::from_millis(500)),
+    ("le_1s", Duration::from_secs(1)),
+    ("le_5s", Duration::from_secs(5)),
+    ("le_10s", Duration::from_secs(10)),
+    ("le_30s", Duration::from_secs(30)),
+    ("le_1m", Duration::from_secs(60)),
+    ("le_5m", Duration::from_secs(300)),
+    ("le_10m", Duration::from_secs(600)),
+    ("le_30m", Duration::from_secs(1800)),
+];
+
+/// Tracks the distribution of durations using fixed buckets. Buckets are
+/// cumulative, so a duration is counted in every bucket whose upper bound
+/// is greater than or equal to it.
+#[derive(Debug, Default)]
+pub struct DurationHistogram {
+    buckets: [AtomicU64; DURATION_HISTOGRAM_BUCKETS.len()],
+    count: AtomicU64,
+    // Sum of all observed durations in nanoseconds.
+    sum_ns: AtomicU64,
+}
+
+impl DurationHistogram {
+    #[inline]
+    pub fn observe(&self, duration: Duration) {
+        for (bucket, (_, upper_bound)) in self.buckets.iter().zip(DURATION_HISTOGRAM_BUCKETS) {
+            if duration <= upper_bound {
+                bucket.fetch_add(1, Ordering::Acquire);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Acquire);
+        self.sum_ns
+            .fetch_add(duration.as_nanos() as u64, Ordering::Acquire);
+    }
+
+    /// Number of durations observed.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Acquire)
+    }
+
+    /// Number of observed durations less than or equal to `upper_bound`,
+    /// rounded up to the nearest bucket.
+    pub fn count_le(&self, upper_bound: Duration) -> u64 {
+        self.buckets
+            .iter()
+            .zip(DURATION_HISTOGRAM_BUCKETS)
+            .find(|(_, (_, bucket_bound))| upper_bound <= *bucket_bound)
+            .map_or_else(
+                || self.count(),
+                |(bucket, _)| bucket.load(Ordering::Acquire),
+            )
+    }
+}
+
+// Derive-macros have no way to tell the collector that the parent
+// is now a group with the name of the group as the field so we
+// can attach multiple values on the same group, so we need to
+// manually implement the `MetricsComponent` trait to do so.
+impl MetricsComponent for DurationHistogram {
+    fn publish(
+        &self,
+        _kind: MetricKind,
+        field_metadata: MetricFieldData,
+    ) -> Result<MetricPublishKnownKindData, nativelink_metric::Error> {
+        let _enter = group!(field_metadata.name).entered();
+
+        for (bucket, (name, _)) in self.buckets.iter().zip(DURATION_HISTOGRAM_BUCKETS) {
+            publish!(
+                name,
+                bucket,
+                MetricKind::Counter,
+                format!(
+                    "Number of {} durations at or below the bucket bound.",
+                    field_metadata.name
+                )
+            );
+        }
+        publish!(
+            "count",
+            &self.count,
+            MetricKind::Counter,
+            format!("Number of {} durations observed.", field_metadata.name)
+        );
+        publish!(
+            "sum_ns",
+            &self.sum_ns,
+            MetricKind::Counter,
+            format!(
+                "The sum of the {} durations in nanoseconds.",
+                field_metadata.name
+            )
+        );
+
+        Ok(MetricPublishKnownKindData::Component)
+    }
+}
@@ -13,12 +13,13 @@
 // limitations under the License.
 
 use core::pin::Pin;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::SystemTime;
 
 use async_trait::async_trait;
 use bitflags::bitflags;
-use futures::Stream;
+use futures::{Stream, StreamExt};
 use nativelink_error::Error;
 use nativelink_metric::MetricsComponent;
 
@@ -54,6 +55,17 @@ pub trait ActionStateResult: Send + Sync + 'static {
     async fn changed(&mut self) -> Result<(Arc<ActionState>, Option<OriginMetadata>), Error>;
     /// Provide result as action info. This behavior will not be supported by all implementations.
     async fn as_action_info(&self) -> Result<(Arc<ActionInfo>, Option<OriginMetadata>), Error>;
+    /// Provides the approximate number of operations that will be picked up
+    /// before this one. None if the operation is not queued or the
+    /// implementation does not know.
+    async fn queue_position(&self) -> Result<Option<usize>, Error> {
+        Ok(None)
+    }
+    /// Provides the workers on which an attempt of the operation already
+    /// failed. Empty if the implementation does not track them.
+    async fn failed_worker_ids(&self) -> Result<Vec<WorkerId>, Error> {
+        Ok(Vec::new())
+    }
 }
 
 /// The direction in which the results are ordered.
@@ -82,6 +94,14 @@ pub struct OperationFilter {
     /// The digest of the action that the operation must have.
     pub action_digest: Option<DigestInfo>,
 
+    /// Platform properties the operation must have. Every entry must be
+    /// present on the action with the same value; other properties are
+    /// ignored.
+    pub platform_properties: BTreeMap<String, String>,
+
+    /// The identity of the client that created the operation.
+    pub identity: Option<String>,
+
     /// The operation must have its worker timestamp before this time.
     pub worker_update_before: Option<SystemTime>,
 
@@ -113,6 +133,17 @@ pub trait ClientStateManager: Sync + Send + Unpin + MetricsComponent + 'static {
         filter: OperationFilter,
     ) -> Result<ActionStateResultStream, Error>;
 
+    /// Returns the number of operations that match the filter. The default
+    /// implementation counts the results of `filter_operations`.
+    async fn count_operations(&self, filter: OperationFilter) -> Result<usize, Error> {
+        Ok(self.filter_operations(filter).await?.count().await)
+    }
+
+    /// Cancels the operation with the given client operation id. The operation
+    /// is marked as completed with a `Cancelled` error and all listeners are
+    /// notified. Cancelling an operation that already finished is a no-op.
+    async fn cancel_operation(&self, client_operation_id: &OperationId) -> Result<(), Error>;
+
     /// Returns the known platform property provider for the given instance
     /// if this implementation supports it.
     // TODO(https://github.com/rust-lang/rust/issues/65991) When this lands we can
@@ -170,4 +201,12 @@ pub trait MatchingEngineStateManager: Sync + Send + MetricsComponent {
         operation_id: &OperationId,
         worker_id_or_reason_for_unassign: Result<&WorkerId, Error>,
     ) -> Result<(), Error>;
+
+    /// Cancels the operation with the given client operation id like
+    /// `ClientStateManager::cancel_operation`. Returns the id of the
+    /// operation if it was cancelled, which is the id workers know it by.
+    async fn cancel_operation(
+        &self,
+        client_operation_id: &OperationId,
+    ) -> Result<Option<OperationId>, Error>;
 }
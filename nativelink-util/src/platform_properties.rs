@@ -46,6 +46,14 @@ impl PlatformProperties {
     /// Determines if the worker's `PlatformProperties` is satisfied by this struct.
     #[must_use]
     pub fn is_satisfied_by(&self, worker_properties: &Self) -> bool {
+        self.first_unsatisfied_property(worker_properties).is_none()
+    }
+
+    /// Returns the name of the first property of this struct the worker's
+    /// `PlatformProperties` is missing or does not satisfy, or None if it
+    /// satisfies all of them.
+    #[must_use]
+    pub fn first_unsatisfied_property(&self, worker_properties: &Self) -> Option<&str> {
         for (property, check_value) in &self.properties {
             if let Some(worker_value) = worker_properties.properties.get(property) {
                 if !check_value.is_satisfied_by(worker_value) {
@@ -55,17 +63,17 @@ impl PlatformProperties {
                             "Property mismatch on worker property {property}. {worker_value:?} != {check_value:?}"
                         );
                     }
-                    return false;
+                    return Some(property);
                 }
             } else {
                 #[cfg(feature = "worker_find_logging")]
                 {
                     info!("Property missing on worker property {property}");
                 }
-                return false;
+                return Some(property);
             }
         }
-        true
+        None
     }
 }
 
@@ -99,19 +107,22 @@ impl From<&PlatformProperties> for ProtoPlatform {
 
 /// Holds the associated value of the key and type.
 ///
-/// Exact    - Means the worker must have this exact value.
-/// Minimum  - Means that workers must have at least this number available. When
-///            a worker executes a task that has this value, the worker will have
-///            this value subtracted from the available resources of the worker.
-/// Priority - Means the worker is given this information, but does not restrict
-///            what workers can take this value. However, the worker must have the
-///            associated key present to be matched.
-///            TODO(palfrey) In the future this will be used by the scheduler and
-///            worker to cause the scheduler to prefer certain workers over others,
-///            but not restrict them based on these values.
+/// Exact      - Means the worker must have this exact value.
+/// Expression - Means the worker value must satisfy the constraint the action
+///              requests, like `>=16000` or `in {avx2, avx512}`.
+/// Minimum    - Means that workers must have at least this number available. When
+///              a worker executes a task that has this value, the worker will have
+///              this value subtracted from the available resources of the worker.
+/// Priority   - Means the worker is given this information, but does not restrict
+///              what workers can take this value. However, the worker must have the
+///              associated key present to be matched.
+///              TODO(palfrey) In the future this will be used by the scheduler and
+///              worker to cause the scheduler to prefer certain workers over others,
+///              but not restrict them based on these values.
 #[derive(Eq, PartialEq, Hash, Clone, Ord, PartialOrd, Debug, Serialize, Deserialize)]
 pub enum PlatformPropertyValue {
     Exact(String),
+    Expression(String),
     Minimum(u64),
     Priority(String),
     Unknown(String),
@@ -135,6 +146,12 @@ impl PlatformPropertyValue {
             // workers can be selected, but might be used to prefer certain workers
             // over others.
             Self::Priority(_) => true,
+            Self::Expression(expression) => {
+                if let Self::Expression(worker_v) = worker_value {
+                    return expression_is_satisfied_by(expression, worker_v);
+                }
+                false
+            }
             // Success exact case is handled above.
             Self::Exact(_) | Self::Unknown(_) => false,
         }
@@ -142,14 +159,48 @@ impl PlatformPropertyValue {
 
     pub fn as_str(&self) -> Cow<'_, str> {
         match self {
-            Self::Exact(value) | Self::Priority(value) | Self::Unknown(value) => {
-                Cow::Borrowed(value)
-            }
+            Self::Exact(value)
+            | Self::Expression(value)
+            | Self::Priority(value)
+            | Self::Unknown(value) => Cow::Borrowed(value),
             Self::Minimum(value) => Cow::Owned(value.to_string()),
         }
     }
 }
 
+/// Checks the value a worker advertises against the constraint an action
+/// requests. Numeric comparisons fail if either side is not a number.
+fn expression_is_satisfied_by(expression: &str, worker_value: &str) -> bool {
+    let expression = expression.trim();
+    let worker_value = worker_value.trim();
+    if let Some(values) = expression
+        .strip_prefix("in")
+        .map(str::trim_start)
+        .and_then(|set| set.strip_prefix('{'))
+        .and_then(|set| set.strip_suffix('}'))
+    {
+        return values.split(',').any(|value| value.trim() == worker_value);
+    }
+    if let Some(value) = expression.strip_prefix("!=") {
+        return value.trim() != worker_value;
+    }
+    let comparisons: [(&str, fn(&f64, &f64) -> bool); 4] = [
+        (">=", f64::ge),
+        ("<=", f64::le),
+        (">", f64::gt),
+        ("<", f64::lt),
+    ];
+    for (operator, is_satisfied) in comparisons {
+        if let Some(value) = expression.strip_prefix(operator) {
+            return match (worker_value.parse::<f64>(), value.trim().parse::<f64>()) {
+                (Ok(worker_v), Ok(v)) => is_satisfied(&worker_v, &v),
+                _ => false,
+            };
+        }
+    }
+    expression == worker_value
+}
+
 impl MetricsComponent for PlatformPropertyValue {
     fn publish(
         &self,
@@ -160,6 +211,7 @@ impl MetricsComponent for PlatformPropertyValue {
         let help = field_metadata.help.as_ref();
         match self {
             Self::Exact(v) => publish!(name, v, kind, help, "exact"),
+            Self::Expression(v) => publish!(name, v, kind, help, "expression"),
             Self::Minimum(v) => publish!(name, v, kind, help, "minimum"),
             Self::Priority(v) => publish!(name, v, kind, help, "priority"),
             Self::Unknown(v) => publish!(name, v, kind, help, "unknown"),
@@ -13,14 +13,23 @@
 // limitations under the License.
 
 use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
 use core::time::Duration;
 use std::sync::Arc;
 
 use futures::future::Future;
 use futures::stream::StreamExt;
-use nativelink_config::stores::{ErrorCode, Retry};
+use nativelink_config::stores::{ErrorCode, Retry, RetryBudgetSpec};
 use nativelink_error::{Code, Error, make_err};
-use tracing::error;
+use tracing::{error, warn};
+
+/// Default number of tokens in a full retry budget.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_RETRY_BUDGET_CAPACITY: u64 = 500;
+
+/// Default number of tokens a retry takes from the budget.
+/// Note: If this changes, remember to change the documentation in the config.
+const DEFAULT_RETRY_COST: u64 = 5;
 
 struct ExponentialBackoff {
     current: Duration,
@@ -51,12 +60,68 @@ pub enum RetryResult<T> {
     Err(Error),
 }
 
+/// Budget of retries which can be shared between multiple `Retrier`s.
+/// Every retry withdraws `retry_cost` tokens and every successful operation
+/// deposits a single token, so once most operations fail the budget runs
+/// dry and failures are no longer retried.
+#[derive(Debug)]
+pub struct RetryBudget {
+    tokens: AtomicU64,
+    capacity: u64,
+    retry_cost: u64,
+}
+
+impl RetryBudget {
+    pub const fn new(spec: &RetryBudgetSpec) -> Self {
+        let capacity = if spec.capacity == 0 {
+            DEFAULT_RETRY_BUDGET_CAPACITY
+        } else {
+            spec.capacity
+        };
+        let retry_cost = if spec.retry_cost == 0 {
+            DEFAULT_RETRY_COST
+        } else {
+            spec.retry_cost
+        };
+        Self {
+            tokens: AtomicU64::new(capacity),
+            capacity,
+            retry_cost,
+        }
+    }
+
+    /// Takes the tokens of a single retry from the budget. Returns false
+    /// and takes nothing if there are not enough tokens left.
+    pub fn try_withdraw(&self) -> bool {
+        self.tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |tokens| {
+                tokens.checked_sub(self.retry_cost)
+            })
+            .is_ok()
+    }
+
+    /// Returns a single token to the budget, up to its capacity.
+    pub fn deposit(&self) {
+        // An error only means the budget is already full.
+        let _ = self
+            .tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |tokens| {
+                (tokens < self.capacity).then_some(tokens + 1)
+            });
+    }
+
+    pub fn available(&self) -> u64 {
+        self.tokens.load(Ordering::Acquire)
+    }
+}
+
 /// Class used to retry a job with a sleep function in between each retry.
 #[derive(Clone)]
 pub struct Retrier {
     sleep_fn: SleepFn,
     jitter_fn: JitterFn,
     config: Retry,
+    budget: Option<Arc<RetryBudget>>,
 }
 
 impl core::fmt::Debug for Retrier {
@@ -94,9 +159,17 @@ impl Retrier {
             sleep_fn,
             jitter_fn,
             config,
+            budget: None,
         }
     }
 
+    /// Only retries while `budget` has tokens left.
+    #[must_use]
+    pub fn with_budget(mut self, budget: Arc<RetryBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
     /// This should only return true if the error code should be interpreted as
     /// temporary.
     fn should_retry(&self, code: Code) -> bool {
@@ -157,7 +230,12 @@ impl Retrier {
                             "Retry stream ended abruptly on attempt {attempt}",
                         ));
                     }
-                    Some(RetryResult::Ok(value)) => return Ok(value),
+                    Some(RetryResult::Ok(value)) => {
+                        if let Some(budget) = &self.budget {
+                            budget.deposit();
+                        }
+                        return Ok(value);
+                    }
                     Some(RetryResult::Err(e)) => {
                         return Err(e.append(format!("On attempt {attempt}")));
                     }
@@ -166,6 +244,16 @@ impl Retrier {
                             error!(?attempt, ?err, "Not retrying permanent error");
                             return Err(err);
                         }
+                        if self
+                            .budget
+                            .as_ref()
+                            .is_some_and(|budget| !budget.try_withdraw())
+                        {
+                            warn!(?attempt, ?err, "Not retrying, retry budget exhausted");
+                            return Err(
+                                err.append(format!("On attempt {attempt}, retry budget exhausted"))
+                            );
+                        }
                         (self.sleep_fn)(
                             iter.next()
                                 .ok_or_else(|| err.append(format!("On attempt {attempt}")))?,
@@ -19,6 +19,7 @@ use core::hash::{Hash, Hasher};
 use core::ops::{Bound, RangeBounds};
 use core::pin::Pin;
 use core::ptr::addr_eq;
+use core::time::Duration;
 use std::borrow::Cow;
 use std::collections::hash_map::DefaultHasher as StdHasher;
 use std::ffi::OsString;
@@ -26,7 +27,7 @@ use std::sync::{Arc, OnceLock};
 
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
-use futures::{Future, FutureExt, Stream, join, try_join};
+use futures::{Future, FutureExt, Stream, TryStreamExt, join, try_join};
 use nativelink_error::{Code, Error, ResultExt, error_if, make_err};
 use nativelink_metric::MetricsComponent;
 use rand::rngs::StdRng;
@@ -491,6 +492,15 @@ pub trait StoreLike: Send + Sync + Sized + Unpin + 'static {
         }
     }
 
+    /// Removes the key from the store. Returns true if the key existed.
+    #[inline]
+    fn remove<'a>(
+        &'a self,
+        key: impl Into<StoreKey<'a>>,
+    ) -> impl Future<Output = Result<bool, Error>> + Send + 'a {
+        self.as_store_driver_pin().remove(key.into())
+    }
+
     /// Sends the data to the store.
     #[inline]
     fn update<'a>(
@@ -634,6 +644,14 @@ pub trait StoreDriver:
         ))
     }
 
+    /// See: [`StoreLike::remove`] for details.
+    async fn remove(self: Pin<&Self>, _key: StoreKey<'_>) -> Result<bool, Error> {
+        Err(make_err!(
+            Code::Unimplemented,
+            "Store::remove() not implemented for this store"
+        ))
+    }
+
     /// See: [`StoreLike::update`] for details.
     async fn update(
         self: Pin<&Self>,
@@ -905,6 +923,26 @@ pub trait SchedulerStore: Send + Sync + 'static {
     where
         K: SchedulerIndexProvider + SchedulerStoreDecodeTo + Send;
 
+    /// Counts all keys in the store that match the given index prefix. The
+    /// default implementation decodes every match, so stores that can count
+    /// on the server should override it.
+    fn count_by_index_prefix<K>(
+        &self,
+        index: K,
+    ) -> impl Future<Output = Result<usize, Error>> + Send
+    where
+        K: SchedulerIndexProvider + SchedulerStoreDecodeTo + Send,
+    {
+        async move {
+            self.search_by_index_prefix(index)
+                .await
+                .err_tip(|| "In SchedulerStore::count_by_index_prefix")?
+                .try_fold(0, |count, _| async move { Ok(count + 1) })
+                .await
+                .err_tip(|| "In SchedulerStore::count_by_index_prefix")
+        }
+    }
+
     /// Returns data for the provided key with the given version if
     /// `StoreKeyProvider::Versioned` is `TrueValue`.
     fn get_and_decode<K>(
@@ -913,6 +951,18 @@ pub trait SchedulerStore: Send + Sync + 'static {
     ) -> impl Future<Output = Result<Option<<K as SchedulerStoreDecodeTo>::DecodeOutput>, Error>> + Send
     where
         K: SchedulerStoreKeyProvider + SchedulerStoreDecodeTo + Send;
+
+    /// Deletes the data for the provided key along with its indexes.
+    /// Returns true if there was data to delete.
+    fn delete_data<K>(&self, key: K) -> impl Future<Output = Result<bool, Error>> + Send
+    where
+        K: SchedulerStoreKeyProvider + Send;
+
+    /// Returns the keys of all the data whose key starts with `prefix`.
+    fn list_keys_with_prefix(
+        &self,
+        prefix: &str,
+    ) -> impl Future<Output = Result<Vec<String>, Error>> + Send;
 }
 
 /// A type that is used to let the scheduler store know what
@@ -952,6 +1002,12 @@ pub trait SchedulerStoreDataProvider {
     fn get_indexes(&self) -> Result<Vec<(&'static str, Bytes)>, Error> {
         Ok(Vec::new())
     }
+
+    /// Returns how long the data is kept for if it should expire on its own.
+    /// Stores which can't expire data keep it until it is deleted.
+    fn expiry(&self) -> Option<Duration> {
+        None
+    }
 }
 
 /// Provides the current version of the data in the store.
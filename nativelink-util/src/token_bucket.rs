@@ -0,0 +1,74 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::time::Duration;
+
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+#[derive(Debug)]
+struct TokenBucketState {
+    /// May be negative when callers reserved more than was available; they
+    /// then wait until the bucket is refilled to zero.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Limits the number of bytes per second everything sharing the bucket
+/// transfers, allowing bursts of up to one second worth of bytes.
+#[derive(Debug)]
+pub struct TokenBucket {
+    bytes_per_second: f64,
+    state: Mutex<TokenBucketState>,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket. `bytes_per_second` must not be zero.
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second.max(1) as f64;
+        Self {
+            bytes_per_second,
+            state: Mutex::new(TokenBucketState {
+                tokens: bytes_per_second,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes `bytes` tokens at `now` and returns how long the caller must
+    /// wait before transferring them. Tokens are handed out in the order
+    /// they are reserved, so a large transfer can't be starved by small ones.
+    pub fn reserve(&self, bytes: u64, now: Instant) -> Duration {
+        let mut state = self.state.lock();
+        let elapsed = now.saturating_duration_since(state.last_refill);
+        state.tokens = elapsed
+            .as_secs_f64()
+            .mul_add(self.bytes_per_second, state.tokens)
+            .min(self.bytes_per_second);
+        state.last_refill = state.last_refill.max(now);
+        state.tokens -= bytes as f64;
+        if state.tokens >= 0. {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-state.tokens / self.bytes_per_second)
+    }
+
+    /// Waits until `bytes` may be transferred.
+    pub async fn acquire(&self, bytes: u64) {
+        let wait = self.reserve(bytes, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,28 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Version of the worker API protocol spoken by this build. Bump it when
+/// existing messages change meaning in a way a feature can not describe.
+pub const WORKER_API_PROTOCOL_VERSION: u32 = 1;
+
+/// The worker reports the resource utilization of its host in keep alive
+/// requests.
+pub const RESOURCE_UTILIZATION: &str = "resource_utilization";
+
+/// The worker handles `PrefetchInputs` hints by downloading the given
+/// directories into its local cache.
+pub const PREFETCH_INPUTS: &str = "prefetch_inputs";
+
+/// The optional worker API features supported by this build.
+pub const SUPPORTED_WORKER_FEATURES: &[&str] = &[RESOURCE_UTILIZATION, PREFETCH_INPUTS];
@@ -1,5 +1,8 @@
+use std::time::SystemTime;
+
 use hex::FromHex;
 use nativelink_macro::nativelink_test;
+use nativelink_proto::build::bazel::remote::execution::v2::{Action, ExecuteRequest};
 use nativelink_util::action_messages::{ActionInfo, ActionUniqueKey, ActionUniqueQualifier};
 use nativelink_util::common::DigestInfo;
 use nativelink_util::digest_hasher::DigestHasherFunc;
@@ -37,3 +40,32 @@ fn old_unique_qualifier_uncachable_works() {
         ActionUniqueQualifier::Uncacheable(make_key())
     );
 }
+
+#[nativelink_test]
+fn do_not_cache_action_is_uncacheable() {
+    let key = make_key();
+    let action = Action {
+        command_digest: Some(key.digest.into()),
+        input_root_digest: Some(key.digest.into()),
+        do_not_cache: true,
+        ..Default::default()
+    };
+    let execute_request = ExecuteRequest {
+        instance_name: key.instance_name.clone(),
+        action_digest: Some(key.digest.into()),
+        digest_function: key.digest_function.proto_digest_func().into(),
+        ..Default::default()
+    };
+    let action_info = ActionInfo::try_from_action_and_execute_request(
+        execute_request,
+        action,
+        SystemTime::UNIX_EPOCH,
+        SystemTime::UNIX_EPOCH,
+    )
+    .unwrap();
+    assert!(action_info.do_not_cache);
+    assert_eq!(
+        action_info.unique_qualifier,
+        ActionUniqueQualifier::Uncacheable(key)
+    );
+}
@@ -0,0 +1,81 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::digest_hasher::{DigestHasher, DigestHasherFunc};
+use pretty_assertions::assert_eq;
+
+const DATA_SIZE: usize = 100 * 1024 + 7;
+
+fn make_data() -> Vec<u8> {
+    (0..DATA_SIZE).map(|i| (i % 251) as u8).collect()
+}
+
+#[nativelink_test]
+async fn blake3_small_chunks_match_one_shot_hash() -> Result<(), Error> {
+    let data = make_data();
+    let expected = DigestInfo::new(blake3::hash(&data).into(), data.len() as u64);
+
+    let mut hasher = DigestHasherFunc::Blake3.hasher();
+    for chunk in data.chunks(100) {
+        hasher.update(chunk);
+    }
+    assert_eq!(hasher.finalize_digest(), expected);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn blake3_mixed_chunk_sizes_match_one_shot_hash() -> Result<(), Error> {
+    let data = make_data();
+    let expected = DigestInfo::new(blake3::hash(&data).into(), data.len() as u64);
+
+    let mut hasher = DigestHasherFunc::Blake3.hasher();
+    // A small write followed by a large one must not be reordered.
+    hasher.update(&data[..10]);
+    hasher.update(&data[10..50 * 1024]);
+    hasher.update(&data[50 * 1024..]);
+    assert_eq!(hasher.finalize_digest(), expected);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn blake3_write_topping_up_staged_data_matches_one_shot_hash() -> Result<(), Error> {
+    let data = make_data();
+    let expected = DigestInfo::new(blake3::hash(&data).into(), data.len() as u64);
+
+    let mut hasher = DigestHasherFunc::Blake3.hasher();
+    // The second write fills the staged data up and leaves a small rest,
+    // which has to be staged again.
+    hasher.update(&data[..10]);
+    hasher.update(&data[10..16 * 1024 + 20]);
+    hasher.update(&data[16 * 1024 + 20..]);
+    assert_eq!(hasher.finalize_digest(), expected);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn finalize_digest_resets_hasher() -> Result<(), Error> {
+    for func in [DigestHasherFunc::Sha256, DigestHasherFunc::Blake3] {
+        let mut hasher = func.hasher();
+        hasher.update(b"foo");
+        let first = hasher.finalize_digest();
+        hasher.update(b"foo");
+        let second = hasher.finalize_digest();
+        assert_eq!(first, second, "Hasher {func} was not reset");
+        assert_eq!(second.size_bytes(), 3);
+    }
+    Ok(())
+}
@@ -18,7 +18,7 @@ use std::sync::Arc;
 
 use bytes::Bytes;
 use mock_instant::thread_local::MockClock;
-use nativelink_config::stores::EvictionPolicy;
+use nativelink_config::stores::{EvictionAlgorithm, EvictionPolicy};
 use nativelink_error::Error;
 use nativelink_macro::nativelink_test;
 use nativelink_util::common::DigestInfo;
@@ -61,6 +61,7 @@ async fn insert_purges_at_max_count() -> Result<(), Error> {
             max_seconds: 0,
             max_bytes: 0,
             evict_bytes: 0,
+            algorithm: EvictionAlgorithm::Lru,
         },
         MockInstantWrapped::default(),
     );
@@ -118,6 +119,7 @@ async fn insert_purges_at_max_bytes() -> Result<(), Error> {
             max_seconds: 0,
             max_bytes: 17,
             evict_bytes: 0,
+            algorithm: EvictionAlgorithm::Lru,
         },
         MockInstantWrapped::default(),
     );
@@ -175,6 +177,7 @@ async fn insert_purges_to_low_watermark_at_max_bytes() -> Result<(), Error> {
             max_seconds: 0,
             max_bytes: 17,
             evict_bytes: 9,
+            algorithm: EvictionAlgorithm::Lru,
         },
         MockInstantWrapped::default(),
     );
@@ -223,6 +226,81 @@ async fn insert_purges_to_low_watermark_at_max_bytes() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn w_tiny_lfu_keeps_frequent_item_during_scan() -> Result<(), Error> {
+    let evicting_map = EvictingMap::<DigestInfo, DigestInfo, BytesWrapper, MockInstantWrapped>::new(
+        &EvictionPolicy {
+            max_count: 4,
+            algorithm: EvictionAlgorithm::WTinyLfu,
+            ..Default::default()
+        },
+        MockInstantWrapped::default(),
+    );
+    let hot_digest = DigestInfo::try_new(HASH1, 0)?;
+    evicting_map.insert(hot_digest, Bytes::new().into()).await;
+    for _ in 0..3 {
+        assert!(evicting_map.get(&hot_digest).await.is_some());
+    }
+
+    // A scan touching each item once must not push out the frequent item.
+    for i in 0..20u8 {
+        evicting_map
+            .insert(DigestInfo::new([i; 32], 1), Bytes::new().into())
+            .await;
+    }
+
+    assert_eq!(evicting_map.len_for_test().await, 4);
+    assert_eq!(
+        evicting_map.size_for_key(&hot_digest).await,
+        Some(0),
+        "Expected map to have the frequent item"
+    );
+    assert_eq!(
+        evicting_map
+            .size_for_key(&DigestInfo::new([0; 32], 1))
+            .await,
+        None,
+        "Expected map to not have the first scanned item"
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn w_tiny_lfu_purges_at_max_seconds() -> Result<(), Error> {
+    let evicting_map = EvictingMap::<DigestInfo, DigestInfo, BytesWrapper, MockInstantWrapped>::new(
+        &EvictionPolicy {
+            max_seconds: 5,
+            algorithm: EvictionAlgorithm::WTinyLfu,
+            ..Default::default()
+        },
+        MockInstantWrapped::default(),
+    );
+    let hot_digest = DigestInfo::try_new(HASH1, 0)?;
+    evicting_map.insert(hot_digest, Bytes::new().into()).await;
+    assert!(evicting_map.get(&hot_digest).await.is_some());
+    evicting_map
+        .insert(DigestInfo::try_new(HASH2, 0)?, Bytes::new().into())
+        .await;
+
+    MockClock::advance(Duration::from_secs(10));
+    evicting_map
+        .insert(DigestInfo::try_new(HASH3, 0)?, Bytes::new().into())
+        .await;
+
+    // Expired items are evicted no matter how often they were accessed.
+    assert_eq!(evicting_map.len_for_test().await, 1);
+    assert_eq!(
+        evicting_map
+            .size_for_key(&DigestInfo::try_new(HASH3, 0)?)
+            .await,
+        Some(0),
+        "Expected map to have item 3"
+    );
+
+    Ok(())
+}
+
 #[nativelink_test]
 async fn insert_purges_at_max_seconds() -> Result<(), Error> {
     const DATA: &str = "12345678";
@@ -233,6 +311,7 @@ async fn insert_purges_at_max_seconds() -> Result<(), Error> {
             max_seconds: 5,
             max_bytes: 0,
             evict_bytes: 0,
+            algorithm: EvictionAlgorithm::Lru,
         },
         MockInstantWrapped::default(),
     );
@@ -295,6 +374,7 @@ async fn get_refreshes_time() -> Result<(), Error> {
             max_seconds: 3,
             max_bytes: 0,
             evict_bytes: 0,
+            algorithm: EvictionAlgorithm::Lru,
         },
         MockInstantWrapped::default(),
     );
@@ -371,6 +451,7 @@ async fn unref_called_on_replace() -> Result<(), Error> {
                 max_seconds: 0,
                 max_bytes: 0,
                 evict_bytes: 0,
+                algorithm: EvictionAlgorithm::Lru,
             },
             MockInstantWrapped::default(),
         );
@@ -416,6 +497,7 @@ async fn contains_key_refreshes_time() -> Result<(), Error> {
             max_seconds: 3,
             max_bytes: 0,
             evict_bytes: 0,
+            algorithm: EvictionAlgorithm::Lru,
         },
         MockInstantWrapped::default(),
     );
@@ -469,6 +551,7 @@ async fn hashes_equal_sizes_different_doesnt_override() -> Result<(), Error> {
             max_seconds: 0,
             max_bytes: 0,
             evict_bytes: 0,
+            algorithm: EvictionAlgorithm::Lru,
         },
         MockInstantWrapped::default(),
     );
@@ -524,6 +607,7 @@ async fn get_evicts_on_time() -> Result<(), Error> {
             max_seconds: 5,
             max_bytes: 0,
             evict_bytes: 0,
+            algorithm: EvictionAlgorithm::Lru,
         },
         MockInstantWrapped::default(),
     );
@@ -557,6 +641,7 @@ async fn remove_evicts_on_time() -> Result<(), Error> {
             max_seconds: 5,
             max_bytes: 0,
             evict_bytes: 0,
+            algorithm: EvictionAlgorithm::Lru,
         },
         MockInstantWrapped::default(),
     );
@@ -613,6 +698,7 @@ async fn range_multiple_items_test() -> Result<(), Error> {
             max_seconds: 0,
             max_bytes: 0,
             evict_bytes: 0,
+            algorithm: EvictionAlgorithm::Lru,
         },
         MockInstantWrapped::default(),
     );
@@ -18,7 +18,7 @@ use std::io::Cursor;
 use bytes::Bytes;
 use futures::stream::StreamExt;
 use nativelink_macro::nativelink_test;
-use nativelink_util::fastcdc::FastCDC;
+use nativelink_util::fastcdc::{DEFAULT_NORMALIZATION_LEVEL, FastCDC};
 use pretty_assertions::assert_eq;
 use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
@@ -221,3 +221,54 @@ async fn insert_garbage_check_boundaries_recover_test() -> Result<(), std::io::E
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn normalization_level_narrows_chunk_sizes_test() -> Result<(), std::io::Error> {
+    let data = {
+        let mut data = vec![0u8; 20 * MEGABYTE_SZ];
+        let mut rng = SmallRng::seed_from_u64(1);
+        rng.fill(&mut data[..]);
+        data
+    };
+    let mut chunk_size_spread = vec![];
+    for normalization_level in 0..=3 {
+        let mut cursor = Cursor::new(&data);
+        let mut frame_reader = FramedRead::new(
+            &mut cursor,
+            FastCDC::with_normalization(0x1000, 0x4000, 0x10000, normalization_level),
+        );
+        let lens: Vec<f64> = get_frames(&mut frame_reader)
+            .await?
+            .iter()
+            .map(|frame| frame.len() as f64)
+            .collect();
+        let mean = lens.iter().sum::<f64>() / lens.len() as f64;
+        let variance = lens.iter().map(|len| (len - mean).powi(2)).sum::<f64>() / lens.len() as f64;
+        chunk_size_spread.push(variance.sqrt());
+    }
+    for spreads in chunk_size_spread.windows(2) {
+        assert!(
+            spreads[1] < spreads[0],
+            "Expected chunk sizes to be spread less with higher normalization: {chunk_size_spread:?}"
+        );
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+async fn default_normalization_level_matches_new_test() -> Result<(), std::io::Error> {
+    let data = {
+        let mut data = vec![0u8; MEGABYTE_SZ];
+        let mut rng = SmallRng::seed_from_u64(1);
+        rng.fill(&mut data[..]);
+        data
+    };
+    let mut frame_reader = FramedRead::new(Cursor::new(&data), FastCDC::new(1024, 4096, 16384));
+    let expected = get_frames(&mut frame_reader).await?;
+    let mut frame_reader = FramedRead::new(
+        Cursor::new(&data),
+        FastCDC::with_normalization(1024, 4096, 16384, DEFAULT_NORMALIZATION_LEVEL),
+    );
+    assert_eq!(get_frames(&mut frame_reader).await?, expected);
+    Ok(())
+}
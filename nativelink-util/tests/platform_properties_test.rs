@@ -0,0 +1,42 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nativelink_macro::nativelink_test;
+use nativelink_util::platform_properties::PlatformPropertyValue;
+
+fn expression_matches(expression: &str, worker_value: &str) -> bool {
+    PlatformPropertyValue::Expression(expression.to_string())
+        .is_satisfied_by(&PlatformPropertyValue::Expression(worker_value.to_string()))
+}
+
+#[nativelink_test]
+async fn expression_numeric_comparisons_test() {
+    assert!(expression_matches(">=16000", "64000"));
+    assert!(expression_matches(">=64000", "64000"));
+    assert!(!expression_matches(">64000", "64000"));
+    assert!(expression_matches("<= 4", "2"));
+    assert!(!expression_matches("<2", "2"));
+    // Values that are not numbers never satisfy a numeric comparison.
+    assert!(!expression_matches(">=16000", "lots"));
+}
+
+#[nativelink_test]
+async fn expression_set_and_equality_test() {
+    assert!(expression_matches("in {avx2, avx512}", "avx512"));
+    assert!(!expression_matches("in {avx2, avx512}", "sse4"));
+    assert!(expression_matches("!=arm64", "x86_64"));
+    assert!(!expression_matches("!=arm64", "arm64"));
+    assert!(expression_matches("x86_64", "x86_64"));
+    assert!(!expression_matches("x86_64", "arm64"));
+}
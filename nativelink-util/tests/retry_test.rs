@@ -18,10 +18,10 @@ use std::sync::Arc;
 
 use futures::future::ready;
 use futures::stream::repeat_with;
-use nativelink_config::stores::Retry;
+use nativelink_config::stores::{Retry, RetryBudgetSpec};
 use nativelink_error::{Code, Error, make_err};
 use nativelink_macro::nativelink_test;
-use nativelink_util::retry::{Retrier, RetryResult};
+use nativelink_util::retry::{Retrier, RetryBudget, RetryResult};
 use pretty_assertions::assert_eq;
 use tokio::time::Duration;
 
@@ -178,3 +178,47 @@ async fn retry_calls_sleep_fn() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn retry_stops_when_budget_exhausted() -> Result<(), Error> {
+    let budget = Arc::new(RetryBudget::new(&RetryBudgetSpec {
+        capacity: 10,
+        retry_cost: 5,
+    }));
+    let retrier = Retrier::new(
+        Arc::new(|_duration| Box::pin(ready(()))),
+        Arc::new(move |_delay| Duration::from_millis(1)),
+        Retry {
+            max_retries: 10,
+            ..Default::default()
+        },
+    )
+    .with_budget(budget.clone());
+    let run_count = Arc::new(AtomicI32::new(0));
+    let result = Pin::new(&retrier)
+        .retry(repeat_with(|| {
+            run_count.fetch_add(1, Ordering::Relaxed);
+            RetryResult::<bool>::Retry(make_err!(Code::Unavailable, "Dummy failure",))
+        }))
+        .await;
+    assert_eq!(
+        run_count.load(Ordering::Relaxed),
+        3,
+        "Expected budget to allow two retries"
+    );
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Error { code: Unavailable, messages: [\"Dummy failure\", \"On attempt 3, retry budget exhausted\"] }"
+    );
+    assert_eq!(budget.available(), 0);
+
+    // Successes refill the budget one token at a time.
+    for _ in 0..5 {
+        Pin::new(&retrier)
+            .retry(repeat_with(|| RetryResult::Ok(true)))
+            .await?;
+    }
+    assert_eq!(budget.available(), 5);
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Functional Source License, Version 1.1, Apache 2.0 Future License (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    See LICENSE file for details
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::time::Duration;
+
+use nativelink_macro::nativelink_test;
+use nativelink_util::token_bucket::TokenBucket;
+use pretty_assertions::assert_eq;
+use tokio::time::Instant;
+
+const BYTES_PER_SECOND: u64 = 1000;
+
+#[nativelink_test]
+async fn burst_up_to_rate_does_not_wait() {
+    let token_bucket = TokenBucket::new(BYTES_PER_SECOND);
+    let now = Instant::now();
+    assert_eq!(token_bucket.reserve(600, now), Duration::ZERO);
+    assert_eq!(token_bucket.reserve(400, now), Duration::ZERO);
+}
+
+#[nativelink_test]
+async fn callers_over_rate_wait_in_order() {
+    let token_bucket = TokenBucket::new(BYTES_PER_SECOND);
+    let now = Instant::now();
+    assert_eq!(token_bucket.reserve(1000, now), Duration::ZERO);
+    assert_eq!(token_bucket.reserve(500, now), Duration::from_millis(500));
+    // The second caller waits behind the first one.
+    assert_eq!(token_bucket.reserve(500, now), Duration::from_secs(1));
+}
+
+#[nativelink_test]
+async fn bucket_refills_over_time_up_to_one_second() {
+    let token_bucket = TokenBucket::new(BYTES_PER_SECOND);
+    let now = Instant::now();
+    assert_eq!(token_bucket.reserve(1000, now), Duration::ZERO);
+
+    let now = now + Duration::from_millis(250);
+    assert_eq!(token_bucket.reserve(250, now), Duration::ZERO);
+    assert_eq!(token_bucket.reserve(250, now), Duration::from_millis(250));
+
+    // Idle time beyond a second does not accumulate more tokens.
+    let now = now + Duration::from_secs(10);
+    assert_eq!(token_bucket.reserve(1000, now), Duration::ZERO);
+    assert_eq!(token_bucket.reserve(100, now), Duration::from_millis(100));
+}
@@ -32,7 +32,7 @@ use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::
 };
 use nativelink_store::fast_slow_store::FastSlowStore;
 use nativelink_util::action_messages::{ActionResult, ActionStage, OperationId};
-use nativelink_util::common::fs;
+use nativelink_util::common::{DigestInfo, fs};
 use nativelink_util::digest_hasher::DigestHasherFunc;
 use nativelink_util::metrics_utils::{AsyncCounterWrapper, CounterWithTime};
 use nativelink_util::shutdown_guard::ShutdownGuard;
@@ -51,7 +51,7 @@ use crate::running_actions_manager::{
     RunningActionsManager, RunningActionsManagerArgs, RunningActionsManagerImpl,
 };
 use crate::worker_api_client_wrapper::{WorkerApiClientTrait, WorkerApiClientWrapper};
-use crate::worker_utils::make_connect_worker_request;
+use crate::worker_utils::{collect_worker_utilization, make_connect_worker_request};
 
 /// Amount of time to wait if we have actions in transit before we try to
 /// consider an error to have occurred.
@@ -154,6 +154,7 @@ impl<'a, T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorkerImpl<'a,
             if let Err(e) = grpc_client
                 .keep_alive(KeepAliveRequest {
                     worker_id: self.worker_id.clone(),
+                    utilization: collect_worker_utilization().await,
                 })
                 .await
             {
@@ -229,6 +230,36 @@ impl<'a, T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorkerImpl<'a,
                                 );
                             }
                         }
+                        Update::PrefetchInputs(prefetch_inputs) => {
+                            self.metrics.prefetch_inputs_received.inc();
+                            if shutting_down {
+                                continue;
+                            }
+                            let directory_digests = match prefetch_inputs
+                                .directory_digests
+                                .into_iter()
+                                .map(DigestInfo::try_from)
+                                .collect::<Result<Vec<_>, _>>()
+                            {
+                                Ok(directory_digests) => directory_digests,
+                                Err(err) => {
+                                    warn!(?err, "Ignoring prefetch hint with an invalid digest");
+                                    continue;
+                                }
+                            };
+                            // The hint is only an optimization, failing to
+                            // prefetch must not disconnect the worker.
+                            let running_actions_manager = self.running_actions_manager.clone();
+                            futures.push(
+                                spawn!("worker_prefetch_inputs", async move {
+                                    if let Err(err) = running_actions_manager.prefetch_inputs(directory_digests).await {
+                                        warn!(?err, "Failed to prefetch inputs");
+                                    }
+                                })
+                                .map(|res| res.err_tip(|| "Failed to launch spawn"))
+                                .boxed()
+                            );
+                        }
                         Update::StartAction(start_execute) => {
                             // Don't accept any new requests if we're shutting down.
                             if shutting_down {
@@ -276,6 +307,7 @@ impl<'a, T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorkerImpl<'a,
                                             operation_id = ?action.get_operation_id(),
                                             "Received request to run action"
                                         );
+                                        let do_not_cache = action.do_not_cache();
                                         action
                                             .clone()
                                             .prepare_action()
@@ -289,6 +321,7 @@ impl<'a, T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorkerImpl<'a,
                                                 }
                                                 result
                                             })
+                                            .map_ok(move |action_result| (action_result, do_not_cache))
                                     }).await
                                 })
                             };
@@ -298,13 +331,14 @@ impl<'a, T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorkerImpl<'a,
 
                                 let worker_id = self.worker_id.clone();
                                 let running_actions_manager = self.running_actions_manager.clone();
-                                move |res: Result<ActionResult, Error>| async move {
+                                move |res: Result<(ActionResult, bool), Error>| async move {
                                     let instance_name = maybe_instance_name
                                         .err_tip(|| "`instance_name` could not be resolved; this is likely an internal error in local_worker.")?;
                                     match res {
-                                        Ok(mut action_result) => {
+                                        Ok((mut action_result, do_not_cache)) => {
                                             // Save in the action cache before notifying the scheduler that we've completed.
-                                            if let Some(digest_info) = action_digest.clone().and_then(|action_digest| action_digest.try_into().ok()) {
+                                            // Actions that asked not to be cached are never written to the action cache.
+                                            if let Some(digest_info) = action_digest.clone().and_then(|action_digest| action_digest.try_into().ok()).filter(|_| !do_not_cache) {
                                                 if let Err(err) = running_actions_manager.cache_action_result(digest_info, &mut action_result, digest_hasher).await {
                                                     error!(
                                                         ?err,
@@ -563,9 +597,12 @@ impl<T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorker<T, U> {
         &self,
         client: &mut T,
     ) -> Result<(String, Streaming<UpdateForWorker>), Error> {
-        let connect_worker_request =
-            make_connect_worker_request(self.config.name.clone(), &self.config.platform_properties)
-                .await?;
+        let connect_worker_request = make_connect_worker_request(
+            self.config.name.clone(),
+            &self.config.platform_properties,
+            self.config.labels.clone(),
+        )
+        .await?;
         let mut update_for_worker_stream = client
             .connect_worker(connect_worker_request)
             .await
@@ -580,7 +617,15 @@ impl<T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorker<T, U> {
             .update;
 
         let worker_id = match first_msg_update {
-            Some(Update::ConnectionResult(connection_result)) => connection_result.worker_id,
+            Some(Update::ConnectionResult(connection_result)) => {
+                info!(
+                    worker_id = connection_result.worker_id,
+                    scheduler_protocol_version = connection_result.protocol_version,
+                    features = ?connection_result.features,
+                    "Registered with scheduler"
+                );
+                connection_result.worker_id
+            }
             other => {
                 return Err(make_input_err!(
                     "Expected first response from scheduler to be a ConnectResult got : {:?}",
@@ -679,6 +724,8 @@ pub struct Metrics {
     disconnects_received: CounterWithTime,
     #[metric(help = "Total number of keep-alives received from the scheduler.")]
     keep_alives_received: CounterWithTime,
+    #[metric(help = "Total number of prefetch hints received from the scheduler.")]
+    prefetch_inputs_received: CounterWithTime,
     #[metric(
         help = "Stats about the calls to check if an action satisfies the config supplied script."
     )]
@@ -699,6 +746,7 @@ impl Metrics {
             start_actions_received: CounterWithTime::default(),
             disconnects_received: CounterWithTime::default(),
             keep_alives_received: CounterWithTime::default(),
+            prefetch_inputs_received: CounterWithTime::default(),
             preconditions: AsyncCounterWrapper::default(),
             running_actions_manager_metrics,
         }
@@ -258,6 +258,55 @@ pub fn download_to_directory<'a>(
     .boxed()
 }
 
+/// Downloads the files of the directory tree at `digest` into the fast store
+/// without creating them anywhere, so a later `download_to_directory` of a
+/// tree sharing these files only needs to hardlink them.
+pub fn prefetch_directory<'a>(
+    cas_store: &'a FastSlowStore,
+    digest: &'a DigestInfo,
+) -> BoxFuture<'a, Result<(), Error>> {
+    async move {
+        let directory = get_and_decode_digest::<ProtoDirectory>(cas_store, digest.into())
+            .await
+            .err_tip(|| "Converting digest to Directory")?;
+        let mut futures = FuturesUnordered::new();
+
+        for file in directory.files {
+            let digest: DigestInfo = file
+                .digest
+                .err_tip(|| "Expected Digest to exist in Directory::file::digest")?
+                .try_into()
+                .err_tip(|| "In Directory::file::digest")?;
+            futures.push(
+                cas_store
+                    .populate_fast_store(digest.into())
+                    .map_err(move |e| e.append(format!("for digest {digest}")))
+                    .boxed(),
+            );
+        }
+
+        for directory in directory.directories {
+            let digest: DigestInfo = directory
+                .digest
+                .err_tip(|| "Expected Digest to exist in Directory::directories::digest")?
+                .try_into()
+                .err_tip(|| "In Directory::directories::digest")?;
+            futures.push(
+                async move {
+                    prefetch_directory(cas_store, &digest)
+                        .await
+                        .err_tip(|| format!("in prefetch_directory : {digest}"))
+                }
+                .boxed(),
+            );
+        }
+
+        while futures.try_next().await?.is_some() {}
+        Ok(())
+    }
+    .boxed()
+}
+
 #[cfg(target_family = "windows")]
 fn is_executable(_metadata: &std::fs::Metadata, full_path: &impl AsRef<Path>) -> bool {
     static EXECUTABLE_EXTENSIONS: &[&str] = &["exe", "bat", "com"];
@@ -604,6 +653,10 @@ pub trait RunningAction: Sync + Send + Sized + Unpin + 'static {
     /// Returns the action id of the action.
     fn get_operation_id(&self) -> &OperationId;
 
+    /// Returns true if the result of the action must not be written to the
+    /// action cache.
+    fn do_not_cache(&self) -> bool;
+
     /// Anything that needs to execute before the actions is actually executed should happen here.
     fn prepare_action(self: Arc<Self>) -> impl Future<Output = Result<Arc<Self>, Error>> + Send;
 
@@ -1351,6 +1404,10 @@ impl RunningAction for RunningActionImpl {
         &self.operation_id
     }
 
+    fn do_not_cache(&self) -> bool {
+        self.action_info.do_not_cache
+    }
+
     async fn prepare_action(self: Arc<Self>) -> Result<Arc<Self>, Error> {
         self.metrics()
             .clone()
@@ -1429,6 +1486,13 @@ pub trait RunningActionsManager: Sync + Send + Sized + Unpin + 'static {
         operation_id: &OperationId,
     ) -> impl Future<Output = Result<(), Error>> + Send;
 
+    /// Downloads the given directory trees into the local cache ahead of
+    /// the actions that will need them.
+    fn prefetch_inputs(
+        &self,
+        directory_digests: Vec<DigestInfo>,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
     fn metrics(&self) -> &Arc<Metrics>;
 }
 
@@ -2097,6 +2161,20 @@ impl RunningActionsManager for RunningActionsManagerImpl {
             .await
     }
 
+    async fn prefetch_inputs(&self, directory_digests: Vec<DigestInfo>) -> Result<(), Error> {
+        self.metrics
+            .prefetch_inputs
+            .wrap(async move {
+                let mut futures: FuturesUnordered<_> = directory_digests
+                    .iter()
+                    .map(|digest| prefetch_directory(&self.cas_store, digest))
+                    .collect();
+                while futures.try_next().await?.is_some() {}
+                Ok(())
+            })
+            .await
+    }
+
     async fn kill_operation(&self, operation_id: &OperationId) -> Result<(), Error> {
         let running_action = {
             let running_actions = self.running_actions.lock();
@@ -2177,6 +2255,8 @@ pub struct Metrics {
     get_proto_command_from_store: AsyncCounterWrapper,
     #[metric(help = "Stats about the download_to_directory command.")]
     download_to_directory: AsyncCounterWrapper,
+    #[metric(help = "Stats about the prefetch_inputs command.")]
+    prefetch_inputs: AsyncCounterWrapper,
     #[metric(help = "Stats about the prepare_output_files command.")]
     prepare_output_files: AsyncCounterWrapper,
     #[metric(help = "Stats about the prepare_output_paths command.")]
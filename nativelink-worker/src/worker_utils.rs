@@ -13,23 +13,29 @@
 // limitations under the License.
 
 use core::hash::BuildHasher;
+use core::num::NonZeroUsize;
 use core::str::from_utf8;
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader, Cursor};
 use std::process::Stdio;
+use std::thread::available_parallelism;
 
 use futures::future::try_join_all;
 use nativelink_config::cas_server::WorkerProperty;
 use nativelink_error::{Error, ResultExt, make_err, make_input_err};
 use nativelink_proto::build::bazel::remote::execution::v2::platform::Property;
-use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::ConnectWorkerRequest;
-use tokio::process;
+use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::{
+    ConnectWorkerRequest, WorkerUtilization,
+};
+use nativelink_util::worker_features::{SUPPORTED_WORKER_FEATURES, WORKER_API_PROTOCOL_VERSION};
+use tokio::{fs, process};
 use tracing::info;
 
 #[expect(clippy::future_not_send)] // TODO(jhpratt) remove this
 pub async fn make_connect_worker_request<S: BuildHasher>(
     worker_id_prefix: String,
     worker_properties: &HashMap<String, WorkerProperty, S>,
+    labels: HashMap<String, String>,
 ) -> Result<ConnectWorkerRequest, Error> {
     let mut futures = vec![];
     for (property_name, worker_property) in worker_properties {
@@ -102,5 +108,47 @@ pub async fn make_connect_worker_request<S: BuildHasher>(
     Ok(ConnectWorkerRequest {
         worker_id_prefix,
         properties: try_join_all(futures).await?.into_iter().flatten().collect(),
+        labels,
+        protocol_version: WORKER_API_PROTOCOL_VERSION,
+        features: SUPPORTED_WORKER_FEATURES
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+    })
+}
+
+/// Collects the current resource utilization of the host from `/proc`.
+/// Returns None where `/proc` is not available. Disk utilization is not
+/// collected yet and is always reported as zero.
+pub async fn collect_worker_utilization() -> Option<WorkerUtilization> {
+    let loadavg = fs::read_to_string("/proc/loadavg").await.ok()?;
+    let meminfo = fs::read_to_string("/proc/meminfo").await.ok()?;
+    let cpu_count = available_parallelism().map_or(1, NonZeroUsize::get);
+    parse_worker_utilization(&loadavg, &meminfo, cpu_count)
+}
+
+/// Builds the utilization from the contents of `/proc/loadavg` and
+/// `/proc/meminfo`. CPU usage is the one minute load average divided by the
+/// number of CPUs.
+#[must_use]
+pub fn parse_worker_utilization(
+    loadavg: &str,
+    meminfo: &str,
+    cpu_count: usize,
+) -> Option<WorkerUtilization> {
+    let load: f64 = loadavg.split_whitespace().next()?.parse().ok()?;
+    let meminfo_bytes = |key: &str| -> Option<u64> {
+        let value = meminfo.lines().find_map(|line| line.strip_prefix(key))?;
+        let kilobytes: u64 = value.split_whitespace().next()?.parse().ok()?;
+        Some(kilobytes * 1024)
+    };
+    let memory_total_bytes = meminfo_bytes("MemTotal:")?;
+    let memory_available_bytes = meminfo_bytes("MemAvailable:")?;
+    Some(WorkerUtilization {
+        cpu_usage: load / cpu_count.max(1) as f64,
+        memory_used_bytes: memory_total_bytes.saturating_sub(memory_available_bytes),
+        memory_total_bytes,
+        disk_used_bytes: 0,
+        disk_total_bytes: 0,
     })
 }
@@ -37,8 +37,8 @@ use nativelink_proto::build::bazel::remote::execution::v2::Platform;
 use nativelink_proto::build::bazel::remote::execution::v2::platform::Property;
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::update_for_worker::Update;
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::{
-    ConnectWorkerRequest, ConnectionResult, ExecuteResult, KillOperationRequest, StartExecute,
-    UpdateForWorker, execute_result,
+    ConnectWorkerRequest, ConnectionResult, ExecuteResult, KillOperationRequest, PrefetchInputs,
+    StartExecute, UpdateForWorker, execute_result,
 };
 use nativelink_store::fast_slow_store::FastSlowStore;
 use nativelink_store::filesystem_store::FilesystemStore;
@@ -50,6 +50,7 @@ use nativelink_util::action_messages::{
 use nativelink_util::common::{DigestInfo, encode_stream_proto, fs};
 use nativelink_util::digest_hasher::DigestHasherFunc;
 use nativelink_util::store_trait::Store;
+use nativelink_util::worker_features::{SUPPORTED_WORKER_FEATURES, WORKER_API_PROTOCOL_VERSION};
 use nativelink_worker::local_worker::new_local_worker;
 use pretty_assertions::assert_eq;
 use prost::Message;
@@ -63,6 +64,18 @@ use utils::mock_running_actions_manager::MockRunningAction;
 
 const INSTANCE_NAME: &str = "foo";
 
+/// The request a worker without platform properties or labels connects with.
+fn default_connect_worker_request() -> ConnectWorkerRequest {
+    ConnectWorkerRequest {
+        protocol_version: WORKER_API_PROTOCOL_VERSION,
+        features: SUPPORTED_WORKER_FEATURES
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        ..Default::default()
+    }
+}
+
 /// Get temporary path from either `TEST_TMPDIR` or best effort temp directory if
 /// not set.
 fn make_temp_path(data: &str) -> String {
@@ -123,7 +136,37 @@ async fn platform_properties_smoke_test() -> Result<(), Error> {
                     name: "foo".to_string(),
                     value: "bar2".to_string(),
                 }
-            ]
+            ],
+            labels: HashMap::new(),
+            ..default_connect_worker_request()
+        }
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn labels_sent_on_connect_test() -> Result<(), Error> {
+    let labels = HashMap::from([
+        ("rack".to_string(), "r12".to_string()),
+        ("pool".to_string(), "spot-pool-a".to_string()),
+    ]);
+    let local_worker_config = LocalWorkerConfig {
+        labels: labels.clone(),
+        ..Default::default()
+    };
+    let mut test_context = setup_local_worker_with_config(local_worker_config).await;
+    let streaming_response = test_context.maybe_streaming_response.take().unwrap();
+
+    let connect_worker_request = test_context
+        .client
+        .expect_connect_worker(Ok(streaming_response))
+        .await;
+    assert_eq!(
+        connect_worker_request,
+        ConnectWorkerRequest {
+            labels,
+            ..default_connect_worker_request()
         }
     );
 
@@ -141,7 +184,7 @@ async fn reconnect_on_server_disconnect_test() -> Result<(), Error> {
             .client
             .expect_connect_worker(Ok(streaming_response))
             .await;
-        assert_eq!(props, ConnectWorkerRequest::default());
+        assert_eq!(props, default_connect_worker_request());
     }
 
     // Disconnect our grpc stream.
@@ -154,7 +197,7 @@ async fn reconnect_on_server_disconnect_test() -> Result<(), Error> {
             .client
             .expect_connect_worker(Ok(streaming_response))
             .await;
-        assert_eq!(props, ConnectWorkerRequest::default());
+        assert_eq!(props, default_connect_worker_request());
     }
 
     Ok(())
@@ -171,7 +214,7 @@ async fn kill_all_called_on_disconnect() -> Result<(), Error> {
             .client
             .expect_connect_worker(Ok(streaming_response))
             .await;
-        assert_eq!(props, ConnectWorkerRequest::default());
+        assert_eq!(props, default_connect_worker_request());
     }
 
     // Handle registration (kill_all not called unless registered).
@@ -182,6 +225,7 @@ async fn kill_all_called_on_disconnect() -> Result<(), Error> {
                 encode_stream_proto(&UpdateForWorker {
                     update: Some(Update::ConnectionResult(ConnectionResult {
                         worker_id: "foobar".to_string(),
+                        ..Default::default()
                     })),
                 })
                 .unwrap(),
@@ -210,7 +254,7 @@ async fn blake3_digest_function_registered_properly() -> Result<(), Error> {
             .client
             .expect_connect_worker(Ok(streaming_response))
             .await;
-        assert_eq!(props, ConnectWorkerRequest::default());
+        assert_eq!(props, default_connect_worker_request());
     }
 
     let expected_worker_id = "foobar".to_string();
@@ -223,6 +267,7 @@ async fn blake3_digest_function_registered_properly() -> Result<(), Error> {
                 encode_stream_proto(&UpdateForWorker {
                     update: Some(Update::ConnectionResult(ConnectionResult {
                         worker_id: expected_worker_id.clone(),
+                        ..Default::default()
                     })),
                 })
                 .unwrap(),
@@ -245,6 +290,7 @@ async fn blake3_digest_function_registered_properly() -> Result<(), Error> {
             digest_function: DigestHasherFunc::Blake3,
             digest: action_digest,
         }),
+        do_not_cache: false,
     };
 
     {
@@ -300,7 +346,7 @@ async fn simple_worker_start_action_test() -> Result<(), Error> {
             .client
             .expect_connect_worker(Ok(streaming_response))
             .await;
-        assert_eq!(props, ConnectWorkerRequest::default());
+        assert_eq!(props, default_connect_worker_request());
     }
 
     let expected_worker_id = "foobar".to_string();
@@ -313,6 +359,7 @@ async fn simple_worker_start_action_test() -> Result<(), Error> {
                 encode_stream_proto(&UpdateForWorker {
                     update: Some(Update::ConnectionResult(ConnectionResult {
                         worker_id: expected_worker_id.clone(),
+                        ..Default::default()
                     })),
                 })
                 .unwrap(),
@@ -335,6 +382,7 @@ async fn simple_worker_start_action_test() -> Result<(), Error> {
             digest_function: DigestHasherFunc::Sha256,
             digest: action_digest,
         }),
+        do_not_cache: false,
     };
 
     {
@@ -431,6 +479,7 @@ async fn new_local_worker_creates_work_directory_test() -> Result<(), Error> {
             // Note: These are not needed for this test, so we put dummy memory stores here.
             fast: StoreSpec::Memory(MemorySpec::default()),
             slow: StoreSpec::Memory(MemorySpec::default()),
+            read_ahead: false,
         },
         Store::new(
             <FilesystemStore>::new(&FilesystemSpec {
@@ -470,6 +519,7 @@ async fn new_local_worker_removes_work_directory_before_start_test() -> Result<(
             // Note: These are not needed for this test, so we put dummy memory stores here.
             fast: StoreSpec::Memory(MemorySpec::default()),
             slow: StoreSpec::Memory(MemorySpec::default()),
+            read_ahead: false,
         },
         Store::new(
             <FilesystemStore>::new(&FilesystemSpec {
@@ -575,7 +625,7 @@ async fn experimental_precondition_script_fails() -> Result<(), Error> {
             .client
             .expect_connect_worker(Ok(streaming_response))
             .await;
-        assert_eq!(props, ConnectWorkerRequest::default());
+        assert_eq!(props, default_connect_worker_request());
     }
 
     let expected_worker_id = "foobar".to_string();
@@ -588,6 +638,7 @@ async fn experimental_precondition_script_fails() -> Result<(), Error> {
                 encode_stream_proto(&UpdateForWorker {
                     update: Some(Update::ConnectionResult(ConnectionResult {
                         worker_id: expected_worker_id.clone(),
+                        ..Default::default()
                     })),
                 })
                 .unwrap(),
@@ -610,6 +661,7 @@ async fn experimental_precondition_script_fails() -> Result<(), Error> {
             digest_function: DigestHasherFunc::Sha256,
             digest: action_digest,
         }),
+        do_not_cache: false,
     };
 
     {
@@ -665,7 +717,7 @@ async fn kill_action_request_kills_action() -> Result<(), Error> {
             .client
             .expect_connect_worker(Ok(streaming_response))
             .await;
-        assert_eq!(props, ConnectWorkerRequest::default());
+        assert_eq!(props, default_connect_worker_request());
     }
 
     let expected_worker_id = "foobar".to_string();
@@ -678,6 +730,7 @@ async fn kill_action_request_kills_action() -> Result<(), Error> {
                 encode_stream_proto(&UpdateForWorker {
                     update: Some(Update::ConnectionResult(ConnectionResult {
                         worker_id: expected_worker_id.clone(),
+                        ..Default::default()
                     })),
                 })
                 .unwrap(),
@@ -700,6 +753,7 @@ async fn kill_action_request_kills_action() -> Result<(), Error> {
             digest_function: DigestHasherFunc::Blake3,
             digest: action_digest,
         }),
+        do_not_cache: false,
     };
 
     let operation_id = OperationId::default();
@@ -751,3 +805,61 @@ async fn kill_action_request_kills_action() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn prefetch_inputs_request_prefetches_directories() -> Result<(), Error> {
+    let mut test_context = setup_local_worker(HashMap::new()).await;
+
+    let streaming_response = test_context.maybe_streaming_response.take().unwrap();
+
+    {
+        // Ensure our worker connects and properties were sent.
+        let props = test_context
+            .client
+            .expect_connect_worker(Ok(streaming_response))
+            .await;
+        assert_eq!(props, default_connect_worker_request());
+    }
+
+    let tx_stream = test_context.maybe_tx_stream.take().unwrap();
+    {
+        tx_stream
+            .send(Frame::data(
+                encode_stream_proto(&UpdateForWorker {
+                    update: Some(Update::ConnectionResult(ConnectionResult {
+                        worker_id: "foobar".to_string(),
+                        ..Default::default()
+                    })),
+                })
+                .unwrap(),
+            ))
+            .await
+            .map_err(|e| make_input_err!("Could not send : {:?}", e))?;
+    }
+
+    let directory_digests = vec![
+        DigestInfo::new([1u8; 32], 10),
+        DigestInfo::new([2u8; 32], 20),
+    ];
+    {
+        // Send prefetch hint.
+        tx_stream
+            .send(Frame::data(
+                encode_stream_proto(&UpdateForWorker {
+                    update: Some(Update::PrefetchInputs(PrefetchInputs {
+                        directory_digests: directory_digests.iter().map(Into::into).collect(),
+                    })),
+                })
+                .unwrap(),
+            ))
+            .await
+            .map_err(|e| make_input_err!("Could not send : {:?}", e))?;
+    }
+
+    assert_eq!(
+        test_context.actions_manager.expect_prefetch_inputs().await,
+        directory_digests
+    );
+
+    Ok(())
+}
@@ -108,6 +108,7 @@ async fn setup_stores() -> Result<
         &FastSlowSpec {
             fast: StoreSpec::Filesystem(fast_config),
             slow: StoreSpec::Memory(slow_config),
+            read_ahead: false,
         },
         Store::new(fast_store.clone()),
         Store::new(slow_store.clone()),
@@ -45,6 +45,9 @@ pub(crate) struct MockRunningActionsManager {
 
     rx_kill_operation: Mutex<mpsc::UnboundedReceiver<OperationId>>,
     tx_kill_operation: mpsc::UnboundedSender<OperationId>,
+
+    rx_prefetch_inputs: Mutex<mpsc::UnboundedReceiver<Vec<DigestInfo>>>,
+    tx_prefetch_inputs: mpsc::UnboundedSender<Vec<DigestInfo>>,
     metrics: Arc<Metrics>,
 }
 
@@ -60,6 +63,7 @@ impl MockRunningActionsManager {
         let (tx_resp, rx_resp) = mpsc::unbounded_channel();
         let (tx_kill_all, rx_kill_all) = mpsc::unbounded_channel();
         let (tx_kill_operation, rx_kill_operation) = mpsc::unbounded_channel();
+        let (tx_prefetch_inputs, rx_prefetch_inputs) = mpsc::unbounded_channel();
         Self {
             rx_call: Mutex::new(rx_call),
             tx_call,
@@ -69,6 +73,8 @@ impl MockRunningActionsManager {
             tx_kill_all,
             rx_kill_operation: Mutex::new(rx_kill_operation),
             tx_kill_operation,
+            rx_prefetch_inputs: Mutex::new(rx_prefetch_inputs),
+            tx_prefetch_inputs,
             metrics: Arc::new(Metrics::default()),
         }
     }
@@ -125,6 +131,14 @@ impl MockRunningActionsManager {
             .await
             .expect("Could not receive msg in mpsc")
     }
+
+    pub(crate) async fn expect_prefetch_inputs(&self) -> Vec<DigestInfo> {
+        let mut rx_prefetch_inputs_lock = self.rx_prefetch_inputs.lock().await;
+        rx_prefetch_inputs_lock
+            .recv()
+            .await
+            .expect("Could not receive msg in mpsc")
+    }
 }
 
 impl RunningActionsManager for MockRunningActionsManager {
@@ -174,6 +188,13 @@ impl RunningActionsManager for MockRunningActionsManager {
         Ok(())
     }
 
+    async fn prefetch_inputs(&self, directory_digests: Vec<DigestInfo>) -> Result<(), Error> {
+        self.tx_prefetch_inputs
+            .send(directory_digests)
+            .expect("Could not send request to mpsc");
+        Ok(())
+    }
+
     async fn kill_all(&self) {
         self.tx_kill_all
             .send(())
@@ -439,4 +460,8 @@ impl RunningAction for MockRunningAction {
     fn get_work_directory(&self) -> &String {
         unreachable!();
     }
+
+    fn do_not_cache(&self) -> bool {
+        false
+    }
 }
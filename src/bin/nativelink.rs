@@ -18,7 +18,7 @@ use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use async_lock::Mutex as AsyncMutex;
-use axum::Router;
+use axum::Extension;
 use axum::http::Uri;
 use clap::Parser;
 use futures::FutureExt;
@@ -36,6 +36,7 @@ use nativelink_config::stores::ConfigDigestHashFunction;
 use nativelink_error::{Code, Error, ResultExt, make_err, make_input_err};
 use nativelink_scheduler::default_scheduler_factory::scheduler_factory;
 use nativelink_service::ac_server::AcServer;
+use nativelink_service::admin_server::{AdminServer, PeerCertificate};
 use nativelink_service::bep_server::BepServer;
 use nativelink_service::bytestream_server::ByteStreamServer;
 use nativelink_service::capabilities_server::CapabilitiesServer;
@@ -505,50 +506,15 @@ async fn inner_main(
             } else {
                 &admin_config.path
             };
-            let worker_schedulers = Arc::new(worker_schedulers.clone());
-            svc = svc.nest_service(
-                path,
-                Router::new().route(
-                    "/scheduler/{instance_name}/set_drain_worker/{worker_id}/{is_draining}",
-                    axum::routing::post(
-                        move |params: axum::extract::Path<(String, String, String)>| async move {
-                            let (instance_name, worker_id, is_draining) = params.0;
-                            (async move {
-                                let is_draining = match is_draining.as_str() {
-                                    "0" => false,
-                                    "1" => true,
-                                    _ => {
-                                        return Err(make_err!(
-                                            Code::Internal,
-                                            "{} is neither 0 nor 1",
-                                            is_draining
-                                        ));
-                                    }
-                                };
-                                worker_schedulers
-                                    .get(&instance_name)
-                                    .err_tip(|| {
-                                        format!(
-                                            "Can not get an instance with the name of '{}'",
-                                            &instance_name
-                                        )
-                                    })?
-                                    .clone()
-                                    .set_drain_worker(&worker_id.clone().into(), is_draining)
-                                    .await?;
-                                Ok::<_, Error>(format!("Draining worker {worker_id}"))
-                            })
-                            .await
-                            .map_err(|e| {
-                                Err::<String, _>((
-                                    StatusCode::INTERNAL_SERVER_ERROR,
-                                    format!("Error: {e:?}"),
-                                ))
-                            })
-                        },
-                    ),
-                ),
-            );
+            let admin_server = AdminServer::new(
+                &admin_config,
+                &action_schedulers,
+                &worker_schedulers,
+                &store_manager,
+                shutdown_tx.clone(),
+            )
+            .err_tip(|| "Could not create admin API")?;
+            svc = svc.nest_service(path, admin_server.into_router());
         }
 
         // This is the default service that executes if no other endpoint matches.
@@ -713,10 +679,25 @@ async fn inner_main(
                                     ).in_scope(|| async move {
                                         let serve_connection = if let Some(tls_acceptor) = maybe_tls_acceptor {
                                             match tls_acceptor.accept(tcp_stream).await {
-                                                Ok(tls_stream) => Either::Left(http.serve_connection(
-                                                    TokioIo::new(tls_stream),
-                                                    TowerToHyperService::new(svc),
-                                                )),
+                                                Ok(tls_stream) => {
+                                                    // Lets the admin API grant roles based on the
+                                                    // verified client certificate.
+                                                    let svc = match tls_stream
+                                                        .get_ref()
+                                                        .1
+                                                        .peer_certificates()
+                                                        .and_then(|certs| certs.first())
+                                                    {
+                                                        Some(cert) => svc.layer(Extension(
+                                                            PeerCertificate(cert.clone().into_owned()),
+                                                        )),
+                                                        None => svc,
+                                                    };
+                                                    Either::Left(http.serve_connection(
+                                                        TokioIo::new(tls_stream),
+                                                        TowerToHyperService::new(svc),
+                                                    ))
+                                                }
                                                 Err(err) => {
                                                     error!(?err, "Failed to accept tls stream");
                                                     return;